@@ -4,9 +4,11 @@ use leptos_router::components::{Route, Router, Routes};
 use leptos_router::path;
 use thaw::*;
 
+use crate::pages::dashboards::Dashboards;
 use crate::pages::profiler::Profiler;
 use crate::pages::timeseries::Timeseries;
 use crate::pages::{activity::Activity, cluster::Cluster, overview::Overview, python::Python};
+use crate::url_read::base_path;
 
 #[component]
 pub fn App() -> impl IntoView {
@@ -44,7 +46,7 @@ fn TheRouter() -> impl IntoView {
     );
 
     view! {
-        <Router set_is_routing>
+        <Router base=base_path() set_is_routing>
             <Routes fallback=|| "404">
                 <Route path=path!("/") view=Overview />
                 <Route path=path!("/cluster") view=Cluster />
@@ -54,6 +56,8 @@ fn TheRouter() -> impl IntoView {
                 <Route path=path!("/profiler") view=|| view! { <Profiler /> } />
                 <Route path=path!("/timeseries") view=|| view! { <Timeseries /> } />
                 <Route path=path!("/inspect") view=|| view! { <Python /> } />
+                <Route path=path!("/dashboards") view=Dashboards />
+                <Route path=path!("/dashboards/:name") view=Dashboards />
             // <Route path="/files" view=|| view! { <Files/> }/>
             </Routes>
         </Router>
@@ -3,6 +3,7 @@ use probing_proto::prelude::Process;
 use thaw::*;
 
 use crate::components::tableview::{Table, TableView};
+use crate::url_read::with_base;
 
 #[component]
 pub fn ProcessCard(process: Process) -> impl IntoView {
@@ -27,7 +28,7 @@ pub fn ThreadsCard(threads: Vec<u64>) -> impl IntoView {
         .iter()
         .map(|t| {
             let tid = *t;
-            let url = format!("/activity/{}", tid);
+            let url = with_base(format!("/activity/{}", tid).as_str());
             view! { <Link href=url>{tid}</Link> }
         })
         .collect::<Vec<_>>();
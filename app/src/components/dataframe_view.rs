@@ -280,10 +280,18 @@ fn ChartRenderer(
 }
 
 #[component]
-pub fn DataFrameChartView(df: DataFrame) -> impl IntoView {
+pub fn DataFrameChartView(
+    df: DataFrame,
+    /// 初始 X 轴列
+    #[prop(optional)]
+    x: Option<String>,
+    /// 初始 Y 轴列
+    #[prop(optional)]
+    y: Vec<String>,
+) -> impl IntoView {
     // 用户选择
-    let x_column = RwSignal::new(String::new());
-    let y_columns = RwSignal::new(Vec::<String>::new());
+    let x_column = RwSignal::new(x.unwrap_or_default());
+    let y_columns = RwSignal::new(y);
     let available_columns = RwSignal::new(df.names.clone());
 
     // 过滤设置
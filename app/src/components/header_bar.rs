@@ -94,6 +94,12 @@ pub fn HeaderBar() -> impl IntoView {
                 >
                     "Inspect"
                 </Button>
+                <Button
+                    appearance=ButtonAppearance::Transparent
+                    on_click=move |_| navigate_signal.get()("/dashboards", Default::default())
+                >
+                    "Dashboards"
+                </Button>
                 <Button appearance=ButtonAppearance::Primary on_click=change_theme>
                     {move || theme_name.get()}
                 </Button>
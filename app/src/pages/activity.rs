@@ -5,7 +5,7 @@ use thaw::*;
 use probing_proto::prelude::CallFrame;
 
 use crate::components::page_layerout::PageLayout;
-use crate::url_read::url_read_resource;
+use crate::url_read::{url_read_resource, with_base};
 
 use super::common::*;
 
@@ -77,7 +77,7 @@ fn CallStackView(#[prop(into)] callstack: CallFrame) -> impl IntoView {
             lineno,
             locals,
         } => {
-            let url = format!("/apis/files?path={}", file.clone());
+            let url = with_base(format!("/apis/files?path={}", file.clone()).as_str());
             // let route_url = format!("/files?path={}", file);
             let key = format!("{func} @ {file}: {lineno}");
             view! {
@@ -22,7 +22,11 @@ pub fn Cluster() -> impl IntoView {
             }>
                 {move || Suspend::new(async move {
                     resp.await
-                        .map(|nodes| {
+                        .map(|mut nodes| {
+                            nodes.sort_by(|a, b| {
+                                (&a.role_name, &a.labels, a.rank)
+                                    .cmp(&(&b.role_name, &b.labels, b.rank))
+                            });
                             nodes
                                 .iter()
                                 .map(|node| {
@@ -32,6 +36,13 @@ pub fn Cluster() -> impl IntoView {
                                         .into();
                                     let timestamp = datetime.to_rfc3339();
                                     let url = format!("http://{}", node.addr);
+                                    let labels = node
+                                        .labels
+                                        .iter()
+                                        .filter(|(key, _)| *key != "framework")
+                                        .map(|(key, value)| format!("{key}={value}"))
+                                        .collect::<Vec<_>>()
+                                        .join(" ");
                                     view! {
                                         <TableRow>
                                             <TableCell>{node.host.to_string()}</TableCell>
@@ -58,6 +69,7 @@ pub fn Cluster() -> impl IntoView {
                                             <TableCell>
                                                 {node.role_world_size.unwrap_or(-1).to_string()}
                                             </TableCell>
+                                            <TableCell>{labels}</TableCell>
                                             <TableCell>{node.status}</TableCell>
                                             <TableCell>{timestamp}</TableCell>
                                         </TableRow>
@@ -87,6 +99,7 @@ pub fn Cluster() -> impl IntoView {
                             <TableHeaderCell>role_name</TableHeaderCell>
                             <TableHeaderCell>role_rank</TableHeaderCell>
                             <TableHeaderCell>role_world_size</TableHeaderCell>
+                            <TableHeaderCell>labels</TableHeaderCell>
                             <TableHeaderCell>status</TableHeaderCell>
                             <TableHeaderCell>timestamp</TableHeaderCell>
                         </TableRow>
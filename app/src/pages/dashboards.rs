@@ -0,0 +1,117 @@
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use thaw::*;
+
+use probing_proto::prelude::{ChartType, Dashboard, DashboardPanel};
+
+use crate::components::dataframe_view::{DataFrameChartView, DataFrameView};
+use crate::components::page_layerout::PageLayout;
+use crate::components::panel::Panel;
+use crate::url_read::{read_query_resource, url_read_resource, with_base};
+
+#[component]
+pub fn Dashboards() -> impl IntoView {
+    let params = use_params_map();
+    let dashboards = url_read_resource::<Vec<Dashboard>>("/apis/dashboards");
+
+    view! {
+        <PageLayout>
+            <Suspense fallback=move || {
+                view! { <p>"Loading..."</p> }
+            }>
+                {move || Suspend::new(async move {
+                    let dashboards = match dashboards.await {
+                        Ok(dashboards) => dashboards,
+                        Err(e) => {
+                            return view! { <p style="color: red;">{e.to_string()}</p> }.into_any();
+                        }
+                    };
+                    if dashboards.is_empty() {
+                        return view! {
+                            <p>
+                                "No dashboards. Upload one to /apis/dashboards/{name} or ship it in $PROBING_CODE_ROOT/dashboards."
+                            </p>
+                        }
+                            .into_any();
+                    }
+                    let selected = params.with(|p| p.get("name"));
+                    let current = selected
+                        .and_then(|name| dashboards.iter().find(|d| d.name == name).cloned())
+                        .unwrap_or_else(|| dashboards[0].clone());
+                    let links = dashboards
+                        .iter()
+                        .map(|d| {
+                            let href = with_base(&format!("/dashboards/{}", d.name));
+                            let appearance = if d.name == current.name {
+                                ButtonAppearance::Primary
+                            } else {
+                                ButtonAppearance::Secondary
+                            };
+                            let label = if d.title.is_empty() {
+                                d.name.clone()
+                            } else {
+                                d.title.clone()
+                            };
+                            view! {
+                                <a href=href>
+                                    <Button appearance>{label}</Button>
+                                </a>
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    view! {
+                        <Space vertical=true>
+                            <Space>{links}</Space>
+                            <DashboardView dashboard=current />
+                        </Space>
+                    }
+                        .into_any()
+                })}
+            </Suspense>
+        </PageLayout>
+    }
+}
+
+#[component]
+fn DashboardView(dashboard: Dashboard) -> impl IntoView {
+    let panels = dashboard
+        .panels
+        .into_iter()
+        .map(|panel| view! { <DashboardPanelView panel /> })
+        .collect::<Vec<_>>();
+    view! { <Space vertical=true>{panels}</Space> }
+}
+
+#[component]
+fn DashboardPanelView(panel: DashboardPanel) -> impl IntoView {
+    let result = read_query_resource(&panel.query);
+    let DashboardPanel {
+        title, chart, x, y, ..
+    } = panel;
+
+    view! {
+        <Panel title>
+            <Suspense fallback=move || {
+                view! { <p>"Loading..."</p> }
+            }>
+                {move || {
+                    let (x, y) = (x.clone(), y.clone());
+                    Suspend::new(async move {
+                        match result.await {
+                            Ok(df) => match chart {
+                                ChartType::Table => view! { <DataFrameView df /> }.into_any(),
+                                ChartType::Line => {
+                                    view! { <DataFrameChartView df x=x.unwrap_or_default() y /> }
+                                        .into_any()
+                                }
+                            },
+                            Err(e) => {
+                                view! { <p style="color: red;">{e.to_string()}</p> }.into_any()
+                            }
+                        }
+                    })
+                }}
+            </Suspense>
+        </Panel>
+    }
+}
@@ -1,5 +1,6 @@
 pub mod activity;
 pub mod cluster;
+pub mod dashboards;
 pub mod overview;
 pub mod profiler;
 pub mod python;
@@ -6,11 +6,16 @@ use leptos::prelude::*;
 use probing_proto::prelude::*;
 
 use crate::components::card_view::{ProcessCard, ThreadsCard};
+use crate::components::dataframe_view::DataFrameView;
 use crate::components::page_layerout::PageLayout;
 use crate::components::panel::Panel;
 use crate::components::tableview::{Table, TableView};
 use crate::errors::AppError;
-use crate::url_read::url_read_resource;
+use crate::url_read::{read_query_resource, url_read_resource};
+
+/// Most recent lines of the target's stdout and stderr, newest first
+const OUTPUT_QUERY: &str =
+    "SELECT timestamp, stream, line FROM process.output ORDER BY seq DESC LIMIT 200";
 
 /// Helper function to parse environment variables string into a Table structure.
 fn parse_env_vars(envs: &HashMap<String, String>) -> Table {
@@ -54,6 +59,7 @@ pub fn Overview() -> impl IntoView {
     // Fetch process data once
     let resource: LocalResource<std::result::Result<Process, AppError>> =
         url_read_resource::<Process>("/apis/overview");
+    let output = read_query_resource(OUTPUT_QUERY);
 
     view! {
         <PageLayout>
@@ -77,6 +83,11 @@ pub fn Overview() -> impl IntoView {
                     view_fn=|process| view! { <TableView tbl=parse_env_vars(&process.env) /> }
                 />
             </Panel>
+
+            // Captured stdout/stderr, empty unless probing.output.capture is set
+            <Panel title="Output">
+                <SuspendedView resource=output view_fn=|df| view! { <DataFrameView df /> } />
+            </Panel>
         </PageLayout>
     }
 }
@@ -2,7 +2,10 @@ use leptos::prelude::*;
 
 use thaw::*;
 
-use crate::{components::page_layerout::PageLayout, url_read::read_query_resource};
+use crate::{
+    components::page_layerout::PageLayout,
+    url_read::{read_query_resource, with_base},
+};
 
 #[component]
 pub fn Profiler() -> impl IntoView {
@@ -18,6 +21,9 @@ pub fn Profiler() -> impl IntoView {
     let torch_enabled = RwSignal::new(false); // torch profiler 的开关状态
     let torch_ratio = RwSignal::new("".to_string());
 
+    let heatmap_input = RwSignal::new("".to_string());
+    let heatmap_module = RwSignal::new("".to_string());
+
     let _ = Effect::new_sync(move || {
         match config.get().as_deref() {
             Some(Ok(df)) => {
@@ -32,6 +38,10 @@ pub fn Profiler() -> impl IntoView {
                                 pprof_freq.set(ele[1].to_string());
                             }
                         }
+                        "probing.pprof.heatmap_module" => {
+                            heatmap_input.set(ele[1].to_string());
+                            heatmap_module.set(ele[1].to_string());
+                        }
                         "probing.torch.sample_ratio" => {
                             if ele[1].to_string() != "" {
                                 torch_enabled.set(true);
@@ -63,6 +73,9 @@ pub fn Profiler() -> impl IntoView {
                     <NavItem icon=icondata::SiPytorch value="torch">
                         "Torch Profiling"
                     </NavItem>
+                    <NavItem icon=icondata::AiFireOutlined value="heatmap">
+                        "Line Heatmap"
+                    </NavItem>
                     <NavCategory value="setting">
                         <NavCategoryItem slot icon=icondata::AiSettingOutlined>
                             "Settings"
@@ -89,6 +102,10 @@ pub fn Profiler() -> impl IntoView {
                 </NavDrawer>
                 <Flex align=FlexAlign::Center vertical=true class="doc-content" style="width: 100%">
                     {move || {
+                        if selected_tab.get() == "heatmap" {
+                            return view! { <HeatmapView input=heatmap_input module=heatmap_module /> }
+                                .into_any();
+                        }
                         if !pprof_enabled.get() && !torch_enabled.get() {
                             return view! {
                                 <div>
@@ -116,6 +133,7 @@ pub fn Profiler() -> impl IntoView {
                             _ => "",
                         };
 
+                        let url = with_base(url);
                         view! { <object data=url style="width: 100%; border: none;"></object> }
                             .into_any()
                     }}
@@ -124,3 +142,46 @@ pub fn Profiler() -> impl IntoView {
         </PageLayout>
     }.into_view()
 }
+
+/// Source of a module shaded by its wall samples per line, see `/apis/heatmap`
+#[component]
+fn HeatmapView(input: RwSignal<String>, module: RwSignal<String>) -> impl IntoView {
+    view! {
+        <Flex vertical=true style="width: 100%">
+            <Flex align=FlexAlign::Center>
+                <Input placeholder="Module, e.g. mypkg.trainer or train.py" value=input />
+                <Button on_click=move |_| module.set(input.get_untracked())>"Show"</Button>
+            </Flex>
+            {move || {
+                let name = module.get();
+                if name.trim().is_empty() {
+                    return view! {
+                        <div>
+                            "Pick a module to see its execution heatmap. Lines are counted while pprof samples in wall mode (probing.pprof.mode=wall)."
+                        </div>
+                    }
+                        .into_any();
+                }
+                let url = with_base(&format!("/apis/heatmap?module={}", encode_query(&name)));
+                view! { <object data=url style="width: 100%; min-height: 80vh; border: none;"></object> }
+                    .into_any()
+            }}
+        </Flex>
+    }
+}
+
+fn encode_query(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            c if c.is_ascii_alphanumeric() || "-_.~/".contains(c) => encoded.push(c),
+            c => {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    encoded.push_str(&format!("%{byte:02X}"));
+                }
+            }
+        }
+    }
+    encoded
+}
@@ -5,7 +5,7 @@ use thaw::*;
 use crate::components::dataframe_view::{DataFrameChartView, DataFrameView};
 use crate::components::page_layerout::PageLayout;
 use crate::errors::AppError;
-use crate::url_read::read_query_resource;
+use crate::url_read::{read_query_resource, read_selftest};
 
 #[component]
 pub fn Timeseries() -> impl IntoView {
@@ -39,6 +39,10 @@ pub fn Timeseries() -> impl IntoView {
                 padding: 16px;
                 white-space: pre-wrap;
             }
+            .selftest-hint {
+                color: #b26a00;
+                padding: 0 16px;
+            }
             
             .query-form {
                 display: flex;
@@ -147,7 +151,13 @@ fn SqlQueryPanel() -> impl IntoView {
                 {move || match query_resource.get() {
                     Some(Ok(df)) => {
                         let df1 = df.clone();
+                        let hint = df
+                            .is_empty()
+                            .then(|| queried_table(&sql.get_untracked()))
+                            .flatten()
+                            .map(|table| view! { <SelfTestHint table /> });
                         view! {
+                            {hint}
                             <DataFrameChartView df=df1 />
                             <DataFrameView df />
                         }
@@ -160,3 +170,48 @@ fn SqlQueryPanel() -> impl IntoView {
         </div>
     }
 }
+
+/// First `namespace.table` the query reads from, e.g. `process.delay`
+fn queried_table(sql: &str) -> Option<String> {
+    let mut words = sql.split_whitespace();
+    words.find(|word| word.eq_ignore_ascii_case("from"))?;
+    let table = words.next()?.trim_end_matches(';').replace('"', "");
+    table.contains('.').then_some(table)
+}
+
+/// Failed checks of the extension serving an empty table, which usually
+/// explain why it has no rows
+#[component]
+fn SelfTestHint(table: String) -> impl IntoView {
+    let selftest = LocalResource::new(move || {
+        let table = table.clone();
+        async move { read_selftest(&table).await }
+    });
+
+    view! {
+        <Suspense fallback=|| ()>
+            {move || Suspend::new(async move {
+                selftest
+                    .await
+                    .ok()
+                    .filter(|selftest| !selftest.passed())
+                    .map(|selftest| {
+                        let failed = selftest
+                            .checks
+                            .into_iter()
+                            .filter(|check| !check.passed)
+                            .map(|check| {
+                                view! { <li>{format!("{}: {}", check.name, check.detail)}</li> }
+                            })
+                            .collect_view();
+                        view! {
+                            <div class="selftest-hint">
+                                <p>{format!("结果为空，扩展 {} 自检未通过：", selftest.extension)}</p>
+                                <ul>{failed}</ul>
+                            </div>
+                        }
+                    })
+            })}
+        </Suspense>
+    }
+}
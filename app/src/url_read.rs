@@ -1,13 +1,34 @@
 use gloo_net::http::Request;
 use leptos::prelude::*;
-use probing_proto::prelude::{DataFrame, Message, Query, QueryDataFormat};
+use probing_proto::prelude::{DataFrame, Message, Query, QueryDataFormat, SelfTest};
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 
 use crate::errors::AppError;
 
+/// URL prefix the app is served under, injected by the probe server as
+/// `<meta name="probing-base">` when running behind a reverse proxy.
+pub fn base_path() -> String {
+    document()
+        .query_selector("meta[name=probing-base]")
+        .ok()
+        .flatten()
+        .and_then(|meta| meta.get_attribute("content"))
+        .map(|base| base.trim_end_matches('/').to_string())
+        .unwrap_or_default()
+}
+
+/// Prefix an absolute path with [`base_path`]
+pub fn with_base(path: &str) -> String {
+    if path.starts_with('/') {
+        format!("{}{}", base_path(), path)
+    } else {
+        path.to_string()
+    }
+}
+
 pub async fn url_read_str(url: &str) -> Result<String, AppError> {
-    Request::get(url)
+    Request::get(with_base(url).as_str())
         .send()
         .await
         .map_err(|e| AppError::NetworkError(e.to_string()))?
@@ -53,7 +74,7 @@ pub async fn read_query(query: &str) -> Result<DataFrame, AppError> {
     let request = Message::new(request);
     let request = serde_json::to_string(&request)
         .map_err(|e| AppError::SerializationError(format!("Failed to serialize request: {}", e)))?;
-    let response = Request::post("/query")
+    let response = Request::post(with_base("/query").as_str())
         .body(request)
         .map_err(|e| AppError::NetworkError(e.to_string()))?
         .send()
@@ -74,6 +95,19 @@ pub async fn read_query(query: &str) -> Result<DataFrame, AppError> {
     }
 }
 
+/// Run the self-test of the extension serving `table`
+pub async fn read_selftest(table: &str) -> Result<SelfTest, AppError> {
+    let url = with_base(&format!("/apis/extensions/{table}/selftest"));
+    let response = Request::post(url.as_str())
+        .send()
+        .await
+        .map_err(|e| AppError::NetworkError(e.to_string()))?
+        .text()
+        .await
+        .map_err(|e| AppError::NetworkError(e.to_string()))?;
+    serde_json::from_str(response.as_str()).map_err(|e| AppError::SerializationError(e.to_string()))
+}
+
 pub fn read_query_resource(query: &str) -> LocalResource<Result<DataFrame, AppError>> {
     let query = query.to_string();
     LocalResource::new(move || {
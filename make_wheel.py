@@ -85,8 +85,13 @@ def get_platform_tag():
     raise RuntimeError(f"Unsupported platform: {sys.platform}")
 
 
-def write_probing_wheel(out_dir):
-    platform = get_platform_tag()
+def write_probing_wheel(out_dir, client_only=False):
+    """Build the probing wheel.
+
+    With `client_only`, native binaries and the startup hook are left out and
+    the wheel only provides `probing.client` for talking to existing probes.
+    """
+    platform = "any" if client_only else get_platform_tag()
     contents = {}
     meta = toml.load("Cargo.toml")
     package_meta = meta.get("package", {})
@@ -115,11 +120,11 @@ def write_probing_wheel(out_dir):
 
     lib_ext = "dylib" if sys.platform == "darwin" else "so"
     lib_name = f"libprobing.{lib_ext}"
-    for name, path in {
-        "probing": f"{target_dir_prefix}/probing",
-        lib_name: f"{target_dir_prefix}/{lib_name}",
-        "probing-repl": "python/probing-repl",
-    }.items():
+    scripts = {"probing-repl": "python/probing-repl"}
+    if not client_only:
+        scripts["probing"] = f"{target_dir_prefix}/probing"
+        scripts[lib_name] = f"{target_dir_prefix}/{lib_name}"
+    for name, path in scripts.items():
         zip_info = ZipInfo(f"probing-{metadata['version']}.data/scripts/{name}")
         zip_info.external_attr = (stat.S_IFREG | 0o755) << 16
         with open(path, "rb") as f:
@@ -152,8 +157,9 @@ def write_probing_wheel(out_dir):
     python_dir = pathlib.Path("python")
     add_python_files_recursively(python_dir, contents, python_dir)
 
-    pth_info = ZipInfo(f"probing.pth")
-    contents[pth_info] = "import probing_hook".encode("utf-8")
+    if not client_only:
+        pth_info = ZipInfo(f"probing.pth")
+        contents[pth_info] = "import probing_hook".encode("utf-8")
 
     with open("README.md", "rb") as f:
         description = f.read()
@@ -185,7 +191,7 @@ def write_probing_wheel(out_dir):
 
 
 def main():
-    wheel_path = write_probing_wheel("dist/")
+    wheel_path = write_probing_wheel("dist/", client_only="--client-only" in sys.argv)
     with open(wheel_path, "rb") as wheel:
         print(f"  {wheel_path}")
         print(f"    {hashlib.sha256(wheel.read()).hexdigest()}")
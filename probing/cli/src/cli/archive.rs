@@ -0,0 +1,58 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use probing_proto::prelude::ProbeArchive;
+
+use crate::table::render_dataframe;
+
+/// Open an offline probe archive written by a (possibly dead) process
+#[derive(Parser, Debug)]
+pub struct OpenCommand {
+    /// Path to the archive file (see `archive.path` on the probe)
+    #[arg()]
+    pub path: String,
+
+    #[command(subcommand)]
+    pub command: Option<OpenSubCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OpenSubCommand {
+    /// Show archive metadata and the archived tables
+    #[command(visible_aliases = ["info"])]
+    Tables,
+
+    /// Query the archived tables with SQL
+    #[command(visible_aliases = ["q"])]
+    Query {
+        #[arg()]
+        query: String,
+    },
+}
+
+impl OpenCommand {
+    pub async fn run(&self) -> Result<()> {
+        let archive = ProbeArchive::load(&self.path)
+            .map_err(|e| anyhow::anyhow!("failed to open archive {}: {e}", self.path))?;
+
+        match self.command.as_ref().unwrap_or(&OpenSubCommand::Tables) {
+            OpenSubCommand::Tables => {
+                println!(
+                    "archive of pid {} on {}, taken at {}",
+                    archive.pid,
+                    archive.host,
+                    probing_proto::prelude::Ele::DataTime(archive.timestamp)
+                );
+                for (name, df) in archive.tables.iter() {
+                    println!("\t{name} ({} rows)", df.len());
+                }
+            }
+            OpenSubCommand::Query { query } => {
+                let engine = probing_core::archive::engine_from_archive(&archive)?;
+                let df = engine.async_query(query.as_str()).await?;
+                render_dataframe(&df);
+            }
+        }
+        Ok(())
+    }
+}
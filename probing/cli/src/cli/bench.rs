@@ -0,0 +1,221 @@
+//! `probing bench`: load a probe with concurrent queries and report the
+//! latency seen by clients along with the CPU the target spends meanwhile.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::Args;
+use probing_proto::prelude::{DataFrame, ProbeStatus, Query, Seq};
+use probing_proto::protocol::handshake::FEATURE_STATUS;
+
+use super::ctrl::{handshake, request, ProbeEndpoint};
+use crate::table::render_dataframe;
+
+/// Longest query text shown in the report
+const QUERY_WIDTH: usize = 48;
+
+#[derive(Args, Debug)]
+pub struct BenchCommand {
+    /// Number of queries kept in flight
+    #[arg(short, long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// File of `;` separated queries, sent round-robin
+    #[arg(short, long)]
+    pub query: PathBuf,
+
+    /// How long to keep the load on, in seconds
+    #[arg(short, long, default_value_t = 10.0)]
+    pub duration: f64,
+}
+
+/// Statements of `sql`, without `--` comment lines and blank statements
+fn statements(sql: &str) -> Vec<String> {
+    let sql = sql
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    sql.split(';')
+        .map(|stmt| stmt.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|stmt| !stmt.is_empty())
+        .collect()
+}
+
+/// Nearest-rank percentile `p` (0 to 1) of sorted `latencies`
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(latency: Duration) -> f64 {
+    (latency.as_secs_f64() * 1e5).round() / 100.0
+}
+
+/// Outcome of one query sent during the run
+struct Sample {
+    stmt: usize,
+    latency: Duration,
+    error: Option<String>,
+}
+
+/// One row of the latency report per query, then one for all of them
+fn latency_report(stmts: &[String], samples: &[Sample], elapsed: Duration) -> DataFrame {
+    let mut groups = stmts
+        .iter()
+        .enumerate()
+        .map(|(i, stmt)| {
+            (
+                stmt.clone(),
+                samples.iter().filter(|s| s.stmt == i).collect(),
+            )
+        })
+        .collect::<Vec<(String, Vec<&Sample>)>>();
+    if stmts.len() > 1 {
+        groups.push(("(all)".to_string(), samples.iter().collect()));
+    }
+
+    let mut cols: [Vec<f64>; 6] = Default::default();
+    let (mut names, mut counts, mut errors) = (vec![], vec![], vec![]);
+    for (stmt, group) in groups {
+        let mut latencies = group
+            .iter()
+            .filter(|s| s.error.is_none())
+            .map(|s| s.latency)
+            .collect::<Vec<_>>();
+        latencies.sort();
+        names.push(match stmt.char_indices().nth(QUERY_WIDTH) {
+            Some((end, _)) => format!("{}...", &stmt[..end]),
+            None => stmt,
+        });
+        counts.push(group.len() as i64);
+        errors.push(group.iter().filter(|s| s.error.is_some()).count() as i64);
+        let qps = latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        cols[0].push((qps * 10.0).round() / 10.0);
+        for (col, p) in cols[1..5].iter_mut().zip([0.5, 0.9, 0.99, 1.0]) {
+            col.push(millis(percentile(&latencies, p)));
+        }
+        let mean = latencies.iter().sum::<Duration>() / latencies.len().max(1) as u32;
+        cols[5].push(millis(mean));
+    }
+
+    let [qps, p50, p90, p99, max, mean] = cols;
+    DataFrame::new(
+        [
+            "query", "count", "errors", "qps", "p50_ms", "p90_ms", "p99_ms", "max_ms", "mean_ms",
+        ]
+        .map(String::from)
+        .to_vec(),
+        vec![
+            Seq::SeqText(names),
+            Seq::SeqI64(counts),
+            Seq::SeqI64(errors),
+            Seq::SeqF64(qps),
+            Seq::SeqF64(p50),
+            Seq::SeqF64(p90),
+            Seq::SeqF64(p99),
+            Seq::SeqF64(max),
+            Seq::SeqF64(mean),
+        ],
+    )
+}
+
+async fn target_cpu(ctrl: &ProbeEndpoint) -> Option<f64> {
+    let reply = request(ctrl.clone(), "/apis/status", None).await.ok()?;
+    serde_json::from_slice::<ProbeStatus>(&reply).ok()?.cpu
+}
+
+impl BenchCommand {
+    pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        let sql = std::fs::read_to_string(&self.query)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", self.query.display()))?;
+        let stmts = Arc::new(statements(&sql));
+        if stmts.is_empty() {
+            anyhow::bail!("no query in {}", self.query.display());
+        }
+        let concurrency = self.concurrency.max(1);
+        let duration = Duration::from_secs_f64(self.duration.max(0.1));
+
+        // the probe refreshes its CPU usage every second, sample it as often
+        let track_cpu = handshake(&ctrl, false).await?.supports(FEATURE_STATUS);
+        let idle_cpu = match track_cpu {
+            true => target_cpu(&ctrl).await,
+            false => None,
+        };
+
+        println!(
+            "sending {} distinct queries with concurrency {concurrency} for {:.1}s ...",
+            stmts.len(),
+            duration.as_secs_f64()
+        );
+        let start = Instant::now();
+        let deadline = start + duration;
+        let next = Arc::new(AtomicUsize::new(0));
+        let workers = (0..concurrency)
+            .map(|_| {
+                let (ctrl, stmts, next) = (ctrl.clone(), stmts.clone(), next.clone());
+                tokio::spawn(async move {
+                    let mut samples = vec![];
+                    while Instant::now() < deadline {
+                        let stmt = next.fetch_add(1, Ordering::Relaxed) % stmts.len();
+                        let begin = Instant::now();
+                        let result = ctrl.query(Query::new(stmts[stmt].clone())).await;
+                        samples.push(Sample {
+                            stmt,
+                            latency: begin.elapsed(),
+                            error: result.err().map(|e| e.to_string()),
+                        });
+                    }
+                    samples
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut load_cpu = vec![];
+        while track_cpu && Instant::now() + Duration::from_secs(1) < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            load_cpu.extend(target_cpu(&ctrl).await);
+        }
+        let mut samples = vec![];
+        for worker in workers {
+            samples.extend(worker.await?);
+        }
+        let elapsed = start.elapsed();
+
+        render_dataframe(&latency_report(&stmts, &samples, elapsed));
+        if let Some(error) = samples.iter().find_map(|s| s.error.as_ref()) {
+            println!("first error: {error}");
+        }
+
+        if !track_cpu {
+            println!("target CPU usage unavailable, the probe does not report its status");
+            return Ok(());
+        }
+        if load_cpu.is_empty() {
+            println!("target CPU usage is sampled every second, run the bench longer");
+            return Ok(());
+        }
+        let mean = load_cpu.iter().sum::<f64>() / load_cpu.len() as f64;
+        let max = load_cpu.iter().cloned().fold(f64::MIN, f64::max);
+        let round = |v: f64| (v * 10.0).round() / 10.0;
+        render_dataframe(&DataFrame::new(
+            ["metric", "idle", "load_mean", "load_max", "overhead"]
+                .map(String::from)
+                .to_vec(),
+            vec![
+                Seq::SeqText(vec!["target_cpu_percent".to_string()]),
+                Seq::SeqF64(vec![idle_cpu.map(round).unwrap_or(f64::NAN)]),
+                Seq::SeqF64(vec![round(mean)]),
+                Seq::SeqF64(vec![round(max)]),
+                Seq::SeqF64(vec![idle_cpu.map_or(f64::NAN, |idle| round(mean - idle))]),
+            ],
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! `probing <pid> eval --children` and `probing <pid> query --children`: run
+//! the same code or query in every probed descendant of the target at once,
+//! e.g. the dataloader workers of a trainer, with the results keyed by pid.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use probing_proto::prelude::{DataFrame, EvalResult, Query, Seq};
+
+use super::ctrl::ProbeEndpoint;
+use super::ptree;
+use crate::table::{render_dataframe_as, Render};
+
+fn target_pid(ctrl: &ProbeEndpoint) -> Result<i32> {
+    match ctrl {
+        ProbeEndpoint::Ptrace { pid }
+        | ProbeEndpoint::Local { pid }
+        | ProbeEndpoint::Named { pid, .. } => Ok(*pid),
+        _ => bail!("--children needs the pid of a process on this host"),
+    }
+}
+
+/// Probed descendants of `pid`, the nearest first, children of children
+/// included since a launcher may sit in between
+async fn probed_children(pid: i32) -> Result<Vec<i32>> {
+    let parents = procfs::process::all_processes()?
+        .filter_map(|p| p.ok()?.stat().ok())
+        .map(|stat| (stat.pid, stat.ppid))
+        .collect::<HashMap<_, _>>();
+    let depth_below = |mut child: i32| {
+        let mut depth = 0;
+        while let Some(ppid) = parents.get(&child) {
+            depth += 1;
+            if *ppid == pid {
+                return Some(depth);
+            }
+            if depth > parents.len() {
+                break;
+            }
+            child = *ppid;
+        }
+        None
+    };
+    let mut children = ptree::collect_probe_processes(false)
+        .await?
+        .into_iter()
+        .filter_map(|p| Some((depth_below(p.pid)?, p.pid)))
+        .collect::<Vec<_>>();
+    children.sort();
+    if children.is_empty() {
+        bail!("no child of {pid} has a probe");
+    }
+    Ok(children.into_iter().map(|(_, pid)| pid).collect())
+}
+
+/// Print every line of `text` after the pid it came from
+fn print_keyed(pid: i32, text: &str, stderr: bool) {
+    for line in text.lines() {
+        match stderr {
+            true => eprintln!("{pid}: {line}"),
+            false => println!("{pid}: {line}"),
+        }
+    }
+}
+
+pub async fn eval(ctrl: ProbeEndpoint, code: String) -> Result<()> {
+    let children = probed_children(target_pid(&ctrl)?).await?;
+    // started together, so the workers are seen in the same state
+    let tasks = children
+        .iter()
+        .map(|pid| {
+            let (pid, code) = (*pid, code.clone());
+            tokio::spawn(async move { ProbeEndpoint::Local { pid }.eval_result(code).await })
+        })
+        .collect::<Vec<_>>();
+
+    let mut failed = 0;
+    for (pid, task) in children.iter().zip(tasks) {
+        let result: Result<EvalResult> = task.await?;
+        match result {
+            Ok(result) => {
+                print_keyed(*pid, &result.stdout, false);
+                print_keyed(*pid, &result.stderr, true);
+                if let Some(value) = &result.value {
+                    print_keyed(*pid, &value.repr, false);
+                }
+                if let Some(error) = &result.error {
+                    print_keyed(*pid, &error.to_string(), true);
+                    failed += 1;
+                }
+            }
+            Err(err) => {
+                eprintln!("{pid}: {err}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("failed in {failed} of {} children", children.len());
+    }
+    Ok(())
+}
+
+/// `df` with a leading `pid` column
+fn keyed_frame(pid: i32, df: DataFrame) -> DataFrame {
+    let mut cols = vec![Seq::SeqI32(vec![pid; df.len()])];
+    cols.extend(df.cols);
+    let mut names = vec!["pid".to_string()];
+    names.extend(df.names);
+    DataFrame::new(names, cols)
+}
+
+/// Rows of `more` appended to `all`, both with the same columns
+fn append(all: &mut DataFrame, more: DataFrame) -> Result<()> {
+    if all.names.is_empty() {
+        *all = more;
+        return Ok(());
+    }
+    if all.names != more.names {
+        bail!("columns differ from those of the other children");
+    }
+    for (col, other) in all.cols.iter_mut().zip(more.cols.iter()) {
+        col.extend(other)
+            .map_err(|_| anyhow::anyhow!("column types differ from those of the other children"))?;
+    }
+    Ok(())
+}
+
+pub async fn query(ctrl: ProbeEndpoint, query: Query, render: Render) -> Result<()> {
+    let children = probed_children(target_pid(&ctrl)?).await?;
+    let tasks = children
+        .iter()
+        .map(|pid| {
+            let (pid, query) = (*pid, query.clone());
+            tokio::spawn(async move { ProbeEndpoint::Local { pid }.query(query).await })
+        })
+        .collect::<Vec<_>>();
+
+    let mut all = DataFrame::default();
+    let mut failed = 0;
+    for (pid, task) in children.iter().zip(tasks) {
+        let rows = task.await?.and_then(|df| match df.names.is_empty() {
+            // a statement such as `set` answers without a table
+            true => Ok(()),
+            false => append(&mut all, keyed_frame(*pid, df)),
+        });
+        if let Err(err) = rows {
+            eprintln!("{pid}: {err}");
+            failed += 1;
+        }
+    }
+    render_dataframe_as(&all, render);
+    if failed > 0 {
+        bail!("failed in {failed} of {} children", children.len());
+    }
+    Ok(())
+}
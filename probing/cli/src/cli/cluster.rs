@@ -0,0 +1,175 @@
+//! `probing <master> cluster config`: change an option of every probe that
+//! reports to the master.
+//!
+//! With `--two-phase` the change is first validated by every probe and only
+//! applied once all of them accept it; should applying still fail on one of
+//! them, the probes already changed get their previous value back, so that a
+//! coordinated profiling run never starts on a half-configured cluster.
+
+use anyhow::{bail, Result};
+use clap::{Args, Subcommand};
+use probing_proto::prelude::{Node, OptionChange, OptionCheck, Query};
+use probing_proto::protocol::handshake::FEATURE_OPTION_VALIDATE;
+
+use super::ctrl::{handshake, request, request_json, ProbeEndpoint};
+
+#[derive(Subcommand, Debug)]
+pub enum ClusterCommand {
+    /// Set an option on the probes of the cluster, e.g. `pprof.sample_freq=99`
+    Config(ClusterConfigCommand),
+}
+
+#[derive(Args, Debug)]
+pub struct ClusterConfigCommand {
+    /// `key=value`, with or without the `probing.` prefix
+    setting: String,
+
+    /// Every node reported to the target
+    #[arg(long, required_unless_present = "nodes")]
+    all: bool,
+
+    /// `host:port` of a probe to change, may be repeated
+    #[arg(long = "node", value_name = "ADDR", conflicts_with = "all")]
+    nodes: Vec<String>,
+
+    /// Validate the change on every probe first, apply it only if all accept
+    /// it and roll it back if applying fails on any of them
+    #[arg(long)]
+    two_phase: bool,
+}
+
+impl ClusterCommand {
+    pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        match self {
+            ClusterCommand::Config(cmd) => cmd.run(ctrl).await,
+        }
+    }
+}
+
+/// Address a node can be reached at, its host when it listens on all
+/// interfaces
+fn node_addr(node: &Node) -> String {
+    match node.addr.strip_prefix("0.0.0.0:") {
+        Some(port) => format!("{}:{port}", node.host),
+        None => node.addr.clone(),
+    }
+}
+
+async fn set(addr: &str, key: &str, value: &str) -> Result<()> {
+    let ctrl = ProbeEndpoint::Remote {
+        addr: addr.to_string(),
+    };
+    ctrl.query(Query::new(format!("set probing.{key}={value}")))
+        .await
+        .map(|_| ())
+}
+
+async fn validate(addr: &str, change: &OptionChange) -> Result<OptionCheck> {
+    let ctrl = ProbeEndpoint::Remote {
+        addr: addr.to_string(),
+    };
+    if !handshake(&ctrl, false)
+        .await?
+        .supports(FEATURE_OPTION_VALIDATE)
+    {
+        bail!("the probe cannot validate options, upgrade it");
+    }
+    let reply = request_json(
+        ctrl,
+        "/apis/extensions/options/validate",
+        serde_json::to_string(change)?,
+    )
+    .await?;
+    serde_json::from_slice::<OptionCheck>(&reply)
+        .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))
+}
+
+impl ClusterConfigCommand {
+    async fn targets(&self, ctrl: ProbeEndpoint) -> Result<Vec<String>> {
+        if !self.all {
+            return Ok(self.nodes.clone());
+        }
+        let reply = request(ctrl, "/apis/nodes", None).await?;
+        let nodes = serde_json::from_slice::<Vec<Node>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        let mut addrs = nodes.iter().map(node_addr).collect::<Vec<_>>();
+        addrs.sort();
+        addrs.dedup();
+        if addrs.is_empty() {
+            bail!("no node reports to the target");
+        }
+        Ok(addrs)
+    }
+
+    pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        let Some((key, value)) = self.setting.split_once('=') else {
+            bail!("invalid setting `{}`, expected `key=value`", self.setting);
+        };
+        let change = OptionChange {
+            key: key.trim().trim_start_matches("probing.").to_string(),
+            value: value.trim().to_string(),
+        };
+        let targets = self.targets(ctrl).await?;
+
+        if !self.two_phase {
+            let mut failed = 0;
+            for addr in &targets {
+                match set(addr, &change.key, &change.value).await {
+                    Ok(()) => println!("{addr:<24}applied"),
+                    Err(err) => {
+                        println!("{addr:<24}failed: {err}");
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                bail!("{failed} of {} probes were not changed", targets.len());
+            }
+            return Ok(());
+        }
+
+        // phase 1: every probe must accept the change
+        let mut previous = vec![];
+        let mut rejected = 0;
+        for addr in &targets {
+            let (error, current) = match validate(addr, &change).await {
+                Ok(check) => (check.error, check.current),
+                Err(err) => (Some(err.to_string()), None),
+            };
+            match error {
+                None => previous.push((addr, current.unwrap_or_default())),
+                Some(err) => {
+                    println!("{addr:<24}rejected: {err}");
+                    rejected += 1;
+                }
+            }
+        }
+        if rejected > 0 {
+            bail!(
+                "{rejected} of {} probes rejected {}={}, nothing was changed",
+                targets.len(),
+                change.key,
+                change.value
+            );
+        }
+
+        // phase 2: apply one probe after the other, undoing on failure
+        for (i, (addr, _)) in previous.iter().enumerate() {
+            if let Err(err) = set(addr, &change.key, &change.value).await {
+                println!("{addr:<24}failed: {err}");
+                for (addr, old) in previous[..i].iter().rev() {
+                    match set(addr, &change.key, old).await {
+                        Ok(()) => println!("{addr:<24}rolled back to `{old}`"),
+                        Err(err) => println!("{addr:<24}rollback failed: {err}"),
+                    }
+                }
+                bail!(
+                    "applying {} failed on {addr}, the change was rolled back",
+                    change.key
+                );
+            }
+            println!("{addr:<24}applied");
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,14 @@
 use clap::{Args, Subcommand};
 
+use super::archive::OpenCommand;
+use super::bench::BenchCommand;
+use super::cluster::ClusterCommand;
+use super::compare::CompareCommand;
+use super::dash::DashCommand;
+use super::proxy::ProxyCommand;
 use super::store::StoreCommand;
+use super::tunnel::TunnelCommand;
+use crate::table::Render;
 
 #[derive(Args, Default, Debug)]
 pub struct Settings {
@@ -123,6 +131,45 @@ impl Settings {
     }
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ExtensionsCommand {
+    /// List extensions and whether they are enabled (default)
+    #[command(visible_aliases = ["ls"])]
+    List,
+
+    /// Enable an extension, e.g. `taskstats`
+    Enable { name: String },
+
+    /// Disable an extension, stopping its background collectors
+    Disable { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// List the snapshots stored in the target process (default)
+    #[command(visible_aliases = ["ls"])]
+    List,
+
+    /// Capture envs, settings, loaded libraries and python packages as `name`
+    Save { name: String },
+
+    /// Show what changed between snapshots `a` and `b`
+    Diff { a: String, b: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionCommand {
+    /// List the sessions saved in the target process (default)
+    #[command(visible_aliases = ["ls"])]
+    List,
+
+    /// Save the statements run since the last save as `name`
+    Save { name: String },
+
+    /// Print a saved session, or the current one, as a Python script
+    Show { name: Option<String> },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     #[cfg(target_os = "linux")]
@@ -132,8 +179,10 @@ pub enum Commands {
     /// List all processes with injected probes
     #[command(visible_aliases = ["ls", "l"])]
     List {
-        #[arg(short, long, help = "Show detailed information")]
-        verbose: bool,
+        /// Show sockets, addresses and worker labels; repeat (`-vv`) to add
+        /// the resources each probe takes
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
 
         #[arg(short, long, help = "Show processes as a tree structure")]
         tree: bool,
@@ -150,7 +199,18 @@ pub enum Commands {
 
     /// Show the backtrace of the target process or thread
     #[command(visible_aliases = ["bt", "b"])]
-    Backtrace { tid: Option<i32> },
+    Backtrace {
+        tid: Option<i32>,
+
+        /// Show the threads without Python state, e.g. NCCL watchdog or IO
+        /// threads, with their native frames
+        #[arg(long, conflicts_with = "all")]
+        native: bool,
+
+        /// Show every thread, each marked as `python` or `native`
+        #[arg(long)]
+        all: bool,
+    },
 
     /// Get RDMA flow of the target process or thread
     #[command(visible_aliases = ["rd", "rdma"])]
@@ -159,8 +219,28 @@ pub enum Commands {
     /// Evaluate Python code in the target process
     #[command(visible_aliases = ["e"])]
     Eval {
-        #[arg()]
-        code: String,
+        #[arg(required_unless_present = "script", conflicts_with = "script")]
+        code: Option<String>,
+
+        /// Run a script statement by statement, printing the output of each
+        #[arg(long, value_name = "FILE")]
+        script: Option<std::path::PathBuf>,
+
+        /// Run the remaining statements of the script after one fails
+        #[arg(long, requires = "script")]
+        keep_going: bool,
+
+        /// Evaluate in every probed child of the target instead, e.g. the
+        /// dataloader workers, prefixing each output line with its pid
+        #[arg(long, conflicts_with = "script")]
+        children: bool,
+    },
+
+    /// Replay REPL and eval sessions recorded in the target process
+    #[command()]
+    Session {
+        #[command(subcommand)]
+        command: Option<SessionCommand>,
     },
 
     /// Query data from the target process
@@ -168,6 +248,156 @@ pub enum Commands {
     Query {
         #[arg()]
         query: String,
+
+        /// Re-run the query every N seconds
+        #[arg(short, long, value_name = "SECONDS")]
+        watch: Option<f64>,
+
+        /// Draw numeric columns as sparklines or value-colored cells
+        #[arg(long, value_enum, default_value_t = Render::Table)]
+        render: Render,
+
+        /// Query every probed child of the target instead and concatenate
+        /// the results, keyed by a leading `pid` column
+        #[arg(long, conflicts_with = "watch")]
+        children: bool,
+    },
+
+    /// Run curated diagnostics and print a summary (cpu, memory, steps, io)
+    #[command()]
+    Report {
+        #[arg(value_enum)]
+        kind: super::report::ReportKind,
+    },
+
+    /// Live panels of the target redrawn in the terminal: CPU and GPU,
+    /// top functions, last exceptions and active spans
+    #[command()]
+    Dash(DashCommand),
+
+    /// Act on every probe reporting to the target
+    #[command(subcommand)]
+    Cluster(ClusterCommand),
+
+    /// Show the step, loss, CPU usage and GPU utilization of the target
+    #[command()]
+    Status {
+        /// Read the shared-memory status of a probe on this host directly,
+        /// without a request to the target
+        #[arg(long)]
+        fast: bool,
+    },
+
+    /// Stop every collector of the target and close its remote listener,
+    /// leaving a dormant probe that only a client on its host can resume
+    #[command()]
+    KillSwitch {
+        /// Resume a dormant probe instead
+        #[arg(long)]
+        resume: bool,
+    },
+
+    /// Collect the tables and settings of the target into a bundle that
+    /// `probing open` can read
+    #[command()]
+    Diagnose {
+        /// Redact secrets, hostnames and paths so the bundle can be attached
+        /// to a public issue, and write a manifest of what was removed
+        #[arg(long)]
+        share: bool,
+
+        /// Path of the bundle, `probing-diagnose-<time>.probe` by default
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Push a snapshot of tables from the target to the store of
+    /// `replication.peers` or to a parquet file in `export.dir` on the
+    /// target's host
+    #[command()]
+    Export {
+        /// Table to export, e.g. `python.stacks`; repeat for several
+        #[arg(long = "table", required = true)]
+        tables: Vec<String>,
+
+        /// `store`, or a `.parquet` file in `export.dir` on the target's host
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Run the self-tests of the extensions of the target, telling why
+    /// their tables may stay empty
+    #[command()]
+    Doctor {
+        /// Extension to test, e.g. `taskstats`, or a table to test the
+        /// extension serving it; every extension by default
+        name: Option<String>,
+    },
+
+    /// Load the target with concurrent queries and report their latency
+    /// distribution and the CPU the target spends meanwhile
+    #[command()]
+    Bench(BenchCommand),
+
+    /// Compare metrics of the target with another rank, or over two windows,
+    /// aligned by step or time with their differences
+    #[command()]
+    Compare(CompareCommand),
+
+    /// Serve the target on a local address, caching the web UI and
+    /// retrying queries, with the last good replies kept for short network
+    /// partitions
+    #[command()]
+    Proxy(ProxyCommand),
+
+    /// Forward a local port to the Unix socket of a probe on this host, to
+    /// open its web UI when `probing list` shows it out of reach
+    #[command()]
+    Tunnel(TunnelCommand),
+
+    /// List, enable or disable extensions of the target process
+    #[command(visible_aliases = ["ext"])]
+    Extensions {
+        #[command(subcommand)]
+        command: Option<ExtensionsCommand>,
+    },
+
+    /// Save and compare snapshots of the target's environment and settings
+    #[command(visible_aliases = ["snap"])]
+    Snapshot {
+        #[command(subcommand)]
+        command: Option<SnapshotCommand>,
+    },
+
+    /// Stop the target, or with --pgid/--sid every process of the group,
+    /// children before their parents
+    #[command()]
+    Pause,
+
+    /// Continue processes stopped by `pause`, parents before their children
+    #[command()]
+    Resume,
+
+    /// Print the Python stacks of the target or of every probed process of
+    /// the group
+    #[command()]
+    Dump,
+
+    /// Start sampling CPU profiles in the target or every probed process of
+    /// the group
+    #[command()]
+    Profile {
+        /// Sample frequency in Hz
+        #[arg(long, default_value_t = 100)]
+        freq: i32,
+    },
+
+    /// Show the version of the CLI, or of the target probe with --remote
+    #[command()]
+    Version {
+        /// Query build metadata and features of the target probe
+        #[arg(short, long)]
+        remote: bool,
     },
 
     /// Launch new Python process
@@ -180,6 +410,10 @@ pub enum Commands {
         args: Vec<String>,
     },
 
+    /// Open an offline probe archive for postmortem analysis
+    #[command()]
+    Open(OpenCommand),
+
     #[command(external_subcommand)]
     External(Vec<String>),
 
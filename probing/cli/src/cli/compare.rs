@@ -0,0 +1,129 @@
+//! `probing compare`: metrics of the target next to those of another rank, or
+//! of another window of the target, joined by the probe on step or time.
+
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use probing_proto::prelude::{AlignBy, CompareRequest, CompareSide, DataFrame};
+
+use crate::cli::ctrl::{request_json, ProbeEndpoint};
+use crate::table::render_dataframe;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    /// Match rows on the `step` column
+    Step,
+    /// Match rows on buckets of the `timestamp` column
+    Time,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareCommand {
+    /// Table holding the metrics, e.g. python.torch_trace
+    table: String,
+
+    /// Comma separated metric columns
+    #[arg(short, long, value_delimiter = ',', required = true)]
+    metrics: Vec<String>,
+
+    /// `host:port` of the probe to compare the target with, a node of its
+    /// cluster
+    #[arg(long, value_name = "ADDR")]
+    with: Option<String>,
+
+    /// Window of the target, `<from>..<to>` with each end in seconds since
+    /// epoch or relative to now (`-10m`), either end may be left out
+    #[arg(long)]
+    window: Option<String>,
+
+    /// Window of the other side, the target itself unless --with is given
+    #[arg(long)]
+    with_window: Option<String>,
+
+    #[arg(long, value_enum, default_value_t = Align::Step)]
+    align: Align,
+
+    /// Seconds of the time buckets the rows are averaged over
+    #[arg(long, default_value_t = 1.0)]
+    bucket: f64,
+}
+
+/// One end of a window in microseconds since epoch
+fn parse_instant(value: &str, now: i64) -> Result<i64> {
+    let Some(ago) = value.strip_prefix('-') else {
+        return Ok((value.parse::<f64>()? * 1e6) as i64);
+    };
+    let split = ago
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(ago.len());
+    let (number, unit) = ago.split_at(split);
+    let scale = match unit {
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        _ => bail!("invalid duration `{value}`"),
+    };
+    Ok(now - (number.parse::<f64>()? * scale * 1e6) as i64)
+}
+
+fn parse_window(window: Option<&str>, now: i64) -> Result<(Option<i64>, Option<i64>)> {
+    let Some(window) = window else {
+        return Ok((None, None));
+    };
+    let Some((from, to)) = window.split_once("..") else {
+        bail!("invalid window `{window}`, expected `<from>..<to>`");
+    };
+    let end = |value: &str| match value.trim() {
+        "" => Ok(None),
+        value => parse_instant(value, now).map(Some),
+    };
+    Ok((end(from)?, end(to)?))
+}
+
+impl CompareCommand {
+    pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        if self.with.is_none() && self.with_window.is_none() {
+            bail!("nothing to compare with, give --with or --with-window");
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as i64;
+        let (from, to) = parse_window(self.window.as_deref(), now)?;
+        let left = CompareSide {
+            target: None,
+            from,
+            to,
+        };
+        let (from, to) = match &self.with_window {
+            Some(window) => parse_window(Some(window), now)?,
+            None => (left.from, left.to),
+        };
+        let right = CompareSide {
+            target: self.with.clone(),
+            from,
+            to,
+        };
+        let compare = CompareRequest {
+            table: self.table.clone(),
+            metrics: self.metrics.clone(),
+            align: match self.align {
+                Align::Step => AlignBy::Step,
+                Align::Time => AlignBy::Time,
+            },
+            bucket: (self.bucket * 1e6) as i64,
+            left,
+            right,
+        };
+
+        let body = serde_json::to_string(&compare)?;
+        let reply = request_json(ctrl, "/apis/compare", body).await?;
+        let df = serde_json::from_slice::<DataFrame>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        if df.is_empty() {
+            println!("no step or time bucket found on both sides");
+            return Ok(());
+        }
+        render_dataframe(&df);
+        Ok(())
+    }
+}
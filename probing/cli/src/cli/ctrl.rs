@@ -1,22 +1,190 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
 use anyhow::Result;
 
 use http_body_util::{BodyExt, Full};
 use hyper_util::rt::TokioIo;
+use once_cell::sync::Lazy;
 
+use probing_core::core::hot_metrics;
+use probing_proto::protocol::frame::{decode_frame, encode_frame, frame_len, FRAME_HEADER_SIZE};
+use probing_proto::protocol::handshake::{
+    FEATURE_BINARY_FRAME, FEATURE_EVAL_JSON, FEATURE_EVAL_SCRIPT, FEATURE_KILL_SWITCH,
+    FEATURE_QUERY_CANCEL, FEATURE_QUERY_CODECS, FEATURE_SELFTEST, FEATURE_STATUS,
+    FEATURE_TABLE_EXPORT, FEATURE_THREAD_STACKS,
+};
 use probing_proto::{prelude::*, protocol::process::CallFrame};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::table::{render_dataframe, render_dataframe_as, Render};
 
-use crate::table::render_dataframe;
+/// Handshakes of the probes talked to in this run, by endpoint
+static PEERS: Lazy<Mutex<HashMap<String, Handshake>>> = Lazy::new(Default::default);
 
-pub async fn query(ctrl: ProbeEndpoint, query: Query) -> Result<()> {
+/// Exchange protocol versions and features with the probe behind `ctrl`.
+///
+/// The result is cached unless `renew` is set, e.g. after a reply could not
+/// be decoded because the probe was restarted with another version. Probes
+/// predating the handshake are treated as [`Handshake::legacy`]. Fails with a
+/// clear error if the probe speaks an incompatible protocol or rejects the
+/// handshake.
+pub async fn handshake(ctrl: &ProbeEndpoint, renew: bool) -> Result<Handshake> {
+    let endpoint = String::from(ctrl.clone());
+    if !renew {
+        if let Some(peer) = PEERS.lock().unwrap().get(&endpoint) {
+            return Ok(peer.clone());
+        }
+    }
+    let client = Handshake::current(env!("CARGO_PKG_VERSION"));
+    let body = serde_json::to_string(&client)?;
+    let (status, reply) = send(
+        ctrl.clone(),
+        "POST",
+        "/apis/handshake",
+        Some(body),
+        Some("application/json"),
+    )
+    .await?;
+    let peer = match status {
+        // no handshake route before the protocol had one
+        hyper::StatusCode::NOT_FOUND | hyper::StatusCode::METHOD_NOT_ALLOWED => Handshake::legacy(),
+        status if status.is_success() => {
+            serde_json::from_slice::<Handshake>(&reply).unwrap_or_else(|_| Handshake::legacy())
+        }
+        status => anyhow::bail!(
+            "handshake with the probe failed ({status}): {}",
+            String::from_utf8_lossy(&reply)
+        ),
+    };
+    client.check(&peer).map_err(|err| anyhow::anyhow!(err))?;
+    log::debug!(
+        "probe speaks protocol {} with features {:?}",
+        peer.protocol,
+        peer.features
+    );
+    PEERS.lock().unwrap().insert(endpoint, peer.clone());
+    Ok(peer)
+}
+
+/// Print what an evaluation wrote, then the value of its last expression
+fn print_eval_result(result: &EvalResult) {
+    print!("{}", result.stdout);
+    eprint!("{}", result.stderr);
+    if let Some(value) = &result.value {
+        println!("{}", value.repr);
+        if value.truncated {
+            eprintln!("... ({} output truncated)", value.type_name);
+        }
+    }
+}
+
+/// Error for a reply of `ctrl` that could not be decoded, naming the probe
+/// protocol
+fn decode_error(ctrl: &ProbeEndpoint, err: serde_json::Error) -> anyhow::Error {
+    match PEERS.lock().unwrap().get(&String::from(ctrl.clone())) {
+        Some(peer) => anyhow::anyhow!(
+            "cannot decode the reply of the probe (probing {}, protocol {}): {err}",
+            peer.version,
+            peer.protocol
+        ),
+        None => anyhow::anyhow!("cannot decode the reply of the probe: {err}"),
+    }
+}
+
+pub async fn query(ctrl: ProbeEndpoint, query: Query, render: Render) -> Result<()> {
     let reply = ctrl.query(query).await?;
-    render_dataframe(&reply);
+    render_dataframe_as(&reply, render);
     Ok(())
 }
 
+/// Re-run a query every `interval` seconds, using the binary socket when the
+/// target is a local process.
+pub async fn watch_query(
+    ctrl: ProbeEndpoint,
+    query: Query,
+    interval: f64,
+    render: Render,
+) -> Result<()> {
+    let peer = handshake(&ctrl, false).await?;
+    let mut binary = match &ctrl {
+        ProbeEndpoint::Ptrace { pid } | ProbeEndpoint::Local { pid }
+            if peer.supports(FEATURE_BINARY_FRAME) =>
+        {
+            BinaryClient::connect(*pid).await.ok()
+        }
+        _ => None,
+    };
+    let interval = std::time::Duration::from_secs_f64(interval.max(0.1));
+    // the same table is fetched over and over, keep the replies small
+    let query = match peer.supports(FEATURE_QUERY_CODECS) {
+        true => query.with_codecs(Codec::available()),
+        false => query,
+    };
+
+    loop {
+        let reply = match binary.as_mut() {
+            Some(client) => client.query(query.clone()).await,
+            None => ctrl.query(query.clone()).await,
+        };
+        // the probe may have been restarted with another version meanwhile
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(err) => {
+                handshake(&ctrl, true).await?;
+                return Err(err);
+            }
+        };
+        print!("\x1b[2J\x1b[H");
+        println!("Every {:.1}s: {}\n", interval.as_secs_f64(), query.expr);
+        render_dataframe_as(&reply, render);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Persistent connection to the binary query socket of a local probe
+pub struct BinaryClient {
+    stream: tokio::net::UnixStream,
+}
+
+impl BinaryClient {
+    pub async fn connect(pid: i32) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let path = format!("\0probing-{pid}.bin");
+        #[cfg(not(target_os = "linux"))]
+        let path = std::env::temp_dir()
+            .join(format!("probing-{pid}.bin.sock"))
+            .to_string_lossy()
+            .to_string();
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        Ok(Self { stream })
+    }
+
+    pub async fn query(&mut self, q: Query) -> Result<DataFrame> {
+        self.stream
+            .write_all(&encode_frame(&Message::new(q))?)
+            .await?;
+
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        self.stream.read_exact(&mut header).await?;
+        let mut payload = vec![0u8; frame_len(header)?];
+        self.stream.read_exact(&mut payload).await?;
+
+        match decode_frame::<QueryDataFormat>(&payload)?.payload {
+            QueryDataFormat::Error(err) => Err(anyhow::anyhow!("error: {}", err)),
+            QueryDataFormat::Nil => Ok(Default::default()),
+            QueryDataFormat::DataFrame(df) => Ok(df),
+            QueryDataFormat::TimeSeries(_) => Err(anyhow::anyhow!("unexpected time series reply")),
+            QueryDataFormat::Compressed(frame) => Ok(frame.decompress()?),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum ProbeEndpoint {
     Ptrace { pid: i32 },
     Local { pid: i32 },
+    Named { pid: i32, name: String },
     Remote { addr: String },
     Launch { cmd: String },
 }
@@ -28,6 +196,12 @@ impl TryFrom<&str> for ProbeEndpoint {
         if let [_, _] = value.split(':').collect::<Vec<_>>()[..] {
             return Ok(Self::Remote { addr: value.into() });
         }
+        if let Some((pid, name)) = value.split_once('@') {
+            return Ok(Self::Named {
+                pid: pid.parse::<i32>()?,
+                name: name.into(),
+            });
+        }
 
         Ok(Self::Local {
             pid: value.parse::<i32>()?,
@@ -47,6 +221,7 @@ impl From<ProbeEndpoint> for String {
     fn from(val: ProbeEndpoint) -> Self {
         match val {
             ProbeEndpoint::Ptrace { pid } | ProbeEndpoint::Local { pid } => format! {"{pid}"},
+            ProbeEndpoint::Named { pid, name } => format!("{pid}@{name}"),
             ProbeEndpoint::Remote { addr } => addr,
             ProbeEndpoint::Launch { cmd } => cmd,
         }
@@ -77,6 +252,99 @@ impl ProbeEndpoint {
         }
     }
 
+    /// Print the stacks of thread `tid`, or of every thread, marking each as
+    /// `python` or `native`; with `native_only` threads running Python are
+    /// left out
+    pub async fn thread_stacks(&self, tid: Option<i32>, native_only: bool) -> Result<()> {
+        if !handshake(self, false)
+            .await?
+            .supports(FEATURE_THREAD_STACKS)
+        {
+            anyhow::bail!("the probe cannot capture per-thread stacks, upgrade it");
+        }
+        let mut params = vec![];
+        if let Some(tid) = tid {
+            params.push(format!("tid={tid}"));
+        }
+        if native_only {
+            params.push(format!("kind={}", StackKind::Native));
+        }
+        let url = format!("/apis/pythonext/threads?{}", params.join("&"));
+        let reply = request(self.clone(), &url, None).await?;
+        let stacks = serde_json::from_slice::<Vec<ThreadStack>>(&reply)
+            .map_err(|err| decode_error(self, err))?;
+        if stacks.is_empty() {
+            println!("no matching threads");
+        }
+        for stack in stacks {
+            println!("{stack}");
+        }
+        Ok(())
+    }
+
+    pub async fn status(&self) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_STATUS) {
+            anyhow::bail!("the probe does not report its status, upgrade it or use --fast");
+        }
+        let reply = request(self.clone(), "/apis/status", None).await?;
+        let status =
+            serde_json::from_slice::<ProbeStatus>(&reply).map_err(|err| decode_error(self, err))?;
+        println!("{status}");
+        Ok(())
+    }
+
+    /// Print the status from the shared-memory segment of a probe on this
+    /// host, without any request to the target
+    pub fn fast_status(&self) -> Result<()> {
+        let pid = match self {
+            Self::Ptrace { pid } | Self::Local { pid } | Self::Named { pid, .. } => *pid,
+            _ => anyhow::bail!("--fast only reads probes on this host, given by pid"),
+        };
+        if nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None).is_err() {
+            anyhow::bail!("process {pid} is not running");
+        }
+        let path = hot_metrics::segment_path(pid as u32);
+        let segment = hot_metrics::Segment::open(&path)
+            .map_err(|err| anyhow::anyhow!("no status segment for process {pid}: {err}"))?;
+        println!("{}", segment.status());
+        Ok(())
+    }
+
+    /// Engage the kill switch of the probe, or release it with `resume`
+    pub async fn kill_switch(&self, resume: bool) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_KILL_SWITCH) {
+            anyhow::bail!("the probe has no kill switch, upgrade it");
+        }
+        if resume && matches!(self, Self::Remote { .. }) {
+            anyhow::bail!("a dormant probe can only be resumed from its host, by pid");
+        }
+        let method = if resume { "DELETE" } else { "POST" };
+        let reply = request_with_method(self.clone(), method, "/apis/killswitch", None).await?;
+        println!("{}", String::from_utf8_lossy(&reply));
+        Ok(())
+    }
+
+    /// Have the probe push a snapshot of `tables` to `store` or a parquet file
+    /// on its own host
+    pub async fn export(&self, tables: &[String], to: &str) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_TABLE_EXPORT) {
+            anyhow::bail!("the probe cannot export tables, upgrade it");
+        }
+        let body = serde_json::json!({ "tables": tables, "to": to }).to_string();
+        let reply = request_json(self.clone(), "/apis/export", body).await?;
+        let exported = serde_json::from_slice::<Vec<serde_json::Value>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        for table in exported {
+            println!(
+                "{}: {} rows to {}",
+                table["table"].as_str().unwrap_or_default(),
+                table["rows"],
+                table["destination"].as_str().unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
     pub async fn rdma(&self, hca_name: String) -> Result<()> {
         let reply = request(self.clone(), "/apis/rdmaextension/", Some(hca_name)).await?;
 
@@ -85,34 +353,380 @@ impl ProbeEndpoint {
         Ok(())
     }
 
+    /// Evaluate `code` in the target without printing anything
+    pub async fn eval_result(&self, code: String) -> Result<EvalResult> {
+        let url = match handshake(self, false).await?.supports(FEATURE_EVAL_JSON) {
+            true => "/apis/pythonext/eval?format=json",
+            false => "/apis/pythonext/eval",
+        };
+        let reply = request(self.clone(), url, Some(code)).await?;
+
+        // probes without structured results answer with plain text
+        Ok(
+            serde_json::from_slice::<EvalResult>(&reply).unwrap_or_else(|_| EvalResult {
+                stdout: format!("{}\n", String::from_utf8_lossy(&reply)),
+                ..Default::default()
+            }),
+        )
+    }
+
     pub async fn eval(&self, code: String) -> Result<()> {
-        let reply = request(self.clone(), "/apis/pythonext/eval", Some(code)).await?;
+        let result = self.eval_result(code).await?;
+        print_eval_result(&result);
+        match result.error {
+            Some(error) => Err(anyhow::anyhow!("{error}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Run `path` statement by statement, echoing each before its output
+    pub async fn eval_script(&self, path: &std::path::Path, keep_going: bool) -> Result<()> {
+        let script = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {e}", path.display()))?;
+        if !handshake(self, false).await?.supports(FEATURE_EVAL_SCRIPT) {
+            anyhow::bail!("the probe cannot run scripts, upgrade it");
+        }
+        let url = match keep_going {
+            true => "/apis/pythonext/script?keep_going=1",
+            false => "/apis/pythonext/script",
+        };
+        let reply = request(self.clone(), url, Some(script)).await?;
+        let steps = serde_json::from_slice::<Vec<ScriptStep>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+
+        let mut failed = 0;
+        for step in &steps {
+            for (i, line) in step.source.lines().enumerate() {
+                let prompt = if i == 0 { ">>>" } else { "..." };
+                println!("{prompt} {line}");
+            }
+            print_eval_result(&step.result);
+            if let Some(error) = &step.result.error {
+                eprintln!("{error}");
+                failed += 1;
+            }
+        }
+        match failed {
+            0 => Ok(()),
+            _ if keep_going => Err(anyhow::anyhow!("{failed} statement(s) failed")),
+            _ => {
+                let line = steps.last().map(|step| step.line).unwrap_or_default();
+                Err(anyhow::anyhow!(
+                    "stopped at line {line} of {}",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    /// List the saved sessions of the target, with when they started
+    pub async fn sessions(&self) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_EVAL_SCRIPT) {
+            anyhow::bail!("the probe does not record sessions, upgrade it");
+        }
+        let reply = request(self.clone(), "/apis/pythonext/sessions", None).await?;
+        let sessions = serde_json::from_slice::<Vec<SessionMeta>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        for session in sessions {
+            println!(
+                "{:<24}{:<12}{} statements",
+                session.name,
+                super::dash::age(session.timestamp as i64),
+                session.statements
+            );
+        }
+        Ok(())
+    }
+
+    /// Save the current session of the target, printing its script
+    pub async fn save_session(&self, name: &str) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_EVAL_SCRIPT) {
+            anyhow::bail!("the probe does not record sessions, upgrade it");
+        }
+        let url = format!("/apis/pythonext/session?save={name}");
+        let reply = request(self.clone(), &url, None).await?;
+        print!("{}", String::from_utf8(reply)?);
+        Ok(())
+    }
+
+    pub async fn show_session(&self, name: Option<&str>) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_EVAL_SCRIPT) {
+            anyhow::bail!("the probe does not record sessions, upgrade it");
+        }
+        let url = match name {
+            Some(name) => format!("/apis/pythonext/session?name={name}"),
+            None => "/apis/pythonext/session".to_string(),
+        };
+        let reply = request(self.clone(), &url, None).await?;
+        print!("{}", String::from_utf8(reply)?);
+        Ok(())
+    }
+
+    pub async fn extensions(&self) -> Result<()> {
+        let reply = request(self.clone(), "/apis/extensions", None).await?;
+        let extensions = serde_json::from_slice::<Vec<ExtensionStatus>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        for ext in extensions {
+            let state = if ext.enabled { "enabled" } else { "disabled" };
+            println!("{:<16}{state}", ext.name);
+        }
+        Ok(())
+    }
+
+    async fn option_statuses(&self) -> Result<Vec<OptionStatus>> {
+        let reply = request(self.clone(), "/apis/extensions/options", None).await?;
+        serde_json::from_slice::<Vec<OptionStatus>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))
+    }
+
+    /// Show the options with whether a change applies immediately or once
+    /// the extension is enabled again
+    pub async fn config_options(&self) -> Result<()> {
+        let options = self.option_statuses().await?;
+        let applies =
+            |option: &OptionStatus| match (option.requires_restart, option.restart_pending) {
+                (_, true) => "re-enable pending",
+                (true, false) => "on re-enable",
+                (false, false) => "immediately",
+            };
+        render_dataframe(&DataFrame::new(
+            ["name", "value", "applies", "depends_on", "description"]
+                .map(String::from)
+                .to_vec(),
+            vec![
+                Seq::SeqText(
+                    options
+                        .iter()
+                        .map(|option| format!("probing.{}", option.key))
+                        .collect(),
+                ),
+                Seq::SeqText(
+                    options
+                        .iter()
+                        .map(|option| option.value.clone().unwrap_or_default())
+                        .collect(),
+                ),
+                Seq::SeqText(
+                    options
+                        .iter()
+                        .map(|option| applies(option).to_string())
+                        .collect(),
+                ),
+                Seq::SeqText(
+                    options
+                        .iter()
+                        .map(|option| option.depends_on.clone().unwrap_or_default())
+                        .collect(),
+                ),
+                Seq::SeqText(options.iter().map(|option| option.help.clone()).collect()),
+            ],
+        ));
+        Ok(())
+    }
+
+    /// Tell which of the options just set wait for their extension to be
+    /// enabled again
+    pub async fn restart_notice(&self, keys: &[String]) -> Result<()> {
+        for option in self.option_statuses().await? {
+            if !option.restart_pending || !keys.contains(&option.key) {
+                continue;
+            }
+            let extension = option.key.split('.').next().unwrap_or_default();
+            eprintln!(
+                "probing.{} takes effect once {extension} is enabled again, run \
+                 `extensions disable {extension}` then `extensions enable {extension}`",
+                option.key
+            );
+        }
+        Ok(())
+    }
+
+    /// Run the self-test of extension `name`, or of every extension, and
+    /// print each check; fails when any check does
+    pub async fn doctor(&self, name: Option<&str>) -> Result<()> {
+        if !handshake(self, false).await?.supports(FEATURE_SELFTEST) {
+            anyhow::bail!("the probe has no extension self-tests, upgrade it");
+        }
+        let names = match name {
+            Some(name) => vec![name.to_string()],
+            None => {
+                let reply = request(self.clone(), "/apis/extensions", None).await?;
+                serde_json::from_slice::<Vec<ExtensionStatus>>(&reply)
+                    .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?
+                    .into_iter()
+                    .map(|ext| ext.name)
+                    .collect()
+            }
+        };
+        let mut failed = 0;
+        for name in names {
+            let url = format!("/apis/extensions/{name}/selftest");
+            let reply = request(self.clone(), &url, Some(String::new())).await?;
+            let selftest = serde_json::from_slice::<SelfTest>(&reply)
+                .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+            println!("{}", selftest.extension);
+            for check in &selftest.checks {
+                let mark = if check.passed { "✔" } else { "✘" };
+                println!("  {mark} {:<18}{}", check.name, check.detail);
+            }
+            if !selftest.passed() {
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("{failed} extensions failed their self-test");
+        }
+        Ok(())
+    }
+
+    pub async fn toggle_extension(&self, name: &str, enable: bool) -> Result<()> {
+        let action = if enable { "enable" } else { "disable" };
+        let url = format!("/apis/extensions/{name}/{action}");
+        let reply = request(self.clone(), &url, Some(String::new())).await?;
 
         println!("{}", String::from_utf8(reply)?);
 
         Ok(())
     }
 
+    pub async fn capabilities(&self) -> Result<()> {
+        let reply = request(self.clone(), "/apis/capabilities", None).await?;
+        let caps = serde_json::from_slice::<Capabilities>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        println!("{caps}");
+        Ok(())
+    }
+
+    pub async fn snapshots(&self) -> Result<()> {
+        let reply = request(self.clone(), "/apis/snapshots", None).await?;
+        let names = serde_json::from_slice::<Vec<String>>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))?;
+        for name in names {
+            println!("{name}");
+        }
+        Ok(())
+    }
+
+    pub async fn save_snapshot(&self, name: &str) -> Result<()> {
+        let url = format!("/apis/snapshots/{name}");
+        let reply = request(self.clone(), &url, Some(String::new())).await?;
+
+        println!("{}", String::from_utf8(reply)?);
+
+        Ok(())
+    }
+
+    async fn snapshot(&self, name: &str) -> Result<EnvSnapshot> {
+        let url = format!("/apis/snapshots/{name}");
+        let reply = request(self.clone(), &url, None).await?;
+        serde_json::from_slice::<EnvSnapshot>(&reply)
+            .map_err(|_| anyhow::anyhow!("error: {}", String::from_utf8_lossy(&reply)))
+    }
+
+    pub async fn diff_snapshots(&self, a: &str, b: &str) -> Result<()> {
+        let (before, after) = (self.snapshot(a).await?, self.snapshot(b).await?);
+        let changes = before.diff(&after);
+        if changes.is_empty() {
+            println!("no changes between {a} and {b}");
+        }
+        for change in changes {
+            println!("{change}");
+        }
+        Ok(())
+    }
+
+    /// Run a query under a fresh id; on Ctrl-C the query is cancelled on the
+    /// probe instead of only dropping the connection
     pub async fn query(&self, q: Query) -> Result<DataFrame> {
-        let request = Message::new(q);
-        let q_str = serde_json::to_string(&request)?;
-        let reply_str = self.send_request("/query", &q_str).await?; // Renamed reply variable
-        let reply = serde_json::from_str::<Message<QueryDataFormat>>(&reply_str)?.payload;
+        let cancellable = handshake(self, false).await?.supports(FEATURE_QUERY_CANCEL);
+        let id = format!(
+            "cli-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        );
+        let q = if cancellable {
+            q.with_id(id.clone())
+        } else {
+            q
+        };
+        let q_str = serde_json::to_string(&Message::new(q))?;
+        let reply_str = tokio::select! {
+            reply = self.send_request("/query", &q_str) => reply?,
+            _ = tokio::signal::ctrl_c() => {
+                if !cancellable {
+                    anyhow::bail!("interrupted, the probe keeps running the query");
+                }
+                let url = format!("/query/{id}");
+                request_with_method(self.clone(), "DELETE", &url, None).await?;
+                anyhow::bail!("query {id} cancelled");
+            }
+        };
+        let reply = serde_json::from_str::<Message<QueryDataFormat>>(&reply_str)
+            .map_err(|err| decode_error(self, err))?
+            .payload;
 
         match reply {
             QueryDataFormat::Error(err) => Err(anyhow::anyhow!("error: {}", err)),
             QueryDataFormat::Nil => Ok(Default::default()),
             QueryDataFormat::DataFrame(df) => Ok(df),
             QueryDataFormat::TimeSeries(_) => todo!(),
+            QueryDataFormat::Compressed(frame) => Ok(frame.decompress()?),
         }
     }
 }
 
 pub async fn request(ctrl: ProbeEndpoint, url: &str, body: Option<String>) -> Result<Vec<u8>> {
+    let method = if body.is_some() { "POST" } else { "GET" };
+    request_with_method(ctrl, method, url, body).await
+}
+
+/// POST a JSON `body`, for the routes decoding it with a `Json`
+/// extractor
+pub async fn request_json(ctrl: ProbeEndpoint, url: &str, body: String) -> Result<Vec<u8>> {
+    let (_, reply) = send(ctrl, "POST", url, Some(body), Some("application/json")).await?;
+    Ok(reply)
+}
+
+/// `user@host` sent as `X-Probing-User`, recorded by the probe as the actor of
+/// the changes made by the request
+fn user() -> String {
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let host = nix::sys::utsname::uname()
+        .map(|uname| uname.nodename().to_string_lossy().to_string())
+        .unwrap_or("localhost".to_string());
+    format!("{user}@{host}")
+}
+
+pub async fn request_with_method(
+    ctrl: ProbeEndpoint,
+    method: &str,
+    url: &str,
+    body: Option<String>,
+) -> Result<Vec<u8>> {
+    let (_, reply) = send(ctrl, method, url, body, None).await?;
+    Ok(reply)
+}
+
+/// Send one request to the probe, returning the status and body of the reply
+async fn send(
+    ctrl: ProbeEndpoint,
+    method: &str,
+    url: &str,
+    body: Option<String>,
+    content_type: Option<&str>,
+) -> Result<(hyper::StatusCode, Vec<u8>)> {
     use hyper::body::Bytes;
     use hyper::client::conn;
     use hyper::Request;
 
+    // the token of a remote probe, see server/AUTH.md
+    let token = match &ctrl {
+        ProbeEndpoint::Remote { .. } => std::env::var("PROBING_AUTH_TOKEN").ok(),
+        _ => None,
+    };
     let mut sender = match ctrl {
         ProbeEndpoint::Ptrace { pid } | ProbeEndpoint::Local { pid } => {
             eprintln!("sending ctrl commands via unix socket...");
@@ -133,6 +747,24 @@ pub async fn request(ctrl: ProbeEndpoint, url: &str, body: Option<String>) -> Re
             });
             sender
         }
+        ProbeEndpoint::Named { pid, name } => {
+            eprintln!("sending ctrl commands to probe instance {name} via unix socket...");
+            #[cfg(target_os = "linux")]
+            let path = format!("\0probing-{pid}-{name}");
+            #[cfg(not(target_os = "linux"))]
+            let path = std::env::temp_dir()
+                .join(format!("probing-{pid}-{name}.sock"))
+                .to_string_lossy()
+                .to_string();
+            let stream = tokio::net::UnixStream::connect(path).await?;
+            let io = TokioIo::new(stream);
+
+            let (sender, connection) = conn::http1::handshake(io).await?;
+            tokio::spawn(async move {
+                connection.await.unwrap();
+            });
+            sender
+        }
         ProbeEndpoint::Remote { addr } => {
             eprintln!("sending ctrl commands via tcp socket...");
             let stream = tokio::net::TcpStream::connect(addr).await?;
@@ -151,21 +783,20 @@ pub async fn request(ctrl: ProbeEndpoint, url: &str, body: Option<String>) -> Re
         }
         _ => todo!(),
     };
-    let request = if let Some(body) = body {
-        Request::builder()
-            .method("POST")
-            .uri(url)
-            .body(Full::<Bytes>::from(body))
-            .unwrap()
-    } else {
-        Request::builder()
-            .method("GET")
-            .uri(url)
-            .body(Full::<Bytes>::default())
-            .unwrap()
-    };
+    let mut request = Request::builder()
+        .method(method)
+        .uri(url)
+        .header("X-Probing-User", user());
+    if let Some(token) = token {
+        request = request.header("X-Probing-Token", token);
+    }
+    if let Some(content_type) = content_type {
+        request = request.header(hyper::header::CONTENT_TYPE, content_type);
+    }
+    let request = request.body(body.map(Full::<Bytes>::from).unwrap_or_default())?;
 
     let res = sender.send_request(request).await?;
+    let status = res.status();
 
-    Ok(res.collect().await.map(|x| x.to_bytes().to_vec())?)
+    Ok((status, res.collect().await.map(|x| x.to_bytes().to_vec())?))
 }
@@ -0,0 +1,199 @@
+//! `probing dash`: live panels of a probe redrawn in the terminal, for hosts
+//! reached over SSH only, where the web UI port cannot be forwarded.
+//!
+//! Each refresh is one `GET /apis/dash` returning a [`DashFrame`]; the CPU
+//! and GPU history behind the sparklines is kept on the client side.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use clap::Args;
+use probing_proto::prelude::DashFrame;
+use probing_proto::protocol::handshake::FEATURE_DASH;
+
+use super::ctrl::{handshake, request, ProbeEndpoint};
+use crate::table::{sparkline, terminal_width};
+
+/// Refreshes kept for the sparklines
+const HISTORY: usize = 60;
+const DEFAULT_WIDTH: usize = 100;
+
+#[derive(Args, Debug)]
+pub struct DashCommand {
+    /// Seconds between two refreshes
+    #[arg(short, long, default_value_t = 1.0)]
+    pub interval: f64,
+
+    /// Functions and exceptions shown at most
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+impl DashCommand {
+    pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        if !handshake(&ctrl, false).await?.supports(FEATURE_DASH) {
+            anyhow::bail!("the probe does not serve the dashboard, upgrade it");
+        }
+        // hide the cursor while redrawing, and bring it back on Ctrl-C
+        print!("\x1b[?25l");
+        let result = tokio::select! {
+            result = self.refresh(ctrl) => result,
+            _ = tokio::signal::ctrl_c() => Ok(()),
+        };
+        println!("\x1b[?25h");
+        result
+    }
+
+    /// Redraw the panels every interval until a request fails
+    async fn refresh(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        let interval = Duration::from_secs_f64(self.interval.max(0.1));
+        let url = format!("/apis/dash?top={}", self.top);
+        let mut history = History::default();
+        loop {
+            let reply = request(ctrl.clone(), &url, None).await?;
+            let frame = serde_json::from_slice::<DashFrame>(&reply)?;
+            history.push(&frame);
+            let width = terminal_width().map_or(DEFAULT_WIDTH, |w| w as usize);
+            print!("\x1b[2J\x1b[H{}", draw(&frame, &history, interval, width));
+            std::io::stdout().flush()?;
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// CPU and GPU utilization of the last refreshes, oldest first
+#[derive(Default)]
+struct History {
+    cpu: VecDeque<Option<f64>>,
+    gpu: VecDeque<Option<f64>>,
+}
+
+impl History {
+    fn push(&mut self, frame: &DashFrame) {
+        for (series, value) in [
+            (&mut self.cpu, frame.status.cpu),
+            (&mut self.gpu, frame.status.gpu_util),
+        ] {
+            if series.len() >= HISTORY {
+                series.pop_front();
+            }
+            series.push_back(value);
+        }
+    }
+}
+
+/// `text` cut to `width` characters
+fn fit(text: &str, width: usize) -> String {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => text[..end].to_string(),
+        None => text.to_string(),
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{v:.1}%"))
+}
+
+pub(crate) fn age(micros: i64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64;
+    let secs = (now - micros).max(0) / 1_000_000;
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+/// The whole screen for `frame`, each line cut to `width`
+fn draw(frame: &DashFrame, history: &History, interval: Duration, width: usize) -> String {
+    let status = &frame.status;
+    let spark_width = HISTORY.min(width.saturating_sub(20));
+    let cpu = history.cpu.iter().copied().collect::<Vec<_>>();
+    let gpu = history.gpu.iter().copied().collect::<Vec<_>>();
+    let mut lines = vec![
+        format!(
+            "probing dash: pid {}, every {:.1}s, Ctrl-C to quit",
+            status.pid,
+            interval.as_secs_f64()
+        ),
+        String::new(),
+        format!(
+            "step {}   loss {}",
+            status
+                .step
+                .map_or_else(|| "-".to_string(), |v| v.to_string()),
+            status
+                .loss
+                .map_or_else(|| "-".to_string(), |v| format!("{v:.4}"))
+        ),
+        format!(
+            "CPU {:>8}  {}",
+            percent(status.cpu),
+            sparkline(&cpu, spark_width)
+        ),
+        format!(
+            "GPU {:>8}  {}",
+            percent(status.gpu_util),
+            sparkline(&gpu, spark_width)
+        ),
+        String::new(),
+        "\x1b[1mTop functions\x1b[0m".to_string(),
+    ];
+    if frame.functions.is_empty() {
+        lines.push("  no profiler running, set probing.pprof.sample_freq to start one".into());
+    }
+    for function in &frame.functions {
+        lines.push(format!(
+            "  {:>5.1}%  {:>7}  {}",
+            function.share * 100.0,
+            function.samples,
+            function.name
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push("\x1b[1mLast exceptions\x1b[0m".to_string());
+    if frame.failures.is_empty() {
+        lines.push("  none".to_string());
+    }
+    for failure in &frame.failures {
+        lines.push(format!(
+            "  {:>8}  {}  {}",
+            age(failure.time),
+            failure.name,
+            failure.message.as_deref().unwrap_or("-")
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push("\x1b[1mActive spans\x1b[0m".to_string());
+    if frame.spans.is_empty() {
+        lines.push("  none".to_string());
+    }
+    for span in &frame.spans {
+        lines.push(format!(
+            "  {:<14} {:>8.1}s  {}{}",
+            span.thread,
+            span.elapsed,
+            "  ".repeat(span.depth),
+            span.name
+        ));
+    }
+
+    lines
+        .iter()
+        .map(|line| {
+            // escape sequences take no room on screen
+            match line.starts_with("\x1b[") {
+                true => line.clone(),
+                false => fit(line, width),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
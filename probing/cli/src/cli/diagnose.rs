@@ -0,0 +1,536 @@
+//! `probing <pid> diagnose`: collect the tables of a probe into one archive
+//! that `probing open` can read on another machine.
+//!
+//! With `--share` the bundle is made safe to attach to a public issue:
+//! secrets are replaced by `<redacted>`, hostnames and IP addresses by
+//! `host-<hash>` and directories by `/<hash>/`, keeping file names. Hashes are
+//! salted per bundle, so equal values stay recognizable within a bundle but
+//! cannot be looked up. What was removed is listed, without the original
+//! values, in a manifest written next to the bundle.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use anyhow::Result;
+use probing_proto::prelude::{DataFrame, ProbeArchive, Query, Seq};
+use probing_proto::protocol::archive::is_skipped;
+use regex::Regex;
+
+use crate::cli::ctrl::ProbeEndpoint;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Rule {
+    Secret,
+    Hostname,
+    Path,
+}
+
+impl Rule {
+    fn name(&self) -> &'static str {
+        match self {
+            Rule::Secret => "secret",
+            Rule::Hostname => "hostname",
+            Rule::Path => "path",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Rule::Secret => {
+                "values of secret-like keys and flags, credentials in URLs and API tokens"
+            }
+            Rule::Hostname => "hostnames of this machine and the job, and IPv4 addresses",
+            Rule::Path => "directories of absolute paths, file names are kept",
+        }
+    }
+}
+
+struct Redactor {
+    salt: u64,
+    /// Known hostnames, longest first so that FQDNs win over short names
+    hosts: Vec<String>,
+    secret_key: Regex,
+    host_key: Regex,
+    assignment: Regex,
+    /// Command line flags such as `--token <value>`
+    flag: Regex,
+    url_credentials: Regex,
+    token: Regex,
+    ipv4: Regex,
+    path: Regex,
+    /// Number of replacements by table, column and rule
+    counts: BTreeMap<(String, String, Rule), usize>,
+}
+
+impl Redactor {
+    fn new() -> Self {
+        let salt = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+            ^ ((std::process::id() as u64) << 32);
+        let mut redactor = Self {
+            salt,
+            hosts: vec![],
+            secret_key: Regex::new(
+                r"(?i)(token|secret|passw(or)?d|api[_-]?key|access[_-]?key|private[_-]?key|credential|auth)",
+            )
+            .unwrap(),
+            host_key: Regex::new(r"(?i)(host(name)?|addr(ess)?|node(_?name)?)$").unwrap(),
+            assignment: Regex::new(
+                r#"(?i)([\w.-]*(token|secret|passw(or)?d|api[_-]?key|access[_-]?key|credential)[\w.-]*\s*[=:]\s*)(["']?)([^\s"',;]+)"#,
+            )
+            .unwrap(),
+            flag: Regex::new(
+                r#"(?i)((?:^|\s)--?[\w.-]*(token|secret|passw(or)?d|api[_-]?key|access[_-]?key|credential)[\w.-]*\s+)(["']?)([^\s"',;-][^\s"',;]*)"#,
+            )
+            .unwrap(),
+            url_credentials: Regex::new(r"([a-zA-Z][a-zA-Z0-9+.-]*://)[^/\s:@]+(:[^/\s@]*)?@")
+                .unwrap(),
+            token: Regex::new(
+                r"\b(ghp_|gho_|ghs_|github_pat_|glpat-|hf_|sk-|xox[abpr]-|AKIA)[A-Za-z0-9_-]{8,}",
+            )
+            .unwrap(),
+            ipv4: Regex::new(r"\b(\d{1,3})\.(\d{1,3})\.(\d{1,3})\.(\d{1,3})\b").unwrap(),
+            path: Regex::new(r#"(^|[\s'"=:(,\[])(/[^/\s'"=:,)\]][^\s'"=:,)\]]*)"#).unwrap(),
+            counts: Default::default(),
+        };
+        if let Ok(uname) = nix::sys::utsname::uname() {
+            redactor.add_host(&uname.nodename().to_string_lossy());
+        }
+        redactor
+    }
+
+    fn add_host(&mut self, host: &str) {
+        let host = host.trim();
+        let host = match host.rsplit_once(':') {
+            Some((name, port)) if port.parse::<u16>().is_ok() => name,
+            _ => host,
+        };
+        // addresses are caught by the IPv4 rule, loopback names are harmless
+        if host.is_empty() || host == "localhost" || self.ipv4.is_match(host) {
+            return;
+        }
+        for name in [Some(host), host.split_once('.').map(|(short, _)| short)]
+            .into_iter()
+            .flatten()
+        {
+            if name.len() > 2 && !self.hosts.iter().any(|h| h == name) {
+                self.hosts.push(name.to_string());
+            }
+        }
+        self.hosts.sort_by_key(|h| std::cmp::Reverse(h.len()));
+    }
+
+    /// Learn hostnames from the columns named like hosts, e.g. `host` and
+    /// `addr` of `cluster.nodes`, and from `key`/`value` tables, e.g.
+    /// `MASTER_ADDR` in the environment of a distributed job
+    fn learn_hosts(&mut self, df: &DataFrame) {
+        let mut hosts = df
+            .names
+            .iter()
+            .zip(df.cols.iter())
+            .filter(|(name, _)| self.host_key.is_match(name))
+            .filter_map(|(_, col)| match col {
+                Seq::SeqText(values) => Some(values.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        if let Some((keys, values)) = key_value_columns(df) {
+            hosts.extend(
+                keys.iter()
+                    .zip(values.iter())
+                    .filter(|(key, _)| self.host_key.is_match(key))
+                    .map(|(_, value)| value.clone()),
+            );
+        }
+        for host in hosts {
+            self.add_host(&host);
+        }
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.salt.hash(&mut hasher);
+        value.hash(&mut hasher);
+        format!("{:08x}", hasher.finish() as u32)
+    }
+
+    fn count(&mut self, table: &str, column: &str, rule: Rule, n: usize) {
+        if n > 0 {
+            *self
+                .counts
+                .entry((table.to_string(), column.to_string(), rule))
+                .or_default() += n;
+        }
+    }
+
+    fn redact_text(&mut self, table: &str, column: &str, text: &str) -> String {
+        let mut n = 0;
+        let text = self
+            .url_credentials
+            .replace_all(text, |c: &regex::Captures| {
+                n += 1;
+                format!("{}{REDACTED}@", &c[1])
+            })
+            .into_owned();
+        let text = self
+            .assignment
+            .replace_all(&text, |c: &regex::Captures| {
+                n += 1;
+                format!("{}{}{REDACTED}", &c[1], &c[4])
+            })
+            .into_owned();
+        let text = self
+            .flag
+            .replace_all(&text, |c: &regex::Captures| {
+                n += 1;
+                format!("{}{}{REDACTED}", &c[1], &c[4])
+            })
+            .into_owned();
+        let text = self
+            .token
+            .replace_all(&text, |_: &regex::Captures| {
+                n += 1;
+                REDACTED
+            })
+            .into_owned();
+        self.count(table, column, Rule::Secret, n);
+
+        let mut n = 0;
+        let mut text = self
+            .ipv4
+            .replace_all(&text, |c: &regex::Captures| {
+                let octets = (1..=4).all(|i| c[i].parse::<u8>().is_ok());
+                match octets && !c[0].starts_with("127.") && &c[0] != "0.0.0.0" {
+                    true => {
+                        n += 1;
+                        format!("host-{}", self.hash(&c[0]))
+                    }
+                    false => c[0].to_string(),
+                }
+            })
+            .into_owned();
+        for host in self.hosts.clone() {
+            let found = text.matches(host.as_str()).count();
+            if found > 0 {
+                n += found;
+                text = text.replace(host.as_str(), &format!("host-{}", self.hash(&host)));
+            }
+        }
+        self.count(table, column, Rule::Hostname, n);
+
+        let mut n = 0;
+        let text = self
+            .path
+            .replace_all(&text, |c: &regex::Captures| {
+                n += 1;
+                let path = &c[2];
+                // keep file names, which tell which library or module is
+                // involved, but not directory names such as home directories
+                let hashed = match path.rsplit_once('/') {
+                    Some((dir, file)) if file.contains('.') => {
+                        format!("/{}/{file}", self.hash(dir))
+                    }
+                    _ => format!("/{}", self.hash(path)),
+                };
+                format!("{}{hashed}", &c[1])
+            })
+            .into_owned();
+        self.count(table, column, Rule::Path, n);
+        text
+    }
+
+    fn redact(&mut self, table: &str, df: &mut DataFrame) {
+        // the value of a secret-like key is dropped whatever it looks like
+        let secret_rows = key_value_columns(df)
+            .map(|(keys, _)| {
+                keys.iter()
+                    .map(|key| self.secret_key.is_match(key))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        for (name, col) in df.names.iter().zip(df.cols.iter_mut()) {
+            let Seq::SeqText(values) = col else {
+                continue;
+            };
+            let is_value = name == "value";
+            let mut secrets = 0;
+            for (row, value) in values.iter_mut().enumerate() {
+                if is_value && secret_rows.get(row).copied().unwrap_or(false) {
+                    if value != REDACTED {
+                        *value = REDACTED.to_string();
+                        secrets += 1;
+                    }
+                    continue;
+                }
+                *value = self.redact_text(table, name, value);
+            }
+            self.count(table, name, Rule::Secret, secrets);
+        }
+    }
+
+    fn manifest(&self, bundle: &str, tables: &[String]) -> serde_json::Value {
+        let rules = [Rule::Secret, Rule::Hostname, Rule::Path]
+            .iter()
+            .map(|rule| (rule.name().to_string(), rule.description().into()))
+            .collect::<serde_json::Map<_, _>>();
+        let removed = self
+            .counts
+            .iter()
+            .map(|((table, column, rule), count)| {
+                serde_json::json!({
+                    "table": table,
+                    "column": column,
+                    "rule": rule.name(),
+                    "count": count,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "bundle": bundle,
+            "tables": tables,
+            "rules": rules,
+            "removed": removed,
+        })
+    }
+}
+
+/// The `key`/`value` (or `name`/`value`) columns of settings and environment
+/// tables
+fn key_value_columns(df: &DataFrame) -> Option<(&Vec<String>, &Vec<String>)> {
+    let column = |name: &str| {
+        df.names
+            .iter()
+            .position(|n| n == name)
+            .and_then(|i| match &df.cols[i] {
+                Seq::SeqText(values) => Some(values),
+                _ => None,
+            })
+    };
+    Some((column("key").or_else(|| column("name"))?, column("value")?))
+}
+
+async fn collected_tables(ctrl: &ProbeEndpoint) -> Result<Vec<String>> {
+    let df = ctrl
+        .query(Query::new(
+            "SELECT table_schema || '.' || table_name FROM information_schema.tables \
+             WHERE table_schema != 'information_schema'"
+                .to_string(),
+        ))
+        .await?;
+    Ok(df
+        .iter()
+        .map(|row| row[0].to_string())
+        .filter(|table| !is_skipped(table))
+        .collect())
+}
+
+/// Collect the tables and settings of the target into `output`, redacted
+/// with `share`
+pub async fn run(ctrl: ProbeEndpoint, share: bool, output: Option<String>) -> Result<()> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let output = output.unwrap_or_else(|| {
+        let suffix = if share { ".shared" } else { "" };
+        format!("probing-diagnose-{}{suffix}.probe", timestamp.as_secs())
+    });
+    let pid = match &ctrl {
+        ProbeEndpoint::Ptrace { pid }
+        | ProbeEndpoint::Local { pid }
+        | ProbeEndpoint::Named { pid, .. } => *pid,
+        _ => 0,
+    };
+    let host = nix::sys::utsname::uname()
+        .map(|uname| uname.nodename().to_string_lossy().to_string())
+        .unwrap_or("localhost".to_string());
+    let mut archive = ProbeArchive::new(pid, host, timestamp.as_micros() as u64);
+
+    for table in collected_tables(&ctrl).await? {
+        match ctrl
+            .query(Query::new(format!("SELECT * FROM {table}")))
+            .await
+        {
+            Ok(df) => {
+                archive.tables.insert(table, df);
+            }
+            Err(err) => eprintln!("skip table {table}: {err}"),
+        }
+    }
+    match ctrl
+        .query(Query::new(
+            "SELECT name, value FROM information_schema.df_settings".to_string(),
+        ))
+        .await
+    {
+        Ok(df) => {
+            archive.tables.insert("probe.settings".to_string(), df);
+        }
+        Err(err) => eprintln!("skip settings: {err}"),
+    }
+    let tables = archive.tables.keys().cloned().collect::<Vec<_>>();
+
+    if !share {
+        archive.save(&output)?;
+        println!("{} tables written to {output}", tables.len());
+        println!("the bundle is not redacted, use --share before posting it publicly");
+        return Ok(());
+    }
+
+    let mut redactor = Redactor::new();
+    for df in archive.tables.values() {
+        redactor.learn_hosts(df);
+    }
+    archive.host = format!("host-{}", redactor.hash(&archive.host));
+    for (table, df) in archive.tables.iter_mut() {
+        redactor.redact(table, df);
+    }
+    archive.save(&output)?;
+
+    let manifest_path = format!("{output}.manifest.json");
+    let manifest = redactor.manifest(&output, &tables);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+
+    println!("{} tables written to {output}", tables.len());
+    for rule in [Rule::Secret, Rule::Hostname, Rule::Path] {
+        let removed = redactor
+            .counts
+            .iter()
+            .filter(|((_, _, r), _)| *r == rule)
+            .map(|(_, count)| count)
+            .sum::<usize>();
+        println!("\t{:<10}{removed} replaced", rule.name());
+    }
+    println!("see {manifest_path} for where values were removed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A redactor that knows no hostname but the ones a test adds
+    fn redactor() -> Redactor {
+        let mut redactor = Redactor::new();
+        redactor.hosts.clear();
+        redactor
+    }
+
+    #[test]
+    fn test_redact_secrets() {
+        let mut redactor = redactor();
+        let text = redactor.redact_text("t", "c", "password=hunter2 api_key: 'abc123'");
+        assert_eq!(text, format!("password={REDACTED} api_key: '{REDACTED}'"));
+
+        let text = redactor.redact_text("t", "c", "train.py --token s3cr3t --lr 0.1");
+        assert_eq!(text, format!("train.py --token {REDACTED} --lr 0.1"));
+        let text = redactor.redact_text("t", "c", "train.py --hf-token=s3cr3t -v");
+        assert_eq!(text, format!("train.py --hf-token={REDACTED} -v"));
+        // a flag without value keeps the next flag
+        let text = redactor.redact_text("t", "c", "serve --no-token --port 80");
+        assert_eq!(text, "serve --no-token --port 80");
+        // prose is left alone
+        let text = redactor.redact_text("t", "c", "the token expired");
+        assert_eq!(text, "the token expired");
+
+        let text = redactor.redact_text("t", "c", "export HF=hf_abcdefghijklmnop");
+        assert_eq!(text, format!("export HF={REDACTED}"));
+    }
+
+    #[test]
+    fn test_redact_urls() {
+        let mut redactor = redactor();
+        let text = redactor.redact_text("t", "c", "https://bob:pw@example.com/x");
+        assert_eq!(text, format!("https://{REDACTED}@example.com/x"));
+        let text = redactor.redact_text("t", "c", "s3://AKIAXXXX@bucket");
+        assert!(!text.contains("AKIAXXXX"));
+    }
+
+    #[test]
+    fn test_redact_hosts() {
+        let mut redactor = redactor();
+        redactor.add_host("gpu-node-17.cluster.local:29500");
+        let text = redactor.redact_text("t", "c", "gpu-node-17.cluster.local and gpu-node-17");
+        assert!(!text.contains("gpu-node-17"));
+        assert_eq!(text.matches("host-").count(), 2);
+
+        let text = redactor.redact_text("t", "c", "10.1.2.3 127.0.0.1 999.1.1.1");
+        assert!(text.starts_with("host-"));
+        assert!(text.ends_with(" 127.0.0.1 999.1.1.1"));
+    }
+
+    #[test]
+    fn test_redact_paths() {
+        let mut redactor = redactor();
+        let text = redactor.redact_text("t", "c", "at /home/alice/project/train.py:12");
+        assert!(!text.contains("alice"));
+        assert!(text.contains("/train.py"));
+        // equal directories hash alike within a bundle
+        let again = redactor.redact_text("t", "c", "at /home/alice/project/train.py:12");
+        assert_eq!(text, again);
+    }
+
+    #[test]
+    fn test_redact_key_values() {
+        let mut redactor = redactor();
+        let mut df = DataFrame::new(
+            vec!["key".to_string(), "value".to_string()],
+            vec![
+                Seq::SeqText(vec!["HF_TOKEN".to_string(), "MASTER_ADDR".to_string()]),
+                Seq::SeqText(vec!["plain".to_string(), "trainer-0".to_string()]),
+            ],
+        );
+        redactor.learn_hosts(&df);
+        redactor.redact("process.envs", &mut df);
+        let Seq::SeqText(values) = &df.cols[1] else {
+            panic!("values are text");
+        };
+        assert_eq!(values[0], REDACTED);
+        assert!(values[1].starts_with("host-"));
+
+        let manifest = redactor.manifest("bundle.probe", &["process.envs".to_string()]);
+        let removed = manifest["removed"].as_array().unwrap();
+        assert!(removed
+            .iter()
+            .any(|r| r["rule"] == "secret" && r["column"] == "value"));
+        assert!(!manifest.to_string().contains("plain"));
+    }
+
+    #[test]
+    fn test_redact_cluster_nodes() {
+        let mut redactor = redactor();
+        let mut nodes = DataFrame::new(
+            vec!["host".to_string(), "addr".to_string(), "rank".to_string()],
+            vec![
+                Seq::SeqText(vec!["trainer-3".to_string()]),
+                Seq::SeqText(vec!["trainer-3.job.svc:9700".to_string()]),
+                Seq::SeqI32(vec![3]),
+            ],
+        );
+        let mut logs = DataFrame::new(
+            vec!["message".to_string()],
+            vec![Seq::SeqText(vec![
+                "rank 3 lost trainer-3.job.svc, retrying".to_string(),
+            ])],
+        );
+        redactor.learn_hosts(&nodes);
+        redactor.learn_hosts(&logs);
+        redactor.redact("cluster.nodes", &mut nodes);
+        redactor.redact("server.logs", &mut logs);
+        for df in [&nodes, &logs] {
+            let Seq::SeqText(values) = &df.cols[0] else {
+                panic!("values are text");
+            };
+            assert!(!values[0].contains("trainer-3"), "{}", values[0]);
+        }
+        let Seq::SeqText(addrs) = &nodes.cols[1] else {
+            panic!("values are text");
+        };
+        assert!(addrs[0].starts_with("host-") && addrs[0].ends_with(":9700"));
+    }
+}
@@ -0,0 +1,209 @@
+//! Control actions broadcast to every process of a process group or session,
+//! e.g. `probing --pgid 4242 pause` to freeze all ranks of a job on one node.
+//!
+//! Processes are ordered by their depth in the process tree: `pause` stops
+//! the deepest processes first and waits for each to be stopped, so that a
+//! launcher such as `torchrun` never sees a worker freeze while it is still
+//! running and reacts by restarting the job. `resume` undoes this from the
+//! top down. `dump` and `profile` only apply to processes with a probe.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use probing_proto::prelude::Query;
+
+use super::ctrl::ProbeEndpoint;
+use super::ptree;
+
+/// Time given to a process to reach the stopped state before moving on
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Scope {
+    ProcessGroup(i32),
+    Session(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Pause,
+    Resume,
+    Dump,
+    Profile { freq: i32 },
+}
+
+#[derive(Debug)]
+struct Member {
+    pid: i32,
+    depth: usize,
+    probed: bool,
+}
+
+/// Pids of this CLI and its ancestors, e.g. the shell of the session, which
+/// must never be stopped
+pub(super) fn own_lineage() -> Vec<i32> {
+    let mut lineage = vec![];
+    let mut pid = std::process::id() as i32;
+    while pid > 1 && !lineage.contains(&pid) {
+        lineage.push(pid);
+        pid = procfs::process::Process::new(pid)
+            .and_then(|p| p.stat())
+            .map(|stat| stat.ppid)
+            .unwrap_or(0);
+    }
+    lineage
+}
+
+async fn members(scope: Scope) -> Result<Vec<Member>> {
+    let lineage = own_lineage();
+    let parents = procfs::process::all_processes()?
+        .filter_map(|p| p.ok()?.stat().ok())
+        .filter(|stat| match scope {
+            Scope::ProcessGroup(pgid) => stat.pgrp == pgid,
+            Scope::Session(sid) => stat.session == sid,
+        })
+        .filter(|stat| !lineage.contains(&stat.pid))
+        .map(|stat| (stat.pid, stat.ppid))
+        .collect::<HashMap<_, _>>();
+    if parents.is_empty() {
+        bail!("no process found in {scope:?}");
+    }
+    let probed = ptree::collect_probe_processes(false)
+        .await?
+        .into_iter()
+        .map(|p| p.pid)
+        .collect::<Vec<_>>();
+
+    let depth = |mut pid: i32| {
+        let mut depth = 0;
+        while let Some(ppid) = parents.get(&pid) {
+            if depth > parents.len() {
+                break;
+            }
+            depth += 1;
+            pid = *ppid;
+        }
+        depth
+    };
+    Ok(parents
+        .keys()
+        .map(|pid| Member {
+            pid: *pid,
+            depth: depth(*pid),
+            probed: probed.contains(pid),
+        })
+        .collect())
+}
+
+fn is_stopped(pid: i32) -> bool {
+    procfs::process::Process::new(pid)
+        .and_then(|p| p.stat())
+        .map(|stat| matches!(stat.state, 'T' | 't' | 'Z' | 'X'))
+        .unwrap_or(true)
+}
+
+async fn wait_stopped(pid: i32) -> Result<()> {
+    let start = Instant::now();
+    while !is_stopped(pid) {
+        if start.elapsed() > STOP_TIMEOUT {
+            bail!("not stopped after {STOP_TIMEOUT:?}");
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    Ok(())
+}
+
+async fn apply(member: &Member, action: Action) -> Result<String> {
+    let pid = member.pid;
+    match action {
+        Action::Pause => {
+            kill(Pid::from_raw(pid), Signal::SIGSTOP)?;
+            wait_stopped(pid).await?;
+            Ok("stopped".to_string())
+        }
+        Action::Resume => {
+            kill(Pid::from_raw(pid), Signal::SIGCONT)?;
+            Ok("continued".to_string())
+        }
+        Action::Dump => {
+            if is_stopped(pid) {
+                bail!("process is stopped, resume it to dump its stacks");
+            }
+            ProbeEndpoint::Local { pid }.backtrace(None).await?;
+            Ok("dumped".to_string())
+        }
+        Action::Profile { freq } => {
+            let sql = format!("SET probing.pprof.sample_freq={freq}");
+            ProbeEndpoint::Local { pid }.query(Query::new(sql)).await?;
+            Ok(format!("profiling at {freq} Hz"))
+        }
+    }
+}
+
+/// Apply `action` to a single target, only `dump` and `profile` reach
+/// remote probes
+pub async fn run_target(ctrl: ProbeEndpoint, action: Action) -> Result<()> {
+    let pid = match &ctrl {
+        ProbeEndpoint::Ptrace { pid }
+        | ProbeEndpoint::Local { pid }
+        | ProbeEndpoint::Named { pid, .. } => Some(*pid),
+        _ => None,
+    };
+    match (action, pid) {
+        (Action::Dump, _) => ctrl.backtrace(None).await,
+        (Action::Profile { freq }, _) => {
+            let sql = format!("SET probing.pprof.sample_freq={freq}");
+            ctrl.query(Query::new(sql)).await?;
+            println!("profiling at {freq} Hz");
+            Ok(())
+        }
+        (Action::Pause | Action::Resume, Some(pid)) => {
+            let member = Member {
+                pid,
+                depth: 0,
+                probed: true,
+            };
+            println!("{:<8}{}", pid, apply(&member, action).await?);
+            Ok(())
+        }
+        (Action::Pause | Action::Resume, None) => {
+            bail!("pause and resume need a local process")
+        }
+    }
+}
+
+/// Apply `action` to every process of `scope`, in tree order
+pub async fn run(scope: Scope, action: Action) -> Result<()> {
+    let mut members = members(scope).await?;
+    match action {
+        Action::Pause => members.sort_by_key(|m| (std::cmp::Reverse(m.depth), m.pid)),
+        _ => members.sort_by_key(|m| (m.depth, m.pid)),
+    }
+    if matches!(action, Action::Dump | Action::Profile { .. }) {
+        members.retain(|m| m.probed);
+        if members.is_empty() {
+            bail!("no process with a probe in {scope:?}");
+        }
+    }
+
+    let mut failed = 0;
+    for member in members.iter() {
+        if action == Action::Dump {
+            println!("== {} ==", member.pid);
+        }
+        match apply(member, action).await {
+            Ok(status) => println!("{:<8}{status}", member.pid),
+            Err(err) => {
+                failed += 1;
+                eprintln!("{:<8}failed: {err}", member.pid);
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} processes failed", members.len());
+    }
+    Ok(())
+}
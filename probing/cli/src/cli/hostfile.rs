@@ -0,0 +1,231 @@
+//! `probing inject --hostfile hosts.txt --ssh`: inject on many machines at
+//! once by running the probing CLI of every host over ssh.
+//!
+//! The hostfile lists one `<host> [<pid>...]` per line; hosts without pids
+//! inject into the processes matching `--match`. MPI and DeepSpeed hostfiles
+//! work as they are since `slots=8` and other `key=value` words are ignored.
+//! Hosts that succeed are appended to `<hostfile>.done`, and `--resume` skips
+//! them, so a run interrupted or failing on a few hosts can be completed.
+
+use std::collections::{HashSet, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+
+/// ssh options keeping one unreachable or unknown host from blocking the run
+const SSH_OPTIONS: &[&str] = &["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub pids: Vec<i32>,
+}
+
+pub fn parse_hostfile(text: &str) -> Result<Vec<Host>> {
+    let mut hosts: Vec<Host> = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace().filter(|w| !w.contains('='));
+        let Some(name) = words.next() else {
+            continue;
+        };
+        // ssh would take it for an option, e.g. `-F/tmp/config`
+        if name.starts_with('-') {
+            bail!("line {}: `{name}` is not a host name", n + 1);
+        }
+        let pids = words
+            .map(|w| w.parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("line {}: expected `<host> [<pid>...]`", n + 1))?;
+        match hosts.iter_mut().find(|h| h.name == name) {
+            Some(host) => host.pids.extend(pids),
+            None => hosts.push(Host {
+                name: name.to_string(),
+                pids,
+            }),
+        }
+    }
+    Ok(hosts)
+}
+
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@,".contains(c))
+    {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Shell command run on `host`: one `inject` per pid, or one for `--match`,
+/// failing if any of them failed
+fn remote_command(host: &Host, cli: &str, args: &[String]) -> String {
+    let quoted = args
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cli = shell_quote(cli);
+    if host.pids.is_empty() {
+        return format!("{cli} inject {quoted}");
+    }
+    let injects = host
+        .pids
+        .iter()
+        .map(|pid| format!("{cli} -t {pid} inject {quoted} || rc=1; "))
+        .collect::<String>();
+    format!("rc=0; {injects}exit $rc")
+}
+
+/// Outcome of the injection on one host
+#[derive(Debug)]
+struct Outcome {
+    host: String,
+    ok: bool,
+    /// Last line printed by the remote CLI
+    message: String,
+    seconds: f64,
+}
+
+fn inject_host(host: &Host, cli: &str, args: &[String]) -> Outcome {
+    let started = Instant::now();
+    let output = Command::new("ssh")
+        .args(SSH_OPTIONS)
+        .arg("--")
+        .arg(&host.name)
+        .arg(remote_command(host, cli, args))
+        .stdin(Stdio::null())
+        .output();
+    let (ok, message) = match output {
+        Ok(output) => {
+            let text = [output.stderr, output.stdout].concat();
+            let text = String::from_utf8_lossy(&text);
+            let last = text.lines().rev().find(|l| !l.trim().is_empty());
+            (
+                output.status.success(),
+                last.unwrap_or_default().to_string(),
+            )
+        }
+        Err(err) => (false, format!("failed to run ssh: {err}")),
+    };
+    Outcome {
+        host: host.name.clone(),
+        ok,
+        message,
+        seconds: started.elapsed().as_secs_f64(),
+    }
+}
+
+fn done_path(hostfile: &Path) -> PathBuf {
+    let mut path = hostfile.as_os_str().to_owned();
+    path.push(".done");
+    PathBuf::from(path)
+}
+
+/// Inject on the hosts of `hostfile`, `parallel` at a time, passing `args`
+/// to `inject` of the CLI `cli` on each of them
+pub fn run(
+    hostfile: &Path,
+    cli: &str,
+    args: Vec<String>,
+    parallel: usize,
+    resume: bool,
+) -> Result<()> {
+    let text = std::fs::read_to_string(hostfile)
+        .with_context(|| format!("failed to read {}", hostfile.display()))?;
+    let mut hosts = parse_hostfile(&text)?;
+    let done = done_path(hostfile);
+    if resume {
+        let finished = std::fs::read_to_string(&done).unwrap_or_default();
+        let finished = finished.lines().collect::<HashSet<_>>();
+        let before = hosts.len();
+        hosts.retain(|h| !finished.contains(h.name.as_str()));
+        eprintln!("skipping {} hosts done before", before - hosts.len());
+    } else {
+        std::fs::write(&done, "")?;
+    }
+    if hosts.is_empty() {
+        eprintln!("no host to inject on");
+        return Ok(());
+    }
+
+    let total = hosts.len();
+    let queue = Mutex::new(hosts.into_iter().collect::<VecDeque<_>>());
+    let outcomes = Mutex::new(vec![]);
+    let record = Mutex::new(
+        std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&done)?,
+    );
+    std::thread::scope(|scope| {
+        for _ in 0..parallel.clamp(1, total) {
+            scope.spawn(|| loop {
+                let Some(host) = queue.lock().unwrap().pop_front() else {
+                    break;
+                };
+                let outcome = inject_host(&host, cli, &args);
+                let mut outcomes = outcomes.lock().unwrap();
+                let status = if outcome.ok { "ok" } else { "FAILED" };
+                eprintln!(
+                    "[{}/{total}] {}: {status} in {:.1}s {}",
+                    outcomes.len() + 1,
+                    outcome.host,
+                    outcome.seconds,
+                    outcome.message,
+                );
+                if outcome.ok {
+                    let _ = writeln!(record.lock().unwrap(), "{}", outcome.host);
+                }
+                outcomes.push(outcome);
+            });
+        }
+    });
+
+    let outcomes = outcomes.into_inner().unwrap();
+    let failed = outcomes
+        .iter()
+        .filter(|o| !o.ok)
+        .map(|o| o.host.as_str())
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        bail!(
+            "failed on {} of {total} hosts ({}), rerun with --resume to retry them",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
+    eprintln!("injected on {total} hosts");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hostfile() {
+        let hosts = parse_hostfile("gpu1 slots=8\ngpu2 12 34 # comment\ngpu1 56\n").unwrap();
+        assert_eq!(
+            hosts,
+            vec![
+                Host {
+                    name: "gpu1".to_string(),
+                    pids: vec![56],
+                },
+                Host {
+                    name: "gpu2".to_string(),
+                    pids: vec![12, 34],
+                },
+            ]
+        );
+        assert!(parse_hostfile("gpu1\n-F/tmp/config\n").is_err());
+        assert!(parse_hostfile("-v gpu1\n").is_err());
+    }
+}
@@ -1,29 +1,93 @@
-use anyhow::{anyhow, Error, Result};
-use clap::Args;
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
 use probing_proto::prelude::Query;
 
 use crate::cli::ctrl::ProbeEndpoint;
-use crate::inject::{Injector, Process};
+use crate::table::Render;
 
 use super::ctrl;
+use super::library::{self, Platform};
 
 /// Inject into the target process
 #[derive(Args, Default, Debug)]
 pub struct InjectCommand {
     #[arg(short='D', long="define", num_args=1..)]
     settings: Vec<String>,
+
+    /// Inject as the named probe instance, with its own engine and socket
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Probe library to inject, instead of the build matching the target
+    #[arg(long)]
+    library: Option<PathBuf>,
+
+    /// Directories searched for libprobing builds, before the CLI's own
+    #[arg(long, env = "PROBING_LIBRARY_PATH", value_delimiter = ':')]
+    library_path: Vec<PathBuf>,
+
+    /// Run a single action, print its result and unload the probe again,
+    /// leaving no server behind
+    #[arg(long, value_enum, conflicts_with_all = ["settings", "name"])]
+    oneshot: Option<OneShot>,
+
+    /// Seconds sampled by `--oneshot flamegraph` [default: 5]
+    #[arg(long, requires = "oneshot")]
+    duration: Option<u64>,
+
+    /// Inject into every process of this host whose command line matches,
+    /// e.g. `train.py`
+    #[arg(long = "match", value_name = "REGEX", conflicts_with = "oneshot")]
+    pattern: Option<String>,
+
+    /// Inject on every host of this file, one `<host> [<pid>...]` per line;
+    /// hosts without pids inject into the processes matching `--match`
+    #[arg(long, requires = "ssh", conflicts_with = "oneshot")]
+    hostfile: Option<PathBuf>,
+
+    /// Run the probing CLI of the hosts of `--hostfile` over ssh
+    #[arg(long, requires = "hostfile")]
+    ssh: bool,
+
+    /// Hosts of `--hostfile` injected at once [default: 16]
+    #[arg(long, requires = "hostfile")]
+    parallel: Option<usize>,
+
+    /// Skip the hosts recorded as done in `<hostfile>.done` by a previous run
+    #[arg(long, requires = "hostfile")]
+    resume: bool,
+
+    /// Path of the probing CLI on the hosts of `--hostfile`
+    #[arg(long, requires = "hostfile", default_value = "probing")]
+    remote_cli: String,
+}
+
+/// Actions of a one-shot probe
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum OneShot {
+    /// Python stacks of every thread
+    Backtrace,
+    /// Wall-clock flamegraph of the Python threads, as SVG
+    Flamegraph,
+}
+
+impl OneShot {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OneShot::Backtrace => "backtrace",
+            OneShot::Flamegraph => "flamegraph",
+        }
+    }
 }
 
 impl InjectCommand {
     fn check_library(&self, pid: i32, lib_name: &str) -> Result<bool> {
-        Ok(procfs::process::Process::new(pid)?.maps()?.iter().any(|m| {
-            matches!(&m.pathname,
-                procfs::process::MMapPath::Path(p) if p
-                    .file_name()
-                    .map(|n| n.to_string_lossy().contains(lib_name))
-                    .unwrap_or(false)
-            )
-        }))
+        probing_inject::Process::get(pid as u32)?.has_library(lib_name)
     }
 
     fn wait_for_library(&self, pid: i32, lib_name: &str) -> Result<()> {
@@ -46,41 +110,291 @@ impl InjectCommand {
             .collect()
     }
 
-    fn inject(&self, pid: i32) -> Result<()> {
-        let soname = std::fs::read_link("/proc/self/exe")?.with_file_name("libprobing.so");
-        let settings = self.build_settings();
+    /// Inject the probe library, returning its handle in the target
+    fn inject(&self, pid: i32, settings: Vec<String>) -> Result<u64> {
+        let soname = match &self.library {
+            Some(library) => library.clone(),
+            None => library::resolve(&Platform::of_process(pid)?, &self.library_path)?,
+        };
 
-        println!("Injecting {} into {}", soname.display(), pid);
-        Injector::attach(Process::get(pid as u32).map_err(Error::msg)?)
-            .map_err(Error::msg)?
-            .inject(&soname, settings)
+        let preflight = probing_inject::preflight(pid)?;
+        if !preflight.problems.is_empty() {
+            anyhow::bail!(
+                "cannot inject into {pid}:\n\t{}",
+                preflight.problems.join("\n\t")
+            );
+        }
+        eprintln!("Injecting {} into {}", soname.display(), pid);
+        probing_inject::inject(pid, &soname, settings)
             .map_err(|e| anyhow!("Failed to inject probing: {}\n\t{}", e, e.root_cause()))
     }
 
+    /// Private directory for the result of a one-shot probe, created with
+    /// mode 0700 under `/tmp` of the target, which may run in another mount
+    /// namespace, and owned by the user of the target. Returns the directory
+    /// as seen from the target and from here.
+    fn oneshot_dir(&self, pid: i32) -> Result<(PathBuf, PathBuf)> {
+        let root = PathBuf::from(format!("/proc/{pid}/root"));
+        let status = procfs::process::Process::new(pid)?.status()?;
+        let local = nix::unistd::mkdtemp(&root.join("tmp/probing-oneshot-XXXXXX"))
+            .map_err(|err| anyhow!("cannot create the one-shot directory of {pid}: {err}"))?;
+        // created by us, it is ours until handed over
+        let handed = match std::fs::metadata(&local)?.uid() == status.euid {
+            true => Ok(()),
+            false => std::os::unix::fs::chown(&local, Some(status.euid), Some(status.egid)),
+        };
+        if let Err(err) = handed {
+            let _ = std::fs::remove_dir(&local);
+            return Err(anyhow!(
+                "cannot hand the one-shot directory to {pid}: {err}"
+            ));
+        }
+        let target = Path::new("/").join(local.strip_prefix(&root)?);
+        Ok((target, local))
+    }
+
+    /// Wait for the result of a one-shot probe, written to `output` as seen
+    /// from here, and remove it
+    async fn wait_for_result(&self, output: &Path, timeout: Duration) -> Result<String> {
+        let error = output.with_extension("error");
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Ok(result) = read_nofollow(output) {
+                let _ = std::fs::remove_file(output);
+                return Ok(result);
+            }
+            if let Ok(err) = read_nofollow(&error) {
+                let _ = std::fs::remove_file(&error);
+                return Err(anyhow!("one-shot probe failed: {err}"));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Err(anyhow!("one-shot probe gave no result within {timeout:?}"))
+    }
+
+    /// Wait for the thread of a one-shot probe to exit, so that no code of
+    /// the library runs when it is unloaded
+    async fn wait_for_oneshot_exit(&self, pid: i32) -> Result<()> {
+        for _ in 0..100 {
+            let running = procfs::process::Process::new(pid)?
+                .tasks()?
+                .flatten()
+                .any(|task| task.stat().is_ok_and(|stat| stat.comm == "probing-oneshot"));
+            if !running {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Err(anyhow!("the one-shot probe of {pid} is still running"))
+    }
+
+    /// Load the probe for a single action and unload it once the result is
+    /// back; nothing of the probe stays resident in the target
+    async fn oneshot(&self, pid: i32, action: OneShot) -> Result<()> {
+        if self.check_library(pid, "libprobing")? {
+            anyhow::bail!("a probe is already loaded in {pid}, query it instead of --oneshot");
+        }
+        self.wait_for_library(pid, "python")?;
+
+        let (dir, local_dir) = self.oneshot_dir(pid)?;
+        let mut settings = vec![
+            format!("PROBING_ONESHOT={}", action.as_str()),
+            format!(
+                "PROBING_ONESHOT_OUTPUT={}",
+                dir.join("result.out").display()
+            ),
+        ];
+        let mut timeout = Duration::from_secs(30);
+        if let OneShot::Flamegraph = action {
+            let seconds = self.duration.unwrap_or(5);
+            settings.push(format!("PROBING_ONESHOT_DURATION={seconds}"));
+            timeout += Duration::from_secs(seconds);
+        }
+        let handle = match self.inject(pid, settings) {
+            Ok(handle) => handle,
+            Err(err) => {
+                let _ = std::fs::remove_dir_all(&local_dir);
+                return Err(err);
+            }
+        };
+
+        let result = self
+            .wait_for_result(&local_dir.join("result.out"), timeout)
+            .await;
+        let _ = std::fs::remove_dir_all(&local_dir);
+        match self.wait_for_oneshot_exit(pid).await {
+            Ok(()) => {
+                probing_inject::uninject(pid, handle).map_err(|e| {
+                    anyhow!("Failed to unload probing: {}\n\t{}", e, e.root_cause())
+                })?;
+                if self.check_library(pid, "libprobing").unwrap_or(false) {
+                    eprintln!("warning: libprobing is still mapped in {pid} after unloading");
+                }
+            }
+            Err(err) => eprintln!("warning: {err}, libprobing is left loaded"),
+        }
+        print!("{}", result?);
+        Ok(())
+    }
+
+    async fn apply_settings(&self, ctrl: ProbeEndpoint) -> Result<()> {
+        let settings = self.build_settings();
+        if settings.is_empty() {
+            return Ok(());
+        }
+        let query: Vec<String> = settings
+            .iter()
+            .map(|setting| format!("set {setting}"))
+            .collect();
+        let query = query.join(";");
+        ctrl::query(
+            ctrl,
+            Query {
+                expr: query,
+                opts: None,
+            },
+            Render::Table,
+        )
+        .await
+    }
+
+    /// Wait for the socket of a freshly injected named instance
+    async fn wait_for_instance(&self, ctrl: &ProbeEndpoint) -> Result<()> {
+        for _ in 0..50 {
+            if ctrl::request(ctrl.clone(), "/apis/instances", None)
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        Err(anyhow!(
+            "probe instance did not start in the target process"
+        ))
+    }
+
+    /// Whether the targets come from `--match` or `--hostfile` rather than
+    /// from the target of the CLI
+    pub fn is_batch(&self) -> bool {
+        self.pattern.is_some() || self.hostfile.is_some()
+    }
+
+    /// Arguments passed on to `inject` on the hosts of `--hostfile`
+    fn forwarded_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        for setting in &self.settings {
+            args.extend(["-D".to_string(), setting.clone()]);
+        }
+        if let Some(name) = &self.name {
+            args.extend(["--name".to_string(), name.clone()]);
+        }
+        if let Some(library) = &self.library {
+            args.extend(["--library".to_string(), library.display().to_string()]);
+        }
+        if let Some(pattern) = &self.pattern {
+            args.extend(["--match".to_string(), pattern.clone()]);
+        }
+        args
+    }
+
+    /// Inject into the Python processes of this host matching `pattern`
+    async fn run_matching(&self, pattern: &str) -> Result<()> {
+        let pattern = regex::Regex::new(pattern)?;
+        let lineage = super::group::own_lineage();
+        let pids = procfs::process::all_processes()?
+            .filter_map(|p| p.ok())
+            .filter(|p| !lineage.contains(&p.pid))
+            .filter(|p| {
+                p.cmdline()
+                    .is_ok_and(|cmdline| pattern.is_match(&cmdline.join(" ")))
+            })
+            .map(|p| p.pid)
+            .filter(|pid| self.check_library(*pid, "python").unwrap_or(false))
+            .collect::<Vec<_>>();
+        if pids.is_empty() {
+            anyhow::bail!("no Python process matches {pattern}");
+        }
+        let mut failed = 0;
+        for pid in &pids {
+            match self.run(ProbeEndpoint::Local { pid: *pid }).await {
+                Ok(()) => eprintln!("{pid}: ok"),
+                Err(err) => {
+                    eprintln!("{pid}: {err}");
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            anyhow::bail!("failed in {failed} of {} processes", pids.len());
+        }
+        Ok(())
+    }
+
+    /// Inject into the processes given by `--hostfile` or `--match`
+    pub async fn run_batch(&self) -> Result<()> {
+        let Some(hostfile) = self.hostfile.clone() else {
+            return match &self.pattern {
+                Some(pattern) => self.run_matching(pattern).await,
+                None => Ok(()),
+            };
+        };
+        let (cli, args) = (self.remote_cli.clone(), self.forwarded_args());
+        let (parallel, resume) = (self.parallel.unwrap_or(16), self.resume);
+        tokio::task::spawn_blocking(move || {
+            super::hostfile::run(&hostfile, &cli, args, parallel, resume)
+        })
+        .await?
+    }
+
     pub async fn run(&self, ctrl: ProbeEndpoint) -> Result<()> {
-        match ctrl {
+        let (pid, name) = match ctrl {
             ProbeEndpoint::Ptrace { pid } | ProbeEndpoint::Local { pid } => {
-                if !self.check_library(pid, "libprobing.so")? {
-                    self.wait_for_library(pid, "python")?;
-                    self.inject(pid)
-                } else {
-                    let settings = self.build_settings();
-                    let query: Vec<String> = settings
-                        .iter()
-                        .map(|setting| format!("set {setting}"))
-                        .collect();
-                    let query = query.join(";");
-                    ctrl::query(
-                        ctrl,
-                        Query {
-                            expr: query,
-                            opts: None,
-                        },
-                    )
-                    .await
-                }
+                (pid, self.name.clone())
+            }
+            ProbeEndpoint::Named { pid, name } => (pid, Some(name)),
+            _ => return Ok(()),
+        };
+
+        let Some(name) = name else {
+            if let Some(action) = self.oneshot {
+                return self.oneshot(pid, action).await;
             }
-            _ => Ok(()),
+            if !self.check_library(pid, "libprobing")? {
+                self.wait_for_library(pid, "python")?;
+                return self.inject(pid, self.build_settings()).map(|_| ());
+            }
+            return self.apply_settings(ProbeEndpoint::Local { pid }).await;
+        };
+
+        // Settings of a named instance go to its own socket, never through the
+        // environment, so they cannot leak into the default probe; it refuses
+        // the options of the extensions, shared by the whole process.
+        let instance = ProbeEndpoint::Named {
+            pid,
+            name: name.clone(),
+        };
+        if !self.check_library(pid, "libprobing")? {
+            self.wait_for_library(pid, "python")?;
+            self.inject(pid, vec![format!("PROBING_NAME={name}")])?;
+        } else {
+            let url = format!("/apis/instances/{name}");
+            let reply =
+                ctrl::request(ProbeEndpoint::Local { pid }, &url, Some(String::new())).await?;
+            println!("{}", String::from_utf8_lossy(&reply));
         }
+        self.wait_for_instance(&instance).await?;
+        self.apply_settings(instance).await
     }
 }
+
+/// Read `path` unless it is a symlink, which the target could have planted
+fn read_nofollow(path: &Path) -> std::io::Result<String> {
+    let mut content = String::new();
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?
+        .read_to_string(&mut content)?;
+    Ok(content)
+}
@@ -0,0 +1,136 @@
+//! Selecting the libprobing build that matches a target process.
+//!
+//! Builds are named `libprobing-<arch>-<libc>[-cuda].so`, e.g.
+//! `libprobing-aarch64-musl.so` or `libprobing-x86_64-gnu-cuda.so`; a plain
+//! `libprobing.so` is the fallback. The architecture comes from the ELF header
+//! of the target executable, the libc and CUDA use from its loaded libraries.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+/// What the target process needs from a probe library
+#[derive(Debug, Clone, PartialEq)]
+pub struct Platform {
+    pub arch: &'static str,
+    pub libc: &'static str,
+    pub cuda: bool,
+}
+
+impl Platform {
+    /// Inspect the executable and mappings of process `pid`
+    pub fn of_process(pid: i32) -> Result<Self> {
+        let arch = elf_arch(Path::new(&format!("/proc/{pid}/exe")))
+            .with_context(|| format!("cannot inspect executable of process {pid}"))?;
+        let libraries = procfs::process::Process::new(pid)?
+            .maps()?
+            .iter()
+            .filter_map(|m| match &m.pathname {
+                procfs::process::MMapPath::Path(p) => {
+                    p.file_name().map(|n| n.to_string_lossy().to_string())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let platform = Self::from_libraries(arch, &libraries);
+        log::debug!("process {pid} needs {platform:?}");
+        Ok(platform)
+    }
+
+    fn from_libraries(arch: &'static str, libraries: &[String]) -> Self {
+        let libc = match libraries
+            .iter()
+            .any(|n| n.starts_with("ld-musl") || n.starts_with("libc.musl"))
+        {
+            true => "musl",
+            false => "gnu",
+        };
+        let cuda = libraries.iter().any(|n| {
+            n.starts_with("libcudart") || n.starts_with("libcuda.so") || n == "libtorch_cuda.so"
+        });
+        Self { arch, libc, cuda }
+    }
+
+    /// File names to look for, most specific first
+    fn candidates(&self) -> Vec<String> {
+        let mut names = vec![];
+        if self.cuda {
+            names.push(format!("libprobing-{}-{}-cuda.so", self.arch, self.libc));
+        }
+        names.push(format!("libprobing-{}-{}.so", self.arch, self.libc));
+        names.push("libprobing.so".to_string());
+        names
+    }
+}
+
+/// Architecture from the `e_machine` field of an ELF header
+fn elf_arch(path: &Path) -> Result<&'static str> {
+    use std::io::Read;
+
+    let mut header = [0u8; 20];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .with_context(|| format!("cannot read ELF header of {}", path.display()))?;
+    if &header[..4] != b"\x7fELF" {
+        return Err(anyhow!("{} is not an ELF file", path.display()));
+    }
+    let machine = match header[5] {
+        2 => u16::from_be_bytes([header[18], header[19]]),
+        _ => u16::from_le_bytes([header[18], header[19]]),
+    };
+    Ok(match machine {
+        3 => "x86",
+        40 => "arm",
+        62 => "x86_64",
+        183 => "aarch64",
+        243 => "riscv64",
+        _ => "unknown",
+    })
+}
+
+/// Directories searched after the configured ones: next to the CLI and in
+/// `../lib` relative to it
+fn default_dirs() -> Vec<PathBuf> {
+    let Some(dir) = std::fs::read_link("/proc/self/exe")
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    else {
+        return vec![];
+    };
+    vec![dir.clone(), dir.join("../lib")]
+}
+
+/// Find the probe library for `platform` in `dirs` and the default
+/// directories, skipping builds for another architecture
+pub fn resolve(platform: &Platform, dirs: &[PathBuf]) -> Result<PathBuf> {
+    let names = platform.candidates();
+    let dirs = dirs
+        .iter()
+        .cloned()
+        .chain(default_dirs())
+        .collect::<Vec<_>>();
+    for name in &names {
+        for dir in &dirs {
+            let path = dir.join(name);
+            if !path.is_file() {
+                continue;
+            }
+            match elf_arch(&path) {
+                Ok(arch) if arch == platform.arch => return Ok(path),
+                Ok(arch) => log::debug!("skipping {} built for {arch}", path.display()),
+                Err(err) => log::debug!("skipping {}: {err}", path.display()),
+            }
+        }
+    }
+    Err(anyhow!(
+        "no probe library for {} {}{} found; looked for {} in {}",
+        platform.arch,
+        platform.libc,
+        if platform.cuda { " with CUDA" } else { "" },
+        names.join(", "),
+        dirs.iter()
+            .map(|d| d.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
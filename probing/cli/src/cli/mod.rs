@@ -1,15 +1,37 @@
 use anyhow::Result;
 use clap::Parser;
 use probing_proto::prelude::Query;
+use probing_proto::protocol::handshake::FEATURE_OPTION_STATUS;
 
+pub mod archive;
+pub mod bench;
+pub mod cluster;
 pub mod commands;
+pub mod compare;
 pub mod ctrl;
+pub mod dash;
+pub mod diagnose;
+pub mod proxy;
+pub mod report;
 
 pub mod store;
+pub mod tunnel;
 
 #[cfg(target_os = "linux")]
 pub mod inject;
 
+#[cfg(target_os = "linux")]
+pub mod children;
+
+#[cfg(target_os = "linux")]
+pub mod group;
+
+#[cfg(target_os = "linux")]
+pub mod hostfile;
+
+#[cfg(target_os = "linux")]
+pub mod library;
+
 #[cfg(target_os = "linux")]
 pub mod process_monitor;
 
@@ -19,7 +41,8 @@ use process_monitor::ProcessMonitor;
 mod ptree;
 
 use crate::cli::ctrl::ProbeEndpoint;
-use commands::Commands;
+use crate::table::Render;
+use commands::{Commands, ExtensionsCommand, SessionCommand, SnapshotCommand};
 use once_cell::sync::Lazy;
 
 fn get_build_info() -> String {
@@ -47,9 +70,17 @@ pub struct Cli {
     verbose: bool,
 
     /// target process, PID (e.g., 1234) for local process, and <ip>:<port> for remote process
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     target: Option<String>,
 
+    /// Apply pause, resume, dump or profile to every process of this process group
+    #[arg(long, value_name = "PGID", conflicts_with_all = ["target", "sid"])]
+    pgid: Option<i32>,
+
+    /// Apply pause, resume, dump or profile to every process of this session
+    #[arg(long, value_name = "SID", conflicts_with = "target")]
+    sid: Option<i32>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -71,20 +102,52 @@ impl Cli {
             Some(Commands::Launch { recursive, args }) => {
                 return ProcessMonitor::new(args, *recursive)?.monitor().await;
             }
+            #[cfg(target_os = "linux")]
+            Some(Commands::Inject(cmd)) if cmd.is_batch() => {
+                return cmd.run_batch().await;
+            }
             Some(Commands::Store(cmd)) => {
                 return cmd.run().await;
             }
+            Some(Commands::Open(cmd)) => {
+                return cmd.run().await;
+            }
+            Some(Commands::Tunnel(cmd)) if cmd.pid.is_some() => {
+                let pid = cmd.pid.unwrap_or_default();
+                return cmd.run(ProbeEndpoint::Local { pid }).await;
+            }
+            Some(Commands::Version { remote: false }) => {
+                println!("probing {}", BUILD_INFO.as_str());
+                return Ok(());
+            }
             _ => {}
         }
 
+        #[cfg(target_os = "linux")]
+        if let Some(scope) = self.group_scope() {
+            let Some(action) = self.command.as_ref().and_then(group_action) else {
+                anyhow::bail!("only pause, resume, dump and profile apply to --pgid and --sid");
+            };
+            return group::run(scope, action).await;
+        }
+
         // For other commands, we need a target
         let target = self.target.clone().unwrap_or("0".to_string());
         let ctrl: ProbeEndpoint = target.as_str().try_into()?;
         self.execute_command(ctrl).await
     }
 
-    async fn handle_list_command(&self, verbose: bool, tree: bool) -> Result<()> {
-        match ptree::collect_probe_processes().await {
+    #[cfg(target_os = "linux")]
+    fn group_scope(&self) -> Option<group::Scope> {
+        match (self.pgid, self.sid) {
+            (Some(pgid), _) => Some(group::Scope::ProcessGroup(pgid)),
+            (None, Some(sid)) => Some(group::Scope::Session(sid)),
+            (None, None) => None,
+        }
+    }
+
+    async fn handle_list_command(&self, verbose: u8, tree: bool) -> Result<()> {
+        match ptree::collect_probe_processes(verbose > 1).await {
             Ok(processes) => {
                 if processes.is_empty() {
                     println!("No processes with injected probes found.");
@@ -96,6 +159,9 @@ impl Cli {
                     println!("Processes with injected probes (tree view):");
                     ptree::print_process_tree(&tree_nodes, verbose, "");
                 } else {
+                    // keep the workers of one framework job or queue together
+                    let mut processes = processes;
+                    processes.sort_by(|a, b| (&a.labels, a.pid).cmp(&(&b.labels, b.pid)));
                     println!("Processes with injected probes:");
                     for p in processes {
                         println!("{}", ptree::format_process(&p, verbose));
@@ -117,11 +183,35 @@ impl Cli {
             return Ok(());
         }
         let command = self.command.as_ref().unwrap();
+        // fail early with a clear error on a probe speaking another protocol;
+        // `version --remote` stays available to find out which one
+        let talks_to_probe = !matches!(
+            command,
+            Commands::Version { .. }
+                | Commands::Proxy(..)
+                | Commands::Tunnel(..)
+                | Commands::Pause
+                | Commands::Resume
+                | Commands::Status { fast: true }
+        ) && matches!(
+            ctrl,
+            ProbeEndpoint::Local { .. }
+                | ProbeEndpoint::Named { .. }
+                | ProbeEndpoint::Remote { .. }
+        );
+        #[cfg(target_os = "linux")]
+        let talks_to_probe = talks_to_probe && !matches!(command, Commands::Inject(..));
+        if talks_to_probe {
+            ctrl::handshake(&ctrl, false).await?;
+        }
         match command {
             #[cfg(target_os = "linux")]
             Commands::Inject(cmd) => cmd.run(ctrl).await,
             Commands::Config { options, setting } => {
                 let options_cfg = options.to_cfg();
+                let options_supported = ctrl::handshake(&ctrl, false)
+                    .await
+                    .is_ok_and(|peer| peer.supports(FEATURE_OPTION_STATUS));
 
                 let query_expr = match (setting, options_cfg) {
                     (Some(setting_str), Some(opts_str)) => {
@@ -142,32 +232,119 @@ impl Cli {
                         }
                     }
                     (None, Some(opts_str)) => opts_str,
+                    (None, None) if options_supported => return ctrl.config_options().await,
                     (None, None) => {
                         "select * from information_schema.df_settings where name like 'probing.%';"
                             .to_string()
                     }
                 };
+                let keys = settings_keys(&query_expr);
 
                 ctrl::query(
-                    ctrl,
+                    ctrl.clone(),
                     Query {
                         expr: query_expr,
                         opts: None,
                     },
+                    Render::Table,
                 )
-                .await
+                .await?;
+                if options_supported && !keys.is_empty() {
+                    ctrl.restart_notice(&keys).await?;
+                }
+                Ok(())
             }
-            Commands::Backtrace { tid } => ctrl.backtrace(*tid).await,
+            Commands::Backtrace { tid, native, all } => match (*native, *all) {
+                (false, false) => ctrl.backtrace(*tid).await,
+                (native, _) => ctrl.thread_stacks(*tid, native).await,
+            },
             Commands::Rdma { hca_name } => {
                 let hca_name = hca_name.clone().unwrap_or_default();
                 ctrl.rdma(hca_name).await
             }
-            Commands::Eval { code } => ctrl.eval(code.clone()).await,
-            Commands::Query { query } => ctrl::query(ctrl, Query::new(query.clone())).await,
+            #[cfg(target_os = "linux")]
+            Commands::Eval {
+                code: Some(code),
+                children: true,
+                ..
+            } => children::eval(ctrl, code.clone()).await,
+            #[cfg(not(target_os = "linux"))]
+            Commands::Eval { children: true, .. } | Commands::Query { children: true, .. } => {
+                anyhow::bail!("--children is only supported on Linux")
+            }
+            Commands::Eval {
+                script: Some(script),
+                keep_going,
+                ..
+            } => ctrl.eval_script(script, *keep_going).await,
+            Commands::Eval { code, .. } => ctrl.eval(code.clone().unwrap_or_default()).await,
+            Commands::Session { command } => match command {
+                None | Some(SessionCommand::List) => ctrl.sessions().await,
+                Some(SessionCommand::Save { name }) => ctrl.save_session(name).await,
+                Some(SessionCommand::Show { name }) => ctrl.show_session(name.as_deref()).await,
+            },
+            #[cfg(target_os = "linux")]
+            Commands::Query {
+                query,
+                render,
+                children: true,
+                ..
+            } => children::query(ctrl, Query::new(query.clone()), *render).await,
+            Commands::Query {
+                query,
+                watch: None,
+                render,
+                ..
+            } => ctrl::query(ctrl, Query::new(query.clone()), *render).await,
+            Commands::Query {
+                query,
+                watch: Some(interval),
+                render,
+                ..
+            } => ctrl::watch_query(ctrl, Query::new(query.clone()), *interval, *render).await,
+            Commands::Extensions { command } => match command {
+                None | Some(ExtensionsCommand::List) => ctrl.extensions().await,
+                Some(ExtensionsCommand::Enable { name }) => ctrl.toggle_extension(name, true).await,
+                Some(ExtensionsCommand::Disable { name }) => {
+                    ctrl.toggle_extension(name, false).await
+                }
+            },
+            Commands::Report { kind } => report::run(ctrl, *kind).await,
+            Commands::Bench(cmd) => cmd.run(ctrl).await,
+            Commands::Dash(cmd) => cmd.run(ctrl).await,
+            Commands::Cluster(cmd) => cmd.run(ctrl).await,
+            Commands::Compare(cmd) => cmd.run(ctrl).await,
+            Commands::Proxy(cmd) => cmd.run(ctrl).await,
+            Commands::Tunnel(cmd) => cmd.run(ctrl).await,
+            Commands::Status { fast: false } => ctrl.status().await,
+            Commands::Status { fast: true } => ctrl.fast_status(),
+            Commands::KillSwitch { resume } => ctrl.kill_switch(*resume).await,
+            Commands::Diagnose { share, output } => {
+                diagnose::run(ctrl, *share, output.clone()).await
+            }
+            Commands::Export { tables, to } => ctrl.export(tables, to).await,
+            Commands::Doctor { name } => ctrl.doctor(name.as_deref()).await,
+            Commands::Version { remote: true } => ctrl.capabilities().await,
+            #[cfg(target_os = "linux")]
+            Commands::Pause | Commands::Resume | Commands::Dump | Commands::Profile { .. } => {
+                let action = group_action(command).expect("a group action");
+                group::run_target(ctrl, action).await
+            }
+            #[cfg(not(target_os = "linux"))]
+            Commands::Pause | Commands::Resume | Commands::Dump | Commands::Profile { .. } => {
+                anyhow::bail!("pause, resume, dump and profile are only supported on Linux")
+            }
+            Commands::Snapshot { command } => match command {
+                None | Some(SnapshotCommand::List) => ctrl.snapshots().await,
+                Some(SnapshotCommand::Save { name }) => ctrl.save_snapshot(name).await,
+                Some(SnapshotCommand::Diff { a, b }) => ctrl.diff_snapshots(a, b).await,
+            },
             // These commands are handled in run() method and don't need a target
             Commands::Launch { .. }
             | Commands::List { .. }
             | Commands::Store(..)
+            | Commands::Open(..)
+            | Commands::Version { .. }
             | Commands::External(..) => {
                 unreachable!("These commands should be handled in run() method")
             }
@@ -175,6 +352,33 @@ impl Cli {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn group_action(command: &Commands) -> Option<group::Action> {
+    match command {
+        Commands::Pause => Some(group::Action::Pause),
+        Commands::Resume => Some(group::Action::Resume),
+        Commands::Dump => Some(group::Action::Dump),
+        Commands::Profile { freq } => Some(group::Action::Profile { freq: *freq }),
+        _ => None,
+    }
+}
+
+/// Option keys assigned by `set <key>=<value>` statements, without the
+/// `probing.` prefix
+fn settings_keys(statements: &str) -> Vec<String> {
+    statements
+        .split(';')
+        .filter_map(|statement| {
+            let statement = statement.trim();
+            let assignment = statement
+                .strip_prefix("set ")
+                .or_else(|| statement.strip_prefix("SET "))?;
+            let (key, _) = assignment.split_once('=')?;
+            Some(key.trim().trim_start_matches("probing.").to_string())
+        })
+        .collect()
+}
+
 fn handle_external_command(args: &[String]) -> Result<()> {
     if args.is_empty() {
         eprintln!("Command not specified. Please provide a subcommand.");
@@ -0,0 +1,591 @@
+//! `probing -t <addr> proxy --listen :9800`: a local HTTP endpoint in front
+//! of a probe reached over a slow or flaky network, such as a VPN.
+//!
+//! Browsers, dashboards and scripts point at the proxy instead of the probe.
+//! The proxy
+//!
+//! - keeps the static assets of the web UI once fetched, so pages load
+//!   without crossing the network again;
+//! - sends identical reads in flight at the same time (`GET` requests and
+//!   `POST /query` without `SET`) once and hands the reply to all of them;
+//! - retries reads that fail on the network or with a 502, 503 or 504 with an
+//!   exponential backoff, and other requests when the probe could not be
+//!   reached at all, since then nothing was sent;
+//! - answers a read that still fails with its last good reply, up to
+//!   `--max-stale` seconds old, flagged with `X-Probing-Stale: true`,
+//!   `X-Probing-Age` in seconds and a `Warning: 110` header, so polling
+//!   dashboards ride out short partitions. A probe put to sleep by its kill
+//!   switch is not a partition, its refusals are passed on as they are.
+//!
+//! Fresh replies carry `X-Probing-Stale: false`. Replies are shared and kept
+//! per token and user, so a client never gets a reply fetched for another.
+//! `PROBING_AUTH_TOKEN` is only added to requests when the proxy listens on
+//! loopback, otherwise anyone reaching the proxy would act with it.
+//!
+//! A web page open in the browser of the operator can send requests to the
+//! proxy too, so requests from another origin are refused, and on loopback
+//! so are requests for a host name other than `localhost`, which a DNS
+//! rebinding page would use. `Origin` is passed on for the CORS check of the
+//! probe.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::Args;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderMap, HeaderValue};
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::sync::OnceCell;
+
+use super::ctrl::ProbeEndpoint;
+
+/// Good replies kept to answer reads during a partition; the oldest go first
+const MAX_KEPT: usize = 1024;
+
+/// Files of the web UI kept, later ones are fetched each time
+const MAX_ASSETS: usize = 256;
+
+/// First retry delay, doubled after each failure
+const FIRST_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Request headers passed on to the probe
+const FORWARDED: &[&str] = &[
+    "content-type",
+    "accept",
+    "x-probing-token",
+    "x-probing-user",
+    "origin",
+];
+
+/// Reply headers passed back to the client
+const RETURNED: &[&str] = &[
+    "content-type",
+    "content-disposition",
+    "cache-control",
+    "x-probing-dormant",
+];
+
+/// `SET` statements in a query body, which change the probe and are neither
+/// shared nor retried
+static SET_STATEMENT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)(^|;|")\s*set\s"#).unwrap());
+
+#[derive(Args, Debug)]
+pub struct ProxyCommand {
+    /// Address to listen on; `:9800` listens on localhost only, pass
+    /// `0.0.0.0:9800` to accept other hosts
+    #[arg(short, long, default_value = ":9800")]
+    pub listen: String,
+
+    /// Retries of a failing request before giving up
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+
+    /// Oldest reply, in seconds, served when the probe cannot be reached
+    #[arg(long, default_value_t = 300.0)]
+    pub max_stale: f64,
+}
+
+/// Reply of the probe, or of the proxy when the probe failed
+#[derive(Clone)]
+struct Reply {
+    status: StatusCode,
+    headers: Vec<(String, HeaderValue)>,
+    body: Bytes,
+}
+
+impl Reply {
+    fn error(status: StatusCode, message: String) -> Self {
+        Reply {
+            status,
+            headers: vec![(
+                "content-type".to_string(),
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            )],
+            body: Bytes::from(message),
+        }
+    }
+
+    fn is_good(&self) -> bool {
+        self.status.is_success()
+    }
+
+    /// Whether the probe, or a gateway in front of it, could not answer; a
+    /// dormant probe did answer, by refusing
+    fn is_unavailable(&self) -> bool {
+        let dormant = self
+            .headers
+            .iter()
+            .any(|(name, _)| name == "x-probing-dormant");
+        !dormant
+            && matches!(
+                self.status,
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            )
+    }
+
+    fn into_response(self, age: Option<Duration>) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(self.body));
+        *response.status_mut() = self.status;
+        let headers = response.headers_mut();
+        for (name, value) in self.headers {
+            if let Ok(name) = hyper::header::HeaderName::try_from(name) {
+                headers.insert(name, value);
+            }
+        }
+        match age {
+            Some(age) => {
+                headers.insert("x-probing-stale", HeaderValue::from_static("true"));
+                headers.insert("x-probing-age", HeaderValue::from(age.as_secs()));
+                headers.insert(
+                    "warning",
+                    HeaderValue::from_static("110 probing \"Response is Stale\""),
+                );
+            }
+            None => {
+                headers.insert("x-probing-stale", HeaderValue::from_static("false"));
+            }
+        }
+        response
+    }
+}
+
+/// Why a request to the probe failed
+enum Failure {
+    /// The probe could not be reached, nothing was sent
+    Connect(String),
+    /// The connection broke after the request was sent
+    Exchange(String),
+}
+
+/// Request as forwarded, also the key of shared and kept replies
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Forward {
+    method: Method,
+    uri: String,
+    body: Bytes,
+    /// `X-Probing-Token` and `X-Probing-User` of the client, the reply
+    /// depends on who asks
+    token: Option<HeaderValue>,
+    user: Option<HeaderValue>,
+}
+
+impl Forward {
+    /// Whether the request reads without changing the probe
+    fn is_read(&self) -> bool {
+        if self.method == Method::GET || self.method == Method::HEAD {
+            return true;
+        }
+        self.method == Method::POST
+            && self.uri == "/query"
+            && !SET_STATEMENT.is_match(&String::from_utf8_lossy(&self.body))
+    }
+
+    /// Whether the request is for a file of the web UI rather than data
+    fn is_asset(&self) -> bool {
+        let path = self.uri.split('?').next().unwrap_or_default();
+        self.method == Method::GET
+            && !["/apis", "/query", "/config", "/ws"]
+                .iter()
+                .any(|prefix| path.starts_with(prefix))
+    }
+}
+
+/// Why a request reaching a local listener is refused, if it is: it must
+/// come from the origin it is sent to, and name `localhost` or an address as
+/// its host when `loopback`, so that neither another site nor a host name
+/// rebound to loopback can use the listener on `port`
+pub(super) fn refuse_request(
+    host: Option<&str>,
+    origin: Option<&str>,
+    port: u16,
+    loopback: bool,
+) -> Option<String> {
+    let Some(host) = host else {
+        return Some("no Host header".to_string());
+    };
+    if loopback {
+        let (name, host_port) = match host.rsplit_once(':') {
+            Some((name, host_port)) if !name.ends_with(':') => (name, host_port),
+            _ => (host, "80"),
+        };
+        let name = name.trim_start_matches('[').trim_end_matches(']');
+        let local =
+            name.eq_ignore_ascii_case("localhost") || name.parse::<std::net::IpAddr>().is_ok();
+        if !local || host_port != port.to_string() {
+            return Some(format!("host {host} is not this listener"));
+        }
+    }
+    match origin {
+        None => None,
+        Some(origin) => {
+            let authority = origin
+                .strip_prefix("http://")
+                .or_else(|| origin.strip_prefix("https://"));
+            match authority {
+                Some(authority) if authority.eq_ignore_ascii_case(host) => None,
+                _ => Some(format!("requests from {origin} are not allowed")),
+            }
+        }
+    }
+}
+
+struct Proxy {
+    target: ProbeEndpoint,
+    /// Port the proxy listens on, and whether on loopback
+    port: u16,
+    loopback: bool,
+    /// `PROBING_AUTH_TOKEN`, sent to a remote probe when the client sent
+    /// none, only when listening on loopback
+    token: Option<HeaderValue>,
+    retries: u32,
+    max_stale: Duration,
+    assets: Mutex<HashMap<String, Reply>>,
+    in_flight: Mutex<HashMap<Forward, Arc<OnceCell<Reply>>>>,
+    kept: Mutex<HashMap<Forward, (Instant, Reply)>>,
+}
+
+impl Proxy {
+    async fn connect(&self) -> Result<hyper::client::conn::http1::SendRequest<Full<Bytes>>> {
+        use hyper::client::conn::http1::handshake;
+
+        macro_rules! open {
+            ($stream:expr) => {{
+                let (sender, connection) = handshake(TokioIo::new($stream)).await?;
+                tokio::spawn(async move {
+                    if let Err(err) = connection.await {
+                        log::debug!("proxy connection closed: {err}");
+                    }
+                });
+                sender
+            }};
+        }
+        let sender = match &self.target {
+            ProbeEndpoint::Remote { addr } => open!(tokio::net::TcpStream::connect(addr).await?),
+            ProbeEndpoint::Local { pid } | ProbeEndpoint::Ptrace { pid } => {
+                #[cfg(target_os = "linux")]
+                let path = format!("\0probing-{pid}");
+                #[cfg(not(target_os = "linux"))]
+                let path = std::env::temp_dir().join(format!("probing-{pid}.sock"));
+                open!(tokio::net::UnixStream::connect(path).await?)
+            }
+            ProbeEndpoint::Named { pid, name } => {
+                #[cfg(target_os = "linux")]
+                let path = format!("\0probing-{pid}-{name}");
+                #[cfg(not(target_os = "linux"))]
+                let path = std::env::temp_dir().join(format!("probing-{pid}-{name}.sock"));
+                open!(tokio::net::UnixStream::connect(path).await?)
+            }
+            ProbeEndpoint::Launch { .. } => anyhow::bail!("the proxy needs a running probe"),
+        };
+        Ok(sender)
+    }
+
+    /// Send the request once
+    async fn send(&self, forward: &Forward, headers: &HeaderMap) -> Result<Reply, Failure> {
+        let mut sender = self
+            .connect()
+            .await
+            .map_err(|err| Failure::Connect(err.to_string()))?;
+        let mut request = Request::builder()
+            .method(forward.method.clone())
+            .uri(forward.uri.as_str());
+        for name in FORWARDED {
+            if let Some(value) = headers.get(*name) {
+                request = request.header(*name, value);
+            }
+        }
+        if let (Some(token), None) = (&self.token, &forward.token) {
+            request = request.header("x-probing-token", token);
+        }
+        let request = request
+            .body(Full::new(forward.body.clone()))
+            .map_err(|err| Failure::Connect(err.to_string()))?;
+
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|err| Failure::Exchange(err.to_string()))?;
+        let status = response.status();
+        let headers = RETURNED
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers().get(*name)?;
+                Some((name.to_string(), value.clone()))
+            })
+            .collect();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|err| Failure::Exchange(err.to_string()))?
+            .to_bytes();
+        Ok(Reply {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    /// Send the request, retrying with backoff what is safe to retry
+    async fn send_with_retries(&self, forward: &Forward, headers: &HeaderMap) -> Reply {
+        let read = forward.is_read();
+        let mut backoff = FIRST_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let (reply, retry) = match self.send(forward, headers).await {
+                Ok(reply) => {
+                    let retry = read && reply.is_unavailable();
+                    (reply, retry)
+                }
+                Err(Failure::Connect(err)) => (
+                    Reply::error(StatusCode::BAD_GATEWAY, format!("probe unreachable: {err}")),
+                    true,
+                ),
+                Err(Failure::Exchange(err)) => (
+                    Reply::error(
+                        StatusCode::BAD_GATEWAY,
+                        format!("probe failed to reply: {err}"),
+                    ),
+                    read,
+                ),
+            };
+            if !retry || attempt >= self.retries {
+                return reply;
+            }
+            attempt += 1;
+            log::warn!(
+                "{} {}: {}, retry {attempt} in {backoff:?}",
+                forward.method,
+                forward.uri,
+                reply.status
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    /// Send a read, sharing the reply with identical reads in flight
+    async fn send_shared(&self, forward: &Forward, headers: &HeaderMap) -> Reply {
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(forward.clone())
+            .or_default()
+            .clone();
+        let reply = cell
+            .get_or_init(|| self.send_with_retries(forward, headers))
+            .await
+            .clone();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(forward)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(forward);
+        }
+        reply
+    }
+
+    fn keep(&self, forward: &Forward, reply: &Reply) {
+        let mut kept = self.kept.lock().unwrap();
+        if kept.len() >= MAX_KEPT && !kept.contains_key(forward) {
+            let oldest = kept
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                kept.remove(&oldest);
+            }
+        }
+        kept.insert(forward.clone(), (Instant::now(), reply.clone()));
+    }
+
+    /// Last good reply to `forward` if recent enough, with its age
+    fn kept(&self, forward: &Forward) -> Option<(Duration, Reply)> {
+        let kept = self.kept.lock().unwrap();
+        let (at, reply) = kept.get(forward)?;
+        let age = at.elapsed();
+        (age <= self.max_stale).then(|| (age, reply.clone()))
+    }
+
+    async fn handle(&self, request: Request<Incoming>) -> Response<Full<Bytes>> {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+        };
+        let host = header(hyper::header::HOST).or(request.uri().authority().map(|a| a.as_str()));
+        if let Some(reason) = refuse_request(
+            host,
+            header(hyper::header::ORIGIN),
+            self.port,
+            self.loopback,
+        ) {
+            log::warn!("{} {}: refused, {reason}", request.method(), request.uri());
+            return Reply::error(StatusCode::FORBIDDEN, reason).into_response(None);
+        }
+        if request.headers().contains_key(hyper::header::UPGRADE) {
+            return Reply::error(
+                StatusCode::NOT_IMPLEMENTED,
+                "the proxy does not forward websockets, connect to the probe directly".into(),
+            )
+            .into_response(None);
+        }
+        let (parts, body) = request.into_parts();
+        let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(err) => {
+                return Reply::error(StatusCode::BAD_REQUEST, err.to_string()).into_response(None)
+            }
+        };
+        let forward = Forward {
+            method: parts.method,
+            uri: parts
+                .uri
+                .path_and_query()
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "/".to_string()),
+            body,
+            token: parts.headers.get("x-probing-token").cloned(),
+            user: parts.headers.get("x-probing-user").cloned(),
+        };
+
+        if forward.is_asset() {
+            if let Some(asset) = self.assets.lock().unwrap().get(&forward.uri) {
+                return asset.clone().into_response(None);
+            }
+        }
+        if !forward.is_read() {
+            return self
+                .send_with_retries(&forward, &parts.headers)
+                .await
+                .into_response(None);
+        }
+
+        let reply = self.send_shared(&forward, &parts.headers).await;
+        if reply.is_good() {
+            match forward.is_asset() {
+                true => {
+                    let mut assets = self.assets.lock().unwrap();
+                    if assets.len() < MAX_ASSETS {
+                        assets.insert(forward.uri.clone(), reply.clone());
+                    }
+                }
+                false => self.keep(&forward, &reply),
+            }
+            return reply.into_response(None);
+        }
+        if reply.is_unavailable() {
+            if let Some((age, kept)) = self.kept(&forward) {
+                log::warn!(
+                    "{} {}: serving the reply of {}s ago",
+                    forward.method,
+                    forward.uri,
+                    age.as_secs()
+                );
+                return kept.into_response(Some(age));
+            }
+        }
+        reply.into_response(None)
+    }
+}
+
+/// `:9800` as `127.0.0.1:9800`
+fn listen_addr(listen: &str) -> String {
+    match listen.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{port}"),
+        None => listen.to_string(),
+    }
+}
+
+impl ProxyCommand {
+    pub async fn run(&self, target: ProbeEndpoint) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(listen_addr(&self.listen)).await?;
+        let local = listener.local_addr()?;
+        let loopback = local.ip().is_loopback();
+        let token = match &target {
+            ProbeEndpoint::Remote { .. } => std::env::var("PROBING_AUTH_TOKEN")
+                .ok()
+                .and_then(|token| HeaderValue::from_str(&token).ok()),
+            _ => None,
+        };
+        if token.is_some() && !loopback {
+            eprintln!(
+                "not adding PROBING_AUTH_TOKEN to requests on a non-loopback listener, \
+                 clients must send their own X-Probing-Token"
+            );
+        }
+        let proxy = Arc::new(Proxy {
+            target,
+            port: local.port(),
+            loopback,
+            token: token.filter(|_| loopback),
+            retries: self.retries,
+            max_stale: Duration::from_secs_f64(self.max_stale.max(0.0)),
+            assets: Default::default(),
+            in_flight: Default::default(),
+            kept: Default::default(),
+        });
+
+        let target = match &proxy.target {
+            ProbeEndpoint::Remote { addr } => addr.clone(),
+            ProbeEndpoint::Named { pid, name } => format!("{pid}@{name}"),
+            ProbeEndpoint::Local { pid } | ProbeEndpoint::Ptrace { pid } => pid.to_string(),
+            ProbeEndpoint::Launch { cmd } => cmd.clone(),
+        };
+        eprintln!("proxying http://{} to {target}", listener.local_addr()?);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let proxy = proxy.clone();
+            tokio::spawn(async move {
+                let service = hyper::service::service_fn(move |request| {
+                    let proxy = proxy.clone();
+                    async move { Ok::<_, Infallible>(proxy.handle(request).await) }
+                });
+                let connection = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service);
+                if let Err(err) = connection.await {
+                    log::debug!("client connection closed: {err}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refuse_request() {
+        assert!(refuse_request(Some("127.0.0.1:9800"), None, 9800, true).is_none());
+        assert!(refuse_request(Some("localhost:9800"), None, 9800, true).is_none());
+        assert!(refuse_request(Some("[::1]:9800"), None, 9800, true).is_none());
+        let same = Some("http://127.0.0.1:9800");
+        assert!(refuse_request(Some("127.0.0.1:9800"), same, 9800, true).is_none());
+
+        // another site, or a host name rebound to loopback
+        let foreign = Some("https://evil.example.com");
+        assert!(refuse_request(Some("127.0.0.1:9800"), foreign, 9800, true).is_some());
+        assert!(refuse_request(Some("127.0.0.1:9800"), Some("null"), 9800, true).is_some());
+        assert!(refuse_request(Some("evil.example.com:9800"), None, 9800, true).is_some());
+        assert!(refuse_request(Some("127.0.0.1:9801"), None, 9800, true).is_some());
+        assert!(refuse_request(None, None, 9800, true).is_some());
+
+        // host names reach a proxy listening on all interfaces
+        assert!(refuse_request(Some("gpu3:9800"), None, 9800, false).is_none());
+        assert!(refuse_request(Some("gpu3:9800"), foreign, 9800, false).is_some());
+    }
+}
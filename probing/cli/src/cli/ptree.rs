@@ -1,5 +1,6 @@
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::path::Path;
 
 #[cfg(target_os = "linux")]
@@ -7,6 +8,10 @@ use std::fs::File;
 #[cfg(target_os = "linux")]
 use std::io::{BufRead, BufReader};
 
+use probing_core::core::frameworks::{self, Labels};
+
+use probing_proto::prelude::ProbeResources;
+
 use crate::cli::ctrl::{self, ProbeEndpoint};
 
 #[derive(Debug, Default, Clone)]
@@ -16,17 +21,77 @@ pub struct ProcessInfo {
     pub cmd: String,
     pub socket_name: Option<String>,
     pub remote_addr: Option<String>,
+    /// Ray/Dask/Celery worker labels, see `probing_core::core::frameworks`
+    pub labels: Labels,
+    /// What the probe takes in the process, fetched for `list -vv`
+    pub resources: Option<ProbeResources>,
+    /// Where the web UI of the probe can be opened
+    pub web: WebUi,
     pub children: Vec<ProcessInfo>,
 }
 
-/// Collect information about processes with injected probes
-pub async fn collect_probe_processes() -> Result<Vec<ProcessInfo>> {
+/// Where the web UI of a probe can be opened from the host of the CLI
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum WebUi {
+    /// The probe serves no HTTP address
+    #[default]
+    Missing,
+    Reachable(String),
+    /// Served at this URL, which the host of the CLI cannot reach
+    Unreachable(String),
+}
+
+/// Web UI of a probe serving HTTP at `addr`, `same_netns` telling whether it
+/// shares the network namespace of the CLI.
+///
+/// A probe listening on all interfaces is named by the hostname; one in
+/// another namespace, e.g. a container, is out of reach on its loopback or
+/// wildcard address.
+fn web_ui(addr: Option<&str>, same_netns: bool, hostname: &str) -> WebUi {
+    let Some(addr) = addr.and_then(|addr| addr.trim().parse::<SocketAddr>().ok()) else {
+        return WebUi::Missing;
+    };
+    let (ip, port) = (addr.ip(), addr.port());
+    let host = match ip {
+        ip if ip.is_unspecified() => hostname.to_string(),
+        std::net::IpAddr::V6(ip) => format!("[{ip}]"),
+        ip => ip.to_string(),
+    };
+    let url = format!("http://{host}:{port}/");
+    match same_netns || !(ip.is_loopback() || ip.is_unspecified()) {
+        true => WebUi::Reachable(url),
+        false => WebUi::Unreachable(url),
+    }
+}
+
+/// Whether process `pid` shares our network namespace, assumed when unknown
+fn shares_netns(pid: i32) -> bool {
+    let ours = std::fs::read_link("/proc/self/ns/net");
+    let theirs = std::fs::read_link(format!("/proc/{pid}/ns/net"));
+    match (ours, theirs) {
+        (Ok(ours), Ok(theirs)) => ours == theirs,
+        _ => true,
+    }
+}
+
+fn hostname() -> String {
+    nix::sys::utsname::uname()
+        .map(|uname| uname.nodename().to_string_lossy().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// Collect information about processes with injected probes, asking each
+/// probe for its resource usage when `resources` is set
+pub async fn collect_probe_processes(resources: bool) -> Result<Vec<ProcessInfo>> {
     let mut processes = Vec::new();
     let mut tasks = Vec::new();
 
     for (pid_val, socket_name_val) in find_probe_sockets()? {
         tasks.push(tokio::spawn(async move {
-            let res = get_process_info(pid_val, Some(socket_name_val.clone())).await;
+            let mut res = get_process_info(pid_val, Some(socket_name_val.clone())).await;
+            if let (true, Ok(info)) = (resources, res.as_mut()) {
+                info.resources = get_resources(pid_val).await;
+            }
             (pid_val, socket_name_val, res) // Return pid and socket_name along with the result
         }));
     }
@@ -44,6 +109,7 @@ pub async fn collect_probe_processes() -> Result<Vec<ProcessInfo>> {
                     ppid,
                     cmd,
                     socket_name: Some(socket_name),
+                    labels: frameworks::detect_pid(pid),
                     ..Default::default()
                 });
             }
@@ -79,6 +145,11 @@ fn find_probe_sockets() -> Result<Vec<(i32, String)>, std::io::Error> {
 
             // Check for the new naming convention: @probing-<pid>
             if let Some(pid_str) = socket_name_full.strip_prefix("@probing-") {
+                // Named instances (@probing-<pid>-<name>) share the process
+                // of the default socket, which is listed already
+                if pid_str.contains('-') {
+                    continue;
+                }
                 if let Ok(pid) = pid_str.parse::<i32>() {
                     result.push((pid, socket_name_full.to_string()));
                 } else {
@@ -148,16 +219,31 @@ pub async fn get_process_info(pid: i32, socket_name: Option<String>) -> Result<P
         }
     }
 
+    let web = web_ui(remote_addr.as_deref(), shares_netns(pid), &hostname());
     Ok(ProcessInfo {
         pid,
         ppid,
         cmd,
         socket_name,
         remote_addr,
+        labels: frameworks::detect_pid(pid),
+        resources: None,
+        web,
         children: Vec::new(), // Initialize children
     })
 }
 
+/// Resources taken by the probe of `pid`, None if it does not report them
+async fn get_resources(pid: i32) -> Option<ProbeResources> {
+    let reply = ctrl::request(ProbeEndpoint::Local { pid }, "/apis/resources", None)
+        .await
+        .inspect_err(|e| log::warn!("PID {pid}: HTTP request to /apis/resources failed: {e}"))
+        .ok()?;
+    serde_json::from_slice(&reply)
+        .inspect_err(|e| log::warn!("PID {pid}: Failed to parse resources: {e}"))
+        .ok()
+}
+
 /// Read parent PID. Implementation is OS-specific.
 #[cfg(target_os = "linux")]
 fn read_parent_pid(pid: i32) -> Result<i32, std::io::Error> {
@@ -363,7 +449,7 @@ fn build_subtree_recursive(
 }
 
 /// Print the process tree
-pub fn print_process_tree(nodes: &[ProcessInfo], verbose: bool, prefix: &str) {
+pub fn print_process_tree(nodes: &[ProcessInfo], verbose: u8, prefix: &str) {
     // `is_parent_last` indicates if the direct parent of the current list of `nodes` was the last in its own sibling list.
     // This helps determine the vertical bar character in the prefix for children.
     for (i, node) in nodes.iter().enumerate() {
@@ -393,16 +479,83 @@ pub fn print_process_tree(nodes: &[ProcessInfo], verbose: bool, prefix: &str) {
     }
 }
 
-/// Format process information for display
-pub fn format_process(info: &ProcessInfo, verbose: bool) -> String {
-    if verbose {
+/// Format process information for display: `-v` adds the sockets and the
+/// worker labels, `-vv` the resources of the probe
+pub fn format_process(info: &ProcessInfo, verbose: u8) -> String {
+    let framework = match info.labels.get("framework") {
+        Some(framework) if verbose > 0 => {
+            let labels = frameworks::format_labels(&info.labels);
+            format!(" [{}]", format!("{framework} {labels}").trim_end())
+        }
+        Some(framework) => format!(" [{framework}]"),
+        None => String::new(),
+    };
+    let resources = match (&info.resources, verbose) {
+        (Some(resources), 2..) => format!(" {{{}}}", format_resources(resources)),
+        (None, 2..) => " {resources: -}".to_string(),
+        _ => String::new(),
+    };
+    let web = format_web_ui(info);
+    if verbose > 0 {
         let local = info.socket_name.as_deref().unwrap_or("-");
         let remote = info.remote_addr.as_deref().unwrap_or("-");
         format!(
-            "{} (local: {local}, remote: {remote}): {}",
+            "{}{framework} (local: {local}, remote: {remote}){resources}: {}{web}",
             info.pid, info.cmd
         )
     } else {
-        format!("{}: {}", info.pid, info.cmd)
+        format!("{}{framework}: {}{web}", info.pid, info.cmd)
+    }
+}
+
+/// ` -> <url>` for the web UI, with how to tunnel to it when out of reach
+fn format_web_ui(info: &ProcessInfo) -> String {
+    let tunnel = format!("`probing tunnel {}`", info.pid);
+    match &info.web {
+        WebUi::Reachable(url) => format!(" -> {url}"),
+        WebUi::Unreachable(url) => format!(" -> {url} (unreachable from here, {tunnel})"),
+        WebUi::Missing if info.socket_name.is_some() => format!(" -> web UI via {tunnel}"),
+        WebUi::Missing => String::new(),
+    }
+}
+
+/// One-line summary of the resources of a probe, e.g.
+/// `cpu: 0.4%, buffers: 1.5MiB, up: 2h13m, collectors: hot-met,stdout`
+fn format_resources(resources: &ProbeResources) -> String {
+    let cpu = resources
+        .cpu
+        .map_or_else(|| "-".to_string(), |cpu| format!("{cpu:.1}%"));
+    let collectors = match resources.collectors.is_empty() {
+        true => "-".to_string(),
+        false => resources.collectors.join(","),
+    };
+    format!(
+        "cpu: {cpu}, buffers: {}, up: {}, collectors: {collectors}",
+        format_bytes(resources.buffer_bytes),
+        format_uptime(resources.uptime)
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes}B"),
+        _ => format!("{value:.1}{}", UNITS[unit]),
+    }
+}
+
+fn format_uptime(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m{}s", seconds / 60, seconds % 60),
+        3600..86400 => format!("{}h{}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d{}h", seconds / 86400, seconds % 86400 / 3600),
     }
 }
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use probing_proto::prelude::Query;
+
+use crate::cli::ctrl::ProbeEndpoint;
+use crate::table::render_dataframe;
+
+/// Window covered by the trend sections, in seconds
+const TREND_WINDOW_SECS: u64 = 600;
+
+/// Curated diagnostics that can be run with `probing <pid> report <kind>`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportKind {
+    /// Hot Python functions and CPU time trend
+    Cpu,
+    /// GPU memory per module and garbage collection pauses
+    Memory,
+    /// Step durations, slowest modules and anomalies
+    Steps,
+    /// Threads blocked in I/O and I/O counters
+    Io,
+}
+
+struct Section {
+    title: &'static str,
+    sql: String,
+}
+
+fn since() -> String {
+    format!("(to_unixtime(now()) - {TREND_WINDOW_SECS}) * 1000000")
+}
+
+fn sections(kind: ReportKind) -> Vec<Section> {
+    match kind {
+        ReportKind::Cpu => vec![
+            Section {
+                title: "Top functions on all Python threads",
+                sql: "SELECT func, file, count(*) AS threads FROM python.stacks \
+                      WHERE depth = 0 GROUP BY func, file ORDER BY threads DESC LIMIT 10"
+                    .to_string(),
+            },
+            Section {
+                title: "CPU time per minute (last 10 min, clock ticks)",
+                sql: format!(
+                    "SELECT timestamp / 60000000 * 60 AS minute, \
+                     max(cpu_utime) - min(cpu_utime) AS user, \
+                     max(cpu_stime) - min(cpu_stime) AS system \
+                     FROM process.cpu WHERE timestamp > {} \
+                     GROUP BY minute ORDER BY minute",
+                    since()
+                ),
+            },
+        ],
+        ReportKind::Memory => vec![
+            Section {
+                title: "Peak GPU memory by module (MB)",
+                sql: "SELECT module, max(max_allocated) AS peak, max(max_cached) AS cached \
+                      FROM python.torch_trace GROUP BY module ORDER BY peak DESC LIMIT 10"
+                    .to_string(),
+            },
+            Section {
+                title: "Allocated memory trend (last 10 min, MB)",
+                sql: format!(
+                    "SELECT timestamp / 60000000 * 60 AS minute, avg(allocated) AS allocated \
+                     FROM python.torch_trace WHERE timestamp > {} \
+                     GROUP BY minute ORDER BY minute",
+                    since()
+                ),
+            },
+            Section {
+                title: "Garbage collection pauses",
+                sql: "SELECT generation, count(*) AS collections, sum(duration) AS total, \
+                      max(duration) AS longest FROM python.gc GROUP BY generation \
+                      ORDER BY generation"
+                    .to_string(),
+            },
+        ],
+        ReportKind::Steps => vec![
+            Section {
+                title: "Step duration (last 20 steps)",
+                sql: "SELECT step, sum(duration) AS duration FROM python.torch_trace \
+                      WHERE step IS NOT NULL GROUP BY step ORDER BY step DESC LIMIT 20"
+                    .to_string(),
+            },
+            Section {
+                title: "Slowest modules (median duration)",
+                sql: "SELECT module, stage, median(duration) AS median, max(duration) AS max \
+                      FROM python.torch_trace WHERE module IS NOT NULL \
+                      GROUP BY module, stage ORDER BY median DESC LIMIT 10"
+                    .to_string(),
+            },
+            Section {
+                title: "Anomalies",
+                sql: "SELECT metric, ts, value, score, severity FROM analysis.anomalies \
+                      ORDER BY ts DESC LIMIT 10"
+                    .to_string(),
+            },
+        ],
+        ReportKind::Io => vec![
+            Section {
+                title: "Threads by innermost Python frame",
+                sql: "SELECT thread_name, func, file, lineno FROM python.stacks \
+                      WHERE depth = 0 ORDER BY thread_name"
+                    .to_string(),
+            },
+            Section {
+                title: "I/O counters (last 10 min)",
+                sql: format!(
+                    "SELECT * FROM process.io WHERE timestamp > {} ORDER BY timestamp DESC LIMIT 10",
+                    since()
+                ),
+            },
+        ],
+    }
+}
+
+/// Run every query of the report and print the results one section at a time.
+///
+/// Sections whose tables are missing in the target are reported and skipped,
+/// so a report is still useful when only some extensions are enabled.
+pub async fn run(ctrl: ProbeEndpoint, kind: ReportKind) -> Result<()> {
+    for section in sections(kind) {
+        println!("== {} ==", section.title);
+        match ctrl.query(Query::new(section.sql)).await {
+            Ok(df) if !df.is_empty() => render_dataframe(&df),
+            Ok(_) => println!("(no data)"),
+            Err(e) => println!("(unavailable: {e})"),
+        }
+        println!();
+    }
+    Ok(())
+}
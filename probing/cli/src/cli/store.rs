@@ -35,6 +35,13 @@ pub enum StoreSubCommand {
         #[arg()]
         key: String,
     },
+
+    #[command()]
+    MultiGet {
+        /// The keys to get, in one round trip
+        #[arg(required = true)]
+        keys: Vec<String>,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -61,6 +68,12 @@ impl StoreCommand {
                 let value = store.get(key).await?;
                 println!("{value}");
             }
+            StoreSubCommand::MultiGet { keys } => {
+                let values = store.multi_get(keys).await?;
+                for (key, value) in keys.iter().zip(values) {
+                    println!("{key}\t{value}");
+                }
+            }
         }
         Ok(())
     }
@@ -0,0 +1,169 @@
+//! `probing tunnel <pid>`: a local TCP port forwarded to the Unix socket of a
+//! probe, for opening its web UI when its HTTP address cannot be reached,
+//! e.g. from a laptop or for a probe in a container.
+//!
+//! Connections are forwarded byte for byte, WebSockets included. The socket
+//! is on the host of the probe, so the tunnel runs there and a laptop reaches
+//! it through ssh:
+//!
+//! ```text
+//! ssh -L 9800:127.0.0.1:9800 gpu3 probing tunnel 1234 --listen :9800
+//! ```
+//!
+//! The Unix socket takes no token, so the tunnel only listens on loopback
+//! and never exposes the probe to other hosts. Web pages of the browser can
+//! reach loopback too, so the first request of each connection must name the
+//! tunnel as its Host and come from it as Origin, as with `probing proxy`. A
+//! browser does not share a connection between sites, so the later requests
+//! of a connection are forwarded unchecked.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::ctrl::ProbeEndpoint;
+use super::proxy::refuse_request;
+
+/// Longest request head read before deciding whether to forward it
+const MAX_HEAD: usize = 16 * 1024;
+
+/// Forward a local port to the Unix socket of a probe
+#[derive(Args, Debug)]
+pub struct TunnelCommand {
+    /// PID of the probe, instead of `-t`
+    pub pid: Option<i32>,
+
+    /// Loopback address to listen on; `:9800` is `127.0.0.1:9800`, and `:0`
+    /// picks a free port
+    #[arg(short, long, default_value = ":0")]
+    pub listen: String,
+}
+
+/// Path of the Unix socket of a probe on this host
+fn socket_path(target: &ProbeEndpoint) -> Result<String> {
+    let name = match target {
+        ProbeEndpoint::Local { pid } | ProbeEndpoint::Ptrace { pid } => format!("probing-{pid}"),
+        ProbeEndpoint::Named { pid, name } => format!("probing-{pid}-{name}"),
+        ProbeEndpoint::Remote { addr } => anyhow::bail!(
+            "{addr} is reached over TCP already, use `probing -t {addr} proxy` instead"
+        ),
+        ProbeEndpoint::Launch { .. } => anyhow::bail!("a tunnel needs a running probe"),
+    };
+    #[cfg(target_os = "linux")]
+    let path = format!("\0{name}");
+    #[cfg(not(target_os = "linux"))]
+    let path = std::env::temp_dir()
+        .join(format!("{name}.sock"))
+        .to_string_lossy()
+        .to_string();
+    Ok(path)
+}
+
+fn listen_addr(listen: &str) -> String {
+    match listen.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{port}"),
+        None => listen.to_string(),
+    }
+}
+
+/// Value of the header `name` in a request head
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    let mut lines = head.lines().skip(1).take_while(|line| !line.is_empty());
+    lines.find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+/// Read the client until the end of the first request head, which is
+/// returned with anything read past it
+async fn read_head(client: &mut tokio::net::TcpStream) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() >= MAX_HEAD {
+            anyhow::bail!("request head over {MAX_HEAD} bytes");
+        }
+        let n = client.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed before a request");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Ok(buf)
+}
+
+async fn forward(mut client: tokio::net::TcpStream, path: String, port: u16) -> Result<()> {
+    let head = read_head(&mut client).await?;
+    let text = String::from_utf8_lossy(&head);
+    if let Some(reason) = refuse_request(header(&text, "host"), header(&text, "origin"), port, true)
+    {
+        let reply = format!(
+            "HTTP/1.1 403 Forbidden\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{reason}",
+            reason.len()
+        );
+        client.write_all(reply.as_bytes()).await?;
+        anyhow::bail!("refused: {reason}");
+    }
+    let mut probe = tokio::net::UnixStream::connect(path).await?;
+    probe.write_all(&head).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut probe).await?;
+    Ok(())
+}
+
+impl TunnelCommand {
+    pub async fn run(&self, target: ProbeEndpoint) -> Result<()> {
+        let path = socket_path(&target)?;
+        // fail now rather than on the first connection of the browser
+        tokio::net::UnixStream::connect(&path)
+            .await
+            .context("no probe is listening, is it injected and on this host?")?;
+
+        let listen = listen_addr(&self.listen);
+        let loopback = listen
+            .parse::<std::net::SocketAddr>()
+            .is_ok_and(|addr| addr.ip().is_loopback());
+        if !loopback {
+            anyhow::bail!(
+                "refusing to expose the unauthenticated socket of the probe on {listen}, \
+                 listen on a loopback address and reach it through ssh"
+            );
+        }
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        let addr = listener.local_addr()?;
+        let host = nix::sys::utsname::uname()
+            .map(|uname| uname.nodename().to_string_lossy().to_string())
+            .unwrap_or("<host>".to_string());
+        eprintln!("web UI at http://{addr}/, Ctrl-C to close the tunnel");
+        let port = addr.port();
+        eprintln!("from another machine: ssh -L {port}:127.0.0.1:{port} {host}");
+        loop {
+            let (client, _) = listener.accept().await?;
+            let path = path.clone();
+            tokio::spawn(async move {
+                if let Err(err) = forward(client, path, port).await {
+                    log::debug!("tunnel connection closed: {err}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let head = "GET / HTTP/1.1\r\nHost: 127.0.0.1:9800\r\norigin:  http://evil.example\r\n\r\n";
+        assert_eq!(header(head, "Host"), Some("127.0.0.1:9800"));
+        assert_eq!(header(head, "origin"), Some("http://evil.example"));
+        assert_eq!(header(head, "referer"), None);
+        // the body that came with the head is not searched
+        let head = "POST / HTTP/1.1\r\nHost: localhost\r\n\r\nOrigin: http://localhost";
+        assert_eq!(header(head, "origin"), None);
+    }
+}
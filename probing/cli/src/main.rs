@@ -5,9 +5,6 @@ use env_logger::Env;
 mod cli;
 mod table;
 
-#[cfg(target_os = "linux")]
-mod inject;
-
 const ENV_PROBING_LOGLEVEL: &str = "PROBING_LOGLEVEL";
 
 #[tokio::main]
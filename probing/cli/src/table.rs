@@ -3,6 +3,7 @@ use nix::libc;
 use std::os::fd::AsFd;
 use std::os::fd::AsRawFd;
 
+use clap::ValueEnum;
 use tabled::builder::Builder;
 use tabled::grid::config::Position;
 use tabled::grid::records::{
@@ -15,7 +16,7 @@ use tabled::settings::{
     Alignment, Settings, Style, Width,
 };
 
-use probing_proto::prelude::{DataFrame, Ele};
+use probing_proto::prelude::{DataFrame, Ele, Seq};
 
 pub struct Table {
     data: VecRecords<Text<String>>,
@@ -61,7 +62,152 @@ impl Table {
     }
 }
 
+/// How `probing query` shows its result
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Render {
+    /// Every cell as text
+    #[default]
+    Table,
+    /// One unicode sparkline per numeric column, with min, max and last value
+    Sparkline,
+    /// The table with numeric cells colored from blue (column min) to red (max)
+    Heatmap,
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// 256-color palette from cold to hot
+const HEAT: [u8; 11] = [33, 39, 45, 49, 48, 118, 190, 226, 214, 208, 196];
+
+fn format_ele(ele: Ele) -> String {
+    match ele {
+        Ele::Nil => "nil".to_string(),
+        Ele::BOOL(x) => x.to_string(),
+        Ele::I32(x) => x.to_string(),
+        Ele::I64(x) => x.to_string(),
+        Ele::F32(x) => x.to_string(),
+        Ele::F64(x) => x.to_string(),
+        Ele::Text(x) => x.to_string(),
+        Ele::Url(x) => x.to_string(),
+        Ele::DataTime(x) => x.to_string(),
+    }
+}
+
+fn numeric(ele: &Ele) -> Option<f64> {
+    match ele {
+        Ele::I32(x) => Some(*x as f64),
+        Ele::I64(x) => Some(*x as f64),
+        Ele::F32(x) => Some(*x as f64),
+        Ele::F64(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Values of a column where every non-nil cell is a number
+fn numeric_column(col: &Seq) -> Option<Vec<Option<f64>>> {
+    let values = (0..col.len())
+        .map(|row| match col.get(row) {
+            Ele::Nil => Ok(None),
+            ele => numeric(&ele).map(Some).ok_or(()),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    values.iter().any(Option::is_some).then_some(values)
+}
+
+fn range(values: &[Option<f64>]) -> Option<(f64, f64)> {
+    values
+        .iter()
+        .flatten()
+        .filter(|v| v.is_finite())
+        .fold(None, |range, &v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((f64::min(min, v), f64::max(max, v))),
+        })
+}
+
+/// Position of `value` in `[min, max]` scaled to `0..levels`
+fn level(value: f64, (min, max): (f64, f64), levels: usize) -> usize {
+    if max <= min || !value.is_finite() {
+        return levels / 2;
+    }
+    (((value - min) / (max - min)) * (levels - 1) as f64).round() as usize
+}
+
+/// Sparkline of `values` at most `width` characters wide, averaging
+/// neighbouring values when there are more of them
+pub fn sparkline(values: &[Option<f64>], width: usize) -> String {
+    let width = width.max(1);
+    let buckets = if values.len() > width {
+        (0..width)
+            .map(|i| {
+                let bucket = values[i * values.len() / width..(i + 1) * values.len() / width]
+                    .iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                match bucket.len() {
+                    0 => None,
+                    n => Some(bucket.into_iter().sum::<f64>() / n as f64),
+                }
+            })
+            .collect::<Vec<_>>()
+    } else {
+        values.to_vec()
+    };
+    let Some(range) = range(&buckets) else {
+        return " ".repeat(buckets.len());
+    };
+    buckets
+        .iter()
+        .map(|v| match v {
+            Some(v) => SPARKS[level(*v, range, SPARKS.len())],
+            None => ' ',
+        })
+        .collect()
+}
+
+fn render_sparklines(df: &DataFrame, termwidth: usize) -> Table {
+    let columns = df
+        .names
+        .iter()
+        .zip(df.cols.iter())
+        .filter_map(|(name, col)| Some((name, numeric_column(col)?)))
+        .collect::<Vec<_>>();
+    let mut table = Table::new(5, columns.len());
+    for (col, header) in ["column", "trend", "min", "max", "last"].iter().enumerate() {
+        table.put((0_usize, col).into(), header.to_string());
+    }
+    // room left for the trend after the name and the three numbers
+    let width = termwidth.saturating_sub(60).max(10);
+    for (row, (name, values)) in columns.iter().enumerate() {
+        let (min, max) = range(values).unwrap_or((f64::NAN, f64::NAN));
+        let last = values.iter().rev().flatten().next().copied();
+        table.put((row + 1, 0).into(), name.to_string());
+        table.put((row + 1, 1).into(), sparkline(values, width));
+        table.put((row + 1, 2).into(), min.to_string());
+        table.put((row + 1, 3).into(), max.to_string());
+        table.put(
+            (row + 1, 4).into(),
+            last.map(|v| v.to_string()).unwrap_or_default(),
+        );
+    }
+    table
+}
+
 pub fn render_dataframe(df: &DataFrame) {
+    render_dataframe_as(df, Render::Table)
+}
+
+pub fn render_dataframe_as(df: &DataFrame, render: Render) {
+    let termwidth = terminal_width().unwrap_or(80) as usize;
+    if render == Render::Sparkline {
+        println!(
+            "{}",
+            render_sparklines(df, termwidth).draw(termwidth).unwrap()
+        );
+        return;
+    }
+
     let ncol = df.names.len();
     let nrow = df.cols.iter().map(|col| col.len()).max().unwrap_or(0);
 
@@ -72,28 +218,27 @@ pub fn render_dataframe(df: &DataFrame) {
     }
 
     for (col, col_data) in df.cols.iter().enumerate() {
+        let heat = match render {
+            Render::Heatmap => {
+                numeric_column(col_data).and_then(|values| Some((range(&values)?, values)))
+            }
+            _ => None,
+        };
         for row in 0..col_data.len() {
-            let value = match col_data.get(row) {
-                Ele::Nil => "nil".to_string(),
-                Ele::BOOL(x) => x.to_string(),
-                Ele::I32(x) => x.to_string(),
-                Ele::I64(x) => x.to_string(),
-                Ele::F32(x) => x.to_string(),
-                Ele::F64(x) => x.to_string(),
-                Ele::Text(x) => x.to_string(),
-                Ele::Url(x) => x.to_string(),
-                Ele::DataTime(x) => x.to_string(),
-            };
+            let mut value = format_ele(col_data.get(row));
+            if let Some((range, values)) = &heat {
+                if let Some(v) = values[row] {
+                    let color = HEAT[level(v, *range, HEAT.len())];
+                    value = format!("\x1b[38;5;{color}m{value}\x1b[0m");
+                }
+            }
             table.put((row + 1, col).into(), value);
         }
     }
-    println!(
-        "{}",
-        table.draw(terminal_width().unwrap_or(80) as usize).unwrap()
-    );
+    println!("{}", table.draw(termwidth).unwrap());
 }
 
-fn terminal_width() -> Option<u32> {
+pub fn terminal_width() -> Option<u32> {
     terminal_size_of(std::io::stdout())
 }
 
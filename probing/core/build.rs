@@ -0,0 +1,13 @@
+// Plugins loaded by `core::dylib` must be built by the same compiler as the
+// host, so record its version for the plugin declaration.
+fn main() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = std::process::Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=PROBING_RUSTC_VERSION={}", version.trim());
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
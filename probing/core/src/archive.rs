@@ -0,0 +1,117 @@
+//! Offline probe archives.
+//!
+//! A probe can periodically persist its tables into a [`ProbeArchive`]; this
+//! module turns such an archive back into a queryable [`Engine`] so that the
+//! data can be inspected after the process is gone.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use arrow::array::{ArrayRef, BooleanArray, RecordBatch, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use datafusion::catalog::SchemaProvider;
+use datafusion::datasource::MemTable;
+use datafusion::execution::SessionState;
+
+use probing_proto::prelude::{DataFrame, ProbeArchive, Seq};
+
+use crate::core::{Engine, Float32Array, Float64Array, Int32Array, Int64Array, Plugin, PluginType};
+
+/// Convert a protocol [`DataFrame`] into an arrow [`RecordBatch`]
+pub fn dataframe_to_recordbatch(df: &DataFrame) -> Result<RecordBatch> {
+    let mut fields: Vec<Field> = vec![];
+    let mut columns: Vec<ArrayRef> = vec![];
+
+    for (name, col) in df.names.iter().zip(df.cols.iter()) {
+        let (dtype, array): (DataType, ArrayRef) = match col {
+            Seq::SeqBOOL(v) => (DataType::Boolean, Arc::new(BooleanArray::from(v.clone()))),
+            Seq::SeqI32(v) => (DataType::Int32, Arc::new(Int32Array::from(v.clone()))),
+            Seq::SeqI64(v) => (DataType::Int64, Arc::new(Int64Array::from(v.clone()))),
+            Seq::SeqF32(v) => (DataType::Float32, Arc::new(Float32Array::from(v.clone()))),
+            Seq::SeqF64(v) => (DataType::Float64, Arc::new(Float64Array::from(v.clone()))),
+            Seq::SeqText(v) => (DataType::Utf8, Arc::new(StringArray::from(v.clone()))),
+            Seq::SeqDateTime(v) => (DataType::UInt64, Arc::new(UInt64Array::from(v.clone()))),
+            Seq::Nil => (
+                DataType::Utf8,
+                Arc::new(StringArray::from(vec![None::<String>; df.len()])),
+            ),
+        };
+        fields.push(Field::new(name, dtype, true));
+        columns.push(array);
+    }
+
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+/// A table restored from an archive
+#[derive(Debug)]
+pub struct ArchivedTable {
+    namespace: String,
+    name: String,
+    batch: RecordBatch,
+}
+
+impl Plugin for ArchivedTable {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> PluginType {
+        PluginType::Table
+    }
+
+    fn namespace(&self) -> String {
+        self.namespace.clone()
+    }
+
+    fn register_table(
+        &self,
+        namespace: Arc<dyn SchemaProvider>,
+        _state: &SessionState,
+    ) -> datafusion::error::Result<()> {
+        let table = MemTable::try_new(self.batch.schema(), vec![vec![self.batch.clone()]])?;
+        namespace.register_table(self.name(), Arc::new(table))?;
+        Ok(())
+    }
+}
+
+/// Build a local engine that serves every table stored in `archive`
+pub fn engine_from_archive(archive: &ProbeArchive) -> Result<Engine> {
+    let mut builder = Engine::builder().with_default_namespace("probe");
+    for (key, df) in archive.tables.iter() {
+        let (namespace, name) = key.split_once('.').unwrap_or(("probe", key.as_str()));
+        builder = builder.with_plugin(Arc::new(ArchivedTable {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            batch: dataframe_to_recordbatch(df)?,
+        }));
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_archived_tables() {
+        let mut archive = ProbeArchive::new(1, "localhost".to_string(), 0);
+        archive.tables.insert(
+            "python.loss".to_string(),
+            DataFrame::new(
+                vec!["step".to_string(), "loss".to_string()],
+                vec![Seq::SeqI64(vec![1, 2, 3]), Seq::SeqF64(vec![0.9, 0.5, 0.1])],
+            ),
+        );
+
+        let engine = engine_from_archive(&archive).unwrap();
+        let df = engine
+            .async_query("SELECT step FROM python.loss WHERE loss < 0.6")
+            .await
+            .unwrap();
+        assert_eq!(df.cols[0], Seq::SeqI64(vec![2, 3]));
+    }
+}
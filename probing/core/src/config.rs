@@ -132,6 +132,24 @@ pub async fn get(key: &str) -> Result<String, EngineError> {
     }
 }
 
+/// Get a configuration value to show to a client, `<redacted>` for the
+/// options marked secret such as `server.auth_token`.
+pub async fn show(key: &str) -> Result<String, EngineError> {
+    let engine = ENGINE.read().await;
+    let state = engine.context.state();
+
+    if let Some(eem) = state
+        .config()
+        .options()
+        .extensions
+        .get::<EngineExtensionManager>()
+    {
+        eem.show_option(key).await
+    } else {
+        Err(EngineError::EngineNotInitialized)
+    }
+}
+
 /// List all available configuration options from all registered extensions.
 ///
 /// This function aggregates configuration options from all registered extensions,
@@ -0,0 +1,274 @@
+//! History of the changes made to the probe, read from `probe.activity`.
+//!
+//! Every extension enabled or disabled, option set and plugin library loaded
+//! is recorded with when it happened and who asked for it, so that a rise of
+//! the overhead can be matched with someone turning a collector on:
+//!
+//! ```sql
+//! SELECT timestamp, action, key, value, actor FROM probe.activity
+//! WHERE key LIKE 'pprof.%'
+//! ```
+//!
+//! Who made a change is the actor of the task making it, set with
+//! [`with_actor`]: the server runs each request as `claimed:<user>`, the user
+//! named by its `X-Probing-User` header, which the client is free to choose,
+//! scheduled tasks and event rules as `scheduler:<task>` and `rule:<name>`.
+//! Changes made by the process itself, from its environment or its Python
+//! code, are made by [`DEFAULT_ACTOR`].
+//!
+//! The values of the options marked `#[option(secret)]` are recorded as
+//! [`REDACTED`].
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use datafusion::arrow::array::{Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use super::ordering::{first_seq, SEQ};
+use super::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    FieldDoc, Plugin, TablePluginHelper,
+};
+
+/// Changes kept in `probe.activity`
+const MAX_ACTIVITIES: usize = 1024;
+
+/// Actor of the changes made outside any [`with_actor`] scope
+pub const DEFAULT_ACTOR: &str = "process";
+
+/// Value recorded in place of the value of a secret option
+pub const REDACTED: &str = "<redacted>";
+
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+/// Run `future` with the changes it makes recorded as made by `actor`
+pub async fn with_actor<F: Future>(actor: impl Into<String>, future: F) -> F::Output {
+    ACTOR.scope(actor.into(), future).await
+}
+
+/// Actor of the running task
+pub fn actor() -> String {
+    ACTOR
+        .try_with(|actor| actor.clone())
+        .unwrap_or_else(|_| DEFAULT_ACTOR.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    /// Microseconds since epoch
+    pub timestamp: i64,
+    /// `enable`, `disable`, `set` or `load`
+    pub action: &'static str,
+    /// Extension enabled or disabled, option set or library loaded
+    pub key: String,
+    pub value: Option<String>,
+    /// Value of the option before it was set
+    pub previous: Option<String>,
+    pub actor: String,
+}
+
+static LOG: Lazy<Mutex<VecDeque<Activity>>> = Lazy::new(Default::default);
+/// Changes ever recorded, numbering them for `probe.activity`
+static LOGGED: AtomicU64 = AtomicU64::new(0);
+
+/// Record a change of `key` made by the actor of the running task
+pub fn record(action: &'static str, key: &str, value: Option<&str>, previous: Option<&str>) {
+    push(
+        action,
+        key,
+        value.map(str::to_string),
+        previous.map(str::to_string),
+    );
+}
+
+/// Record the option `key` set to `value`, redacting both values of a
+/// `secret` option
+pub fn record_set(key: &str, value: &str, previous: &str, secret: bool) {
+    let redact = |value: &str| match secret {
+        true => REDACTED.to_string(),
+        false => value.to_string(),
+    };
+    push("set", key, Some(redact(value)), Some(redact(previous)));
+}
+
+fn push(action: &'static str, key: &str, value: Option<String>, previous: Option<String>) {
+    let activity = Activity {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as i64,
+        action,
+        key: key.to_string(),
+        value,
+        previous,
+        actor: actor(),
+    };
+    let Ok(mut log) = LOG.lock() else {
+        return;
+    };
+    log.push_back(activity);
+    LOGGED.fetch_add(1, Ordering::Relaxed);
+    while log.len() > MAX_ACTIVITIES {
+        log.pop_front();
+    }
+}
+
+/// Changes kept, oldest first, with the sequence number of the first one
+pub fn activities() -> (i64, Vec<Activity>) {
+    let Ok(log) = LOG.lock() else {
+        return (0, vec![]);
+    };
+    let first = first_seq(LOGGED.load(Ordering::Relaxed), log.len());
+    (first, log.iter().cloned().collect())
+}
+
+#[derive(Default, Debug)]
+pub struct ActivityTable {}
+
+impl CustomTable for ActivityTable {
+    fn name() -> &'static str {
+        "activity"
+    }
+
+    fn description() -> &'static str {
+        "Extensions enabled or disabled, options set and libraries loaded, with who did it"
+    }
+
+    fn ordering() -> Option<&'static str> {
+        Some(SEQ)
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new(SEQ, DataType::Int64, false).with_doc("Number of the change, never reused"),
+            Field::new("timestamp", DataType::Int64, false).with_unit("us"),
+            Field::new("action", DataType::Utf8, false).with_doc("enable, disable, set or load"),
+            Field::new("key", DataType::Utf8, false)
+                .with_doc("Extension, option or plugin library changed"),
+            Field::new("value", DataType::Utf8, true)
+                .with_doc("Value set, <redacted> for the options marked secret"),
+            Field::new("previous", DataType::Utf8, true)
+                .with_doc("Value of the option before the change"),
+            Field::new("actor", DataType::Utf8, false).with_doc(
+                "Who made the change: claimed:<user> for the user a client names itself, \
+                 scheduler:<task>, rule:<name>, or process for the environment and the \
+                 Python code of the process",
+            ),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let (first, activities) = activities();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(
+                    first..first + activities.len() as i64,
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    activities.iter().map(|a| a.timestamp),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    activities.iter().map(|a| a.action),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    activities.iter().map(|a| a.key.as_str()),
+                )),
+                Arc::new(StringArray::from_iter(
+                    activities.iter().map(|a| a.value.as_deref()),
+                )),
+                Arc::new(StringArray::from_iter(
+                    activities.iter().map(|a| a.previous.as_deref()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    activities.iter().map(|a| a.actor.as_str()),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type ActivityPlugin = TablePluginHelper<ActivityTable>;
+
+/// Source of `probe.activity`, without options of its own
+#[derive(Debug, Default)]
+pub struct ActivityExtension {}
+
+impl EngineCall for ActivityExtension {}
+
+impl EngineDatasource for ActivityExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<Arc<dyn Plugin + Sync + Send>> {
+        name.map(|name| ActivityPlugin::create(namespace, name) as Arc<dyn Plugin + Sync + Send>)
+    }
+}
+
+impl EngineExtension for ActivityExtension {
+    fn name(&self) -> String {
+        "activity".to_string()
+    }
+
+    fn set(&mut self, key: &str, _value: &str) -> Result<String, EngineError> {
+        Err(EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn get(&self, key: &str) -> Result<String, EngineError> {
+        Err(EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn options(&self) -> Vec<EngineExtensionOption> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Last change of `key`, other tests recording their own concurrently
+    fn last(key: &str) -> Activity {
+        activities()
+            .1
+            .into_iter()
+            .rev()
+            .find(|a| a.key == key)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_record_actor() {
+        record("enable", "test.pprof", None, None);
+        assert_eq!(last("test.pprof").actor, DEFAULT_ACTOR);
+
+        with_actor("alice@node1", async {
+            record("set", "test.pprof.sample_freq", Some("99"), Some(""));
+        })
+        .await;
+        let activity = last("test.pprof.sample_freq");
+        assert_eq!(activity.actor, "alice@node1");
+        assert_eq!(activity.value.as_deref(), Some("99"));
+        assert_eq!(activity.previous.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_record_redacts_secrets() {
+        record_set("test.otlp.headers", "authorization=Bearer xyz", "", true);
+        let activity = last("test.otlp.headers");
+        assert_eq!(activity.value.as_deref(), Some(REDACTED));
+        assert_eq!(activity.previous.as_deref(), Some(REDACTED));
+
+        record_set("test.otlp.token_limit", "10", "", false);
+        assert_eq!(last("test.otlp.token_limit").value.as_deref(), Some("10"));
+    }
+}
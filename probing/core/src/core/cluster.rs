@@ -1,7 +1,9 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::{Arc, LazyLock, RwLock};
 
 use arrow::array::{ArrayRef, Int32Array, StringArray, TimestampMicrosecondArray};
-use probing_proto::prelude::{Cluster, Node};
+use probing_proto::prelude::{ClockReply, ClockSync, Cluster, DataFrame, Node, Seq};
 
 pub trait IntoArrow {
     fn into_arrow_array(values: Vec<Self>) -> ArrayRef
@@ -50,11 +52,15 @@ where
 
 pub static CLUSTER: LazyLock<RwLock<Cluster>> = LazyLock::new(|| RwLock::new(Cluster::default()));
 
-pub fn update_node(mut node: Node) {
-    node.timestamp = std::time::SystemTime::now()
+pub fn now_micros() -> u64 {
+    std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_micros() as u64;
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+pub fn update_node(mut node: Node) {
+    node.timestamp = now_micros();
     CLUSTER.write().unwrap().put(node);
 }
 
@@ -69,3 +75,146 @@ pub fn update_nodes(nodes: Vec<Node>) {
 pub fn get_nodes() -> Vec<Node> {
     CLUSTER.read().unwrap().list()
 }
+
+/// Exchanges kept by [`ClockEstimator`]
+const CLOCK_WINDOW: usize = 8;
+
+/// NTP-style estimate of the offset between this node and the master.
+///
+/// Each exchange gives `offset = ((t1 - t0) + (t2 - t3)) / 2` with an error
+/// bounded by half its round trip, so the estimate is taken from the exchange
+/// with the shortest round trip among the last few.
+#[derive(Debug, Default)]
+pub struct ClockEstimator {
+    samples: VecDeque<(i64, u64)>,
+}
+
+impl ClockEstimator {
+    /// Add the exchange sent at `sent` and answered at `returned`, both on
+    /// the node clock, and return the `(offset, delay)` estimate
+    pub fn add(&mut self, sent: u64, reply: ClockReply, returned: u64) -> (i64, u64) {
+        let (t0, t1, t2, t3) = (
+            sent as i64,
+            reply.received as i64,
+            reply.replied as i64,
+            returned as i64,
+        );
+        let offset = ((t1 - t0) + (t2 - t3)) / 2;
+        let delay = ((t3 - t0) - (t2 - t1)).max(0) as u64;
+        if self.samples.len() == CLOCK_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((offset, delay));
+        self.estimate().unwrap_or((offset, delay))
+    }
+
+    pub fn estimate(&self) -> Option<(i64, u64)> {
+        self.samples.iter().min_by_key(|(_, delay)| *delay).copied()
+    }
+
+    /// Clock fields of the next heartbeat sent at `sent`
+    pub fn sync(&self, sent: u64) -> ClockSync {
+        let estimate = self.estimate();
+        ClockSync {
+            sent,
+            offset: estimate.map(|(offset, _)| offset),
+            delay: estimate.map(|(_, delay)| delay),
+        }
+    }
+}
+
+/// Offset of the master clock to the local clock, in microseconds
+static LOCAL_CLOCK_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+/// Shift timestamps of federated results onto the master clock
+static CLOCK_CORRECTION: AtomicBool = AtomicBool::new(false);
+
+pub fn set_local_clock_offset(offset: i64) {
+    LOCAL_CLOCK_OFFSET.store(offset, Ordering::Relaxed);
+}
+
+pub fn local_clock_offset() -> i64 {
+    LOCAL_CLOCK_OFFSET.load(Ordering::Relaxed)
+}
+
+pub fn set_clock_correction(enabled: bool) {
+    CLOCK_CORRECTION.store(enabled, Ordering::Relaxed);
+}
+
+pub fn clock_correction() -> bool {
+    CLOCK_CORRECTION.load(Ordering::Relaxed)
+}
+
+/// Shift the timestamp columns of `df` by `offset` microseconds: datetime
+/// columns and integer columns named `timestamp`
+pub fn shift_timestamps(df: &mut DataFrame, offset: i64) {
+    if offset == 0 {
+        return;
+    }
+    for (name, col) in df.names.iter().zip(df.cols.iter_mut()) {
+        match col {
+            Seq::SeqDateTime(values) => values
+                .iter_mut()
+                .for_each(|v| *v = v.saturating_add_signed(offset)),
+            Seq::SeqI64(values) if name == "timestamp" => values
+                .iter_mut()
+                .for_each(|v| *v = v.saturating_add(offset)),
+            _ => {}
+        }
+    }
+}
+
+/// Put the timestamps of a result leaving this node on the master clock, if
+/// `clock.correction` is enabled
+pub fn correct_timestamps(df: &mut DataFrame) {
+    if clock_correction() {
+        shift_timestamps(df, local_clock_offset());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_estimator() {
+        let mut estimator = ClockEstimator::default();
+        // master is 1000us ahead, 100us each way
+        let reply = ClockReply {
+            received: 1_100 + 1_000,
+            replied: 1_150 + 1_000,
+        };
+        assert_eq!(estimator.add(1_000, reply, 1_250), (1_000, 200));
+
+        // a congested exchange is asymmetric and less accurate
+        let reply = ClockReply {
+            received: 2_900 + 1_000,
+            replied: 2_950 + 1_000,
+        };
+        assert_eq!(estimator.add(2_000, reply, 3_050), (1_000, 200));
+        assert_eq!(
+            estimator.sync(4_000),
+            ClockSync {
+                sent: 4_000,
+                offset: Some(1_000),
+                delay: Some(200)
+            }
+        );
+    }
+
+    #[test]
+    fn test_shift_timestamps() {
+        let mut df = DataFrame::new(
+            vec!["ts".into(), "timestamp".into(), "value".into()],
+            vec![
+                Seq::SeqDateTime(vec![10_000]),
+                Seq::SeqI64(vec![20_000]),
+                Seq::SeqI64(vec![5]),
+            ],
+        );
+        shift_timestamps(&mut df, -1_000);
+        assert_eq!(df.cols[0], Seq::SeqDateTime(vec![9_000]));
+        assert_eq!(df.cols[1], Seq::SeqI64(vec![19_000]));
+        assert_eq!(df.cols[2], Seq::SeqI64(vec![5]));
+    }
+}
@@ -0,0 +1,225 @@
+//! Engine extensions loaded from shared libraries at runtime.
+//!
+//! A plugin is a `cdylib` crate depending on `probing-core` that declares its
+//! extensions with [`declare_plugin!`](crate::declare_plugin):
+//!
+//! ```ignore
+//! fn register(registrar: &mut PluginRegistrar) {
+//!     registrar.register(MyExtension::default(), "mine", Some("events"));
+//! }
+//!
+//! probing_core::declare_plugin!(register);
+//! ```
+//!
+//! and is loaded into a running probe with
+//! `SET probing.extensions.load='/path/libmine.so'`.
+//!
+//! Rust has no stable ABI: extensions cross the library boundary as trait
+//! objects, which is only sound when both sides were built from the same
+//! probing-core by the same compiler. The declaration records both, along with
+//! the protocol version, and a library that does not match is rejected before
+//! any of its code runs.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use libloading::Library;
+use once_cell::sync::Lazy;
+use probing_proto::prelude::ProtocolVersion;
+
+use super::error::EngineError;
+use super::extension::EngineExtension;
+use super::Plugin;
+
+/// Bumped whenever [`PluginDeclaration`] changes layout
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Name of the static exported by [`declare_plugin!`](crate::declare_plugin)
+pub const PLUGIN_DECLARATION_SYMBOL: &[u8] = b"probing_plugin_declaration\0";
+
+/// Version of probing-core a plugin was built against
+pub const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Compiler a plugin was built with, see `build.rs`
+pub const RUSTC_VERSION: &str = env!("PROBING_RUSTC_VERSION");
+
+/// Protocol version baked into declarations, [`ProtocolVersion::current`]
+pub const PROTOCOL_MAJOR: u16 = ProtocolVersion::current().major;
+pub const PROTOCOL_MINOR: u16 = ProtocolVersion::current().minor;
+
+/// Entry point of a plugin library.
+///
+/// `abi_version` comes first and the layout is `repr(C)`, so it can be checked
+/// before any other field is trusted.
+#[repr(C)]
+pub struct PluginDeclaration {
+    pub abi_version: u32,
+    pub protocol_major: u16,
+    pub protocol_minor: u16,
+    pub core_version: &'static str,
+    pub rustc_version: &'static str,
+    pub register: fn(&mut PluginRegistrar),
+}
+
+type Registered = (
+    String,
+    Arc<tokio::sync::Mutex<dyn EngineExtension + Send + Sync>>,
+    Option<Arc<dyn Plugin + Sync + Send>>,
+);
+
+/// Collects the extensions a plugin registers, mirroring
+/// [`EngineBuilder::with_extension`](super::EngineBuilder::with_extension)
+#[derive(Default)]
+pub struct PluginRegistrar {
+    extensions: Vec<Registered>,
+}
+
+impl PluginRegistrar {
+    pub fn register<T>(&mut self, ext: T, namespace: &str, name: Option<&str>)
+    where
+        T: EngineExtension + Send + Sync + 'static,
+    {
+        let datasrc = ext.datasrc(namespace, name);
+        let ext_name = ext.name();
+        self.extensions
+            .push((ext_name, Arc::new(tokio::sync::Mutex::new(ext)), datasrc));
+    }
+
+    pub(crate) fn into_parts(self) -> Vec<Registered> {
+        self.extensions
+    }
+}
+
+/// Export the [`PluginDeclaration`] of a plugin library, `$register` being a
+/// `fn(&mut PluginRegistrar)`
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static probing_plugin_declaration: $crate::core::dylib::PluginDeclaration =
+            $crate::core::dylib::PluginDeclaration {
+                abi_version: $crate::core::dylib::PLUGIN_ABI_VERSION,
+                protocol_major: $crate::core::dylib::PROTOCOL_MAJOR,
+                protocol_minor: $crate::core::dylib::PROTOCOL_MINOR,
+                core_version: $crate::core::dylib::CORE_VERSION,
+                rustc_version: $crate::core::dylib::RUSTC_VERSION,
+                register: $register,
+            };
+    };
+}
+
+/// Libraries are never unloaded: the extensions they registered keep pointing
+/// into their code.
+static LIBRARIES: Lazy<Mutex<BTreeMap<String, &'static PluginDeclaration>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+fn check_declaration(decl: &PluginDeclaration) -> Result<(), String> {
+    if decl.abi_version != PLUGIN_ABI_VERSION {
+        return Err(format!(
+            "plugin abi {} is not supported, expected {PLUGIN_ABI_VERSION}",
+            decl.abi_version
+        ));
+    }
+    let protocol = ProtocolVersion {
+        major: decl.protocol_major,
+        minor: decl.protocol_minor,
+        patch: 0,
+    };
+    if !ProtocolVersion::current().is_compatible_with(&protocol) {
+        return Err(format!(
+            "plugin protocol {}.{} is not compatible with {:?}",
+            decl.protocol_major,
+            decl.protocol_minor,
+            ProtocolVersion::current()
+        ));
+    }
+    if decl.core_version != CORE_VERSION {
+        return Err(format!(
+            "plugin built against probing-core {}, expected {CORE_VERSION}",
+            decl.core_version
+        ));
+    }
+    if decl.rustc_version != RUSTC_VERSION {
+        return Err(format!(
+            "plugin built with {}, expected {RUSTC_VERSION}",
+            decl.rustc_version
+        ));
+    }
+    Ok(())
+}
+
+fn open_declaration(path: &str) -> Result<&'static PluginDeclaration, String> {
+    let mut libraries = LIBRARIES.lock().map_err(|e| e.to_string())?;
+    if let Some(decl) = libraries.get(path) {
+        return Ok(decl);
+    }
+
+    let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+    let decl = unsafe {
+        library
+            .get::<*const PluginDeclaration>(PLUGIN_DECLARATION_SYMBOL)
+            .map(|symbol| *symbol)
+            .map_err(|e| format!("not a probing plugin: {e}"))?
+    };
+    // SAFETY: the library is leaked below, so the declaration lives forever
+    let decl: &'static PluginDeclaration = unsafe { &*decl };
+    check_declaration(decl)?;
+
+    std::mem::forget(library);
+    libraries.insert(path.to_string(), decl);
+    Ok(decl)
+}
+
+/// Load the plugin at `path` and collect the extensions it registers. Every
+/// call creates fresh extension instances, so each engine gets its own.
+pub fn load_plugin(path: &str) -> Result<PluginRegistrar, EngineError> {
+    let decl = open_declaration(path)
+        .map_err(|e| EngineError::PluginRegistrationFailed(format!("{path}: {e}")))?;
+    let mut registrar = PluginRegistrar::default();
+    (decl.register)(&mut registrar);
+    Ok(registrar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(_: &mut PluginRegistrar) {}
+
+    fn declaration() -> PluginDeclaration {
+        PluginDeclaration {
+            abi_version: PLUGIN_ABI_VERSION,
+            protocol_major: PROTOCOL_MAJOR,
+            protocol_minor: PROTOCOL_MINOR,
+            core_version: CORE_VERSION,
+            rustc_version: RUSTC_VERSION,
+            register,
+        }
+    }
+
+    #[test]
+    fn test_check_declaration() {
+        assert!(check_declaration(&declaration()).is_ok());
+
+        let mut decl = declaration();
+        decl.abi_version += 1;
+        assert!(check_declaration(&decl).is_err());
+
+        let mut decl = declaration();
+        decl.protocol_major += 1;
+        assert!(check_declaration(&decl).is_err());
+
+        let mut decl = declaration();
+        decl.rustc_version = "rustc 0.0.0";
+        assert!(check_declaration(&decl).is_err());
+    }
+
+    #[test]
+    fn test_load_missing_plugin() {
+        assert!(matches!(
+            load_plugin("/nonexistent/libplugin.so"),
+            Err(EngineError::PluginRegistrationFailed(_))
+        ));
+    }
+}
@@ -16,12 +16,46 @@ use datafusion::config::ConfigExtension;
 use datafusion::error::DataFusionError;
 use datafusion::error::Result;
 use datafusion::execution::SessionState;
+use datafusion::logical_expr::ScalarUDF;
 use datafusion::prelude::{DataFrame, SessionConfig, SessionContext};
 use futures;
 
 use super::extension::EngineExtension;
 use super::extension::EngineExtensionManager;
-use probing_proto::prelude::Seq;
+use super::plugin::column_docs;
+use probing_proto::prelude::{ColumnDoc, Seq, TableDoc};
+
+/// Table named by a `DESCRIBE <table>` statement
+fn describe_target(query: &str) -> Option<&str> {
+    let query = query.trim().trim_end_matches(';');
+    let (keyword, table) = query.split_once(char::is_whitespace)?;
+    let table = table.trim();
+    (keyword.eq_ignore_ascii_case("describe") && !table.is_empty()).then_some(table)
+}
+
+/// Columns of the `DESCRIBE` result, those of DataFusion followed by the
+/// documentation of the plugin
+const DESCRIBE_COLUMNS: [&str; 5] = [
+    "column_name",
+    "data_type",
+    "is_nullable",
+    "unit",
+    "description",
+];
+
+fn describe_frame(columns: Vec<ColumnDoc>) -> probing_proto::prelude::DataFrame {
+    let text = |f: fn(&ColumnDoc) -> String| Seq::SeqText(columns.iter().map(f).collect());
+    probing_proto::prelude::DataFrame::new(
+        DESCRIBE_COLUMNS.map(String::from).to_vec(),
+        vec![
+            text(|c| c.name.clone()),
+            text(|c| c.data_type.clone()),
+            text(|c| if c.nullable { "YES" } else { "NO" }.to_string()),
+            text(|c| c.unit.clone().unwrap_or_default()),
+            text(|c| c.description.clone().unwrap_or_default()),
+        ],
+    )
+}
 
 /// Defines the types of plugins supported by the Probing query engine.
 /// These plugin types determine how data sources are registered with the engine.
@@ -92,6 +126,11 @@ pub trait Plugin {
     ///   Tables in this namespace will be accessible as "namespace.table_name".
     fn namespace(&self) -> String;
 
+    /// Human readable description of the table provided by this plugin
+    fn description(&self) -> Option<String> {
+        None
+    }
+
     /// Registers a table with the provided namespace.
     ///
     /// Implemented by Table plugins to register their data source
@@ -150,7 +189,7 @@ pub trait Plugin {
 ///     .build().unwrap();
 ///
 /// // Execute a SQL query
-/// let result = engine.query("SELECT * FROM information_schema.tables");
+/// let result = engine.blocking_query("SELECT * FROM information_schema.tables");
 /// ```
 pub struct Engine {
     /// DataFusion session context for executing SQL queries
@@ -201,7 +240,30 @@ impl Engine {
     }
 
     pub async fn sql(&self, query: &str) -> Result<DataFrame> {
-        self.context.sql(query).await
+        for udf in super::functions::registered_udfs() {
+            self.context.register_udf(udf);
+        }
+        let df = self.context.sql(query).await?;
+        // `SET probing.extensions.load=...` registers tables only on the way out
+        self.enable_loaded_plugins()?;
+        Ok(df)
+    }
+
+    /// Enable the data sources of extensions loaded from plugin libraries
+    fn enable_loaded_plugins(&self) -> Result<()> {
+        let state = self.context.state();
+        let Some(eem) = state
+            .config()
+            .options()
+            .extensions
+            .get::<EngineExtensionManager>()
+        else {
+            return Ok(());
+        };
+        for plugin in eem.take_pending() {
+            self.enable(plugin)?;
+        }
+        Ok(())
     }
 
     pub async fn async_query<T: Into<String>>(
@@ -209,6 +271,9 @@ impl Engine {
         query: T,
     ) -> Result<probing_proto::prelude::DataFrame> {
         let query: String = query.into();
+        if let Some(table) = describe_target(&query) {
+            return Ok(describe_frame(self.describe(table).await?));
+        }
         let batches = self.sql(query.as_str()).await?.collect().await?;
         if batches.is_empty() {
             return Ok(probing_proto::prelude::DataFrame::default());
@@ -246,7 +311,67 @@ impl Engine {
         Ok(probing_proto::prelude::DataFrame::new(names, columns))
     }
 
-    #[deprecated]
+    /// Columns of `table` with the units and descriptions supplied by its
+    /// plugin
+    pub async fn describe(&self, table: &str) -> Result<Vec<ColumnDoc>> {
+        let provider = self.context.table_provider(table).await?;
+        Ok(column_docs(&provider.schema()))
+    }
+
+    /// Tables of the `probe` catalog with their documentation. Tables of
+    /// dynamic namespaces (e.g. `python`) are listed without resolving their
+    /// columns, which may evaluate code in the target.
+    pub async fn tables(&self) -> Result<Vec<TableDoc>> {
+        let Some(catalog) = self.context.catalog("probe") else {
+            return Ok(vec![]);
+        };
+        let plugins = self
+            .plugins
+            .read()
+            .map(|plugins| plugins.clone())
+            .unwrap_or_default();
+
+        let mut namespaces = catalog.schema_names();
+        namespaces.sort();
+        let mut docs = vec![];
+        for namespace in namespaces {
+            let Some(schema) = catalog.schema(&namespace) else {
+                continue;
+            };
+            let dynamic = plugins.contains_key(&format!("probe.{namespace}"));
+            let mut names = schema.table_names();
+            names.sort();
+            for name in names {
+                let columns = match dynamic {
+                    true => vec![],
+                    false => schema
+                        .table(&name)
+                        .await?
+                        .map(|table| column_docs(&table.schema()))
+                        .unwrap_or_default(),
+                };
+                docs.push(TableDoc {
+                    description: plugins
+                        .get(&format!("probe.{namespace}.{name}"))
+                        .and_then(|plugin| plugin.description()),
+                    name: format!("{namespace}.{name}"),
+                    columns,
+                });
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Run `query` without an async context, see [`crate::block_on`]
+    pub fn blocking_query<T: Into<String>>(
+        &self,
+        query: T,
+    ) -> Result<probing_proto::prelude::DataFrame> {
+        crate::block_on(self.async_query(query))
+            .map_err(|e| DataFusionError::Execution(e.to_string()))?
+    }
+
+    #[deprecated(note = "use `blocking_query`, which also works without a tokio runtime")]
     pub fn query<T: Into<String>>(&self, q: T) -> Result<probing_proto::prelude::DataFrame> {
         futures::executor::block_on(async { self.async_query(q).await })
     }
@@ -308,7 +433,11 @@ pub struct EngineBuilder {
     config: SessionConfig,
     default_namespace: Option<String>,
     plugins: Vec<Arc<dyn Plugin + Sync + Send>>,
+    udfs: Vec<ScalarUDF>,
     extensions: HashMap<String, Arc<tokio::sync::Mutex<dyn EngineExtension + Send + Sync>>>,
+    /// Tables of the extensions, as `namespace` or `namespace.name`, with the
+    /// name of the extension serving them
+    tables: Vec<(String, String)>,
 }
 
 impl EngineBuilder {
@@ -318,7 +447,9 @@ impl EngineBuilder {
             config: SessionConfig::default(),
             default_namespace: None,
             plugins: Vec::new(),
+            udfs: Vec::new(),
             extensions: Default::default(),
+            tables: Vec::new(),
         }
     }
 
@@ -334,17 +465,28 @@ impl EngineBuilder {
         self
     }
 
+    // Add a scalar function callable from queries
+    pub fn with_udf(mut self, udf: ScalarUDF) -> Self {
+        self.udfs.push(udf);
+        self
+    }
+
     pub fn with_extension<T>(mut self, ext: T, namespace: &str, name: Option<&str>) -> Self
     where
         T: EngineExtension + Send + Sync + 'static,
     {
+        let ext_name = ext.name();
         if let Some(datasrc) = ext.datasrc(namespace, name) {
+            let table = match datasrc.kind() {
+                PluginType::Namespace => datasrc.namespace(),
+                _ => format!("{}.{}", datasrc.namespace(), datasrc.name()),
+            };
+            self.tables.push((table, ext_name.clone()));
             self.plugins.push(datasrc)
         };
-        let name = ext.name();
         let ext = Arc::new(tokio::sync::Mutex::new(ext));
 
-        self.extensions.insert(name, ext);
+        self.extensions.insert(ext_name, ext);
         self
     }
 
@@ -354,6 +496,9 @@ impl EngineBuilder {
         for (name, extension) in self.extensions.iter() {
             eem.register(name.clone(), extension.clone());
         }
+        for (table, name) in self.tables.drain(..) {
+            eem.register_table(table, name);
+        }
         self.config.options_mut().extensions.insert(eem);
         if let Some(namespace) = self.default_namespace {
             self.config = self
@@ -365,8 +510,20 @@ impl EngineBuilder {
                 .with_default_catalog_and_schema("probe", "probe");
         }
         self.config = self.config.with_information_schema(true);
+        // needed to walk trees such as `system.process_tree` with `WITH RECURSIVE`
+        self.config.options_mut().execution.enable_recursive_ctes = true;
 
         let context = SessionContext::new_with_config(self.config);
+        context.register_udf(super::functions::histogram_quantile());
+        context.register_udaf(super::functions::histogram_merge());
+        context.register_udf(super::masking::mask());
+        context.register_udf(super::masking::hash());
+        context.register_udf(super::masking::last4());
+        context.add_analyzer_rule(Arc::new(super::masking::MaskingRule::default()));
+        context.add_analyzer_rule(Arc::new(super::ordering::DefaultOrderingRule::default()));
+        for udf in self.udfs {
+            context.register_udf(udf);
+        }
         let engine = Engine {
             context,
             plugins: Default::default(),
@@ -387,7 +544,7 @@ impl Default for EngineBuilder {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::{EngineCall, EngineDatasource};
+    use crate::core::{EngineCall, EngineDatasource, FieldDoc};
 
     use super::*;
     use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
@@ -409,7 +566,9 @@ mod tests {
     impl Default for TestTablePlugin {
         fn default() -> Self {
             let schema = Arc::new(Schema::new(vec![
-                Field::new("id", DataType::Int32, false),
+                Field::new("id", DataType::Int32, false)
+                    .with_doc("Row id")
+                    .with_unit("rows"),
                 Field::new("name", DataType::Utf8, false),
             ]));
 
@@ -442,6 +601,10 @@ mod tests {
             "test_namespace".to_string()
         }
 
+        fn description(&self) -> Option<String> {
+            Some("Rows for tests".to_string())
+        }
+
         fn register_table(
             &self,
             schema_provider: Arc<dyn SchemaProvider>,
@@ -545,6 +708,57 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_recursive_query() -> Result<()> {
+        let engine = Engine::builder().build()?;
+        engine.enable(Arc::new(TestTablePlugin::default()))?;
+
+        let result = engine
+            .async_query(
+                "WITH RECURSIVE chain(id, depth) AS (
+                    SELECT id, 0 FROM test_namespace.test_table WHERE id = 1
+                    UNION ALL
+                    SELECT t.id, c.depth + 1
+                    FROM test_namespace.test_table t JOIN chain c ON t.id = c.id + 1
+                ) SELECT max(depth) AS depth FROM chain",
+            )
+            .await?;
+        assert_eq!(result.cols[0], Seq::SeqI64(vec![2]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_describe_table() -> Result<()> {
+        let engine = Engine::builder().build()?;
+        engine.enable(Arc::new(TestTablePlugin::default()))?;
+
+        let result = engine
+            .async_query("describe test_namespace.test_table;")
+            .await?;
+        assert_eq!(result.names, DESCRIBE_COLUMNS);
+        assert_eq!(
+            result.cols[0],
+            Seq::SeqText(vec!["id".into(), "name".into()])
+        );
+        assert_eq!(result.cols[3], Seq::SeqText(vec!["rows".into(), "".into()]));
+        assert_eq!(
+            result.cols[4],
+            Seq::SeqText(vec!["Row id".into(), "".into()])
+        );
+
+        let tables = engine.tables().await?;
+        let table = tables
+            .iter()
+            .find(|t| t.name == "test_namespace.test_table")
+            .unwrap();
+        assert_eq!(table.description.as_deref(), Some("Rows for tests"));
+        assert_eq!(table.columns[0].data_type, "Int32");
+        assert!(!table.columns[0].nullable);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_extension_registration() {
         #[derive(Debug)]
@@ -676,4 +890,17 @@ mod tests {
         let result = engine.async_query("SHOW TABLES").await;
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_blocking_query() {
+        let engine = Engine::builder().build().unwrap();
+        let result = engine.blocking_query("SELECT 1 AS one").unwrap();
+        assert_eq!(result.names, vec!["one".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_query_in_runtime() {
+        let engine = Engine::builder().build().unwrap();
+        assert!(engine.blocking_query("SELECT 1").is_err());
+    }
 }
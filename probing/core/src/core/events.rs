@@ -0,0 +1,608 @@
+//! Event bus letting extensions react to each other.
+//!
+//! An extension announces what happened with [`publish`], e.g. a watchdog
+//! publishing `watchdog.hang`, and other extensions react with [`subscribe`].
+//! Users wire events to actions with rules, registered like scheduler tasks:
+//!
+//! ```sql
+//! SET probing.events.rule.hang_profile = 'watchdog.hang @flamegraph';
+//! SET probing.events.rule.failures = 'scheduler.* SELECT * FROM scheduler.tasks';
+//! SET probing.events.rule.hang_profile = '';  -- remove the rule
+//! ```
+//!
+//! A rule is `<topic> <action>`, the topic ending with `*` to match a prefix,
+//! and the action is one of the scheduler's: `@<action> [args]` or SQL. Rules
+//! run one at a time on the `probing-events` thread, so publishing never waits
+//! for them. The last events are kept in `events.log` and the rules with their
+//! last firing in `events.rules`.
+//!
+//! Actions publish events too, e.g. `@flamegraph` publishes
+//! `profiles.captured`, so `* @flamegraph` would feed itself. Events published
+//! by an action fire no rule, and a rule fires at most once per
+//! [`RULE_COOLDOWN`]; the events it skipped are counted as `suppressed`.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use super::activity::with_actor;
+use super::ordering::{first_seq, SEQ};
+use super::scheduler::{run_action, TaskAction};
+use super::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    FieldDoc, Plugin, TablePluginHelper,
+};
+
+/// Events kept in `events.log`
+const MAX_EVENTS: usize = 1024;
+
+/// Option prefix of the rules, `events.rule.<name>`
+const RULE_PREFIX: &str = "rule.";
+
+/// Time after a run of a rule during which its topic does not fire it again
+pub const RULE_COOLDOWN: Duration = Duration::from_secs(5);
+
+thread_local! {
+    /// Set on the `probing-events` thread while an action runs
+    static IN_RULE: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Microseconds since epoch
+    pub timestamp: i64,
+    /// Dotted name of what happened, e.g. `watchdog.hang`
+    pub topic: String,
+    /// Extension that published the event
+    pub source: String,
+    pub message: String,
+}
+
+/// Callback of an extension subscribed to a topic, called on the publishing
+/// thread so it should hand anything slow over to its own worker
+pub type Handler = Arc<dyn Fn(&Event) + Send + Sync>;
+
+static SUBSCRIBERS: Lazy<RwLock<Vec<(String, Handler)>>> = Lazy::new(Default::default);
+static LOG: Lazy<Mutex<VecDeque<Event>>> = Lazy::new(Default::default);
+/// Events ever added to `LOG`, numbering them for `events.log`
+static LOGGED: AtomicU64 = AtomicU64::new(0);
+static RULES: Lazy<Mutex<BTreeMap<String, Rule>>> = Lazy::new(Default::default);
+static WORKER: Lazy<Mutex<Option<Sender<(String, Event)>>>> = Lazy::new(Default::default);
+
+/// Whether `topic` is matched by `pattern`: `*`, `<prefix>*` or the topic
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+/// Call `handler` for every event whose topic matches `pattern`
+pub fn subscribe(pattern: &str, handler: Handler) {
+    SUBSCRIBERS
+        .write()
+        .unwrap()
+        .push((pattern.to_string(), handler));
+}
+
+fn now_micros() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Record an event and notify the subscribers and rules of its topic. A
+/// dormant probe (see [`super::killswitch`]) still logs events but runs no
+/// rule, and neither do events published by the action of a rule.
+pub fn publish(topic: &str, source: &str, message: &str) {
+    let event = Event {
+        timestamp: now_micros(),
+        topic: topic.to_string(),
+        source: source.to_string(),
+        message: message.to_string(),
+    };
+    log::debug!("event {topic} from {source}: {message}");
+    {
+        let mut events = LOG.lock().unwrap();
+        if events.len() >= MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+        LOGGED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let handlers = SUBSCRIBERS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(pattern, _)| topic_matches(pattern, topic))
+        .map(|(_, handler)| handler.clone())
+        .collect::<Vec<_>>();
+    for handler in handlers {
+        handler(&event);
+    }
+
+    if super::killswitch::is_dormant() {
+        return;
+    }
+    if IN_RULE.with(Cell::get) {
+        log::debug!("event {topic} published by a rule action fires no rule");
+        return;
+    }
+    let mut fired = vec![];
+    for (name, rule) in RULES.lock().unwrap().iter_mut() {
+        if !topic_matches(&rule.spec.topic, topic) {
+            continue;
+        }
+        match rule.cooling_down() {
+            true => rule.suppressed += 1,
+            false => fired.push(name.clone()),
+        }
+    }
+    for name in fired {
+        dispatch(name, event.clone());
+    }
+}
+
+/// Topic and action of a rule, written `<topic> <action>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSpec {
+    pub topic: String,
+    pub action: TaskAction,
+}
+
+impl FromStr for RuleSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (topic, action) = value
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected `<topic> <action>`, got `{value}`"))?;
+        Ok(RuleSpec {
+            topic: topic.to_string(),
+            action: action.parse()?,
+        })
+    }
+}
+
+impl Display for RuleSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.topic, self.action)
+    }
+}
+
+struct Rule {
+    spec: RuleSpec,
+    fires: u64,
+    failures: u64,
+    /// Start of the last run in microseconds since epoch
+    last_fired: Option<i64>,
+    last_duration: Option<f64>,
+    last_output: Option<String>,
+    last_error: Option<String>,
+    /// End of the last run, for the cooldown
+    last_finished: Option<Instant>,
+    /// Events skipped during a cooldown
+    suppressed: u64,
+}
+
+impl Rule {
+    fn new(spec: RuleSpec) -> Self {
+        Self {
+            spec,
+            fires: 0,
+            failures: 0,
+            last_fired: None,
+            last_duration: None,
+            last_output: None,
+            last_error: None,
+            last_finished: None,
+            suppressed: 0,
+        }
+    }
+
+    fn cooling_down(&self) -> bool {
+        self.last_finished
+            .is_some_and(|finished| finished.elapsed() < RULE_COOLDOWN)
+    }
+}
+
+/// Queue rule `name` on the `probing-events` thread, starting it on first use
+fn dispatch(name: String, event: Event) {
+    let mut worker = WORKER.lock().unwrap();
+    if let Some(tx) = worker.as_ref() {
+        if tx.send((name.clone(), event.clone())).is_ok() {
+            return;
+        }
+    }
+    let (tx, rx) = channel::<(String, Event)>();
+    let spawned = std::thread::Builder::new()
+        .name("probing-events".to_string())
+        .spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().build() {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    log::error!("failed to start the event rules: {err}");
+                    return;
+                }
+            };
+            while let Ok((name, event)) = rx.recv() {
+                run_rule(&runtime, &name, &event);
+            }
+        });
+    match spawned {
+        Ok(_) => {
+            let _ = tx.send((name, event));
+            *worker = Some(tx);
+        }
+        Err(err) => log::error!("failed to start the event rules: {err}"),
+    }
+}
+
+fn run_rule(runtime: &tokio::runtime::Runtime, name: &str, event: &Event) {
+    let action = match RULES.lock().unwrap().get_mut(name) {
+        // queued again while it was running
+        Some(rule) if rule.cooling_down() => {
+            rule.suppressed += 1;
+            return;
+        }
+        Some(rule) => rule.spec.action.clone(),
+        None => return,
+    };
+    let started = now_micros();
+    let start = Instant::now();
+    IN_RULE.with(|in_rule| in_rule.set(true));
+    let outcome = runtime.block_on(with_actor(format!("rule:{name}"), run_action(&action)));
+    IN_RULE.with(|in_rule| in_rule.set(false));
+    let elapsed = start.elapsed().as_secs_f64() * 1e3;
+
+    let mut rules = RULES.lock().unwrap();
+    // removed or replaced while running
+    let Some(rule) = rules.get_mut(name) else {
+        return;
+    };
+    rule.fires += 1;
+    rule.last_fired = Some(started);
+    rule.last_duration = Some(elapsed);
+    rule.last_finished = Some(Instant::now());
+    match outcome {
+        Ok(outcome) => {
+            rule.last_output = Some(outcome.summary);
+            rule.last_error = None;
+        }
+        Err(err) => {
+            log::warn!("event rule {name} failed on {}: {err}", event.topic);
+            rule.failures += 1;
+            rule.last_error = Some(err.to_string());
+        }
+    }
+}
+
+/// Rule names from `PROBING_EVENTS_RULE_<NAME>` come with dots, `hang.profile`
+/// is `hang_profile`
+fn rule_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/// Add, replace or, with an empty `spec`, remove rule `name`
+pub fn set_rule(name: &str, spec: &str) -> Result<(), String> {
+    let name = rule_name(name);
+    if name.is_empty() {
+        return Err("missing rule name".to_string());
+    }
+    if spec.trim().is_empty() {
+        RULES.lock().unwrap().remove(&name);
+        return Ok(());
+    }
+    let spec = spec.parse::<RuleSpec>()?;
+    RULES.lock().unwrap().insert(name, Rule::new(spec));
+    Ok(())
+}
+
+/// Spec of rule `name`, if registered
+pub fn rule(name: &str) -> Option<String> {
+    let rules = RULES.lock().unwrap();
+    rules
+        .get(&rule_name(name))
+        .map(|rule| rule.spec.to_string())
+}
+
+/// Events published so far, oldest first
+#[derive(Default, Debug)]
+pub struct EventLogTable {}
+
+impl CustomTable for EventLogTable {
+    fn name() -> &'static str {
+        "log"
+    }
+
+    fn description() -> &'static str {
+        "Events published by the extensions, oldest first"
+    }
+
+    fn ordering() -> Option<&'static str> {
+        Some(SEQ)
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new(SEQ, DataType::Int64, false)
+                .with_doc("Number of the event since the probe started"),
+            Field::new("timestamp", DataType::Int64, false).with_unit("us"),
+            Field::new("topic", DataType::Utf8, false),
+            Field::new("source", DataType::Utf8, false)
+                .with_doc("Extension that published the event"),
+            Field::new("message", DataType::Utf8, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let events = LOG.lock().unwrap();
+        let first = first_seq(LOGGED.load(Ordering::Relaxed), events.len());
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from_iter_values(
+                first..first + events.len() as i64,
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                events.iter().map(|e| e.timestamp),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.topic.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.source.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                events.iter().map(|e| e.message.as_str()),
+            )),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+            .map(|rb| vec![rb])
+            .unwrap_or_default()
+    }
+}
+
+pub type EventLogPlugin = TablePluginHelper<EventLogTable>;
+
+/// Rules with their last firing
+#[derive(Default, Debug)]
+pub struct EventRulesTable {}
+
+impl CustomTable for EventRulesTable {
+    fn name() -> &'static str {
+        "rules"
+    }
+
+    fn description() -> &'static str {
+        "Event rules and their last run"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("topic", DataType::Utf8, false),
+            Field::new("action", DataType::Utf8, false),
+            Field::new("fires", DataType::Int64, false),
+            Field::new("failures", DataType::Int64, false),
+            Field::new("last_fired", DataType::Int64, true)
+                .with_unit("us")
+                .with_doc("Start of the last run since epoch"),
+            Field::new("last_duration", DataType::Float64, true).with_unit("ms"),
+            Field::new("last_output", DataType::Utf8, true),
+            Field::new("last_error", DataType::Utf8, true)
+                .with_doc("Error of the last run, cleared by a successful one"),
+            Field::new("suppressed", DataType::Int64, false)
+                .with_doc("Events skipped while the rule was cooling down"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let rules = RULES.lock().unwrap();
+        let rules = rules.iter().collect::<Vec<_>>();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                rules.iter().map(|(name, _)| name.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rules.iter().map(|(_, r)| r.spec.topic.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                rules.iter().map(|(_, r)| r.spec.action.to_string()),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                rules.iter().map(|(_, r)| r.fires as i64),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                rules.iter().map(|(_, r)| r.failures as i64),
+            )),
+            Arc::new(Int64Array::from(
+                rules.iter().map(|(_, r)| r.last_fired).collect::<Vec<_>>(),
+            )),
+            Arc::new(Float64Array::from(
+                rules
+                    .iter()
+                    .map(|(_, r)| r.last_duration)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rules
+                    .iter()
+                    .map(|(_, r)| r.last_output.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                rules
+                    .iter()
+                    .map(|(_, r)| r.last_error.clone())
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                rules.iter().map(|(_, r)| r.suppressed as i64),
+            )),
+        ];
+        RecordBatch::try_new(Self::schema(), columns)
+            .map(|rb| vec![rb])
+            .unwrap_or_default()
+    }
+}
+
+pub type EventRulesPlugin = TablePluginHelper<EventRulesTable>;
+
+/// Event log and rules, see the module documentation
+#[derive(Debug, Default)]
+pub struct EventsExtension {}
+
+impl EngineCall for EventsExtension {}
+
+impl EngineDatasource for EventsExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<Arc<dyn Plugin + Sync + Send>> {
+        match name {
+            Some("log") => Some(EventLogPlugin::create(namespace, "log")),
+            Some("rules") => Some(EventRulesPlugin::create(namespace, "rules")),
+            _ => None,
+        }
+    }
+}
+
+impl EngineExtension for EventsExtension {
+    fn name(&self) -> String {
+        "events".to_string()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<String, EngineError> {
+        let Some(name) = key.strip_prefix(RULE_PREFIX) else {
+            return Err(EngineError::UnsupportedOption(key.to_string()));
+        };
+        let old = rule(name).unwrap_or_default();
+        set_rule(name, value).map_err(|err| {
+            EngineError::InvalidOptionValue(key.to_string(), format!("{value}: {err}"))
+        })?;
+        Ok(old)
+    }
+
+    fn get(&self, key: &str) -> Result<String, EngineError> {
+        key.strip_prefix(RULE_PREFIX)
+            .and_then(rule)
+            .ok_or_else(|| EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn options(&self) -> Vec<EngineExtensionOption> {
+        let rules = RULES.lock().unwrap();
+        rules
+            .iter()
+            .map(|(name, rule)| EngineExtensionOption {
+                key: format!("events.{RULE_PREFIX}{name}"),
+                value: Some(rule.spec.to_string()),
+                help: "Event rule `<topic> <sql or @action>`, empty to remove it.\nENV[PROBING_EVENTS_RULE_<NAME>]",
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("*", "watchdog.hang"));
+        assert!(topic_matches("watchdog.*", "watchdog.hang"));
+        assert!(topic_matches("watchdog.hang", "watchdog.hang"));
+        assert!(!topic_matches("watchdog.hang", "watchdog.hangs"));
+        assert!(!topic_matches("probe.*", "watchdog.hang"));
+    }
+
+    #[test]
+    fn test_parse_rule_spec() {
+        let spec = "watchdog.hang @flamegraph pprof"
+            .parse::<RuleSpec>()
+            .unwrap();
+        assert_eq!(spec.topic, "watchdog.hang");
+        assert_eq!(
+            spec.action,
+            TaskAction::Call {
+                name: "flamegraph".to_string(),
+                args: "pprof".to_string()
+            }
+        );
+        assert_eq!(spec.to_string(), "watchdog.hang @flamegraph pprof");
+        assert!("watchdog.hang".parse::<RuleSpec>().is_err());
+    }
+
+    #[test]
+    fn test_publish_and_rules() {
+        static SEEN: AtomicUsize = AtomicUsize::new(0);
+        subscribe(
+            "test.*",
+            Arc::new(|_| {
+                SEEN.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+        super::super::scheduler::register_action("test_event", Arc::new(|_| Ok("ok".into())));
+        set_rule("test_rule", "test.fired @test_event").unwrap();
+
+        publish("test.fired", "test", "first");
+        publish("other.fired", "test", "ignored");
+        assert_eq!(SEEN.load(Ordering::SeqCst), 1);
+        assert!(LOG
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|e| e.topic == "test.fired" && e.message == "first"));
+
+        for _ in 0..100 {
+            if RULES.lock().unwrap()["test_rule"].fires > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let rules = RULES.lock().unwrap();
+        assert_eq!(rules["test_rule"].fires, 1);
+        assert_eq!(rules["test_rule"].last_output.as_deref(), Some("ok"));
+    }
+
+    #[test]
+    fn test_rules_do_not_feed_themselves() {
+        super::super::scheduler::register_action(
+            "test_loop",
+            Arc::new(|_| {
+                publish("loop.fired", "test", "from the action");
+                Ok("ok".into())
+            }),
+        );
+        set_rule("test_loop_rule", "loop.* @test_loop").unwrap();
+
+        publish("loop.fired", "test", "first");
+        for _ in 0..100 {
+            if RULES.lock().unwrap()["test_loop_rule"].fires > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // within the cooldown
+        publish("loop.fired", "test", "second");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let rules = RULES.lock().unwrap();
+        assert_eq!(rules["test_loop_rule"].fires, 1);
+        assert_eq!(rules["test_loop_rule"].suppressed, 1);
+    }
+}
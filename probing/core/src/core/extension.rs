@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::fmt::Debug;
@@ -8,6 +9,7 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use datafusion::config::{ConfigExtension, ExtensionOptions};
+use probing_proto::prelude::{SelfCheck, SelfTest};
 use tokio::sync::Mutex;
 
 use super::error::EngineError;
@@ -68,10 +70,19 @@ impl<T: Display> From<Maybe<T>> for String {
 /// * `key` - The unique identifier for this option
 /// * `value` - The current value of the option, if set
 /// * `help` - Static help text describing the purpose and usage of this option
+/// * `requires_restart` - Changes of a configured value only take effect once
+///   the extension is disabled and enabled again
+/// * `depends_on` - Option that must be set before this one, e.g. `backtrace.locals`
+/// * `secret` - The value is a credential, redacted in `probe.activity` and
+///   wherever clients read it
+#[derive(Debug, Clone, Default)]
 pub struct EngineExtensionOption {
     pub key: String,
     pub value: Option<String>,
     pub help: &'static str,
+    pub requires_restart: bool,
+    pub depends_on: Option<&'static str>,
+    pub secret: bool,
 }
 
 /// Extension trait for handling API calls
@@ -131,6 +142,12 @@ pub trait EngineDatasource: Debug + Send + Sync {
 /// * [`get`] - Retrieves a configuration option value  
 /// * [`options`] - Lists all available configuration options
 ///
+/// # Lifecycle Hooks
+///
+/// * [`on_enable`] - Called when the extension is re-enabled at runtime
+/// * [`on_disable`] - Called when the extension is disabled at runtime; heavy
+///   collectors should stop their background work here
+///
 /// # Examples
 ///
 /// ```
@@ -176,7 +193,8 @@ pub trait EngineDatasource: Debug + Send + Sync {
 ///             EngineExtensionOption {
 ///                 key: "some_option".to_string(),
 ///                 value: Some(self.some_option.clone()),
-///                 help: "An example option"
+///                 help: "An example option",
+///                 ..Default::default()
 ///             }
 ///         ]
 ///     }
@@ -198,6 +216,27 @@ pub trait EngineExtension: Debug + Send + Sync + EngineCall + EngineDatasource {
     fn options(&self) -> Vec<EngineExtensionOption> {
         todo!()
     }
+    /// The option `key`, by name or alias, with its metadata
+    fn option(&self, key: &str) -> Option<EngineExtensionOption> {
+        None
+    }
+    /// Check that `value` could be set for `key` without setting it. Derived
+    /// extensions check the type and constraints of the option; checks made
+    /// by their setters only run on [`set`](EngineExtension::set).
+    fn validate(&self, key: &str, value: &str) -> Result<(), EngineError> {
+        self.get(key).map(|_| ())
+    }
+    fn on_enable(&mut self) -> Result<(), EngineError> {
+        Ok(())
+    }
+    fn on_disable(&mut self) -> Result<(), EngineError> {
+        Ok(())
+    }
+    /// Quick checks of what the extension needs from the host, such as
+    /// permissions or libraries, telling why its tables may stay empty
+    fn selftest(&self) -> Vec<SelfCheck> {
+        vec![]
+    }
 }
 
 /// Engine extension management module for configurable functionality.
@@ -258,7 +297,8 @@ pub trait EngineExtension: Debug + Send + Sync + EngineCall + EngineDatasource {
 ///             EngineExtensionOption {
 ///                 key: "some_option".to_string(), // Local option key
 ///                 value: Some(self.some_option.clone()),
-///                 help: "An example option"
+///                 help: "An example option",
+///                 ..Default::default()
 ///             }
 ///         ]
 ///     }
@@ -300,16 +340,83 @@ pub trait EngineExtension: Debug + Send + Sync + EngineCall + EngineDatasource {
 /// ```
 #[derive(Clone, Debug, Default)]
 pub struct EngineExtensionManager {
-    extensions: BTreeMap<String, Arc<Mutex<dyn EngineExtension + Send + Sync>>>,
+    extensions: Arc<std::sync::RwLock<BTreeMap<String, ExtensionRef>>>,
+    disabled: Arc<std::sync::RwLock<BTreeSet<String>>>,
+    /// Shared libraries loaded through `extensions.load`
+    loaded: Arc<std::sync::RwLock<Vec<String>>>,
+    /// Data sources of loaded extensions not yet enabled in the engine
+    pending: Arc<std::sync::Mutex<Vec<Arc<dyn Plugin + Sync + Send>>>>,
+    /// Options changed since their extension was last enabled that only
+    /// take effect on the next enable
+    restart_pending: Arc<std::sync::RwLock<BTreeSet<String>>>,
+    /// Extension serving each table, keyed by `namespace.name`, or by
+    /// `namespace` for all tables of a namespace
+    tables: Arc<std::sync::RwLock<BTreeMap<String, String>>>,
 }
 
+type ExtensionRef = Arc<Mutex<dyn EngineExtension + Send + Sync>>;
+
+/// Option loading extensions from a shared library, see [`super::dylib`]
+pub const OPTION_EXTENSIONS_LOAD: &str = "extensions.load";
+
 impl EngineExtensionManager {
-    pub fn register(
-        &mut self,
-        name: String,
-        extension: Arc<Mutex<dyn EngineExtension + Send + Sync>>,
-    ) {
-        self.extensions.insert(name, extension);
+    pub fn register(&mut self, name: String, extension: ExtensionRef) {
+        if let Ok(mut extensions) = self.extensions.write() {
+            extensions.insert(name, extension);
+        }
+    }
+
+    /// Record that `table`, as `namespace` or `namespace.name`, is served by
+    /// the extension `name`
+    pub fn register_table(&mut self, table: String, name: String) {
+        if let Ok(mut tables) = self.tables.write() {
+            tables.insert(table, name);
+        }
+    }
+
+    /// Name of the extension serving `table`, e.g. `process.delay`
+    fn serving(&self, table: &str) -> Option<String> {
+        let tables = self.tables.read().ok()?;
+        let namespace = table.split('.').next().unwrap_or(table);
+        tables.get(table).or_else(|| tables.get(namespace)).cloned()
+    }
+
+    /// Extensions registered so far; the map is shared with the clones held
+    /// by DataFusion, so it is never locked across an await
+    fn snapshot(&self) -> Vec<ExtensionRef> {
+        self.extensions
+            .read()
+            .map(|extensions| extensions.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Load the extensions of the plugin library at `path` and register them.
+    ///
+    /// Their data sources are queued until the engine picks them up with
+    /// [`EngineExtensionManager::take_pending`]. Loading a library twice is a
+    /// no-op.
+    pub fn load(&mut self, path: &str) -> Result<(), EngineError> {
+        if self.loaded.read()?.iter().any(|p| p == path) {
+            return Ok(());
+        }
+        let registrar = super::dylib::load_plugin(path)?;
+        for (name, extension, datasrc) in registrar.into_parts() {
+            log::info!("extension [{name}] loaded from {path}");
+            self.register(name, extension);
+            if let Some(datasrc) = datasrc {
+                self.pending.lock()?.push(datasrc);
+            }
+        }
+        self.loaded.write()?.push(path.to_string());
+        Ok(())
+    }
+
+    /// Data sources of loaded extensions that still need to be enabled
+    pub fn take_pending(&self) -> Vec<Arc<dyn Plugin + Sync + Send>> {
+        self.pending
+            .lock()
+            .map(|mut pending| std::mem::take(&mut *pending))
+            .unwrap_or_default()
     }
 
     /// Extract namespace from extension name by removing "extension" suffix and converting to lowercase
@@ -321,20 +428,179 @@ impl EngineExtensionManager {
         format!("{namespace}.")
     }
 
+    fn is_disabled(&self, extension_name: &str) -> bool {
+        self.disabled
+            .read()
+            .map(|disabled| disabled.contains(extension_name))
+            .unwrap_or(false)
+    }
+
+    /// Find an extension by its name or its option namespace (e.g. `server`)
+    async fn find(&self, name: &str) -> Result<ExtensionRef, EngineError> {
+        for extension in self.snapshot() {
+            let ext_name = extension.lock().await.name();
+            if ext_name == name || Self::extract_namespace(&ext_name) == format!("{name}.") {
+                return Ok(extension);
+            }
+        }
+        Err(EngineError::PluginNotFound(name.to_string()))
+    }
+
+    /// List all extensions by namespace together with their enabled state
+    pub async fn list(&self) -> Vec<(String, bool)> {
+        let mut extensions = Vec::new();
+        for extension in self.snapshot() {
+            let name = extension.lock().await.name();
+            let namespace = Self::extract_namespace(&name);
+            extensions.push((
+                namespace.trim_end_matches('.').to_string(),
+                !self.is_disabled(&name),
+            ));
+        }
+        extensions
+    }
+
+    /// Enable a disabled extension, running its [`EngineExtension::on_enable`] hook
+    pub async fn enable(&self, name: &str) -> Result<(), EngineError> {
+        let extension = self.find(name).await?;
+        let mut ext = extension.lock().await;
+        let ext_name = ext.name();
+        if !self.is_disabled(&ext_name) {
+            return Ok(());
+        }
+        ext.on_enable()?;
+        self.disabled.write()?.remove(&ext_name);
+        let namespace = Self::extract_namespace(&ext_name);
+        self.restart_pending
+            .write()?
+            .retain(|key| !key.starts_with(&namespace));
+        super::activity::record("enable", namespace.trim_end_matches('.'), None, None);
+        log::info!("extension [{ext_name}] enabled");
+        Ok(())
+    }
+
+    /// Disable an extension, running its [`EngineExtension::on_disable`] hook.
+    ///
+    /// Disabled extensions reject option updates and API calls until enabled again.
+    pub async fn disable(&self, name: &str) -> Result<(), EngineError> {
+        let extension = self.find(name).await?;
+        let mut ext = extension.lock().await;
+        let ext_name = ext.name();
+        if self.is_disabled(&ext_name) {
+            return Ok(());
+        }
+        ext.on_disable()?;
+        self.disabled.write()?.insert(ext_name.clone());
+        let namespace = Self::extract_namespace(&ext_name);
+        super::activity::record("disable", namespace.trim_end_matches('.'), None, None);
+        log::info!("extension [{ext_name}] disabled");
+        Ok(())
+    }
+
+    /// Run the self-test of an extension, preceded by whether it is enabled;
+    /// `name` may also be a table, testing the extension serving it
+    pub async fn selftest(&self, name: &str) -> Result<SelfTest, EngineError> {
+        let extension = match self.find(name).await {
+            Ok(extension) => extension,
+            Err(err) => match self.serving(name) {
+                Some(serving) => self.find(&serving).await?,
+                None => return Err(err),
+            },
+        };
+        let ext = extension.lock().await;
+        let ext_name = ext.name();
+        let namespace = Self::extract_namespace(&ext_name);
+        let namespace = namespace.trim_end_matches('.');
+        let enabled = match self.is_disabled(&ext_name) {
+            true => SelfCheck::fail(
+                "enabled",
+                format!("disabled, run `probing <pid> extensions enable {namespace}`"),
+            ),
+            false => SelfCheck::pass("enabled", "enabled"),
+        };
+        let mut checks = vec![enabled];
+        checks.extend(ext.selftest());
+        Ok(SelfTest {
+            extension: namespace.to_string(),
+            checks,
+        })
+    }
+
+    /// The option `key` with its metadata, if its extension provides them
+    pub async fn option(&self, key: &str) -> Option<EngineExtensionOption> {
+        for extension in self.snapshot() {
+            let ext = extension.lock().await;
+            let namespace = Self::extract_namespace(&ext.name());
+            if let Some(option) = key
+                .strip_prefix(&namespace)
+                .and_then(|local_key| ext.option(local_key))
+            {
+                return Some(option);
+            }
+        }
+        None
+    }
+
+    /// Options whose last change waits for their extension to be re-enabled
+    pub fn restart_pending(&self) -> BTreeSet<String> {
+        self.restart_pending
+            .read()
+            .map(|pending| pending.clone())
+            .unwrap_or_default()
+    }
+
+    /// Set an option, enforcing the metadata of [`EngineExtension::option`]:
+    /// an option may only be set once the option it depends on is, and
+    /// options requiring a restart may also be set while their extension is
+    /// disabled, to be applied when it is enabled again.
     pub async fn set_option(&mut self, key: &str, value: &str) -> Result<(), EngineError> {
-        for extension in self.extensions.values() {
+        if key == OPTION_EXTENSIONS_LOAD {
+            for path in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                self.load(path)?;
+                super::activity::record("load", path, None, None);
+            }
+            return Ok(());
+        }
+        let option = self.option(key).await;
+        self.check_dependency(key, value, option.as_ref()).await?;
+        let requires_restart = option
+            .as_ref()
+            .is_some_and(|option| option.requires_restart);
+        let secret = option.as_ref().is_some_and(|option| option.secret);
+        for extension in self.snapshot() {
             let mut ext = extension.lock().await;
             let namespace = Self::extract_namespace(&ext.name());
             if !key.starts_with(&namespace) {
                 continue;
             }
+            let disabled = self.is_disabled(&ext.name());
+            if disabled && !requires_restart {
+                return Err(EngineError::ConfigError(format!(
+                    "extension {} is disabled",
+                    namespace.trim_end_matches('.')
+                )));
+            }
             let local_key = key.trim_start_matches(&namespace);
             match ext.set(local_key, value) {
                 Ok(old) => {
-                    log::info!(
-                        "setting update [{}]:{local_key}={value} <= {old}",
-                        ext.name()
-                    );
+                    if secret {
+                        log::info!("setting update [{}]:{local_key}", ext.name());
+                    } else {
+                        log::info!(
+                            "setting update [{}]:{local_key}={value} <= {old}",
+                            ext.name()
+                        );
+                    }
+                    super::activity::record_set(key, value, &old, secret);
+                    // the first value is picked up when the collector starts
+                    if requires_restart && !disabled && !old.is_empty() && old != value {
+                        let key = option.map(|option| option.key).unwrap_or(key.to_string());
+                        log::info!(
+                            "{key} takes effect once {} is enabled again",
+                            namespace.trim_end_matches('.')
+                        );
+                        self.restart_pending.write()?.insert(key);
+                    }
                     return Ok(());
                 }
                 Err(EngineError::UnsupportedOption(_)) => continue,
@@ -344,8 +610,83 @@ impl EngineExtensionManager {
         Err(EngineError::UnsupportedOption(key.to_string()))
     }
 
+    /// Check that [`set_option`](Self::set_option) would accept `value` for
+    /// `key`, without applying it, e.g. before changing every probe of a
+    /// cluster at once
+    pub async fn validate_option(&self, key: &str, value: &str) -> Result<(), EngineError> {
+        if key == OPTION_EXTENSIONS_LOAD {
+            let missing = value
+                .split(',')
+                .map(str::trim)
+                .find(|p| !p.is_empty() && !std::path::Path::new(p).exists());
+            return match missing {
+                Some(path) => Err(EngineError::InvalidOptionValue(
+                    key.to_string(),
+                    format!("{path} does not exist"),
+                )),
+                None => Ok(()),
+            };
+        }
+        let option = self.option(key).await;
+        self.check_dependency(key, value, option.as_ref()).await?;
+        let requires_restart = option
+            .as_ref()
+            .is_some_and(|option| option.requires_restart);
+        for extension in self.snapshot() {
+            let ext = extension.lock().await;
+            let namespace = Self::extract_namespace(&ext.name());
+            if !key.starts_with(&namespace) {
+                continue;
+            }
+            if self.is_disabled(&ext.name()) && !requires_restart {
+                return Err(EngineError::ConfigError(format!(
+                    "extension {} is disabled",
+                    namespace.trim_end_matches('.')
+                )));
+            }
+            match ext.validate(key.trim_start_matches(&namespace), value) {
+                Err(EngineError::UnsupportedOption(_)) => continue,
+                result => return result,
+            }
+        }
+        Err(EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    /// An option may only be set once the option it depends on is
+    async fn check_dependency(
+        &self,
+        key: &str,
+        value: &str,
+        option: Option<&EngineExtensionOption>,
+    ) -> Result<(), EngineError> {
+        if let Some(dependency) = option.and_then(|option| option.depends_on) {
+            // resetting an option is always allowed
+            let current = self.get_option(dependency).await.unwrap_or_default();
+            if !value.is_empty() && !is_set(&current) {
+                return Err(EngineError::ConfigError(format!(
+                    "{key} depends on {dependency}, set it first"
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub async fn get_option(&self, key: &str) -> Result<String, EngineError> {
-        for extension in self.extensions.values() {
+        self.lookup_option(key, false).await
+    }
+
+    /// Value of an option as shown to clients: that of
+    /// [`get_option`](Self::get_option), but `<redacted>` for the options
+    /// marked secret, which only the probe itself reads
+    pub async fn show_option(&self, key: &str) -> Result<String, EngineError> {
+        self.lookup_option(key, true).await
+    }
+
+    async fn lookup_option(&self, key: &str, redact: bool) -> Result<String, EngineError> {
+        if key == OPTION_EXTENSIONS_LOAD {
+            return Ok(self.loaded.read()?.join(","));
+        }
+        for extension in self.snapshot() {
             let ext = extension.lock().await;
             let namespace = Self::extract_namespace(&ext.name());
             if !key.starts_with(&namespace) {
@@ -353,19 +694,42 @@ impl EngineExtensionManager {
             }
             let local_key = key.trim_start_matches(&namespace);
             if let Ok(value) = ext.get(local_key) {
-                log::info!("setting read [{}]:{local_key}={value}", ext.name());
-                return Ok(value);
+                let secret = ext.option(local_key).is_some_and(|option| option.secret);
+                if secret {
+                    log::info!("setting read [{}]:{local_key}", ext.name());
+                } else {
+                    log::info!("setting read [{}]:{local_key}={value}", ext.name());
+                }
+                // whether a secret is set at all is no secret
+                return Ok(match redact && secret && !value.is_empty() {
+                    true => super::activity::REDACTED.to_string(),
+                    false => value,
+                });
             }
         }
         Err(EngineError::UnsupportedOption(key.to_string()))
     }
 
+    /// Options of all extensions as shown to clients, with the values of the
+    /// options marked secret redacted as by [`show_option`](Self::show_option),
+    /// also in `SHOW ALL`
     pub async fn options(&self) -> Vec<EngineExtensionOption> {
         let mut all_options = Vec::new();
-        for extension_arc in self.extensions.values() {
+        for extension_arc in self.snapshot() {
             let ext_guard = extension_arc.lock().await;
-            all_options.extend(ext_guard.options());
+            all_options.extend(ext_guard.options().into_iter().map(|mut option| {
+                if option.secret && option.value.as_deref().is_some_and(|v| !v.is_empty()) {
+                    option.value = Some(super::activity::REDACTED.to_string());
+                }
+                option
+            }));
         }
+        all_options.push(EngineExtensionOption {
+            key: OPTION_EXTENSIONS_LOAD.to_string(),
+            value: self.loaded.read().ok().map(|loaded| loaded.join(",")),
+            help: "Comma separated plugin libraries (.so) to load extensions from.\nENV[PROBING_EXTENSIONS_LOAD]",
+            ..Default::default()
+        });
         all_options
     }
 
@@ -375,13 +739,13 @@ impl EngineExtensionManager {
         params: &HashMap<String, String>,
         body: &[u8],
     ) -> Result<Vec<u8>, EngineError> {
-        for extension in self.extensions.values() {
+        for extension in self.snapshot() {
             let ext = extension.lock().await;
             let name = ext.name();
             log::debug!("checking extension [{name}]:{path}");
 
             let expected_prefix = format!("/{name}/");
-            if !path.starts_with(&expected_prefix) {
+            if !path.starts_with(&expected_prefix) || self.is_disabled(&name) {
                 continue;
             }
 
@@ -396,6 +760,14 @@ impl EngineExtensionManager {
     }
 }
 
+/// Whether an option value enables something, e.g. for `depends_on`
+fn is_set(value: &str) -> bool {
+    !matches!(
+        value.trim().to_lowercase().as_str(),
+        "" | "0" | "false" | "off" | "none"
+    )
+}
+
 impl ConfigExtension for EngineExtensionManager {
     const PREFIX: &'static str = "probing";
 }
@@ -410,13 +782,7 @@ impl ExtensionOptions for EngineExtensionManager {
     }
 
     fn cloned(&self) -> Box<dyn ExtensionOptions> {
-        Box::new(EngineExtensionManager {
-            extensions: self
-                .extensions
-                .iter()
-                .map(|(name, ext)| (name.clone(), ext.clone()))
-                .collect(),
-        })
+        Box::new(self.clone())
     }
 
     fn set(&mut self, key: &str, value: &str) -> datafusion::error::Result<()> {
@@ -445,3 +811,178 @@ impl ExtensionOptions for EngineExtensionManager {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct CollectorExtension {
+        running: bool,
+    }
+
+    impl EngineCall for CollectorExtension {}
+
+    impl EngineDatasource for CollectorExtension {}
+
+    impl EngineExtension for CollectorExtension {
+        fn name(&self) -> String {
+            "collectorextension".to_string()
+        }
+
+        fn set(&mut self, key: &str, _value: &str) -> Result<String, EngineError> {
+            Err(EngineError::UnsupportedOption(key.to_string()))
+        }
+
+        fn on_enable(&mut self) -> Result<(), EngineError> {
+            self.running = true;
+            Ok(())
+        }
+
+        fn on_disable(&mut self) -> Result<(), EngineError> {
+            self.running = false;
+            Ok(())
+        }
+
+        fn selftest(&self) -> Vec<SelfCheck> {
+            match self.running {
+                true => vec![SelfCheck::pass("running", "collecting")],
+                false => vec![SelfCheck::fail("running", "stopped")],
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enable_disable_extension() {
+        let collector = Arc::new(Mutex::new(CollectorExtension { running: true }));
+        let mut manager = EngineExtensionManager::default();
+        manager.register("collector".to_string(), collector.clone());
+
+        assert_eq!(manager.list().await, vec![("collector".to_string(), true)]);
+
+        manager.disable("collector").await.unwrap();
+        assert!(!collector.lock().await.running);
+        assert_eq!(manager.list().await, vec![("collector".to_string(), false)]);
+        assert!(matches!(
+            manager.set_option("collector.any", "1").await,
+            Err(EngineError::ConfigError(_))
+        ));
+
+        // disabled state is shared with clones handed to DataFusion
+        let cloned = manager.clone();
+        cloned.enable("collectorextension").await.unwrap();
+        assert!(collector.lock().await.running);
+        assert_eq!(manager.list().await, vec![("collector".to_string(), true)]);
+
+        assert!(manager.enable("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_selftest() {
+        let collector = Arc::new(Mutex::new(CollectorExtension { running: true }));
+        let mut manager = EngineExtensionManager::default();
+        manager.register("collector".to_string(), collector);
+        manager.register_table("collector.samples".to_string(), "collector".to_string());
+
+        let selftest = manager.selftest("collector").await.unwrap();
+        assert_eq!(selftest.extension, "collector");
+        assert_eq!(selftest.checks.len(), 2);
+        assert!(selftest.passed());
+
+        // a table is tested through the extension serving it
+        manager.disable("collector").await.unwrap();
+        let selftest = manager.selftest("collector.samples").await.unwrap();
+        assert!(!selftest.checks[0].passed);
+        assert!(!selftest.checks[1].passed);
+        assert!(manager.selftest("missing.samples").await.is_err());
+    }
+
+    #[derive(Debug, Default)]
+    struct SamplerExtension {
+        interval: String,
+        detail: String,
+    }
+
+    impl EngineCall for SamplerExtension {}
+
+    impl EngineDatasource for SamplerExtension {}
+
+    impl EngineExtension for SamplerExtension {
+        fn name(&self) -> String {
+            "samplerextension".to_string()
+        }
+
+        fn set(&mut self, key: &str, value: &str) -> Result<String, EngineError> {
+            match key {
+                "interval" => Ok(std::mem::replace(&mut self.interval, value.to_string())),
+                "detail" => Ok(std::mem::replace(&mut self.detail, value.to_string())),
+                _ => Err(EngineError::UnsupportedOption(key.to_string())),
+            }
+        }
+
+        fn get(&self, key: &str) -> Result<String, EngineError> {
+            match key {
+                "interval" => Ok(self.interval.clone()),
+                "detail" => Ok(self.detail.clone()),
+                _ => Err(EngineError::UnsupportedOption(key.to_string())),
+            }
+        }
+
+        fn option(&self, key: &str) -> Option<EngineExtensionOption> {
+            match key {
+                "interval" => Some(EngineExtensionOption {
+                    key: "sampler.interval".to_string(),
+                    value: Some(self.interval.clone()),
+                    requires_restart: true,
+                    ..Default::default()
+                }),
+                "detail" => Some(EngineExtensionOption {
+                    key: "sampler.detail".to_string(),
+                    value: Some(self.detail.clone()),
+                    depends_on: Some("sampler.interval"),
+                    ..Default::default()
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_option_metadata() {
+        let mut manager = EngineExtensionManager::default();
+        let sampler = Arc::new(Mutex::new(SamplerExtension::default()));
+        manager.register("sampler".to_string(), sampler);
+
+        assert!(matches!(
+            manager.set_option("sampler.detail", "full").await,
+            Err(EngineError::ConfigError(_))
+        ));
+        assert!(manager
+            .validate_option("sampler.detail", "full")
+            .await
+            .is_err());
+        assert!(manager
+            .validate_option("sampler.missing", "1")
+            .await
+            .is_err());
+        manager
+            .validate_option("sampler.interval", "10")
+            .await
+            .unwrap();
+        assert_eq!(manager.get_option("sampler.interval").await.unwrap(), "");
+        manager.set_option("sampler.interval", "10").await.unwrap();
+        manager.set_option("sampler.detail", "full").await.unwrap();
+        assert!(manager.restart_pending().is_empty());
+
+        // changing a running collector waits for the next enable
+        manager.set_option("sampler.interval", "20").await.unwrap();
+        assert!(manager.restart_pending().contains("sampler.interval"));
+
+        manager.disable("sampler").await.unwrap();
+        assert!(manager.set_option("sampler.detail", "").await.is_err());
+        manager.set_option("sampler.interval", "30").await.unwrap();
+        manager.enable("sampler").await.unwrap();
+        assert!(manager.restart_pending().is_empty());
+        assert_eq!(manager.get_option("sampler.interval").await.unwrap(), "30");
+    }
+}
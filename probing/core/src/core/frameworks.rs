@@ -0,0 +1,188 @@
+//! Detection of worker processes of non-PyTorch frameworks.
+//!
+//! Ray, Dask and Celery workers carry no `RANK`/`WORLD_SIZE`, so they are
+//! recognized from their command line and well-known environment variables
+//! instead, and labeled with what identifies them in their framework (job,
+//! node, queue, ...). The labels always contain `framework`.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub type Labels = BTreeMap<String, String>;
+
+/// Value of `--name value` or `--name=value` in `args`, for any of `names`
+fn flag(args: &[String], names: &[&str]) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, arg)| {
+        names.iter().find_map(|name| {
+            if arg == name {
+                args.get(i + 1).cloned()
+            } else {
+                arg.strip_prefix(name)?
+                    .strip_prefix('=')
+                    .map(str::to_string)
+            }
+        })
+    })
+}
+
+fn insert(labels: &mut Labels, key: &str, value: Option<String>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        labels.insert(key.to_string(), value);
+    }
+}
+
+fn ray(args: &[String], env: &HashMap<String, String>) -> Option<Labels> {
+    // workers rename their process title to `ray::IDLE`, `ray::Actor.method`
+    let title = args.first().and_then(|arg| arg.strip_prefix("ray::"));
+    let is_worker = title.is_some()
+        || args.iter().any(|arg| arg.ends_with("default_worker.py"))
+        || env.contains_key("RAY_RAYLET_PID");
+    if !is_worker {
+        return None;
+    }
+    let mut labels = Labels::new();
+    insert(&mut labels, "task", title.map(|t| t.trim().to_string()));
+    insert(&mut labels, "job_id", env.get("RAY_JOB_ID").cloned());
+    insert(&mut labels, "node_id", flag(args, &["--node-id"]));
+    insert(&mut labels, "node_ip", flag(args, &["--node-ip-address"]));
+    Some(labels)
+}
+
+fn dask(args: &[String], env: &HashMap<String, String>) -> Option<Labels> {
+    // `dask-worker`, `dask worker` or `python -m distributed.cli.dask_worker`
+    let command = args
+        .iter()
+        .position(|arg| arg.ends_with("dask-worker") || arg.ends_with("dask_worker"))
+        .or_else(|| {
+            let dask = args.iter().position(|arg| arg.ends_with("dask"))?;
+            (args.get(dask + 1)? == "worker").then_some(dask + 1)
+        })?;
+    let args = &args[command + 1..];
+    let mut labels = Labels::new();
+    insert(&mut labels, "worker_name", flag(args, &["--name"]));
+    insert(
+        &mut labels,
+        "scheduler",
+        args.iter()
+            .find(|arg| arg.contains("://"))
+            .cloned()
+            .or_else(|| env.get("DASK_SCHEDULER_ADDRESS").cloned()),
+    );
+    Some(labels)
+}
+
+fn celery(args: &[String], _env: &HashMap<String, String>) -> Option<Labels> {
+    let celery = args.iter().position(|arg| arg.ends_with("celery"))?;
+    let args = &args[celery + 1..];
+    if !args.iter().any(|arg| arg == "worker") {
+        return None;
+    }
+    let mut labels = Labels::new();
+    insert(&mut labels, "app", flag(args, &["-A", "--app"]));
+    insert(&mut labels, "queue", flag(args, &["-Q", "--queues"]));
+    insert(
+        &mut labels,
+        "worker_name",
+        flag(args, &["-n", "--hostname"]),
+    );
+    Some(labels)
+}
+
+/// Labels of a framework worker with command line `args` and environment
+/// `env`, or an empty map for any other process
+pub fn detect(args: &[String], env: &HashMap<String, String>) -> Labels {
+    let detectors: [(
+        &str,
+        fn(&[String], &HashMap<String, String>) -> Option<Labels>,
+    ); 3] = [("ray", ray), ("dask", dask), ("celery", celery)];
+    detectors
+        .iter()
+        .find_map(|(name, detector)| {
+            let mut labels = detector(args, env)?;
+            labels.insert("framework".to_string(), name.to_string());
+            Some(labels)
+        })
+        .unwrap_or_default()
+}
+
+/// Labels of the current process
+pub fn detect_self() -> Labels {
+    detect(
+        &std::env::args().collect::<Vec<_>>(),
+        &std::env::vars().collect(),
+    )
+}
+
+/// Labels of process `pid`, read from `/proc`
+pub fn detect_pid(pid: i32) -> Labels {
+    let split = |data: Vec<u8>| {
+        data.split(|b| *b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).to_string())
+            .collect::<Vec<_>>()
+    };
+    let args = std::fs::read(format!("/proc/{pid}/cmdline"))
+        .map(split)
+        .unwrap_or_default();
+    let env = std::fs::read(format!("/proc/{pid}/environ"))
+        .map(split)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|var| {
+            var.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        })
+        .collect();
+    detect(&args, &env)
+}
+
+/// Space separated `key=value` pairs of `labels` other than `framework`
+pub fn format_labels(labels: &Labels) -> String {
+    labels
+        .iter()
+        .filter(|(key, _)| *key != "framework")
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmdline: &str) -> Vec<String> {
+        cmdline.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_detect_workers() {
+        let env = HashMap::from([("RAY_JOB_ID".to_string(), "02000000".to_string())]);
+        let labels = detect(&args("ray::Trainer.step"), &env);
+        assert_eq!(labels["framework"], "ray");
+        assert_eq!(labels["task"], "Trainer.step");
+        assert_eq!(labels["job_id"], "02000000");
+
+        let labels = detect(
+            &args("/usr/bin/python3 /usr/bin/dask worker tcp://10.0.0.1:8786 --name w3"),
+            &HashMap::new(),
+        );
+        assert_eq!(labels["framework"], "dask");
+        assert_eq!(labels["scheduler"], "tcp://10.0.0.1:8786");
+        assert_eq!(labels["worker_name"], "w3");
+
+        let labels = detect(
+            &args("/venv/bin/celery -A tasks worker -Q images,video --hostname=w1@%h"),
+            &HashMap::new(),
+        );
+        assert_eq!(labels["framework"], "celery");
+        assert_eq!(labels["app"], "tasks");
+        assert_eq!(labels["queue"], "images,video");
+        assert_eq!(labels["worker_name"], "w1@%h");
+        assert_eq!(
+            format_labels(&labels),
+            "app=tasks queue=images,video worker_name=w1@%h"
+        );
+
+        assert!(detect(&args("python train.py"), &HashMap::new()).is_empty());
+        assert!(detect(&args("celery -A tasks beat"), &HashMap::new()).is_empty());
+    }
+}
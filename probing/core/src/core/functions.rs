@@ -0,0 +1,166 @@
+//! SQL functions over histograms stored as text, see [`Histogram`], and the
+//! registry of functions added while the probe runs.
+//!
+//! ```sql
+//! -- p99 step latency over all ranks
+//! SELECT histogram_quantile(histogram_merge(latency), 0.99) FROM python.step_latency;
+//! ```
+
+use std::sync::{Arc, RwLock};
+
+use datafusion::arrow::array::{ArrayRef, Float64Array};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::cast::{as_float64_array, as_string_array};
+use datafusion::common::ScalarValue;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    create_udaf, create_udf, Accumulator, AggregateUDF, ColumnarValue, ScalarUDF, Volatility,
+};
+use once_cell::sync::Lazy;
+use probing_proto::prelude::Histogram;
+
+/// Functions registered after the engines were built, e.g. Python UDFs
+static REGISTERED: Lazy<RwLock<Vec<ScalarUDF>>> = Lazy::new(Default::default);
+
+/// Make `udf` callable from queries of every engine, replacing a function
+/// registered earlier under the same name
+pub fn register_udf(udf: ScalarUDF) {
+    if let Ok(mut registered) = REGISTERED.write() {
+        registered.retain(|f| f.name() != udf.name());
+        registered.push(udf);
+    }
+}
+
+/// Functions registered with [`register_udf`]
+pub fn registered_udfs() -> Vec<ScalarUDF> {
+    REGISTERED
+        .read()
+        .map(|registered| registered.clone())
+        .unwrap_or_default()
+}
+
+fn parse(text: &str) -> Result<Histogram> {
+    text.parse()
+        .map_err(|e| DataFusionError::Execution(format!("invalid histogram: {e}")))
+}
+
+/// `histogram_quantile(histogram, q)`: estimated `q` quantile, NULL for an
+/// empty histogram
+pub fn histogram_quantile() -> ScalarUDF {
+    create_udf(
+        "histogram_quantile",
+        vec![DataType::Utf8, DataType::Float64],
+        DataType::Float64,
+        Volatility::Immutable,
+        Arc::new(|args: &[ColumnarValue]| {
+            let args = ColumnarValue::values_to_arrays(args)?;
+            let hists = as_string_array(&args[0])?;
+            let qs = as_float64_array(&args[1])?;
+            let quantiles = hists
+                .iter()
+                .zip(qs.iter())
+                .map(|(hist, q)| match (hist, q) {
+                    (Some(hist), Some(q)) => Ok(parse(hist)?.quantile(q)),
+                    _ => Ok(None),
+                })
+                .collect::<Result<Float64Array>>()?;
+            Ok(ColumnarValue::Array(Arc::new(quantiles)))
+        }),
+    )
+}
+
+/// Merges the histograms of a group, NULLs and empty strings are skipped
+#[derive(Debug, Default)]
+struct HistogramMerge {
+    merged: Option<Histogram>,
+}
+
+impl HistogramMerge {
+    fn merge_texts(&mut self, values: &ArrayRef) -> Result<()> {
+        for text in as_string_array(values)?.iter().flatten() {
+            if text.is_empty() {
+                continue;
+            }
+            let hist = parse(text)?;
+            match self.merged.as_mut() {
+                Some(merged) => merged
+                    .merge(&hist)
+                    .map_err(|e| DataFusionError::Execution(e.to_string()))?,
+                None => self.merged = Some(hist),
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&self) -> ScalarValue {
+        ScalarValue::Utf8(self.merged.as_ref().map(|h| h.to_string()))
+    }
+}
+
+impl Accumulator for HistogramMerge {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        self.merge_texts(&values[0])
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        self.merge_texts(&states[0])
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![self.value()])
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        Ok(self.value())
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self
+                .merged
+                .as_ref()
+                .map_or(0, |h| (h.bounds().len() + h.counts().len()) * 8)
+    }
+}
+
+/// `histogram_merge(histogram)`: aggregate merging histograms with the same
+/// buckets into one
+pub fn histogram_merge() -> AggregateUDF {
+    create_udaf(
+        "histogram_merge",
+        vec![DataType::Utf8],
+        Arc::new(DataType::Utf8),
+        Volatility::Immutable,
+        Arc::new(|_| Ok(Box::new(HistogramMerge::default()) as Box<dyn Accumulator>)),
+        Arc::new(vec![DataType::Utf8]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::StringArray;
+
+    use super::*;
+
+    #[test]
+    fn test_histogram_merge_accumulator() {
+        let mut a = Histogram::default();
+        a.record(1.0);
+        let mut b = Histogram::default();
+        b.record_n(3.0, 3);
+
+        let values: ArrayRef = Arc::new(StringArray::from(vec![
+            Some(a.to_string()),
+            None,
+            Some(b.to_string()),
+        ]));
+        let mut acc = HistogramMerge::default();
+        acc.update_batch(&[values]).unwrap();
+        let ScalarValue::Utf8(Some(text)) = acc.evaluate().unwrap() else {
+            panic!("expected a merged histogram");
+        };
+        let merged: Histogram = text.parse().unwrap();
+        assert_eq!(merged.count(), 4);
+        assert_eq!(merged.max(), Some(3.0));
+    }
+}
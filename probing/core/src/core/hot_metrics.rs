@@ -0,0 +1,274 @@
+//! Hot metrics shared with same-host clients through memory.
+//!
+//! The probe maps a small segment, `/dev/shm/probing-<pid>.status`, and keeps
+//! its step, loss, CPU usage and GPU utilization there. Every metric is a
+//! single atomic word, so writers never lock and never wait for a reader.
+//! `probing <pid> status --fast` maps the same file read-only and polls it
+//! without a socket round-trip or any work in the target.
+
+use std::fs::{File, OpenOptions};
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use once_cell::sync::Lazy;
+use probing_proto::prelude::ProbeStatus;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"PRBSTAT\0");
+const LAYOUT_VERSION: u32 = 1;
+
+/// Step value of a segment no step was published to
+const NO_STEP: u64 = u64::MAX;
+
+/// Layout of the segment; fields are only appended, bumping the version
+#[repr(C)]
+pub struct HotMetrics {
+    magic: AtomicU64,
+    version: AtomicU32,
+    pid: AtomicU32,
+    /// Microseconds since the epoch of the last update, 0 if never updated
+    updated: AtomicI64,
+    step: AtomicU64,
+    /// `f64` bits, NaN while unknown
+    loss: AtomicU64,
+    cpu: AtomicU64,
+    gpu_util: AtomicU64,
+}
+
+fn load_f64(value: &AtomicU64) -> Option<f64> {
+    Some(f64::from_bits(value.load(Ordering::Relaxed))).filter(|v| !v.is_nan())
+}
+
+impl HotMetrics {
+    fn touch(&self) {
+        let now = chrono::Utc::now().timestamp_micros();
+        self.updated.store(now, Ordering::Release);
+    }
+
+    pub fn set_step(&self, step: u64) {
+        self.step.store(step, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn set_loss(&self, loss: f64) {
+        self.loss.store(loss.to_bits(), Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn set_cpu(&self, percent: f64) {
+        self.cpu.store(percent.to_bits(), Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn set_gpu_util(&self, percent: f64) {
+        self.gpu_util.store(percent.to_bits(), Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn status(&self) -> ProbeStatus {
+        let updated = self.updated.load(Ordering::Acquire);
+        ProbeStatus {
+            pid: self.pid.load(Ordering::Relaxed),
+            step: Some(self.step.load(Ordering::Relaxed)).filter(|step| *step != NO_STEP),
+            loss: load_f64(&self.loss),
+            cpu: load_f64(&self.cpu),
+            gpu_util: load_f64(&self.gpu_util),
+            updated: (updated != 0).then_some(updated),
+        }
+    }
+}
+
+/// A mapping of a hot metrics segment
+pub struct Segment {
+    metrics: NonNull<HotMetrics>,
+}
+
+// The mapping is only accessed through atomics.
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
+
+const SEGMENT_SIZE: usize = std::mem::size_of::<HotMetrics>();
+
+fn map(file: &File, prot: ProtFlags) -> Result<NonNull<HotMetrics>> {
+    let len = NonZeroUsize::new(SEGMENT_SIZE).unwrap();
+    let addr = unsafe { mmap(None, len, prot, MapFlags::MAP_SHARED, file, 0)? };
+    Ok(addr.cast())
+}
+
+impl Segment {
+    /// Create the segment of process `pid` at `path`, replacing a stale one
+    /// left by an earlier process with the same pid
+    pub fn create(path: &Path, pid: u32) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(SEGMENT_SIZE as u64)?;
+        let metrics = map(&file, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+        let segment = Self { metrics };
+        segment.version.store(LAYOUT_VERSION, Ordering::Relaxed);
+        segment.pid.store(pid, Ordering::Relaxed);
+        segment.step.store(NO_STEP, Ordering::Relaxed);
+        for value in [&segment.loss, &segment.cpu, &segment.gpu_util] {
+            value.store(f64::NAN.to_bits(), Ordering::Relaxed);
+        }
+        // readers reject the segment until the header is complete
+        segment.magic.store(MAGIC, Ordering::Release);
+        Ok(segment)
+    }
+
+    /// Map the segment at `path` read-only
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() < SEGMENT_SIZE as u64 {
+            anyhow::bail!("{} is not a probing status segment", path.display());
+        }
+        let segment = Self {
+            metrics: map(&file, ProtFlags::PROT_READ)?,
+        };
+        if segment.magic.load(Ordering::Acquire) != MAGIC {
+            anyhow::bail!("{} is not a probing status segment", path.display());
+        }
+        let version = segment.version.load(Ordering::Relaxed);
+        if version != LAYOUT_VERSION {
+            anyhow::bail!(
+                "status segment layout {version} is not supported, expected {LAYOUT_VERSION}"
+            );
+        }
+        Ok(segment)
+    }
+}
+
+impl Deref for Segment {
+    type Target = HotMetrics;
+
+    fn deref(&self) -> &HotMetrics {
+        unsafe { self.metrics.as_ref() }
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.metrics.cast(), SEGMENT_SIZE) };
+    }
+}
+
+/// Path of the segment of process `pid`
+pub fn segment_path(pid: u32) -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    let root = if shm.is_dir() {
+        shm.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    };
+    root.join(format!("probing-{pid}.status"))
+}
+
+static SEGMENT: Lazy<Option<Segment>> = Lazy::new(|| {
+    let pid = std::process::id();
+    Segment::create(&segment_path(pid), pid)
+        .map_err(|err| log::warn!("failed to create status segment: {err}"))
+        .ok()
+});
+
+/// Hot metrics of the current process, `None` if the segment could not be
+/// created
+pub fn local() -> Option<&'static HotMetrics> {
+    SEGMENT.as_deref()
+}
+
+/// Ticks of CPU time used by the current process, from `/proc/self/stat`
+fn cpu_ticks() -> Option<u64> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // the command name may contain spaces, fields are counted after it
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Create the segment of the current process and refresh its CPU usage
+/// every `interval` from a background thread
+pub fn start(interval: Duration) {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        let Some(metrics) = local() else {
+            return;
+        };
+        let ticks_per_sec = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK)
+            .ok()
+            .flatten()
+            .unwrap_or(100) as f64;
+        let worker = std::thread::Builder::new()
+            .name("probing-hot-metrics".to_string())
+            .spawn(move || {
+                let mut last = (Instant::now(), cpu_ticks());
+                loop {
+                    std::thread::sleep(interval);
+                    let now = (Instant::now(), cpu_ticks());
+                    if super::killswitch::is_dormant() {
+                        last = now;
+                        continue;
+                    }
+                    if let ((then, Some(before)), (at, Some(after))) = (last, now) {
+                        let elapsed = at.duration_since(then).as_secs_f64();
+                        let used = after.saturating_sub(before) as f64 / ticks_per_sec;
+                        metrics.set_cpu(100.0 * used / elapsed.max(f64::EPSILON));
+                    }
+                    last = now;
+                }
+            });
+        if let Err(err) = worker {
+            log::warn!("failed to start hot metrics updater: {err}");
+        }
+    });
+}
+
+/// Remove the segment of the current process, e.g. at exit
+pub fn remove() {
+    let path = segment_path(std::process::id());
+    if path.exists() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_roundtrip() {
+        let path = std::env::temp_dir().join(format!("probing-test-{}.status", std::process::id()));
+        let writer = Segment::create(&path, 42).unwrap();
+        let reader = Segment::open(&path).unwrap();
+        assert_eq!(
+            reader.status(),
+            ProbeStatus {
+                pid: 42,
+                ..Default::default()
+            }
+        );
+
+        writer.set_step(7);
+        writer.set_loss(0.5);
+        writer.set_cpu(150.0);
+        let status = reader.status();
+        assert_eq!(status.step, Some(7));
+        assert_eq!(status.loss, Some(0.5));
+        assert_eq!(status.cpu, Some(150.0));
+        assert_eq!(status.gpu_util, None);
+        assert!(status.updated.is_some());
+
+        std::fs::write(&path, [0u8; SEGMENT_SIZE]).unwrap();
+        assert!(Segment::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
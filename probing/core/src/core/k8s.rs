@@ -0,0 +1,166 @@
+//! Detection of the Kubernetes pod and container of the process.
+//!
+//! A pod is recognized by the `KUBERNETES_SERVICE_HOST` variable the kubelet
+//! sets in every container. Its name, namespace and node come from the
+//! downward API variables `POD_NAME`, `POD_NAMESPACE` and `NODE_NAME` when the
+//! pod spec exposes them, falling back to the hostname (the pod name unless
+//! overridden) and the service account namespace. The container id and pod
+//! uid are parsed from the cgroup paths, which the kubelet names after them.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+use super::frameworks::Labels;
+
+const SERVICE_ACCOUNT_NAMESPACE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Where the process runs in the cluster, empty fields were not found
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct K8sInfo {
+    pub pod: Option<String>,
+    pub namespace: Option<String>,
+    pub node: Option<String>,
+    pub pod_uid: Option<String>,
+    pub container_id: Option<String>,
+}
+
+impl K8sInfo {
+    /// Heartbeat labels, prefixed with `k8s_`
+    pub fn labels(&self) -> Labels {
+        [
+            ("k8s_pod", &self.pod),
+            ("k8s_namespace", &self.namespace),
+            ("k8s_node", &self.node),
+            ("k8s_container", &self.container_id),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.clone()?)))
+        .collect()
+    }
+}
+
+fn is_hex_id(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Container id of a cgroup path segment: the bare id of the cgroupfs driver
+/// or `<runtime>-<id>.scope` of the systemd driver
+fn container_id(segment: &str) -> Option<String> {
+    let id = segment.strip_suffix(".scope").unwrap_or(segment);
+    let id = id.rsplit_once('-').map(|(_, id)| id).unwrap_or(id);
+    is_hex_id(id).then(|| id.to_string())
+}
+
+/// Pod uid of a cgroup path segment, `pod<uid>` or `kubepods-besteffort-pod<uid>.slice`
+/// with the dashes of the uid replaced by underscores
+fn pod_uid(segment: &str) -> Option<String> {
+    let segment = segment.strip_suffix(".slice").unwrap_or(segment);
+    let (_, uid) = segment.rsplit_once("pod")?;
+    let uid = uid.replace('_', "-");
+    (uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-')).then_some(uid)
+}
+
+/// Pod uid and container id from the content of `/proc/<pid>/cgroup`
+fn parse_cgroup(content: &str) -> (Option<String>, Option<String>) {
+    for line in content.lines() {
+        let Some((_, path)) = line.rsplit_once(':') else {
+            continue;
+        };
+        if !path.contains("kubepods") {
+            continue;
+        }
+        let segments = path.split('/').collect::<Vec<_>>();
+        let uid = segments.iter().find_map(|s| pod_uid(s));
+        let id = segments.iter().rev().find_map(|s| container_id(s));
+        if uid.is_some() || id.is_some() {
+            return (uid, id);
+        }
+    }
+    (None, None)
+}
+
+/// Pod of a process with environment `env`, cgroup file content `cgroup` and
+/// service account namespace `namespace_file`; `None` outside Kubernetes
+pub fn detect(
+    env: &HashMap<String, String>,
+    cgroup: &str,
+    namespace_file: Option<String>,
+) -> Option<K8sInfo> {
+    let (pod_uid, container_id) = parse_cgroup(cgroup);
+    if !env.contains_key("KUBERNETES_SERVICE_HOST") && pod_uid.is_none() {
+        return None;
+    }
+    let var = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| env.get(*name).filter(|v| !v.is_empty()).cloned())
+    };
+    Some(K8sInfo {
+        pod: var(&["POD_NAME", "K8S_POD_NAME", "HOSTNAME"]),
+        namespace: var(&["POD_NAMESPACE", "K8S_NAMESPACE"]).or_else(|| {
+            namespace_file
+                .map(|ns| ns.trim().to_string())
+                .filter(|ns| !ns.is_empty())
+        }),
+        node: var(&["NODE_NAME", "K8S_NODE_NAME"]),
+        pod_uid: var(&["POD_UID"]).or(pod_uid),
+        container_id,
+    })
+}
+
+static SELF: Lazy<Option<K8sInfo>> = Lazy::new(|| {
+    detect(
+        &std::env::vars().collect(),
+        &std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default(),
+        std::fs::read_to_string(SERVICE_ACCOUNT_NAMESPACE).ok(),
+    )
+});
+
+/// Pod of the current process, detected once
+pub fn detect_self() -> Option<K8sInfo> {
+    SELF.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "3f1c2a7b9e0d4c5f8a6b1e2d3c4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f";
+
+    #[test]
+    fn test_detect_pod() {
+        let env = HashMap::from([
+            (
+                "KUBERNETES_SERVICE_HOST".to_string(),
+                "10.96.0.1".to_string(),
+            ),
+            ("HOSTNAME".to_string(), "trainer-worker-3".to_string()),
+            ("NODE_NAME".to_string(), "gpu-node-12".to_string()),
+        ]);
+        let cgroup = format!(
+            "0::/kubepods.slice/kubepods-burstable.slice/\
+             kubepods-burstable-pod1b2c3d4e_5f60_7182_93a4_b5c6d7e8f901.slice/cri-containerd-{ID}.scope\n"
+        );
+        let info = detect(&env, &cgroup, Some("training\n".to_string())).unwrap();
+        assert_eq!(info.pod.as_deref(), Some("trainer-worker-3"));
+        assert_eq!(info.namespace.as_deref(), Some("training"));
+        assert_eq!(info.node.as_deref(), Some("gpu-node-12"));
+        assert_eq!(
+            info.pod_uid.as_deref(),
+            Some("1b2c3d4e-5f60-7182-93a4-b5c6d7e8f901")
+        );
+        assert_eq!(info.container_id.as_deref(), Some(ID));
+        assert_eq!(info.labels()["k8s_node"], "gpu-node-12");
+        assert!(!info.labels().contains_key("k8s_pod_uid"));
+
+        let cgroup = format!(
+            "11:memory:/kubepods/besteffort/pod1b2c3d4e-5f60-7182-93a4-b5c6d7e8f901/{ID}\n"
+        );
+        let info = detect(&HashMap::new(), &cgroup, None).unwrap();
+        assert_eq!(info.container_id.as_deref(), Some(ID));
+        assert_eq!(info.pod, None);
+
+        assert!(detect(&HashMap::new(), "0::/user.slice/session-1.scope\n", None).is_none());
+    }
+}
@@ -0,0 +1,42 @@
+//! Kill switch putting the probe into a dormant state.
+//!
+//! Engaging it stops the collectors that registered a shutdown hook, as if
+//! the process was exiting, and makes the periodic ones (scheduler, exporters,
+//! scanners, ...) skip their work until the probe is resumed. The server
+//! closes its remote listener on top of that; see `probing <pid> kill-switch`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static DORMANT: AtomicBool = AtomicBool::new(false);
+
+/// Whether the kill switch is engaged; collectors check it before each round
+pub fn is_dormant() -> bool {
+    DORMANT.load(Ordering::Relaxed)
+}
+
+/// Engage the kill switch and stop hooked collectors, returning `false` if
+/// the probe was already dormant
+pub fn engage() -> bool {
+    if DORMANT.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let hooks = super::shutdown::run_hooks();
+    log::warn!("kill switch engaged, {hooks} collectors stopped");
+    super::events::publish(
+        "probe.dormant",
+        "killswitch",
+        &format!("{hooks} collectors stopped"),
+    );
+    true
+}
+
+/// Leave the dormant state, returning `false` if the probe was not dormant.
+/// Collectors stopped by [`engage`] stay stopped until configured again.
+pub fn release() -> bool {
+    let released = DORMANT.swap(false, Ordering::SeqCst);
+    if released {
+        log::warn!("kill switch released");
+        super::events::publish("probe.resumed", "killswitch", "");
+    }
+    released
+}
@@ -0,0 +1,476 @@
+//! Masking of sensitive columns in query output.
+//!
+//! `mask(text)`, `hash(text)` and `last4(text)` can be called from any
+//! query. Masking rules apply them to the columns of a table for every query,
+//! optionally only to the rows whose key column matches a case-insensitive
+//! regex:
+//!
+//! ```sql
+//! SET probing.masking.rule.envs = 'process.envs.value mask when name ~ secret|token|password|key';
+//! SET probing.masking.rule.envs_diff = 'process.envs_diff.initial,current hash when name ~ secret';
+//! ```
+//!
+//! Rules are enforced while the query is planned, by reading the table
+//! through a projection masking its columns, so filters, joins, subqueries
+//! and views all see masked values. Columns other than text are replaced by
+//! NULL. A rule cannot be changed or removed once set, so that a client
+//! cannot lift it; rules meant to be permanent are best given as
+//! `PROBING_MASKING_RULE_<NAME>` when the probe starts.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use datafusion::arrow::array::StringArray;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::cast::as_string_array;
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{Column, ScalarValue};
+use datafusion::config::ConfigOptions;
+use datafusion::error::Result;
+use datafusion::logical_expr::expr::{Case, Cast, ScalarFunction};
+use datafusion::logical_expr::{
+    create_udf, lit, BinaryExpr, ColumnarValue, Expr, LogicalPlan, Operator, Projection, ScalarUDF,
+    Volatility,
+};
+use datafusion::optimizer::AnalyzerRule;
+use once_cell::sync::Lazy;
+
+use super::{EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption};
+
+/// Option prefix of the rules, `masking.rule.<name>`
+const RULE_PREFIX: &str = "rule.";
+
+/// Characters left visible by `last4`
+const VISIBLE: usize = 4;
+
+static RULES: Lazy<Mutex<BTreeMap<String, MaskRule>>> = Lazy::new(Default::default);
+
+/// Masking function applied by a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskFunction {
+    Mask,
+    Hash,
+    Last4,
+}
+
+impl MaskFunction {
+    fn name(&self) -> &'static str {
+        match self {
+            MaskFunction::Mask => "mask",
+            MaskFunction::Hash => "hash",
+            MaskFunction::Last4 => "last4",
+        }
+    }
+
+    fn apply(&self, text: &str) -> String {
+        match self {
+            MaskFunction::Mask => "*".repeat(text.chars().count()),
+            MaskFunction::Hash => format!("{:016x}", fnv1a(text.as_bytes())),
+            MaskFunction::Last4 => {
+                let hidden = text.chars().count().saturating_sub(VISIBLE);
+                text.chars()
+                    .enumerate()
+                    .map(|(i, c)| if i < hidden { '*' } else { c })
+                    .collect()
+            }
+        }
+    }
+
+    fn udf(&self) -> ScalarUDF {
+        let function = *self;
+        create_udf(
+            self.name(),
+            vec![DataType::Utf8],
+            DataType::Utf8,
+            Volatility::Immutable,
+            Arc::new(move |args: &[ColumnarValue]| {
+                let args = ColumnarValue::values_to_arrays(args)?;
+                let masked = as_string_array(&args[0])?
+                    .iter()
+                    .map(|text| text.map(|text| function.apply(text)))
+                    .collect::<StringArray>();
+                Ok(ColumnarValue::Array(Arc::new(masked)))
+            }),
+        )
+    }
+}
+
+/// 64-bit FNV-1a, stable across builds so that hashed values can be joined
+/// between probes
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// `mask(text)`: every character replaced by `*`
+pub fn mask() -> ScalarUDF {
+    MaskFunction::Mask.udf()
+}
+
+/// `hash(text)`: 16 hex digits identifying the text without revealing it
+pub fn hash() -> ScalarUDF {
+    MaskFunction::Hash.udf()
+}
+
+/// `last4(text)`: all but the last four characters replaced by `*`
+pub fn last4() -> ScalarUDF {
+    MaskFunction::Last4.udf()
+}
+
+/// `<namespace>.<table>.<column>[,<column>...] <function> [when <column> ~ <regex>]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskRule {
+    pub namespace: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub function: MaskFunction,
+    /// Key column and the regex its value must match for a row to be masked
+    pub when: Option<(String, String)>,
+}
+
+impl FromStr for MaskRule {
+    type Err = String;
+
+    fn from_str(spec: &str) -> std::result::Result<Self, Self::Err> {
+        let spec = spec.trim();
+        let (target, rest) = spec
+            .split_once(char::is_whitespace)
+            .ok_or("expected `<namespace>.<table>.<column> <function>`")?;
+        let (table, columns) = target
+            .rsplit_once('.')
+            .ok_or("expected `<namespace>.<table>.<column>`")?;
+        let (namespace, table) = table
+            .split_once('.')
+            .ok_or("expected `<namespace>.<table>.<column>`")?;
+        let columns = columns
+            .split(',')
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+        if columns.is_empty() {
+            return Err("missing column".to_string());
+        }
+
+        let rest = rest.trim();
+        let (function, when) = match rest.split_once(char::is_whitespace) {
+            Some((function, when)) => (function, Some(when.trim())),
+            None => (rest, None),
+        };
+        let function = match function {
+            "mask" => MaskFunction::Mask,
+            "hash" => MaskFunction::Hash,
+            "last4" => MaskFunction::Last4,
+            _ => {
+                return Err(format!(
+                    "unknown function `{function}`, use mask, hash or last4"
+                ))
+            }
+        };
+        let when = match when {
+            None => None,
+            Some(when) => {
+                let condition = when
+                    .strip_prefix("when ")
+                    .ok_or("expected `when <column> ~ <regex>`")?;
+                let (column, regex) = condition
+                    .split_once('~')
+                    .ok_or("expected `when <column> ~ <regex>`")?;
+                let regex = regex.trim().trim_matches('\'');
+                if column.trim().is_empty() || regex.is_empty() {
+                    return Err("expected `when <column> ~ <regex>`".to_string());
+                }
+                Some((column.trim().to_string(), regex.to_string()))
+            }
+        };
+        Ok(MaskRule {
+            namespace: namespace.to_string(),
+            table: table.to_string(),
+            columns,
+            function,
+            when,
+        })
+    }
+}
+
+impl Display for MaskRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{} {}",
+            self.namespace,
+            self.table,
+            self.columns.join(","),
+            self.function.name()
+        )?;
+        if let Some((column, regex)) = &self.when {
+            write!(f, " when {column} ~ {regex}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Rule names from `PROBING_MASKING_RULE_<NAME>` come with dots
+fn rule_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/// Add rule `name`; a rule cannot be replaced or removed once set
+pub fn set_rule(name: &str, spec: &str) -> std::result::Result<(), String> {
+    let name = rule_name(name);
+    if name.is_empty() {
+        return Err("missing rule name".to_string());
+    }
+    let rule = spec.parse::<MaskRule>()?;
+    let mut rules = RULES.lock().unwrap();
+    match rules.get(&name) {
+        Some(existing) if *existing == rule => Ok(()),
+        Some(_) => Err(format!("masking rule `{name}` is already set")),
+        None => {
+            rules.insert(name, rule);
+            Ok(())
+        }
+    }
+}
+
+fn rule(name: &str) -> Option<String> {
+    let rules = RULES.lock().unwrap();
+    rules.get(&rule_name(name)).map(|rule| rule.to_string())
+}
+
+fn utf8(expr: Expr, dtype: &DataType) -> Expr {
+    match dtype {
+        DataType::Utf8 => expr,
+        _ => Expr::Cast(Cast::new(Box::new(expr), DataType::Utf8)),
+    }
+}
+
+/// Projection over `scan` masking the columns of `rules`, or `scan` itself
+/// when no rule applies to it
+fn mask_scan(scan: LogicalPlan, rules: &[MaskRule]) -> Result<Transformed<LogicalPlan>> {
+    let LogicalPlan::TableScan(table_scan) = &scan else {
+        return Ok(Transformed::no(scan));
+    };
+    let name = &table_scan.table_name;
+    let rules = rules
+        .iter()
+        .filter(|r| r.table == name.table() && name.schema().is_none_or(|s| s == r.namespace))
+        .collect::<Vec<_>>();
+    let schema = scan.schema().clone();
+    let column = |column: &str| {
+        let (qualifier, field) = schema.qualified_field_with_unqualified_name(column).ok()?;
+        Some((
+            Expr::Column(Column::new(qualifier.cloned(), column)),
+            field.data_type().clone(),
+        ))
+    };
+
+    let mut masked = false;
+    let mut exprs = vec![];
+    for (qualifier, field) in schema.iter() {
+        let value = Expr::Column(Column::new(qualifier.cloned(), field.name()));
+        let rule = rules.iter().find(|r| r.columns.contains(field.name()));
+        let Some(rule) = rule else {
+            exprs.push(value);
+            continue;
+        };
+        let masking = match field.data_type() {
+            DataType::Utf8 => Expr::ScalarFunction(ScalarFunction::new_udf(
+                Arc::new(rule.function.udf()),
+                vec![value.clone()],
+            )),
+            // masked text would not have the type the query was planned with
+            dtype => Expr::Literal(ScalarValue::try_from(dtype)?),
+        };
+        let expr = match &rule.when {
+            None => masking,
+            Some((key, regex)) => {
+                // an unknown key column masks every row
+                let Some((key, dtype)) = column(key) else {
+                    exprs.push(masking.alias_qualified(qualifier.cloned(), field.name()));
+                    masked = true;
+                    continue;
+                };
+                let condition = Expr::BinaryExpr(BinaryExpr::new(
+                    Box::new(utf8(key, &dtype)),
+                    Operator::RegexIMatch,
+                    Box::new(lit(regex.clone())),
+                ));
+                Expr::Case(Case::new(
+                    None,
+                    vec![(Box::new(condition), Box::new(masking))],
+                    Some(Box::new(value)),
+                ))
+            }
+        };
+        exprs.push(expr.alias_qualified(qualifier.cloned(), field.name()));
+        masked = true;
+    }
+    if !masked {
+        return Ok(Transformed::no(scan));
+    }
+    let projection = Projection::try_new(exprs, Arc::new(scan))?;
+    Ok(Transformed::yes(LogicalPlan::Projection(projection)))
+}
+
+/// Applies the masking rules to every table read by a query
+#[derive(Debug, Default)]
+pub struct MaskingRule {}
+
+impl AnalyzerRule for MaskingRule {
+    fn analyze(&self, plan: LogicalPlan, _config: &ConfigOptions) -> Result<LogicalPlan> {
+        let rules = RULES.lock().unwrap().values().cloned().collect::<Vec<_>>();
+        if rules.is_empty() {
+            return Ok(plan);
+        }
+        plan.transform_up_with_subqueries(|plan| mask_scan(plan, &rules))
+            .map(|transformed| transformed.data)
+    }
+
+    fn name(&self) -> &str {
+        "masking"
+    }
+}
+
+/// Masking rules, see the module documentation
+#[derive(Debug, Default)]
+pub struct MaskingExtension {}
+
+impl EngineCall for MaskingExtension {}
+
+impl EngineDatasource for MaskingExtension {}
+
+impl EngineExtension for MaskingExtension {
+    fn name(&self) -> String {
+        "masking".to_string()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> std::result::Result<String, EngineError> {
+        let Some(name) = key.strip_prefix(RULE_PREFIX) else {
+            return Err(EngineError::UnsupportedOption(key.to_string()));
+        };
+        let old = rule(name).unwrap_or_default();
+        set_rule(name, value).map_err(|err| {
+            EngineError::InvalidOptionValue(key.to_string(), format!("{value}: {err}"))
+        })?;
+        Ok(old)
+    }
+
+    fn get(&self, key: &str) -> std::result::Result<String, EngineError> {
+        key.strip_prefix(RULE_PREFIX)
+            .and_then(rule)
+            .ok_or_else(|| EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn options(&self) -> Vec<EngineExtensionOption> {
+        let rules = RULES.lock().unwrap();
+        rules
+            .iter()
+            .map(|(name, rule)| EngineExtensionOption {
+                key: format!("masking.{RULE_PREFIX}{name}"),
+                value: Some(rule.to_string()),
+                help: "Masking rule `<namespace>.<table>.<column>[,...] <mask|hash|last4> [when <column> ~ <regex>]`, fixed once set.\nENV[PROBING_MASKING_RULE_<NAME>]",
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::{Array, ArrayRef, RecordBatch};
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    #[test]
+    fn test_mask_functions() {
+        assert_eq!(MaskFunction::Mask.apply("abc"), "***");
+        assert_eq!(
+            MaskFunction::Last4.apply("4111111111111111"),
+            "************1111"
+        );
+        assert_eq!(MaskFunction::Last4.apply("abc"), "abc");
+        let hash = MaskFunction::Hash.apply("token");
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, MaskFunction::Hash.apply("token"));
+        assert_ne!(hash, MaskFunction::Hash.apply("tokens"));
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = "process.envs.value mask when name ~ secret|token"
+            .parse::<MaskRule>()
+            .unwrap();
+        assert_eq!(rule.namespace, "process");
+        assert_eq!(rule.table, "envs");
+        assert_eq!(rule.columns, vec!["value"]);
+        assert_eq!(rule.function, MaskFunction::Mask);
+        assert_eq!(
+            rule.when,
+            Some(("name".to_string(), "secret|token".to_string()))
+        );
+        assert_eq!(
+            rule.to_string().parse::<MaskRule>().unwrap(),
+            rule,
+            "a rule prints as its spec"
+        );
+
+        let rule = "process.envs_diff.initial,current hash"
+            .parse::<MaskRule>()
+            .unwrap();
+        assert_eq!(rule.columns, vec!["initial", "current"]);
+        assert_eq!(rule.when, None);
+
+        assert!("process.envs.value scramble".parse::<MaskRule>().is_err());
+        assert!("envs mask".parse::<MaskRule>().is_err());
+        assert!("process.envs.value mask if name"
+            .parse::<MaskRule>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_masking_rule() {
+        let spec = "test.masked_envs.value mask when name ~ secret";
+        set_rule("masked_envs", spec).unwrap();
+        set_rule("masked_envs", spec).unwrap();
+        assert!(set_rule("masked_envs", "test.masked_envs.value hash").is_err());
+
+        let ctx = SessionContext::new();
+        ctx.add_analyzer_rule(Arc::new(MaskingRule::default()));
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "name",
+                Arc::new(StringArray::from(vec!["HOME", "API_SECRET"])) as ArrayRef,
+            ),
+            (
+                "value",
+                Arc::new(StringArray::from(vec!["/root", "hunter2"])) as ArrayRef,
+            ),
+        ])
+        .unwrap();
+        ctx.register_batch("masked_envs", batch).unwrap();
+
+        let query = |sql: &'static str| {
+            let ctx = ctx.clone();
+            async move { ctx.sql(sql).await.unwrap().collect().await.unwrap() }
+        };
+        let batches = query("SELECT value FROM masked_envs ORDER BY name").await;
+        let values = as_string_array(batches[0].column(0)).unwrap();
+        assert_eq!(values.value(0), "*******");
+        assert_eq!(values.value(1), "/root");
+
+        // the secret cannot be probed for through a filter or a subquery
+        let batches =
+            query("SELECT name FROM (SELECT * FROM masked_envs) WHERE value = 'hunter2'").await;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+        let batches = query("SELECT max(value) FROM masked_envs WHERE name = 'API_SECRET'").await;
+        assert!(!batches[0].column(0).is_null(0));
+        assert_eq!(
+            as_string_array(batches[0].column(0)).unwrap().value(0),
+            "*******"
+        );
+    }
+}
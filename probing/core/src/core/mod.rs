@@ -1,9 +1,22 @@
+pub mod activity;
 pub mod cluster;
 pub mod cluster_model;
+pub mod dylib;
 mod engine;
 mod error;
+pub mod events;
 pub mod extension;
+pub mod frameworks;
+pub mod functions;
+pub mod hot_metrics;
+pub mod k8s;
+pub mod killswitch;
+pub mod masking;
+pub mod ordering;
 mod plugin;
+pub mod scheduler;
+pub mod shutdown;
+mod timeseries;
 
 pub use engine::Engine;
 pub use engine::EngineBuilder;
@@ -13,12 +26,17 @@ pub use engine::PluginType;
 pub use error::EngineError;
 pub use error::Result;
 
+pub use plugin::column_docs;
 pub use plugin::CustomNamespace;
 pub use plugin::CustomTable;
+pub use plugin::FieldDoc;
 pub use plugin::LazyTableSource;
 pub use plugin::NamespacePluginHelper;
 pub use plugin::TablePluginHelper;
 
+pub use timeseries::time_series_to_recordbatch;
+pub use timeseries::TimeSeriesSource;
+
 pub use extension::EngineCall;
 pub use extension::EngineDatasource;
 pub use extension::EngineExtension;
@@ -29,6 +47,7 @@ pub use extension::Maybe;
 pub use probing_macros::EngineExtension;
 
 pub use datafusion::arrow::array::ArrayRef;
+pub use datafusion::arrow::array::BooleanArray;
 pub use datafusion::arrow::array::Float32Array;
 pub use datafusion::arrow::array::Float64Array;
 pub use datafusion::arrow::array::Int32Array;
@@ -41,6 +60,7 @@ pub use datafusion::arrow::datatypes::Schema;
 pub use datafusion::arrow::datatypes::SchemaRef;
 pub use datafusion::arrow::datatypes::TimeUnit;
 pub use datafusion::arrow::util::pretty;
+pub use datafusion::catalog::TableProvider;
 pub use datafusion::common::error::DataFusionError;
 pub use datafusion::config::CatalogOptions;
 
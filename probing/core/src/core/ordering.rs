@@ -0,0 +1,210 @@
+//! Default ordering of the append-only tables.
+//!
+//! Logs and time series are kept in the order their rows were appended, but a
+//! query has no order unless it says so: DataFusion may split a scan across
+//! partitions and return the rows in any order. A table declares the column
+//! its rows are kept in with [`with_ordering`], and [`DefaultOrderingRule`]
+//! sorts on it the queries reading the table without `ORDER BY`:
+//!
+//! ```sql
+//! SELECT * FROM server.logs WHERE level = 'WARN' LIMIT 100
+//! -- runs as
+//! SELECT * FROM server.logs WHERE level = 'WARN' ORDER BY seq LIMIT 100
+//! ```
+//!
+//! When the column is a `seq` numbering the rows from 0, unique and never
+//! reused, it is also a cursor: `WHERE seq > <last seen>` reads the next page
+//! without skipping or repeating rows, however many rows were appended or
+//! dropped from the front in between, which `OFFSET` cannot promise.
+
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::Schema;
+use datafusion::common::Column;
+use datafusion::config::ConfigOptions;
+use datafusion::error::Result;
+use datafusion::logical_expr::{Expr, LogicalPlan, Projection, Sort, SortExpr};
+use datafusion::optimizer::AnalyzerRule;
+
+/// Schema metadata naming the column the rows of a table are kept in
+pub const ORDERING_KEY: &str = "probing.ordering";
+
+/// Column numbering the rows of the log tables in append order
+pub const SEQ: &str = "seq";
+
+/// `schema` declaring its rows to be kept in the order of `column`
+pub fn with_ordering(schema: Schema, column: &str) -> Schema {
+    let mut metadata = schema.metadata().clone();
+    metadata.insert(ORDERING_KEY.to_string(), column.to_string());
+    schema.with_metadata(metadata)
+}
+
+/// Column the rows of a table with `schema` are kept in, if declared
+pub fn ordering(schema: &Schema) -> Option<&str> {
+    schema.metadata().get(ORDERING_KEY).map(|c| c.as_str())
+}
+
+/// Sequence number of the first of `len` rows kept out of `appended` ever
+/// appended, for tables dropping their oldest rows
+pub fn first_seq(appended: u64, len: usize) -> i64 {
+    appended.saturating_sub(len as u64) as i64
+}
+
+/// Column the rows read by `plan` come in, when it reads one ordered table
+/// without joining, grouping or sorting its rows
+fn scan_ordering(plan: &LogicalPlan) -> Option<String> {
+    match plan {
+        LogicalPlan::TableScan(scan) => ordering(&scan.source.schema()).map(|c| c.to_string()),
+        LogicalPlan::Filter(filter) => scan_ordering(&filter.input),
+        LogicalPlan::SubqueryAlias(alias) => scan_ordering(&alias.input),
+        // the projection masking columns keeps every column of the scan
+        LogicalPlan::Projection(projection) => match projection.input.as_ref() {
+            LogicalPlan::TableScan(_) => scan_ordering(&projection.input),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `input` sorted on `column`, if `input` has it
+fn sorted(input: Arc<LogicalPlan>, column: &str) -> Option<LogicalPlan> {
+    let (qualifier, field) = input
+        .schema()
+        .qualified_field_with_unqualified_name(column)
+        .ok()?;
+    let expr = Expr::Column(Column::from((qualifier, field)));
+    Some(LogicalPlan::Sort(Sort {
+        expr: vec![SortExpr::new(expr, true, false)],
+        input,
+        fetch: None,
+    }))
+}
+
+/// `plan` with its rows sorted on the ordering of the table it reads, below
+/// its limits and outermost projection so that those see the ordered rows
+fn order(plan: LogicalPlan) -> Option<LogicalPlan> {
+    match plan {
+        LogicalPlan::Limit(mut limit) => {
+            let input = Arc::unwrap_or_clone(limit.input);
+            limit.input = Arc::new(order(input)?);
+            Some(LogicalPlan::Limit(limit))
+        }
+        LogicalPlan::Projection(projection) => {
+            let column = scan_ordering(&projection.input)?;
+            let input = sorted(projection.input.clone(), &column)?;
+            let projection = Projection::try_new(projection.expr, Arc::new(input)).ok()?;
+            Some(LogicalPlan::Projection(projection))
+        }
+        plan => {
+            let column = scan_ordering(&plan)?;
+            sorted(Arc::new(plan), &column)
+        }
+    }
+}
+
+/// Sorts the queries reading an ordered table on its ordering column when
+/// they have no `ORDER BY` of their own
+#[derive(Debug, Default)]
+pub struct DefaultOrderingRule {}
+
+impl AnalyzerRule for DefaultOrderingRule {
+    fn analyze(&self, plan: LogicalPlan, _config: &ConfigOptions) -> Result<LogicalPlan> {
+        Ok(order(plan.clone()).unwrap_or(plan))
+    }
+
+    fn name(&self) -> &str {
+        "default_ordering"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::{Int64Array, RecordBatch, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field};
+    use datafusion::datasource::MemTable;
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    /// Rows appended with seq 0 to 5, stored out of order in two partitions
+    fn context() -> SessionContext {
+        let schema = Arc::new(with_ordering(
+            Schema::new(vec![
+                Field::new(SEQ, DataType::Int64, false),
+                Field::new("message", DataType::Utf8, false),
+            ]),
+            SEQ,
+        ));
+        let batch = |seqs: Vec<i64>| {
+            let messages = seqs.iter().map(|s| format!("m{s}")).collect::<Vec<_>>();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(seqs)),
+                    Arc::new(StringArray::from(messages)),
+                ],
+            )
+            .unwrap()
+        };
+        let table = MemTable::try_new(
+            schema.clone(),
+            vec![vec![batch(vec![3, 5, 4])], vec![batch(vec![1, 0, 2])]],
+        )
+        .unwrap();
+        let ctx = SessionContext::new();
+        ctx.add_analyzer_rule(Arc::new(DefaultOrderingRule::default()));
+        ctx.register_table("logs", Arc::new(table)).unwrap();
+        ctx
+    }
+
+    async fn messages(ctx: &SessionContext, sql: &str) -> Vec<String> {
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        batches
+            .iter()
+            .flat_map(|b| {
+                let col = b.column_by_name("message").unwrap();
+                let col = col.as_any().downcast_ref::<StringArray>().unwrap();
+                col.iter()
+                    .map(|v| v.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_first_seq() {
+        assert_eq!(first_seq(5, 5), 0);
+        assert_eq!(first_seq(1030, 1024), 6);
+        assert_eq!(first_seq(0, 0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_default_ordering() {
+        let ctx = context();
+        assert_eq!(
+            messages(&ctx, "SELECT message FROM logs").await,
+            ["m0", "m1", "m2", "m3", "m4", "m5"]
+        );
+        assert_eq!(
+            messages(&ctx, "SELECT * FROM logs WHERE seq > 1 LIMIT 2").await,
+            ["m2", "m3"]
+        );
+        assert_eq!(
+            messages(&ctx, "SELECT message FROM logs l LIMIT 2 OFFSET 3").await,
+            ["m3", "m4"]
+        );
+        // an explicit order is kept
+        assert_eq!(
+            messages(&ctx, "SELECT message FROM logs ORDER BY seq DESC LIMIT 2").await,
+            ["m5", "m4"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unordered_plans() {
+        let ctx = context();
+        let sql = "SELECT count(*) FROM logs";
+        let plan = ctx.sql(sql).await.unwrap().into_optimized_plan().unwrap();
+        assert!(!format!("{plan}").contains("Sort"));
+    }
+}
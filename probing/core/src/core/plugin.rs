@@ -13,6 +13,57 @@ use datafusion::error::{DataFusionError, Result};
 use datafusion::execution::SessionState;
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion::prelude::Expr;
+use probing_proto::prelude::ColumnDoc;
+
+/// Field metadata key of the human readable description of a column
+pub const FIELD_DESCRIPTION: &str = "description";
+
+/// Field metadata key of the unit of a column, e.g. `bytes` or `us`
+pub const FIELD_UNIT: &str = "unit";
+
+/// Documentation of table columns, kept in the arrow field metadata and
+/// reported by `DESCRIBE <table>`
+///
+/// ```
+/// use probing_core::core::{DataType, Field, FieldDoc};
+///
+/// let field = Field::new("rss", DataType::Int64, false)
+///     .with_doc("Resident set size")
+///     .with_unit("bytes");
+/// ```
+pub trait FieldDoc {
+    fn with_doc(self, description: &str) -> Self;
+    fn with_unit(self, unit: &str) -> Self;
+}
+
+impl FieldDoc for Field {
+    fn with_doc(self, description: &str) -> Self {
+        let mut metadata = self.metadata().clone();
+        metadata.insert(FIELD_DESCRIPTION.to_string(), description.to_string());
+        self.with_metadata(metadata)
+    }
+
+    fn with_unit(self, unit: &str) -> Self {
+        let mut metadata = self.metadata().clone();
+        metadata.insert(FIELD_UNIT.to_string(), unit.to_string());
+        self.with_metadata(metadata)
+    }
+}
+
+/// Columns of `schema` with their documentation
+pub fn column_docs(schema: &Schema) -> Vec<ColumnDoc> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| ColumnDoc {
+            name: field.name().clone(),
+            data_type: field.data_type().to_string(),
+            nullable: field.is_nullable(),
+            unit: field.metadata().get(FIELD_UNIT).cloned(),
+            description: field.metadata().get(FIELD_DESCRIPTION).cloned(),
+        })
+        .collect()
+}
 
 /// Trait defining a custom table with static/dynamic schema and data
 ///
@@ -28,6 +79,29 @@ pub trait CustomTable {
 
     /// Provides the data batches
     fn data() -> Vec<RecordBatch>;
+
+    /// Human readable description of the table, listed by `/apis/tables`
+    fn description() -> &'static str {
+        ""
+    }
+
+    /// Column the rows are appended in the order of, see [`super::ordering`].
+    /// Queries without `ORDER BY` return the rows in this order, and a `seq`
+    /// column also serves as the cursor of `/apis/tables/<table>?since=`.
+    fn ordering() -> Option<&'static str> {
+        None
+    }
+}
+
+/// Schema of `T`, declaring the ordering of its rows if it has one
+fn table_schema<T: CustomTable>() -> SchemaRef {
+    match T::ordering() {
+        Some(column) => SchemaRef::new(super::ordering::with_ordering(
+            T::schema().as_ref().clone(),
+            column,
+        )),
+        None => T::schema(),
+    }
 }
 
 /// Helper struct that bridges a CustomTable implementation with the Plugin system.
@@ -87,6 +161,10 @@ impl<T: CustomTable + Default + Debug + Send + Sync + 'static> Plugin for TableP
         self.namespace.clone()
     }
 
+    fn description(&self) -> Option<String> {
+        Some(T::description().to_string()).filter(|d| !d.is_empty())
+    }
+
     /// Registers this table with the provided schema provider
     /// Links the CustomTable implementation with DataFusion's query engine
     fn register_table(
@@ -113,7 +191,7 @@ impl<T: CustomTable + Default + Debug + Send + Sync + 'static> TableProvider
     }
 
     fn schema(&self) -> SchemaRef {
-        T::schema()
+        table_schema::<T>()
     }
 
     fn table_type(&self) -> TableType {
@@ -128,8 +206,15 @@ impl<T: CustomTable + Default + Debug + Send + Sync + 'static> TableProvider
         _filters: &[Expr],
         _limit: Option<usize>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        let data = T::data();
-        let srccfg = MemorySourceConfig::try_new(&[data], T::schema(), projection.cloned())?;
+        let schema = table_schema::<T>();
+        let mut data = T::data();
+        if T::ordering().is_some() {
+            data = data
+                .into_iter()
+                .map(|batch| batch.with_schema(schema.clone()))
+                .collect::<std::result::Result<_, _>>()?;
+        }
+        let srccfg = MemorySourceConfig::try_new(&[data], schema, projection.cloned())?;
         let exec = DataSourceExec::new(Arc::new(srccfg));
         Ok(Arc::new(exec))
     }
@@ -0,0 +1,633 @@
+//! Periodic tasks run by the probe itself, replacing an external cron that
+//! polls the HTTP API.
+//!
+//! A task pairs an interval with an action and is registered with an option,
+//! or the matching `PROBING_SCHEDULER_TASK_<NAME>` environment variable:
+//!
+//! ```sql
+//! SET probing.scheduler.task.gpu_mem = '60s SELECT * FROM torch.cuda_memory';
+//! SET probing.scheduler.task.profile = '10m @flamegraph';
+//! SET probing.scheduler.task.profile = '';  -- remove the task
+//! ```
+//!
+//! An action is either a SQL statement, whose last `scheduler.history` results
+//! are kept as the table `scheduler.<name>`, or `@<action> [args]` naming an
+//! action registered by an extension with [`register_action`], such as the
+//! `@flamegraph` of the server. `scheduler.tasks` lists every task with its
+//! last run. Tasks run one at a time on the `probing-scheduler` thread, so a
+//! slow action delays the others instead of piling up.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::TableProvider;
+use once_cell::sync::Lazy;
+
+use super::activity::with_actor;
+use super::{
+    CustomNamespace, EngineCall, EngineDatasource, EngineError, EngineExtension,
+    EngineExtensionOption, FieldDoc, LazyTableSource, NamespacePluginHelper, Plugin,
+};
+
+const DEFAULT_HISTORY: usize = 16;
+
+/// Shortest interval accepted, tasks are not meant to be samplers
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Option prefix of the tasks, `scheduler.task.<name>`
+const TASK_PREFIX: &str = "task.";
+
+/// Action registered by an extension, called with the arguments following its
+/// name and returning a short summary of what it did
+pub type ActionFn = Arc<dyn Fn(&str) -> Result<String> + Send + Sync>;
+
+static ACTIONS: Lazy<RwLock<HashMap<String, ActionFn>>> = Lazy::new(Default::default);
+static TASKS: Lazy<Mutex<BTreeMap<String, Task>>> = Lazy::new(Default::default);
+static HISTORY: AtomicUsize = AtomicUsize::new(DEFAULT_HISTORY);
+static WORKER: Lazy<Mutex<Option<std::thread::Thread>>> = Lazy::new(Default::default);
+
+/// Make `@<name>` available to tasks
+pub fn register_action(name: &str, action: ActionFn) {
+    ACTIONS.write().unwrap().insert(name.to_string(), action);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskAction {
+    Sql(String),
+    Call { name: String, args: String },
+}
+
+/// Interval and action of a task, written `<interval> <action>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSpec {
+    pub interval: Duration,
+    pub action: TaskAction,
+}
+
+fn parse_interval(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (number, unit) = value.split_at(split.unwrap_or(value.len()));
+    let scale = match unit {
+        "" | "s" => 1.0,
+        "ms" => 1e-3,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number.parse::<f64>().ok()? * scale).ok()
+}
+
+fn format_interval(interval: Duration) -> String {
+    let secs = interval.as_secs_f64();
+    match secs {
+        s if s >= 3600.0 && s % 3600.0 == 0.0 => format!("{}h", s / 3600.0),
+        s if s >= 60.0 && s % 60.0 == 0.0 => format!("{}m", s / 60.0),
+        s => format!("{s}s"),
+    }
+}
+
+impl FromStr for TaskAction {
+    type Err = String;
+
+    /// `@<action> [args]` or a SQL statement
+    fn from_str(action: &str) -> Result<Self, Self::Err> {
+        let action = action.trim();
+        if action.is_empty() {
+            return Err("missing action".to_string());
+        }
+        Ok(match action.strip_prefix('@') {
+            Some(call) => {
+                let (name, args) = call.split_once(char::is_whitespace).unwrap_or((call, ""));
+                TaskAction::Call {
+                    name: name.to_string(),
+                    args: args.trim().to_string(),
+                }
+            }
+            None => TaskAction::Sql(action.trim_end_matches(';').to_string()),
+        })
+    }
+}
+
+impl FromStr for TaskSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (interval, action) = value
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected `<interval> <action>`, got `{value}`"))?;
+        let interval =
+            parse_interval(interval).ok_or_else(|| format!("invalid interval `{interval}`"))?;
+        if interval < MIN_INTERVAL {
+            return Err(format!("interval below {}", format_interval(MIN_INTERVAL)));
+        }
+        Ok(TaskSpec {
+            interval,
+            action: action.parse()?,
+        })
+    }
+}
+
+impl Display for TaskAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskAction::Sql(sql) => write!(f, "{sql}"),
+            TaskAction::Call { name, args } if args.is_empty() => write!(f, "@{name}"),
+            TaskAction::Call { name, args } => write!(f, "@{name} {args}"),
+        }
+    }
+}
+
+impl Display for TaskSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", format_interval(self.interval), self.action)
+    }
+}
+
+struct Task {
+    spec: TaskSpec,
+    next_run: Instant,
+    runs: u64,
+    failures: u64,
+    /// Start of the last run in microseconds since epoch
+    last_run: Option<i64>,
+    last_duration: Option<Duration>,
+    last_output: Option<String>,
+    last_error: Option<String>,
+    /// Rows returned by the last runs of a SQL action, oldest first
+    history: VecDeque<Vec<RecordBatch>>,
+}
+
+impl Task {
+    fn new(spec: TaskSpec) -> Self {
+        Self {
+            next_run: Instant::now() + spec.interval,
+            spec,
+            runs: 0,
+            failures: 0,
+            last_run: None,
+            last_duration: None,
+            last_output: None,
+            last_error: None,
+            history: Default::default(),
+        }
+    }
+}
+
+fn now_micros() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Result of an action: a summary and, for SQL, the rows it returned
+pub(crate) struct Outcome {
+    pub summary: String,
+    pub batches: Vec<RecordBatch>,
+}
+
+pub(crate) async fn run_action(action: &TaskAction) -> Result<Outcome> {
+    match action {
+        TaskAction::Sql(sql) => {
+            let batches = {
+                let engine = crate::ENGINE.read().await;
+                engine.sql(sql).await?.collect().await?
+            };
+            let rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            Ok(Outcome {
+                summary: format!("{rows} rows"),
+                batches,
+            })
+        }
+        TaskAction::Call { name, args } => {
+            let action = ACTIONS.read().unwrap().get(name).cloned();
+            let action = action.ok_or_else(|| anyhow::anyhow!("unknown action @{name}"))?;
+            Ok(Outcome {
+                summary: action(args)?,
+                batches: vec![],
+            })
+        }
+    }
+}
+
+/// Prefix the rows of a run with the time it started
+fn stamp(batch: &RecordBatch, timestamp: i64) -> Result<RecordBatch> {
+    let mut fields = vec![Arc::new(Field::new("snapshot_ts", DataType::Int64, false))];
+    fields.extend(batch.schema().fields().iter().cloned());
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(vec![
+        timestamp;
+        batch.num_rows()
+    ]))];
+    columns.extend(batch.columns().iter().cloned());
+    Ok(RecordBatch::try_new(
+        SchemaRef::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+fn record(name: &str, started: i64, elapsed: Duration, outcome: Result<Outcome>) {
+    let mut tasks = TASKS.lock().unwrap();
+    // removed or replaced while running
+    let Some(task) = tasks.get_mut(name) else {
+        return;
+    };
+    task.runs += 1;
+    task.last_run = Some(started);
+    task.last_duration = Some(elapsed);
+    match outcome {
+        Ok(outcome) => {
+            task.last_output = Some(outcome.summary);
+            task.last_error = None;
+            let batches = outcome
+                .batches
+                .iter()
+                .map(|batch| stamp(batch, started))
+                .collect::<Result<Vec<_>>>();
+            match batches {
+                Ok(batches) if !batches.is_empty() => {
+                    // a change of schema starts a new history
+                    let schema = batches[0].schema();
+                    task.history.retain(|run| run[0].schema() == schema);
+                    task.history.push_back(batches);
+                    let excess = task
+                        .history
+                        .len()
+                        .saturating_sub(HISTORY.load(Ordering::Relaxed));
+                    task.history.drain(..excess);
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!("cannot keep the result of task {name}: {err}"),
+            }
+        }
+        Err(err) => {
+            log::warn!("scheduled task {name} failed: {err}");
+            task.failures += 1;
+            task.last_error = Some(err.to_string());
+            super::events::publish(
+                "scheduler.task_failed",
+                "scheduler",
+                &format!("{name}: {err}"),
+            );
+        }
+    }
+}
+
+/// Run the tasks that are due, returning how long to wait for the next one
+fn tick(runtime: &tokio::runtime::Runtime) -> Duration {
+    let due = {
+        let mut tasks = TASKS.lock().unwrap();
+        let now = Instant::now();
+        tasks
+            .iter_mut()
+            .filter(|(_, task)| task.next_run <= now)
+            .map(|(name, task)| {
+                // runs late rather than catching up missed ones
+                task.next_run = now + task.spec.interval;
+                (name.clone(), task.spec.action.clone())
+            })
+            .collect::<Vec<_>>()
+    };
+    for (name, action) in due {
+        let started = now_micros();
+        let start = Instant::now();
+        let actor = format!("scheduler:{name}");
+        let outcome = runtime.block_on(with_actor(actor, run_action(&action)));
+        record(&name, started, start.elapsed(), outcome);
+    }
+    TASKS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|task| task.next_run.saturating_duration_since(Instant::now()))
+        .min()
+        .unwrap_or(Duration::from_secs(60))
+}
+
+/// Start the scheduler thread on first use, or wake it up to pick up changes
+fn wake_worker() {
+    let mut worker = WORKER.lock().unwrap();
+    if let Some(thread) = worker.as_ref() {
+        thread.unpark();
+        return;
+    }
+    let spawned = std::thread::Builder::new()
+        .name("probing-scheduler".to_string())
+        .spawn(|| {
+            let runtime = match tokio::runtime::Builder::new_current_thread().build() {
+                Ok(runtime) => runtime,
+                Err(err) => {
+                    log::error!("failed to start the scheduler: {err}");
+                    return;
+                }
+            };
+            loop {
+                let timeout = match super::killswitch::is_dormant() {
+                    true => Duration::from_secs(1),
+                    false => tick(&runtime),
+                };
+                std::thread::park_timeout(timeout);
+            }
+        });
+    match spawned {
+        Ok(handle) => *worker = Some(handle.thread().clone()),
+        Err(err) => log::error!("failed to start the scheduler: {err}"),
+    }
+}
+
+/// Add, replace or, with an empty `spec`, remove task `name`
+pub fn set_task(name: &str, spec: &str) -> Result<(), String> {
+    let name = task_name(name);
+    if name.is_empty() || name == "tasks" {
+        return Err(format!("invalid task name `{name}`"));
+    }
+    if spec.trim().is_empty() {
+        TASKS.lock().unwrap().remove(&name);
+        return Ok(());
+    }
+    let spec = spec.parse::<TaskSpec>()?;
+    TASKS.lock().unwrap().insert(name, Task::new(spec));
+    wake_worker();
+    Ok(())
+}
+
+/// Spec of task `name`, if registered
+pub fn task(name: &str) -> Option<String> {
+    let tasks = TASKS.lock().unwrap();
+    tasks
+        .get(&task_name(name))
+        .map(|task| task.spec.to_string())
+}
+
+fn tasks_schema() -> SchemaRef {
+    SchemaRef::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("interval", DataType::Float64, false).with_unit("s"),
+        Field::new("action", DataType::Utf8, false),
+        Field::new("runs", DataType::Int64, false),
+        Field::new("failures", DataType::Int64, false),
+        Field::new("last_run", DataType::Int64, true)
+            .with_unit("us")
+            .with_doc("Start of the last run since epoch"),
+        Field::new("last_duration", DataType::Float64, true).with_unit("ms"),
+        Field::new("next_run", DataType::Float64, false)
+            .with_unit("s")
+            .with_doc("Time left until the next run"),
+        Field::new("last_output", DataType::Utf8, true)
+            .with_doc("Summary of the last successful run, e.g. rows returned"),
+        Field::new("last_error", DataType::Utf8, true)
+            .with_doc("Error of the last run, cleared by a successful one"),
+    ]))
+}
+
+/// Task names are used as table names, `gpu.mem` (e.g. from
+/// `PROBING_SCHEDULER_TASK_GPU_MEM`) becomes `gpu_mem`
+fn task_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+fn tasks_data() -> Result<RecordBatch> {
+    let tasks = TASKS.lock().unwrap();
+    let now = Instant::now();
+    let tasks = tasks.iter().collect::<Vec<_>>();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            tasks.iter().map(|(name, _)| name.as_str()),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            tasks.iter().map(|(_, t)| t.spec.interval.as_secs_f64()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            tasks.iter().map(|(_, t)| t.spec.action.to_string()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            tasks.iter().map(|(_, t)| t.runs as i64),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            tasks.iter().map(|(_, t)| t.failures as i64),
+        )),
+        Arc::new(Int64Array::from(
+            tasks.iter().map(|(_, t)| t.last_run).collect::<Vec<_>>(),
+        )),
+        Arc::new(Float64Array::from(
+            tasks
+                .iter()
+                .map(|(_, t)| t.last_duration.map(|d| d.as_secs_f64() * 1e3))
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(Float64Array::from_iter_values(tasks.iter().map(
+            |(_, t)| t.next_run.saturating_duration_since(now).as_secs_f64(),
+        ))),
+        Arc::new(StringArray::from(
+            tasks
+                .iter()
+                .map(|(_, t)| t.last_output.clone())
+                .collect::<Vec<_>>(),
+        )),
+        Arc::new(StringArray::from(
+            tasks
+                .iter()
+                .map(|(_, t)| t.last_error.clone())
+                .collect::<Vec<_>>(),
+        )),
+    ];
+    Ok(RecordBatch::try_new(tasks_schema(), columns)?)
+}
+
+/// `scheduler.tasks` and the kept results of every SQL task
+#[derive(Default, Debug)]
+pub struct SchedulerNamespace {}
+
+#[async_trait]
+impl CustomNamespace for SchedulerNamespace {
+    fn name() -> &'static str {
+        "scheduler"
+    }
+
+    fn list() -> Vec<String> {
+        let tasks = TASKS.lock().unwrap();
+        let results = tasks
+            .iter()
+            .filter(|(_, task)| matches!(task.spec.action, TaskAction::Sql(_)))
+            .map(|(name, _)| name.clone());
+        std::iter::once("tasks".to_string())
+            .chain(results)
+            .collect()
+    }
+
+    async fn table(expr: String) -> datafusion::error::Result<Option<Arc<dyn TableProvider>>> {
+        let (schema, data) = if expr == "tasks" {
+            let data = tasks_data().map_err(|e| {
+                datafusion::error::DataFusionError::Execution(format!("scheduler.tasks: {e}"))
+            })?;
+            (Some(tasks_schema()), vec![data])
+        } else {
+            let tasks = TASKS.lock().unwrap();
+            let Some(task) = tasks.get(&expr) else {
+                return Ok(None);
+            };
+            let data = task.history.iter().flatten().cloned().collect::<Vec<_>>();
+            (data.first().map(|b| b.schema()), data)
+        };
+        let table: Arc<dyn TableProvider> = Arc::new(LazyTableSource {
+            name: expr,
+            schema,
+            data,
+        });
+        Ok(Some(table))
+    }
+}
+
+pub type SchedulerPlugin = NamespacePluginHelper<SchedulerNamespace>;
+
+/// Periodic tasks, see the module documentation
+#[derive(Debug, Default)]
+pub struct SchedulerExtension {}
+
+impl EngineCall for SchedulerExtension {}
+
+impl EngineDatasource for SchedulerExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        _name: Option<&str>,
+    ) -> Option<Arc<dyn Plugin + Sync + Send>> {
+        Some(SchedulerPlugin::create(namespace))
+    }
+}
+
+impl EngineExtension for SchedulerExtension {
+    fn name(&self) -> String {
+        "scheduler".to_string()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<String, EngineError> {
+        if key == "history" {
+            let old = HISTORY.load(Ordering::Relaxed).to_string();
+            let history = value
+                .parse::<usize>()
+                .ok()
+                .filter(|h| *h > 0)
+                .ok_or_else(|| {
+                    EngineError::InvalidOptionValue(key.to_string(), value.to_string())
+                })?;
+            HISTORY.store(history, Ordering::Relaxed);
+            return Ok(old);
+        }
+        let Some(name) = key.strip_prefix(TASK_PREFIX) else {
+            return Err(EngineError::UnsupportedOption(key.to_string()));
+        };
+        let old = task(name).unwrap_or_default();
+        set_task(name, value).map_err(|err| {
+            EngineError::InvalidOptionValue(key.to_string(), format!("{value}: {err}"))
+        })?;
+        Ok(old)
+    }
+
+    fn get(&self, key: &str) -> Result<String, EngineError> {
+        if key == "history" {
+            return Ok(HISTORY.load(Ordering::Relaxed).to_string());
+        }
+        key.strip_prefix(TASK_PREFIX)
+            .and_then(task)
+            .ok_or_else(|| EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn options(&self) -> Vec<EngineExtensionOption> {
+        let mut options = vec![EngineExtensionOption {
+            key: "scheduler.history".to_string(),
+            value: Some(HISTORY.load(Ordering::Relaxed).to_string()),
+            help: "Results kept per SQL task as scheduler.<task>.\nENV[PROBING_SCHEDULER_HISTORY]",
+            ..Default::default()
+        }];
+        let tasks = TASKS.lock().unwrap();
+        options.extend(tasks.iter().map(|(name, task)| EngineExtensionOption {
+            key: format!("scheduler.{TASK_PREFIX}{name}"),
+            value: Some(task.spec.to_string()),
+            help: "Periodic task `<interval> <sql or @action>`, empty to remove it.\nENV[PROBING_SCHEDULER_TASK_<NAME>]",
+            ..Default::default()
+        }));
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_task_spec() {
+        let spec = "60s SELECT * FROM torch.cuda_memory;"
+            .parse::<TaskSpec>()
+            .unwrap();
+        assert_eq!(spec.interval, Duration::from_secs(60));
+        assert_eq!(
+            spec.action,
+            TaskAction::Sql("SELECT * FROM torch.cuda_memory".to_string())
+        );
+        assert_eq!(spec.to_string(), "1m SELECT * FROM torch.cuda_memory");
+
+        let spec = "10m @flamegraph torch".parse::<TaskSpec>().unwrap();
+        assert_eq!(spec.interval, Duration::from_secs(600));
+        assert_eq!(
+            spec.action,
+            TaskAction::Call {
+                name: "flamegraph".to_string(),
+                args: "torch".to_string()
+            }
+        );
+        assert_eq!(spec.to_string(), "10m @flamegraph torch");
+
+        assert!("1.5h @flamegraph".parse::<TaskSpec>().is_ok());
+        assert!("100ms SELECT 1".parse::<TaskSpec>().is_err());
+        assert!("soon SELECT 1".parse::<TaskSpec>().is_err());
+        assert!("60s".parse::<TaskSpec>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_actions() {
+        register_action("echo", Arc::new(|args| Ok(format!("echo {args}"))));
+        let outcome = run_action(&"1s @echo hi".parse::<TaskSpec>().unwrap().action)
+            .await
+            .unwrap();
+        assert_eq!(outcome.summary, "echo hi");
+        assert!(
+            run_action(&"1s @missing".parse::<TaskSpec>().unwrap().action)
+                .await
+                .is_err()
+        );
+
+        let action = "1s SELECT 1 AS one".parse::<TaskSpec>().unwrap().action;
+        TASKS.lock().unwrap().insert(
+            "one".to_string(),
+            Task::new("1s SELECT 1 AS one".parse().unwrap()),
+        );
+        HISTORY.store(2, Ordering::Relaxed);
+        for started in 1..=3 {
+            let outcome = run_action(&action).await;
+            record("one", started, Duration::from_millis(1), outcome);
+        }
+        let tasks = TASKS.lock().unwrap();
+        let task = &tasks["one"];
+        assert_eq!(task.runs, 3);
+        assert_eq!(task.last_output.as_deref(), Some("1 rows"));
+        let kept = task
+            .history
+            .iter()
+            .map(|run| {
+                let ts = run[0].column(0).as_any().downcast_ref::<Int64Array>();
+                ts.unwrap().value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kept, [2, 3]);
+        assert_eq!(task.history[0][0].schema().field(1).name(), "one");
+    }
+}
@@ -0,0 +1,65 @@
+//! Hooks executed when the host process exits.
+//!
+//! Collectors that own background threads or buffered data (samplers, time
+//! series writers, ...) register a hook here so that the server can stop
+//! them and flush their data before the process goes away.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+type ShutdownHook = Box<dyn FnOnce() + Send>;
+
+static SHUTDOWN_HOOKS: Lazy<Mutex<Vec<(String, ShutdownHook)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Register a hook to run at shutdown. Hooks run in reverse registration order.
+pub fn register_hook<N, F>(name: N, hook: F)
+where
+    N: Into<String>,
+    F: FnOnce() + Send + 'static,
+{
+    if let Ok(mut hooks) = SHUTDOWN_HOOKS.lock() {
+        hooks.push((name.into(), Box::new(hook)));
+    }
+}
+
+/// Run and drain all registered hooks, returning the number of hooks executed.
+///
+/// A panicking hook is logged and does not prevent the remaining hooks from running.
+pub fn run_hooks() -> usize {
+    let hooks = match SHUTDOWN_HOOKS.lock() {
+        Ok(mut hooks) => std::mem::take(&mut *hooks),
+        Err(_) => return 0,
+    };
+
+    let count = hooks.len();
+    for (name, hook) in hooks.into_iter().rev() {
+        log::debug!("running shutdown hook: {name}");
+        if std::panic::catch_unwind(std::panic::AssertUnwindSafe(hook)).is_err() {
+            log::error!("shutdown hook `{name}` panicked");
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn test_hooks_run_once_in_reverse_order() {
+        let order = Arc::new(Mutex::new(vec![]));
+        for i in 0..3 {
+            let order = order.clone();
+            register_hook(format!("hook-{i}"), move || order.lock().unwrap().push(i));
+        }
+        register_hook("panicking", || panic!("boom"));
+
+        assert!(run_hooks() >= 4);
+        assert_eq!(*order.lock().unwrap(), vec![2, 1, 0]);
+        assert_eq!(run_hooks(), 0);
+    }
+}
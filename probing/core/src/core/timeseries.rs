@@ -0,0 +1,261 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{
+    ArrayRef, Float32Array, Float64Array, Int32Array, Int64Array, RecordBatch, StringArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::ScalarValue;
+use datafusion::datasource::memory::{DataSourceExec, MemorySourceConfig};
+use datafusion::datasource::TableType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{Between, BinaryExpr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::Expr;
+
+use probing_proto::prelude::{Ele, TimeSeries};
+use probing_proto::types::EleType;
+
+use super::ordering::with_ordering;
+
+const TIMESTAMP: &str = "timestamp";
+
+/// A table over an in-memory [`TimeSeries`], with a leading `timestamp`
+/// column.
+///
+/// Filters comparing `timestamp` with a constant are pushed down, and chunks
+/// whose timestamp range cannot satisfy them are skipped without being
+/// decompressed, so `WHERE timestamp > <recent>` only reads the latest
+/// chunks. The filters are still applied to the rows of the chunks read.
+/// Queries without `ORDER BY` return the rows by timestamp.
+#[derive(Debug)]
+pub struct TimeSeriesSource {
+    pub name: String,
+    pub series: Arc<Mutex<TimeSeries>>,
+}
+
+impl TimeSeriesSource {
+    pub fn new<S: Into<String>>(name: S, series: Arc<Mutex<TimeSeries>>) -> Self {
+        Self {
+            name: name.into(),
+            series,
+        }
+    }
+}
+
+fn data_type(dtype: EleType) -> DataType {
+    match dtype {
+        EleType::I64 => DataType::Int64,
+        EleType::F64 => DataType::Float64,
+        EleType::I32 => DataType::Int32,
+        EleType::F32 => DataType::Float32,
+        _ => DataType::Utf8,
+    }
+}
+
+/// Schema of `ts`, its rows kept in the order of their timestamps
+fn time_series_schema(ts: &TimeSeries) -> SchemaRef {
+    let mut fields = vec![Field::new(TIMESTAMP, DataType::Int64, true)];
+    for (name, col) in ts.names.iter().zip(ts.cols.iter()) {
+        fields.push(Field::new(name, data_type(col.dtype()), false));
+    }
+    SchemaRef::new(with_ordering(Schema::new(fields), TIMESTAMP))
+}
+
+pub fn time_series_to_recordbatch(ts: &TimeSeries) -> Result<Vec<RecordBatch>> {
+    let length = ts.len();
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(
+        ts.timestamp
+            .iter()
+            .take(length)
+            .map(|x| match x {
+                Ele::I64(x) => x,
+                _ => 0,
+            })
+            .collect::<Vec<_>>(),
+    ))];
+
+    for col in ts.cols.iter() {
+        let values = col.iter().take(length);
+        let col = match col.dtype() {
+            EleType::I64 => Arc::new(Int64Array::from(
+                values
+                    .map(|x| match x {
+                        Ele::I64(x) => x,
+                        _ => 0,
+                    })
+                    .collect::<Vec<_>>(),
+            )) as ArrayRef,
+            EleType::F64 => Arc::new(Float64Array::from(
+                values
+                    .map(|x| match x {
+                        Ele::F64(x) => x,
+                        _ => 0.0,
+                    })
+                    .collect::<Vec<_>>(),
+            )) as ArrayRef,
+            EleType::I32 => Arc::new(Int32Array::from(
+                values
+                    .map(|x| match x {
+                        Ele::I32(x) => x,
+                        _ => 0,
+                    })
+                    .collect::<Vec<_>>(),
+            )) as ArrayRef,
+            EleType::F32 => Arc::new(Float32Array::from(
+                values
+                    .map(|x| match x {
+                        Ele::F32(x) => x,
+                        _ => 0.0,
+                    })
+                    .collect::<Vec<_>>(),
+            )) as ArrayRef,
+            _ => Arc::new(StringArray::from(
+                values
+                    .map(|x| match x {
+                        Ele::Text(x) => x,
+                        _ => x.to_string(),
+                    })
+                    .collect::<Vec<_>>(),
+            )) as ArrayRef,
+        };
+        columns.push(col);
+    }
+
+    Ok(vec![RecordBatch::try_new(time_series_schema(ts), columns)?])
+}
+
+fn is_timestamp(expr: &Expr) -> bool {
+    matches!(expr, Expr::Column(col) if col.name == TIMESTAMP)
+}
+
+fn literal_i64(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Literal(ScalarValue::Int64(Some(x))) => Some(*x),
+        Expr::Literal(ScalarValue::Int32(Some(x))) => Some(*x as i64),
+        Expr::Literal(ScalarValue::UInt32(Some(x))) => Some(*x as i64),
+        Expr::Literal(ScalarValue::UInt64(Some(x))) => i64::try_from(*x).ok(),
+        _ => None,
+    }
+}
+
+/// Inclusive timestamp bounds implied by a filter, if it is a comparison of
+/// `timestamp` with a constant
+fn timestamp_bounds(filter: &Expr) -> Option<(Option<i64>, Option<i64>)> {
+    match filter {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let (op, value) = if is_timestamp(left) {
+                (*op, literal_i64(right)?)
+            } else if is_timestamp(right) {
+                (op.swap()?, literal_i64(left)?)
+            } else {
+                return None;
+            };
+            match op {
+                Operator::Gt | Operator::GtEq => Some((Some(value), None)),
+                Operator::Lt | Operator::LtEq => Some((None, Some(value))),
+                Operator::Eq => Some((Some(value), Some(value))),
+                _ => None,
+            }
+        }
+        Expr::Between(Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        }) if is_timestamp(expr) => Some((Some(literal_i64(low)?), Some(literal_i64(high)?))),
+        _ => None,
+    }
+}
+
+/// Intersection of the bounds of all pushed-down filters
+fn time_range(filters: &[Expr]) -> (Option<i64>, Option<i64>) {
+    filters
+        .iter()
+        .filter_map(timestamp_bounds)
+        .fold((None, None), |(min, max), (lo, hi)| {
+            (min.max(lo), max.into_iter().chain(hi).min())
+        })
+}
+
+#[async_trait]
+impl TableProvider for TimeSeriesSource {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        match self.series.lock() {
+            Ok(ts) => time_series_schema(&ts),
+            Err(_) => SchemaRef::new(Schema::new(vec![Field::new(
+                TIMESTAMP,
+                DataType::Int64,
+                true,
+            )])),
+        }
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match timestamp_bounds(filter) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let (min, max) = time_range(filters);
+        let selected = self
+            .series
+            .lock()
+            .map_err(|e| DataFusionError::Execution(format!("{}: {e}", self.name)))?
+            .select_range(min, max);
+        let data = time_series_to_recordbatch(&selected)?;
+        let srccfg = MemorySourceConfig::try_new(
+            &[data],
+            time_series_schema(&selected),
+            projection.cloned(),
+        )?;
+        Ok(Arc::new(DataSourceExec::new(Arc::new(srccfg))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+
+    #[test]
+    fn test_time_range() {
+        let filters = vec![
+            col("timestamp").gt(lit(100i64)),
+            lit(500i64).gt_eq(col("timestamp")),
+            col("timestamp").lt(lit(800i64)),
+            col("value").gt(lit(1000i64)),
+        ];
+        assert_eq!(time_range(&filters), (Some(100), Some(500)));
+        assert_eq!(
+            time_range(&[col("timestamp").between(lit(1i64), lit(2i64))]),
+            (Some(1), Some(2))
+        );
+        assert_eq!(time_range(&[]), (None, None));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod archive;
 pub mod config;
 pub mod core;
 pub mod storage;
@@ -10,6 +11,8 @@ pub fn create_engine() -> EngineBuilder {
     Engine::builder().with_default_namespace("probe")
 }
 
+use std::future::Future;
+
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use tokio::sync::RwLock;
@@ -30,3 +33,22 @@ pub async fn initialize_engine(builder: EngineBuilder) -> Result<()> {
 
     Ok(())
 }
+
+/// Drive `future` on a current-thread runtime of the calling thread, for
+/// embedders that query in process without the server runtime (unit tests,
+/// offline tools). Fails when called from within a tokio runtime, where the
+/// future must be awaited instead.
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        anyhow::bail!("blocking call from within a tokio runtime, await the future instead");
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    Ok(runtime.block_on(future))
+}
+
+/// Blocking variant of [`initialize_engine`]
+pub fn initialize_engine_blocking(builder: EngineBuilder) -> Result<()> {
+    block_on(initialize_engine(builder))?
+}
@@ -99,8 +99,7 @@ impl DistributedEntityStore {
             ConsistencyLevel::Primary => {
                 vec![&locations[0]]
             }
-            ConsistencyLevel::Quorum => locations.iter().take(self.default_replica_count).collect(),
-            ConsistencyLevel::All => locations.iter().collect(),
+            ConsistencyLevel::Quorum | ConsistencyLevel::All => locations.iter().collect(),
         }
     }
 
@@ -191,36 +190,84 @@ impl DistributedEntityStore {
     pub fn local_store(&self) -> &Arc<MemoryStore> {
         &self.local_store
     }
-}
 
-#[async_trait]
-impl EntityStore for DistributedEntityStore {
-    async fn put<T: PersistentEntity>(&self, entity: &T) -> Result<()> {
-        let locations = self.allocate_addresses(entity).await?;
+    pub fn default_consistency(&self) -> &ConsistencyLevel {
+        &self.default_consistency
+    }
+
+    /// Sets the consistency level used by [`EntityStore::put`].
+    pub fn with_consistency(mut self, consistency: ConsistencyLevel) -> Self {
+        self.default_consistency = consistency;
+        self
+    }
 
-        let write_locations = self.select_write_locations(&locations, &self.default_consistency);
+    /// Writes an entity to its primary and replica locations.
+    ///
+    /// Fails unless enough locations acknowledged the write for `consistency`:
+    /// the primary for `Primary`, a majority of the remote replicas for
+    /// `Quorum` and every location for `All`. The write to this worker never
+    /// counts towards a quorum, it cannot stand in for a peer that was never
+    /// written. Returns the number of acknowledged writes.
+    pub async fn put_with_consistency<T: PersistentEntity>(
+        &self,
+        entity: &T,
+        consistency: &ConsistencyLevel,
+    ) -> Result<usize> {
+        let locations = self.allocate_addresses(entity).await?;
+        if locations.is_empty() {
+            return Err(anyhow!("No addresses allocated for the entity."));
+        }
 
-        println!(
-            "Write locations for entity {}: {:?}\n\t{:?}",
+        let write_locations = self.select_write_locations(&locations, consistency);
+        log::debug!(
+            "write locations for entity {}: {:?}",
             entity.id(),
-            write_locations,
-            locations
+            write_locations
         );
-
-        if write_locations.is_empty() && !locations.is_empty() {
+        if write_locations.is_empty() {
             return Err(anyhow!(
                 "No suitable write locations found, though addresses were allocated."
             ));
         }
-        if write_locations.is_empty() && locations.is_empty() {
-            return Err(anyhow!("No addresses allocated for the entity."));
-        }
 
         let write_results = self.write(entity, &write_locations).await?;
+        let acks = write_results.iter().filter(|r| r.is_ok()).count();
+        let remote: Vec<bool> = write_locations
+            .iter()
+            .zip(&write_results)
+            .filter(|(location, _)| !location.is_local(&self.worker_id))
+            .map(|(_, result)| result.is_ok())
+            .collect();
+        let remote_acks = remote.iter().filter(|ok| **ok).count();
+        let (acked, required) = match consistency {
+            ConsistencyLevel::Primary => (acks, 1),
+            // without replicas there is nobody else to agree with
+            ConsistencyLevel::Quorum if remote.is_empty() => (acks, write_locations.len()),
+            ConsistencyLevel::Quorum => (remote_acks, remote.len() / 2 + 1),
+            ConsistencyLevel::All => (acks, write_locations.len()),
+        };
 
-        let _writes = write_results.iter().filter(|r| r.is_ok()).count();
+        if acked < required {
+            let errors = write_results
+                .iter()
+                .filter_map(|r| r.as_ref().err().map(|e| e.to_string()))
+                .collect::<Vec<_>>();
+            return Err(anyhow!(
+                "write of {} acknowledged by {acked} locations, {required} required for {consistency:?}: {}",
+                entity.id(),
+                errors.join("; ")
+            ));
+        }
+        Ok(acks)
+    }
+}
 
-        Ok(())
+#[async_trait]
+impl EntityStore for DistributedEntityStore {
+    async fn put<T: PersistentEntity>(&self, entity: &T) -> Result<()> {
+        self.put_with_consistency(entity, &self.default_consistency)
+            .await
+            .map(|_| ())
     }
 
     async fn get<T: PersistentEntity>(&self, id: &T::Id) -> Result<Option<T>> {
@@ -414,4 +461,96 @@ mod tests {
             "Job should not exist after deletion"
         );
     }
+
+    #[tokio::test]
+    async fn test_put_with_consistency_counts_acks() {
+        let mut workers_map = HashMap::new();
+        workers_map.insert("node1".to_string(), vec!["worker1".to_string()]);
+        workers_map.insert("node2".to_string(), vec!["worker2".to_string()]);
+        let store = DistributedEntityStore::new(
+            "node1".to_string(),
+            "worker1".to_string(),
+            TopologyView::new(workers_map, 2),
+            Arc::new(MemoryStore::new()),
+            1,
+        );
+
+        let job = ClusterJob {
+            id: "job789".to_string(),
+            name: "Replicated Job".to_string(),
+            tasks_count: 1,
+            status: "Running".to_string(),
+        };
+
+        // no client for worker2 yet: the primary succeeds, the replica fails
+        assert_eq!(
+            store
+                .put_with_consistency(&job, &ConsistencyLevel::Primary)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(store
+            .put_with_consistency(&job, &ConsistencyLevel::All)
+            .await
+            .is_err());
+
+        let peer = Arc::new(MemoryStore::new());
+        store
+            .add_remote_client(
+                "worker2".to_string(),
+                Arc::new(crate::storage::MemoryRemoteClient::new(peer.clone())),
+            )
+            .await;
+        assert_eq!(
+            store
+                .put_with_consistency(&job, &ConsistencyLevel::All)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(peer.get::<ClusterJob>(&job.id).await.unwrap(), Some(job));
+    }
+
+    #[tokio::test]
+    async fn test_quorum_needs_remote_acks() {
+        let mut workers_map = HashMap::new();
+        workers_map.insert("node1".to_string(), vec!["worker1".to_string()]);
+        workers_map.insert("node2".to_string(), vec!["worker2".to_string()]);
+        let store = DistributedEntityStore::new(
+            "node1".to_string(),
+            "worker1".to_string(),
+            TopologyView::new(workers_map, 2),
+            Arc::new(MemoryStore::new()),
+            1,
+        );
+        let job = ClusterJob {
+            id: "job-quorum".to_string(),
+            name: "Quorum Job".to_string(),
+            tasks_count: 1,
+            status: "Running".to_string(),
+        };
+
+        // the local write alone is no quorum
+        assert!(store
+            .put_with_consistency(&job, &ConsistencyLevel::Quorum)
+            .await
+            .is_err());
+
+        let peer = Arc::new(MemoryStore::new());
+        store
+            .add_remote_client(
+                "worker2".to_string(),
+                Arc::new(crate::storage::MemoryRemoteClient::new(peer.clone())),
+            )
+            .await;
+        assert_eq!(
+            store
+                .put_with_consistency(&job, &ConsistencyLevel::Quorum)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(peer.get::<ClusterJob>(&job.id).await.unwrap(), Some(job));
+    }
 }
@@ -34,6 +34,25 @@ impl MemoryStore {
     pub async fn raw_entities_contains(&self, key: &str) -> bool {
         self.entities.read().await.contains_key(key)
     }
+
+    /// Raw entities whose key satisfies `filter`, without copying the others
+    pub async fn raw_entities_filter<F: Fn(&str) -> bool>(
+        &self,
+        filter: F,
+    ) -> Vec<(String, Vec<u8>)> {
+        self.entities
+            .read()
+            .await
+            .iter()
+            .filter(|(key, _)| filter(key))
+            .map(|(key, data)| (key.clone(), data.clone()))
+            .collect()
+    }
+
+    /// Total size of the stored entities, in bytes
+    pub async fn raw_entities_nbytes(&self) -> usize {
+        self.entities.read().await.values().map(|v| v.len()).sum()
+    }
 }
 
 #[async_trait]
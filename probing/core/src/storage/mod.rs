@@ -3,6 +3,7 @@ pub mod distributed;
 pub mod entity;
 pub mod mem_store;
 pub mod remote_client;
+pub mod replica;
 pub mod topology;
 
 // Re-export the main interfaces for easier access
@@ -13,4 +14,5 @@ pub use mem_store::MemoryStore;
 pub use addressing::{Address, AddressAllocator};
 pub use distributed::{ConsistencyLevel, DistributedEntityStore, RemoteStoreClient};
 pub use remote_client::MemoryRemoteClient;
+pub use replica::TableReplica;
 pub use topology::{TopologyStats, TopologyView};
@@ -0,0 +1,222 @@
+use probing_proto::prelude::{DataFrame, Seq};
+use serde::{Deserialize, Serialize};
+
+use super::entity::PersistentEntity;
+
+/// Worker of the segment holding the rows merged by compaction
+pub const MERGED_WORKER: &str = "*merged";
+
+/// Copy of a probe table shipped to peer probes, so the data outlives the
+/// process that produced it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableReplica {
+    /// `<table>@<worker>`, unique per source probe and table
+    pub id: String,
+    pub table: String,
+    pub worker: String,
+    pub timestamp: u64,
+    pub data: DataFrame,
+    /// Microseconds the replica, and each row by its `timestamp` column, is
+    /// kept after being written; kept forever when unset
+    #[serde(default)]
+    pub ttl: Option<u64>,
+    /// Unit of the `timestamp` column in the schema of the table, `us` when
+    /// unset
+    #[serde(default)]
+    pub timestamp_unit: Option<String>,
+}
+
+impl TableReplica {
+    pub fn new(table: &str, worker: &str, timestamp: u64, data: DataFrame) -> Self {
+        Self {
+            id: format!("{table}@{worker}"),
+            table: table.to_string(),
+            worker: worker.to_string(),
+            timestamp,
+            data,
+            ttl: None,
+            timestamp_unit: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Option<u64>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_timestamp_unit(mut self, unit: Option<String>) -> Self {
+        self.timestamp_unit = unit;
+        self
+    }
+
+    /// Whether the whole replica has outlived its TTL at `now` (micros)
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.ttl
+            .is_some_and(|ttl| self.timestamp.saturating_add(ttl) < now)
+    }
+
+    /// Drop the rows whose `timestamp` has outlived the TTL at `now`,
+    /// returning how many were dropped. Rows are kept when the unit of the
+    /// column is not one of time.
+    pub fn expire_rows(&mut self, now: u64) -> usize {
+        let Some(ttl) = self.ttl else {
+            return 0;
+        };
+        let deadline = now.saturating_sub(ttl);
+        let deadline = match self.timestamp_unit.as_deref() {
+            None | Some("us") => deadline,
+            Some("ns") => deadline.saturating_mul(1_000),
+            Some("ms") => deadline / 1_000,
+            Some("s") => deadline / 1_000_000,
+            Some(_) => return 0,
+        };
+        let Some(idx) = self.data.names.iter().position(|n| n == "timestamp") else {
+            return 0;
+        };
+        let keep: Vec<bool> = match &self.data.cols[idx] {
+            Seq::SeqI64(values) => values.iter().map(|v| *v >= deadline as i64).collect(),
+            Seq::SeqDateTime(values) => values.iter().map(|v| *v >= deadline).collect(),
+            _ => return 0,
+        };
+        let expired = keep.iter().filter(|keep| !**keep).count();
+        if expired > 0 {
+            self.data.cols = self.data.cols.iter().map(|c| c.filter(&keep)).collect();
+        }
+        expired
+    }
+
+    /// Merge segments of one table into a single [`MERGED_WORKER`] segment,
+    /// keeping the `source` of every row. `None` when there is nothing to
+    /// merge or when the segments disagree on their columns.
+    pub fn merge(replicas: &[TableReplica]) -> Option<TableReplica> {
+        let first = replicas.first()?;
+        let mut merged = first.with_source();
+        let mut timestamp = first.timestamp;
+        let mut ttl = first.ttl;
+        for replica in replicas.iter().skip(1) {
+            let df = replica.with_source();
+            if df.names != merged.names || replica.timestamp_unit != first.timestamp_unit {
+                return None;
+            }
+            for (col, more) in merged.cols.iter_mut().zip(df.cols.iter()) {
+                col.extend(more).ok()?;
+            }
+            timestamp = timestamp.max(replica.timestamp);
+            ttl = ttl.zip(replica.ttl).map(|(a, b)| a.max(b));
+        }
+        let replica = TableReplica::new(&first.table, MERGED_WORKER, timestamp, merged);
+        Some(
+            replica
+                .with_ttl(ttl)
+                .with_timestamp_unit(first.timestamp_unit.clone()),
+        )
+    }
+
+    /// Replicated rows with a leading `source` column naming the probe they came from.
+    /// Merged segments already carry it.
+    pub fn with_source(&self) -> DataFrame {
+        if self.worker == MERGED_WORKER {
+            return self.data.clone();
+        }
+        let mut names = vec!["source".to_string()];
+        names.extend(self.data.names.iter().cloned());
+        let mut cols = vec![Seq::SeqText(vec![self.worker.clone(); self.data.len()])];
+        cols.extend(self.data.cols.iter().cloned());
+        DataFrame::new(names, cols)
+    }
+}
+
+impl PersistentEntity for TableReplica {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn entity_type() -> &'static str {
+        "table_replica"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replica_with_source() {
+        let df = DataFrame::new(vec!["value".to_string()], vec![Seq::SeqI64(vec![1, 2])]);
+        let replica = TableReplica::new("python.alerts", "node1:1234", 0, df);
+        assert_eq!(replica.id, "python.alerts@node1:1234");
+
+        let df = replica.with_source();
+        assert_eq!(df.names, vec!["source", "value"]);
+        assert_eq!(
+            df.cols[0],
+            Seq::SeqText(vec!["node1:1234".to_string(), "node1:1234".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_replica_ttl() {
+        let df = DataFrame::new(
+            vec!["timestamp".to_string(), "value".to_string()],
+            vec![Seq::SeqI64(vec![100, 200, 300]), Seq::SeqI64(vec![1, 2, 3])],
+        );
+        let mut replica = TableReplica::new("t", "w", 300, df);
+        assert!(!replica.is_expired(u64::MAX));
+        assert_eq!(replica.expire_rows(1000), 0);
+
+        let mut replica = replica.with_ttl(Some(150));
+        assert!(!replica.is_expired(400));
+        assert!(replica.is_expired(451));
+        assert_eq!(replica.expire_rows(400), 2);
+        assert_eq!(replica.data.cols[0], Seq::SeqI64(vec![300]));
+        assert_eq!(replica.data.cols[1], Seq::SeqI64(vec![3]));
+    }
+
+    #[test]
+    fn test_replica_ttl_unit() {
+        let df = DataFrame::new(
+            vec!["timestamp".to_string()],
+            vec![Seq::SeqI64(vec![100, 200, 300])],
+        );
+        let replica = TableReplica::new("t", "w", 300_000, df).with_ttl(Some(150_000));
+        let mut millis = replica.clone().with_timestamp_unit(Some("ms".to_string()));
+        assert_eq!(millis.expire_rows(400_000), 2);
+        assert_eq!(millis.data.cols[0], Seq::SeqI64(vec![300]));
+
+        // counters named timestamp are not expired
+        let mut samples = replica.with_timestamp_unit(Some("samples".to_string()));
+        assert_eq!(samples.expire_rows(u64::MAX), 0);
+    }
+
+    #[test]
+    fn test_replica_merge() {
+        let df = |v: i64| DataFrame::new(vec!["value".to_string()], vec![Seq::SeqI64(vec![v])]);
+        let a = TableReplica::new("t", "a", 1, df(1)).with_ttl(Some(10));
+        let b = TableReplica::new("t", "b", 2, df(2)).with_ttl(Some(20));
+        let other = TableReplica::new(
+            "t",
+            "c",
+            3,
+            DataFrame::new(vec!["other".to_string()], vec![Seq::SeqI64(vec![3])]),
+        );
+        assert!(TableReplica::merge(&[a.clone(), other]).is_none());
+
+        let merged = TableReplica::merge(&[a, b]).unwrap();
+        assert_eq!(merged.id, format!("t@{MERGED_WORKER}"));
+        assert_eq!((merged.timestamp, merged.ttl), (2, Some(20)));
+        assert_eq!(
+            merged.data.cols[0],
+            Seq::SeqText(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(merged.data.cols[1], Seq::SeqI64(vec![1, 2]));
+
+        // merging again keeps the source of every row
+        let c = TableReplica::new("t", "c", 3, df(3));
+        let merged = TableReplica::merge(&[merged, c]).unwrap();
+        assert_eq!(merged.ttl, None);
+        assert_eq!(merged.with_source().names, vec!["source", "value"]);
+        assert_eq!(merged.data.cols[1], Seq::SeqI64(vec![1, 2, 3]));
+    }
+}
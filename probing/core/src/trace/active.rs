@@ -0,0 +1,212 @@
+//! Spans active on each thread, for tagging profiler samples.
+//!
+//! The sampling profilers cannot take the tracer locks: the cpu sampler runs
+//! inside a `SIGPROF` handler on the sampled thread, and the wall sampler
+//! looks at other threads. Each tracer therefore publishes its span stack
+//! as interned names, into a lock-free thread-local slot read by
+//! [`current`] and into a map by native thread id read by [`of_thread`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use once_cell::sync::Lazy;
+
+/// Spans of a stack kept in a tag; deeper stacks keep the outermost ones
+/// and the innermost one
+pub const MAX_NESTING: usize = 8;
+
+/// Active spans of a thread when a sample was taken, outermost first
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanTag {
+    /// Innermost span, unique within its thread
+    pub span_id: u64,
+    depth: u8,
+    /// Interned names, see [`name`]
+    names: [u32; MAX_NESTING],
+}
+
+impl SpanTag {
+    fn new(span_id: u64, stack: &[u32]) -> Self {
+        let mut tag = SpanTag {
+            span_id,
+            depth: stack.len().min(MAX_NESTING) as u8,
+            names: [0; MAX_NESTING],
+        };
+        for (slot, name) in tag.names.iter_mut().zip(stack) {
+            *slot = *name;
+        }
+        if let (Some(last), true) = (stack.last(), stack.len() > MAX_NESTING) {
+            tag.names[MAX_NESTING - 1] = *last;
+        }
+        tag
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.depth == 0
+    }
+
+    /// Names of the spans, outermost first
+    pub fn names(&self) -> Vec<String> {
+        self.names[..self.depth as usize]
+            .iter()
+            .filter_map(|idx| name(*idx))
+            .collect()
+    }
+
+    /// Name of the innermost span, which the sample counts as self time of
+    pub fn innermost(&self) -> Option<String> {
+        self.names[..self.depth as usize]
+            .last()
+            .and_then(|idx| name(*idx))
+    }
+
+    /// Whether the sample was taken within a span called `span`
+    pub fn within(&self, span: &str) -> bool {
+        let Some(idx) = lookup(span) else {
+            return false;
+        };
+        self.names[..self.depth as usize].contains(&idx)
+    }
+}
+
+#[derive(Default)]
+struct Names {
+    names: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+static NAMES: Lazy<RwLock<Names>> = Lazy::new(Default::default);
+
+/// Index of `span` in the name table, added if new. Span names are few, the
+/// table is never pruned.
+pub fn intern(span: &str) -> u32 {
+    if let Some(idx) = lookup(span) {
+        return idx;
+    }
+    let Ok(mut names) = NAMES.write() else {
+        return 0;
+    };
+    if let Some(idx) = names.index.get(span) {
+        return *idx;
+    }
+    let idx = names.names.len() as u32;
+    names.names.push(span.to_string());
+    names.index.insert(span.to_string(), idx);
+    idx
+}
+
+fn lookup(span: &str) -> Option<u32> {
+    NAMES.read().ok()?.index.get(span).copied()
+}
+
+/// Name of an interned span
+pub fn name(idx: u32) -> Option<String> {
+    NAMES.read().ok()?.names.get(idx as usize).cloned()
+}
+
+/// Tag of the calling thread, published with release stores after zeroing
+/// `depth` so a signal handler interrupting an update sees no span rather
+/// than a mix of two stacks
+struct Slot {
+    depth: AtomicU8,
+    span_id: AtomicU64,
+    names: [AtomicU32; MAX_NESTING],
+}
+
+thread_local! {
+    static CURRENT: Slot = const {
+        Slot {
+            depth: AtomicU8::new(0),
+            span_id: AtomicU64::new(0),
+            names: [const { AtomicU32::new(0) }; MAX_NESTING],
+        }
+    };
+}
+
+static BY_THREAD: Lazy<Mutex<HashMap<i32, SpanTag>>> = Lazy::new(Default::default);
+
+fn gettid() -> i32 {
+    unsafe { nix::libc::syscall(nix::libc::SYS_gettid) as i32 }
+}
+
+/// Publish the span stack of the calling thread, `span_id` being the
+/// innermost span and `stack` the interned names, outermost first
+pub(crate) fn publish(span_id: u64, stack: &[u32]) {
+    let tag = SpanTag::new(span_id, stack);
+    let _ = CURRENT.try_with(|slot| {
+        slot.depth.store(0, Ordering::Release);
+        slot.span_id.store(tag.span_id, Ordering::Relaxed);
+        for (dst, src) in slot.names.iter().zip(tag.names) {
+            dst.store(src, Ordering::Relaxed);
+        }
+        slot.depth.store(tag.depth, Ordering::Release);
+    });
+    if let Ok(mut threads) = BY_THREAD.lock() {
+        match tag.is_empty() {
+            true => threads.remove(&gettid()),
+            false => threads.insert(gettid(), tag),
+        };
+    }
+}
+
+/// Spans active on the calling thread.
+///
+/// Async-signal-safe: reads atomics of a constant thread-local only.
+pub fn current() -> SpanTag {
+    CURRENT
+        .try_with(|slot| {
+            let depth = slot.depth.load(Ordering::Acquire);
+            let mut tag = SpanTag {
+                span_id: slot.span_id.load(Ordering::Relaxed),
+                depth,
+                names: [0; MAX_NESTING],
+            };
+            for (dst, src) in tag.names.iter_mut().zip(&slot.names) {
+                *dst = src.load(Ordering::Relaxed);
+            }
+            tag
+        })
+        .unwrap_or_default()
+}
+
+/// Spans active on the thread with native id `tid`
+pub fn of_thread(tid: i32) -> SpanTag {
+    BY_THREAD
+        .lock()
+        .ok()
+        .and_then(|threads| threads.get(&tid).copied())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_tag() {
+        let (forward, attention) = (intern("test.forward"), intern("test.attention"));
+        assert_eq!(intern("test.forward"), forward);
+
+        publish(7, &[forward, attention]);
+        let tag = current();
+        assert_eq!(tag.span_id, 7);
+        assert_eq!(tag.names(), vec!["test.forward", "test.attention"]);
+        assert_eq!(tag.innermost().as_deref(), Some("test.attention"));
+        assert!(tag.within("test.forward"));
+        assert!(!tag.within("test.backward"));
+        assert_eq!(of_thread(gettid()), tag);
+
+        publish(0, &[]);
+        assert!(current().is_empty());
+        assert!(of_thread(gettid()).is_empty());
+
+        let deep = (0..10)
+            .map(|i| intern(&format!("test.level{i}")))
+            .collect::<Vec<_>>();
+        let tag = SpanTag::new(1, &deep);
+        assert_eq!(tag.names().len(), MAX_NESTING);
+        assert_eq!(tag.innermost().as_deref(), Some("test.level9"));
+        assert!(tag.within("test.level0"));
+    }
+}
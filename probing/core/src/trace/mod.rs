@@ -1,6 +1,10 @@
+pub mod active;
 mod span;
 
-use crate::trace::span::{Attribute, Ele, SpanStatus, GLOBAL_TRACER, LOCAL_TRACER};
+pub use span::SpanStatus;
+pub use span::{Attribute, Event, Span, SpanId, Timestamp, TraceId};
+
+use crate::trace::span::{Ele, GLOBAL_TRACER, LOCAL_TRACER};
 use std::collections::HashMap;
 use std::sync::PoisonError;
 use std::thread::ThreadId;
@@ -280,3 +284,28 @@ pub fn all_thread_spans() -> Result<HashMap<ThreadId, Vec<span::Span>>, TraceErr
 pub fn thread_spans(thread_id: ThreadId) -> Result<Option<Vec<span::Span>>, TraceError> {
     GLOBAL_TRACER.thread_spans(thread_id) // No longer needs map_err
 }
+
+/// Starts or stops keeping the spans ended on any thread, e.g. for an
+/// exporter shipping them to a tracing backend.
+///
+/// While enabled, at most a few thousand spans are kept between two calls of
+/// [`take_completed_spans`]; older ones are dropped and counted.
+pub fn collect_completed_spans(enable: bool) {
+    GLOBAL_TRACER.collect_completed(enable)
+}
+
+/// Takes the spans ended since the last call, oldest first, together with the
+/// number of spans dropped in between.
+///
+/// Returns a `TraceError` if the global tracer's lock was poisoned.
+pub fn take_completed_spans() -> Result<(Vec<span::Span>, u64), TraceError> {
+    GLOBAL_TRACER.take_completed()
+}
+
+/// Returns the last spans that ended with an error on any thread, newest
+/// first; only the most recent few dozen are kept.
+///
+/// Returns a `TraceError` if the global tracer's lock was poisoned.
+pub fn recent_failed_spans() -> Result<Vec<span::Span>, TraceError> {
+    GLOBAL_TRACER.recent_failures()
+}
@@ -1,7 +1,7 @@
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::hash::Hash; // Added for SpanStatus hashing
-use std::sync::atomic::{AtomicU16, Ordering}; // For unique tracer ID generation
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering}; // For unique tracer ID generation
 use std::sync::{Arc, Mutex, RwLock, Weak}; // Ensure PoisonError is imported
 use std::thread::{self, ThreadId}; // For thread-local storage
 
@@ -18,9 +18,27 @@ pub struct TraceId(u128);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct SpanId(u64);
 
+impl TraceId {
+    pub fn as_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl SpanId {
+    /// Sequence number of the span, unique within its thread
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
 // Global atomic counter for assigning unique short numeric IDs to LocalTracer instances.
 static NEXT_TRACER_NUM: AtomicU16 = AtomicU16::new(0);
 
+/// Completed spans kept for an exporter at most, the oldest are dropped first
+const MAX_COMPLETED_SPANS: usize = 4096;
+/// Failed spans kept for [`GlobalSpanManager::recent_failures`]
+const MAX_FAILED_SPANS: usize = 32;
+
 // Configuration for TraceId: 16 bits for tracer prefix, 112 bits for sequence number.
 const TRACE_ID_PREFIX_SHIFT: u32 = 128 - 16; // 112 bits for sequence
 const MAX_TRACE_SEQ: u128 = (1u128 << TRACE_ID_PREFIX_SHIFT) - 1;
@@ -39,6 +57,11 @@ impl Timestamp {
             )
     }
 
+    /// Nanoseconds since the UNIX epoch
+    pub fn as_nanos(&self) -> u128 {
+        self.0
+    }
+
     pub fn duration_since(&self, earlier: Timestamp) -> Duration {
         if self.0 > earlier.0 {
             Duration::from_nanos((self.0 - earlier.0) as u64)
@@ -51,6 +74,16 @@ impl Timestamp {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Attribute(String, Ele);
 
+impl Attribute {
+    pub fn key(&self) -> &str {
+        &self.0
+    }
+
+    pub fn value(&self) -> &Ele {
+        &self.1
+    }
+}
+
 pub fn attr<K: Into<String>, V: Into<Ele>>(key: K, value: V) -> Attribute {
     Attribute(key.into(), value.into())
 }
@@ -128,6 +161,8 @@ pub struct LocalSpanManager {
     next_span_seq: u64,
 
     span_stack: Vec<SpanId>,
+    /// Interned names of `span_stack`, published for the sampling profilers
+    stack_names: Vec<u32>,
     spans: HashMap<SpanId, Span>,
     statistics: HashMap<(Option<String>, String, SpanStatus), SpanStats>, // Added statistics field
 }
@@ -142,6 +177,7 @@ impl LocalSpanManager {
             next_trace_seq: 0,
             next_span_seq: 0,
             span_stack: Vec::new(),
+            stack_names: Vec::new(),
             spans: HashMap::new(),
             statistics: HashMap::new(), // Initialize statistics
         }
@@ -202,8 +238,10 @@ impl LocalSpanManager {
             status: SpanStatus::Running,
         };
 
+        self.stack_names.push(super::active::intern(&span.name));
         self.spans.insert(span_id, span);
         self.span_stack.push(span_id);
+        super::active::publish(span_id.0, &self.stack_names);
         (span_id, trace_id_to_use)
     }
 
@@ -257,6 +295,9 @@ impl LocalSpanManager {
         let end_time = Timestamp::now();
 
         if let Some(active_id_on_stack) = self.span_stack.pop() {
+            self.stack_names.pop();
+            let parent_id = self.span_stack.last().map(|id| id.0).unwrap_or_default();
+            super::active::publish(parent_id, &self.stack_names);
             if let Some(ended_span) = self.spans.get_mut(&active_id_on_stack) {
                 ended_span.end_time = Some(end_time);
                 ended_span.status = final_status.clone(); // Update span's status
@@ -272,6 +313,12 @@ impl LocalSpanManager {
                     stats_entry.count += 1;
                     stats_entry.total_duration += duration;
                 }
+                if GLOBAL_TRACER.is_collecting() {
+                    GLOBAL_TRACER.push_completed(ended_span.clone());
+                }
+                if matches!(ended_span.status, SpanStatus::Error(_)) {
+                    GLOBAL_TRACER.push_failed(ended_span.clone());
+                }
             } else {
                 eprintln!(
                     "Error: Popped span_id {active_id_on_stack:?} not found in spans map during end_span."
@@ -343,15 +390,71 @@ thread_local! {
 #[derive(Debug, Default)]
 pub struct GlobalSpanManager {
     local_tracers: Mutex<HashMap<ThreadId, Weak<RwLock<LocalSpanManager>>>>,
-    // TODO: completed_span_exporter: Mutex<Option<Box<dyn SpanExporter + Send + Sync>>>;,
+    /// Spans ended on any thread since an exporter last took them
+    completed: Mutex<VecDeque<Span>>,
+    collecting: AtomicBool,
+    dropped: AtomicU64,
+    /// Last spans ended with an error on any thread
+    failed: Mutex<VecDeque<Span>>,
 }
 
 impl GlobalSpanManager {
-    pub fn new(/* exporter: Box<dyn SpanExporter + Send + Sync> */) -> Self {
+    pub fn new() -> Self {
         GlobalSpanManager {
             local_tracers: Mutex::new(HashMap::new()),
-            // completed_span_exporter: Mutex::new(Some(exporter)),
+            completed: Mutex::new(VecDeque::new()),
+            collecting: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+            failed: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Start or stop keeping ended spans for [`Self::take_completed`]; the
+    /// spans kept so far are discarded when stopping
+    pub fn collect_completed(&self, enable: bool) {
+        self.collecting.store(enable, Ordering::Relaxed);
+        if !enable {
+            if let Ok(mut completed) = self.completed.lock() {
+                completed.clear();
+            }
+        }
+    }
+
+    fn is_collecting(&self) -> bool {
+        self.collecting.load(Ordering::Relaxed)
+    }
+
+    fn push_completed(&self, span: Span) {
+        let Ok(mut completed) = self.completed.lock() else {
+            return;
+        };
+        if completed.len() >= MAX_COMPLETED_SPANS {
+            completed.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
         }
+        completed.push_back(span);
+    }
+
+    fn push_failed(&self, span: Span) {
+        let Ok(mut failed) = self.failed.lock() else {
+            return;
+        };
+        if failed.len() >= MAX_FAILED_SPANS {
+            failed.pop_front();
+        }
+        failed.push_back(span);
+    }
+
+    /// The last spans ended with an error, newest first
+    pub fn recent_failures(&self) -> Result<Vec<Span>, TraceError> {
+        Ok(self.failed.lock()?.iter().rev().cloned().collect())
+    }
+
+    /// Spans ended since the last call, oldest first, with the number of
+    /// spans dropped meanwhile because nobody took them in time
+    pub fn take_completed(&self) -> Result<(Vec<Span>, u64), TraceError> {
+        let spans = std::mem::take(&mut *self.completed.lock()?);
+        Ok((spans.into(), self.dropped.swap(0, Ordering::Relaxed)))
     }
 
     fn register_tracer(&self, thread_id: ThreadId, tracer: Weak<RwLock<LocalSpanManager>>) {
@@ -422,6 +525,7 @@ mod tests {
             next_trace_seq: 0,
             next_span_seq: 0,
             span_stack: vec![],
+            stack_names: vec![],
             spans: Default::default(),
             statistics: Default::default(), // Initialize statistics
         }
@@ -1046,4 +1150,49 @@ mod tests {
             assert_stats_entry(&stats, key, count, min_duration);
         }
     }
+
+    #[test]
+    fn test_collect_completed_spans() {
+        let mut tracer = setup_tracer();
+        GLOBAL_TRACER.collect_completed(true);
+        tracer.start_span("exported_outer", Some("python"), None);
+        tracer.start_span("exported_inner", Some("python"), None);
+        tracer.end_span(SpanStatus::Close);
+        tracer.end_span(SpanStatus::Error(Some("boom".to_string())));
+
+        let (spans, _) = GLOBAL_TRACER.take_completed().unwrap();
+        let names = spans
+            .iter()
+            .map(|s| s.name.as_str())
+            .filter(|n| n.starts_with("exported_"))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["exported_inner", "exported_outer"]);
+        let inner = spans.iter().find(|s| s.name == "exported_inner").unwrap();
+        let outer = spans.iter().find(|s| s.name == "exported_outer").unwrap();
+        assert_eq!(inner.parent_span_id, Some(outer.span_id));
+        assert!(inner.end_time.is_some());
+        GLOBAL_TRACER.collect_completed(false);
+    }
+
+    #[test]
+    fn test_recent_failures() {
+        let mut tracer = setup_tracer();
+        tracer.start_span("failing_outer", None, None);
+        tracer.start_span("failing_inner", None, None);
+        tracer.end_span(SpanStatus::Error(Some("ValueError('bad')".to_string())));
+        tracer.end_span(SpanStatus::Close);
+
+        let failures = GLOBAL_TRACER.recent_failures().unwrap();
+        let names = failures
+            .iter()
+            .map(|s| s.name.as_str())
+            .filter(|n| n.starts_with("failing_"))
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["failing_inner"]);
+        let inner = failures.iter().find(|s| s.name == "failing_inner").unwrap();
+        assert_eq!(
+            inner.status,
+            SpanStatus::Error(Some("ValueError('bad')".to_string()))
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use crate::inject::{LibcAddrs, Process};
+use crate::{LibcAddrs, Process};
 use anyhow::Context;
 use anyhow::Result;
 use std::os::unix::ffi::OsStringExt;
@@ -75,16 +75,33 @@ impl<'a> Injection<'a> {
     }
 
     /// Use the injected shellcode to load the library at the given path.
-    pub(crate) fn execute(&mut self, filename: &std::path::Path) -> Result<()> {
+    ///
+    /// Returns the handle `dlopen` gave for the library.
+    pub(crate) fn execute(&mut self, filename: &std::path::Path) -> Result<u64> {
         let address = self
             .write_filename(filename)
             .context("couldn't write library filename to tracee address space")?;
-        self.open_library(address)
+        let handle = self
+            .open_library(address)
             .context("failed to load library in tracee")?;
         self.free_alloc(address)
             .context("failed to free memory we stored the library filename in")?;
         log::debug!("Executed injected shellcode to load library");
-        Ok(())
+        Ok(handle)
+    }
+
+    /// Use the injected shellcode to close a library opened by [`Self::execute`].
+    pub(crate) fn close(&mut self, handle: u64) -> Result<()> {
+        // RSI is unused, 0 is arbitrary.
+        let result = self
+            .call_function(self.libc.dlclose, handle, 0)
+            .context("calling dlclose in tracee failed")?;
+        log::debug!("Called dlclose in tracee, result = {result:x}");
+        if result as u32 != 0 {
+            Err(anyhow::anyhow!("dlclose within tracee failed"))
+        } else {
+            Ok(())
+        }
     }
 
     /// Allocate space for, and write, a filename in the tracee's address space.
@@ -118,7 +135,7 @@ impl<'a> Injection<'a> {
 
     /// Open a library in the tracee, where the library's filename is already
     /// stored in the tracee's address space, at `filename_address`.
-    fn open_library(&mut self, filename_address: u64) -> Result<()> {
+    fn open_library(&mut self, filename_address: u64) -> Result<u64> {
         let result = self
             .call_function(self.libc.dlopen, filename_address, 1) // flags = RTLD_LAZY
             .context("calling dlopen in tracee failed")?;
@@ -126,7 +143,7 @@ impl<'a> Injection<'a> {
         if result == 0 {
             Err(anyhow::anyhow!("dlopen within tracee returned NULL"))
         } else {
-            Ok(())
+            Ok(result)
         }
     }
 
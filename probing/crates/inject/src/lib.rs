@@ -1,38 +1,41 @@
-//! A library for injecting shared libraries into running processes via ptrace.
+//! Injection of shared libraries, such as the probing probe, into running
+//! processes via ptrace.
 //!
-//! # Platform support
-//!
-//! This library currently only supports x64 \*nix systems, mainly because that's
-//! what I have. Support for other architectures should be possible - the only
-//! barrier being that I cannot test it. In theory though, it would just be
-//! a matter of re-writing the shellcode for each architecture and selecting the
-//! correct one with conditional compilation.
-//!
-//! For Windows, use other projects like [`dll-syringe`][1].
-//!
-//! # Example
+//! The functions at the root of the crate are the API launchers and
+//! schedulers embed instead of spawning `probing <pid> inject`:
 //!
 //! ```no_run
-//! use std::{process::Command, path::PathBuf};
-//! use ptrace_inject::{Injector, Process};
+//! use std::path::Path;
 //!
-//! # fn main() -> eyre::Result<()> {
-//! let library = PathBuf::from("path/to/library.so");
-//!
-//! // Spawn a new process and inject the library into it.
-//! let target = Command::new("target-process");
-//! Injector::spawn(target)?.inject(&library)?;
-//!
-//! // Or attach to an existing process.
-//! let proc = Process::by_name("target-process")?.expect("to find target process");
-//! Injector::attach(proc)?.inject(&library)?;
+//! # fn main() -> anyhow::Result<()> {
+//! let pid = 1234;
+//! let preflight = probing_inject::preflight(pid)?;
+//! if !preflight.problems.is_empty() {
+//!     anyhow::bail!("cannot inject {pid}: {}", preflight.problems.join("; "));
+//! }
+//! if !preflight.probe_loaded {
+//!     // `NAME=value` settings are set in the environment of the target
+//!     // before the library is loaded, e.g. the options of the probe
+//!     let settings = vec!["PROBING_PORT=9700".to_string()];
+//!     let handle = probing_inject::inject(pid, Path::new("/path/to/libprobing.so"), settings)?;
+//!     // ...
+//!     probing_inject::uninject(pid, handle)?;
+//! }
 //! # Ok(())
 //! # }
 //! ```
 //!
+//! Several injections into the same stop of the target go through an
+//! [`Injector`], returned by [`attach`].
+//!
+//! # Platform support
+//!
+//! Only x64 Linux is supported: the shellcode is written for x64, and the
+//! crate is empty on other systems.
+//!
 //! # Ptrace note
 //!
-//! This library was inspired by [`linux-inject`][2]. As noted by that project:
+//! This library was inspired by [`linux-inject`][1]. As noted by that project:
 //!
 //! > On many Linux distributions, the kernel is configured by default to
 //! > prevent any process from calling `ptrace()` on another process that it did
@@ -43,11 +46,13 @@
 //! > echo 0 | sudo tee /proc/sys/kernel/yama/ptrace_scope
 //! > ```
 //!
-//! This library uses [`log`][3] for logging.
+//! [`preflight`] reports this, and the other reasons an injection would
+//! fail, without attaching to the target. This library uses [`log`][2] for
+//! logging.
 //!
-//!  [1]: https://crates.io/crates/dll-syringe
-//!  [2]: https://github.com/gaffe23/linux-inject
-//!  [3]: https://crates.io/crates/log
+//!  [1]: https://github.com/gaffe23/linux-inject
+//!  [2]: https://crates.io/crates/log
+#![cfg(target_os = "linux")]
 #![warn(clippy::all, clippy::pedantic, clippy::nursery, missing_docs)]
 #![allow(
     // Errors can happen for such a diverse set of reasons out of the user's
@@ -61,12 +66,36 @@ use anyhow::Context;
 use anyhow::Result;
 use injection::Injection;
 pub use libc_addresses::LibcAddrs;
+pub use preflight::{preflight, Preflight};
 pub use process::Process;
 
 mod injection;
 mod libc_addresses;
+mod preflight;
 mod process;
 
+/// Attach to process `pid`, stopping it until the returned [`Injector`] is
+/// dropped.
+pub fn attach(pid: i32) -> Result<Injector> {
+    Injector::attach(Process::get(u32::try_from(pid)?)?)
+}
+
+/// Load `library` into process `pid`, after setting each `NAME=value` of
+/// `settings` in its environment; names are upper-cased and settings
+/// without `=` are ignored.
+///
+/// Returns the handle of the library in the target, for [`uninject`].
+pub fn inject(pid: i32, library: &std::path::Path, settings: Vec<String>) -> Result<u64> {
+    attach(pid)?.inject(library, settings)
+}
+
+/// Unload a library loaded by [`inject`] from process `pid`, given its
+/// handle. The library must not be running any code, e.g. a thread of its
+/// own, when it is unmapped.
+pub fn uninject(pid: i32, handle: u64) -> Result<()> {
+    attach(pid)?.unload(handle)
+}
+
 /// A type capable of loading libraries into a ptrace'd target process.
 ///
 /// When this struct is dropped it will detach from the target process.
@@ -147,7 +176,9 @@ impl Injector {
     }
 
     /// Inject the given library into the traced process.
-    pub fn inject(&mut self, library: &std::path::Path, settings: Vec<String>) -> Result<()> {
+    ///
+    /// Returns the handle of the library in the target, for [`Self::unload`].
+    pub fn inject(&mut self, library: &std::path::Path, settings: Vec<String>) -> Result<u64> {
         let Some(tracee) = self.tracer.wait()? else {
             return Err(anyhow::anyhow!(
                 "the target exited quietly as soon as we started tracing it"
@@ -168,7 +199,7 @@ impl Injector {
             }
         }
 
-        injection
+        let handle = injection
             .execute(library)
             .context("failed to execute shellcode")?;
         injection.remove().context("failed to remove shellcode")?;
@@ -177,6 +208,27 @@ impl Injector {
             library.display(),
             self.proc
         );
+        Ok(handle)
+    }
+
+    /// Close a library injected by [`Self::inject`], given its handle. The
+    /// library is unmapped once nothing else holds it open.
+    pub fn unload(&mut self, handle: u64) -> Result<()> {
+        let Some(tracee) = self.tracer.wait()? else {
+            return Err(anyhow::anyhow!(
+                "the target exited quietly as soon as we started tracing it"
+            ));
+        };
+        let mut injection = Injection::inject(&self.proc, &mut self.tracer, tracee)
+            .context("failed to inject shellcode")?;
+        injection
+            .close(handle)
+            .context("failed to close the injected library")?;
+        injection.remove().context("failed to remove shellcode")?;
+        log::info!(
+            "Unloaded library {handle:x} from process with PID {}",
+            self.proc
+        );
         Ok(())
     }
 
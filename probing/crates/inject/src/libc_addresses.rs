@@ -1,4 +1,4 @@
-use crate::inject::Process;
+use crate::Process;
 use anyhow::Context;
 use anyhow::Result;
 use libloading::os::unix::Library;
@@ -9,6 +9,7 @@ pub struct LibcAddrs {
     pub(crate) use_libdl: bool,
     pub(crate) malloc: u64,
     pub(crate) dlopen: u64,
+    pub(crate) dlclose: u64,
     pub(crate) free: u64,
     pub(crate) putenv: u64,
     pub(crate) setenv: u64,
@@ -36,6 +37,14 @@ impl LibcAddrs {
         Self::find_symbol(&lib, name)
     }
 
+    /// `dlclose` of the library `dlopen` was found in
+    fn find_dlclose(use_libdl: bool) -> Result<u64> {
+        match use_libdl {
+            true => Self::addr_of("libdl.so.2", "dlclose"),
+            false => Self::addr_of("libc.so.6", "dlclose"),
+        }
+    }
+
     fn find_dlopen() -> Result<(bool, u64)> {
         if let Ok(addr) = Self::addr_of("libc.so.6", "___dlopen") {
             return Ok((false, addr));
@@ -61,6 +70,7 @@ impl LibcAddrs {
             getenv: Self::addr_of("libc.so.6", "getenv")?,
             printf: Self::addr_of("libc.so.6", "printf")?,
             dlopen: dlopen_addr,
+            dlclose: Self::find_dlclose(use_libdl)?,
         };
         log::debug!("Got libc addresses for current process: {addrs:x?}");
         Ok(addrs)
@@ -78,14 +88,20 @@ impl LibcAddrs {
     ) -> Self {
         // We cannot calculate an offset as `new_base - old_base` because it
         // might be less than 0.
-        let dlopen = match (old_dl, new_dl) {
-            (Some(old), Some(new)) if self.use_libdl => self.dlopen - old + new,
-            _ => self.dlopen - old_base + new_base,
+        let (dlopen, dlclose) = match (old_dl, new_dl) {
+            (Some(old), Some(new)) if self.use_libdl => {
+                (self.dlopen - old + new, self.dlclose - old + new)
+            }
+            _ => (
+                self.dlopen - old_base + new_base,
+                self.dlclose - old_base + new_base,
+            ),
         };
         Self {
             use_libdl: self.use_libdl,
             malloc: self.malloc - old_base + new_base,
             dlopen,
+            dlclose,
             free: self.free - old_base + new_base,
             putenv: self.putenv - old_base + new_base,
             setenv: self.setenv - old_base + new_base,
@@ -0,0 +1,129 @@
+use anyhow::Result;
+
+use crate::Process;
+
+/// Yama setting restricting which processes may be ptrace'd.
+const PTRACE_SCOPE: &str = "/proc/sys/kernel/yama/ptrace_scope";
+
+/// Whether a process can be injected, checked without attaching to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preflight {
+    /// The PID of the target process.
+    pub pid: i32,
+    /// Whether a Python interpreter is mapped in the target.
+    pub python: bool,
+    /// Whether the probing library is already loaded in the target.
+    pub probe_loaded: bool,
+    /// The Yama ptrace scope of the host, `None` without Yama.
+    pub ptrace_scope: Option<u8>,
+    /// Reasons the injection would fail, empty when it should succeed.
+    pub problems: Vec<String>,
+}
+
+/// Bit of `CAP_SYS_PTRACE` in the capability sets of `/proc/<pid>/status`.
+const CAP_SYS_PTRACE: u32 = 19;
+
+/// Whether we hold `CAP_SYS_PTRACE`, which lets us attach to processes of
+/// other users and whatever the Yama scope short of 3.
+fn can_ptrace_any() -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let caps = status.lines().find_map(|l| l.strip_prefix("CapEff:"))?;
+            u64::from_str_radix(caps.trim(), 16).ok()
+        })
+        .is_some_and(|caps| caps & (1 << CAP_SYS_PTRACE) != 0)
+}
+
+/// Why the Yama `scope` keeps us from attaching, holding `CAP_SYS_PTRACE`
+/// or not.
+///
+/// With scope 1 only ancestors may attach, and the target is rarely a child
+/// of the caller, so it is reported without the capability.
+pub(crate) fn ptrace_scope_problem(scope: u8, privileged: bool) -> Option<String> {
+    match scope {
+        0 => None,
+        1 | 2 if privileged => None,
+        1 => Some(
+            "kernel.yama.ptrace_scope is 1, only parents may attach: run as root or \
+             `echo 0 | sudo tee /proc/sys/kernel/yama/ptrace_scope`"
+                .to_string(),
+        ),
+        2 => Some("kernel.yama.ptrace_scope is 2, only root may attach".to_string()),
+        _ => Some(format!(
+            "kernel.yama.ptrace_scope is {scope}, attaching is disabled until reboot"
+        )),
+    }
+}
+
+/// Check whether process `pid` can be injected.
+///
+/// Fails only when the process cannot be read at all; anything that would
+/// make the injection fail is listed in [`Preflight::problems`].
+pub fn preflight(pid: i32) -> Result<Preflight> {
+    let proc = Process::get(u32::try_from(pid)?)?;
+    let mut problems = vec![];
+
+    if !cfg!(target_arch = "x86_64") {
+        problems.push(format!(
+            "injection supports x86_64 only, not {}",
+            std::env::consts::ARCH
+        ));
+    }
+    let euid = nix::unistd::geteuid();
+    let privileged = can_ptrace_any();
+    let ptrace_scope = std::fs::read_to_string(PTRACE_SCOPE)
+        .ok()
+        .and_then(|s| s.trim().parse::<u8>().ok());
+    if let Some(problem) = ptrace_scope.and_then(|scope| ptrace_scope_problem(scope, privileged)) {
+        problems.push(problem);
+    }
+    match proc.uid() {
+        Ok(uid) if !privileged && uid != euid.as_raw() => problems.push(format!(
+            "process {pid} runs as uid {uid}, not as ours ({euid})"
+        )),
+        Ok(_) => {}
+        Err(err) => problems.push(format!("{err:#}")),
+    }
+    match proc.tracer_pid() {
+        Ok(0) => {}
+        Ok(tracer) => problems.push(format!("process {pid} is already traced by {tracer}")),
+        Err(err) => problems.push(format!("{err:#}")),
+    }
+    let probe_loaded = proc.has_library("libprobing").unwrap_or(false);
+    let python = proc.has_library("python").unwrap_or(false);
+    if proc.libc_address().is_err() {
+        problems.push(format!("no libc is mapped in process {pid}"));
+    }
+
+    Ok(Preflight {
+        pid,
+        python,
+        probe_loaded,
+        ptrace_scope,
+        problems,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ptrace_scope_problem() {
+        assert_eq!(ptrace_scope_problem(0, false), None);
+        assert_eq!(ptrace_scope_problem(1, true), None);
+        assert!(ptrace_scope_problem(1, false).is_some());
+        assert_eq!(ptrace_scope_problem(2, true), None);
+        assert!(ptrace_scope_problem(2, false).is_some());
+        assert!(ptrace_scope_problem(3, true).is_some());
+    }
+
+    #[test]
+    fn test_preflight_self() {
+        let pid = i32::try_from(std::process::id()).unwrap();
+        let preflight = preflight(pid).unwrap();
+        assert_eq!(preflight.pid, pid);
+        assert!(!preflight.probe_loaded);
+    }
+}
@@ -95,6 +95,41 @@ impl Process {
         }
     }
 
+    /// Whether a library whose file name contains `name`, e.g. `python` or
+    /// `libprobing`, is mapped in the process.
+    pub fn has_library(&self, name: &str) -> Result<bool> {
+        Ok(self
+            .0
+            .maps()
+            .context("failed to read process memory maps to find libraries")?
+            .iter()
+            .any(|m| {
+                matches!(&m.pathname,
+                    process::MMapPath::Path(p) if p
+                        .file_name()
+                        .is_some_and(|n| n.to_string_lossy().contains(name))
+                )
+            }))
+    }
+
+    /// The real user ID the process runs as.
+    pub(crate) fn uid(&self) -> Result<u32> {
+        Ok(self
+            .0
+            .status()
+            .context("failed to read process status")?
+            .ruid)
+    }
+
+    /// The PID of the process tracing this one, 0 when it is not traced.
+    pub(crate) fn tracer_pid(&self) -> Result<i32> {
+        Ok(self
+            .0
+            .status()
+            .context("failed to read process status")?
+            .tracerpid)
+    }
+
     /// Find a suitable address to inject the shellcode into.
     pub(crate) fn find_executable_space(&self) -> Result<u64> {
         log::trace!("Finding executable space in target process");
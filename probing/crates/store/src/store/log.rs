@@ -0,0 +1,179 @@
+//! Append-only log of timestamped records.
+//!
+//! Records are kept in segment files named after the timestamp of their first
+//! record, `<micros>.log`, holding one `<micros> <record>\n` line per record.
+//! A new segment starts once the current one spans `segment_span`, so that
+//! expired records are dropped a whole segment at a time.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SEGMENT_SUFFIX: &str = ".log";
+
+pub struct RecordLog {
+    dir: PathBuf,
+    /// In microseconds
+    segment_span: u64,
+    /// First timestamp and file of the segment being appended to
+    current: Option<(u64, File)>,
+}
+
+impl RecordLog {
+    /// Open the log kept in `dir`, creating the directory if needed
+    pub fn open<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            segment_span: Duration::from_secs(600).as_micros() as u64,
+            current: None,
+        })
+    }
+
+    pub fn with_segment_span(mut self, span: Duration) -> Self {
+        self.segment_span = (span.as_micros() as u64).max(1);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// First timestamps of the segments, oldest first
+    fn segments(&self) -> std::io::Result<Vec<u64>> {
+        let mut segments = std::fs::read_dir(&self.dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_suffix(SEGMENT_SUFFIX)?.parse::<u64>().ok()
+            })
+            .collect::<Vec<_>>();
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    fn segment_path(&self, start: u64) -> PathBuf {
+        self.dir.join(format!("{start}{SEGMENT_SUFFIX}"))
+    }
+
+    /// Append a record, which must fit on one line
+    pub fn append(&mut self, timestamp: u64, record: &str) -> std::io::Result<()> {
+        if record.contains('\n') {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "records of the log cannot span lines",
+            ));
+        }
+        let current = match self.current.take() {
+            Some((start, file)) if timestamp < start.saturating_add(self.segment_span) => {
+                (start, file)
+            }
+            _ => {
+                // resume the newest segment of a previous run if it is recent
+                let start = match self.segments()?.last() {
+                    Some(&start)
+                        if start <= timestamp
+                            && timestamp < start.saturating_add(self.segment_span) =>
+                    {
+                        start
+                    }
+                    _ => timestamp,
+                };
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.segment_path(start))?;
+                (start, file)
+            }
+        };
+        let (_, file) = self.current.insert(current);
+        writeln!(file, "{timestamp} {record}")
+    }
+
+    /// Records at or after `since`, oldest first. The last record may have
+    /// been cut short by a crash, callers skip records they cannot parse.
+    pub fn replay(&self, since: u64) -> std::io::Result<Vec<(u64, String)>> {
+        let segments = self.segments()?;
+        let mut records = vec![];
+        for (i, start) in segments.iter().enumerate() {
+            // every record of the segment precedes the start of the next one
+            if segments.get(i + 1).is_some_and(|next| *next <= since) {
+                continue;
+            }
+            let file = File::open(self.segment_path(*start))?;
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let Some((timestamp, record)) = line.split_once(' ') else {
+                    continue;
+                };
+                match timestamp.parse::<u64>() {
+                    Ok(timestamp) if timestamp >= since => {
+                        records.push((timestamp, record.to_string()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Remove the segments holding only records older than `before`,
+    /// returning how many were removed
+    pub fn expire(&mut self, before: u64) -> std::io::Result<usize> {
+        let segments = self.segments()?;
+        let mut removed = 0;
+        for pair in segments.windows(2) {
+            if pair[1] > before {
+                break;
+            }
+            if self
+                .current
+                .as_ref()
+                .is_some_and(|(start, _)| *start == pair[0])
+            {
+                self.current = None;
+            }
+            std::fs::remove_file(self.segment_path(pair[0]))?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_log() {
+        let dir = std::env::temp_dir().join(format!("probing-log-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut log = RecordLog::open(&dir)
+            .unwrap()
+            .with_segment_span(Duration::from_micros(100));
+        for ts in [10, 50, 120, 180, 250] {
+            log.append(ts, &format!("r{ts}")).unwrap();
+        }
+        assert!(log.append(300, "two\nlines").is_err());
+        assert_eq!(log.segments().unwrap(), vec![10, 120, 250]);
+
+        // a new run resumes the newest segment
+        let mut log = RecordLog::open(&dir)
+            .unwrap()
+            .with_segment_span(Duration::from_micros(100));
+        log.append(260, "r260").unwrap();
+        assert_eq!(log.segments().unwrap(), vec![10, 120, 250]);
+
+        let records = log.replay(120).unwrap();
+        let timestamps = records.iter().map(|(ts, _)| *ts).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![120, 180, 250, 260]);
+        assert_eq!(records[0].1, "r120");
+
+        assert_eq!(log.expire(200).unwrap(), 1);
+        assert_eq!(log.segments().unwrap(), vec![120, 250]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
+mod log;
 mod tcpstore;
+mod wal;
 
-pub use tcpstore::TCPStore;
+pub use log::RecordLog;
+pub use tcpstore::{TCPStore, TCPStoreError};
+pub use wal::{DurableStore, RetryPolicy, WriteAheadLog};
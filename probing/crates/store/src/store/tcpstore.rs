@@ -99,24 +99,38 @@ impl TCPStore {
         Ok(result)
     }
 
-    pub async fn set(&self, key: &str, value: &str) -> Result<(), TCPStoreError> {
-        // Implement the logic to set a key-value pair in the store
-        // This is a placeholder implementation
+    /// Validate the connection and wait for the server to answer a ping
+    async fn handshake(stream: &mut TcpStream) -> Result<(), TCPStoreError> {
+        stream.write_u8(QueryType::VALIDATE as u8).await?;
+        stream.write_u32_le(0x3C85F7CE_u32).await?;
+        stream.write_u8(QueryType::PING as u8).await?;
+        stream.write_u32_le(0_u32).await?;
+        stream.read_u32_le().await?;
+        Ok(())
+    }
 
-        self.run(|mut stream| async move {
-            stream.write_u8(QueryType::VALIDATE as u8).await?;
-            stream.write_u32_le(0x3C85F7CE_u32).await?;
-            stream.write_u8(QueryType::PING as u8).await?;
-            stream.write_u32_le(0_u32).await?;
+    async fn write_key(&self, stream: &mut TcpStream, key: &str) -> Result<(), TCPStoreError> {
+        stream
+            .write_u64_le((key.len() + self.keyprefix.len()) as u64)
+            .await?;
+        stream.write_all(self.keyprefix.as_bytes()).await?;
+        stream.write_all(key.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn read_value(stream: &mut TcpStream) -> Result<String, TCPStoreError> {
+        let len = stream.read_u64_le().await?;
+        let mut buffer = vec![0; len as usize];
+        stream.read_exact(&mut buffer).await?;
+        Ok(String::from_utf8_lossy(&buffer).to_string())
+    }
 
-            let pong = stream.read_u32_le().await?;
+    pub async fn set(&self, key: &str, value: &str) -> Result<(), TCPStoreError> {
+        self.run(|mut stream| async move {
+            Self::handshake(&mut stream).await?;
 
             stream.write_u8(QueryType::SET as u8).await?;
-            stream
-                .write_u64_le((key.len() + self.keyprefix.len()) as u64)
-                .await?;
-            stream.write_all(self.keyprefix.as_bytes()).await?;
-            stream.write_all(key.as_bytes()).await?;
+            self.write_key(&mut stream, key).await?;
             stream.write_u64_le(value.len() as u64).await?;
             stream.write_all(value.as_bytes()).await?;
             Ok(())
@@ -126,33 +140,61 @@ impl TCPStore {
 
     pub async fn get(&self, key: &str) -> Result<String, TCPStoreError> {
         self.run(|mut stream| async move {
-            stream.write_u8(QueryType::VALIDATE as u8).await?;
-            stream.write_u32_le(0x3C85F7CE_u32).await?;
+            Self::handshake(&mut stream).await?;
+
+            stream.write_u8(QueryType::GET as u8).await?;
+            self.write_key(&mut stream, key).await?;
+            Self::read_value(&mut stream).await
+        })
+        .await
+    }
+
+    /// Set several keys over one connection.
+    ///
+    /// The server answers a trailing ping only after it has applied the
+    /// writes, so `Ok` means the values were stored, not just sent.
+    pub async fn multi_set(&self, items: &[(String, String)]) -> Result<(), TCPStoreError> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        self.run(|mut stream| async move {
+            Self::handshake(&mut stream).await?;
+
+            stream.write_u8(QueryType::MULTI_SET as u8).await?;
+            stream.write_u64_le(items.len() as u64).await?;
+            for (key, value) in items {
+                self.write_key(&mut stream, key).await?;
+                stream.write_u64_le(value.len() as u64).await?;
+                stream.write_all(value.as_bytes()).await?;
+            }
+
             stream.write_u8(QueryType::PING as u8).await?;
             stream.write_u32_le(0_u32).await?;
+            stream.read_u32_le().await?;
+            Ok(())
+        })
+        .await
+    }
 
-            let pong = stream.read_u32_le().await?;
+    /// Get several keys over one connection, in the order of `keys`
+    pub async fn multi_get(&self, keys: &[String]) -> Result<Vec<String>, TCPStoreError> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        self.run(|mut stream| async move {
+            Self::handshake(&mut stream).await?;
 
-            stream.write_u8(QueryType::GET as u8).await?;
-            stream
-                .write_u64_le((key.len() + self.keyprefix.len()) as u64)
-                .await?;
-            stream.write_all(self.keyprefix.as_bytes()).await?;
-            stream.write_all(key.as_bytes()).await?;
-
-            // Read the response
-            let len = stream.read_u64_le().await?;
-            let mut buffer = vec![0; len as usize]; // Adjust size as needed
-
-            let readlen = stream.read_exact(&mut buffer).await?;
-            if readlen as u64 != len {
-                return Err(TCPStoreError::HandlerError(
-                    "Failed to read the expected number of bytes".to_string(),
-                ));
+            stream.write_u8(QueryType::MULTI_GET as u8).await?;
+            stream.write_u64_le(keys.len() as u64).await?;
+            for key in keys {
+                self.write_key(&mut stream, key).await?;
             }
-            let value = String::from_utf8_lossy(&buffer[..readlen]).to_string();
 
-            Ok(value)
+            let mut values = Vec::with_capacity(keys.len());
+            for _ in keys {
+                values.push(Self::read_value(&mut stream).await?);
+            }
+            Ok(values)
         })
         .await
     }
@@ -0,0 +1,380 @@
+//! Write-ahead queue in front of [`TCPStore`].
+//!
+//! Writes are first recorded in a local log and only dropped from it once the
+//! master acknowledged them, so a connection drop never loses a write. They
+//! are sent by a background thread, so a slow or unreachable master never
+//! blocks the caller. Writes are keyed: a newer value for a
+//! key replaces a pending older one, which makes retries idempotent.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::time::Duration;
+
+use super::tcpstore::{TCPStore, TCPStoreError};
+
+/// Exponential backoff between attempts to flush the queue
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_attempts: 5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, starting at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingWrite {
+    id: u64,
+    key: String,
+    value: String,
+}
+
+/// Pending writes, optionally mirrored to a log file.
+///
+/// The file holds `S <id> <key len> <value len>\n<key><value>\n` records for
+/// queued writes and `A <id>\n` records for acknowledged ones; it is truncated
+/// whenever the queue drains.
+#[derive(Debug, Default)]
+pub struct WriteAheadLog {
+    file: Option<File>,
+    pending: Vec<PendingWrite>,
+    next_id: u64,
+}
+
+impl WriteAheadLog {
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Open the log at `path`, replaying writes that were never acknowledged
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut content = vec![];
+        file.read_to_end(&mut content)?;
+
+        let mut wal = Self::default();
+        wal.replay(&content);
+        wal.file = Some(file);
+        Ok(wal)
+    }
+
+    fn replay(&mut self, mut content: &[u8]) {
+        while let Some(end) = content.iter().position(|&b| b == b'\n') {
+            let header = String::from_utf8_lossy(&content[..end]).to_string();
+            content = &content[end + 1..];
+            let fields = header.split(' ').collect::<Vec<_>>();
+            match fields[..] {
+                ["S", id, klen, vlen] => {
+                    let (Ok(id), Ok(klen), Ok(vlen)) =
+                        (id.parse(), klen.parse::<usize>(), vlen.parse::<usize>())
+                    else {
+                        break;
+                    };
+                    // a record cut short by a crash ends the log
+                    if content.len() < klen + vlen + 1 {
+                        break;
+                    }
+                    let key = String::from_utf8_lossy(&content[..klen]).to_string();
+                    let value = String::from_utf8_lossy(&content[klen..klen + vlen]).to_string();
+                    content = &content[klen + vlen + 1..];
+                    self.insert(PendingWrite { id, key, value });
+                    self.next_id = self.next_id.max(id + 1);
+                }
+                ["A", id] => {
+                    if let Ok(id) = id.parse::<u64>() {
+                        self.pending.retain(|w| w.id != id);
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn insert(&mut self, write: PendingWrite) {
+        match self.pending.iter_mut().find(|w| w.key == write.key) {
+            Some(pending) => *pending = write,
+            None => self.pending.push(write),
+        }
+    }
+
+    /// Queue a write, replacing a pending write of the same key
+    pub fn push(&mut self, key: &str, value: &str) -> std::io::Result<()> {
+        let write = PendingWrite {
+            id: self.next_id,
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        self.next_id += 1;
+        if let Some(file) = self.file.as_mut() {
+            let mut record = format!("S {} {} {}\n", write.id, key.len(), value.len()).into_bytes();
+            record.extend_from_slice(key.as_bytes());
+            record.extend_from_slice(value.as_bytes());
+            record.push(b'\n');
+            file.write_all(&record)?;
+            file.flush()?;
+        }
+        self.insert(write);
+        Ok(())
+    }
+
+    /// Drop acknowledged writes from the queue
+    fn ack(&mut self, ids: &[u64]) -> std::io::Result<()> {
+        self.pending.retain(|w| !ids.contains(&w.id));
+        if let Some(file) = self.file.as_mut() {
+            if self.pending.is_empty() {
+                file.set_len(0)?;
+            } else {
+                for id in ids {
+                    writeln!(file, "A {id}")?;
+                }
+                file.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn batch(&self) -> (Vec<u64>, Vec<(String, String)>) {
+        self.pending
+            .iter()
+            .map(|w| (w.id, (w.key.clone(), w.value.clone())))
+            .unzip()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Store and queue shared with the flusher thread
+struct Shared {
+    store: TCPStore,
+    wal: Mutex<WriteAheadLog>,
+}
+
+impl Shared {
+    /// Send all pending writes in one batch, once
+    async fn try_flush(&self) -> Result<(), TCPStoreError> {
+        let (ids, items) = self.wal.lock().unwrap().batch();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.store.multi_set(&items).await?;
+        self.wal.lock().unwrap().ack(&ids)?;
+        Ok(())
+    }
+}
+
+/// Send the queue whenever writes are added, retrying with backoff until the
+/// master acknowledges them. Returns once the store is dropped.
+///
+/// The thread runs its own runtime since callers such as the Python binding
+/// only run one for the duration of a call.
+fn flush_in_background(shared: Weak<Shared>, retry: RetryPolicy, kicks: mpsc::Receiver<()>) {
+    let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    else {
+        return;
+    };
+    while kicks.recv().is_ok() {
+        let mut attempt = 0;
+        loop {
+            // writes queued up to now go out with this batch
+            while kicks.try_recv().is_ok() {}
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            if runtime.block_on(shared.try_flush()).is_ok() {
+                break;
+            }
+            drop(shared);
+            attempt += 1;
+            std::thread::sleep(retry.backoff(attempt));
+        }
+    }
+}
+
+/// A [`TCPStore`] whose writes survive a flaky master
+pub struct DurableStore {
+    shared: Arc<Shared>,
+    retry: RetryPolicy,
+    /// Wakes the flusher thread, started by the first write
+    flusher: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl DurableStore {
+    pub fn new(store: TCPStore, wal: WriteAheadLog) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                store,
+                wal: Mutex::new(wal),
+            }),
+            retry: RetryPolicy::default(),
+            flusher: Mutex::new(None),
+        }
+    }
+
+    /// Open a store whose queue is persisted at `path`
+    pub fn open(store: TCPStore, path: PathBuf) -> Result<Self, TCPStoreError> {
+        Ok(Self::new(store, WriteAheadLog::open(path)?))
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn store(&self) -> &TCPStore {
+        &self.shared.store
+    }
+
+    /// Number of writes not yet acknowledged by the master
+    pub fn pending(&self) -> usize {
+        self.shared.wal.lock().unwrap().len()
+    }
+
+    /// Have the flusher thread send the queue, starting it if needed
+    fn kick(&self) -> Result<(), TCPStoreError> {
+        let mut flusher = self.flusher.lock().unwrap();
+        if flusher.as_ref().is_some_and(|kicks| kicks.send(()).is_ok()) {
+            return Ok(());
+        }
+        let (kicks, received) = mpsc::channel();
+        let shared = Arc::downgrade(&self.shared);
+        let retry = self.retry.clone();
+        std::thread::Builder::new()
+            .name("probing-wal".to_string())
+            .spawn(move || flush_in_background(shared, retry, received))?;
+        let _ = kicks.send(());
+        *flusher = Some(kicks);
+        Ok(())
+    }
+
+    /// Queue a write to be sent in the background, retried with backoff
+    /// while the master fails. Returns the number of writes pending.
+    pub async fn set(&self, key: &str, value: &str) -> Result<usize, TCPStoreError> {
+        self.multi_set(&[(key.to_string(), value.to_string())])
+            .await
+    }
+
+    /// Queue several writes, see [`DurableStore::set`]
+    pub async fn multi_set(&self, items: &[(String, String)]) -> Result<usize, TCPStoreError> {
+        {
+            let mut wal = self.shared.wal.lock().unwrap();
+            for (key, value) in items {
+                wal.push(key, value)?;
+            }
+        }
+        self.kick()?;
+        Ok(self.pending())
+    }
+
+    /// Send pending writes, retrying with backoff until they are acknowledged
+    /// or the retry policy gives up
+    pub async fn flush(&self) -> Result<(), TCPStoreError> {
+        let mut attempt = 0;
+        loop {
+            match self.shared.try_flush().await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt + 1 >= self.retry.max_attempts => return Err(err),
+                Err(_) => {
+                    attempt += 1;
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wal_replay() {
+        let path = std::env::temp_dir().join(format!("probing-wal-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        wal.push("a", "1").unwrap();
+        wal.push("b", "2").unwrap();
+        wal.push("a", "3").unwrap();
+        assert_eq!(wal.len(), 2);
+        let (ids, items) = wal.batch();
+        assert_eq!(
+            items,
+            vec![
+                ("a".to_string(), "3".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+
+        wal.ack(&ids[1..]).unwrap();
+        drop(wal);
+
+        // only the unacknowledged write comes back, and new ids do not clash
+        let mut wal = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(wal.batch().1, vec![("a".to_string(), "3".to_string())]);
+        wal.push("c", "4").unwrap();
+        assert!(wal.pending.iter().find(|w| w.key == "c").unwrap().id > ids[0]);
+
+        let (ids, _) = wal.batch();
+        wal.ack(&ids).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_does_not_wait_for_master() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // nothing listens on the discard port
+        let store = TCPStore::new("127.0.0.1:9".to_string()).with_timeout(Duration::from_secs(5));
+        let store = DurableStore::new(store, WriteAheadLog::in_memory());
+        let start = std::time::Instant::now();
+        assert_eq!(runtime.block_on(store.set("a", "1")).unwrap(), 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(store.pending(), 1);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let retry = RetryPolicy::default();
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(3), Duration::from_millis(400));
+        assert_eq!(retry.backoff(40), Duration::from_secs(5));
+    }
+}
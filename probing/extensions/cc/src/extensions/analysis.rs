@@ -0,0 +1,434 @@
+use std::any::Any;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{Array, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::compute::{cast, concat_batches};
+use datafusion::catalog::{SchemaProvider, Session, TableProvider};
+use datafusion::datasource::memory::{DataSourceExec, MemorySourceConfig};
+use datafusion::datasource::TableType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::SessionState;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::{Expr, SessionContext};
+
+use probing_core::core::{
+    ArrayRef, DataType, EngineCall, EngineDatasource, EngineError, EngineExtension,
+    EngineExtensionOption, Field, Maybe, Plugin, PluginType, RecordBatch, Schema, SchemaRef,
+};
+
+/// Method used to score each point against its trailing window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnomalyMethod {
+    /// Rolling z-score: `(x - mean) / stddev`
+    ZScore,
+    /// Rolling median absolute deviation: `0.6745 * (x - median) / MAD`
+    Mad,
+}
+
+impl std::str::FromStr for AnomalyMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zscore" | "z-score" => Ok(AnomalyMethod::ZScore),
+            "mad" => Ok(AnomalyMethod::Mad),
+            _ => Err(format!("unknown anomaly method: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnomalyConfig {
+    pub tables: Vec<String>,
+    pub method: AnomalyMethod,
+    pub window: usize,
+    pub threshold: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            tables: vec![],
+            method: AnomalyMethod::ZScore,
+            window: 32,
+            threshold: 3.0,
+        }
+    }
+}
+
+static ANOMALY_CONFIG: OnceLock<Mutex<AnomalyConfig>> = OnceLock::new();
+
+fn anomaly_config() -> &'static Mutex<AnomalyConfig> {
+    ANOMALY_CONFIG.get_or_init(|| Mutex::new(AnomalyConfig::default()))
+}
+
+/// A point whose score exceeded the configured threshold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub index: usize,
+    pub value: f64,
+    pub score: f64,
+}
+
+/// Score each point of `values` against the `window` points preceding it and
+/// return the ones whose absolute score reaches `threshold`.
+pub fn detect_anomalies(
+    values: &[f64],
+    method: AnomalyMethod,
+    window: usize,
+    threshold: f64,
+) -> Vec<Anomaly> {
+    if window < 2 || values.len() <= window {
+        return vec![];
+    }
+
+    let mut anomalies = vec![];
+    for i in window..values.len() {
+        let history = &values[i - window..i];
+        if history.iter().any(|x| !x.is_finite()) || !values[i].is_finite() {
+            continue;
+        }
+        let score = match method {
+            AnomalyMethod::ZScore => {
+                let mean = history.iter().sum::<f64>() / window as f64;
+                let var = history.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / window as f64;
+                let std = var.sqrt();
+                if std == 0.0 {
+                    continue;
+                }
+                (values[i] - mean) / std
+            }
+            AnomalyMethod::Mad => {
+                let median = median(history.to_vec());
+                let mad = median_abs_deviation(history, median);
+                if mad == 0.0 {
+                    continue;
+                }
+                0.6745 * (values[i] - median) / mad
+            }
+        };
+        if score.abs() >= threshold {
+            anomalies.push(Anomaly {
+                index: i,
+                value: values[i],
+                score,
+            });
+        }
+    }
+    anomalies
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+fn median_abs_deviation(values: &[f64], median_value: f64) -> f64 {
+    median(values.iter().map(|x| (x - median_value).abs()).collect())
+}
+
+/// Map an anomaly score to a coarse severity label
+pub fn severity(score: f64, threshold: f64) -> &'static str {
+    if score.abs() >= 2.0 * threshold {
+        "critical"
+    } else {
+        "warning"
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AnomalyTable {}
+
+impl AnomalyTable {
+    fn table_schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("metric", DataType::Utf8, false),
+            Field::new("ts", DataType::Int64, false),
+            Field::new("value", DataType::Float64, false),
+            Field::new("score", DataType::Float64, false),
+            Field::new("severity", DataType::Utf8, false),
+        ]))
+    }
+
+    /// Run the detection pass over every configured table
+    async fn analyze(ctx: &SessionContext, config: &AnomalyConfig) -> Result<RecordBatch> {
+        let mut metrics: Vec<String> = vec![];
+        let mut tss: Vec<i64> = vec![];
+        let mut values: Vec<f64> = vec![];
+        let mut scores: Vec<f64> = vec![];
+        let mut severities: Vec<&'static str> = vec![];
+
+        for table in config.tables.iter() {
+            if table == "analysis.anomalies" {
+                continue;
+            }
+            let batches = match ctx.sql(&format!("SELECT * FROM {table}")).await {
+                Ok(df) => df.collect().await,
+                Err(e) => Err(e),
+            };
+            let batches = match batches {
+                Ok(batches) => batches,
+                Err(e) => {
+                    log::warn!("anomaly detection skipped table {table}: {e}");
+                    continue;
+                }
+            };
+
+            if batches.is_empty() {
+                continue;
+            }
+            let batch = concat_batches(&batches[0].schema(), batches.iter())?;
+
+            let schema = batch.schema();
+            let timestamps = match schema.index_of("timestamp") {
+                Ok(idx) => cast(batch.column(idx), &DataType::Int64)?,
+                Err(_) => {
+                    Arc::new(Int64Array::from_iter_values(0..batch.num_rows() as i64)) as ArrayRef
+                }
+            };
+            let timestamps = timestamps
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .ok_or_else(|| DataFusionError::Internal("invalid timestamp".to_string()))?;
+
+            for (field, column) in schema.fields().iter().zip(batch.columns()) {
+                if field.name() == "timestamp" || !field.data_type().is_numeric() {
+                    continue;
+                }
+                let column = cast(column, &DataType::Float64)?;
+                let column = column
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .ok_or_else(|| DataFusionError::Internal("invalid column".to_string()))?;
+                let series = (0..column.len())
+                    .map(|i| {
+                        if column.is_null(i) {
+                            f64::NAN
+                        } else {
+                            column.value(i)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let metric = format!("{table}.{}", field.name());
+                for anomaly in
+                    detect_anomalies(&series, config.method, config.window, config.threshold)
+                {
+                    metrics.push(metric.clone());
+                    tss.push(timestamps.value(anomaly.index));
+                    values.push(anomaly.value);
+                    scores.push(anomaly.score);
+                    severities.push(severity(anomaly.score, config.threshold));
+                }
+            }
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(metrics)),
+            Arc::new(Int64Array::from(tss)),
+            Arc::new(Float64Array::from(values)),
+            Arc::new(Float64Array::from(scores)),
+            Arc::new(StringArray::from(severities)),
+        ];
+        Ok(RecordBatch::try_new(Self::table_schema(), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for AnomalyTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Self::table_schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let state = state
+            .as_any()
+            .downcast_ref::<SessionState>()
+            .ok_or_else(|| DataFusionError::Internal("unsupported session".to_string()))?;
+        let ctx = SessionContext::new_with_state(state.clone());
+        let config = anomaly_config().lock().unwrap().clone();
+
+        let batch = Self::analyze(&ctx, &config).await?;
+        let srccfg =
+            MemorySourceConfig::try_new(&[vec![batch]], self.schema(), projection.cloned())?;
+        Ok(Arc::new(DataSourceExec::new(Arc::new(srccfg))))
+    }
+}
+
+#[derive(Debug)]
+pub struct AnomalyPlugin {
+    namespace: String,
+    name: String,
+}
+
+impl AnomalyPlugin {
+    pub fn create<S: Into<String>>(namespace: S, name: S) -> Arc<dyn Plugin + Send + Sync> {
+        Arc::new(Self {
+            namespace: namespace.into(),
+            name: name.into(),
+        })
+    }
+}
+
+impl Plugin for AnomalyPlugin {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> PluginType {
+        PluginType::Table
+    }
+
+    fn namespace(&self) -> String {
+        self.namespace.clone()
+    }
+
+    fn register_table(&self, schema: Arc<dyn SchemaProvider>, _state: &SessionState) -> Result<()> {
+        schema.register_table(self.name(), Arc::new(AnomalyTable::default()))?;
+        Ok(())
+    }
+}
+
+/// Anomaly annotations over time-series tables
+#[derive(Debug, EngineExtension)]
+pub struct AnalysisExtension {
+    /// Comma separated tables to scan for anomalies (e.g. python.loss,process.cpu)
+    #[option(aliases=["anomaly.tables"])]
+    anomaly_tables: Maybe<String>,
+
+    /// Anomaly scoring method (zscore or mad)
+    #[option(aliases=["anomaly.method"])]
+    anomaly_method: Maybe<String>,
+
+    /// Number of trailing samples each point is scored against
+    #[option(aliases=["anomaly.window"])]
+    anomaly_window: Maybe<usize>,
+
+    /// Absolute score at which a point is reported as an anomaly
+    #[option(aliases=["anomaly.threshold"])]
+    anomaly_threshold: Maybe<f64>,
+}
+
+impl Default for AnalysisExtension {
+    fn default() -> Self {
+        let config = AnomalyConfig::default();
+        Self {
+            anomaly_tables: Maybe::Nothing,
+            anomaly_method: Maybe::Just("zscore".to_string()),
+            anomaly_window: Maybe::Just(config.window),
+            anomaly_threshold: Maybe::Just(config.threshold),
+        }
+    }
+}
+
+impl EngineCall for AnalysisExtension {}
+
+impl EngineDatasource for AnalysisExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        Some(AnomalyPlugin::create(
+            namespace,
+            name.unwrap_or("anomalies"),
+        ))
+    }
+}
+
+impl AnalysisExtension {
+    fn set_anomaly_tables(&mut self, anomaly_tables: Maybe<String>) -> Result<(), EngineError> {
+        let tables: String = anomaly_tables.clone().into();
+        anomaly_config().lock().unwrap().tables = tables
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        self.anomaly_tables = anomaly_tables;
+        Ok(())
+    }
+
+    fn set_anomaly_method(&mut self, anomaly_method: Maybe<String>) -> Result<(), EngineError> {
+        let method: String = anomaly_method.clone().into();
+        let parsed = method.parse::<AnomalyMethod>().map_err(|_| {
+            EngineError::InvalidOptionValue(Self::OPTION_ANOMALY_METHOD.to_string(), method)
+        })?;
+        anomaly_config().lock().unwrap().method = parsed;
+        self.anomaly_method = anomaly_method;
+        Ok(())
+    }
+
+    fn set_anomaly_window(&mut self, anomaly_window: Maybe<usize>) -> Result<(), EngineError> {
+        match anomaly_window {
+            Maybe::Just(window) if window >= 2 => {
+                anomaly_config().lock().unwrap().window = window;
+                self.anomaly_window = anomaly_window;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_ANOMALY_WINDOW.to_string(),
+                anomaly_window.into(),
+            )),
+        }
+    }
+
+    fn set_anomaly_threshold(&mut self, anomaly_threshold: Maybe<f64>) -> Result<(), EngineError> {
+        match anomaly_threshold {
+            Maybe::Just(threshold) if threshold > 0.0 => {
+                anomaly_config().lock().unwrap().threshold = threshold;
+                self.anomaly_threshold = anomaly_threshold;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_ANOMALY_THRESHOLD.to_string(),
+                anomaly_threshold.into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_anomalies, severity, AnomalyMethod};
+
+    #[test]
+    fn test_detect_spike() {
+        let mut values = (0..64).map(|x| (x % 4) as f64).collect::<Vec<_>>();
+        values[50] = 100.0;
+
+        for method in [AnomalyMethod::ZScore, AnomalyMethod::Mad] {
+            let anomalies = detect_anomalies(&values, method, 16, 3.0);
+            assert_eq!(anomalies.len(), 1);
+            assert_eq!(anomalies[0].index, 50);
+            assert_eq!(severity(anomalies[0].score, 3.0), "critical");
+        }
+    }
+
+    #[test]
+    fn test_flat_series_has_no_anomalies() {
+        let values = vec![1.0; 64];
+        assert!(detect_anomalies(&values, AnomalyMethod::ZScore, 16, 3.0).is_empty());
+        assert!(detect_anomalies(&values, AnomalyMethod::Mad, 16, 3.0).is_empty());
+        assert!(detect_anomalies(&values[..8], AnomalyMethod::ZScore, 16, 3.0).is_empty());
+    }
+}
@@ -1,4 +1,7 @@
+use std::sync::Arc;
+
 use probing_core::core::cluster;
+use probing_core::core::frameworks;
 use probing_core::core::CustomTable;
 use probing_core::core::EngineCall;
 use probing_core::core::EngineDatasource;
@@ -7,6 +10,8 @@ use probing_core::core::TablePluginHelper;
 use probing_core::core::ArrayRef;
 use probing_core::core::DataType;
 use probing_core::core::Field;
+use probing_core::core::FieldDoc;
+use probing_core::core::Int64Array;
 use probing_core::core::RecordBatch;
 use probing_core::core::Schema;
 use probing_core::core::SchemaRef;
@@ -35,6 +40,10 @@ impl CustomTable for ClusterTable {
             Field::new("role_rank", DataType::Int32, true),
             Field::new("role_world_size", DataType::Int32, true),
             Field::new("status", DataType::Utf8, true),
+            Field::new("framework", DataType::Utf8, true),
+            Field::new("labels", DataType::Utf8, true),
+            Field::new("pod", DataType::Utf8, true),
+            Field::new("k8s_node", DataType::Utf8, true),
             Field::new(
                 "timestamp",
                 DataType::Timestamp(TimeUnit::Microsecond, None),
@@ -58,6 +67,18 @@ impl CustomTable for ClusterTable {
         fields.push(cluster::extract_array(&nodes, |n| n.role_rank));
         fields.push(cluster::extract_array(&nodes, |n| n.role_world_size));
         fields.push(cluster::extract_array(&nodes, |n| n.status.clone()));
+        fields.push(cluster::extract_array(&nodes, |n| {
+            n.labels.get("framework").cloned()
+        }));
+        fields.push(cluster::extract_array(&nodes, |n| {
+            Some(frameworks::format_labels(&n.labels)).filter(|l| !l.is_empty())
+        }));
+        fields.push(cluster::extract_array(&nodes, |n| {
+            n.labels.get("k8s_pod").cloned()
+        }));
+        fields.push(cluster::extract_array(&nodes, |n| {
+            n.labels.get("k8s_node").cloned()
+        }));
         fields.push(cluster::extract_array(&nodes, |n| {
             std::time::Duration::from_micros(n.timestamp)
         }));
@@ -75,6 +96,8 @@ pub type ClusterPlugin = TablePluginHelper<ClusterTable>;
 use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
+use probing_core::core::Maybe;
+use probing_proto::prelude::ClockSync;
 
 #[derive(Debug, Default, EngineExtension)]
 pub struct ClusterExtension {}
@@ -93,3 +116,93 @@ impl EngineDatasource for ClusterExtension {
         }
     }
 }
+
+/// Clock offset of every node to the master, as estimated by the nodes from
+/// their heartbeats
+#[derive(Default, Debug)]
+pub struct ClockSkewTable {}
+
+impl CustomTable for ClockSkewTable {
+    fn name() -> &'static str {
+        "clock_skew"
+    }
+
+    fn description() -> &'static str {
+        "Clock offset of every node to the master, estimated from heartbeats"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("host", DataType::Utf8, false),
+            Field::new("addr", DataType::Utf8, false),
+            Field::new("rank", DataType::Int32, true),
+            Field::new("offset_us", DataType::Int64, true)
+                .with_doc("Master clock minus node clock")
+                .with_unit("us"),
+            Field::new("delay_us", DataType::Int64, true)
+                .with_doc("Round trip the offset was estimated from")
+                .with_unit("us"),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                false,
+            ),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let nodes = cluster::get_nodes();
+        let clock = |f: fn(&ClockSync) -> Option<i64>| -> ArrayRef {
+            Arc::new(Int64Array::from(
+                nodes
+                    .iter()
+                    .map(|n| n.clock.as_ref().and_then(f))
+                    .collect::<Vec<_>>(),
+            ))
+        };
+        let fields: Vec<ArrayRef> = vec![
+            cluster::extract_array(&nodes, |n| n.host.clone()),
+            cluster::extract_array(&nodes, |n| n.addr.clone()),
+            cluster::extract_array(&nodes, |n| n.rank),
+            clock(|c| c.offset),
+            clock(|c| c.delay.map(|d| d as i64)),
+            cluster::extract_array(&nodes, |n| std::time::Duration::from_micros(n.timestamp)),
+        ];
+
+        if let Ok(batches) = RecordBatch::try_new(Self::schema(), fields) {
+            vec![batches]
+        } else {
+            Default::default()
+        }
+    }
+}
+
+pub type ClockSkewPlugin = TablePluginHelper<ClockSkewTable>;
+
+/// Clock skew between the nodes and the master
+#[derive(Debug, Default, EngineExtension)]
+pub struct ClockExtension {
+    /// Shift timestamps of replicated tables onto the master clock
+    #[option()]
+    correction: Maybe<bool>,
+}
+
+impl EngineCall for ClockExtension {}
+
+impl EngineDatasource for ClockExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| ClockSkewPlugin::create(namespace, name))
+    }
+}
+
+impl ClockExtension {
+    fn set_correction(&mut self, correction: Maybe<bool>) -> Result<(), EngineError> {
+        cluster::set_clock_correction(matches!(correction, Maybe::Just(true)));
+        self.correction = correction;
+        Ok(())
+    }
+}
@@ -0,0 +1,620 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use datafusion::arrow::array::{
+    BooleanBuilder, Float64Builder, GenericStringBuilder, Int64Builder, RecordBatch,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    Maybe, TablePluginHelper,
+};
+
+const DEFAULT_PREFIX_DEPTH: usize = 3;
+
+/// Files counted one by one per prefix, later ones are counted together as
+/// [`OTHER_FILES`]
+const MAX_FILES: usize = 10000;
+const OTHER_FILES: &str = "<other>";
+
+/// Processes counted per prefix, the dataloader workers of a few trainers
+const MAX_PIDS: usize = 4096;
+
+/// Filesystems served over the network, where a page cache miss is a round trip
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "lustre",
+    "ceph",
+    "glusterfs",
+    "gpfs",
+    "beegfs",
+    "wekafs",
+    "9p",
+    "afs",
+];
+
+/// Scan interval in milliseconds, 0 when tracing is off
+static INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+static SCANNING: AtomicBool = AtomicBool::new(false);
+static TRACKER: Lazy<Mutex<Tracker>> =
+    Lazy::new(|| Mutex::new(Tracker::new(DEFAULT_PREFIX_DEPTH, vec![])));
+
+/// A regular file open for reading, seen by a scan
+#[derive(Debug, Clone, PartialEq)]
+struct OpenFile {
+    pid: i64,
+    fd: i64,
+    path: String,
+    /// File offset, which sequential reads advance
+    pos: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct FileStats {
+    opens: u64,
+    bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct PrefixStats {
+    files: HashMap<String, FileStats>,
+    pids: HashSet<i64>,
+    /// Microseconds since epoch
+    last_access: i64,
+}
+
+/// Diffs successive scans of the file descriptors of the process and its
+/// descendants (the dataloader workers) into per-prefix access counts.
+///
+/// This samples the open files rather than tracing the opens: a descriptor
+/// counts as one open the first time a scan sees it, so files opened and
+/// closed between two scans are missed and the counts are lower bounds.
+/// Bytes are the advance of the file offsets: `pread` and `mmap` reads do not
+/// move them.
+#[derive(Debug)]
+struct Tracker {
+    open: HashMap<(i64, i64), OpenFile>,
+    prefixes: HashMap<String, PrefixStats>,
+    depth: usize,
+    /// Only files under these paths are traced, all when empty
+    paths: Vec<String>,
+}
+
+impl Tracker {
+    fn new(depth: usize, paths: Vec<String>) -> Self {
+        Self {
+            open: HashMap::new(),
+            prefixes: HashMap::new(),
+            depth,
+            paths,
+        }
+    }
+
+    fn traced(&self, path: &str) -> bool {
+        if ["/proc/", "/sys/", "/dev/"]
+            .iter()
+            .any(|pseudo| path.starts_with(pseudo))
+        {
+            return false;
+        }
+        self.paths.is_empty()
+            || self
+                .paths
+                .iter()
+                .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Record the difference between the last scan and `files`
+    fn update(&mut self, now: i64, files: Vec<OpenFile>) {
+        let mut open = HashMap::new();
+        for file in files {
+            if !self.traced(&file.path) {
+                continue;
+            }
+            let key = (file.pid, file.fd);
+            let (opened, bytes) = match self.open.get(&key) {
+                // a backwards seek of the same open file reads nothing new
+                Some(last) if last.path == file.path => (false, file.pos.saturating_sub(last.pos)),
+                // the fd was reused between two scans, count its reads so far
+                _ => (true, file.pos),
+            };
+            let stats = self
+                .prefixes
+                .entry(prefix(&file.path, self.depth))
+                .or_default();
+            let name = match stats.files.len() < MAX_FILES || stats.files.contains_key(&file.path) {
+                true => file.path.clone(),
+                false => OTHER_FILES.to_string(),
+            };
+            let entry = stats.files.entry(name).or_default();
+            entry.opens += opened as u64;
+            entry.bytes += bytes;
+            if stats.pids.len() < MAX_PIDS {
+                stats.pids.insert(file.pid);
+            }
+            if opened || bytes > 0 {
+                stats.last_access = now;
+            }
+            open.insert(key, file);
+        }
+        self.open = open;
+    }
+
+    fn reset(&mut self) {
+        self.open.clear();
+        self.prefixes.clear();
+    }
+}
+
+/// The first `depth` components of the directory holding `path`
+fn prefix(path: &str, depth: usize) -> String {
+    let dir = path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let components = dir
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .take(depth.max(1))
+        .collect::<Vec<_>>();
+    format!("/{}", components.join("/"))
+}
+
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Parent pid from `/proc/<pid>/stat`, after the parenthesized command name
+fn parse_ppid(content: &str) -> Option<i64> {
+    let (_, tail) = content.rsplit_once(')')?;
+    tail.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// This process and all of its descendants
+fn process_family() -> Vec<i64> {
+    let root = std::process::id() as i64;
+    let mut children = HashMap::<i64, Vec<i64>>::new();
+    if let Ok(entries) = std::fs::read_dir("/proc") {
+        for entry in entries.flatten() {
+            let Ok(pid) = entry.file_name().to_string_lossy().parse::<i64>() else {
+                continue;
+            };
+            // processes may exit while being listed
+            let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+                continue;
+            };
+            if let Some(ppid) = parse_ppid(&stat) {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
+    }
+    let mut family = vec![root];
+    let mut i = 0;
+    while i < family.len() {
+        if let Some(pids) = children.get(&family[i]) {
+            family.extend(pids);
+        }
+        i += 1;
+    }
+    family
+}
+
+/// Offset and access mode of a `/proc/<pid>/fdinfo/<fd>`
+fn parse_fdinfo(content: &str) -> Option<(u64, u32)> {
+    let field = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::trim)
+    };
+    let pos = field("pos:")?.parse().ok()?;
+    let flags = u32::from_str_radix(field("flags:")?, 8).ok()?;
+    Some((pos, flags & libc::O_ACCMODE as u32))
+}
+
+/// Regular files open for reading in the process `pid`
+fn open_files(pid: i64) -> Vec<OpenFile> {
+    let Ok(entries) = std::fs::read_dir(format!("/proc/{pid}/fd")) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let fd = entry.file_name().to_string_lossy().parse::<i64>().ok()?;
+            let link = std::fs::read_link(entry.path()).ok()?;
+            let path = link.to_str()?;
+            // sockets, pipes and anonymous inodes are not paths
+            if !path.starts_with('/') || path.ends_with(" (deleted)") {
+                return None;
+            }
+            let fdinfo = std::fs::read_to_string(format!("/proc/{pid}/fdinfo/{fd}")).ok()?;
+            let (pos, mode) = parse_fdinfo(&fdinfo)?;
+            if mode == libc::O_WRONLY as u32 {
+                return None;
+            }
+            Some(OpenFile {
+                pid,
+                fd,
+                path: path.to_string(),
+                pos,
+            })
+        })
+        .collect()
+}
+
+fn scan_once() {
+    let files = process_family()
+        .into_iter()
+        .flat_map(open_files)
+        .collect::<Vec<_>>();
+    if let Ok(mut tracker) = TRACKER.lock() {
+        tracker.update(now_us(), files);
+    }
+}
+
+/// Start the scanning thread unless it is running; it stops by itself once
+/// the interval is set back to 0
+fn start_scanning() -> std::io::Result<()> {
+    if SCANNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let spawned = std::thread::Builder::new()
+        .name("probing-files".to_string())
+        .spawn(|| {
+            loop {
+                let interval = INTERVAL_MS.load(Ordering::Relaxed);
+                if interval == 0 {
+                    break;
+                }
+                if !probing_core::core::killswitch::is_dormant() {
+                    scan_once();
+                }
+                std::thread::sleep(Duration::from_millis(interval));
+            }
+            SCANNING.store(false, Ordering::SeqCst);
+        });
+    if let Err(err) = spawned {
+        SCANNING.store(false, Ordering::SeqCst);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Mount points with their filesystem type, from `/proc/self/mountinfo`
+fn parse_mountinfo(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let mountpoint = fields.get(4)?.replace("\\040", " ");
+            // optional fields end with a lone `-`, followed by the type
+            let sep = fields.iter().position(|f| *f == "-")?;
+            Some((mountpoint, fields.get(sep + 1)?.to_string()))
+        })
+        .collect()
+}
+
+/// Type of the filesystem holding `path`, from the longest matching mount point
+fn filesystem_type(mounts: &[(String, String)], path: &str) -> Option<String> {
+    mounts
+        .iter()
+        .filter(|(mountpoint, _)| {
+            mountpoint == "/"
+                || path == mountpoint
+                || path
+                    .strip_prefix(mountpoint.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|(mountpoint, _)| mountpoint.len())
+        .map(|(_, fstype)| fstype.clone())
+}
+
+fn is_network_fs(fstype: &str) -> bool {
+    NETWORK_FILESYSTEMS.contains(&fstype) || fstype.starts_with("fuse.")
+}
+
+#[derive(Default, Debug)]
+pub struct FileAccessTable {}
+
+impl CustomTable for FileAccessTable {
+    fn name() -> &'static str {
+        "file_access_samples"
+    }
+
+    fn description() -> &'static str {
+        "Files seen open by periodic scans of the process and its dataloader workers, by dataset prefix; files opened and closed between two scans are missed"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("prefix", DataType::Utf8, false),
+            Field::new("fstype", DataType::Utf8, true),
+            Field::new("network", DataType::Boolean, false),
+            Field::new("workers", DataType::Int64, false),
+            Field::new("files", DataType::Int64, false),
+            Field::new("opens", DataType::Int64, false),
+            Field::new("bytes", DataType::Int64, false),
+            Field::new("hot_file", DataType::Utf8, false),
+            Field::new("hot_file_opens", DataType::Int64, false),
+            Field::new("skew", DataType::Float64, false),
+            Field::new("last_access", DataType::Int64, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        if INTERVAL_MS.load(Ordering::Relaxed) > 0 {
+            // include files opened since the last periodic scan
+            scan_once();
+        }
+        let mounts =
+            parse_mountinfo(&std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default());
+        let Ok(tracker) = TRACKER.lock() else {
+            return vec![];
+        };
+
+        let mut prefixes = tracker.prefixes.iter().collect::<Vec<_>>();
+        prefixes.sort_by_key(|(prefix, stats)| {
+            let opens = stats.files.values().map(|f| f.opens).sum::<u64>();
+            (std::cmp::Reverse(opens), prefix.to_string())
+        });
+
+        let mut names = GenericStringBuilder::<i32>::new();
+        let mut fstypes = GenericStringBuilder::<i32>::new();
+        let mut networks = BooleanBuilder::new();
+        let mut workers = Int64Builder::new();
+        let mut files = Int64Builder::new();
+        let mut opens = Int64Builder::new();
+        let mut bytes = Int64Builder::new();
+        let mut hot_files = GenericStringBuilder::<i32>::new();
+        let mut hot_file_opens = Int64Builder::new();
+        let mut skews = Float64Builder::new();
+        let mut last_accesses = Int64Builder::new();
+        for (prefix, stats) in prefixes {
+            let fstype = filesystem_type(&mounts, prefix);
+            let total = stats.files.values().map(|f| f.opens).sum::<u64>();
+            let (hot_file, hot) = stats
+                .files
+                .iter()
+                .filter(|(path, _)| path.as_str() != OTHER_FILES)
+                .max_by(|a, b| a.1.opens.cmp(&b.1.opens).then(b.0.cmp(a.0)))
+                .map(|(path, f)| (path.as_str(), f.opens))
+                .unwrap_or_default();
+            let mean = total as f64 / stats.files.len().max(1) as f64;
+
+            names.append_value(prefix);
+            networks.append_value(fstype.as_deref().is_some_and(is_network_fs));
+            fstypes.append_option(fstype);
+            workers.append_value(stats.pids.len() as i64);
+            files.append_value(stats.files.len() as i64);
+            opens.append_value(total as i64);
+            bytes.append_value(stats.files.values().map(|f| f.bytes).sum::<u64>() as i64);
+            hot_files.append_value(hot_file);
+            hot_file_opens.append_value(hot as i64);
+            skews.append_value(if mean > 0.0 { hot as f64 / mean } else { 0.0 });
+            last_accesses.append_value(stats.last_access);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(names.finish()),
+                Arc::new(fstypes.finish()),
+                Arc::new(networks.finish()),
+                Arc::new(workers.finish()),
+                Arc::new(files.finish()),
+                Arc::new(opens.finish()),
+                Arc::new(bytes.finish()),
+                Arc::new(hot_files.finish()),
+                Arc::new(hot_file_opens.finish()),
+                Arc::new(skews.finish()),
+                Arc::new(last_accesses.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type FileAccessPlugin = TablePluginHelper<FileAccessTable>;
+
+/// File access sampling of the dataloader workers by scanning their open
+/// file descriptors
+#[derive(Debug, EngineExtension)]
+pub struct DataExtension {
+    /// Milliseconds between scans of the open files, 0 to stop sampling
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Directory levels of the dataset prefixes files are grouped by
+    #[option(min = 1)]
+    prefix_depth: Maybe<usize>,
+
+    /// Comma separated directories to trace, all files when empty
+    #[option()]
+    paths: Maybe<String>,
+}
+
+impl Default for DataExtension {
+    fn default() -> Self {
+        Self {
+            interval: Maybe::Just(0),
+            prefix_depth: Maybe::Just(DEFAULT_PREFIX_DEPTH),
+            paths: Maybe::Nothing,
+        }
+    }
+}
+
+impl EngineCall for DataExtension {}
+
+impl EngineDatasource for DataExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| FileAccessPlugin::create(namespace, name))
+    }
+}
+
+impl DataExtension {
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let millis = match interval {
+            Maybe::Just(millis) => millis,
+            Maybe::Nothing => 0,
+        };
+        INTERVAL_MS.store(millis, Ordering::Relaxed);
+        if millis > 0 {
+            start_scanning().map_err(|e| {
+                EngineError::InvalidOptionValue(Self::OPTION_INTERVAL.to_string(), e.to_string())
+            })?;
+        }
+        self.interval = interval;
+        Ok(())
+    }
+
+    /// Changing the grouping drops the counts collected so far
+    fn set_prefix_depth(&mut self, prefix_depth: Maybe<usize>) -> Result<(), EngineError> {
+        match prefix_depth {
+            Maybe::Just(depth) if depth > 0 => {
+                if let Ok(mut tracker) = TRACKER.lock() {
+                    tracker.depth = depth;
+                    tracker.reset();
+                }
+                self.prefix_depth = prefix_depth;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_PREFIX_DEPTH.to_string(),
+                prefix_depth.into(),
+            )),
+        }
+    }
+
+    fn set_paths(&mut self, paths: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = paths.clone().into();
+        let dirs = value
+            .split(',')
+            .map(|dir| dir.trim().trim_end_matches('/'))
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| format!("{dir}/"))
+            .collect::<Vec<_>>();
+        if let Some(dir) = dirs.iter().find(|dir| !dir.starts_with('/')) {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_PATHS.to_string(),
+                format!("{} is not an absolute path", dir.trim_end_matches('/')),
+            ));
+        }
+        if let Ok(mut tracker) = TRACKER.lock() {
+            tracker.paths = dirs;
+            tracker.reset();
+        }
+        self.paths = paths;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(pid: i64, fd: i64, path: &str, pos: u64) -> OpenFile {
+        OpenFile {
+            pid,
+            fd,
+            path: path.to_string(),
+            pos,
+        }
+    }
+
+    #[test]
+    fn test_tracker_update() {
+        let mut tracker = Tracker::new(2, vec![]);
+        tracker.update(
+            100,
+            vec![
+                file(10, 3, "/data/shards/a.tar", 4096),
+                file(11, 3, "/data/shards/b.tar", 0),
+                file(11, 4, "/proc/11/status", 10),
+            ],
+        );
+        tracker.update(
+            200,
+            vec![
+                file(10, 3, "/data/shards/a.tar", 10240),
+                // fd 3 of pid 11 was closed and reused for another shard
+                file(11, 3, "/data/shards/a.tar", 1024),
+                file(11, 5, "/data/index/meta.json", 0),
+            ],
+        );
+        tracker.update(300, vec![file(10, 3, "/data/shards/a.tar", 512)]);
+
+        assert_eq!(tracker.prefixes.len(), 2);
+        let shards = &tracker.prefixes["/data/shards"];
+        assert_eq!(
+            shards.files["/data/shards/a.tar"],
+            FileStats {
+                opens: 2,
+                bytes: 11264
+            }
+        );
+        assert_eq!(shards.files["/data/shards/b.tar"].opens, 1);
+        assert_eq!(shards.pids.len(), 2);
+        assert_eq!(shards.last_access, 200);
+        assert!(tracker.prefixes.contains_key("/data/index"));
+
+        tracker.paths = vec!["/scratch/".to_string()];
+        assert!(tracker.traced("/scratch/x.bin"));
+        assert!(!tracker.traced("/data/shards/a.tar"));
+
+        // past the cap, new files are counted together
+        let mut tracker = Tracker::new(1, vec![]);
+        let files = (0..MAX_FILES as i64 + 10)
+            .map(|fd| file(10, fd, &format!("/data/{fd}.bin"), 0))
+            .collect();
+        tracker.update(100, files);
+        let data = &tracker.prefixes["/data"];
+        assert_eq!(data.files.len(), MAX_FILES + 1);
+        assert_eq!(data.files[OTHER_FILES].opens, 10);
+    }
+
+    #[test]
+    fn test_parse_procfs() {
+        assert_eq!(
+            prefix("/mnt/nfs/imagenet/train/n01/x.jpg", 3),
+            "/mnt/nfs/imagenet"
+        );
+        assert_eq!(prefix("/x.bin", 3), "/");
+        assert_eq!(parse_ppid("42 (pt_data (1)) S 7 42 42"), Some(7));
+        assert_eq!(
+            parse_fdinfo("pos:\t8192\nflags:\t0100002\nmnt_id:\t31\n"),
+            Some((8192, libc::O_RDWR as u32))
+        );
+
+        let mountinfo = "22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw\n\
+                         40 22 0:45 / /mnt/nfs rw,relatime shared:20 - nfs4 srv:/export rw\n\
+                         41 22 0:46 / /mnt/nfs2 rw - fuse.juicefs jfs rw\n";
+        let mounts = parse_mountinfo(mountinfo);
+        assert_eq!(
+            filesystem_type(&mounts, "/mnt/nfs/imagenet").as_deref(),
+            Some("nfs4")
+        );
+        assert_eq!(
+            filesystem_type(&mounts, "/mnt/nfs2/x").as_deref(),
+            Some("fuse.juicefs")
+        );
+        assert_eq!(
+            filesystem_type(&mounts, "/mnt/nfsx").as_deref(),
+            Some("ext4")
+        );
+        assert!(is_network_fs("fuse.juicefs"));
+        assert!(!is_network_fs("ext4"));
+    }
+}
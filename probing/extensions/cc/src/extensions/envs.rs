@@ -1,9 +1,41 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use datafusion::arrow::array::{GenericStringBuilder, RecordBatch};
 use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
 
-use probing_core::core::{CustomTable, EngineCall, EngineDatasource, TablePluginHelper};
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, FieldDoc, TablePluginHelper};
+
+/// Environment of the process when the probe was injected, before any
+/// `setenv` of the probe itself or of the application afterwards
+static INITIAL_ENVS: Lazy<BTreeMap<String, String>> = Lazy::new(|| std::env::vars().collect());
+
+/// Take the snapshot served by `process.envs_initial`; call it as early as
+/// possible after injection
+pub fn record_initial() {
+    Lazy::force(&INITIAL_ENVS);
+}
+
+fn envs_batch(schema: SchemaRef, envs: impl Iterator<Item = (String, String)>) -> Vec<RecordBatch> {
+    let mut names = GenericStringBuilder::<i32>::new();
+    let mut values = GenericStringBuilder::<i32>::new();
+
+    for env in envs {
+        names.append_value(env.0);
+        values.append_value(env.1);
+    }
+
+    let rbs = RecordBatch::try_new(
+        schema,
+        vec![Arc::new(names.finish()), Arc::new(values.finish())],
+    );
+    if let Ok(rbs) = rbs {
+        vec![rbs]
+    } else {
+        Default::default()
+    }
+}
 
 #[derive(Default, Debug)]
 pub struct EnvTable {}
@@ -21,18 +53,99 @@ impl CustomTable for EnvTable {
     }
 
     fn data() -> Vec<datafusion::arrow::array::RecordBatch> {
-        let envs = std::env::vars();
+        envs_batch(Self::schema(), std::env::vars())
+    }
+}
+
+pub type EnvPlugin = TablePluginHelper<EnvTable>;
+
+/// `process.envs_initial`: the environment at injection time
+#[derive(Default, Debug)]
+pub struct InitialEnvTable {}
+
+impl CustomTable for InitialEnvTable {
+    fn name() -> &'static str {
+        "envs_initial"
+    }
+
+    fn schema() -> SchemaRef {
+        EnvTable::schema()
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        envs_batch(
+            Self::schema(),
+            INITIAL_ENVS.iter().map(|(k, v)| (k.clone(), v.clone())),
+        )
+    }
+}
+
+pub type InitialEnvPlugin = TablePluginHelper<InitialEnvTable>;
+
+/// `(name, initial, current, change)` of every variable that differs
+/// between `initial` and `current`, by name
+fn diff_envs(
+    initial: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> Vec<(String, Option<String>, Option<String>, &'static str)> {
+    let mut names = initial.keys().chain(current.keys()).collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (before, after) = (initial.get(name), current.get(name));
+            let change = match (before, after) {
+                (None, Some(_)) => "added",
+                (Some(_), None) => "removed",
+                (Some(before), Some(after)) if before != after => "changed",
+                _ => return None,
+            };
+            Some((name.clone(), before.cloned(), after.cloned(), change))
+        })
+        .collect()
+}
+
+/// `process.envs_diff`: variables set, unset or modified since injection
+#[derive(Default, Debug)]
+pub struct EnvDiffTable {}
+
+impl CustomTable for EnvDiffTable {
+    fn name() -> &'static str {
+        "envs_diff"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("initial", DataType::Utf8, true).with_doc("Value at injection"),
+            Field::new("current", DataType::Utf8, true).with_doc("Value now"),
+            Field::new("change", DataType::Utf8, false)
+                .with_doc("`added`, `removed` or `changed` since injection"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let current = std::env::vars().collect();
         let mut names = GenericStringBuilder::<i32>::new();
+        let mut initial = GenericStringBuilder::<i32>::new();
         let mut values = GenericStringBuilder::<i32>::new();
-
-        for env in envs {
-            names.append_value(env.0.clone());
-            values.append_value(env.1.clone());
+        let mut changes = GenericStringBuilder::<i32>::new();
+        for (name, before, after, change) in diff_envs(&INITIAL_ENVS, &current) {
+            names.append_value(name);
+            initial.append_option(before);
+            values.append_option(after);
+            changes.append_value(change);
         }
 
         let rbs = RecordBatch::try_new(
             Self::schema(),
-            vec![Arc::new(names.finish()), Arc::new(values.finish())],
+            vec![
+                Arc::new(names.finish()),
+                Arc::new(initial.finish()),
+                Arc::new(values.finish()),
+                Arc::new(changes.finish()),
+            ],
         );
         if let Ok(rbs) = rbs {
             vec![rbs]
@@ -42,12 +155,14 @@ impl CustomTable for EnvTable {
     }
 }
 
-pub type EnvPlugin = TablePluginHelper<EnvTable>;
+pub type EnvDiffPlugin = TablePluginHelper<EnvDiffTable>;
 
 use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
 
+/// Serves `envs`, `envs_initial` or `envs_diff`, by the table name it is
+/// registered with
 #[derive(Debug, Default, EngineExtension)]
 pub struct EnvExtension {}
 
@@ -60,8 +175,40 @@ impl EngineDatasource for EnvExtension {
         name: Option<&str>,
     ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
         match name {
+            Some("envs_initial") => Some(InitialEnvPlugin::create(namespace, "envs_initial")),
+            Some("envs_diff") => Some(EnvDiffPlugin::create(namespace, "envs_diff")),
             Some(name) => Some(EnvPlugin::create(namespace, name)),
             None => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_envs() {
+        let envs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let initial = envs(&[("NCCL_DEBUG", "WARN"), ("HOME", "/root"), ("OLD", "1")]);
+        let current = envs(&[("NCCL_DEBUG", "INFO"), ("HOME", "/root"), ("NEW", "2")]);
+        assert_eq!(
+            diff_envs(&initial, &current),
+            vec![
+                (
+                    "NCCL_DEBUG".to_string(),
+                    Some("WARN".to_string()),
+                    Some("INFO".to_string()),
+                    "changed"
+                ),
+                ("NEW".to_string(), None, Some("2".to_string()), "added"),
+                ("OLD".to_string(), Some("1".to_string()), None, "removed"),
+            ]
+        );
+    }
+}
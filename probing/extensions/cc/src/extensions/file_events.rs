@@ -0,0 +1,565 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use datafusion::arrow::array::{BooleanBuilder, GenericStringBuilder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{CustomTable, TablePluginHelper};
+
+/// Events kept by `files.events`, the oldest are dropped first
+const MAX_EVENTS: usize = 4096;
+/// Directories watched at most, inotify watches are a per-user resource
+const MAX_WATCHES: usize = 4096;
+/// Writes to the same file within this window make a single `modify` event
+const COALESCE_US: i64 = 1_000_000;
+
+/// Bumped on every change of the watched paths, stopping the previous watcher
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static JOURNAL: Lazy<Mutex<Journal>> = Lazy::new(|| Mutex::new(Journal::default()));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Create,
+    Modify,
+    Delete,
+    MovedFrom,
+    MovedTo,
+}
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Create => "create",
+            Kind::Modify => "modify",
+            Kind::Delete => "delete",
+            Kind::MovedFrom => "moved_from",
+            Kind::MovedTo => "moved_to",
+        }
+    }
+
+    fn removes(&self) -> bool {
+        matches!(self, Kind::Delete | Kind::MovedFrom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileEvent {
+    /// Microseconds since epoch of the first change
+    timestamp: i64,
+    path: String,
+    kind: Kind,
+    is_dir: bool,
+    /// Size after the change, None once removed
+    size: Option<u64>,
+    size_delta: i64,
+    /// The file got smaller, e.g. a checkpoint being overwritten or cut short
+    truncated: bool,
+}
+
+/// Changes seen under the watched paths, with the last known size of every
+/// file to tell how much each change grew or shrank it.
+#[derive(Debug, Default)]
+struct Journal {
+    events: VecDeque<FileEvent>,
+    sizes: HashMap<String, u64>,
+}
+
+impl Journal {
+    /// Record a change, returning the topic and message to publish for the
+    /// ones worth an alert
+    fn record(
+        &mut self,
+        now: i64,
+        path: String,
+        kind: Kind,
+        is_dir: bool,
+        size: Option<u64>,
+    ) -> Option<(&'static str, String)> {
+        let previous = if kind.removes() {
+            self.sizes.remove(&path)
+        } else {
+            match size {
+                Some(size) => self.sizes.insert(path.clone(), size),
+                None => self.sizes.get(&path).copied(),
+            }
+        };
+        let size_delta = match size {
+            _ if kind.removes() => -(previous.unwrap_or(0) as i64),
+            Some(size) => size as i64 - previous.unwrap_or(0) as i64,
+            // a directory, or a file removed before it could be measured
+            None => 0,
+        };
+        let size = if kind.removes() { None } else { size };
+        let truncated = kind == Kind::Modify && size_delta < 0;
+
+        let alert = if truncated {
+            Some((
+                "files.truncated",
+                format!(
+                    "{path}: {} -> {} bytes",
+                    previous.unwrap_or(0),
+                    size.unwrap_or(0)
+                ),
+            ))
+        } else if kind == Kind::Delete && !is_dir {
+            Some(("files.deleted", path.clone()))
+        } else {
+            None
+        };
+
+        if kind == Kind::Modify {
+            let recent = self
+                .events
+                .iter_mut()
+                .rev()
+                .take_while(|event| now - event.timestamp < COALESCE_US)
+                .find(|event| {
+                    event.path == path && matches!(event.kind, Kind::Create | Kind::Modify)
+                });
+            if let Some(event) = recent {
+                event.size = size;
+                event.size_delta += size_delta;
+                // report a truncation only once per event
+                let first = truncated && !event.truncated;
+                event.truncated |= truncated;
+                return alert.filter(|_| first);
+            }
+        }
+
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(FileEvent {
+            timestamp: now,
+            path,
+            kind,
+            is_dir,
+            size,
+            size_delta,
+            truncated,
+        });
+        alert
+    }
+}
+
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+fn record(path: PathBuf, kind: Kind, is_dir: bool) {
+    let size = if is_dir || kind.removes() {
+        None
+    } else {
+        // the file may already be gone again
+        std::fs::symlink_metadata(&path).ok().map(|meta| meta.len())
+    };
+    let path = path.to_string_lossy().to_string();
+    let alert = JOURNAL
+        .lock()
+        .ok()
+        .and_then(|mut journal| journal.record(now_us(), path, kind, is_dir, size));
+    if let Some((topic, message)) = alert {
+        probing_core::core::events::publish(topic, "files", &message);
+    }
+}
+
+/// Watch `paths` for changes, replacing the paths watched so far; nothing
+/// is watched when empty.
+///
+/// A directory is watched with all of its subdirectories, including the
+/// ones created later, a file through its parent directory so that it is
+/// still seen after being replaced by a rename.
+pub fn watch(paths: Vec<PathBuf>) -> std::io::Result<()> {
+    for path in &paths {
+        if !path.is_absolute() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not an absolute path", path.display()),
+            ));
+        }
+        if !path.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} does not exist", path.display()),
+            ));
+        }
+    }
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    if let Ok(mut journal) = JOURNAL.lock() {
+        journal.sizes.clear();
+    }
+    if paths.is_empty() {
+        return Ok(());
+    }
+    inotify::start(paths, generation)
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::Ordering;
+
+    use super::{record, Kind, GENERATION, JOURNAL, MAX_WATCHES};
+
+    const DIR_MASK: u32 = libc::IN_CREATE
+        | libc::IN_MODIFY
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_ONLYDIR;
+
+    #[derive(Debug)]
+    struct Watch {
+        dir: PathBuf,
+        /// Names the events are kept for, all when None
+        only: Option<HashSet<OsString>>,
+        recursive: bool,
+    }
+
+    struct Watcher {
+        fd: i32,
+        watches: HashMap<i32, Watch>,
+    }
+
+    impl Watcher {
+        fn add(&mut self, dir: &Path, only: Option<OsString>, recursive: bool) -> bool {
+            if self.watches.len() >= MAX_WATCHES {
+                log::warn!("not watching {}: {MAX_WATCHES} watches", dir.display());
+                return false;
+            }
+            let Ok(cpath) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+                return false;
+            };
+            let wd = unsafe { libc::inotify_add_watch(self.fd, cpath.as_ptr(), DIR_MASK) };
+            if wd < 0 {
+                log::warn!(
+                    "cannot watch {}: {}",
+                    dir.display(),
+                    std::io::Error::last_os_error()
+                );
+                return false;
+            }
+            let watch = self.watches.entry(wd).or_insert_with(|| Watch {
+                dir: dir.to_path_buf(),
+                only: Some(HashSet::new()),
+                recursive: false,
+            });
+            match (&mut watch.only, only) {
+                (Some(names), Some(name)) => {
+                    names.insert(name);
+                }
+                (only, None) => *only = None,
+                (None, Some(_)) => {}
+            }
+            watch.recursive |= recursive;
+            true
+        }
+
+        /// Watch `dir` and its subdirectories; with `created` set their
+        /// content was created before the watch and is recorded as such
+        fn add_tree(&mut self, dir: &Path, created: bool) {
+            let mut pending = vec![dir.to_path_buf()];
+            while let Some(dir) = pending.pop() {
+                if !self.add(&dir, None, true) {
+                    return;
+                }
+                let Ok(entries) = std::fs::read_dir(&dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let Ok(meta) = entry.metadata() else {
+                        continue;
+                    };
+                    let path = entry.path();
+                    if created {
+                        record(path.clone(), Kind::Create, meta.is_dir());
+                    } else if meta.is_file() {
+                        seed(&path, meta.len());
+                    }
+                    if meta.is_dir() {
+                        pending.push(path);
+                    }
+                }
+            }
+        }
+
+        fn handle(&mut self, wd: i32, mask: u32, name: &OsStr) {
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                log::warn!("file events were lost, the inotify queue overflowed");
+                return;
+            }
+            if mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&wd);
+                return;
+            }
+            let Some(watch) = self.watches.get(&wd) else {
+                return;
+            };
+            if watch.only.as_ref().is_some_and(|only| !only.contains(name)) {
+                return;
+            }
+            let kind = if mask & libc::IN_CREATE != 0 {
+                Kind::Create
+            } else if mask & libc::IN_MODIFY != 0 {
+                Kind::Modify
+            } else if mask & libc::IN_DELETE != 0 {
+                Kind::Delete
+            } else if mask & libc::IN_MOVED_FROM != 0 {
+                Kind::MovedFrom
+            } else if mask & libc::IN_MOVED_TO != 0 {
+                Kind::MovedTo
+            } else {
+                return;
+            };
+            let path = watch.dir.join(name);
+            let is_dir = mask & libc::IN_ISDIR != 0;
+            let recursive = watch.recursive;
+            record(path.clone(), kind, is_dir);
+            if is_dir && recursive && matches!(kind, Kind::Create | Kind::MovedTo) {
+                self.add_tree(&path, true);
+            }
+        }
+    }
+
+    fn seed(path: &Path, size: u64) {
+        if let Ok(mut journal) = JOURNAL.lock() {
+            journal
+                .sizes
+                .insert(path.to_string_lossy().to_string(), size);
+        }
+    }
+
+    /// Watch descriptor, mask and name of the `inotify_event`s in `buf`
+    pub(super) fn parse_events(buf: &[u8]) -> Vec<(i32, u32, OsString)> {
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut events = vec![];
+        let mut offset = 0;
+        while offset + header <= buf.len() {
+            let event = unsafe {
+                std::ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+            };
+            let start = offset + header;
+            let end = (start + event.len as usize).min(buf.len());
+            // the name is padded with NULs
+            let name = buf[start..end].split(|b| *b == 0).next().unwrap_or(&[]);
+            events.push((event.wd, event.mask, OsStr::from_bytes(name).to_os_string()));
+            offset = start + event.len as usize;
+        }
+        events
+    }
+
+    pub(super) fn start(paths: Vec<PathBuf>, generation: u64) -> std::io::Result<()> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut watcher = Watcher {
+            fd,
+            watches: HashMap::new(),
+        };
+        for path in &paths {
+            if path.is_dir() {
+                watcher.add_tree(path, false);
+            } else if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+                if let Ok(meta) = std::fs::metadata(path) {
+                    seed(path, meta.len());
+                }
+                watcher.add(dir, Some(name.to_os_string()), false);
+            }
+        }
+        if watcher.watches.is_empty() {
+            unsafe { libc::close(fd) };
+            return Err(std::io::Error::other("none of the paths can be watched"));
+        }
+
+        let spawned = std::thread::Builder::new()
+            .name("probing-watch".to_string())
+            .spawn(move || {
+                let mut buf = vec![0u8; 64 * 1024];
+                while GENERATION.load(Ordering::SeqCst) == generation {
+                    let mut pollfd = libc::pollfd {
+                        fd: watcher.fd,
+                        events: libc::POLLIN,
+                        revents: 0,
+                    };
+                    // wake up now and then to notice a change of the paths
+                    if unsafe { libc::poll(&mut pollfd, 1, 500) } <= 0 {
+                        continue;
+                    }
+                    let n = unsafe {
+                        libc::read(watcher.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                    };
+                    if n <= 0 || probing_core::core::killswitch::is_dormant() {
+                        continue;
+                    }
+                    for (wd, mask, name) in parse_events(&buf[..n as usize]) {
+                        watcher.handle(wd, mask, &name);
+                    }
+                }
+                unsafe { libc::close(watcher.fd) };
+            });
+        if let Err(err) = spawned {
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod inotify {
+    use std::path::PathBuf;
+
+    pub(super) fn start(_paths: Vec<PathBuf>, _generation: u64) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "watching files requires inotify",
+        ))
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct FileEventsTable {}
+
+impl CustomTable for FileEventsTable {
+    fn name() -> &'static str {
+        "events"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("path", DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("is_dir", DataType::Boolean, false),
+            Field::new("size", DataType::Int64, true),
+            Field::new("size_delta", DataType::Int64, false),
+            Field::new("truncated", DataType::Boolean, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let Ok(journal) = JOURNAL.lock() else {
+            return vec![];
+        };
+        let mut timestamps = Int64Builder::new();
+        let mut paths = GenericStringBuilder::<i32>::new();
+        let mut kinds = GenericStringBuilder::<i32>::new();
+        let mut dirs = BooleanBuilder::new();
+        let mut sizes = Int64Builder::new();
+        let mut deltas = Int64Builder::new();
+        let mut truncated = BooleanBuilder::new();
+        for event in journal.events.iter() {
+            timestamps.append_value(event.timestamp);
+            paths.append_value(&event.path);
+            kinds.append_value(event.kind.as_str());
+            dirs.append_value(event.is_dir);
+            sizes.append_option(event.size.map(|size| size as i64));
+            deltas.append_value(event.size_delta);
+            truncated.append_value(event.truncated);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(timestamps.finish()),
+                Arc::new(paths.finish()),
+                Arc::new(kinds.finish()),
+                Arc::new(dirs.finish()),
+                Arc::new(sizes.finish()),
+                Arc::new(deltas.finish()),
+                Arc::new(truncated.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type FileEventsPlugin = TablePluginHelper<FileEventsTable>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_record() {
+        let mut journal = Journal::default();
+        let ckpt = "/ckpt/step-100/model.pt".to_string();
+        assert_eq!(
+            journal.record(0, ckpt.clone(), Kind::Create, false, Some(0)),
+            None
+        );
+        // writes right after the creation grow the same event
+        journal.record(300_000, ckpt.clone(), Kind::Modify, false, Some(4096));
+        journal.record(600_000, ckpt.clone(), Kind::Modify, false, Some(8192));
+        assert_eq!(journal.events.len(), 1);
+        assert_eq!(journal.events[0].size, Some(8192));
+        assert_eq!(journal.events[0].size_delta, 8192);
+
+        let alert = journal.record(5_000_000, ckpt.clone(), Kind::Modify, false, Some(100));
+        assert_eq!(
+            alert,
+            Some((
+                "files.truncated",
+                "/ckpt/step-100/model.pt: 8192 -> 100 bytes".to_string()
+            ))
+        );
+        // a truncation is reported once per event
+        assert_eq!(
+            journal.record(5_100_000, ckpt.clone(), Kind::Modify, false, Some(50)),
+            None
+        );
+        let event = journal.events.back().unwrap();
+        assert!(event.truncated);
+        assert_eq!(event.size_delta, -8142);
+
+        assert_eq!(
+            journal.record(9_000_000, ckpt.clone(), Kind::Delete, false, None),
+            Some(("files.deleted", ckpt.clone()))
+        );
+        let event = journal.events.back().unwrap();
+        assert_eq!((event.size, event.size_delta), (None, -50));
+        assert!(journal.sizes.is_empty());
+        assert_eq!(journal.events.len(), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_events() {
+        let mut buf = vec![];
+        for (wd, mask, name) in [(1, libc::IN_CREATE, "a.pt"), (2, libc::IN_DELETE, "")] {
+            let padded = if name.is_empty() { 0 } else { 16 };
+            let event = libc::inotify_event {
+                wd,
+                mask,
+                cookie: 0,
+                len: padded,
+            };
+            let header = unsafe {
+                std::slice::from_raw_parts(
+                    &event as *const _ as *const u8,
+                    std::mem::size_of::<libc::inotify_event>(),
+                )
+            };
+            buf.extend_from_slice(header);
+            let mut name = name.as_bytes().to_vec();
+            name.resize(padded as usize, 0);
+            buf.extend_from_slice(&name);
+        }
+        let events = inotify::parse_events(&buf);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], (1, libc::IN_CREATE, "a.pt".into()));
+        assert_eq!(events[1], (2, libc::IN_DELETE, "".into()));
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use async_trait::async_trait;
@@ -57,13 +58,21 @@ pub type FilesPlugin = NamespacePluginHelper<FileList>;
 use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
+use probing_core::core::Maybe;
 
+use super::file_events::{self, FileEventsPlugin};
+
+/// CSV files of the working directory, and the changes under watched paths
 #[derive(Debug, Default, EngineExtension)]
-pub struct FilesExtension {}
+pub struct FilesExtension {
+    /// Comma separated files and directories to record changes of in
+    /// `files.events`, e.g. the checkpoint directory and the dataset index
+    #[option()]
+    watch: Maybe<String>,
+}
 
 impl EngineCall for FilesExtension {}
 
-#[allow(unused)]
 impl EngineDatasource for FilesExtension {
     fn datasrc(
         &self,
@@ -71,8 +80,26 @@ impl EngineDatasource for FilesExtension {
         name: Option<&str>,
     ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
         match name {
-            Some(name) => Some(FilesPlugin::create(namespace)),
+            Some("events") => Some(FileEventsPlugin::create(namespace, "events")),
+            Some(_) => Some(FilesPlugin::create(namespace)),
             None => None,
         }
     }
 }
+
+impl FilesExtension {
+    fn set_watch(&mut self, watch: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = watch.clone().into();
+        let paths = value
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect::<Vec<_>>();
+        file_events::watch(paths).map_err(|e| {
+            EngineError::InvalidOptionValue(Self::OPTION_WATCH.to_string(), e.to_string())
+        })?;
+        self.watch = watch;
+        Ok(())
+    }
+}
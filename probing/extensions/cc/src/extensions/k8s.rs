@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{GenericStringBuilder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+
+use probing_core::core::k8s;
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, FieldDoc, TablePluginHelper};
+
+/// Kubernetes pod and container of the process, no row outside Kubernetes
+#[derive(Default, Debug)]
+pub struct K8sTable {}
+
+impl CustomTable for K8sTable {
+    fn name() -> &'static str {
+        "k8s"
+    }
+
+    fn description() -> &'static str {
+        "Kubernetes pod, namespace, node and container of the process"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("pod", DataType::Utf8, true),
+            Field::new("namespace", DataType::Utf8, true),
+            Field::new("node", DataType::Utf8, true)
+                .with_doc("Node of the pod, from the `NODE_NAME` downward API variable"),
+            Field::new("pod_uid", DataType::Utf8, true),
+            Field::new("container_id", DataType::Utf8, true)
+                .with_doc("Id of the container given by its runtime"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let mut pods = GenericStringBuilder::<i32>::new();
+        let mut namespaces = GenericStringBuilder::<i32>::new();
+        let mut nodes = GenericStringBuilder::<i32>::new();
+        let mut uids = GenericStringBuilder::<i32>::new();
+        let mut containers = GenericStringBuilder::<i32>::new();
+
+        if let Some(info) = k8s::detect_self() {
+            pods.append_option(info.pod);
+            namespaces.append_option(info.namespace);
+            nodes.append_option(info.node);
+            uids.append_option(info.pod_uid);
+            containers.append_option(info.container_id);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(pods.finish()),
+                Arc::new(namespaces.finish()),
+                Arc::new(nodes.finish()),
+                Arc::new(uids.finish()),
+                Arc::new(containers.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type K8sPlugin = TablePluginHelper<K8sTable>;
+
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+
+#[derive(Debug, Default, EngineExtension)]
+pub struct K8sExtension {}
+
+impl EngineCall for K8sExtension {}
+
+impl EngineDatasource for K8sExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| K8sPlugin::create(namespace, name))
+    }
+}
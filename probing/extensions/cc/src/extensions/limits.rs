@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use datafusion::arrow::array::{GenericStringBuilder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, TablePluginHelper};
+
+pub(crate) const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Resource limits in effect for the process: cpu affinity, cgroup quotas,
+/// rlimits and OOM score, with the file each value was read from.
+#[derive(Default, Debug)]
+pub struct LimitsTable {}
+
+/// A `(name, value, source)` row of `process.limits`
+type Limit = (String, String, String);
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Parse `/proc/<pid>/cgroup` into `(controllers, path)` pairs; the unified
+/// cgroup v2 hierarchy has no controllers.
+pub(crate) fn parse_cgroups(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let _id = fields.next()?;
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Parse `/proc/<pid>/limits` into `(resource, soft, hard)`, e.g.
+/// `("open_files", "1024", "1048576")`
+fn parse_rlimits(content: &str) -> Vec<(String, String, String)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            // columns are aligned: name, soft, hard and an optional unit
+            let name = line.get(..26)?.trim();
+            let mut values = line.get(26..)?.split_whitespace();
+            let soft = values.next()?.to_string();
+            let hard = values.next()?.to_string();
+            let name = name
+                .trim_start_matches("Max ")
+                .to_lowercase()
+                .replace(' ', "_");
+            Some((name, soft, hard))
+        })
+        .collect()
+}
+
+/// Directory of a cgroup, falling back to the hierarchy root when the
+/// process path is not visible, as inside most containers
+pub(crate) fn cgroup_dir(hierarchy: &Path, path: &str) -> PathBuf {
+    let dir = hierarchy.join(path.trim_start_matches('/'));
+    if dir.exists() {
+        dir
+    } else {
+        hierarchy.to_path_buf()
+    }
+}
+
+fn push_file(limits: &mut Vec<Limit>, name: &str, path: PathBuf) {
+    if let Some(value) = read_trimmed(&path) {
+        limits.push((name.to_string(), value, path.to_string_lossy().to_string()));
+    }
+}
+
+fn cgroup_limits(limits: &mut Vec<Limit>) {
+    let content = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let root = Path::new(CGROUP_ROOT);
+    for (controllers, path) in parse_cgroups(&content) {
+        if controllers.is_empty() {
+            let dir = cgroup_dir(root, &path);
+            push_file(limits, "cgroup.cpuset", dir.join("cpuset.cpus.effective"));
+            push_file(limits, "cgroup.cpu.max", dir.join("cpu.max"));
+            push_file(limits, "cgroup.memory.max", dir.join("memory.max"));
+            push_file(limits, "cgroup.memory.current", dir.join("memory.current"));
+            continue;
+        }
+        for controller in controllers.split(',') {
+            let dir = cgroup_dir(&root.join(controller), &path);
+            match controller {
+                "cpuset" => push_file(limits, "cgroup.cpuset", dir.join("cpuset.effective_cpus")),
+                "cpu" => {
+                    push_file(
+                        limits,
+                        "cgroup.cpu.cfs_quota_us",
+                        dir.join("cpu.cfs_quota_us"),
+                    );
+                    push_file(
+                        limits,
+                        "cgroup.cpu.cfs_period_us",
+                        dir.join("cpu.cfs_period_us"),
+                    );
+                }
+                "memory" => {
+                    push_file(
+                        limits,
+                        "cgroup.memory.max",
+                        dir.join("memory.limit_in_bytes"),
+                    );
+                    push_file(
+                        limits,
+                        "cgroup.memory.current",
+                        dir.join("memory.usage_in_bytes"),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn process_limits() -> Vec<Limit> {
+    let mut limits = vec![];
+
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if let Some(cpus) = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+    {
+        limits.push((
+            "affinity".to_string(),
+            cpus.trim().to_string(),
+            "/proc/self/status".to_string(),
+        ));
+    }
+
+    cgroup_limits(&mut limits);
+
+    let rlimits = std::fs::read_to_string("/proc/self/limits").unwrap_or_default();
+    for (name, soft, hard) in parse_rlimits(&rlimits) {
+        let source = "/proc/self/limits".to_string();
+        limits.push((format!("rlimit.{name}.soft"), soft, source.clone()));
+        limits.push((format!("rlimit.{name}.hard"), hard, source));
+    }
+
+    push_file(&mut limits, "oom_score", "/proc/self/oom_score".into());
+    push_file(
+        &mut limits,
+        "oom_score_adj",
+        "/proc/self/oom_score_adj".into(),
+    );
+    limits
+}
+
+impl CustomTable for LimitsTable {
+    fn name() -> &'static str {
+        "limits"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("value", DataType::Utf8, true),
+            Field::new("source", DataType::Utf8, true),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let mut names = GenericStringBuilder::<i32>::new();
+        let mut values = GenericStringBuilder::<i32>::new();
+        let mut sources = GenericStringBuilder::<i32>::new();
+
+        for (name, value, source) in process_limits() {
+            names.append_value(name);
+            values.append_value(value);
+            sources.append_value(source);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(names.finish()),
+                Arc::new(values.finish()),
+                Arc::new(sources.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type LimitsPlugin = TablePluginHelper<LimitsTable>;
+
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+
+#[derive(Debug, Default, EngineExtension)]
+pub struct LimitsExtension {}
+
+impl EngineCall for LimitsExtension {}
+
+impl EngineDatasource for LimitsExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| LimitsPlugin::create(namespace, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        let cgroups = parse_cgroups("12:cpu,cpuacct:/job/1\n0::/system.slice/train.service\n");
+        assert_eq!(
+            cgroups,
+            vec![
+                ("cpu,cpuacct".to_string(), "/job/1".to_string()),
+                ("".to_string(), "/system.slice/train.service".to_string()),
+            ]
+        );
+
+        let rlimits = parse_rlimits(concat!(
+            "Limit                     Soft Limit           Hard Limit           Units     \n",
+            "Max cpu time              unlimited            unlimited            seconds   \n",
+            "Max open files            1024                 1048576              files     \n",
+        ));
+        assert_eq!(
+            rlimits,
+            vec![
+                (
+                    "cpu_time".to_string(),
+                    "unlimited".to_string(),
+                    "unlimited".to_string()
+                ),
+                (
+                    "open_files".to_string(),
+                    "1024".to_string(),
+                    "1048576".to_string()
+                ),
+            ]
+        );
+    }
+}
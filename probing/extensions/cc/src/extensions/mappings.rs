@@ -0,0 +1,317 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use datafusion::arrow::array::{GenericStringBuilder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, FieldDoc, TablePluginHelper};
+
+/// Memory of the process grouped by what is mapped: each shared library,
+/// file, shared memory segment, device, the heap, stacks and anonymous
+/// regions, with RSS growth since the previous and the first query.
+///
+/// A `[malloc]` row adds the glibc allocator's view; its memory lives inside
+/// `[heap]` and `[anon]` and must not be added to them.
+#[derive(Default, Debug)]
+pub struct MemoryByMappingTable {}
+
+/// Memory of all regions with the same mapping, sizes in KiB
+#[derive(Debug, Default, Clone, PartialEq)]
+struct MappingUsage {
+    kind: &'static str,
+    path: String,
+    regions: i64,
+    size: i64,
+    rss: i64,
+    anonymous: i64,
+    swap: i64,
+}
+
+/// RSS of each mapping at the first and at the previous query
+#[derive(Debug, Default)]
+struct History {
+    first: HashMap<String, i64>,
+    previous: HashMap<String, i64>,
+}
+
+static HISTORY: Lazy<Mutex<Option<History>>> = Lazy::new(|| Mutex::new(None));
+
+/// Name and kind of a mapping from the path column of `/proc/self/smaps`
+fn classify(path: &str) -> (String, &'static str) {
+    let basename = || path.rsplit('/').next().unwrap_or(path).to_string();
+    match path {
+        "" => ("[anon]".to_string(), "anon"),
+        "[heap]" => (path.to_string(), "heap"),
+        _ if path.starts_with("[stack") => ("[stack]".to_string(), "stack"),
+        _ if path.starts_with("[anon") => (path.to_string(), "anon"),
+        _ if path.starts_with('[') => (path.to_string(), "special"),
+        _ if path.starts_with("/dev/shm/") || path.starts_with("/SYSV") => (basename(), "shm"),
+        _ if path.starts_with("/dev/") => (path.to_string(), "device"),
+        _ if path.contains(".so") => (basename(), "library"),
+        _ => (basename(), "file"),
+    }
+}
+
+fn is_region_header(line: &str) -> bool {
+    line.split_once(' ')
+        .and_then(|(range, _)| range.split_once('-'))
+        .is_some_and(|(start, end)| {
+            u64::from_str_radix(start, 16).is_ok() && u64::from_str_radix(end, 16).is_ok()
+        })
+}
+
+/// Parse `/proc/self/smaps` into usage per mapping name
+fn parse_smaps(content: &str) -> BTreeMap<String, MappingUsage> {
+    let mut usages: BTreeMap<String, MappingUsage> = BTreeMap::new();
+    let mut current: Option<String> = None;
+    for line in content.lines() {
+        if is_region_header(line) {
+            // range perms offset dev inode [path], the path may contain spaces
+            let path = line
+                .splitn(6, ' ')
+                .nth(5)
+                .map(|p| p.trim().trim_end_matches(" (deleted)"))
+                .unwrap_or_default();
+            let (name, kind) = classify(path);
+            let usage = usages.entry(name.clone()).or_default();
+            usage.kind = kind;
+            if kind != "anon" && kind != "stack" && kind != "heap" {
+                usage.path = path.to_string();
+            }
+            usage.regions += 1;
+            current = Some(name);
+            continue;
+        }
+        let Some(usage) = current.as_ref().and_then(|name| usages.get_mut(name)) else {
+            continue;
+        };
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(kb) = value
+            .trim()
+            .strip_suffix("kB")
+            .and_then(|v| v.trim().parse::<i64>().ok())
+        else {
+            continue;
+        };
+        match key {
+            "Size" => usage.size += kb,
+            "Rss" => usage.rss += kb,
+            "Anonymous" => usage.anonymous += kb,
+            "Swap" => usage.swap += kb,
+            _ => {}
+        }
+    }
+    usages
+}
+
+/// Allocator statistics as `(arena, hblks, hblkhd, uordblks)` in bytes.
+///
+/// `mallinfo2` is looked up at runtime since it only exists from glibc 2.33,
+/// and the release builds also run on glibc 2.17. The `int` fields of the
+/// older `mallinfo` wrap past 2 GiB, they are read as unsigned to get up to
+/// 4 GiB right.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn malloc_stats() -> (usize, usize, usize, usize) {
+    type Mallinfo2 = unsafe extern "C" fn() -> libc::mallinfo2;
+
+    // SAFETY: the name is a nul terminated string
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"mallinfo2".as_ptr()) };
+    if !symbol.is_null() {
+        // SAFETY: `mallinfo2` of glibc has this signature and only reads
+        // allocator statistics
+        let info = unsafe { std::mem::transmute::<*mut libc::c_void, Mallinfo2>(symbol)() };
+        return (info.arena, info.hblks, info.hblkhd, info.uordblks);
+    }
+    // SAFETY: mallinfo only reads allocator statistics
+    #[allow(deprecated)]
+    let info = unsafe { libc::mallinfo() };
+    let bytes = |field: libc::c_int| field as u32 as usize;
+    (
+        bytes(info.arena),
+        bytes(info.hblks),
+        bytes(info.hblkhd),
+        bytes(info.uordblks),
+    )
+}
+
+/// In-use and total bytes of the glibc allocator as a `[malloc]` row
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn malloc_usage() -> Option<MappingUsage> {
+    let (arena, hblks, hblkhd, uordblks) = malloc_stats();
+    Some(MappingUsage {
+        kind: "allocator",
+        path: String::new(),
+        regions: hblks as i64 + 1,
+        size: ((arena + hblkhd) / 1024) as i64,
+        rss: ((uordblks + hblkhd) / 1024) as i64,
+        anonymous: 0,
+        swap: 0,
+    })
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn malloc_usage() -> Option<MappingUsage> {
+    None
+}
+
+impl CustomTable for MemoryByMappingTable {
+    fn name() -> &'static str {
+        "memory_by_mapping"
+    }
+
+    fn description() -> &'static str {
+        "Memory of the process attributed to its mappings and the allocator"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("mapping", DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("path", DataType::Utf8, true),
+            Field::new("regions", DataType::Int64, false),
+            Field::new("size_kb", DataType::Int64, false)
+                .with_doc("Virtual size")
+                .with_unit("KiB"),
+            Field::new("rss_kb", DataType::Int64, false)
+                .with_doc("Resident set size")
+                .with_unit("KiB"),
+            Field::new("anonymous_kb", DataType::Int64, false).with_unit("KiB"),
+            Field::new("swap_kb", DataType::Int64, false).with_unit("KiB"),
+            Field::new("rss_delta_kb", DataType::Int64, false)
+                .with_doc("RSS change since the previous query")
+                .with_unit("KiB"),
+            Field::new("rss_growth_kb", DataType::Int64, false)
+                .with_doc("RSS change since the first query")
+                .with_unit("KiB"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap_or_default();
+        let mut usages = parse_smaps(&smaps);
+        if let Some(malloc) = malloc_usage() {
+            usages.insert("[malloc]".to_string(), malloc);
+        }
+
+        let rss = usages
+            .iter()
+            .map(|(name, usage)| (name.clone(), usage.rss))
+            .collect::<HashMap<_, _>>();
+        let (previous, first) = match HISTORY.lock() {
+            Ok(mut history) => {
+                let history = history.get_or_insert_with(|| History {
+                    first: rss.clone(),
+                    previous: rss.clone(),
+                });
+                let previous = std::mem::replace(&mut history.previous, rss);
+                (previous, history.first.clone())
+            }
+            Err(_) => (HashMap::new(), HashMap::new()),
+        };
+
+        let mut mappings = GenericStringBuilder::<i32>::new();
+        let mut kinds = GenericStringBuilder::<i32>::new();
+        let mut paths = GenericStringBuilder::<i32>::new();
+        let mut regions = Int64Builder::new();
+        let mut sizes = Int64Builder::new();
+        let mut rsses = Int64Builder::new();
+        let mut anonymous = Int64Builder::new();
+        let mut swaps = Int64Builder::new();
+        let mut deltas = Int64Builder::new();
+        let mut growths = Int64Builder::new();
+        for (name, usage) in usages {
+            // mappings that appeared since count as grown from nothing
+            deltas.append_value(usage.rss - previous.get(&name).copied().unwrap_or_default());
+            growths.append_value(usage.rss - first.get(&name).copied().unwrap_or_default());
+            mappings.append_value(name);
+            kinds.append_value(usage.kind);
+            paths.append_value(usage.path);
+            regions.append_value(usage.regions);
+            sizes.append_value(usage.size);
+            rsses.append_value(usage.rss);
+            anonymous.append_value(usage.anonymous);
+            swaps.append_value(usage.swap);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(mappings.finish()),
+                Arc::new(kinds.finish()),
+                Arc::new(paths.finish()),
+                Arc::new(regions.finish()),
+                Arc::new(sizes.finish()),
+                Arc::new(rsses.finish()),
+                Arc::new(anonymous.finish()),
+                Arc::new(swaps.finish()),
+                Arc::new(deltas.finish()),
+                Arc::new(growths.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type MemoryByMappingPlugin = TablePluginHelper<MemoryByMappingTable>;
+
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+
+#[derive(Debug, Default, EngineExtension)]
+pub struct MappingsExtension {}
+
+impl EngineCall for MappingsExtension {}
+
+impl EngineDatasource for MappingsExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| MemoryByMappingPlugin::create(namespace, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_smaps() {
+        let smaps = concat!(
+            "7f0000000000-7f0000100000 r-xp 00000000 08:01 1234   /usr/lib/libnccl.so.2\n",
+            "Size:               1024 kB\n",
+            "Rss:                 512 kB\n",
+            "Anonymous:             0 kB\n",
+            "7f0000100000-7f0000200000 rw-p 00100000 08:01 1234   /usr/lib/libnccl.so.2\n",
+            "Size:               1024 kB\n",
+            "Rss:                 100 kB\n",
+            "Anonymous:            64 kB\n",
+            "VmFlags: rd wr mr mw me ac\n",
+            "55d000000000-55d000400000 rw-p 00000000 00:00 0      [heap]\n",
+            "Rss:                4096 kB\n",
+            "7f1000000000-7f1000200000 rw-s 00000000 00:1a 77     /dev/shm/nccl-AbCd (deleted)\n",
+            "Rss:                2048 kB\n",
+            "7f2000000000-7f2000100000 rw-p 00000000 00:00 0 \n",
+            "Rss:                  16 kB\n",
+            "Swap:                  8 kB\n",
+        );
+        let usages = parse_smaps(smaps);
+        assert_eq!(usages.len(), 4);
+
+        let nccl = &usages["libnccl.so.2"];
+        assert_eq!(nccl.kind, "library");
+        assert_eq!(nccl.path, "/usr/lib/libnccl.so.2");
+        assert_eq!((nccl.regions, nccl.size, nccl.rss), (2, 2048, 612));
+        assert_eq!(nccl.anonymous, 64);
+
+        assert_eq!(usages["[heap]"].rss, 4096);
+        assert_eq!(usages["nccl-AbCd"].kind, "shm");
+        assert_eq!(usages["[anon]"].swap, 8);
+    }
+}
@@ -0,0 +1,604 @@
+//! Memory pressure of the process and early warning of the OOM killer.
+//!
+//! Every `memory.interval` seconds the PSI memory pressure (the cgroup's
+//! `memory.pressure`, or `/proc/pressure/memory` outside a cgroup v2) and the
+//! cgroup's usage, limit and `memory.events` counters are sampled into the
+//! `memory.pressure` time series. A `memory.warning` event is published when
+//! the share of time all tasks stalled on memory over the last 10 seconds
+//! rises to `memory.warn_pressure` percent, when the usage reaches
+//! `memory.warn_usage` of the limit, or when the cgroup hits its limit, so
+//! that a rule can take a heap snapshot while the process is still alive:
+//!
+//! ```sql
+//! SET probing.memory.interval = 5;
+//! SET probing.events.rule.oom_snapshot = 'memory.warning @heap_snapshot';
+//! ```
+//!
+//! Kills by the OOM killer within the cgroup are published as
+//! `memory.oom_kill`; when the process itself was killed there is nothing
+//! left to publish them, but the snapshot taken on the warning remains in the
+//! bundles and archives written before.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use datafusion::arrow::array::{Float64Builder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::ordering::{first_seq, SEQ};
+use probing_core::core::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    FieldDoc, Maybe, TablePluginHelper,
+};
+
+use super::limits::{cgroup_dir, parse_cgroups, CGROUP_ROOT};
+
+/// Samples kept in `memory.pressure`
+const MAX_SAMPLES: usize = 3600;
+
+/// Default `memory.warn_pressure`, in percent of the time
+const DEFAULT_WARN_PRESSURE: f64 = 10.0;
+
+/// Default `memory.warn_usage`, as a fraction of the cgroup limit
+const DEFAULT_WARN_USAGE: f64 = 0.9;
+
+/// Limits of cgroup v1 at or above this are "no limit"
+const UNLIMITED: i64 = 1 << 62;
+
+/// Sample interval in seconds, 0 when monitoring is off
+static INTERVAL_S: AtomicU64 = AtomicU64::new(0);
+static SAMPLING: AtomicBool = AtomicBool::new(false);
+static THRESHOLDS: Lazy<RwLock<Thresholds>> = Lazy::new(Default::default);
+static SAMPLES: Lazy<Mutex<VecDeque<Sample>>> = Lazy::new(Default::default);
+/// Samples ever taken, numbering them for `memory.pressure`
+static TAKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Pressure stall information: the share of time some or all non-idle tasks
+/// waited for memory, in percent, and the total of those waits
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Psi {
+    some_avg10: f64,
+    some_avg60: f64,
+    /// Microseconds
+    some_total: i64,
+    full_avg10: f64,
+    full_avg60: f64,
+    /// Microseconds
+    full_total: i64,
+}
+
+/// Counters of `memory.events` since the cgroup was created
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct MemoryEvents {
+    /// Usage went over `memory.high` and was throttled
+    high: i64,
+    /// Usage hit `memory.max` and had to be reclaimed
+    max: i64,
+    /// Allocations failed at the limit, the OOM killer being about to run
+    oom: i64,
+    /// Processes killed by the OOM killer
+    oom_kill: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Sample {
+    /// Microseconds since epoch
+    timestamp: i64,
+    psi: Option<Psi>,
+    /// Bytes charged to the cgroup
+    usage: Option<i64>,
+    /// Bytes the cgroup may use, None without a limit
+    limit: Option<i64>,
+    events: Option<MemoryEvents>,
+}
+
+impl Sample {
+    fn usage_ratio(&self) -> Option<f64> {
+        match (self.usage, self.limit) {
+            (Some(usage), Some(limit)) if limit > 0 => Some(usage as f64 / limit as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Levels raising a `memory.warning`, 0 turning a check off
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Thresholds {
+    pressure: f64,
+    usage: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            pressure: DEFAULT_WARN_PRESSURE,
+            usage: DEFAULT_WARN_USAGE,
+        }
+    }
+}
+
+/// `some avg10=0.00 avg60=0.00 avg300=0.00 total=0` lines of a PSI file
+fn parse_psi(content: &str) -> Option<Psi> {
+    let mut psi = Psi::default();
+    let mut found = false;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(kind) = fields.next() else {
+            continue;
+        };
+        let (mut avg10, mut avg60, mut total) = (0.0, 0.0, 0);
+        for field in fields {
+            match field.split_once('=') {
+                Some(("avg10", value)) => avg10 = value.parse().ok()?,
+                Some(("avg60", value)) => avg60 = value.parse().ok()?,
+                Some(("total", value)) => total = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        match kind {
+            "some" => (psi.some_avg10, psi.some_avg60, psi.some_total) = (avg10, avg60, total),
+            "full" => (psi.full_avg10, psi.full_avg60, psi.full_total) = (avg10, avg60, total),
+            _ => continue,
+        }
+        found = true;
+    }
+    found.then_some(psi)
+}
+
+/// `name value` lines of `memory.events`
+fn parse_events(content: &str) -> MemoryEvents {
+    let mut events = MemoryEvents::default();
+    for line in content.lines() {
+        let Some((name, value)) = line.split_once(' ') else {
+            continue;
+        };
+        let value = value.trim().parse().unwrap_or_default();
+        match name {
+            "high" => events.high = value,
+            "max" => events.max = value,
+            "oom" => events.oom = value,
+            "oom_kill" => events.oom_kill = value,
+            _ => {}
+        }
+    }
+    events
+}
+
+/// `max`, or a number of bytes
+fn parse_limit(content: &str) -> Option<i64> {
+    content
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|limit| *limit < UNLIMITED)
+}
+
+/// Files a sample is read from
+#[derive(Debug, Default)]
+struct Sources {
+    pressure: Option<PathBuf>,
+    usage: Option<PathBuf>,
+    limit: Option<PathBuf>,
+    events: Option<PathBuf>,
+}
+
+impl Sources {
+    fn locate() -> Self {
+        let content = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        let root = Path::new(CGROUP_ROOT);
+        let mut sources = Sources::default();
+        for (controllers, path) in parse_cgroups(&content) {
+            if controllers.is_empty() {
+                let dir = cgroup_dir(root, &path);
+                if dir.join("memory.current").exists() {
+                    sources.pressure = Some(dir.join("memory.pressure"));
+                    sources.usage = Some(dir.join("memory.current"));
+                    sources.limit = Some(dir.join("memory.max"));
+                    sources.events = Some(dir.join("memory.events"));
+                }
+            } else if controllers.split(',').any(|c| c == "memory") {
+                let dir = cgroup_dir(&root.join("memory"), &path);
+                sources.usage = Some(dir.join("memory.usage_in_bytes"));
+                sources.limit = Some(dir.join("memory.limit_in_bytes"));
+            }
+        }
+        if !sources.pressure.as_ref().is_some_and(|p| p.exists()) {
+            sources.pressure = Some(PathBuf::from("/proc/pressure/memory"));
+        }
+        sources
+    }
+
+    fn sample(&self) -> Sample {
+        let read = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+        };
+        Sample {
+            timestamp: now_us(),
+            psi: read(&self.pressure).and_then(|c| parse_psi(&c)),
+            usage: read(&self.usage).and_then(|c| c.trim().parse().ok()),
+            limit: read(&self.limit).and_then(|c| parse_limit(&c)),
+            events: read(&self.events).map(|c| parse_events(&c)),
+        }
+    }
+}
+
+static SOURCES: Lazy<Sources> = Lazy::new(Sources::locate);
+
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Warnings raised by `sample` following `last`: thresholds are reported
+/// when crossed, not for as long as they stay crossed
+fn warnings(last: Option<&Sample>, sample: &Sample, thresholds: Thresholds) -> Vec<String> {
+    let mut warnings = vec![];
+    let pressure = |s: &Sample| s.psi.map(|psi| psi.full_avg10).unwrap_or_default();
+    if thresholds.pressure > 0.0
+        && pressure(sample) >= thresholds.pressure
+        && last.is_none_or(|last| pressure(last) < thresholds.pressure)
+    {
+        warnings.push(format!(
+            "memory pressure: all tasks stalled {:.1}% of the last 10s",
+            pressure(sample)
+        ));
+    }
+    let usage = |s: &Sample| s.usage_ratio().unwrap_or_default();
+    if thresholds.usage > 0.0
+        && usage(sample) >= thresholds.usage
+        && last.is_none_or(|last| usage(last) < thresholds.usage)
+    {
+        warnings.push(format!(
+            "memory usage: {} of {} bytes ({:.0}%)",
+            sample.usage.unwrap_or_default(),
+            sample.limit.unwrap_or_default(),
+            usage(sample) * 100.0
+        ));
+    }
+    if let (Some(before), Some(now)) = (last.and_then(|s| s.events), sample.events) {
+        if now.max > before.max {
+            warnings.push(format!("memory limit hit {} times", now.max - before.max));
+        }
+    }
+    warnings
+}
+
+/// OOM kills in the cgroup between `last` and `sample`
+fn oom_kills(last: Option<&Sample>, sample: &Sample) -> i64 {
+    match (last.and_then(|s| s.events), sample.events) {
+        (Some(before), Some(now)) => now.oom_kill - before.oom_kill,
+        _ => 0,
+    }
+}
+
+fn sample_once() {
+    let sample = SOURCES.sample();
+    let thresholds = THRESHOLDS.read().map(|t| *t).unwrap_or_default();
+    let Ok(mut samples) = SAMPLES.lock() else {
+        return;
+    };
+    let last = samples.back();
+    let warnings = warnings(last, &sample, thresholds);
+    let kills = oom_kills(last, &sample);
+    samples.push_back(sample);
+    TAKEN.fetch_add(1, Ordering::Relaxed);
+    while samples.len() > MAX_SAMPLES {
+        samples.pop_front();
+    }
+    drop(samples);
+
+    for warning in warnings {
+        log::warn!("{warning}");
+        probing_core::core::events::publish("memory.warning", "memory", &warning);
+    }
+    if kills > 0 {
+        let message = format!("{kills} processes killed by the OOM killer");
+        log::warn!("{message}");
+        probing_core::core::events::publish("memory.oom_kill", "memory", &message);
+    }
+}
+
+/// Start the sampling thread unless it is running; it stops by itself once
+/// the interval is set back to 0
+fn start_sampling() -> std::io::Result<()> {
+    if SAMPLING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let spawned = std::thread::Builder::new()
+        .name("probing-memory".to_string())
+        .spawn(|| {
+            loop {
+                let interval = INTERVAL_S.load(Ordering::Relaxed);
+                if interval == 0 {
+                    break;
+                }
+                if !probing_core::core::killswitch::is_dormant() {
+                    sample_once();
+                }
+                std::thread::sleep(Duration::from_secs(interval));
+            }
+            SAMPLING.store(false, Ordering::SeqCst);
+        });
+    if let Err(err) = spawned {
+        SAMPLING.store(false, Ordering::SeqCst);
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+pub struct PressureTable {}
+
+impl CustomTable for PressureTable {
+    fn name() -> &'static str {
+        "pressure"
+    }
+
+    fn description() -> &'static str {
+        "Memory pressure, usage and cgroup memory events, sampled every memory.interval seconds"
+    }
+
+    fn ordering() -> Option<&'static str> {
+        Some(SEQ)
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new(SEQ, DataType::Int64, false),
+            Field::new("timestamp", DataType::Int64, false).with_unit("us"),
+            Field::new("some_avg10", DataType::Float64, true)
+                .with_doc("Share of the last 10s some tasks stalled on memory")
+                .with_unit("%"),
+            Field::new("some_avg60", DataType::Float64, true).with_unit("%"),
+            Field::new("full_avg10", DataType::Float64, true)
+                .with_doc("Share of the last 10s all tasks stalled on memory")
+                .with_unit("%"),
+            Field::new("full_avg60", DataType::Float64, true).with_unit("%"),
+            Field::new("full_total", DataType::Int64, true)
+                .with_doc("Time all tasks stalled on memory so far")
+                .with_unit("us"),
+            Field::new("usage", DataType::Int64, true)
+                .with_doc("Memory charged to the cgroup")
+                .with_unit("bytes"),
+            Field::new("limit", DataType::Int64, true)
+                .with_doc("Memory limit of the cgroup, NULL without one")
+                .with_unit("bytes"),
+            Field::new("high", DataType::Int64, true)
+                .with_doc("Times the usage went over memory.high and was throttled"),
+            Field::new("max", DataType::Int64, true)
+                .with_doc("Times the usage hit memory.max and had to be reclaimed"),
+            Field::new("oom", DataType::Int64, true)
+                .with_doc("Allocations that failed at the limit"),
+            Field::new("oom_kill", DataType::Int64, true)
+                .with_doc("Processes of the cgroup killed by the OOM killer"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let Ok(samples) = SAMPLES.lock() else {
+            return vec![];
+        };
+        let first = first_seq(TAKEN.load(Ordering::Relaxed), samples.len());
+
+        let mut seqs = Int64Builder::new();
+        let mut timestamps = Int64Builder::new();
+        let mut some_avg10 = Float64Builder::new();
+        let mut some_avg60 = Float64Builder::new();
+        let mut full_avg10 = Float64Builder::new();
+        let mut full_avg60 = Float64Builder::new();
+        let mut full_total = Int64Builder::new();
+        let mut usage = Int64Builder::new();
+        let mut limit = Int64Builder::new();
+        let mut high = Int64Builder::new();
+        let mut max = Int64Builder::new();
+        let mut oom = Int64Builder::new();
+        let mut oom_kill = Int64Builder::new();
+        for (seq, sample) in (first..).zip(samples.iter()) {
+            seqs.append_value(seq);
+            timestamps.append_value(sample.timestamp);
+            some_avg10.append_option(sample.psi.map(|p| p.some_avg10));
+            some_avg60.append_option(sample.psi.map(|p| p.some_avg60));
+            full_avg10.append_option(sample.psi.map(|p| p.full_avg10));
+            full_avg60.append_option(sample.psi.map(|p| p.full_avg60));
+            full_total.append_option(sample.psi.map(|p| p.full_total));
+            usage.append_option(sample.usage);
+            limit.append_option(sample.limit);
+            high.append_option(sample.events.map(|e| e.high));
+            max.append_option(sample.events.map(|e| e.max));
+            oom.append_option(sample.events.map(|e| e.oom));
+            oom_kill.append_option(sample.events.map(|e| e.oom_kill));
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(seqs.finish()),
+                Arc::new(timestamps.finish()),
+                Arc::new(some_avg10.finish()),
+                Arc::new(some_avg60.finish()),
+                Arc::new(full_avg10.finish()),
+                Arc::new(full_avg60.finish()),
+                Arc::new(full_total.finish()),
+                Arc::new(usage.finish()),
+                Arc::new(limit.finish()),
+                Arc::new(high.finish()),
+                Arc::new(max.finish()),
+                Arc::new(oom.finish()),
+                Arc::new(oom_kill.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type PressurePlugin = TablePluginHelper<PressureTable>;
+
+/// Memory pressure monitoring and OOM early warning
+#[derive(Debug, EngineExtension)]
+pub struct MemoryExtension {
+    /// Seconds between samples of the memory pressure, 0 to stop monitoring
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Percent of the last 10s all tasks stalled on memory that raises a
+    /// memory.warning, 0 to not warn on pressure
+    #[option(min = 0, max = 100, aliases = ["warn.pressure"])]
+    warn_pressure: Maybe<f64>,
+
+    /// Fraction of the cgroup limit in use that raises a memory.warning, 0 to
+    /// not warn on usage
+    #[option(min = 0, max = 1, aliases = ["warn.usage"])]
+    warn_usage: Maybe<f64>,
+}
+
+impl Default for MemoryExtension {
+    fn default() -> Self {
+        Self {
+            interval: Maybe::Just(0),
+            warn_pressure: Maybe::Just(DEFAULT_WARN_PRESSURE),
+            warn_usage: Maybe::Just(DEFAULT_WARN_USAGE),
+        }
+    }
+}
+
+impl EngineCall for MemoryExtension {}
+
+impl EngineDatasource for MemoryExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| PressurePlugin::create(namespace, name))
+    }
+}
+
+impl MemoryExtension {
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let seconds = match interval {
+            Maybe::Just(seconds) => seconds,
+            Maybe::Nothing => 0,
+        };
+        INTERVAL_S.store(seconds, Ordering::Relaxed);
+        if seconds > 0 {
+            start_sampling().map_err(|e| {
+                EngineError::InvalidOptionValue(Self::OPTION_INTERVAL.to_string(), e.to_string())
+            })?;
+        }
+        self.interval = interval;
+        Ok(())
+    }
+
+    fn set_warn_pressure(&mut self, warn_pressure: Maybe<f64>) -> Result<(), EngineError> {
+        match warn_pressure {
+            Maybe::Just(percent) if (0.0..=100.0).contains(&percent) => {
+                if let Ok(mut thresholds) = THRESHOLDS.write() {
+                    thresholds.pressure = percent;
+                }
+                self.warn_pressure = warn_pressure;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_WARN_PRESSURE.to_string(),
+                warn_pressure.into(),
+            )),
+        }
+    }
+
+    fn set_warn_usage(&mut self, warn_usage: Maybe<f64>) -> Result<(), EngineError> {
+        match warn_usage {
+            Maybe::Just(fraction) if (0.0..=1.0).contains(&fraction) => {
+                if let Ok(mut thresholds) = THRESHOLDS.write() {
+                    thresholds.usage = fraction;
+                }
+                self.warn_usage = warn_usage;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_WARN_USAGE.to_string(),
+                warn_usage.into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PSI: &str = "some avg10=12.50 avg60=3.20 avg300=0.80 total=123456\n\
+                       full avg10=4.00 avg60=1.10 avg300=0.20 total=65432\n";
+
+    fn sample(full_avg10: f64, usage: i64, max: i64) -> Sample {
+        Sample {
+            timestamp: 0,
+            psi: Some(Psi {
+                full_avg10,
+                ..Default::default()
+            }),
+            usage: Some(usage),
+            limit: Some(1000),
+            events: Some(MemoryEvents {
+                max,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_parse_psi() {
+        let psi = parse_psi(PSI).unwrap();
+        assert_eq!(psi.some_avg10, 12.5);
+        assert_eq!(psi.some_total, 123456);
+        assert_eq!(psi.full_avg60, 1.1);
+        assert_eq!(psi.full_total, 65432);
+        assert_eq!(parse_psi(""), None);
+    }
+
+    #[test]
+    fn test_parse_events_and_limit() {
+        let events = parse_events("low 0\nhigh 3\nmax 7\noom 1\noom_kill 1\noom_group_kill 0\n");
+        assert_eq!(
+            events,
+            MemoryEvents {
+                high: 3,
+                max: 7,
+                oom: 1,
+                oom_kill: 1
+            }
+        );
+        assert_eq!(parse_limit("max\n"), None);
+        assert_eq!(parse_limit("9223372036854771712\n"), None);
+        assert_eq!(parse_limit("4294967296\n"), Some(4294967296));
+    }
+
+    #[test]
+    fn test_warnings_on_crossing() {
+        let thresholds = Thresholds::default();
+        let calm = sample(1.0, 500, 0);
+        assert!(warnings(None, &calm, thresholds).is_empty());
+
+        let stalled = sample(25.0, 950, 0);
+        assert_eq!(warnings(Some(&calm), &stalled, thresholds).len(), 2);
+        // still over the thresholds, already reported
+        assert!(warnings(Some(&stalled), &stalled, thresholds).is_empty());
+
+        let hit = sample(25.0, 950, 2);
+        let reported = warnings(Some(&stalled), &hit, thresholds);
+        assert_eq!(reported, vec!["memory limit hit 2 times".to_string()]);
+
+        let off = Thresholds {
+            pressure: 0.0,
+            usage: 0.0,
+        };
+        assert!(warnings(Some(&calm), &stalled, off).is_empty());
+    }
+}
@@ -3,15 +3,43 @@ pub mod taskstats;
 #[cfg(all(feature = "taskstats", not(target_os = "macos")))]
 pub use taskstats::TaskStatsExtension;
 
+pub mod analysis;
+pub use analysis::AnalysisExtension;
+
 pub mod cluster;
-pub use cluster::ClusterExtension;
+pub use cluster::{ClockExtension, ClusterExtension};
 
 pub mod envs;
 pub use envs::EnvExtension;
 
+pub mod file_events;
 pub mod files;
 pub use files::FilesExtension;
 
+pub mod k8s;
+pub use k8s::K8sExtension;
+
+pub mod limits;
+pub use limits::LimitsExtension;
+
+pub mod mappings;
+pub use mappings::MappingsExtension;
+
+pub mod memory;
+pub use memory::MemoryExtension;
+
+pub mod output;
+pub use output::OutputExtension;
+
+pub mod process_tree;
+pub use process_tree::ProcessTreeExtension;
+
+pub mod sockets;
+pub use sockets::SocketsExtension;
+
+pub mod threads;
+pub use threads::ThreadsExtension;
+
 #[cfg(feature = "kmsg")]
 pub mod kmsg;
 #[cfg(feature = "kmsg")]
@@ -21,3 +49,6 @@ pub use kmsg::KMsgExtension;
 pub mod rdma;
 #[cfg(not(target_os = "macos"))]
 pub use rdma::RdmaExtension;
+
+pub mod data;
+pub use data::DataExtension;
@@ -0,0 +1,396 @@
+//! Capture of the stdout and stderr of the target.
+//!
+//! With `probing.output.capture=true` file descriptors 1 and 2 are swapped for
+//! pipes. A reader thread per stream copies everything back to the original
+//! descriptor, so the output still goes wherever it went before, and keeps the
+//! most recent lines with their timestamps for `process.output`. This makes
+//! `print`-based training logs queryable even when a scheduler swallows them.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use datafusion::arrow::array::{GenericStringBuilder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    FieldDoc, Maybe, TablePluginHelper,
+};
+
+const DEFAULT_MAX_LINES: usize = 10000;
+
+/// Longest line kept, longer ones are split
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+static SEQ: AtomicI64 = AtomicI64::new(0);
+static LINES: Lazy<Mutex<Lines>> = Lazy::new(|| Mutex::new(Lines::new(DEFAULT_MAX_LINES)));
+
+/// Streams being captured
+static TEES: Mutex<Vec<Tee>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq)]
+struct OutputLine {
+    seq: i64,
+    /// Microseconds since epoch at which the line was read
+    timestamp: i64,
+    stream: &'static str,
+    line: String,
+}
+
+#[derive(Debug)]
+struct Lines {
+    lines: VecDeque<OutputLine>,
+    max_lines: usize,
+}
+
+impl Lines {
+    fn new(max_lines: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            max_lines,
+        }
+    }
+
+    fn push(&mut self, line: OutputLine) {
+        while self.lines.len() >= self.max_lines {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Append `chunk` to the unterminated line in `pending` and take the complete
+/// lines out. Carriage returns end lines too, so each refresh of a progress
+/// bar is a line of its own; empty lines are dropped.
+fn split_lines(pending: &mut Vec<u8>, chunk: &[u8]) -> Vec<String> {
+    let mut lines = vec![];
+    for byte in chunk {
+        match byte {
+            b'\n' | b'\r' => {
+                if !pending.is_empty() {
+                    lines.push(String::from_utf8_lossy(pending).to_string());
+                    pending.clear();
+                }
+            }
+            byte => {
+                pending.push(*byte);
+                if pending.len() >= MAX_LINE_BYTES {
+                    lines.push(String::from_utf8_lossy(pending).to_string());
+                    pending.clear();
+                }
+            }
+        }
+    }
+    lines
+}
+
+fn record(stream: &'static str, lines: Vec<String>) {
+    if lines.is_empty() || probing_core::core::killswitch::is_dormant() {
+        return;
+    }
+    let timestamp = now_us();
+    if let Ok(mut buffer) = LINES.lock() {
+        for line in lines {
+            buffer.push(OutputLine {
+                seq: SEQ.fetch_add(1, Ordering::Relaxed),
+                timestamp,
+                stream,
+                line,
+            });
+        }
+    }
+}
+
+/// A stream redirected into a pipe
+#[derive(Debug)]
+struct Tee {
+    stream: &'static str,
+    /// Descriptor of the stream, 1 or 2
+    fd: RawFd,
+    /// Duplicate of the original descriptor, owned by the reader thread
+    saved: RawFd,
+    /// Write end of the pipe
+    write: RawFd,
+}
+
+fn cvt(ret: libc::c_int) -> std::io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// Copy the pipe to the original descriptor until the write end is closed
+fn forward(stream: &'static str, mut pipe: File, mut original: File) {
+    let mut buf = [0u8; 8192];
+    let mut pending = vec![];
+    loop {
+        let n = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        // the target must not notice the capture, so losing the copy is
+        // preferable to blocking it
+        let _ = original.write_all(&buf[..n]);
+        record(stream, split_lines(&mut pending, &buf[..n]));
+    }
+    if !pending.is_empty() {
+        record(stream, vec![String::from_utf8_lossy(&pending).to_string()]);
+    }
+}
+
+fn start_tee(stream: &'static str, fd: RawFd) -> std::io::Result<Tee> {
+    let mut fds = [0 as RawFd; 2];
+    cvt(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    let [read, write] = fds;
+    let saved = unsafe {
+        libc::fcntl(read, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(write, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0)
+    };
+    let close_pipe = || unsafe {
+        libc::close(read);
+        libc::close(write);
+    };
+    if saved < 0 {
+        let err = std::io::Error::last_os_error();
+        close_pipe();
+        return Err(err);
+    }
+
+    let (pipe, original) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(saved)) };
+    if let Err(err) = std::thread::Builder::new()
+        .name(format!("probing-{stream}"))
+        .spawn(move || forward(stream, pipe, original))
+    {
+        // the closure owned both files and closed them when it was dropped
+        unsafe { libc::close(write) };
+        return Err(err);
+    }
+
+    if let Err(err) = cvt(unsafe { libc::dup2(write, fd) }) {
+        // closing the write end ends the reader thread
+        unsafe { libc::close(write) };
+        return Err(err);
+    }
+    Ok(Tee {
+        stream,
+        fd,
+        saved,
+        write,
+    })
+}
+
+fn stop_tee(tee: Tee) {
+    unsafe {
+        // restore the stream first, the reader thread then drains the pipe
+        // and exits once nothing writes to it anymore
+        libc::dup2(tee.saved, tee.fd);
+        libc::close(tee.write);
+    }
+}
+
+fn start_capture() -> std::io::Result<()> {
+    let mut tees = TEES
+        .lock()
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    for (stream, fd) in [
+        ("stdout", libc::STDOUT_FILENO),
+        ("stderr", libc::STDERR_FILENO),
+    ] {
+        if tees.iter().any(|tee| tee.stream == stream) {
+            continue;
+        }
+        tees.push(start_tee(stream, fd)?);
+    }
+    Ok(())
+}
+
+fn stop_capture() {
+    if let Ok(mut tees) = TEES.lock() {
+        for tee in tees.drain(..) {
+            stop_tee(tee);
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct OutputTable {}
+
+impl CustomTable for OutputTable {
+    fn name() -> &'static str {
+        "output"
+    }
+
+    fn description() -> &'static str {
+        "Most recent lines written by the process to stdout and stderr"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("seq", DataType::Int64, false)
+                .with_doc("Order in which the lines were read, across both streams"),
+            Field::new("timestamp", DataType::Int64, false)
+                .with_doc("Time the line was read since the epoch")
+                .with_unit("us"),
+            Field::new("stream", DataType::Utf8, false).with_doc("`stdout` or `stderr`"),
+            Field::new("line", DataType::Utf8, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let Ok(lines) = LINES.lock() else {
+            return vec![];
+        };
+
+        let mut seqs = Int64Builder::new();
+        let mut timestamps = Int64Builder::new();
+        let mut streams = GenericStringBuilder::<i32>::new();
+        let mut texts = GenericStringBuilder::<i32>::new();
+        for line in lines.lines.iter() {
+            seqs.append_value(line.seq);
+            timestamps.append_value(line.timestamp);
+            streams.append_value(line.stream);
+            texts.append_value(&line.line);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(seqs.finish()),
+                Arc::new(timestamps.finish()),
+                Arc::new(streams.finish()),
+                Arc::new(texts.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type OutputPlugin = TablePluginHelper<OutputTable>;
+
+/// Tee of the stdout and stderr of the process into `process.output`
+#[derive(Debug, EngineExtension)]
+pub struct OutputExtension {
+    /// Capture stdout and stderr of the process
+    #[option()]
+    capture: Maybe<bool>,
+
+    /// Number of most recent lines kept
+    #[option()]
+    max_lines: Maybe<usize>,
+}
+
+impl Default for OutputExtension {
+    fn default() -> Self {
+        Self {
+            capture: Maybe::Just(false),
+            max_lines: Maybe::Just(DEFAULT_MAX_LINES),
+        }
+    }
+}
+
+impl EngineCall for OutputExtension {}
+
+impl EngineDatasource for OutputExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| OutputPlugin::create(namespace, name))
+    }
+}
+
+impl OutputExtension {
+    fn set_capture(&mut self, capture: Maybe<bool>) -> Result<(), EngineError> {
+        match capture {
+            Maybe::Just(true) => start_capture().map_err(|e| {
+                stop_capture();
+                EngineError::InvalidOptionValue(Self::OPTION_CAPTURE.to_string(), e.to_string())
+            })?,
+            _ => stop_capture(),
+        }
+        self.capture = capture;
+        Ok(())
+    }
+
+    fn set_max_lines(&mut self, max_lines: Maybe<usize>) -> Result<(), EngineError> {
+        match max_lines {
+            Maybe::Just(max) if max > 0 => {
+                if let Ok(mut lines) = LINES.lock() {
+                    lines.max_lines = max;
+                    while lines.lines.len() > max {
+                        lines.lines.pop_front();
+                    }
+                }
+                self.max_lines = max_lines;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_MAX_LINES.to_string(),
+                max_lines.into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_lines() {
+        let mut pending = vec![];
+        assert_eq!(
+            split_lines(&mut pending, b"epoch 1\nloss=0."),
+            vec!["epoch 1".to_string()]
+        );
+        assert_eq!(pending, b"loss=0.");
+        assert_eq!(
+            split_lines(&mut pending, b"5\n\n 10%|#\r 20%|##\r"),
+            vec![
+                "loss=0.5".to_string(),
+                " 10%|#".to_string(),
+                " 20%|##".to_string()
+            ]
+        );
+        assert!(pending.is_empty());
+
+        let long = vec![b'x'; MAX_LINE_BYTES + 1];
+        assert_eq!(split_lines(&mut pending, &long).len(), 1);
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_bounded_lines() {
+        let mut lines = Lines::new(2);
+        for seq in 0..3 {
+            lines.push(OutputLine {
+                seq,
+                timestamp: seq,
+                stream: "stdout",
+                line: format!("line {seq}"),
+            });
+        }
+        let seqs = lines.lines.iter().map(|l| l.seq).collect::<Vec<_>>();
+        assert_eq!(seqs, vec![1, 2]);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    BooleanBuilder, Float64Builder, GenericStringBuilder, Int64Builder, RecordBatch,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, TablePluginHelper};
+
+/// Clock ticks per second of `/proc/<pid>/stat` times, fixed at 100 on Linux
+const USER_HZ: f64 = 100.0;
+
+/// All processes visible on the host with their parent, so job trees
+/// (launcher -> trainers -> dataloader workers) can be walked with
+/// `WITH RECURSIVE`.
+#[derive(Default, Debug)]
+pub struct ProcessTreeTable {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ProcessInfo {
+    pid: i64,
+    ppid: i64,
+    name: String,
+    state: String,
+    threads: i64,
+    /// User plus system CPU time in seconds
+    cpu_time: f64,
+    cmd: String,
+}
+
+/// Parse `/proc/<pid>/stat`. The command name is enclosed in parentheses and
+/// may itself contain spaces or parentheses, so split at the last `)`.
+fn parse_stat(content: &str) -> Option<ProcessInfo> {
+    let (head, tail) = content.rsplit_once(')')?;
+    let (pid, name) = head.split_once(" (")?;
+    // fields after the name, starting with field 3 (state)
+    let fields = tail.split_whitespace().collect::<Vec<_>>();
+    let ticks = |i: usize| fields.get(i)?.parse::<u64>().ok();
+    Some(ProcessInfo {
+        pid: pid.trim().parse().ok()?,
+        ppid: fields.get(1)?.parse().ok()?,
+        name: name.to_string(),
+        state: fields.first()?.to_string(),
+        threads: fields.get(17)?.parse().ok()?,
+        cpu_time: (ticks(11)? + ticks(12)?) as f64 / USER_HZ,
+        cmd: String::new(),
+    })
+}
+
+/// Pids with a probe, found by the `@probing-<pid>` socket of their local server
+fn probed_pids() -> HashSet<i64> {
+    let unix = std::fs::read_to_string("/proc/net/unix").unwrap_or_default();
+    unix.lines()
+        .filter_map(|line| line.split_whitespace().nth(7))
+        .filter_map(|path| path.strip_prefix("@probing-")?.parse().ok())
+        .collect()
+}
+
+fn processes() -> Vec<ProcessInfo> {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let mut processes = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| {
+            // processes may exit while being listed
+            let mut info = parse_stat(&std::fs::read_to_string(entry.path().join("stat")).ok()?)?;
+            info.cmd = std::fs::read(entry.path().join("cmdline"))
+                .map(|cmd| {
+                    String::from_utf8_lossy(&cmd)
+                        .trim_end_matches('\0')
+                        .replace('\0', " ")
+                })
+                .unwrap_or_default();
+            Some(info)
+        })
+        .collect::<Vec<_>>();
+    processes.sort_by_key(|p| p.pid);
+    processes
+}
+
+impl CustomTable for ProcessTreeTable {
+    fn name() -> &'static str {
+        "process_tree"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("pid", DataType::Int64, false),
+            Field::new("ppid", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("threads", DataType::Int64, false),
+            Field::new("cpu_time", DataType::Float64, false),
+            Field::new("cmd", DataType::Utf8, false),
+            Field::new("probed", DataType::Boolean, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let probed = probed_pids();
+
+        let mut pids = Int64Builder::new();
+        let mut ppids = Int64Builder::new();
+        let mut names = GenericStringBuilder::<i32>::new();
+        let mut states = GenericStringBuilder::<i32>::new();
+        let mut threads = Int64Builder::new();
+        let mut cpu_times = Float64Builder::new();
+        let mut cmds = GenericStringBuilder::<i32>::new();
+        let mut probes = BooleanBuilder::new();
+
+        for process in processes() {
+            pids.append_value(process.pid);
+            ppids.append_value(process.ppid);
+            names.append_value(process.name);
+            states.append_value(process.state);
+            threads.append_value(process.threads);
+            cpu_times.append_value(process.cpu_time);
+            cmds.append_value(process.cmd);
+            probes.append_value(probed.contains(&process.pid));
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(pids.finish()),
+                Arc::new(ppids.finish()),
+                Arc::new(names.finish()),
+                Arc::new(states.finish()),
+                Arc::new(threads.finish()),
+                Arc::new(cpu_times.finish()),
+                Arc::new(cmds.finish()),
+                Arc::new(probes.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type ProcessTreePlugin = TablePluginHelper<ProcessTreeTable>;
+
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+
+#[derive(Debug, Default, EngineExtension)]
+pub struct ProcessTreeExtension {}
+
+impl EngineCall for ProcessTreeExtension {}
+
+impl EngineDatasource for ProcessTreeExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| ProcessTreePlugin::create(namespace, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4242 (pt_data_worker (1)) S 4200 4200 4200 0 -1 4194560 100 0 0 0 \
+                    250 50 0 0 20 0 7 0 12345 1000000 500 18446744073709551615";
+        let info = parse_stat(stat).unwrap();
+        assert_eq!(info.pid, 4242);
+        assert_eq!(info.ppid, 4200);
+        assert_eq!(info.name, "pt_data_worker (1)");
+        assert_eq!(info.state, "S");
+        assert_eq!(info.threads, 7);
+        assert_eq!(info.cpu_time, 3.0);
+
+        assert!(parse_stat("garbage").is_none());
+    }
+}
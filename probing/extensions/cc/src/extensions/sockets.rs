@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use datafusion::arrow::array::{GenericStringBuilder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+
+use probing_core::core::{CustomTable, EngineCall, EngineDatasource, TablePluginHelper};
+
+/// Sockets opened by the process, from `/proc/self/fd` joined with the
+/// `/proc/self/net` tables by inode. Peer addresses can in turn be joined with
+/// `cluster.nodes` to find the rank at the other end of a stuck connection.
+#[derive(Default, Debug)]
+pub struct SocketsTable {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Socket {
+    protocol: String,
+    local_ip: String,
+    local_port: Option<i64>,
+    peer_ip: String,
+    peer_port: Option<i64>,
+    state: String,
+    tx_queue: Option<i64>,
+    rx_queue: Option<i64>,
+    inode: u64,
+    path: String,
+}
+
+const TCP_STATES: &[&str] = &[
+    "",
+    "ESTABLISHED",
+    "SYN_SENT",
+    "SYN_RECV",
+    "FIN_WAIT1",
+    "FIN_WAIT2",
+    "TIME_WAIT",
+    "CLOSE",
+    "CLOSE_WAIT",
+    "LAST_ACK",
+    "LISTEN",
+    "CLOSING",
+    "NEW_SYN_RECV",
+];
+
+const UNIX_STATES: &[&str] = &[
+    "",
+    "UNCONNECTED",
+    "CONNECTING",
+    "CONNECTED",
+    "DISCONNECTING",
+];
+
+/// Decode an `ADDR:PORT` pair of `/proc/net/{tcp,udp}{,6}`. Addresses are
+/// printed as 32-bit words in host byte order.
+fn parse_endpoint(field: &str) -> Option<(String, i64)> {
+    let (addr, port) = field.split_once(':')?;
+    let port = i64::from_str_radix(port, 16).ok()?;
+    let mut bytes = vec![];
+    for i in (0..addr.len()).step_by(8) {
+        let word = u32::from_str_radix(addr.get(i..i + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?).to_string(),
+        16 => {
+            let ip = Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?);
+            match ip.to_ipv4_mapped() {
+                Some(ip) => ip.to_string(),
+                None => ip.to_string(),
+            }
+        }
+        _ => return None,
+    };
+    Some((ip, port))
+}
+
+fn parse_hex(field: &str) -> Option<i64> {
+    i64::from_str_radix(field, 16).ok()
+}
+
+/// Parse `/proc/net/{tcp,udp}{,6}`
+fn parse_inet(protocol: &str, content: &str) -> Vec<Socket> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 10 {
+                return None;
+            }
+            let (local_ip, local_port) = parse_endpoint(fields[1])?;
+            let (peer_ip, peer_port) = parse_endpoint(fields[2])?;
+            let state = parse_hex(fields[3]).unwrap_or_default() as usize;
+            let (tx_queue, rx_queue) = fields[4].split_once(':')?;
+            Some(Socket {
+                protocol: protocol.to_string(),
+                local_ip,
+                local_port: Some(local_port),
+                peer_ip,
+                peer_port: Some(peer_port),
+                state: TCP_STATES.get(state).unwrap_or(&"").to_string(),
+                tx_queue: parse_hex(tx_queue),
+                rx_queue: parse_hex(rx_queue),
+                inode: fields[9].parse().ok()?,
+                path: String::new(),
+            })
+        })
+        .collect()
+}
+
+/// Parse `/proc/net/unix`; the kernel does not expose unix peers there
+fn parse_unix(content: &str) -> Vec<Socket> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 7 {
+                return None;
+            }
+            let kind = match fields[4] {
+                "0001" => "unix_stream",
+                "0002" => "unix_dgram",
+                "0005" => "unix_seqpacket",
+                _ => "unix",
+            };
+            let state = parse_hex(fields[5]).unwrap_or_default() as usize;
+            Some(Socket {
+                protocol: kind.to_string(),
+                state: UNIX_STATES.get(state).unwrap_or(&"").to_string(),
+                inode: fields[6].parse().ok()?,
+                path: fields.get(7).map(|p| p.to_string()).unwrap_or_default(),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Socket inodes of the open file descriptors, by inode
+fn socket_fds() -> HashMap<u64, i64> {
+    let mut fds = HashMap::new();
+    let Ok(entries) = std::fs::read_dir("/proc/self/fd") else {
+        return fds;
+    };
+    for entry in entries.flatten() {
+        let Ok(fd) = entry.file_name().to_string_lossy().parse::<i64>() else {
+            continue;
+        };
+        let Ok(target) = std::fs::read_link(entry.path()) else {
+            continue;
+        };
+        if let Some(inode) = target
+            .to_string_lossy()
+            .strip_prefix("socket:[")
+            .and_then(|s| s.strip_suffix(']'))
+            .and_then(|s| s.parse().ok())
+        {
+            fds.insert(inode, fd);
+        }
+    }
+    fds
+}
+
+fn process_sockets() -> Vec<(i64, Socket)> {
+    let fds = socket_fds();
+    let mut sockets = vec![];
+    for protocol in ["tcp", "tcp6", "udp", "udp6"] {
+        let content = std::fs::read_to_string(format!("/proc/self/net/{protocol}"));
+        sockets.extend(parse_inet(protocol, &content.unwrap_or_default()));
+    }
+    let unix = std::fs::read_to_string("/proc/self/net/unix").unwrap_or_default();
+    sockets.extend(parse_unix(&unix));
+
+    let mut sockets = sockets
+        .into_iter()
+        .filter_map(|socket| fds.get(&socket.inode).map(|fd| (*fd, socket)))
+        .collect::<Vec<_>>();
+    sockets.sort_by_key(|(fd, _)| *fd);
+    sockets
+}
+
+impl CustomTable for SocketsTable {
+    fn name() -> &'static str {
+        "sockets"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("fd", DataType::Int64, false),
+            Field::new("protocol", DataType::Utf8, false),
+            Field::new("local_ip", DataType::Utf8, true),
+            Field::new("local_port", DataType::Int64, true),
+            Field::new("peer_ip", DataType::Utf8, true),
+            Field::new("peer_port", DataType::Int64, true),
+            Field::new("state", DataType::Utf8, true),
+            Field::new("tx_queue", DataType::Int64, true),
+            Field::new("rx_queue", DataType::Int64, true),
+            Field::new("inode", DataType::Int64, false),
+            Field::new("path", DataType::Utf8, true),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let mut fds = Int64Builder::new();
+        let mut protocols = GenericStringBuilder::<i32>::new();
+        let mut local_ips = GenericStringBuilder::<i32>::new();
+        let mut local_ports = Int64Builder::new();
+        let mut peer_ips = GenericStringBuilder::<i32>::new();
+        let mut peer_ports = Int64Builder::new();
+        let mut states = GenericStringBuilder::<i32>::new();
+        let mut tx_queues = Int64Builder::new();
+        let mut rx_queues = Int64Builder::new();
+        let mut inodes = Int64Builder::new();
+        let mut paths = GenericStringBuilder::<i32>::new();
+
+        for (fd, socket) in process_sockets() {
+            fds.append_value(fd);
+            protocols.append_value(socket.protocol);
+            local_ips.append_value(socket.local_ip);
+            local_ports.append_option(socket.local_port);
+            peer_ips.append_value(socket.peer_ip);
+            peer_ports.append_option(socket.peer_port);
+            states.append_value(socket.state);
+            tx_queues.append_option(socket.tx_queue);
+            rx_queues.append_option(socket.rx_queue);
+            inodes.append_value(socket.inode as i64);
+            paths.append_value(socket.path);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(fds.finish()),
+                Arc::new(protocols.finish()),
+                Arc::new(local_ips.finish()),
+                Arc::new(local_ports.finish()),
+                Arc::new(peer_ips.finish()),
+                Arc::new(peer_ports.finish()),
+                Arc::new(states.finish()),
+                Arc::new(tx_queues.finish()),
+                Arc::new(rx_queues.finish()),
+                Arc::new(inodes.finish()),
+                Arc::new(paths.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type SocketsPlugin = TablePluginHelper<SocketsTable>;
+
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+
+#[derive(Debug, Default, EngineExtension)]
+pub struct SocketsExtension {}
+
+impl EngineCall for SocketsExtension {}
+
+impl EngineDatasource for SocketsExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| SocketsPlugin::create(namespace, name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sockets() {
+        let ip = |ip: [u8; 4]| format!("{:08X}", u32::from_ne_bytes(ip));
+        let tcp = format!(
+            concat!(
+                "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n",
+                "   0: {}:1F90 {}:D431 01 00000010:00000020 00:00000000 00000000  1000        0 4242 1 0 20 4 30 10 -1\n",
+            ),
+            ip([127, 0, 0, 1]),
+            ip([10, 0, 0, 2]),
+        );
+        let sockets = parse_inet("tcp", &tcp);
+        assert_eq!(
+            sockets,
+            vec![Socket {
+                protocol: "tcp".to_string(),
+                local_ip: "127.0.0.1".to_string(),
+                local_port: Some(8080),
+                peer_ip: "10.0.0.2".to_string(),
+                peer_port: Some(54321),
+                state: "ESTABLISHED".to_string(),
+                tx_queue: Some(16),
+                rx_queue: Some(32),
+                inode: 4242,
+                path: String::new(),
+            }]
+        );
+
+        let unix = parse_unix(concat!(
+            "Num       RefCount Protocol Flags    Type St Inode Path\n",
+            "0000000000000000: 00000002 00000000 00010000 0001 01 1234 /tmp/probing.sock\n",
+            "0000000000000000: 00000003 00000000 00000000 0001 03 1235\n",
+        ));
+        assert_eq!(unix.len(), 2);
+        assert_eq!(unix[0].protocol, "unix_stream");
+        assert_eq!(unix[0].state, "UNCONNECTED");
+        assert_eq!(unix[0].path, "/tmp/probing.sock");
+        assert_eq!(unix[1].state, "CONNECTED");
+        assert_eq!(unix[1].inode, 1235);
+    }
+}
@@ -7,14 +7,21 @@ use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
 use probing_core::core::Maybe;
+use probing_proto::prelude::SelfCheck;
 
 mod datasrc;
+mod delay;
 
 #[derive(Debug, Default, EngineExtension)]
+#[extension(on_enable = "resume", on_disable = "pause", selftest = "selftest")]
 pub struct TaskStatsExtension {
-    /// Task statistics collection interval in milliseconds (0 to disable)
-    #[option(aliases=["taskstats_interval"])]
+    /// Task statistics collection interval in milliseconds (0 to disable);
+    /// a new interval of a running collector applies once it is re-enabled
+    #[option(aliases=["taskstats_interval"], requires_restart, min = 0)]
     task_stats_interval: Maybe<i64>,
+
+    /// Disabled by `on_disable`, the interval is only recorded until resumed
+    paused: bool,
 }
 
 impl EngineCall for TaskStatsExtension {}
@@ -36,10 +43,17 @@ impl TaskStatsExtension {
         task_stats_interval: Maybe<i64>,
     ) -> Result<(), EngineError> {
         match self.task_stats_interval {
-            Maybe::Just(_) => Err(EngineError::InvalidOptionValue(
-                Self::OPTION_TASK_STATS_INTERVAL.to_string(),
-                task_stats_interval.clone().into(),
-            )),
+            // the running collector keeps its interval until re-enabled
+            Maybe::Just(_) => match task_stats_interval {
+                Maybe::Just(interval) if interval >= 0 => {
+                    self.task_stats_interval = task_stats_interval;
+                    Ok(())
+                }
+                _ => Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_TASK_STATS_INTERVAL.to_string(),
+                    task_stats_interval.clone().into(),
+                )),
+            },
             Maybe::Nothing => match task_stats_interval {
                 Maybe::Nothing => Err(EngineError::InvalidOptionValue(
                     Self::OPTION_TASK_STATS_INTERVAL.to_string(),
@@ -53,6 +67,9 @@ impl TaskStatsExtension {
                         ));
                     }
                     self.task_stats_interval = task_stats_interval.clone();
+                    if self.paused {
+                        return Ok(());
+                    }
                     match datasrc::TaskStatsWorker::instance().start(datasrc::TaskStatsConfig {
                         interval: Duration::from_millis(interval as u64),
                         iterations: None,
@@ -67,4 +84,64 @@ impl TaskStatsExtension {
             },
         }
     }
+
+    fn resume(&mut self) -> Result<(), EngineError> {
+        self.paused = false;
+        let Maybe::Just(interval) = self.task_stats_interval else {
+            return Ok(());
+        };
+        datasrc::TaskStatsWorker::instance()
+            .start(datasrc::TaskStatsConfig {
+                interval: Duration::from_millis(interval as u64),
+                iterations: None,
+            })
+            .map_err(|e| EngineError::PluginError(format!("failed to resume taskstats: {e}")))
+    }
+
+    fn selftest(&self) -> Vec<SelfCheck> {
+        let collector = match self.task_stats_interval {
+            Maybe::Just(interval) if interval > 0 => {
+                SelfCheck::pass("collector", format!("sampling every {interval} ms"))
+            }
+            _ => SelfCheck::fail(
+                "collector",
+                format!(
+                    "not sampling, set {} to an interval in ms",
+                    Self::OPTION_TASK_STATS_INTERVAL
+                ),
+            ),
+        };
+        let netlink = match delay::check_netlink() {
+            Ok(_) => SelfCheck::pass("netlink", "taskstats netlink is readable"),
+            Err(e) => SelfCheck::fail(
+                "netlink",
+                format!(
+                    "taskstats netlink failed ({e}), swap-in and reclaim delays read 0; \
+                     it needs CAP_NET_ADMIN in the initial network namespace"
+                ),
+            ),
+        };
+        let accounting = match delay::delay_accounting() {
+            Some(true) => SelfCheck::pass("delay accounting", "kernel.task_delayacct is 1"),
+            Some(false) => SelfCheck::fail(
+                "delay accounting",
+                "kernel.task_delayacct is 0, delays read 0: `sysctl kernel.task_delayacct=1`",
+            ),
+            None => SelfCheck::pass(
+                "delay accounting",
+                "no kernel.task_delayacct, accounted when booted with `delayacct`",
+            ),
+        };
+        vec![collector, netlink, accounting]
+    }
+
+    fn pause(&mut self) -> Result<(), EngineError> {
+        self.paused = true;
+        if let Maybe::Just(_) = self.task_stats_interval {
+            datasrc::TaskStatsWorker::instance()
+                .stop()
+                .map_err(|e| EngineError::PluginError(format!("failed to stop taskstats: {e}")))?;
+        }
+        Ok(())
+    }
 }
@@ -1,7 +1,7 @@
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        Arc, Mutex, Once,
     },
     thread::{self, JoinHandle},
     time::Duration,
@@ -19,6 +19,15 @@ use probing_core::core::{
 use probing_proto::prelude::{Ele, TimeSeries};
 use probing_proto::types; // Keep this for types::EleType
 
+use super::delay::DelayReader;
+
+const DELAY_COLUMNS: [&str; 4] = [
+    "cpu_delay",
+    "blkio_delay",
+    "swapin_delay",
+    "freepages_delay",
+];
+
 #[allow(unused)]
 #[derive(Error, Debug)]
 pub enum WorkerError {
@@ -47,6 +56,8 @@ impl Default for TaskStatsConfig {
 pub struct TaskStatsWorker {
     running: Arc<AtomicBool>,
     time_series: Arc<Mutex<TimeSeries>>,
+    /// Cumulative delay accounting in nanoseconds
+    delays: Arc<Mutex<TimeSeries>>,
     handle: Mutex<Option<JoinHandle<()>>>,
 }
 
@@ -59,6 +70,11 @@ impl TaskStatsWorker {
                     .with_columns(vec!["cpu_utime".to_string(), "cpu_stime".to_string()])
                     .build(),
             )),
+            delays: Arc::new(Mutex::new(
+                TimeSeries::builder()
+                    .with_columns(DELAY_COLUMNS.iter().map(|c| c.to_string()).collect())
+                    .build(),
+            )),
             handle: Mutex::new(None),
         });
         &INSTANCE
@@ -71,6 +87,7 @@ impl TaskStatsWorker {
 
         let running = self.running.clone();
         let time_series = self.time_series.clone();
+        let delays = self.delays.clone();
 
         let handle = thread::spawn(move || {
             let task = match procfs::process::Process::myself() {
@@ -81,6 +98,7 @@ impl TaskStatsWorker {
                 }
             };
 
+            let mut delay_reader = DelayReader::new();
             let mut iterations = config.iterations;
             while running.load(Ordering::SeqCst) {
                 if let Some(iter) = iterations.as_mut() {
@@ -105,16 +123,32 @@ impl TaskStatsWorker {
                         Ok(_) => {}
                         Err(e) => log::error!("Failed to append to time series: {e}"),
                     };
+
+                    let delay = delay_reader.read();
+                    let values = [delay.cpu, delay.blkio, delay.swapin, delay.freepages]
+                        .into_iter()
+                        .map(Ele::from)
+                        .collect();
+                    if let Err(e) = delays.lock().unwrap().append(t.into(), values) {
+                        log::error!("Failed to append to delay series: {e}");
+                    }
                 }
                 thread::sleep(config.interval);
             }
         });
 
         *self.handle.lock().unwrap() = Some(handle);
+        static SHUTDOWN_HOOK: Once = Once::new();
+        SHUTDOWN_HOOK.call_once(|| {
+            probing_core::core::shutdown::register_hook("taskstats", || {
+                if let Err(e) = TaskStatsWorker::instance().stop() {
+                    log::error!("Failed to stop task stats worker: {e}");
+                }
+            })
+        });
         Ok(())
     }
 
-    #[allow(unused)]
     pub fn stop(&self) -> Result<(), WorkerError> {
         if !self.running.swap(false, Ordering::SeqCst) {
             return Ok(());
@@ -132,6 +166,10 @@ impl TaskStatsWorker {
     pub fn get_stats(&self) -> Result<TimeSeries, WorkerError> {
         Ok(self.time_series.lock().unwrap().clone())
     }
+
+    pub fn get_delays(&self) -> Result<TimeSeries, WorkerError> {
+        Ok(self.delays.lock().unwrap().clone())
+    }
 }
 
 #[derive(Default, Debug)]
@@ -143,7 +181,12 @@ impl CustomNamespace for TaskStatsSchema {
     }
 
     fn list() -> Vec<String> {
-        vec!["cpu".to_string(), "memory".to_string(), "io".to_string()]
+        vec![
+            "cpu".to_string(),
+            "delay".to_string(),
+            "memory".to_string(),
+            "io".to_string(),
+        ]
     }
 
     fn make_lazy(expr: &str) -> Arc<probing_core::core::LazyTableSource>
@@ -160,6 +203,19 @@ impl CustomNamespace for TaskStatsSchema {
                 ]))),
                 data: Default::default(),
             }),
+            "delay" => Arc::new(probing_core::core::LazyTableSource {
+                name: expr.to_string(),
+                schema: Some(SchemaRef::new(Schema::new(
+                    std::iter::once(Field::new("timestamp", DataType::Int64, true))
+                        .chain(
+                            DELAY_COLUMNS
+                                .iter()
+                                .map(|name| Field::new(*name, DataType::Int64, false)),
+                        )
+                        .collect::<Vec<_>>(),
+                ))),
+                data: Default::default(),
+            }),
             _ => Arc::new(probing_core::core::LazyTableSource {
                 name: expr.to_string(),
                 schema: None,
@@ -182,6 +238,14 @@ impl CustomNamespace for TaskStatsSchema {
                     vec![]
                 }
             }
+            "delay" => {
+                let time_series = TaskStatsWorker::instance().get_delays().unwrap();
+                let names = time_series.names.clone();
+                time_series_to_recordbatch(names, &time_series).unwrap_or_else(|e| {
+                    log::error!("error convert delay series to table: {e:?}");
+                    vec![]
+                })
+            }
             _ => vec![],
         }
     }
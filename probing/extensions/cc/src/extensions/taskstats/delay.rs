@@ -0,0 +1,340 @@
+//! Kernel delay accounting of the process.
+//!
+//! The taskstats netlink interface reports, summed over all threads, how long
+//! they waited for a CPU, for block IO, for swapping pages in and for reclaiming
+//! memory. Where netlink is not reachable the CPU wait is taken from
+//! `/proc/self/task/*/schedstat` and the block IO wait from `/proc/self/stat`;
+//! swap-in and reclaim delays then read 0.
+//!
+//! The kernel only accounts delays when booted with `delayacct` or after
+//! `sysctl kernel.task_delayacct=1`.
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+/// Cumulative delays in nanoseconds
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Delays {
+    pub cpu: i64,
+    pub blkio: i64,
+    pub swapin: i64,
+    pub freepages: i64,
+}
+
+const NLMSG_HDRLEN: usize = 16;
+const GENL_HDRLEN: usize = 4;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+const TASKSTATS_CMD_GET: u8 = 1;
+const TASKSTATS_CMD_ATTR_TGID: u16 = 2;
+const TASKSTATS_TYPE_STATS: u16 = 3;
+const TASKSTATS_TYPE_AGGR_TGID: u16 = 5;
+
+/// Offsets in `struct taskstats` (linux/taskstats.h)
+const CPU_DELAY_TOTAL: usize = 24;
+const BLKIO_DELAY_TOTAL: usize = 40;
+const SWAPIN_DELAY_TOTAL: usize = 56;
+/// Since version 4 of the struct
+const FREEPAGES_DELAY_TOTAL: usize = 320;
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Netlink attributes in `buf` as (type, payload)
+fn attributes(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attrs = vec![];
+    while buf.len() >= 4 {
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let kind = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < 4 || len > buf.len() {
+            break;
+        }
+        attrs.push((kind, &buf[4..len]));
+        buf = &buf[align4(len).min(buf.len())..];
+    }
+    attrs
+}
+
+fn u64_at(buf: &[u8], offset: usize) -> Option<i64> {
+    let bytes = buf.get(offset..offset + 8)?.try_into().ok()?;
+    Some(u64::from_ne_bytes(bytes) as i64)
+}
+
+fn parse_stats(stats: &[u8]) -> Option<Delays> {
+    let version = u16::from_ne_bytes([*stats.first()?, *stats.get(1)?]);
+    Some(Delays {
+        cpu: u64_at(stats, CPU_DELAY_TOTAL)?,
+        blkio: u64_at(stats, BLKIO_DELAY_TOTAL)?,
+        swapin: u64_at(stats, SWAPIN_DELAY_TOTAL)?,
+        freepages: match version {
+            4.. => u64_at(stats, FREEPAGES_DELAY_TOTAL).unwrap_or_default(),
+            _ => 0,
+        },
+    })
+}
+
+/// A generic netlink socket bound to the TASKSTATS family
+struct Taskstats {
+    fd: OwnedFd,
+    family: u16,
+    seq: u32,
+}
+
+impl Taskstats {
+    fn connect() -> Result<Self> {
+        // SAFETY: plain socket creation, the descriptor is owned right after
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_GENERIC,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // SAFETY: fd is a freshly created descriptor nobody else owns
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // never block the sampling thread on a silent kernel
+        let timeout = libc::timeval {
+            tv_sec: 1,
+            tv_usec: 0,
+        };
+        // SAFETY: timeout outlives the call and the size matches its type
+        unsafe {
+            libc::setsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            );
+        }
+
+        let mut taskstats = Self {
+            fd,
+            family: 0,
+            seq: 0,
+        };
+        let reply = taskstats.request(
+            GENL_ID_CTRL,
+            CTRL_CMD_GETFAMILY,
+            CTRL_ATTR_FAMILY_NAME,
+            b"TASKSTATS\0",
+        )?;
+        taskstats.family = attributes(&reply)
+            .into_iter()
+            .find(|(kind, value)| *kind == CTRL_ATTR_FAMILY_ID && value.len() >= 2)
+            .map(|(_, value)| u16::from_ne_bytes([value[0], value[1]]))
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no TASKSTATS netlink family"))?;
+        Ok(taskstats)
+    }
+
+    /// Send a generic netlink request with a single attribute and return the
+    /// attributes of the reply
+    fn request(&mut self, family: u16, cmd: u8, attr: u16, payload: &[u8]) -> Result<Vec<u8>> {
+        self.seq = self.seq.wrapping_add(1);
+        let attr_len = 4 + payload.len();
+        let len = NLMSG_HDRLEN + GENL_HDRLEN + align4(attr_len);
+
+        let mut msg = Vec::with_capacity(len);
+        msg.extend_from_slice(&(len as u32).to_ne_bytes());
+        msg.extend_from_slice(&family.to_ne_bytes());
+        msg.extend_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+        msg.extend_from_slice(&self.seq.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+        msg.extend_from_slice(&[cmd, 1, 0, 0]);
+        msg.extend_from_slice(&(attr_len as u16).to_ne_bytes());
+        msg.extend_from_slice(&attr.to_ne_bytes());
+        msg.extend_from_slice(payload);
+        msg.resize(len, 0);
+
+        // SAFETY: an all-zero sockaddr_nl addresses the kernel
+        let mut kernel: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        kernel.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        // SAFETY: msg and kernel outlive the call, lengths match the buffers
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                msg.as_ptr() as *const libc::c_void,
+                msg.len(),
+                0,
+                &kernel as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; 8192];
+        // SAFETY: buf is writable for its whole length
+        let received = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if received < 0 {
+            return Err(Error::last_os_error());
+        }
+        buf.truncate(received as usize);
+        if buf.len() < NLMSG_HDRLEN + 4 {
+            return Err(Error::new(ErrorKind::InvalidData, "short netlink reply"));
+        }
+        let msg_len =
+            (u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize).min(buf.len());
+        if u16::from_ne_bytes([buf[4], buf[5]]) == NLMSG_ERROR {
+            let code = i32::from_ne_bytes([buf[16], buf[17], buf[18], buf[19]]);
+            return Err(Error::from_raw_os_error(-code));
+        }
+        Ok(buf
+            .get(NLMSG_HDRLEN + GENL_HDRLEN..msg_len)
+            .unwrap_or_default()
+            .to_vec())
+    }
+
+    /// Delays summed over the threads of process `tgid`
+    fn delays(&mut self, tgid: u32) -> Result<Delays> {
+        let reply = self.request(
+            self.family,
+            TASKSTATS_CMD_GET,
+            TASKSTATS_CMD_ATTR_TGID,
+            &tgid.to_ne_bytes(),
+        )?;
+        attributes(&reply)
+            .into_iter()
+            .filter(|(kind, _)| *kind == TASKSTATS_TYPE_AGGR_TGID)
+            .flat_map(|(_, nested)| attributes(nested))
+            .filter(|(kind, _)| *kind == TASKSTATS_TYPE_STATS)
+            .find_map(|(_, stats)| parse_stats(stats))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no stats in taskstats reply"))
+    }
+}
+
+/// Delays from procfs, without swap-in and reclaim
+fn proc_delays() -> Delays {
+    // the second field of schedstat is the time spent waiting on a runqueue
+    let cpu = std::fs::read_dir("/proc/self/task")
+        .map(|tasks| {
+            tasks
+                .flatten()
+                .filter_map(|task| std::fs::read_to_string(task.path().join("schedstat")).ok())
+                .filter_map(|stat| stat.split_whitespace().nth(1)?.parse::<i64>().ok())
+                .sum()
+        })
+        .unwrap_or_default();
+    let blkio = procfs::process::Process::myself()
+        .and_then(|p| p.stat())
+        .ok()
+        .and_then(|stat| stat.delayacct_blkio_ticks)
+        .map(|ticks| (ticks * 1_000_000_000 / procfs::ticks_per_second()) as i64)
+        .unwrap_or_default();
+    Delays {
+        cpu,
+        blkio,
+        ..Default::default()
+    }
+}
+
+/// Reads the delays of this process, over netlink while it works
+pub struct DelayReader {
+    netlink: Option<Taskstats>,
+}
+
+impl DelayReader {
+    pub fn new() -> Self {
+        let netlink = Taskstats::connect()
+            .inspect_err(|e| log::debug!("taskstats netlink unavailable, using procfs: {e}"))
+            .ok();
+        Self { netlink }
+    }
+
+    pub fn read(&mut self) -> Delays {
+        if let Some(netlink) = self.netlink.as_mut() {
+            match netlink.delays(std::process::id()) {
+                Ok(delays) => return delays,
+                Err(e) => {
+                    log::debug!("taskstats netlink query failed, using procfs: {e}");
+                    self.netlink = None;
+                }
+            }
+        }
+        proc_delays()
+    }
+}
+
+/// Query the delays of this process once over netlink, failing with the
+/// reason the reader falls back to procfs
+pub fn check_netlink() -> Result<Delays> {
+    Taskstats::connect()?.delays(std::process::id())
+}
+
+/// Whether the kernel accounts delays, `None` when it has no such sysctl
+pub fn delay_accounting() -> Option<bool> {
+    std::fs::read_to_string("/proc/sys/kernel/task_delayacct")
+        .ok()
+        .map(|value| value.trim() == "1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attr(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut buf = ((4 + payload.len()) as u16).to_ne_bytes().to_vec();
+        buf.extend_from_slice(&kind.to_ne_bytes());
+        buf.extend_from_slice(payload);
+        buf.resize(align4(buf.len()), 0);
+        buf
+    }
+
+    #[test]
+    fn test_parse_taskstats_reply() {
+        let mut stats = vec![0u8; FREEPAGES_DELAY_TOTAL + 8];
+        stats[..2].copy_from_slice(&8u16.to_ne_bytes());
+        for (offset, value) in [
+            (CPU_DELAY_TOTAL, 10u64),
+            (BLKIO_DELAY_TOTAL, 20),
+            (SWAPIN_DELAY_TOTAL, 30),
+            (FREEPAGES_DELAY_TOTAL, 40),
+        ] {
+            stats[offset..offset + 8].copy_from_slice(&value.to_ne_bytes());
+        }
+        let mut nested = attr(2, &1234u32.to_ne_bytes());
+        nested.extend(attr(TASKSTATS_TYPE_STATS, &stats));
+        let reply = attr(TASKSTATS_TYPE_AGGR_TGID | 0x8000, &nested);
+
+        let attrs = attributes(&reply);
+        assert_eq!(attrs.len(), 1);
+        assert_eq!(attrs[0].0, TASKSTATS_TYPE_AGGR_TGID);
+        let inner = attributes(attrs[0].1);
+        assert_eq!(inner.len(), 2);
+        assert_eq!(
+            parse_stats(inner[1].1),
+            Some(Delays {
+                cpu: 10,
+                blkio: 20,
+                swapin: 30,
+                freepages: 40,
+            })
+        );
+    }
+
+    #[test]
+    fn test_delay_reader() {
+        let delays = DelayReader::new().read();
+        assert!(delays.cpu >= 0 && delays.blkio >= 0);
+    }
+}
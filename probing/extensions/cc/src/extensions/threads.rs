@@ -0,0 +1,385 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use datafusion::arrow::array::{Float64Builder, GenericStringBuilder, Int64Builder, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    CustomTable, EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption,
+    Maybe, TablePluginHelper,
+};
+
+/// Clock ticks per second of `/proc/<pid>/stat` times, fixed at 100 on Linux
+const USER_HZ: i64 = 100;
+
+const DEFAULT_MAX_EVENTS: usize = 10000;
+
+/// Scan interval in milliseconds, 0 when tracking is off
+static INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+static SCANNING: AtomicBool = AtomicBool::new(false);
+static TRACKER: Lazy<Mutex<Tracker>> = Lazy::new(|| Mutex::new(Tracker::new(DEFAULT_MAX_EVENTS)));
+
+/// A thread seen by the last scan
+#[derive(Debug, Clone, PartialEq)]
+struct ThreadInfo {
+    name: String,
+    /// Creation time in microseconds since epoch
+    started: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ThreadEvent {
+    /// Event time in microseconds since epoch; exits are only noticed by the
+    /// next scan, so their time is late by up to one interval
+    timestamp: i64,
+    event: &'static str,
+    tid: i64,
+    name: String,
+    /// Seconds the thread was alive, for exits
+    lifetime: Option<f64>,
+}
+
+/// Diffs successive scans of `/proc/self/task` into lifecycle events.
+///
+/// The kernel does not record which thread created another, so creations
+/// carry no parent; use their timestamps to correlate with the activity of
+/// other threads.
+#[derive(Debug)]
+struct Tracker {
+    threads: HashMap<i64, ThreadInfo>,
+    events: VecDeque<ThreadEvent>,
+    max_events: usize,
+}
+
+impl Tracker {
+    fn new(max_events: usize) -> Self {
+        Self {
+            threads: HashMap::new(),
+            events: VecDeque::new(),
+            max_events,
+        }
+    }
+
+    fn push(&mut self, event: ThreadEvent) {
+        while self.events.len() >= self.max_events {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn exit(&mut self, now: i64, tid: i64, info: ThreadInfo) {
+        self.push(ThreadEvent {
+            timestamp: now,
+            event: "exit",
+            tid,
+            name: info.name,
+            lifetime: Some((now - info.started).max(0) as f64 / 1e6),
+        });
+    }
+
+    fn create(&mut self, tid: i64, info: &ThreadInfo) {
+        self.push(ThreadEvent {
+            timestamp: info.started,
+            event: "create",
+            tid,
+            name: info.name.clone(),
+            lifetime: None,
+        });
+    }
+
+    /// Record the difference between the last scan and `threads`
+    fn update(&mut self, now: i64, threads: HashMap<i64, ThreadInfo>) {
+        let mut gone = self
+            .threads
+            .keys()
+            .filter(|tid| !threads.contains_key(tid))
+            .copied()
+            .collect::<Vec<_>>();
+        gone.sort();
+        for tid in gone {
+            if let Some(info) = self.threads.remove(&tid) {
+                self.exit(now, tid, info);
+            }
+        }
+
+        let mut tids = threads.keys().copied().collect::<Vec<_>>();
+        tids.sort_by_key(|tid| (threads[tid].started, *tid));
+        for tid in tids {
+            let info = &threads[&tid];
+            match self.threads.get(&tid).cloned() {
+                None => self.create(tid, info),
+                // the tid was reused by a new thread between two scans
+                Some(old) if old.started != info.started => {
+                    self.exit(now, tid, old);
+                    self.create(tid, info);
+                }
+                Some(old) if old.name != info.name => self.push(ThreadEvent {
+                    timestamp: now,
+                    event: "rename",
+                    tid,
+                    name: info.name.clone(),
+                    lifetime: None,
+                }),
+                Some(_) => {}
+            }
+        }
+        self.threads = threads;
+    }
+}
+
+fn now_us() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64
+}
+
+/// Boot time in microseconds since epoch, from the `btime` line of `/proc/stat`
+fn boot_time_us() -> Option<i64> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let btime = stat.lines().find_map(|line| line.strip_prefix("btime "))?;
+    Some(btime.trim().parse::<i64>().ok()? * 1_000_000)
+}
+
+/// Parse the name and start time (in ticks since boot) of a task `stat`
+fn parse_task_stat(content: &str) -> Option<(String, i64)> {
+    let (head, tail) = content.rsplit_once(')')?;
+    let (_, name) = head.split_once(" (")?;
+    // field 22 (starttime), counting from field 3 (state)
+    let starttime = tail.split_whitespace().nth(19)?.parse().ok()?;
+    Some((name.to_string(), starttime))
+}
+
+fn scan_threads(boot_time: i64) -> HashMap<i64, ThreadInfo> {
+    let Ok(entries) = std::fs::read_dir("/proc/self/task") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let tid = entry.file_name().to_string_lossy().parse::<i64>().ok()?;
+            // threads may exit while being listed
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            let (name, starttime) = parse_task_stat(&stat)?;
+            let started = boot_time + starttime * 1_000_000 / USER_HZ;
+            Some((tid, ThreadInfo { name, started }))
+        })
+        .collect()
+}
+
+fn scan_once() {
+    let Some(boot_time) = boot_time_us() else {
+        return;
+    };
+    let threads = scan_threads(boot_time);
+    if let Ok(mut tracker) = TRACKER.lock() {
+        tracker.update(now_us(), threads);
+    }
+}
+
+/// Start the scanning thread unless it is running; it stops by itself once
+/// the interval is set back to 0
+fn start_scanning() -> std::io::Result<()> {
+    if SCANNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let spawned = std::thread::Builder::new()
+        .name("probing-threads".to_string())
+        .spawn(|| {
+            loop {
+                let interval = INTERVAL_MS.load(Ordering::Relaxed);
+                if interval == 0 {
+                    break;
+                }
+                if !probing_core::core::killswitch::is_dormant() {
+                    scan_once();
+                }
+                std::thread::sleep(Duration::from_millis(interval));
+            }
+            SCANNING.store(false, Ordering::SeqCst);
+        });
+    if let Err(err) = spawned {
+        SCANNING.store(false, Ordering::SeqCst);
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+pub struct ThreadEventsTable {}
+
+impl CustomTable for ThreadEventsTable {
+    fn name() -> &'static str {
+        "thread_events"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("event", DataType::Utf8, false),
+            Field::new("tid", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("lifetime", DataType::Float64, true),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        if INTERVAL_MS.load(Ordering::Relaxed) > 0 {
+            // include changes since the last periodic scan
+            scan_once();
+        }
+        let Ok(tracker) = TRACKER.lock() else {
+            return vec![];
+        };
+
+        let mut timestamps = Int64Builder::new();
+        let mut events = GenericStringBuilder::<i32>::new();
+        let mut tids = Int64Builder::new();
+        let mut names = GenericStringBuilder::<i32>::new();
+        let mut lifetimes = Float64Builder::new();
+        for event in tracker.events.iter() {
+            timestamps.append_value(event.timestamp);
+            events.append_value(event.event);
+            tids.append_value(event.tid);
+            names.append_value(&event.name);
+            lifetimes.append_option(event.lifetime);
+        }
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(timestamps.finish()),
+                Arc::new(events.finish()),
+                Arc::new(tids.finish()),
+                Arc::new(names.finish()),
+                Arc::new(lifetimes.finish()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type ThreadEventsPlugin = TablePluginHelper<ThreadEventsTable>;
+
+/// Thread creation and exit tracking by scanning `/proc/self/task`
+#[derive(Debug, EngineExtension)]
+pub struct ThreadsExtension {
+    /// Milliseconds between scans of the process threads, 0 to stop tracking
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Number of most recent thread events kept
+    #[option(min = 1)]
+    max_events: Maybe<usize>,
+}
+
+impl Default for ThreadsExtension {
+    fn default() -> Self {
+        Self {
+            interval: Maybe::Just(0),
+            max_events: Maybe::Just(DEFAULT_MAX_EVENTS),
+        }
+    }
+}
+
+impl EngineCall for ThreadsExtension {}
+
+impl EngineDatasource for ThreadsExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| ThreadEventsPlugin::create(namespace, name))
+    }
+}
+
+impl ThreadsExtension {
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let millis = match interval {
+            Maybe::Just(millis) => millis,
+            Maybe::Nothing => 0,
+        };
+        INTERVAL_MS.store(millis, Ordering::Relaxed);
+        if millis > 0 {
+            start_scanning().map_err(|e| {
+                EngineError::InvalidOptionValue(Self::OPTION_INTERVAL.to_string(), e.to_string())
+            })?;
+        }
+        self.interval = interval;
+        Ok(())
+    }
+
+    fn set_max_events(&mut self, max_events: Maybe<usize>) -> Result<(), EngineError> {
+        match max_events {
+            Maybe::Just(max) if max > 0 => {
+                if let Ok(mut tracker) = TRACKER.lock() {
+                    tracker.max_events = max;
+                    while tracker.events.len() > max {
+                        tracker.events.pop_front();
+                    }
+                }
+                self.max_events = max_events;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_MAX_EVENTS.to_string(),
+                max_events.into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str, started: i64) -> ThreadInfo {
+        ThreadInfo {
+            name: name.to_string(),
+            started,
+        }
+    }
+
+    #[test]
+    fn test_tracker_events() {
+        let mut tracker = Tracker::new(16);
+        tracker.update(100, HashMap::from([(1, info("main", 10))]));
+        tracker.update(
+            200,
+            HashMap::from([(1, info("main", 10)), (2, info("pt_worker", 150))]),
+        );
+        tracker.update(
+            3_000_300,
+            HashMap::from([(1, info("trainer", 10)), (2, info("pt_worker", 250))]),
+        );
+        tracker.update(3_000_400, HashMap::from([(1, info("trainer", 10))]));
+
+        let events = tracker
+            .events
+            .iter()
+            .map(|e| (e.event, e.tid, e.timestamp))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ("create", 1, 10),
+                ("create", 2, 150),
+                ("rename", 1, 3_000_300),
+                ("exit", 2, 3_000_300),
+                ("create", 2, 250),
+                ("exit", 2, 3_000_400),
+            ]
+        );
+        assert_eq!(tracker.events[3].lifetime, Some(3.00015));
+
+        let stat = "7 (data loader) S 1 1 1 0 -1 0 0 0 0 0 0 0 0 0 20 0 9 0 4242 0";
+        let (name, starttime) = parse_task_stat(stat).unwrap();
+        assert_eq!(name, "data loader");
+        assert_eq!(starttime, 4242);
+    }
+}
@@ -1 +1,9 @@
 pub mod extensions;
+
+/// Cargo features this crate was built with
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "kmsg")]
+    "kmsg",
+    #[cfg(feature = "taskstats")]
+    "taskstats",
+];
@@ -1,7 +1,11 @@
+mod backtrace;
+mod gpu;
 mod pprof;
 pub mod python;
 mod torch;
 
+pub use backtrace::BacktraceExtension;
+pub use gpu::GpuExtension;
 pub use pprof::PprofExtension;
 pub use python::PythonExt;
 pub use torch::TorchExtension;
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use probing_core::core::{
+    EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
+};
+
+use crate::features::backtrace::{self, LocalsCapture, DEFAULT_MAX_REPR};
+
+/// Depth and locals capture policy of backtraces
+#[derive(Debug, EngineExtension)]
+pub struct BacktraceExtension {
+    /// Innermost frames kept per stack, 0 for all of them
+    #[option()]
+    max_depth: Maybe<usize>,
+
+    /// Locals captured with each Python frame: none, names or values
+    #[option(choices = ["none", "names", "values"])]
+    locals: Maybe<String>,
+
+    /// Characters kept of the value of a local
+    #[option(min = 1, depends_on = "backtrace.locals")]
+    max_repr: Maybe<usize>,
+
+    /// Per-type formatters of locals, e.g. `torch.Tensor=summary`; one of
+    /// type, summary or value
+    #[option()]
+    formatters: Maybe<String>,
+}
+
+impl Default for BacktraceExtension {
+    fn default() -> Self {
+        Self {
+            max_depth: Maybe::Just(0),
+            locals: Maybe::Just(LocalsCapture::default().to_string()),
+            max_repr: Maybe::Just(DEFAULT_MAX_REPR),
+            formatters: Maybe::Nothing,
+        }
+    }
+}
+
+impl EngineCall for BacktraceExtension {}
+
+impl EngineDatasource for BacktraceExtension {
+    fn datasrc(
+        &self,
+        _namespace: &str,
+        _name: Option<&str>,
+    ) -> Option<Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        None
+    }
+}
+
+impl BacktraceExtension {
+    fn set_max_depth(&mut self, max_depth: Maybe<usize>) -> Result<(), EngineError> {
+        let depth = match max_depth {
+            Maybe::Just(depth) => depth,
+            Maybe::Nothing => 0,
+        };
+        backtrace::update_policy(|policy| policy.max_depth = depth);
+        self.max_depth = max_depth;
+        Ok(())
+    }
+
+    fn set_locals(&mut self, locals: Maybe<String>) -> Result<(), EngineError> {
+        let capture = match &locals {
+            Maybe::Just(locals) => locals
+                .parse::<LocalsCapture>()
+                .map_err(|e| EngineError::InvalidOptionValue(Self::OPTION_LOCALS.to_string(), e))?,
+            Maybe::Nothing => LocalsCapture::default(),
+        };
+        backtrace::update_policy(|policy| policy.locals = capture);
+        self.locals = locals;
+        Ok(())
+    }
+
+    fn set_max_repr(&mut self, max_repr: Maybe<usize>) -> Result<(), EngineError> {
+        match max_repr {
+            Maybe::Just(max) if max > 0 => {
+                backtrace::update_policy(|policy| policy.max_repr = max);
+                self.max_repr = max_repr;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_MAX_REPR.to_string(),
+                max_repr.into(),
+            )),
+        }
+    }
+
+    fn set_formatters(&mut self, formatters: Maybe<String>) -> Result<(), EngineError> {
+        let parsed = match &formatters {
+            Maybe::Just(spec) => backtrace::parse_formatters(spec).map_err(|e| {
+                EngineError::InvalidOptionValue(Self::OPTION_FORMATTERS.to_string(), e)
+            })?,
+            Maybe::Nothing => Default::default(),
+        };
+        backtrace::update_policy(|policy| policy.formatters = parsed);
+        self.formatters = formatters;
+        Ok(())
+    }
+}
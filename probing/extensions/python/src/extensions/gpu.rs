@@ -0,0 +1,347 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use probing_core::core::EngineError;
+use probing_core::core::EngineExtension;
+use probing_core::core::EngineExtensionOption;
+use probing_core::core::Maybe;
+use probing_core::core::{
+    ArrayRef, CustomTable, DataType, EngineCall, EngineDatasource, Field, FieldDoc, Float64Array,
+    Int64Array, RecordBatch, Schema, SchemaRef, StringArray, TablePluginHelper,
+};
+use probing_proto::prelude::SelfCheck;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::features::gpu_kernels::{self, KernelRow};
+
+/// GPU devices of the host, the libraries loaded to drive them, and the
+/// kernels they run
+#[derive(Debug, Default, EngineExtension)]
+#[extension(selftest = "selftest")]
+pub struct GpuExtension {
+    /// Record the kernels and copies of the GPUs in `gpu.kernels` through
+    /// CUPTI, needs probing built with the `cupti` feature
+    #[option(choices = ["true", "false"])]
+    cupti: Maybe<bool>,
+
+    /// Path of libcupti, found next to the CUDA runtime torch loaded otherwise
+    #[option()]
+    cupti_library: Maybe<String>,
+
+    /// Kernel launches of a thread sharing one capture of its Python stack,
+    /// 16 by default; 1 captures the stack of every launch
+    #[option(min = 1)]
+    stack_interval: Maybe<u64>,
+
+    /// Rows kept in `gpu.kernels`, 100000 by default
+    #[option(min = 1)]
+    kernel_rows: Maybe<u64>,
+}
+
+impl GpuExtension {
+    fn selftest(&self) -> Vec<SelfCheck> {
+        let nvml = Python::with_gil(|py| {
+            py.import("probing.inspect.gpu")?
+                .call_method0("check_nvml")?
+                .extract::<String>()
+        });
+        let mut checks = vec![match nvml {
+            Ok(detail) => SelfCheck::pass("nvml", detail),
+            Err(e) => SelfCheck::fail(
+                "nvml",
+                format!("NVML is unusable ({e}), gpu.topology lists only the devices of torch"),
+            ),
+        }];
+        if let Maybe::Just(true) = self.cupti {
+            checks.push(match gpu_kernels::is_enabled() {
+                true => SelfCheck::pass("cupti", "recording kernels"),
+                false => {
+                    SelfCheck::fail("cupti", "CUPTI is not recording, gpu.kernels stays empty")
+                }
+            });
+        }
+        checks
+    }
+
+    fn set_cupti(&mut self, cupti: Maybe<bool>) -> Result<(), EngineError> {
+        let enable = matches!(cupti, Maybe::Just(true));
+        match gpu_kernels::enable(enable) {
+            Ok(()) => {
+                self.cupti = Maybe::Just(enable);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to switch CUPTI: {e}");
+                Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_CUPTI.to_string(),
+                    e.to_string(),
+                ))
+            }
+        }
+    }
+
+    fn set_cupti_library(&mut self, cupti_library: Maybe<String>) -> Result<(), EngineError> {
+        gpu_kernels::set_library(cupti_library.clone().into());
+        self.cupti_library = cupti_library;
+        Ok(())
+    }
+
+    fn set_stack_interval(&mut self, stack_interval: Maybe<u64>) -> Result<(), EngineError> {
+        gpu_kernels::set_stack_interval(match stack_interval {
+            Maybe::Just(launches) => launches,
+            Maybe::Nothing => gpu_kernels::DEFAULT_STACK_INTERVAL,
+        });
+        self.stack_interval = stack_interval;
+        Ok(())
+    }
+
+    fn set_kernel_rows(&mut self, kernel_rows: Maybe<u64>) -> Result<(), EngineError> {
+        gpu_kernels::set_capacity(match kernel_rows {
+            Maybe::Just(rows) => rows as usize,
+            Maybe::Nothing => gpu_kernels::DEFAULT_CAPACITY,
+        });
+        self.kernel_rows = kernel_rows;
+        Ok(())
+    }
+}
+
+impl EngineCall for GpuExtension {}
+
+impl EngineDatasource for GpuExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        match name {
+            Some("topology") => Some(TopologyPlugin::create(namespace, "topology")),
+            Some("kernels") => Some(KernelsPlugin::create(namespace, "kernels")),
+            _ => None,
+        }
+    }
+}
+
+/// One row of `probing.inspect.gpu.topology()`
+#[derive(Debug, Clone, Default)]
+pub struct LinkRow {
+    pub device: Option<i64>,
+    pub physical: Option<i64>,
+    pub name: Option<String>,
+    pub uuid: Option<String>,
+    pub bus_id: Option<String>,
+    pub peer: Option<i64>,
+    pub peer_device: Option<i64>,
+    pub link: Option<String>,
+    pub nvlinks: Option<i64>,
+    pub driver: Option<String>,
+    pub cuda: Option<String>,
+    pub cudnn: Option<String>,
+    pub nccl: Option<String>,
+}
+
+fn text(row: &Bound<'_, PyDict>, key: &str) -> PyResult<Option<String>> {
+    match row.get_item(key)? {
+        Some(value) if !value.is_none() => Ok(Some(value.str()?.to_string())),
+        _ => Ok(None),
+    }
+}
+
+fn int(row: &Bound<'_, PyDict>, key: &str) -> PyResult<Option<i64>> {
+    match row.get_item(key)? {
+        Some(value) if !value.is_none() => value.extract::<i64>().map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Devices of the host and their links, read by the Python side through NVML
+pub fn topology() -> Result<Vec<LinkRow>> {
+    Python::with_gil(|py| {
+        let rows = py
+            .import("probing.inspect.gpu")?
+            .call_method0("topology")?
+            .extract::<Vec<Bound<'_, PyDict>>>()?;
+        rows.iter()
+            .map(|row| {
+                Ok(LinkRow {
+                    device: int(row, "device")?,
+                    physical: int(row, "physical")?,
+                    name: text(row, "name")?,
+                    uuid: text(row, "uuid")?,
+                    bus_id: text(row, "bus_id")?,
+                    peer: int(row, "peer")?,
+                    peer_device: int(row, "peer_device")?,
+                    link: text(row, "link")?,
+                    nvlinks: int(row, "nvlinks")?,
+                    driver: text(row, "driver")?,
+                    cuda: text(row, "cuda")?,
+                    cudnn: text(row, "cudnn")?,
+                    nccl: text(row, "nccl")?,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()
+    })
+    .map_err(|e: PyErr| anyhow::anyhow!("failed to read the GPU topology: {e}"))
+}
+
+/// Devices and their interconnects, with driver and library versions
+#[derive(Default, Debug)]
+pub struct TopologyTable {}
+
+impl CustomTable for TopologyTable {
+    fn name() -> &'static str {
+        "topology"
+    }
+
+    fn description() -> &'static str {
+        "Pairs of GPU devices of the host with their link, and the driver and library versions"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("device", DataType::Int64, true)
+                .with_doc("CUDA index in this process, NULL if hidden by CUDA_VISIBLE_DEVICES"),
+            Field::new("physical", DataType::Int64, true)
+                .with_doc("NVML index on the host, as in nvidia-smi"),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("uuid", DataType::Utf8, true),
+            Field::new("bus_id", DataType::Utf8, true).with_doc("PCI bus id"),
+            Field::new("peer", DataType::Int64, true)
+                .with_doc("NVML index of the other device, NULL on a single-GPU host"),
+            Field::new("peer_device", DataType::Int64, true)
+                .with_doc("CUDA index of the other device in this process"),
+            Field::new("link", DataType::Utf8, true).with_doc(
+                "NVLink, or the closest common PCIe ancestor: PIX, PXB, PHB, NODE or SYS",
+            ),
+            Field::new("nvlinks", DataType::Int64, true)
+                .with_doc("Active NVLinks between the two devices"),
+            Field::new("driver", DataType::Utf8, true),
+            Field::new("cuda", DataType::Utf8, true).with_doc("CUDA version torch was built with"),
+            Field::new("cudnn", DataType::Utf8, true),
+            Field::new("nccl", DataType::Utf8, true),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let rows = match topology() {
+            Ok(rows) => rows,
+            Err(err) => {
+                log::debug!("{err}");
+                return vec![];
+            }
+        };
+        let ints = |f: fn(&LinkRow) -> Option<i64>| -> ArrayRef {
+            Arc::new(Int64Array::from_iter(rows.iter().map(f)))
+        };
+        let texts = |f: fn(&LinkRow) -> Option<&str>| -> ArrayRef {
+            Arc::new(StringArray::from_iter(rows.iter().map(f)))
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                ints(|r| r.device),
+                ints(|r| r.physical),
+                texts(|r| r.name.as_deref()),
+                texts(|r| r.uuid.as_deref()),
+                texts(|r| r.bus_id.as_deref()),
+                ints(|r| r.peer),
+                ints(|r| r.peer_device),
+                texts(|r| r.link.as_deref()),
+                ints(|r| r.nvlinks),
+                texts(|r| r.driver.as_deref()),
+                texts(|r| r.cuda.as_deref()),
+                texts(|r| r.cudnn.as_deref()),
+                texts(|r| r.nccl.as_deref()),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type TopologyPlugin = TablePluginHelper<TopologyTable>;
+
+/// Kernels and copies run by the GPUs, with the Python stack issuing them
+#[derive(Default, Debug)]
+pub struct KernelsTable {}
+
+impl CustomTable for KernelsTable {
+    fn name() -> &'static str {
+        "kernels"
+    }
+
+    fn description() -> &'static str {
+        "Kernels and memory copies run by the GPUs, recorded through CUPTI with probing.gpu.cupti"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("kind", DataType::Utf8, false).with_doc("kernel or memcpy"),
+            Field::new("name", DataType::Utf8, false)
+                .with_doc("Demangled kernel name, or the direction of the copy such as HtoD"),
+            Field::new("device", DataType::Int64, false),
+            Field::new("stream", DataType::Int64, false),
+            Field::new("correlation", DataType::Int64, false)
+                .with_doc("CUPTI id of the API call that launched it"),
+            Field::new("start", DataType::Int64, false)
+                .with_doc("Start on the GPU clock")
+                .with_unit("ns"),
+            Field::new("duration", DataType::Int64, false).with_unit("ns"),
+            Field::new("grid", DataType::Utf8, true).with_doc("Grid size as x,y,z"),
+            Field::new("block", DataType::Utf8, true).with_doc("Block size as x,y,z"),
+            Field::new("registers", DataType::Int64, true).with_doc("Registers per thread"),
+            Field::new("shared_bytes", DataType::Int64, true)
+                .with_doc("Static and dynamic shared memory per block")
+                .with_unit("bytes"),
+            Field::new("occupancy", DataType::Float64, true).with_doc(
+                "Theoretical occupancy from 0 to 1, from the block size, registers and shared memory",
+            ),
+            Field::new("bytes", DataType::Int64, true)
+                .with_doc("Bytes copied")
+                .with_unit("bytes"),
+            Field::new("stack", DataType::Utf8, false).with_doc(
+                "Python frames of the launching thread, outermost first and separated by ;",
+            ),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let rows = gpu_kernels::rows();
+        let ints = |f: fn(&KernelRow) -> Option<i64>| -> ArrayRef {
+            Arc::new(Int64Array::from_iter(rows.iter().map(f)))
+        };
+        let dims = |f: fn(&KernelRow) -> Option<[i32; 3]>| -> ArrayRef {
+            Arc::new(StringArray::from_iter(
+                rows.iter()
+                    .map(|r| f(r).map(|[x, y, z]| format!("{x},{y},{z}"))),
+            ))
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.kind))),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|r| r.name.as_str()),
+                )),
+                ints(|r| Some(r.device)),
+                ints(|r| Some(r.stream)),
+                ints(|r| Some(r.correlation)),
+                ints(|r| Some(r.start)),
+                ints(|r| Some(r.duration)),
+                dims(|r| r.grid),
+                dims(|r| r.block),
+                ints(|r| r.registers),
+                ints(|r| r.shared_bytes),
+                Arc::new(Float64Array::from_iter(rows.iter().map(|r| r.occupancy))),
+                ints(|r| r.bytes),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|r| r.stack.as_ref()),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type KernelsPlugin = TablePluginHelper<KernelsTable>;
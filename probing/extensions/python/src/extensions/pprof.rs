@@ -1,20 +1,390 @@
+use std::sync::Arc;
+
 use probing_core::core::EngineCall;
 use probing_core::core::EngineDatasource;
 use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
 use probing_core::core::Maybe;
+use probing_core::core::{
+    BooleanArray, CustomTable, DataType, Field, FieldDoc, Float64Array, Int64Array, RecordBatch,
+    Schema, SchemaRef, StringArray, TablePluginHelper,
+};
+use probing_proto::prelude::SelfCheck;
+
+use crate::features::heatmap;
+use crate::features::pprof::ProfileMode;
+use crate::features::sampling;
+use crate::features::step_profile;
+use crate::features::wall_profiler::LineHits;
 
 #[derive(Debug, Default, EngineExtension)]
+#[extension(on_enable = "resume", on_disable = "pause", selftest = "selftest")]
 pub struct PprofExtension {
     /// CPU profiling sample frequency in Hz (higher values increase overhead)
     #[option(aliases=["sample.freq"])]
     sample_freq: Maybe<i32>,
+
+    /// Sampling mode: "cpu" (default) for on-CPU native samples, or "wall" to
+    /// sample all Python threads by wall time, split into on-cpu, gil-wait,
+    /// io-wait and idle
+    #[option()]
+    mode: Maybe<String>,
+
+    /// Sample frequency in Hz that can be changed while sampling is live; the
+    /// new rate applies in place and the samples so far are kept
+    #[option(min = 1)]
+    frequency: Maybe<i32>,
+
+    /// Module whose per-line wall samples `probe.heatmap` shows, as a dotted
+    /// name (`mypkg.trainer`) or part of a path (`train.py`)
+    #[option()]
+    heatmap_module: Maybe<String>,
+
+    /// Fraction of the interval by which each wall sample is moved at
+    /// random, so that sampling cannot lock onto periodic work (0 to 0.5)
+    #[option(min = 0, max = 0.5)]
+    jitter: Maybe<f64>,
+
+    /// Delay of the first sample after sampling starts, as a fraction of the
+    /// interval (0 to 1)
+    #[option(min = 0, max = 1)]
+    phase: Maybe<f64>,
+
+    /// Seed of every random choice of the samplers and the torch probe, for
+    /// runs that are sampled alike; unset, they vary from run to run
+    #[option()]
+    seed: Maybe<u64>,
 }
 
 impl EngineCall for PprofExtension {}
 
-impl EngineDatasource for PprofExtension {}
+impl EngineDatasource for PprofExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        match name {
+            Some("heatmap") => Some(HeatmapPlugin::create(namespace, "heatmap")),
+            Some("spans") => Some(SpansPlugin::create(namespace, "spans")),
+            Some("packages") => Some(PackagesPlugin::create(namespace, "packages")),
+            Some("steps") => Some(StepsPlugin::create(namespace, "steps")),
+            Some(name) => Some(OverheadPlugin::create(namespace, name)),
+            None => None,
+        }
+    }
+}
+
+/// Samples taken and dropped by the profilers, to tell whether a profile is
+/// under-sampled
+#[derive(Default, Debug)]
+pub struct OverheadTable {}
+
+impl CustomTable for OverheadTable {
+    fn name() -> &'static str {
+        "overhead"
+    }
+
+    fn description() -> &'static str {
+        "Samples captured and dropped by the profilers since they started"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("sampler", DataType::Utf8, false).with_doc("cpu or wall"),
+            Field::new("running", DataType::Boolean, false),
+            Field::new("frequency", DataType::Int64, false).with_unit("Hz"),
+            Field::new("captured", DataType::Int64, false).with_unit("samples"),
+            Field::new("dropped", DataType::Int64, false)
+                .with_doc("Samples lost because the buffer was full")
+                .with_unit("samples"),
+            Field::new("drop_ratio", DataType::Float64, false),
+            Field::new("pending", DataType::Int64, false)
+                .with_doc("Samples waiting to be aggregated")
+                .with_unit("samples"),
+            Field::new("capacity", DataType::Int64, false).with_unit("samples"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let stats = crate::features::pprof::sampler_stats();
+        let ratio = |s: &crate::features::pprof::SamplerStats| match s.captured + s.dropped {
+            0 => 0.0,
+            total => s.dropped as f64 / total as f64,
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    stats.iter().map(|s| s.sampler),
+                )),
+                Arc::new(BooleanArray::from(
+                    stats.iter().map(|s| s.running).collect::<Vec<_>>(),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.iter().map(|s| s.frequency as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.iter().map(|s| s.captured as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.iter().map(|s| s.dropped as i64),
+                )),
+                Arc::new(Float64Array::from_iter_values(stats.iter().map(ratio))),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.iter().map(|s| s.pending as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    stats.iter().map(|s| s.capacity as i64),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type OverheadPlugin = TablePluginHelper<OverheadTable>;
+
+/// Wall samples per source line of `probing.pprof.heatmap_module`
+#[derive(Default, Debug)]
+pub struct HeatmapTable {}
+
+impl CustomTable for HeatmapTable {
+    fn name() -> &'static str {
+        "heatmap"
+    }
+
+    fn description() -> &'static str {
+        "Wall samples per source line of the module set by probing.pprof.heatmap_module"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("file", DataType::Utf8, false),
+            Field::new("lineno", DataType::Int64, false),
+            Field::new("func", DataType::Utf8, false),
+            Field::new("samples", DataType::Int64, false)
+                .with_doc("Samples with the line anywhere on the stack")
+                .with_unit("samples"),
+            Field::new("self_samples", DataType::Int64, false)
+                .with_doc("Samples with the line in the innermost frame")
+                .with_unit("samples"),
+            Field::new("heat", DataType::Float64, false)
+                .with_doc("Samples relative to the hottest line of the file, from 0 to 1"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let hits = heatmap::heatmap(&heatmap::module());
+        let hottest = heatmap::hottest(&hits);
+        let heat = |hit: &LineHits| match hottest.get(hit.file.as_str()) {
+            Some(max) if *max > 0 => hit.samples as f64 / *max as f64,
+            _ => 0.0,
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    hits.iter().map(|h| h.file.as_str()),
+                )),
+                Arc::new(Int64Array::from_iter_values(hits.iter().map(|h| h.lineno))),
+                Arc::new(StringArray::from_iter_values(
+                    hits.iter().map(|h| h.func.as_str()),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    hits.iter().map(|h| h.samples as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    hits.iter().map(|h| h.self_samples as i64),
+                )),
+                Arc::new(Float64Array::from_iter_values(hits.iter().map(heat))),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type HeatmapPlugin = TablePluginHelper<HeatmapTable>;
+
+/// Self time of the trace spans, from the spans active when each sample was
+/// taken
+#[derive(Default, Debug)]
+pub struct SpansTable {}
+
+impl CustomTable for SpansTable {
+    fn name() -> &'static str {
+        "spans"
+    }
+
+    fn description() -> &'static str {
+        "Self time of the trace spans estimated from the samples of the active profiler"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("span", DataType::Utf8, true)
+                .with_doc("Innermost span active when sampled, NULL outside any span"),
+            Field::new("self_time", DataType::Float64, false)
+                .with_doc("On CPU in cpu mode, wall time in wall mode")
+                .with_unit("s"),
+            Field::new("share", DataType::Float64, false)
+                .with_doc("Fraction of all the samples, from 0 to 1"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let times = crate::features::pprof::span_times().unwrap_or_default();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from(
+                    times.iter().map(|t| t.span.clone()).collect::<Vec<_>>(),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    times.iter().map(|t| t.self_time.as_secs_f64()),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    times.iter().map(|t| t.share),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type SpansPlugin = TablePluginHelper<SpansTable>;
+
+/// CPU time by the package of the innermost frame of the samples
+#[derive(Default, Debug)]
+pub struct PackagesTable {}
+
+impl CustomTable for PackagesTable {
+    fn name() -> &'static str {
+        "packages"
+    }
+
+    fn description() -> &'static str {
+        "CPU time of the active profiler by the package its innermost frames belong to"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("package", DataType::Utf8, false)
+                .with_doc("Top level package, module or shared library"),
+            Field::new("origin", DataType::Utf8, false).with_doc(
+                "user, package, extension, stdlib, interpreter (Python code in cpu mode) or native",
+            ),
+            Field::new("self_time", DataType::Float64, false)
+                .with_doc("On CPU with the package in the innermost frame")
+                .with_unit("s"),
+            Field::new("share", DataType::Float64, false)
+                .with_doc("Fraction of the on-cpu samples, from 0 to 1"),
+            Field::new("hot_frame", DataType::Utf8, false)
+                .with_doc("Innermost frame of the package with the most samples"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let times = crate::features::breakdown::package_times(None).unwrap_or_default();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    times.iter().map(|t| t.package.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    times.iter().map(|t| t.origin.as_str()),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    times.iter().map(|t| t.self_time.as_secs_f64()),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    times.iter().map(|t| t.share),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    times.iter().map(|t| t.hot_frame.as_str()),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type PackagesPlugin = TablePluginHelper<PackagesTable>;
+
+/// Profiles of the steps bracketed by `probing.profile_step()`
+#[derive(Default, Debug)]
+pub struct StepsTable {}
+
+impl CustomTable for StepsTable {
+    fn name() -> &'static str {
+        "steps"
+    }
+
+    fn description() -> &'static str {
+        "Profiles of the last steps bracketed by probing.profile_step(), see /apis/steps/{step}"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("step", DataType::Int64, false),
+            Field::new("timestamp", DataType::Int64, false)
+                .with_doc("Start of the step")
+                .with_unit("us"),
+            Field::new("duration", DataType::Float64, false).with_unit("s"),
+            Field::new("mode", DataType::Utf8, false),
+            Field::new("frequency", DataType::Int64, false)
+                .with_doc("Sample rate within the step")
+                .with_unit("Hz"),
+            Field::new("samples", DataType::Int64, false)
+                .with_doc("Weight of the stacks sampled within the step, in wall mode microseconds")
+                .with_unit("samples"),
+            Field::new("stacks", DataType::Int64, false).with_doc("Distinct stacks sampled"),
+            Field::new("error", DataType::Utf8, true)
+                .with_doc("Exception raised out of the step, NULL if it completed"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let steps = step_profile::profiles();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(steps.iter().map(|s| s.step))),
+                Arc::new(Int64Array::from_iter_values(
+                    steps.iter().map(|s| s.timestamp),
+                )),
+                Arc::new(Float64Array::from_iter_values(
+                    steps.iter().map(|s| s.duration.as_secs_f64()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    steps.iter().map(|s| s.mode.to_string()),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    steps.iter().map(|s| s.frequency as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    steps.iter().map(|s| s.samples() as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    steps.iter().map(|s| s.folded.len() as i64),
+                )),
+                Arc::new(StringArray::from_iter(
+                    steps.iter().map(|s| s.error.as_deref()),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type StepsPlugin = TablePluginHelper<StepsTable>;
 
 impl PprofExtension {
     fn set_sample_freq(&mut self, pprof_sample_freq: Maybe<i32>) -> Result<(), EngineError> {
@@ -36,15 +406,126 @@ impl PprofExtension {
                         ));
                     }
                     self.sample_freq = pprof_sample_freq.clone();
-                    crate::features::pprof::setup(freq as u64).map_err(|e| {
-                        EngineError::InvalidOptionValue(
-                            Self::OPTION_SAMPLE_FREQ.to_string(),
-                            e.to_string(),
-                        )
-                    })?;
+                    crate::features::pprof::setup_with_mode(freq as u64, self.profile_mode())
+                        .map_err(|e| {
+                            EngineError::InvalidOptionValue(
+                                Self::OPTION_SAMPLE_FREQ.to_string(),
+                                e.to_string(),
+                            )
+                        })?;
                     Ok(())
                 }
             },
         }
     }
+
+    fn set_mode(&mut self, mode: Maybe<String>) -> Result<(), EngineError> {
+        if let Maybe::Just(ref value) = mode {
+            if value.parse::<ProfileMode>().is_err() {
+                return Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_MODE.to_string(),
+                    value.clone(),
+                ));
+            }
+        }
+        self.mode = mode;
+        // Restart a running profiler so the new mode takes effect immediately.
+        self.resume()
+    }
+
+    fn set_frequency(&mut self, frequency: Maybe<i32>) -> Result<(), EngineError> {
+        let Maybe::Just(freq) = frequency else {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_FREQUENCY.to_string(),
+                frequency.into(),
+            ));
+        };
+        if freq < 1 {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_FREQUENCY.to_string(),
+                frequency.into(),
+            ));
+        }
+        self.frequency = frequency;
+        // not sampling yet: the rate is picked up by the next start
+        crate::features::pprof::set_frequency(freq as u64)
+            .map_err(|e| EngineError::PluginError(e.to_string()))?;
+        Ok(())
+    }
+
+    fn set_heatmap_module(&mut self, module: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = module.clone().into();
+        heatmap::set_module(&value);
+        self.heatmap_module = module;
+        Ok(())
+    }
+
+    fn set_jitter(&mut self, jitter: Maybe<f64>) -> Result<(), EngineError> {
+        sampling::set_jitter(Option::<f64>::from(jitter.clone()).unwrap_or_default());
+        self.jitter = jitter;
+        Ok(())
+    }
+
+    fn set_phase(&mut self, phase: Maybe<f64>) -> Result<(), EngineError> {
+        sampling::set_phase(Option::<f64>::from(phase.clone()).unwrap_or_default());
+        self.phase = phase;
+        Ok(())
+    }
+
+    fn set_seed(&mut self, seed: Maybe<u64>) -> Result<(), EngineError> {
+        sampling::set_seed(Option::from(seed.clone()));
+        self.seed = seed;
+        Ok(())
+    }
+
+    /// Rate to sample at, `frequency` overriding the initial `sample_freq`
+    fn freq(&self) -> Option<i32> {
+        match (&self.frequency, &self.sample_freq) {
+            (Maybe::Just(freq), _) | (Maybe::Nothing, Maybe::Just(freq)) => Some(*freq),
+            _ => None,
+        }
+    }
+
+    fn profile_mode(&self) -> ProfileMode {
+        match &self.mode {
+            Maybe::Just(mode) => mode.parse().unwrap_or_default(),
+            Maybe::Nothing => ProfileMode::default(),
+        }
+    }
+
+    fn resume(&mut self) -> Result<(), EngineError> {
+        if let (Some(freq), Maybe::Just(_)) = (self.freq(), &self.sample_freq) {
+            crate::features::pprof::setup_with_mode(freq as u64, self.profile_mode())
+                .map_err(|e| EngineError::PluginError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn selftest(&self) -> Vec<SelfCheck> {
+        let sampling = match self.freq() {
+            Some(freq) => SelfCheck::pass(
+                "sampling",
+                format!("{:?} samples at {freq} Hz", self.profile_mode()),
+            ),
+            None => SelfCheck::fail(
+                "sampling",
+                format!("not sampling, set {}", Self::OPTION_SAMPLE_FREQ),
+            ),
+        };
+        let mut checks = vec![sampling];
+        if let ProfileMode::Cpu = self.profile_mode() {
+            checks.push(
+                match crate::features::cpu_sampler::check_signal_delivery() {
+                    Ok(detail) => SelfCheck::pass("signal delivery", detail),
+                    Err(e) => SelfCheck::fail("signal delivery", e.to_string()),
+                },
+            );
+        }
+        checks
+    }
+
+    fn pause(&mut self) -> Result<(), EngineError> {
+        crate::features::pprof::reset();
+        Ok(())
+    }
 }
@@ -11,14 +11,19 @@ use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
 use probing_core::core::Maybe;
 use probing_proto::prelude::CallFrame;
-use pyo3::types::PyAnyMethods;
-use pyo3::Python;
+use pyo3::types::{PyAnyMethods, PyModule};
+use pyo3::{Bound, PyAny, PyResult, Python};
 
+pub use exttbls::external_tables_nbytes;
+pub use exttbls::external_time_series;
 pub use exttbls::ExternalTable;
 pub use exttbls::PyExternalTableConfig;
 pub use tbls::PythonPlugin;
 
+use crate::features::counters::enable_counters;
+use crate::features::func_tracer::set_trace_functions;
 use crate::features::stack_tracer::{SignalTracer, StackTracer};
+use crate::features::udf;
 use crate::python::enable_crash_handler;
 use crate::python::enable_monitoring;
 use crate::python::CRASH_HANDLER;
@@ -29,7 +34,7 @@ mod exttbls;
 mod stack;
 mod tbls;
 
-pub use stack::get_python_stacks;
+pub use stack::{get_all_python_stacks, get_python_stacks, ThreadFrame};
 pub use tbls::PythonNamespace;
 
 /// Collection of Python extensions loaded into the system
@@ -70,6 +75,26 @@ pub struct PythonExt {
     #[option()]
     disabled: Maybe<String>,
 
+    /// Functions traced via sys.monitoring (Python 3.12+), e.g. `pkg.mod:func,pkg.mod:Class.method`.
+    /// Set to empty to stop tracing.
+    #[option(aliases = ["trace.functions"])]
+    trace_functions: Maybe<String>,
+
+    /// Count calls per function group and exceptions per type in
+    /// `python.counters` (Python 3.12+). Off by default.
+    #[option(choices = ["true", "false"])]
+    counters: Maybe<bool>,
+
+    /// Milliseconds a Python UDF may spend on one batch of rows
+    #[option(aliases = ["udf.timeout"])]
+    udf_timeout: Maybe<u64>,
+
+    /// Include the threads of subinterpreters in `python.stacks` and wall
+    /// profiles. Their frames are read without their GIL, which may crash the
+    /// process if they exit meanwhile. Off by default.
+    #[option(choices = ["true", "false"])]
+    subinterpreters: Maybe<bool>,
+
     tracer: Box<dyn StackTracer>,
 }
 
@@ -80,6 +105,10 @@ impl Default for PythonExt {
             monitoring: Default::default(),
             enabled: Default::default(),
             disabled: Default::default(),
+            trace_functions: Default::default(),
+            counters: Maybe::Just(false),
+            udf_timeout: Maybe::Just(udf::DEFAULT_TIMEOUT_MS),
+            subinterpreters: Maybe::Just(false),
             tracer: Box::new(SignalTracer),
         }
     }
@@ -115,6 +144,28 @@ impl EngineCall for PythonExt {
                 EngineError::PluginError(format!("Failed to serialize call stack: {e}"))
             });
         }
+        if path == "threads" {
+            let stacks = match params.get("tid") {
+                Some(tid) => {
+                    let tid = tid.parse::<i32>().map_err(|e| {
+                        EngineError::InvalidOptionValue("tid".to_string(), e.to_string())
+                    })?;
+                    self.tracer.trace_thread(tid).map(|stack| vec![stack])
+                }
+                None => self.tracer.trace_threads(),
+            }
+            .map_err(|e| EngineError::PluginError(format!("Failed to get thread stacks: {e}")))?;
+            let stacks = match params.get("kind") {
+                Some(kind) => stacks
+                    .into_iter()
+                    .filter(|stack| stack.kind.to_string() == *kind)
+                    .collect(),
+                None => stacks,
+            };
+            return serde_json::to_vec(&stacks).map_err(|e| {
+                EngineError::PluginError(format!("Failed to serialize thread stacks: {e}"))
+            });
+        }
         if path == "eval" {
             let code = String::from_utf8(body.to_vec()).map_err(|e| {
                 log::error!("Failed to convert body to UTF-8 string: {e}");
@@ -124,8 +175,34 @@ impl EngineCall for PythonExt {
             log::debug!("Python eval code: {code}");
 
             let mut repl = PythonRepl::default();
+            if params.get("format").map(String::as_str) == Some("json") {
+                return serde_json::to_vec(&repl.evaluate(code.as_str()))
+                    .map_err(|e| EngineError::PluginError(e.to_string()));
+            }
             return Ok(repl.process(code.as_str()).unwrap_or_default().into_bytes());
         }
+        if path == "script" {
+            let script = String::from_utf8(body.to_vec())
+                .map_err(|e| EngineError::PluginError(format!("invalid script: {e}")))?;
+            let keep_going = params.contains_key("keep_going");
+            return repl_text(|repl| repl.call_method1("evaluate_script", (script, keep_going)))
+                .map_err(|e| EngineError::PluginError(format!("failed to run script: {e}")));
+        }
+        if path == "sessions" {
+            return repl_text(|repl| repl.call_method0("sessions"))
+                .map_err(|e| EngineError::PluginError(format!("failed to list sessions: {e}")));
+        }
+        if path == "session" {
+            let (name, save) = (params.get("name"), params.get("save"));
+            return repl_text(|repl| repl.call_method1("session_script", (name, save)))
+                .map_err(|e| EngineError::PluginError(format!("failed to get session: {e}")));
+        }
+        if path == "tensor" {
+            let expr = String::from_utf8(body.to_vec())
+                .map_err(|e| EngineError::PluginError(format!("invalid tensor expression: {e}")))?;
+            return export_tensor(&expr, params.get("index"), params.get("limit"))
+                .map_err(|e| EngineError::PluginError(format!("failed to export {expr}: {e}")));
+        }
         if path == "flamegraph" {
             return Ok(crate::features::torch::flamegraph().into_bytes());
         }
@@ -133,6 +210,32 @@ impl EngineCall for PythonExt {
     }
 }
 
+/// Text returned by a function of `probing.repl`, e.g. the JSON of the
+/// steps of a script or a recorded session
+fn repl_text<F>(call: F) -> Result<Vec<u8>>
+where
+    F: for<'py> FnOnce(&Bound<'py, PyModule>) -> PyResult<Bound<'py, PyAny>>,
+{
+    Python::with_gil(|py| {
+        let text = call(&py.import("probing.repl")?)?.extract::<String>()?;
+        Ok(text.into_bytes())
+    })
+}
+
+/// Slice and encode a tensor of the target, see `probing.inspect.tensor`
+fn export_tensor(expr: &str, index: Option<&String>, limit: Option<&String>) -> Result<Vec<u8>> {
+    let limit = limit.map(|l| l.parse::<usize>()).transpose()?;
+    Python::with_gil(|py| {
+        let tensor = py.import("probing.inspect.tensor")?;
+        let limit = match limit {
+            Some(limit) => limit,
+            None => tensor.getattr("MAX_ELEMENTS")?.extract()?,
+        };
+        let data = tensor.call_method1("export", (expr, index, limit))?;
+        Ok(data.extract::<&[u8]>()?.to_vec())
+    })
+}
+
 impl EngineDatasource for PythonExt {
     /// Create a plugin instance for the specified namespace
     fn datasrc(
@@ -279,6 +382,63 @@ impl PythonExt {
             Ok(())
         }
     }
+
+    /// Replace the set of functions traced via sys.monitoring
+    fn set_trace_functions(&mut self, trace_functions: Maybe<String>) -> Result<(), EngineError> {
+        let specs: String = trace_functions.clone().into();
+        match set_trace_functions(&specs) {
+            Ok(traced) => {
+                log::info!("Python function tracing updated: {traced:?}");
+                self.trace_functions = trace_functions;
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to trace functions '{specs}': {e}");
+                Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_TRACE_FUNCTIONS.to_string(),
+                    specs,
+                ))
+            }
+        }
+    }
+
+    fn set_counters(&mut self, counters: Maybe<bool>) -> Result<(), EngineError> {
+        let enable = matches!(counters, Maybe::Just(true));
+        match enable_counters(enable) {
+            Ok(()) => {
+                self.counters = Maybe::Just(enable);
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Failed to switch python counters: {e}");
+                Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_COUNTERS.to_string(),
+                    e.to_string(),
+                ))
+            }
+        }
+    }
+
+    fn set_udf_timeout(&mut self, udf_timeout: Maybe<u64>) -> Result<(), EngineError> {
+        match udf_timeout {
+            Maybe::Just(ms) if ms > 0 => {
+                udf::set_timeout(ms);
+                self.udf_timeout = udf_timeout;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_UDF_TIMEOUT.to_string(),
+                udf_timeout.into(),
+            )),
+        }
+    }
+
+    fn set_subinterpreters(&mut self, subinterpreters: Maybe<bool>) -> Result<(), EngineError> {
+        let enable = matches!(subinterpreters, Maybe::Just(true));
+        crate::features::spy::threads::enable(enable);
+        self.subinterpreters = Maybe::Just(enable);
+        Ok(())
+    }
 }
 
 /// Execute Python code and return the resulting object
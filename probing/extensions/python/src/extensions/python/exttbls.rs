@@ -2,12 +2,14 @@ use std::sync::Arc;
 use std::{collections::HashMap, sync::Mutex};
 
 use once_cell::sync::Lazy;
-use probing_proto::prelude::{Ele, TimeSeries};
+use probing_proto::prelude::{Codec, Ele, TimeSeries};
 use probing_proto::types::series::DiscardStrategy;
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyType};
 use pyo3::{pyclass, pymethods, Bound, IntoPyObjectExt, PyObject, PyResult, Python};
 
+use crate::pkg::Histogram;
+
 fn value_to_object(py: Python, v: &Ele) -> PyObject {
     let ret = match v {
         Ele::Nil => Option::<i32>::None.into_bound_py_any(py),
@@ -23,6 +25,19 @@ fn value_to_object(py: Python, v: &Ele) -> PyObject {
     ret.map(|x| x.unbind()).unwrap_or(py.None())
 }
 
+/// Parse the codec names given to `ExternalTable`, pco when unset
+fn parse_codecs(codecs: Option<Vec<String>>) -> PyResult<Vec<Codec>> {
+    codecs
+        .unwrap_or_default()
+        .iter()
+        .map(|name| {
+            name.parse::<Codec>().map_err(|_| {
+                pyo3::exceptions::PyValueError::new_err(format!("unknown codec {name}"))
+            })
+        })
+        .collect()
+}
+
 #[pyclass]
 pub struct PyExternalTableConfig {
     #[pyo3(get)]
@@ -99,6 +114,25 @@ impl PyExternalTableConfig {
 pub static EXTERN_TABLES: Lazy<Mutex<HashMap<String, Arc<Mutex<TimeSeries>>>>> =
     Lazy::new(|| Mutex::new(Default::default()));
 
+/// Time series backing the external table `name`, if any
+pub fn external_time_series(name: &str) -> Option<Arc<Mutex<TimeSeries>>> {
+    EXTERN_TABLES.lock().unwrap().get(name).cloned()
+}
+
+/// Memory held by all external tables
+pub fn external_tables_nbytes() -> usize {
+    let tables = EXTERN_TABLES
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    tables
+        .iter()
+        .filter_map(|ts| ts.lock().ok().map(|ts| ts.nbytes()))
+        .sum()
+}
+
 #[pyclass]
 #[derive(Clone, Debug)]
 pub struct ExternalTable(Arc<Mutex<TimeSeries>>, usize);
@@ -106,15 +140,17 @@ pub struct ExternalTable(Arc<Mutex<TimeSeries>>, usize);
 #[pymethods]
 impl ExternalTable {
     #[new]
-    #[pyo3(signature = (name, columns, chunk_size = 10000, discard_threshold = 20_000_000, discard_strategy = "BaseMemorySize".to_string()))]
+    #[pyo3(signature = (name, columns, chunk_size = 10000, discard_threshold = 20_000_000, discard_strategy = "BaseMemorySize".to_string(), codecs = None))]
     fn new(
         name: &str,
         columns: Vec<String>,
         chunk_size: usize,
         discard_threshold: usize,
         discard_strategy: String,
-    ) -> Self {
+        codecs: Option<Vec<String>>,
+    ) -> PyResult<Self> {
         let ncolumn = columns.len();
+        let codecs = parse_codecs(codecs)?;
         let config = PyExternalTableConfig {
             chunk_size,
             discard_threshold,
@@ -123,6 +159,7 @@ impl ExternalTable {
         let config: DiscardStrategy = config.into();
         let ts = Arc::new(Mutex::new(
             TimeSeries::builder_with_config(config)
+                .with_codecs(codecs)
                 .with_columns(columns)
                 .build(),
         ));
@@ -130,7 +167,7 @@ impl ExternalTable {
             .lock()
             .unwrap()
             .insert(name.to_string(), ts.clone());
-        ExternalTable(ts, ncolumn)
+        Ok(ExternalTable(ts, ncolumn))
     }
 
     #[classmethod]
@@ -148,7 +185,7 @@ impl ExternalTable {
     }
 
     #[classmethod]
-    #[pyo3(signature = (name, columns, chunk_size = 10000, discard_threshold = 20_000_000, discard_strategy = "BaseMemorySize".to_string()))]
+    #[pyo3(signature = (name, columns, chunk_size = 10000, discard_threshold = 20_000_000, discard_strategy = "BaseMemorySize".to_string(), codecs = None))]
     fn get_or_create(
         _cls: &Bound<'_, PyType>,
         name: &str,
@@ -156,6 +193,7 @@ impl ExternalTable {
         chunk_size: usize,
         discard_threshold: usize,
         discard_strategy: String,
+        codecs: Option<Vec<String>>,
     ) -> PyResult<ExternalTable> {
         let mut binding = EXTERN_TABLES.lock().unwrap();
         let ts = binding.get(name);
@@ -164,6 +202,7 @@ impl ExternalTable {
             Ok(ExternalTable(ts.clone(), ncolumn))
         } else {
             let ncolumn = columns.len();
+            let codecs = parse_codecs(codecs)?;
             let config = PyExternalTableConfig {
                 chunk_size,
                 discard_threshold,
@@ -172,6 +211,7 @@ impl ExternalTable {
             let config: DiscardStrategy = config.into();
             let ts = Arc::new(Mutex::new(
                 TimeSeries::builder_with_config(config)
+                    .with_codecs(codecs)
                     .with_columns(columns)
                     .build(),
             ));
@@ -210,6 +250,8 @@ impl ExternalTable {
                         Ele::F64(v)
                     } else if let Ok(v) = v.extract::<String>(py) {
                         Ele::Text(v)
+                    } else if let Ok(v) = v.extract::<PyRef<Histogram>>(py) {
+                        Ele::Text(v.0.to_string())
                     } else {
                         Ele::Nil
                     }
@@ -238,6 +280,8 @@ impl ExternalTable {
                         Ele::F64(v)
                     } else if let Ok(v) = v.extract::<String>(py) {
                         Ele::Text(v)
+                    } else if let Ok(v) = v.extract::<PyRef<Histogram>>(py) {
+                        Ele::Text(v.0.to_string())
                     } else {
                         Ele::Nil
                     }
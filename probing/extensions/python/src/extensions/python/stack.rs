@@ -1,8 +1,14 @@
+use std::collections::HashMap;
 use std::ffi::CString;
 
 use log::error;
+use probing_proto::prelude::CallFrame;
 use pyo3::{prelude::*, types::PyDict};
 
+use crate::features::backtrace;
+use crate::features::spy::threads::{self, subinterpreter_threads};
+use crate::features::spy::PYVERSION;
+
 const STACK_THREADS: &str = include_str!("stack_get_threads.py");
 
 pub fn get_python_stacks(tid: i32) -> Option<String> {
@@ -10,10 +16,23 @@ pub fn get_python_stacks(tid: i32) -> Option<String> {
 
     Python::with_gil(|py| {
         log::debug!("Start calling backtrace for TID: {tid:?}");
+        let policy = backtrace::policy();
+        let formatters = policy
+            .formatters
+            .iter()
+            .map(|(class, formatter)| (class.clone(), formatter.to_string()))
+            .collect::<HashMap<_, _>>();
         let global = PyDict::new(py);
-        global.set_item("tid", tid).unwrap_or_else(|err| {
-            error!("Failed to set 'tid' in Python global dict: {err}");
-        });
+        let params = [
+            global.set_item("tid", tid),
+            global.set_item("max_depth", policy.max_depth),
+            global.set_item("locals_capture", policy.locals.to_string()),
+            global.set_item("max_repr", policy.max_repr),
+            global.set_item("formatters", formatters),
+        ];
+        for err in params.into_iter().filter_map(Result::err) {
+            error!("Failed to set the parameters of the Python stack dump script: {err}");
+        }
         let script_cstr = CString::new(STACK_THREADS).unwrap_or_default();
         if let Err(err) = py.run(&script_cstr, Some(&global), Some(&global)) {
             error!("Failed to execute Python stack dump script: {err}");
@@ -32,3 +51,111 @@ pub fn get_python_stacks(tid: i32) -> Option<String> {
         }
     })
 }
+
+/// One Python frame of one thread, as captured by [`get_all_python_stacks`].
+#[derive(Debug, Clone)]
+pub struct ThreadFrame {
+    /// Id of the interpreter running the thread, 0 for the main interpreter
+    pub interpreter: i64,
+    pub thread_id: i64,
+    pub native_id: Option<i64>,
+    pub thread_name: Option<String>,
+    pub is_daemon: Option<bool>,
+    /// Whether the thread held the GIL when the capture started, unknown on
+    /// Python versions the thread state reader does not support
+    pub holds_gil: Option<bool>,
+    /// Whether this is the thread doing the capture, which then holds the GIL
+    pub capturing: bool,
+    pub depth: i64,
+    pub file: String,
+    pub func: String,
+    pub lineno: i64,
+}
+
+/// Capture the Python stacks of all threads via `sys._current_frames()`,
+/// each limited to `probing.backtrace.max_depth` frames.
+///
+/// Unlike [`get_python_stacks`] this needs no signal: the frames are read while
+/// holding the GIL, by the thread marked `capturing` when it runs Python. The
+/// thread that held the GIL before is read from the thread states first, so
+/// `holds_gil` names the thread the capture waited for. Threads of
+/// subinterpreters are invisible to `sys._current_frames()` and are read by the
+/// spy threadstate walker instead.
+pub fn get_all_python_stacks() -> PyResult<Vec<ThreadFrame>> {
+    #[allow(static_mut_refs)]
+    let version = unsafe { &PYVERSION };
+    let holder = threads::supported(version).then(|| threads::gil_holder(version));
+    Python::with_gil(|py| {
+        let threading = py.import("threading")?;
+        let current = threading.call_method0("get_ident")?.extract::<i64>()?;
+
+        let mut threads = HashMap::new();
+        for thread in threading.call_method0("enumerate")?.try_iter()? {
+            let thread = thread?;
+            if let Some(ident) = thread.getattr("ident")?.extract::<Option<i64>>()? {
+                threads.insert(ident, thread);
+            }
+        }
+
+        let policy = backtrace::policy();
+        let frames = py.import("sys")?.call_method0("_current_frames")?;
+        let mut rows = vec![];
+        for (tid, frame) in frames.downcast::<PyDict>()?.iter() {
+            let thread_id = tid.extract::<i64>()?;
+            let thread = threads.get(&thread_id);
+            let native_id = thread.and_then(|t| t.getattr("native_id").ok()?.extract().ok());
+            let thread_name = thread.and_then(|t| t.getattr("name").ok()?.extract().ok());
+            let is_daemon = thread.and_then(|t| t.getattr("daemon").ok()?.extract().ok());
+
+            let mut depth = 0;
+            let mut curr = frame;
+            while !curr.is_none() && (policy.max_depth == 0 || depth < policy.max_depth as i64) {
+                let code = curr.getattr("f_code")?;
+                rows.push(ThreadFrame {
+                    interpreter: 0,
+                    thread_id,
+                    native_id,
+                    thread_name: thread_name.clone(),
+                    is_daemon,
+                    holds_gil: holder.map(|h| h == Some(thread_id as u64)),
+                    capturing: thread_id == current,
+                    depth,
+                    file: code.getattr("co_filename")?.extract()?,
+                    func: code.getattr("co_name")?.extract()?,
+                    lineno: curr
+                        .getattr("f_lineno")?
+                        .extract::<Option<i64>>()?
+                        .unwrap_or(0),
+                });
+                depth += 1;
+                curr = curr.getattr("f_back")?;
+            }
+        }
+
+        let threads = subinterpreter_threads(version);
+        for mut thread in threads {
+            policy.truncate(&mut thread.frames);
+            for (depth, frame) in thread.frames.into_iter().enumerate() {
+                if let CallFrame::PyFrame {
+                    file, func, lineno, ..
+                } = frame
+                {
+                    rows.push(ThreadFrame {
+                        interpreter: thread.interpreter,
+                        thread_id: thread.thread_id as i64,
+                        native_id: thread.native_id.map(|id| id as i64),
+                        thread_name: None,
+                        is_daemon: None,
+                        holds_gil: Some(thread.holds_gil),
+                        capturing: false,
+                        depth: depth as i64,
+                        file,
+                        func,
+                        lineno,
+                    });
+                }
+            }
+        }
+        Ok(rows)
+    })
+}
@@ -10,21 +10,39 @@ def _get_obj_type(obj):
         return str(type(obj))
 
 
-def _get_obj_repr(obj, value=False):
+if "max_depth" not in locals():
+    max_depth = 0
+if "locals_capture" not in locals():
+    locals_capture = "values"
+if "max_repr" not in locals():
+    max_repr = 150
+if "formatters" not in locals():
+    formatters = {}
+
+
+def _get_obj_repr(obj):
     typ = _get_obj_type(obj)
     ret = {
         "id": id(obj),
-        "class": _get_obj_type(obj),
+        "class": typ,
     }
-    if typ == "torch.Tensor":
+    if locals_capture != "values":
+        return ret
+    formatter = formatters.get(typ)
+    if formatter == "type":
+        return ret
+    if typ == "torch.Tensor" and formatter != "value":
         ret["shape"] = str(obj.shape)
         ret["dtype"] = str(obj.dtype)
         ret["device"] = str(obj.device)
-        if value and "cpu" in ret["device"]:
-            ret["value"] = str(obj)[:150]
+        if formatter is None and "cpu" in ret["device"]:
+            ret["value"] = str(obj)[:max_repr]
+    elif formatter == "summary":
+        for attr in ("shape", "dtype", "device"):
+            if hasattr(obj, attr):
+                ret[attr] = str(getattr(obj, attr))
     else:
-        if value:
-            ret["value"] = str(obj)[:150]
+        ret["value"] = str(obj)[:max_repr]
     return ret
 
 
@@ -42,13 +60,13 @@ tid, nid = get_threads(), tid
 
 if tid in frames:
     curr = frames[tid]
-    while curr is not None:
+    while curr is not None and (max_depth == 0 or len(stacks) < max_depth):
         stack = {"PyFrame": {
             "file": curr.f_code.co_filename,
             "func": curr.f_code.co_name,
             "lineno": curr.f_lineno,
-            "locals": {
-                k: _get_obj_repr(v, value=True) for k, v in curr.f_locals.items()
+            "locals": {} if locals_capture == "none" else {
+                k: _get_obj_repr(v) for k, v in curr.f_locals.items()
             },
         }}
         stacks.append(stack)
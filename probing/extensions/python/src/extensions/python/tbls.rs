@@ -1,17 +1,18 @@
 use std::collections::HashMap;
 use std::ffi::CString;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use async_trait::async_trait;
 
 use log::error;
 use probing_core::core::{
-    ArrayRef, CustomNamespace, DataType, Field, Float64Array, Int64Array, NamespacePluginHelper,
-    RecordBatch, Schema, SchemaRef, StringArray,
+    ArrayRef, BooleanArray, CustomNamespace, DataType, Field, Float64Array, Int64Array,
+    NamespacePluginHelper, RecordBatch, Schema, SchemaRef, StringArray,
 };
-use probing_core::core::{Float32Array, Int32Array, LazyTableSource};
-use probing_proto::prelude::{CallFrame, Ele, TimeSeries};
-use probing_proto::types;
+use probing_core::core::{DataFusionError, LazyTableSource, TableProvider, TimeSeriesSource};
+use probing_proto::prelude::{CallFrame, TimeSeries};
 use pyo3::types::PyAnyMethods;
 use pyo3::types::PyDict;
 use pyo3::types::PyDictMethods;
@@ -23,6 +24,12 @@ use pyo3::Bound;
 use pyo3::PyAny;
 use pyo3::Python;
 
+/// Minimum interval between two captures of `python.stacks`; queries in
+/// between reuse the previous snapshot.
+const STACKS_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+static STACKS_SNAPSHOT: Mutex<Option<(Instant, Vec<RecordBatch>)>> = Mutex::new(None);
+
 #[derive(Default, Debug)]
 pub struct PythonNamespace {}
 
@@ -96,6 +103,120 @@ impl PythonNamespace {
         Ok(vec![RecordBatch::try_new(schema, columns)?])
     }
 
+    fn get_stacks_data() -> Result<Vec<RecordBatch>> {
+        let mut snapshot = STACKS_SNAPSHOT
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock STACKS_SNAPSHOT: {:?}", e))?;
+        if let Some((captured, data)) = snapshot.as_ref() {
+            if captured.elapsed() < STACKS_MIN_INTERVAL {
+                return Ok(data.clone());
+            }
+        }
+
+        let frames = super::stack::get_all_python_stacks()
+            .map_err(|e| anyhow::anyhow!("Failed to capture python stacks: {:?}", e))?;
+
+        let schema = SchemaRef::new(Schema::new(vec![
+            Field::new("interpreter", DataType::Int64, false),
+            Field::new("thread_id", DataType::Int64, false),
+            Field::new("native_id", DataType::Int64, true),
+            Field::new("thread_name", DataType::Utf8, true),
+            Field::new("is_daemon", DataType::Boolean, true),
+            Field::new("holds_gil", DataType::Boolean, true),
+            Field::new("depth", DataType::Int64, false),
+            Field::new("file", DataType::Utf8, false),
+            Field::new("func", DataType::Utf8, false),
+            Field::new("lineno", DataType::Int64, false),
+        ]));
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from_iter_values(
+                frames.iter().map(|f| f.interpreter),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                frames.iter().map(|f| f.thread_id),
+            )),
+            Arc::new(Int64Array::from_iter(frames.iter().map(|f| f.native_id))),
+            Arc::new(StringArray::from_iter(
+                frames.iter().map(|f| f.thread_name.as_deref()),
+            )),
+            Arc::new(BooleanArray::from_iter(frames.iter().map(|f| f.is_daemon))),
+            Arc::new(BooleanArray::from_iter(frames.iter().map(|f| f.holds_gil))),
+            Arc::new(Int64Array::from_iter_values(frames.iter().map(|f| f.depth))),
+            Arc::new(StringArray::from_iter_values(
+                frames.iter().map(|f| f.file.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                frames.iter().map(|f| f.func.as_str()),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                frames.iter().map(|f| f.lineno),
+            )),
+        ];
+
+        let data = vec![RecordBatch::try_new(schema, columns)?];
+        *snapshot = Some((Instant::now(), data.clone()));
+        Ok(data)
+    }
+
+    fn get_packages_data() -> Result<Vec<RecordBatch>> {
+        let packages = crate::features::packages::package_list()?;
+
+        let schema = SchemaRef::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("version", DataType::Utf8, false),
+            Field::new("location", DataType::Utf8, true),
+        ]));
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                packages.iter().map(|p| p.name.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                packages.iter().map(|p| p.version.as_str()),
+            )),
+            Arc::new(StringArray::from_iter(
+                packages.iter().map(|p| p.location.as_deref()),
+            )),
+        ];
+
+        Ok(vec![RecordBatch::try_new(schema, columns)?])
+    }
+
+    fn get_counters_data() -> Result<Vec<RecordBatch>> {
+        let counters = crate::features::counters::counters()?;
+
+        let schema = SchemaRef::new(Schema::new(vec![
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("group", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("count", DataType::Int64, false),
+            Field::new("functions", DataType::Int64, true),
+            Field::new("saturated", DataType::Boolean, false),
+        ]));
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(
+                counters.iter().map(|c| c.kind.as_str()),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                counters.iter().map(|c| c.group.as_str()),
+            )),
+            Arc::new(StringArray::from_iter(
+                counters.iter().map(|c| c.name.as_deref()),
+            )),
+            Arc::new(Int64Array::from_iter_values(
+                counters.iter().map(|c| c.count),
+            )),
+            Arc::new(Int64Array::from_iter(counters.iter().map(|c| c.functions))),
+            Arc::new(BooleanArray::from_iter(
+                counters.iter().map(|c| Some(c.saturated)),
+            )),
+        ];
+
+        Ok(vec![RecordBatch::try_new(schema, columns)?])
+    }
+
     fn data_from_python(expr: &str) -> Result<Vec<RecordBatch>> {
         Python::with_gil(|py| {
             let parts: Vec<&str> = expr.split('.').collect();
@@ -145,20 +266,15 @@ impl PythonNamespace {
             .get(expr)
             .ok_or_else(|| anyhow::anyhow!("Table '{}' not found", expr))?;
 
-        let names = table
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to lock table: {:?}", e))?
-            .names
-            .clone();
-
         let ts = table
             .lock()
             .map_err(|e| anyhow::anyhow!("Failed to lock table: {:?}", e))?;
 
-        Self::time_series_to_recordbatch(names, &ts)
+        Self::time_series_to_recordbatch(&ts)
     }
 }
 
+#[async_trait]
 impl CustomNamespace for PythonNamespace {
     fn name() -> &'static str {
         "python"
@@ -173,6 +289,9 @@ impl CustomNamespace for PythonNamespace {
             |binding| binding.keys().cloned().collect(),
         );
         tables.push("backtrace".to_string()); // Add backtrace to the list
+        tables.push("stacks".to_string());
+        tables.push("packages".to_string());
+        tables.push("counters".to_string());
         tables
     }
 
@@ -185,6 +304,30 @@ impl CustomNamespace for PythonNamespace {
                     vec![]
                 }
             }
+        } else if expr == "stacks" {
+            match Self::get_stacks_data() {
+                Ok(batches) => batches,
+                Err(e) => {
+                    error!("Error getting stacks data: {e:?}");
+                    vec![]
+                }
+            }
+        } else if expr == "packages" {
+            match Self::get_packages_data() {
+                Ok(batches) => batches,
+                Err(e) => {
+                    error!("Error getting packages data: {e:?}");
+                    vec![]
+                }
+            }
+        } else if expr == "counters" {
+            match Self::get_counters_data() {
+                Ok(batches) => batches,
+                Err(e) => {
+                    error!("Error getting counters data: {e:?}");
+                    vec![]
+                }
+            }
         } else if Self::list().contains(&expr.to_string()) {
             match Self::data_from_extern(expr) {
                 Ok(batches) => batches,
@@ -205,8 +348,14 @@ impl CustomNamespace for PythonNamespace {
     }
 
     fn make_lazy(expr: &str) -> Arc<LazyTableSource> {
-        if expr == "backtrace" {
-            let data = Self::get_backtrace_data().unwrap_or_default();
+        if ["backtrace", "stacks", "packages", "counters"].contains(&expr) {
+            let data = match expr {
+                "backtrace" => Self::get_backtrace_data(),
+                "stacks" => Self::get_stacks_data(),
+                "counters" => Self::get_counters_data(),
+                _ => Self::get_packages_data(),
+            }
+            .unwrap_or_default();
             let schema = if data.is_empty() {
                 None
             } else {
@@ -219,158 +368,33 @@ impl CustomNamespace for PythonNamespace {
             });
         }
 
-        let binding = super::exttbls::EXTERN_TABLES.lock().map_or_else(
-            |e| {
-                log::error!("Failed to lock EXTERN_TABLES: {e:?}");
-                Default::default()
-            },
-            |binding| binding.clone(),
-        );
-
-        if binding.contains_key(expr) {
-            let table = binding.get(expr).unwrap();
-            let names = table.lock().unwrap().names.clone();
-            let dtypes = table
-                .lock()
-                .unwrap()
-                .cols
-                .iter()
-                .map(|x| x.dtype())
-                .collect::<Vec<_>>();
-            let mut fields = vec![Field::new("timestamp", DataType::Int64, true)];
-
-            for (name, dtype) in names.iter().zip(dtypes.iter()) {
-                fields.push(Field::new(
-                    name,
-                    match dtype {
-                        types::EleType::I64 => DataType::Int64,
-                        types::EleType::F64 => DataType::Float64,
-                        types::EleType::I32 => DataType::Int32,
-                        types::EleType::F32 => DataType::Float32,
-                        _ => DataType::Utf8,
-                    },
-                    false,
-                ));
-            }
-
-            let schema = Some(SchemaRef::new(Schema::new(fields)));
-
-            Arc::new(LazyTableSource {
-                name: expr.to_string(),
-                schema,
-                data: Self::data_from_extern(expr).unwrap_or_default(),
-            })
+        let data: Vec<RecordBatch> = Self::data_from_python(expr).unwrap_or_default();
+        let schema = if data.is_empty() {
+            None
         } else {
-            let data: Vec<RecordBatch> = Self::data_from_python(expr).unwrap_or_default();
-            let schema = if data.is_empty() {
-                None
-            } else {
-                Some(data[0].schema().clone())
-            };
-            Arc::new(LazyTableSource {
-                name: expr.to_string(),
-                schema,
-                data,
-            })
+            Some(data[0].schema().clone())
+        };
+        Arc::new(LazyTableSource {
+            name: expr.to_string(),
+            schema,
+            data,
+        })
+    }
+
+    /// External tables are scanned in place, so time filters can skip chunks
+    async fn table(
+        expr: String,
+    ) -> std::result::Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        if let Some(series) = super::exttbls::external_time_series(&expr) {
+            return Ok(Some(Arc::new(TimeSeriesSource::new(expr, series))));
         }
+        Ok(Some(Self::make_lazy(expr.as_str())))
     }
 }
 
 impl PythonNamespace {
-    pub fn time_series_to_recordbatch(
-        names: Vec<String>,
-        ts: &TimeSeries,
-    ) -> Result<Vec<RecordBatch>> {
-        let mut fields: Vec<Field> = vec![];
-        let mut columns: Vec<ArrayRef> = vec![];
-
-        fields.push(Field::new("timestamp", DataType::Int64, true));
-        names.iter().zip(ts.cols.iter()).for_each(|(name, col)| {
-            let data_type = match col.dtype() {
-                types::EleType::I64 => DataType::Int64,
-                types::EleType::F64 => DataType::Float64,
-                types::EleType::I32 => DataType::Int32,
-                types::EleType::F32 => DataType::Float32,
-                _ => DataType::Utf8,
-            };
-            fields.push(Field::new(name, data_type, false));
-        });
-
-        let length = ts.len();
-
-        let timeseries = ts
-            .timestamp
-            .iter()
-            .take(length)
-            .map(|x| match x {
-                Ele::I64(x) => x,
-                _ => 0,
-            })
-            .collect::<Vec<_>>();
-        columns.push(Arc::new(Int64Array::from(timeseries)));
-
-        for col in ts.cols.iter() {
-            let col = match col.dtype() {
-                types::EleType::I64 => Arc::new(Int64Array::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| match x {
-                            Ele::I64(x) => x,
-                            _ => 0,
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-                types::EleType::F64 => Arc::new(Float64Array::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| match x {
-                            Ele::F64(x) => x,
-                            _ => 0.0,
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-                types::EleType::I32 => Arc::new(Int32Array::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| match x {
-                            Ele::I32(x) => x,
-                            _ => 0,
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-                types::EleType::F32 => Arc::new(Float32Array::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| match x {
-                            Ele::F32(x) => x,
-                            _ => 0.0,
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-                types::EleType::Text => Arc::new(StringArray::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| match x {
-                            Ele::Text(x) => x,
-                            _ => x.to_string(),
-                        })
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-                _ => Arc::new(StringArray::from(
-                    col.iter()
-                        .take(length)
-                        .map(|x| x.to_string())
-                        .collect::<Vec<_>>(),
-                )) as ArrayRef,
-            };
-
-            columns.push(col);
-        }
-
-        Ok(vec![RecordBatch::try_new(
-            SchemaRef::new(Schema::new(fields)),
-            columns,
-        )?])
+    pub fn time_series_to_recordbatch(ts: &TimeSeries) -> Result<Vec<RecordBatch>> {
+        Ok(probing_core::core::time_series_to_recordbatch(ts)?)
     }
 
     pub fn object_to_recordbatch(obj: Bound<'_, PyAny>) -> Result<Vec<RecordBatch>> {
@@ -1,9 +1,18 @@
+use std::sync::Arc;
+
+use anyhow::Result;
 use probing_core::core::EngineCall;
 use probing_core::core::EngineDatasource;
 use probing_core::core::EngineError;
 use probing_core::core::EngineExtension;
 use probing_core::core::EngineExtensionOption;
 use probing_core::core::Maybe;
+use probing_core::core::{
+    ArrayRef, BooleanArray, CustomNamespace, DataType, Field, Float64Array, Int64Array,
+    LazyTableSource, NamespacePluginHelper, RecordBatch, Schema, SchemaRef, StringArray,
+};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
 
 use super::python::execute_python_code;
 
@@ -13,8 +22,8 @@ pub struct TorchExtension {
     #[option()]
     profiling_mode: Maybe<String>,
 
-    /// PyTorch profiling sample rate (range: 0.0-1.0)
-    #[option(aliases=["sample.rate"])]
+    /// PyTorch profiling sample rate
+    #[option(aliases=["sample.rate"], min = 0, max = 1)]
     sample_rate: Maybe<f64>,
 
     /// Variables to capture during PyTorch profiling
@@ -25,7 +34,15 @@ pub struct TorchExtension {
 
 impl EngineCall for TorchExtension {}
 
-impl EngineDatasource for TorchExtension {}
+impl EngineDatasource for TorchExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        _name: Option<&str>,
+    ) -> Option<Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        Some(TorchPlugin::create(namespace))
+    }
+}
 
 impl TorchExtension {
     fn set_profiling_mode(&mut self, profiling_mode: Maybe<String>) -> Result<(), EngineError> {
@@ -52,14 +69,6 @@ impl TorchExtension {
     }
 
     fn set_sample_rate(&mut self, sample_rate: Maybe<f64>) -> Result<(), EngineError> {
-        if let Maybe::Just(rate) = sample_rate {
-            if !(0.0..=1.0).contains(&rate) {
-                return Err(EngineError::InvalidOptionValue(
-                    Self::OPTION_SAMPLE_RATE.to_string(),
-                    rate.to_string(),
-                ));
-            }
-        }
         self.sample_rate = sample_rate;
         Ok(())
     }
@@ -70,3 +79,137 @@ impl TorchExtension {
         Ok(())
     }
 }
+
+/// Tables summarizing models registered with `probing.watch_model`
+#[derive(Default, Debug)]
+pub struct TorchNamespace {}
+
+const PARAMETER_COLUMNS: &[(&str, DataType)] = &[
+    ("model", DataType::Utf8),
+    ("name", DataType::Utf8),
+    ("shape", DataType::Utf8),
+    ("dtype", DataType::Utf8),
+    ("device", DataType::Utf8),
+    ("layout", DataType::Utf8),
+    ("numel", DataType::Int64),
+    ("requires_grad", DataType::Boolean),
+    ("norm", DataType::Float64),
+    ("grad_norm", DataType::Float64),
+];
+
+const OPTIMIZER_STATE_COLUMNS: &[(&str, DataType)] = &[
+    ("model", DataType::Utf8),
+    ("optimizer", DataType::Utf8),
+    ("param_group", DataType::Int64),
+    ("lr", DataType::Float64),
+    ("param", DataType::Utf8),
+    ("slot", DataType::Utf8),
+    ("shape", DataType::Utf8),
+    ("dtype", DataType::Utf8),
+    ("layout", DataType::Utf8),
+    ("numel", DataType::Int64),
+    ("norm", DataType::Float64),
+    ("value", DataType::Float64),
+];
+
+impl TorchNamespace {
+    fn table_def(expr: &str) -> Option<(&'static str, &'static [(&'static str, DataType)])> {
+        match expr {
+            "parameters" => Some(("get_torch_parameters", PARAMETER_COLUMNS)),
+            "optimizer_state" => Some(("get_torch_optimizer_state", OPTIMIZER_STATE_COLUMNS)),
+            _ => None,
+        }
+    }
+
+    fn schema(columns: &[(&str, DataType)]) -> SchemaRef {
+        SchemaRef::new(Schema::new(
+            columns
+                .iter()
+                .map(|(name, dtype)| Field::new(*name, dtype.clone(), true))
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    fn get_data(func: &str, columns: &[(&str, DataType)]) -> Result<RecordBatch> {
+        Python::with_gil(|py| {
+            let rows = py.import("probing.inspect.torch")?.getattr(func)?.call0()?;
+            let rows = rows
+                .downcast::<PyList>()
+                .map_err(|e| anyhow::anyhow!("{func} should return a list: {e}"))?;
+
+            let mut arrays: Vec<ArrayRef> = vec![];
+            for (name, dtype) in columns {
+                let mut values = vec![];
+                for row in rows.iter() {
+                    let row = row
+                        .downcast::<PyDict>()
+                        .map_err(|e| anyhow::anyhow!("{func} should return dicts: {e}"))?
+                        .clone();
+                    values.push(row.get_item(name)?.filter(|v| !v.is_none()));
+                }
+                let array: ArrayRef = match dtype {
+                    DataType::Int64 => Arc::new(Int64Array::from(
+                        values
+                            .iter()
+                            .map(|v| v.as_ref().and_then(|v| v.extract::<i64>().ok()))
+                            .collect::<Vec<_>>(),
+                    )),
+                    DataType::Float64 => Arc::new(Float64Array::from(
+                        values
+                            .iter()
+                            .map(|v| v.as_ref().and_then(|v| v.extract::<f64>().ok()))
+                            .collect::<Vec<_>>(),
+                    )),
+                    DataType::Boolean => Arc::new(BooleanArray::from(
+                        values
+                            .iter()
+                            .map(|v| v.as_ref().and_then(|v| v.extract::<bool>().ok()))
+                            .collect::<Vec<_>>(),
+                    )),
+                    _ => Arc::new(StringArray::from(
+                        values
+                            .iter()
+                            .map(|v| v.as_ref().map(|v| v.to_string()))
+                            .collect::<Vec<_>>(),
+                    )),
+                };
+                arrays.push(array);
+            }
+            Ok(RecordBatch::try_new(Self::schema(columns), arrays)?)
+        })
+    }
+}
+
+impl CustomNamespace for TorchNamespace {
+    fn name() -> &'static str {
+        "torch"
+    }
+
+    fn list() -> Vec<String> {
+        vec!["parameters".to_string(), "optimizer_state".to_string()]
+    }
+
+    fn data(expr: &str) -> Vec<RecordBatch> {
+        let Some((func, columns)) = Self::table_def(expr) else {
+            return vec![];
+        };
+        match Self::get_data(func, columns) {
+            Ok(batch) => vec![batch],
+            Err(e) => {
+                log::error!("Error getting torch.{expr} data: {e:?}");
+                vec![]
+            }
+        }
+    }
+
+    fn make_lazy(expr: &str) -> Arc<LazyTableSource> {
+        let schema = Self::table_def(expr).map(|(_, columns)| Self::schema(columns));
+        Arc::new(LazyTableSource {
+            name: expr.to_string(),
+            schema,
+            data: Self::data(expr),
+        })
+    }
+}
+
+pub type TorchPlugin = NamespacePluginHelper<TorchNamespace>;
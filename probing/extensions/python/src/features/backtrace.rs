@@ -0,0 +1,180 @@
+//! Bounds on what a backtrace captures, set by the `probing.backtrace.*`
+//! options.
+//!
+//! Capturing every frame with the repr of every local is costly on deep
+//! stacks and may leak tokens or user data into a diagnosis bundle. The
+//! policy limits the depth of the stacks of both the signal-based and the
+//! spy-based captures, and how much of the locals the stack dump script
+//! records.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+pub const DEFAULT_MAX_REPR: usize = 150;
+
+/// How much of the locals of a frame is captured
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LocalsCapture {
+    /// No locals at all
+    None,
+    /// Names and types, without the value
+    Names,
+    /// Names, types and truncated values
+    #[default]
+    Values,
+}
+
+impl FromStr for LocalsCapture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "none" => Ok(Self::None),
+            "names" => Ok(Self::Names),
+            "values" => Ok(Self::Values),
+            other => Err(format!("unknown locals capture `{other}`")),
+        }
+    }
+}
+
+impl Display for LocalsCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Names => write!(f, "names"),
+            Self::Values => write!(f, "values"),
+        }
+    }
+}
+
+/// How locals of a given type are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Formatter {
+    /// Type only
+    Type,
+    /// Shape, dtype and device, for tensors and arrays
+    Summary,
+    /// Truncated `str()` of the value
+    Value,
+}
+
+impl FromStr for Formatter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "type" => Ok(Self::Type),
+            "summary" => Ok(Self::Summary),
+            "value" => Ok(Self::Value),
+            other => Err(format!("unknown formatter `{other}`")),
+        }
+    }
+}
+
+impl Display for Formatter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Type => write!(f, "type"),
+            Self::Summary => write!(f, "summary"),
+            Self::Value => write!(f, "value"),
+        }
+    }
+}
+
+/// Parse per-type formatters, e.g. `torch.Tensor=summary,builtins.str=type`
+pub fn parse_formatters(spec: &str) -> Result<BTreeMap<String, Formatter>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (class, formatter) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected <type>=<formatter>, got `{item}`"))?;
+            Ok((class.trim().to_string(), formatter.parse()?))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktracePolicy {
+    /// Innermost frames kept per stack, 0 for all of them
+    pub max_depth: usize,
+    pub locals: LocalsCapture,
+    /// Characters kept of the value of a local
+    pub max_repr: usize,
+    /// Formatters by fully qualified type name, e.g. `torch.Tensor`
+    pub formatters: BTreeMap<String, Formatter>,
+}
+
+impl Default for BacktracePolicy {
+    fn default() -> Self {
+        Self {
+            max_depth: 0,
+            locals: LocalsCapture::default(),
+            max_repr: DEFAULT_MAX_REPR,
+            formatters: BTreeMap::new(),
+        }
+    }
+}
+
+impl BacktracePolicy {
+    /// Drop the outermost frames beyond `max_depth`; stacks list the
+    /// innermost frame first
+    pub fn truncate<T>(&self, frames: &mut Vec<T>) {
+        if self.max_depth > 0 {
+            frames.truncate(self.max_depth);
+        }
+    }
+}
+
+static POLICY: RwLock<BacktracePolicy> = RwLock::new(BacktracePolicy {
+    max_depth: 0,
+    locals: LocalsCapture::Values,
+    max_repr: DEFAULT_MAX_REPR,
+    formatters: BTreeMap::new(),
+});
+
+/// Policy in effect for the next capture
+pub fn policy() -> BacktracePolicy {
+    POLICY.read().map(|p| p.clone()).unwrap_or_default()
+}
+
+pub fn update_policy(update: impl FnOnce(&mut BacktracePolicy)) {
+    if let Ok(mut policy) = POLICY.write() {
+        update(&mut policy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formatters() {
+        let formatters = parse_formatters("torch.Tensor=summary, builtins.str=type,").unwrap();
+        assert_eq!(formatters["torch.Tensor"], Formatter::Summary);
+        assert_eq!(formatters["builtins.str"], Formatter::Type);
+        assert!(parse_formatters("torch.Tensor").is_err());
+        assert!(parse_formatters("torch.Tensor=pretty").is_err());
+        assert!(parse_formatters("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut frames = vec![1, 2, 3];
+        let policy = BacktracePolicy {
+            max_depth: 2,
+            ..Default::default()
+        };
+        policy.truncate(&mut frames);
+        assert_eq!(frames, vec![1, 2]);
+        BacktracePolicy::default().truncate(&mut frames);
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!("none".parse::<LocalsCapture>(), Ok(LocalsCapture::None));
+        assert_eq!(LocalsCapture::Names.to_string(), "names");
+        assert!("all".parse::<LocalsCapture>().is_err());
+    }
+}
@@ -0,0 +1,340 @@
+//! CPU time of the active profiler by the package its innermost frame
+//! belongs to, served as `probe.packages` and as a treemap by
+//! `/apis/flamegraph?format=treemap`.
+//!
+//! In cpu mode a sample goes to the executable or shared library it
+//! interrupted, so Python code shows up as the interpreter and native code
+//! as the package shipping it (`torch`, `numpy`, ...). In wall mode only the
+//! on-cpu samples count, each going to the Python file of its innermost
+//! frame, so the time in a C extension goes to the Python code calling it.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use probing_proto::prelude::TreemapNode;
+
+use super::cpu_sampler::CPU_SAMPLER;
+use super::pprof::{active_mode, ProfileMode};
+use super::wall_profiler::{ThreadState, WALL_PROFILER};
+
+/// Frames kept per package in the treemap, the others are merged
+const MAX_FRAMES: usize = 20;
+
+/// Where the code of a frame comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Origin {
+    /// Python code outside any installed package
+    User,
+    /// Python code of an installed package
+    Package,
+    /// Native code shipped by an installed package
+    Extension,
+    /// The standard library, in Python or native
+    Stdlib,
+    /// The interpreter itself, running Python code in cpu mode
+    Interpreter,
+    /// Other shared libraries: libc, the CUDA driver, ...
+    Native,
+}
+
+impl Origin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Origin::User => "user",
+            Origin::Package => "package",
+            Origin::Extension => "extension",
+            Origin::Stdlib => "stdlib",
+            Origin::Interpreter => "interpreter",
+            Origin::Native => "native",
+        }
+    }
+}
+
+/// First path component under `site-packages` or `dist-packages`
+fn installed_package(path: &str) -> Option<&str> {
+    let (_, rest) = path
+        .split_once("/site-packages/")
+        .or_else(|| path.split_once("/dist-packages/"))?;
+    let name = rest.split('/').next()?;
+    // a top level module: `six.py`, `_cffi_backend.cpython-312-x86_64-linux-gnu.so`
+    name.split('.').next().filter(|name| !name.is_empty())
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Origin and package of the Python source file `path`
+pub fn classify_file(path: &str) -> (Origin, String) {
+    if let Some(package) = installed_package(path) {
+        return (Origin::Package, package.to_string());
+    }
+    // `<frozen importlib._bootstrap>`, `<string>`
+    if let Some(name) = path.strip_prefix('<').and_then(|p| p.strip_suffix('>')) {
+        return match name.strip_prefix("frozen ") {
+            Some(module) => (Origin::Stdlib, module.split('.').next().unwrap().into()),
+            None => (Origin::User, path.to_string()),
+        };
+    }
+    let module = file_name(path).trim_end_matches(".py");
+    if path.contains("/lib/python3") {
+        // `json/decoder.py` belongs to `json`
+        let stdlib = path.split_once("/lib/python3").map(|(_, p)| p).unwrap();
+        let package = stdlib.split('/').nth(1).unwrap_or(module);
+        return (Origin::Stdlib, package.trim_end_matches(".py").to_string());
+    }
+    (Origin::User, module.to_string())
+}
+
+/// Origin and package of the executable or shared library `path`
+pub fn classify_object(path: &str) -> (Origin, String) {
+    if path.is_empty() {
+        return (Origin::Native, "[unknown]".to_string());
+    }
+    if let Some(package) = installed_package(path) {
+        return (Origin::Extension, package.to_string());
+    }
+    let name = file_name(path);
+    if path.contains("/lib-dynload/") {
+        return (Origin::Stdlib, name.split('.').next().unwrap().to_string());
+    }
+    if name.starts_with("libpython") || name.starts_with("python") {
+        return (Origin::Interpreter, "python".to_string());
+    }
+    let library = name.split(".so").next().unwrap_or(name);
+    (Origin::Native, library.to_string())
+}
+
+/// Innermost function and file of a wall frame, `func (file:line)`
+fn parse_frame(frame: &str) -> Option<&str> {
+    let (_, location) = frame.rsplit_once(" (")?;
+    let (file, _) = location.strip_suffix(')')?.rsplit_once(':')?;
+    Some(file)
+}
+
+/// Weight in microseconds by origin, package and innermost frame
+type Leaves = HashMap<(Origin, String, String), u64>;
+
+fn leaves(span: Option<&str>) -> Result<Leaves> {
+    let mut leaves = Leaves::new();
+    match active_mode() {
+        ProfileMode::Cpu => {
+            for ((object, symbol), weight) in CPU_SAMPLER.leaf_weights(span)? {
+                let (origin, package) = classify_object(&object);
+                *leaves.entry((origin, package, symbol)).or_default() += weight;
+            }
+        }
+        ProfileMode::Wall => {
+            for line in WALL_PROFILER.folded(span)? {
+                let Some((stack, weight)) = line.rsplit_once(' ') else {
+                    continue;
+                };
+                let on_cpu = ThreadState::OnCpu.as_str();
+                if stack.split(';').next() != Some(on_cpu) {
+                    continue;
+                }
+                let frame = stack.rsplit(';').next().unwrap_or_default();
+                let Some(file) = parse_frame(frame) else {
+                    continue;
+                };
+                let (origin, package) = classify_file(file);
+                *leaves
+                    .entry((origin, package, frame.to_string()))
+                    .or_default() += weight.parse::<u64>().unwrap_or(0);
+            }
+        }
+    }
+    Ok(leaves)
+}
+
+/// Self time of a package, reported by `probe.packages`
+#[derive(Debug, Clone)]
+pub struct PackageTime {
+    pub package: String,
+    pub origin: Origin,
+    /// On CPU, estimated from the samples and the sample rate
+    pub self_time: Duration,
+    /// Fraction of all the on-cpu samples
+    pub share: f64,
+    /// Innermost frame with the most samples
+    pub hot_frame: String,
+}
+
+fn package_times_of(leaves: Leaves) -> Vec<PackageTime> {
+    let total = leaves.values().sum::<u64>().max(1) as f64;
+    let mut packages: HashMap<(Origin, String), (u64, String, u64)> = HashMap::new();
+    for ((origin, package, frame), weight) in leaves {
+        let (sum, hot, hot_weight) = packages.entry((origin, package)).or_default();
+        *sum += weight;
+        if weight > *hot_weight || (weight == *hot_weight && frame < *hot) {
+            (*hot, *hot_weight) = (frame, weight);
+        }
+    }
+    let mut times = packages
+        .into_iter()
+        .map(|((origin, package), (weight, hot_frame, _))| PackageTime {
+            package,
+            origin,
+            self_time: Duration::from_micros(weight),
+            share: weight as f64 / total,
+            hot_frame,
+        })
+        .collect::<Vec<_>>();
+    times.sort_by(|a, b| {
+        b.self_time
+            .cmp(&a.self_time)
+            .then_with(|| a.package.cmp(&b.package))
+    });
+    times
+}
+
+/// Self time by package of the samples of the active profiler, of those
+/// taken within a trace span called `span` if given, most expensive first
+pub fn package_times(span: Option<&str>) -> Result<Vec<PackageTime>> {
+    Ok(package_times_of(leaves(span)?))
+}
+
+fn node(name: String, mut children: Vec<TreemapNode>) -> TreemapNode {
+    children.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.name.cmp(&b.name)));
+    TreemapNode {
+        name,
+        value: children.iter().map(|c| c.value).sum(),
+        children,
+    }
+}
+
+fn treemap_of(leaves: Leaves) -> TreemapNode {
+    let mut tree: HashMap<Origin, HashMap<String, Vec<TreemapNode>>> = HashMap::new();
+    for ((origin, package, frame), value) in leaves {
+        tree.entry(origin)
+            .or_default()
+            .entry(package)
+            .or_default()
+            .push(TreemapNode {
+                name: frame,
+                value,
+                children: vec![],
+            });
+    }
+    let origins = tree
+        .into_iter()
+        .map(|(origin, packages)| {
+            let packages = packages
+                .into_iter()
+                .map(|(package, frames)| {
+                    let mut package = node(package, frames);
+                    if package.children.len() > MAX_FRAMES {
+                        let rest = package.children.split_off(MAX_FRAMES - 1);
+                        package.children.push(TreemapNode {
+                            name: "[other]".to_string(),
+                            value: rest.iter().map(|f| f.value).sum(),
+                            children: vec![],
+                        });
+                    }
+                    package
+                })
+                .collect();
+            node(origin.as_str().to_string(), packages)
+        })
+        .collect();
+    node("all".to_string(), origins)
+}
+
+/// Samples of the active profiler as a treemap of origins, packages and
+/// innermost frames, weighted in microseconds
+pub fn treemap(span: Option<&str>) -> Result<TreemapNode> {
+    Ok(treemap_of(leaves(span)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let site = "/venv/lib/python3.12/site-packages";
+        assert_eq!(
+            classify_file(&format!("{site}/torch/nn/modules/linear.py")),
+            (Origin::Package, "torch".to_string())
+        );
+        assert_eq!(
+            classify_file("/usr/lib/python3.12/json/decoder.py"),
+            (Origin::Stdlib, "json".to_string())
+        );
+        assert_eq!(
+            classify_file("/usr/lib/python3.12/threading.py"),
+            (Origin::Stdlib, "threading".to_string())
+        );
+        assert_eq!(
+            classify_file("<frozen importlib._bootstrap>"),
+            (Origin::Stdlib, "importlib".to_string())
+        );
+        assert_eq!(
+            classify_file("/work/train.py"),
+            (Origin::User, "train".to_string())
+        );
+
+        assert_eq!(
+            classify_object(&format!("{site}/torch/lib/libtorch_cpu.so")),
+            (Origin::Extension, "torch".to_string())
+        );
+        assert_eq!(
+            classify_object(&format!(
+                "{site}/_cffi_backend.cpython-312-x86_64-linux-gnu.so"
+            )),
+            (Origin::Extension, "_cffi_backend".to_string())
+        );
+        assert_eq!(
+            classify_object("/usr/lib/python3.12/lib-dynload/_json.cpython-312.so"),
+            (Origin::Stdlib, "_json".to_string())
+        );
+        assert_eq!(
+            classify_object("/usr/lib/libpython3.12.so.1.0"),
+            (Origin::Interpreter, "python".to_string())
+        );
+        assert_eq!(
+            classify_object("/lib/x86_64-linux-gnu/libc.so.6"),
+            (Origin::Native, "libc".to_string())
+        );
+        assert_eq!(
+            parse_frame("forward (/work/model.py:42)"),
+            Some("/work/model.py")
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let leaf =
+            |origin, package: &str, frame: &str| (origin, package.to_string(), frame.to_string());
+        let leaves = Leaves::from([
+            (leaf(Origin::Extension, "torch", "at::native::addmm"), 600),
+            (leaf(Origin::Extension, "torch", "at::native::copy_"), 100),
+            (
+                leaf(Origin::Interpreter, "python", "_PyEval_EvalFrameDefault"),
+                300,
+            ),
+        ]);
+
+        let times = package_times_of(leaves.clone());
+        assert_eq!(times.len(), 2);
+        assert_eq!(times[0].package, "torch");
+        assert_eq!(times[0].self_time, Duration::from_micros(700));
+        assert_eq!(times[0].hot_frame, "at::native::addmm");
+        assert!((times[0].share - 0.7).abs() < 1e-9);
+
+        let tree = treemap_of(leaves);
+        assert_eq!(tree.value, 1000);
+        assert_eq!(tree.children[0].name, "extension");
+        let torch = &tree.children[0].children[0];
+        assert_eq!((torch.name.as_str(), torch.value), ("torch", 700));
+        assert_eq!(torch.children[0].name, "at::native::addmm");
+
+        let many = (0..30)
+            .map(|i| (leaf(Origin::User, "train", &format!("f{i}")), 1))
+            .collect::<Leaves>();
+        let train = &treemap_of(many).children[0].children[0];
+        assert_eq!(train.children.len(), MAX_FRAMES);
+        assert_eq!(train.children.last().unwrap().value, 11);
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// A row of `python.counters`: the calls of a function group or the raises
+/// of an exception type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter {
+    /// `call` or `exception`
+    pub kind: String,
+    /// Top level package of the functions, or module of the exception type
+    pub group: String,
+    /// Qualified name of the exception type, None for calls
+    pub name: Option<String>,
+    pub count: i64,
+    /// Distinct functions of the group that were called
+    pub functions: Option<i64>,
+    /// Whether a function of the group hit the per-tick cap, so that `count`
+    /// is a lower bound
+    pub saturated: bool,
+}
+
+/// Counters kept by `probing.profiling.counters` since it was enabled
+pub fn counters() -> Result<Vec<Counter>> {
+    Python::with_gil(|py| {
+        let rows = py
+            .import("probing.profiling.counters")?
+            .call_method0("counters")?
+            .extract::<Vec<Bound<'_, PyDict>>>()?;
+        rows.iter()
+            .map(|row| {
+                let item = |key: &str| row.as_any().get_item(key);
+                Ok(Counter {
+                    kind: item("kind")?.extract()?,
+                    group: item("group")?.extract()?,
+                    name: item("name")?.extract()?,
+                    count: item("count")?.extract()?,
+                    functions: item("functions")?.extract()?,
+                    saturated: item("saturated")?.extract()?,
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()
+    })
+    .map_err(|e: PyErr| anyhow::anyhow!("failed to read python counters: {e}"))
+}
+
+/// Start or stop counting calls and exceptions, Python 3.12+ only
+pub fn enable_counters(enable: bool) -> Result<()> {
+    Python::with_gil(|py| {
+        let counters = py.import("probing.profiling.counters")?;
+        counters.call_method0(if enable { "enable" } else { "disable" })?;
+        Ok(())
+    })
+}
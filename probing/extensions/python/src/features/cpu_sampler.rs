@@ -0,0 +1,526 @@
+//! On-CPU native sampler driven by `SIGPROF`.
+//!
+//! The signal handler only unwinds the interrupted thread into a fixed-size
+//! sample and pushes it into a [`SampleRing`]; symbolization and aggregation
+//! happen on the `probing-cpu-aggregator` thread. The handler never takes a
+//! lock, so it cannot block the target, and samples that do not fit in the
+//! ring are counted as dropped in `probe.overhead`. It follows frame
+//! pointers, stacks through code built without them end early.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use nix::libc;
+use once_cell::sync::Lazy;
+use probing_core::trace::active::{self, SpanTag};
+
+use super::sample_ring::{RingStats, SampleRing};
+use super::sampling;
+
+/// Frames kept per sample, deeper stacks are truncated at the root
+const MAX_DEPTH: usize = 64;
+
+/// Samples buffered between two drains of the aggregator
+const RING_CAPACITY: usize = 2048;
+
+/// How often the aggregator drains the ring
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Copy)]
+struct RawSample {
+    tid: i32,
+    /// Spans active on the thread when sampled
+    span: SpanTag,
+    depth: u16,
+    /// Instruction pointers, innermost first
+    ips: [usize; MAX_DEPTH],
+}
+
+static RING: Lazy<SampleRing<RawSample>> = Lazy::new(|| SampleRing::new(RING_CAPACITY));
+
+/// Set while a profile is being taken; the handler stays installed between
+/// profiles and ignores stray signals
+static SAMPLING: AtomicBool = AtomicBool::new(false);
+
+static INSTALL_HANDLER: Once = Once::new();
+
+/// Held while the handler is swapped, so that a check putting back the
+/// handler of the target does not undo an install for a profile
+static HANDLER_LOCK: Mutex<()> = Mutex::new(());
+
+/// `SIGPROF` signals that reached the handler, sampling or not
+static DELIVERED: AtomicU64 = AtomicU64::new(0);
+
+/// Instruction, frame and stack pointers of the interrupted code
+#[cfg(target_arch = "x86_64")]
+unsafe fn registers(ucontext: *const libc::ucontext_t) -> (usize, usize, usize) {
+    let gregs = &(*ucontext).uc_mcontext.gregs;
+    (
+        gregs[libc::REG_RIP as usize] as usize,
+        gregs[libc::REG_RBP as usize] as usize,
+        gregs[libc::REG_RSP as usize] as usize,
+    )
+}
+
+/// Instruction, frame and stack pointers of the interrupted code
+#[cfg(target_arch = "aarch64")]
+unsafe fn registers(ucontext: *const libc::ucontext_t) -> (usize, usize, usize) {
+    let mcontext = &(*ucontext).uc_mcontext;
+    (
+        mcontext.pc as usize,
+        mcontext.regs[29] as usize,
+        mcontext.sp as usize,
+    )
+}
+
+/// The saved frame pointer and return address of the frame at `fp`, `None`
+/// if unreadable. `process_vm_readv` fails on unmapped memory instead of
+/// faulting and is async-signal-safe, so a code without frame pointers only
+/// truncates the stack.
+fn read_frame(fp: usize) -> Option<[usize; 2]> {
+    let mut frame = [0usize; 2];
+    let len = std::mem::size_of_val(&frame);
+    let local = libc::iovec {
+        iov_base: frame.as_mut_ptr() as *mut libc::c_void,
+        iov_len: len,
+    };
+    let remote = libc::iovec {
+        iov_base: fp as *mut libc::c_void,
+        iov_len: len,
+    };
+    let read = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    (read == len as isize).then_some(frame)
+}
+
+/// Unwind the interrupted thread by following its frame pointers, as the
+/// libgcc unwinder takes the `dl_iterate_phdr` lock and may allocate, which
+/// deadlocks when the signal lands inside either
+extern "C" fn on_sigprof(_: libc::c_int, _: *mut libc::siginfo_t, ucontext: *mut libc::c_void) {
+    DELIVERED.fetch_add(1, Ordering::Relaxed);
+    if !SAMPLING.load(Ordering::Relaxed) || ucontext.is_null() {
+        return;
+    }
+    let mut sample = RawSample {
+        tid: unsafe { libc::syscall(libc::SYS_gettid) } as i32,
+        span: active::current(),
+        depth: 0,
+        ips: [0; MAX_DEPTH],
+    };
+    let (ip, mut fp, sp) = unsafe { registers(ucontext as *const libc::ucontext_t) };
+    sample.ips[0] = ip;
+    let mut depth = 1;
+    // frames of the callers lie above on the stack, stop at any other link
+    let mut floor = sp;
+    while depth < MAX_DEPTH && fp >= floor && fp % std::mem::align_of::<usize>() == 0 {
+        let Some([next, ret]) = read_frame(fp) else {
+            break;
+        };
+        if ret == 0 {
+            break;
+        }
+        sample.ips[depth] = ret;
+        depth += 1;
+        floor = fp + 2 * std::mem::size_of::<usize>();
+        fp = next;
+    }
+    sample.depth = depth as u16;
+    RING.push(sample);
+}
+
+/// Install [`on_sigprof`] with `SA_SIGINFO`, for the registers of the
+/// interrupted code, returning the action it replaced
+fn set_handler() -> std::io::Result<libc::sigaction> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_sigprof as usize;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    let mut previous: libc::sigaction = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sigaction(libc::SIGPROF, &action, &mut previous) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(previous)
+}
+
+fn install_handler() {
+    let _lock = HANDLER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Err(err) = set_handler() {
+        log::error!("Failed to register SIGPROF handler: {err}");
+    }
+}
+
+/// Threads of this process blocking `SIGPROF`, which are never sampled
+fn threads_blocking_sigprof() -> usize {
+    let bit = 1u64 << (libc::SIGPROF - 1);
+    std::fs::read_dir("/proc/self/task")
+        .map(|tasks| {
+            tasks
+                .flatten()
+                .filter_map(|task| std::fs::read_to_string(task.path().join("status")).ok())
+                .filter_map(|status| {
+                    let mask = status.lines().find_map(|l| l.strip_prefix("SigBlk:"))?;
+                    u64::from_str_radix(mask.trim(), 16).ok()
+                })
+                .filter(|mask| mask & bit != 0)
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+/// Check that a `SIGPROF` raised on this thread reaches the handler; a
+/// handler installed over ours by the target, or a blocked signal, leaves the
+/// CPU profile empty. Before the first profile the handler is installed for
+/// the check only, and the one of the target is put back afterwards.
+pub fn check_signal_delivery() -> Result<String> {
+    let _lock = HANDLER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if INSTALL_HANDLER.is_completed() {
+        return deliver_sigprof();
+    }
+    let previous = set_handler()?;
+    let result = deliver_sigprof();
+    if unsafe { libc::sigaction(libc::SIGPROF, &previous, std::ptr::null_mut()) } != 0 {
+        log::error!(
+            "Failed to restore the SIGPROF handler: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    result
+}
+
+fn deliver_sigprof() -> Result<String> {
+    let blocking = threads_blocking_sigprof();
+    let mask = nix::sys::signal::SigSet::thread_get_mask()?;
+    if mask.contains(nix::sys::signal::Signal::SIGPROF) {
+        anyhow::bail!("SIGPROF is blocked on {blocking} threads, these are never sampled");
+    }
+    let before = DELIVERED.load(Ordering::Relaxed);
+    // an unblocked signal raised by a thread is delivered before raise returns
+    if unsafe { libc::raise(libc::SIGPROF) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    if DELIVERED.load(Ordering::Relaxed) == before {
+        anyhow::bail!("SIGPROF did not reach the sampler, another handler replaced it");
+    }
+    match blocking {
+        0 => Ok("SIGPROF reaches the sampler".to_string()),
+        n => anyhow::bail!(
+            "SIGPROF reaches the sampler, but {n} threads block it and are never sampled"
+        ),
+    }
+}
+
+fn timeval(us: u64) -> libc::timeval {
+    libc::timeval {
+        tv_sec: (us / 1_000_000) as libc::time_t,
+        tv_usec: (us % 1_000_000) as libc::suseconds_t,
+    }
+}
+
+/// Arm the `SIGPROF` timer at `freq` Hz, or disarm it with 0. On `start` the
+/// first sample is delayed by `probing.pprof.phase`; the kernel timer fires
+/// on CPU time and takes no jitter.
+fn set_timer(freq: i32, start: bool) -> Result<()> {
+    let interval = match freq {
+        0 => 0,
+        freq => 1_000_000 / freq.max(1) as u64,
+    };
+    let first = match start {
+        true => sampling::first_delay(interval),
+        false => interval,
+    };
+    let timer = libc::itimerval {
+        it_interval: timeval(interval),
+        it_value: timeval(first),
+    };
+    if unsafe { libc::setitimer(libc::ITIMER_PROF, &timer, std::ptr::null_mut()) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Symbols of an instruction pointer, outermost inlined function first
+fn symbolize(ip: usize, return_address: bool) -> Vec<String> {
+    // a return address points after the call, resolve the call itself
+    let ip = if return_address {
+        ip.saturating_sub(1)
+    } else {
+        ip
+    };
+    let mut names = vec![];
+    backtrace::resolve(ip as *mut std::ffi::c_void, |symbol| {
+        let name = symbol
+            .name()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("{ip:#x}"));
+        names.push(name);
+    });
+    if names.is_empty() {
+        names.push(format!("{ip:#x}"));
+    }
+    names.reverse();
+    names
+}
+
+/// Path of the executable or shared library holding the code at `ip`, empty
+/// for code outside any of them
+fn object_file(ip: usize) -> String {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(ip as *const libc::c_void, &mut info) } == 0
+        || info.dli_fname.is_null()
+    {
+        return String::new();
+    }
+    unsafe { std::ffi::CStr::from_ptr(info.dli_fname) }
+        .to_string_lossy()
+        .to_string()
+}
+
+fn thread_name(tid: i32) -> String {
+    std::fs::read_to_string(format!("/proc/self/task/{tid}/comm"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| tid.to_string())
+}
+
+#[derive(Default)]
+struct Stacks {
+    /// Samples by thread, span stack and instruction pointers; the span ids
+    /// are cleared so calls of the same spans share their counts
+    counts: HashMap<(i32, SpanTag, Vec<usize>), u64>,
+}
+
+impl Stacks {
+    fn drain(&mut self) {
+        while let Some(sample) = RING.pop() {
+            let ips = sample.ips[..sample.depth as usize].to_vec();
+            let span = SpanTag {
+                span_id: 0,
+                ..sample.span
+            };
+            *self.counts.entry((sample.tid, span, ips)).or_default() += 1;
+        }
+    }
+}
+
+struct Aggregator {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+pub struct CpuSampler {
+    aggregator: Mutex<Option<Aggregator>>,
+    stacks: Arc<Mutex<Stacks>>,
+    frequency: AtomicU64,
+    /// Rate of the samples kept, not cleared when sampling stops
+    sampled_frequency: AtomicU64,
+}
+
+impl CpuSampler {
+    pub fn setup(&self, freq: i32) {
+        log::debug!("setup cpu sampler with sample freq: {freq}");
+        self.reset();
+        if let Ok(mut stacks) = self.stacks.lock() {
+            stacks.counts.clear();
+        }
+        while RING.pop().is_some() {}
+        RING.reset_stats();
+
+        INSTALL_HANDLER.call_once(install_handler);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        let stacks = self.stacks.clone();
+        let handle = std::thread::Builder::new()
+            .name("probing-cpu-aggregator".to_string())
+            .spawn(move || {
+                while flag.load(Ordering::Relaxed) {
+                    std::thread::park_timeout(DRAIN_INTERVAL);
+                    if let Ok(mut stacks) = stacks.lock() {
+                        stacks.drain();
+                    }
+                }
+            });
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => {
+                log::error!("failed to start cpu sampler: {e}");
+                return;
+            }
+        };
+
+        SAMPLING.store(true, Ordering::Relaxed);
+        if let Err(e) = set_timer(freq, true) {
+            log::error!("failed to start cpu sampler: {e}");
+        }
+        self.frequency.store(freq as u64, Ordering::Relaxed);
+        self.sampled_frequency.store(freq as u64, Ordering::Relaxed);
+        if let Ok(mut aggregator) = self.aggregator.lock() {
+            aggregator.replace(Aggregator { running, handle });
+        }
+    }
+
+    /// Stop sampling; the samples taken so far stay available
+    pub fn reset(&self) {
+        let aggregator = self.aggregator.lock().ok().and_then(|mut a| a.take());
+        if let Some(aggregator) = aggregator {
+            if let Err(e) = set_timer(0, false) {
+                log::warn!("failed to stop cpu sampler timer: {e}");
+            }
+            SAMPLING.store(false, Ordering::Relaxed);
+            aggregator.running.store(false, Ordering::Relaxed);
+            aggregator.handle.thread().unpark();
+            let _ = aggregator.handle.join();
+            if let Ok(mut stacks) = self.stacks.lock() {
+                stacks.drain();
+            }
+        }
+        self.frequency.store(0, Ordering::Relaxed);
+    }
+
+    /// Re-arm the `SIGPROF` timer of a running sampler at `freq` Hz.
+    ///
+    /// `setitimer` swaps the interval in a single call, so the samples so far
+    /// are kept and there is no window at a mixed rate. Returns `false` if the
+    /// sampler is not running.
+    pub fn retime(&self, freq: i32) -> Result<bool> {
+        let aggregator = self.aggregator.lock().unwrap();
+        if aggregator.is_none() {
+            return Ok(false);
+        }
+        set_timer(freq, false)?;
+        self.frequency.store(freq as u64, Ordering::Relaxed);
+        self.sampled_frequency.store(freq as u64, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Sample rate in Hz, 0 when not sampling
+    pub fn frequency(&self) -> u64 {
+        self.frequency.load(Ordering::Relaxed)
+    }
+
+    /// Samples taken, dropped and waiting in the ring since the last setup
+    pub fn stats(&self) -> RingStats {
+        RING.stats()
+    }
+
+    /// Samples so far, drained from the ring first
+    fn counts(&self) -> Result<HashMap<(i32, SpanTag, Vec<usize>), u64>> {
+        let mut stacks = self
+            .stacks
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock cpu samples: {:?}", e))?;
+        stacks.drain();
+        Ok(stacks.counts.clone())
+    }
+
+    /// Time on CPU in microseconds by innermost active span, `None` outside
+    /// any span, estimated from the samples so far and the sample rate
+    pub fn span_weights(&self) -> Result<HashMap<Option<String>, u64>> {
+        let interval = 1_000_000 / self.sampled_frequency.load(Ordering::Relaxed).max(1);
+        let mut spans = HashMap::new();
+        for ((_, span, _), count) in self.counts()? {
+            *spans.entry(span.innermost()).or_default() += count * interval;
+        }
+        Ok(spans)
+    }
+
+    /// Time on CPU in microseconds by innermost frame, as the object file
+    /// holding its code and its symbol, of the samples taken within a span
+    /// called `span` if given
+    pub fn leaf_weights(&self, span: Option<&str>) -> Result<HashMap<(String, String), u64>> {
+        let interval = 1_000_000 / self.sampled_frequency.load(Ordering::Relaxed).max(1);
+        let mut counts = self.counts()?;
+        if let Some(span) = span {
+            counts.retain(|(_, tag, _), _| tag.within(span));
+        }
+
+        let mut symbols: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut objects: HashMap<usize, String> = HashMap::new();
+        let mut leaves = HashMap::new();
+        for ((_, _, ips), count) in counts {
+            // samples start at the interrupted frame
+            let Some(&leaf) = ips.first() else {
+                continue;
+            };
+            let name = symbols
+                .entry(leaf)
+                .or_insert_with(|| symbolize(leaf, false))
+                .last()
+                .cloned()
+                .unwrap_or_default();
+            let object = objects
+                .entry(leaf)
+                .or_insert_with(|| object_file(leaf))
+                .clone();
+            *leaves.entry((object, name)).or_default() += count * interval;
+        }
+        Ok(leaves)
+    }
+
+    /// Collapsed stacks (`thread;outer;...;inner count`) of the samples so
+    /// far, only those taken within a span called `span` if given
+    pub fn folded(&self, span: Option<&str>) -> Result<Vec<String>> {
+        // symbolizing is slow, keep the aggregator draining meanwhile
+        let mut counts = self.counts()?;
+        if counts.is_empty() {
+            return Err(anyhow::anyhow!("no cpu samples"));
+        }
+        if let Some(span) = span {
+            counts.retain(|(_, tag, _), _| tag.within(span));
+            if counts.is_empty() {
+                return Err(anyhow::anyhow!("no cpu samples within span {span}"));
+            }
+        }
+
+        let mut symbols: HashMap<(usize, bool), Vec<String>> = HashMap::new();
+        let mut names: HashMap<i32, String> = HashMap::new();
+        let mut lines: HashMap<String, u64> = HashMap::new();
+        for ((tid, _, ips), count) in counts.iter() {
+            let frames = ips
+                .iter()
+                .enumerate()
+                .map(|(i, ip)| {
+                    symbols
+                        .entry((*ip, i > 0))
+                        .or_insert_with(|| symbolize(*ip, i > 0))
+                        .clone()
+                })
+                .collect::<Vec<_>>();
+
+            let mut line = names
+                .entry(*tid)
+                .or_insert_with(|| thread_name(*tid))
+                .clone();
+            for frame in frames.iter().rev() {
+                for symbol in frame {
+                    line.push(';');
+                    line.push_str(symbol);
+                }
+            }
+            *lines.entry(line).or_default() += count;
+        }
+        Ok(lines
+            .into_iter()
+            .map(|(line, count)| format!("{line} {count}"))
+            .collect())
+    }
+
+    pub fn flamegraph(&self, span: Option<&str>) -> Result<String> {
+        let lines = self.folded(span)?;
+
+        let mut opt = inferno::flamegraph::Options::default();
+        opt.deterministic = true;
+        let mut graph: Vec<u8> = vec![];
+        inferno::flamegraph::from_lines(&mut opt, lines.iter().map(|x| x.as_str()), &mut graph)?;
+        Ok(String::from_utf8(graph)?)
+    }
+}
+
+pub static CPU_SAMPLER: Lazy<CpuSampler> = Lazy::new(|| CpuSampler {
+    aggregator: Mutex::new(None),
+    stacks: Arc::new(Mutex::new(Stacks::default())),
+    frequency: AtomicU64::new(0),
+    sampled_frequency: AtomicU64::new(0),
+});
@@ -0,0 +1,498 @@
+//! GPU activity recorded through CUPTI, loaded with `dlopen` so that probing
+//! builds and runs without the CUDA toolkit.
+//!
+//! Two CUPTI APIs are used together:
+//! - the callback API sees every kernel launch and memcpy on the thread
+//!   issuing it, where the Python stack is captured and keyed by the
+//!   correlation id CUPTI gives the call;
+//! - the activity API delivers the kernels and copies once run, in buffers
+//!   filled asynchronously, each carrying the correlation id of its launch.
+//!
+//! Capturing a stack costs a few microseconds, so a thread reuses its last
+//! stack for `probing.gpu.stack_interval` launches before taking a new one.
+
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use libloading::Library;
+use once_cell::sync::{Lazy, OnceCell};
+use pyo3::prelude::*;
+
+use super::gpu_kernels::{self, DeviceLimits, KernelRow, NO_PYTHON_FRAME};
+
+type CuptiResult = u32;
+const CUPTI_SUCCESS: CuptiResult = 0;
+
+const ACTIVITY_KIND_MEMCPY: u32 = 1;
+const ACTIVITY_KIND_CONCURRENT_KERNEL: u32 = 10;
+
+const CB_DOMAIN_DRIVER_API: u32 = 1;
+const CB_DOMAIN_RUNTIME_API: u32 = 2;
+const API_ENTER: u32 = 0;
+
+/// `CUdevice_attribute` values read for the occupancy
+const ATTR_WARP_SIZE: i32 = 10;
+const ATTR_MAX_THREADS_PER_SM: i32 = 39;
+const ATTR_MAX_SHARED_MEMORY_PER_SM: i32 = 81;
+const ATTR_MAX_REGISTERS_PER_SM: i32 = 82;
+const ATTR_MAX_BLOCKS_PER_SM: i32 = 106;
+
+/// Size of the activity buffers handed to CUPTI
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// Frames kept per stack, the innermost ones
+const MAX_DEPTH: usize = 64;
+
+/// Launches whose activity has not arrived yet; beyond this the map is reset
+/// rather than grow with launches CUPTI never reports
+const MAX_PENDING: usize = 1 << 16;
+
+type BufferRequested = extern "C" fn(*mut *mut u8, *mut usize, *mut usize);
+type BufferCompleted = extern "C" fn(*mut c_void, u32, *mut u8, usize, usize);
+type Callback = extern "C" fn(*mut c_void, u32, u32, *const c_void);
+
+/// Entry points of libcupti, resolved once and kept for the life of the
+/// process
+struct Api {
+    _cupti: Library,
+    register_callbacks: unsafe extern "C" fn(BufferRequested, BufferCompleted) -> CuptiResult,
+    activity_enable: unsafe extern "C" fn(u32) -> CuptiResult,
+    activity_disable: unsafe extern "C" fn(u32) -> CuptiResult,
+    next_record: unsafe extern "C" fn(*mut u8, usize, *mut *mut c_void) -> CuptiResult,
+    flush_all: unsafe extern "C" fn(u32) -> CuptiResult,
+    subscribe: unsafe extern "C" fn(*mut *mut c_void, Callback, *mut c_void) -> CuptiResult,
+    unsubscribe: unsafe extern "C" fn(*mut c_void) -> CuptiResult,
+    enable_domain: unsafe extern "C" fn(u32, *mut c_void, u32) -> CuptiResult,
+}
+
+static API: OnceCell<Api> = OnceCell::new();
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// `CUpti_SubscriberHandle` of the running session
+static SUBSCRIBER: Mutex<usize> = Mutex::new(0);
+
+/// Python stack of each launch, by correlation id
+static LAUNCHES: Lazy<Mutex<HashMap<u32, Arc<str>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Limits of the devices by ordinal, read when recording starts
+static LIMITS: Lazy<Mutex<Vec<Option<DeviceLimits>>>> = Lazy::new(|| Mutex::new(vec![]));
+
+thread_local! {
+    /// Launches since the last capture, and the stack captured then
+    static STACK: RefCell<(u64, Option<Arc<str>>)> = const { RefCell::new((0, None)) };
+    /// Inside a runtime API call, whose driver calls are not recorded again
+    static IN_RUNTIME: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Prefix of `CUpti_CallbackData`
+#[repr(C)]
+#[allow(dead_code)]
+struct CallbackData {
+    callback_site: u32,
+    function_name: *const c_char,
+    function_params: *const c_void,
+    function_return_value: *mut c_void,
+    symbol_name: *const c_char,
+    context: *mut c_void,
+    context_uid: u32,
+    correlation_data: *mut u64,
+    correlation_id: u32,
+}
+
+/// Prefix shared by `CUpti_ActivityKernel4` to `CUpti_ActivityKernel9`
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct KernelRecord {
+    kind: u32,
+    cache_config: u8,
+    shared_memory_config: u8,
+    registers_per_thread: u16,
+    partitioned_cache_requested: u32,
+    partitioned_cache_executed: u32,
+    start: u64,
+    end: u64,
+    completed: u64,
+    device_id: u32,
+    context_id: u32,
+    stream_id: u32,
+    grid: [i32; 3],
+    block: [i32; 3],
+    static_shared_memory: i32,
+    dynamic_shared_memory: i32,
+    local_memory_per_thread: u32,
+    local_memory_total: u32,
+    correlation_id: u32,
+    grid_id: i64,
+    name: *const c_char,
+}
+
+/// Prefix shared by `CUpti_ActivityMemcpy` and its later versions
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct MemcpyRecord {
+    kind: u32,
+    copy_kind: u8,
+    src_kind: u8,
+    dst_kind: u8,
+    flags: u8,
+    bytes: u64,
+    start: u64,
+    end: u64,
+    device_id: u32,
+    context_id: u32,
+    stream_id: u32,
+    correlation_id: u32,
+}
+
+/// `CUpti_ActivityMemcpyKind` as shown in `nsys`
+fn copy_kind(kind: u8) -> &'static str {
+    match kind {
+        1 => "HtoD",
+        2 => "DtoH",
+        3 => "HtoA",
+        4 => "AtoH",
+        5 => "AtoA",
+        6 => "AtoD",
+        7 => "DtoA",
+        8 => "DtoD",
+        9 => "HtoH",
+        10 => "PtoP",
+        _ => "unknown",
+    }
+}
+
+/// Candidates for libcupti: the configured path, the loader search path, the
+/// `nvidia-cuda-cupti` wheel next to the CUDA runtime torch loaded, and the
+/// toolkit
+fn cupti_candidates() -> Vec<String> {
+    let mut candidates = vec![];
+    if let Some(path) = gpu_kernels::library() {
+        candidates.push(path);
+    }
+    candidates.extend(["libcupti.so".to_string(), "libcupti.so.12".to_string()]);
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    let runtime = maps
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .find(|path| path.contains("/nvidia/cuda_runtime/lib/"));
+    if let Some((site, _)) = runtime.and_then(|path| path.split_once("/cuda_runtime/lib/")) {
+        candidates.push(format!("{site}/cuda_cupti/lib/libcupti.so.12"));
+    }
+    candidates.push("/usr/local/cuda/extras/CUPTI/lib64/libcupti.so".to_string());
+    candidates
+}
+
+fn load() -> Result<&'static Api> {
+    API.get_or_try_init(|| {
+        let candidates = cupti_candidates();
+        let cupti = candidates
+            .iter()
+            .find_map(|path| unsafe { Library::new(path) }.ok())
+            .with_context(|| {
+                format!(
+                    "libcupti not found in {}, set probing.gpu.cupti_library",
+                    candidates.join(", ")
+                )
+            })?;
+        unsafe {
+            Ok(Api {
+                register_callbacks: *cupti.get(b"cuptiActivityRegisterCallbacks\0")?,
+                activity_enable: *cupti.get(b"cuptiActivityEnable\0")?,
+                activity_disable: *cupti.get(b"cuptiActivityDisable\0")?,
+                next_record: *cupti.get(b"cuptiActivityGetNextRecord\0")?,
+                flush_all: *cupti.get(b"cuptiActivityFlushAll\0")?,
+                subscribe: *cupti.get(b"cuptiSubscribe\0")?,
+                unsubscribe: *cupti.get(b"cuptiUnsubscribe\0")?,
+                enable_domain: *cupti.get(b"cuptiEnableDomain\0")?,
+                _cupti: cupti,
+            })
+        }
+    })
+}
+
+fn check(call: &str, result: CuptiResult) -> Result<()> {
+    if result != CUPTI_SUCCESS {
+        bail!("{call} failed with CUPTI error {result}");
+    }
+    Ok(())
+}
+
+/// Limits of every device, from the driver the process already loaded
+fn device_limits() -> Vec<Option<DeviceLimits>> {
+    let Ok(cuda) = (unsafe { Library::new("libcuda.so.1") }) else {
+        return vec![];
+    };
+    unsafe {
+        let count: libloading::Symbol<unsafe extern "C" fn(*mut i32) -> u32> =
+            match cuda.get(b"cuDeviceGetCount\0") {
+                Ok(f) => f,
+                Err(_) => return vec![],
+            };
+        let attribute: libloading::Symbol<unsafe extern "C" fn(*mut i32, i32, i32) -> u32> =
+            match cuda.get(b"cuDeviceGetAttribute\0") {
+                Ok(f) => f,
+                Err(_) => return vec![],
+            };
+        let mut devices = 0;
+        if count(&mut devices) != 0 {
+            return vec![];
+        }
+        let read = |attr: i32, device: i32| {
+            let mut value = 0;
+            (attribute(&mut value, attr, device) == 0).then_some(value)
+        };
+        (0..devices)
+            .map(|device| {
+                Some(DeviceLimits {
+                    warp_size: read(ATTR_WARP_SIZE, device)?,
+                    max_threads: read(ATTR_MAX_THREADS_PER_SM, device)?,
+                    max_blocks: read(ATTR_MAX_BLOCKS_PER_SM, device)?,
+                    registers: read(ATTR_MAX_REGISTERS_PER_SM, device)?,
+                    shared_bytes: read(ATTR_MAX_SHARED_MEMORY_PER_SM, device)?,
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+pub fn start() -> Result<()> {
+    if RUNNING.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let api = load()?;
+    if let Ok(mut limits) = LIMITS.lock() {
+        *limits = device_limits();
+    }
+    unsafe {
+        check(
+            "cuptiActivityRegisterCallbacks",
+            (api.register_callbacks)(buffer_requested, buffer_completed),
+        )?;
+        let mut subscriber: *mut c_void = std::ptr::null_mut();
+        check(
+            "cuptiSubscribe",
+            (api.subscribe)(&mut subscriber, on_api_call, std::ptr::null_mut()),
+        )?;
+        *SUBSCRIBER.lock().unwrap() = subscriber as usize;
+        for domain in [CB_DOMAIN_RUNTIME_API, CB_DOMAIN_DRIVER_API] {
+            check(
+                "cuptiEnableDomain",
+                (api.enable_domain)(1, subscriber, domain),
+            )?;
+        }
+        for kind in [ACTIVITY_KIND_CONCURRENT_KERNEL, ACTIVITY_KIND_MEMCPY] {
+            check("cuptiActivityEnable", (api.activity_enable)(kind))?;
+        }
+    }
+    RUNNING.store(true, Ordering::Relaxed);
+    log::info!("recording GPU kernels and copies with CUPTI");
+    Ok(())
+}
+
+pub fn stop() -> Result<()> {
+    let Some(api) = API.get() else {
+        return Ok(());
+    };
+    if !RUNNING.swap(false, Ordering::Relaxed) {
+        return Ok(());
+    }
+    unsafe {
+        for kind in [ACTIVITY_KIND_CONCURRENT_KERNEL, ACTIVITY_KIND_MEMCPY] {
+            check("cuptiActivityDisable", (api.activity_disable)(kind))?;
+        }
+        check("cuptiActivityFlushAll", (api.flush_all)(0))?;
+        let subscriber = std::mem::take(&mut *SUBSCRIBER.lock().unwrap());
+        if subscriber != 0 {
+            check(
+                "cuptiUnsubscribe",
+                (api.unsubscribe)(subscriber as *mut c_void),
+            )?;
+        }
+    }
+    if let Ok(mut launches) = LAUNCHES.lock() {
+        launches.clear();
+    }
+    Ok(())
+}
+
+/// Deliver the activity CUPTI still buffers to the ring
+pub fn flush() {
+    if let (Some(api), true) = (API.get(), is_running()) {
+        unsafe { (api.flush_all)(0) };
+    }
+}
+
+/// Frames of the calling thread, outermost first, if it runs Python code
+fn python_stack() -> Option<Arc<str>> {
+    if unsafe { pyo3::ffi::PyGILState_Check() } != 1 {
+        return None;
+    }
+    // SAFETY: the GIL is held by this thread, checked above
+    let py = unsafe { Python::assume_gil_acquired() };
+    let mut frame = py
+        .import("sys")
+        .ok()?
+        .call_method1("_getframe", (0,))
+        .ok()?;
+    let mut frames = vec![];
+    while !frame.is_none() && frames.len() < MAX_DEPTH {
+        let code = frame.getattr("f_code").ok()?;
+        frames.push(format!(
+            "{} ({}:{})",
+            code.getattr("co_name").ok()?,
+            code.getattr("co_filename").ok()?,
+            frame.getattr("f_lineno").ok()?,
+        ));
+        frame = frame.getattr("f_back").ok()?;
+    }
+    frames.reverse();
+    Some(Arc::from(frames.join(";").as_str()))
+}
+
+/// Stack of the calling thread, captured again every `stack_interval`
+/// launches
+fn launch_stack() -> Arc<str> {
+    STACK.with(|cached| {
+        let mut cached = cached.borrow_mut();
+        let (launches, stack) = &mut *cached;
+        *launches += 1;
+        if stack.is_none() || *launches >= gpu_kernels::stack_interval() {
+            *launches = 0;
+            *stack = python_stack();
+        }
+        stack.clone().unwrap_or_else(|| Arc::from(NO_PYTHON_FRAME))
+    })
+}
+
+extern "C" fn on_api_call(_: *mut c_void, domain: u32, _: u32, data: *const c_void) {
+    if data.is_null() {
+        return;
+    }
+    // SAFETY: CUPTI passes `CUpti_CallbackData` for the API domains
+    let data = unsafe { &*(data as *const CallbackData) };
+    let enter = data.callback_site == API_ENTER;
+    match domain {
+        CB_DOMAIN_RUNTIME_API => IN_RUNTIME.with(|inside| inside.set(enter)),
+        CB_DOMAIN_DRIVER_API if !IN_RUNTIME.with(|inside| inside.get()) => {}
+        _ => return,
+    }
+    if !enter || data.function_name.is_null() {
+        return;
+    }
+    let function = unsafe { CStr::from_ptr(data.function_name) };
+    if !gpu_kernels::is_launch(&function.to_string_lossy()) {
+        return;
+    }
+    let stack = launch_stack();
+    if let Ok(mut launches) = LAUNCHES.lock() {
+        if launches.len() >= MAX_PENDING {
+            launches.clear();
+        }
+        launches.insert(data.correlation_id, stack);
+    }
+}
+
+extern "C" fn buffer_requested(buffer: *mut *mut u8, size: *mut usize, max_records: *mut usize) {
+    let layout = Layout::from_size_align(BUFFER_SIZE, 8).unwrap();
+    unsafe {
+        *buffer = std::alloc::alloc(layout);
+        *size = if (*buffer).is_null() { 0 } else { BUFFER_SIZE };
+        *max_records = 0;
+    }
+}
+
+extern "C" fn buffer_completed(_: *mut c_void, _: u32, buffer: *mut u8, _: usize, valid: usize) {
+    if buffer.is_null() {
+        return;
+    }
+    if let Some(api) = API.get() {
+        let mut rows = vec![];
+        let mut record: *mut c_void = std::ptr::null_mut();
+        while unsafe { (api.next_record)(buffer, valid, &mut record) } == CUPTI_SUCCESS {
+            if let Some(row) = unsafe { parse(record) } {
+                rows.push(row);
+            }
+        }
+        gpu_kernels::push(rows);
+    }
+    let layout = Layout::from_size_align(BUFFER_SIZE, 8).unwrap();
+    unsafe { std::alloc::dealloc(buffer, layout) };
+}
+
+fn take_stack(correlation: u32) -> Arc<str> {
+    LAUNCHES
+        .lock()
+        .ok()
+        .and_then(|mut launches| launches.remove(&correlation))
+        .unwrap_or_else(|| Arc::from(NO_PYTHON_FRAME))
+}
+
+/// Row of an activity record, if it is a kernel or a copy
+unsafe fn parse(record: *const c_void) -> Option<KernelRow> {
+    match *(record as *const u32) {
+        ACTIVITY_KIND_CONCURRENT_KERNEL => {
+            let kernel = std::ptr::read_unaligned(record as *const KernelRecord);
+            let name = match kernel.name.is_null() {
+                true => "unknown".to_string(),
+                false => {
+                    let raw = CStr::from_ptr(kernel.name).to_string_lossy();
+                    cpp_demangle::Symbol::new(raw.as_bytes())
+                        .ok()
+                        .map(|demangled| demangled.to_string())
+                        .unwrap_or_else(|| raw.to_string())
+                }
+            };
+            let threads = kernel.block.iter().map(|d| *d as i64).product::<i64>();
+            let shared = (kernel.static_shared_memory + kernel.dynamic_shared_memory) as i64;
+            let registers = kernel.registers_per_thread as i64;
+            let occupancy = LIMITS.lock().ok().and_then(|limits| {
+                let limits = limits.get(kernel.device_id as usize)?.as_ref()?;
+                Some(gpu_kernels::occupancy(limits, threads, registers, shared))
+            });
+            Some(KernelRow {
+                kind: "kernel",
+                name,
+                device: kernel.device_id as i64,
+                stream: kernel.stream_id as i64,
+                correlation: kernel.correlation_id as i64,
+                start: kernel.start as i64,
+                duration: kernel.end.saturating_sub(kernel.start) as i64,
+                grid: Some(kernel.grid),
+                block: Some(kernel.block),
+                registers: Some(registers),
+                shared_bytes: Some(shared),
+                occupancy,
+                bytes: None,
+                stack: take_stack(kernel.correlation_id),
+            })
+        }
+        ACTIVITY_KIND_MEMCPY => {
+            let copy = std::ptr::read_unaligned(record as *const MemcpyRecord);
+            Some(KernelRow {
+                kind: "memcpy",
+                name: copy_kind(copy.copy_kind).to_string(),
+                device: copy.device_id as i64,
+                stream: copy.stream_id as i64,
+                correlation: copy.correlation_id as i64,
+                start: copy.start as i64,
+                duration: copy.end.saturating_sub(copy.start) as i64,
+                bytes: Some(copy.bytes as i64),
+                stack: take_stack(copy.correlation_id),
+                ..Default::default()
+            })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use pyo3::prelude::*;
+
+use probing_core::trace;
+use probing_core::trace::SpanStatus;
+
+/// Called by `probing.profiling.func_tracer` when a traced function starts or resumes
+#[pyfunction]
+#[pyo3(signature = (name, location=None))]
+pub fn _trace_enter(name: &str, location: Option<&str>) -> PyResult<()> {
+    trace::begin_span(name, Some("python"), location).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("failed to begin span: {e:?}"))
+    })?;
+    Ok(())
+}
+
+/// Called by `probing.profiling.func_tracer` when a traced function returns, yields or unwinds
+#[pyfunction]
+#[pyo3(signature = (error=None))]
+pub fn _trace_exit(error: Option<String>) -> PyResult<()> {
+    let ret = match error {
+        Some(error) => trace::end_span_with_status(SpanStatus::Error(Some(error))),
+        None => trace::end_span(),
+    };
+    ret.map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("failed to end span: {e:?}"))
+    })
+}
+
+/// Replace the set of functions traced via `sys.monitoring`.
+///
+/// `specs` is a comma separated list of `pkg.mod:func` entries; an empty
+/// string turns tracing off. Returns the functions that are now traced.
+pub fn set_trace_functions(specs: &str) -> Result<Vec<String>> {
+    Python::with_gil(|py| {
+        let tracer = py.import("probing.profiling.func_tracer")?;
+        let traced = tracer.call_method1("set_trace_functions", (specs,))?;
+        Ok(traced.extract::<Vec<String>>()?)
+    })
+}
@@ -0,0 +1,294 @@
+//! Kernels and memory copies run by the GPUs of this process, each with the
+//! Python stack that issued it, as recorded by [`super::cupti`] when built
+//! with the `cupti` feature.
+//!
+//! Rows land in a bounded ring read by `gpu.kernels`; the same rows folded as
+//! `python frames;[gpu] kernel` weighted by GPU time give a flamegraph going
+//! from the Python code to the kernels it launched.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+/// Stack recorded for launches from threads not running Python code, such as
+/// the autograd engine threads
+pub const NO_PYTHON_FRAME: &str = "[no Python frame]";
+
+/// Rows kept by default, the oldest being dropped first
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Launches between two captures of the Python stack of a thread by default
+pub const DEFAULT_STACK_INTERVAL: u64 = 16;
+
+/// One kernel or memory copy run by a GPU
+#[derive(Debug, Clone, Default)]
+pub struct KernelRow {
+    /// `kernel` or `memcpy`
+    pub kind: &'static str,
+    /// Demangled kernel name, or the direction of the copy such as `HtoD`
+    pub name: String,
+    pub device: i64,
+    pub stream: i64,
+    pub correlation: i64,
+    /// Start on the GPU clock, in nanoseconds
+    pub start: i64,
+    pub duration: i64,
+    pub grid: Option<[i32; 3]>,
+    pub block: Option<[i32; 3]>,
+    pub registers: Option<i64>,
+    /// Static and dynamic shared memory per block
+    pub shared_bytes: Option<i64>,
+    /// Theoretical occupancy, see [`occupancy`]
+    pub occupancy: Option<f64>,
+    /// Bytes copied
+    pub bytes: Option<i64>,
+    /// Python frames of the launching thread, outermost first and separated
+    /// by `;`
+    pub stack: Arc<str>,
+}
+
+/// Per-SM limits of a device, from `cuDeviceGetAttribute`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLimits {
+    pub warp_size: i32,
+    pub max_threads: i32,
+    pub max_blocks: i32,
+    pub registers: i32,
+    pub shared_bytes: i32,
+}
+
+/// Fraction of the warps of an SM a kernel can keep resident, from 0 to 1.
+///
+/// This is the theoretical occupancy from the block size, the registers per
+/// thread and the shared memory per block, ignoring allocation granularity,
+/// so it can be slightly above what the occupancy calculator reports.
+pub fn occupancy(limits: &DeviceLimits, threads: i64, registers: i64, shared_bytes: i64) -> f64 {
+    if threads <= 0 || limits.warp_size <= 0 || limits.max_threads <= 0 {
+        return 0.0;
+    }
+    let warp = limits.warp_size as i64;
+    let warps = (threads + warp - 1) / warp;
+    let mut blocks = (limits.max_threads as i64 / (warps * warp)).min(limits.max_blocks as i64);
+    if registers > 0 {
+        blocks = blocks.min(limits.registers as i64 / (registers * warps * warp));
+    }
+    if shared_bytes > 0 {
+        blocks = blocks.min(limits.shared_bytes as i64 / shared_bytes);
+    }
+    let resident = (blocks.max(0) * warps) as f64;
+    (resident / (limits.max_threads as i64 / warp) as f64).min(1.0)
+}
+
+/// Whether a runtime or driver API call launches work that CUPTI reports as a
+/// kernel or memcpy activity
+pub fn is_launch(function: &str) -> bool {
+    ["cudaLaunch", "cuLaunch", "cudaMemcpy", "cuMemcpy"]
+        .iter()
+        .any(|prefix| function.starts_with(prefix))
+}
+
+struct Ring {
+    rows: VecDeque<KernelRow>,
+    dropped: u64,
+}
+
+static RING: Lazy<Mutex<Ring>> = Lazy::new(|| {
+    Mutex::new(Ring {
+        rows: VecDeque::new(),
+        dropped: 0,
+    })
+});
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static STACK_INTERVAL: AtomicU64 = AtomicU64::new(DEFAULT_STACK_INTERVAL);
+
+/// Path of libcupti set with `probing.gpu.cupti_library`, tried first
+static LIBRARY: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity.max(1), Ordering::Relaxed);
+}
+
+pub fn set_stack_interval(launches: u64) {
+    STACK_INTERVAL.store(launches.max(1), Ordering::Relaxed);
+}
+
+pub fn stack_interval() -> u64 {
+    STACK_INTERVAL.load(Ordering::Relaxed)
+}
+
+pub fn set_library(path: Option<String>) {
+    if let Ok(mut library) = LIBRARY.lock() {
+        *library = path;
+    }
+}
+
+pub fn library() -> Option<String> {
+    LIBRARY.lock().ok().and_then(|library| library.clone())
+}
+
+pub fn push(rows: impl IntoIterator<Item = KernelRow>) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    let Ok(mut ring) = RING.lock() else {
+        return;
+    };
+    for row in rows {
+        if ring.rows.len() >= capacity {
+            ring.rows.pop_front();
+            ring.dropped += 1;
+        }
+        ring.rows.push_back(row);
+    }
+}
+
+/// Rows recorded so far, after delivering those CUPTI still buffers
+pub fn rows() -> Vec<KernelRow> {
+    #[cfg(feature = "cupti")]
+    super::cupti::flush();
+    RING.lock()
+        .map(|ring| ring.rows.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Rows dropped from the ring since the process started
+pub fn dropped() -> u64 {
+    RING.lock().map(|ring| ring.dropped).unwrap_or_default()
+}
+
+pub fn clear() {
+    if let Ok(mut ring) = RING.lock() {
+        ring.rows.clear();
+    }
+}
+
+/// Start or stop recording the GPU activity
+pub fn enable(on: bool) -> Result<()> {
+    match on {
+        #[cfg(feature = "cupti")]
+        true => super::cupti::start(),
+        #[cfg(feature = "cupti")]
+        false => super::cupti::stop(),
+        #[cfg(not(feature = "cupti"))]
+        true => anyhow::bail!("probing was built without the `cupti` feature"),
+        #[cfg(not(feature = "cupti"))]
+        false => Ok(()),
+    }
+}
+
+pub fn is_enabled() -> bool {
+    #[cfg(feature = "cupti")]
+    let running = super::cupti::is_running();
+    #[cfg(not(feature = "cupti"))]
+    let running = false;
+    running
+}
+
+/// Folded `python frames;[gpu] name` stacks weighted by GPU time in
+/// microseconds
+pub fn fold(rows: &[KernelRow]) -> Vec<String> {
+    let mut stacks: HashMap<String, i64> = HashMap::new();
+    for row in rows {
+        let frame = match row.kind {
+            "memcpy" => format!("[gpu] memcpy {}", row.name),
+            _ => format!("[gpu] {}", row.name.replace(';', ":")),
+        };
+        let key = format!("{};{frame}", row.stack);
+        *stacks.entry(key).or_default() += row.duration / 1000;
+    }
+    let mut lines = stacks
+        .into_iter()
+        .filter(|(_, us)| *us > 0)
+        .map(|(stack, us)| format!("{stack} {us}"))
+        .collect::<Vec<_>>();
+    lines.sort();
+    lines
+}
+
+pub fn folded() -> Result<Vec<String>> {
+    let rows = rows();
+    if rows.is_empty() && !is_enabled() {
+        anyhow::bail!("no GPU activity recorded, set probing.gpu.cupti=true first");
+    }
+    Ok(fold(&rows))
+}
+
+/// CPU to GPU flamegraph as SVG
+pub fn flamegraph() -> Result<String> {
+    let lines = folded()?;
+    let mut opt = inferno::flamegraph::Options::default();
+    opt.deterministic = true;
+    opt.count_name = "us".to_string();
+    opt.title = "CPU to GPU".to_string();
+    let mut graph: Vec<u8> = vec![];
+    inferno::flamegraph::from_lines(&mut opt, lines.iter().map(|x| x.as_str()), &mut graph)?;
+    Ok(String::from_utf8(graph)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Limits of an A100
+    const A100: DeviceLimits = DeviceLimits {
+        warp_size: 32,
+        max_threads: 2048,
+        max_blocks: 32,
+        registers: 65536,
+        shared_bytes: 167936,
+    };
+
+    #[test]
+    fn test_occupancy() {
+        assert_eq!(occupancy(&A100, 256, 32, 0), 1.0);
+        // 128 registers per thread leave room for 2 blocks of 256 threads
+        assert_eq!(occupancy(&A100, 256, 128, 0), 0.25);
+        // 64KB of shared memory per block, 2 blocks fit
+        assert_eq!(occupancy(&A100, 128, 32, 65536), 0.125);
+        // small blocks are limited by the blocks per SM
+        assert_eq!(occupancy(&A100, 32, 16, 0), 0.5);
+        assert_eq!(occupancy(&A100, 0, 16, 0), 0.0);
+    }
+
+    #[test]
+    fn test_is_launch() {
+        assert!(is_launch("cudaLaunchKernel"));
+        assert!(is_launch("cuLaunchKernelEx"));
+        assert!(is_launch("cudaMemcpyAsync"));
+        assert!(!is_launch("cudaMalloc"));
+        assert!(!is_launch("cudaStreamSynchronize"));
+    }
+
+    #[test]
+    fn test_fold() {
+        let stack: Arc<str> = Arc::from("main (a.py:1);step (a.py:5)");
+        let kernel = |name: &str, duration| KernelRow {
+            kind: "kernel",
+            name: name.to_string(),
+            duration,
+            stack: stack.clone(),
+            ..Default::default()
+        };
+        let rows = vec![
+            kernel("gemm", 3000),
+            kernel("gemm", 2000),
+            kernel("relu", 100),
+            KernelRow {
+                kind: "memcpy",
+                name: "HtoD".to_string(),
+                duration: 4000,
+                stack: Arc::from(NO_PYTHON_FRAME),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            fold(&rows),
+            vec![
+                "[no Python frame];[gpu] memcpy HtoD 4",
+                "main (a.py:1);step (a.py:5);[gpu] gemm 5",
+            ]
+        );
+    }
+}
@@ -0,0 +1,12 @@
+use anyhow::Result;
+use pyo3::prelude::*;
+
+/// Snapshot of the Python heap taken by `probing.profiling.heap`, as JSON
+pub fn snapshot() -> Result<String> {
+    Python::with_gil(|py| {
+        py.import("probing.profiling.heap")?
+            .call_method0("snapshot_json")?
+            .extract::<String>()
+    })
+    .map_err(|e: PyErr| anyhow::anyhow!("failed to take a heap snapshot: {e}"))
+}
@@ -0,0 +1,182 @@
+//! Execution heatmaps of a Python module built from the wall samples.
+//!
+//! Every line seen by the wall sampler (`probing.pprof.mode=wall`) is counted,
+//! so the lines of a module that never show up were not running while the
+//! profiler was, and the hot ones stand out when the source is annotated with
+//! their share of the samples. The module is picked with
+//! `probing.pprof.heatmap_module`, or per request by `/apis/heatmap?module=`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use super::wall_profiler::{LineHits, WALL_PROFILER};
+
+/// Module shown by `probe.heatmap`, set by `probing.pprof.heatmap_module`
+static MODULE: Mutex<String> = Mutex::new(String::new());
+
+pub fn set_module(module: &str) {
+    if let Ok(mut current) = MODULE.lock() {
+        *current = module.trim().to_string();
+    }
+}
+
+/// Module of `probe.heatmap`, empty when none was chosen
+pub fn module() -> String {
+    MODULE.lock().map(|m| m.clone()).unwrap_or_default()
+}
+
+/// Whether `file` belongs to `module`, given either as a dotted module name
+/// (`torch.nn`, matching `torch/nn.py` and everything under `torch/nn/`) or
+/// as part of a path (`train.py`, `models/`)
+pub fn matches_module(file: &str, module: &str) -> bool {
+    let module = module.trim();
+    if module.is_empty() {
+        return false;
+    }
+    if module.contains('/') || module.ends_with(".py") {
+        return file.contains(module);
+    }
+    let path = module.replace('.', "/");
+    let file = file.strip_prefix('/').unwrap_or(file);
+    let source = format!("{path}.py");
+    let package = format!("{path}/");
+    file == source
+        || file.ends_with(&format!("/{source}"))
+        || file.starts_with(&package)
+        || file.contains(&format!("/{package}"))
+}
+
+/// Lines of `module` hit by the wall sampler, hottest first
+pub fn heatmap(module: &str) -> Vec<LineHits> {
+    let mut lines = WALL_PROFILER.line_hits(|file| matches_module(file, module));
+    lines.sort_by(|a, b| {
+        b.samples
+            .cmp(&a.samples)
+            .then_with(|| a.file.cmp(&b.file))
+            .then_with(|| a.lineno.cmp(&b.lineno))
+    });
+    lines
+}
+
+/// Samples of the hottest line of each file, which the heat of the other
+/// lines is relative to
+pub fn hottest(hits: &[LineHits]) -> BTreeMap<&str, u64> {
+    let mut hottest: BTreeMap<&str, u64> = BTreeMap::new();
+    for hit in hits {
+        let max = hottest.entry(hit.file.as_str()).or_default();
+        *max = (*max).max(hit.samples);
+    }
+    hottest
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Source of every sampled file of `module`, each line shaded by its samples
+/// relative to the hottest line of the file
+pub fn render_html(module: &str) -> Result<String> {
+    let hits = heatmap(module);
+    if hits.is_empty() {
+        anyhow::bail!("no wall samples in `{module}`, sample with probing.pprof.mode=wall");
+    }
+    let hottest = hottest(&hits);
+    let mut by_file: BTreeMap<&str, BTreeMap<i64, &LineHits>> = BTreeMap::new();
+    for hit in hits.iter() {
+        by_file
+            .entry(hit.file.as_str())
+            .or_default()
+            .insert(hit.lineno, hit);
+    }
+
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title><style>\
+         body{{font-family:sans-serif}} table{{border-collapse:collapse;font-family:monospace;font-size:12px}} \
+         td{{padding:0 6px;white-space:pre}} td.n{{color:#888;text-align:right}}</style></head><body>",
+        escape(module)
+    )?;
+    writeln!(html, "<h2>Execution heatmap of {}</h2>", escape(module))?;
+    for (file, lines) in by_file {
+        let max = hottest.get(file).copied().unwrap_or(1).max(1);
+        let total: u64 = lines.values().map(|hit| hit.self_samples).sum();
+        writeln!(
+            html,
+            "<h3>{}</h3><p>{} samples ending in the file, {} on its hottest line</p><table>",
+            escape(file),
+            total,
+            max
+        )?;
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                writeln!(
+                    html,
+                    "<tr><td>source unavailable: {}</td></tr></table>",
+                    escape(&err.to_string())
+                )?;
+                continue;
+            }
+        };
+        for (idx, text) in source.lines().enumerate() {
+            let lineno = idx as i64 + 1;
+            let (samples, self_samples) = lines
+                .get(&lineno)
+                .map(|hit| (hit.samples, hit.self_samples))
+                .unwrap_or_default();
+            let heat = samples as f64 / max as f64;
+            writeln!(
+                html,
+                "<tr style=\"background:rgba(255,80,0,{heat:.3})\" title=\"{samples} samples, {self_samples} self\">\
+                 <td class=\"n\">{lineno}</td><td class=\"n\">{}</td><td>{}</td></tr>",
+                if samples > 0 { samples.to_string() } else { String::new() },
+                escape(text)
+            )?;
+        }
+        writeln!(html, "</table>")?;
+    }
+    writeln!(html, "</body></html>")?;
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_module() {
+        let file = "/opt/venv/lib/python3.12/site-packages/torch/nn/modules/linear.py";
+        assert!(matches_module(file, "torch.nn"));
+        assert!(matches_module(file, "torch.nn.modules.linear"));
+        assert!(matches_module(file, "nn/modules"));
+        assert!(!matches_module(file, "torch.optim"));
+        assert!(!matches_module(file, "torch.nn.modules.lin"));
+        assert!(!matches_module(file, ""));
+
+        assert!(matches_module("/work/train.py", "train"));
+        assert!(matches_module("train.py", "train"));
+        assert!(!matches_module("/work/pretrain.py", "train"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("if a < b & c > \"d\":"),
+            "if a &lt; b &amp; c &gt; &quot;d&quot;:"
+        );
+    }
+}
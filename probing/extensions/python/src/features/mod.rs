@@ -1,6 +1,23 @@
+pub mod backtrace;
+pub mod breakdown;
+pub mod counters;
+pub mod cpu_sampler;
+#[cfg(feature = "cupti")]
+pub mod cupti;
+pub mod func_tracer;
+pub mod gpu_kernels;
+pub mod heap;
+pub mod heatmap;
+pub mod oneshot;
+pub mod packages;
 pub mod pprof;
 pub mod python_api;
+pub mod sample_ring;
+pub mod sampling;
 pub mod spy;
 pub mod stack_tracer;
+pub mod step_profile;
 pub mod torch;
+pub mod udf;
 pub mod vm_tracer;
+pub mod wall_profiler;
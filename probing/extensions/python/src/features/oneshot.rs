@@ -0,0 +1,204 @@
+//! One-shot probes, for hosts where no agent may stay resident.
+//!
+//! `probing inject --oneshot <action>` loads the library with
+//! `PROBING_ONESHOT=<action>` and `PROBING_ONESHOT_OUTPUT=<path>` set, the
+//! path lying in a directory only the user of the target can enter. The
+//! library then starts no server and registers nothing with the interpreter:
+//! a single `probing-oneshot` thread runs the action once the injector has
+//! detached, writes the result to the output path and exits, after which the
+//! injector unloads the library again.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use probing_proto::prelude::{CallFrame, StackKind, ThreadStack};
+
+use crate::extensions::python::get_all_python_stacks;
+use crate::features::wall_profiler::WALL_PROFILER;
+
+pub const ENV_ONESHOT: &str = "PROBING_ONESHOT";
+pub const ENV_ONESHOT_OUTPUT: &str = "PROBING_ONESHOT_OUTPUT";
+/// Seconds sampled by the `flamegraph` action
+pub const ENV_ONESHOT_DURATION: &str = "PROBING_ONESHOT_DURATION";
+
+const DEFAULT_DURATION: u64 = 5;
+const SAMPLE_FREQ: i32 = 99;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Single action of a one-shot probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Python stacks of every thread
+    Backtrace,
+    /// Wall-clock flamegraph of the Python threads over a few seconds, as SVG
+    Flamegraph { duration: Duration },
+}
+
+impl Action {
+    fn parse(action: &str, duration: Option<&str>) -> Result<Self> {
+        match action {
+            "backtrace" => Ok(Action::Backtrace),
+            "flamegraph" => {
+                let seconds = match duration {
+                    Some(seconds) => seconds
+                        .parse::<u64>()
+                        .map_err(|_| anyhow::anyhow!("invalid duration `{seconds}`"))?,
+                    None => DEFAULT_DURATION,
+                };
+                Ok(Action::Flamegraph {
+                    duration: Duration::from_secs(seconds),
+                })
+            }
+            _ => anyhow::bail!("unknown one-shot action `{action}`"),
+        }
+    }
+
+    fn run(self) -> Result<String> {
+        match self {
+            Action::Backtrace => backtrace(),
+            Action::Flamegraph { duration } => {
+                WALL_PROFILER.setup(SAMPLE_FREQ);
+                std::thread::sleep(duration);
+                // joins the sampler, no thread of the library outlives the action
+                WALL_PROFILER.reset();
+                WALL_PROFILER.flamegraph(None)
+            }
+        }
+    }
+}
+
+/// Whether the library was loaded as a one-shot probe
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Start the action requested by the injector, if any. Returns `false` when
+/// the library was loaded as a regular probe.
+///
+/// The variables are removed from the environment so that a regular probe
+/// injected later does not take them for its own.
+pub fn start_if_requested() -> bool {
+    let Ok(action) = std::env::var(ENV_ONESHOT) else {
+        return false;
+    };
+    ACTIVE.store(true, Ordering::Relaxed);
+    let output = std::env::var(ENV_ONESHOT_OUTPUT).ok().map(PathBuf::from);
+    let duration = std::env::var(ENV_ONESHOT_DURATION).ok();
+    for name in [ENV_ONESHOT, ENV_ONESHOT_OUTPUT, ENV_ONESHOT_DURATION] {
+        std::env::remove_var(name);
+    }
+    let Some(output) = output else {
+        log::error!("{ENV_ONESHOT} is set without {ENV_ONESHOT_OUTPUT}, nothing to do");
+        return true;
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name("probing-oneshot".to_string())
+        .spawn(move || {
+            let result = Action::parse(&action, duration.as_deref()).and_then(Action::run);
+            if let Err(err) = write_result(&output, result) {
+                log::error!("Failed to write the one-shot result to {output:?}: {err}");
+            }
+        });
+    if let Err(err) = spawned {
+        log::error!("Failed to start the one-shot probe: {err}");
+    }
+    true
+}
+
+/// Write the result next to `output` and rename it into place, so the
+/// injector never reads a partial file. Errors go to `<output>.error`.
+///
+/// The injector puts `output` in a private directory; the file is still
+/// created anew and never through a symlink, readable by its owner only.
+fn write_result(output: &Path, result: Result<String>) -> std::io::Result<()> {
+    let (path, content) = match result {
+        Ok(content) => (output.to_path_buf(), content),
+        Err(err) => (output.with_extension("error"), format!("{err:#}")),
+    };
+    let partial = output.with_extension("partial");
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(nix::libc::O_NOFOLLOW)
+        .open(&partial)?
+        .write_all(content.as_bytes())?;
+    std::fs::rename(&partial, path)
+}
+
+fn backtrace() -> Result<String> {
+    let mut threads = BTreeMap::<i64, ThreadStack>::new();
+    for frame in get_all_python_stacks()? {
+        let stack = threads
+            .entry(frame.thread_id)
+            .or_insert_with(|| ThreadStack {
+                tid: frame.native_id.unwrap_or(frame.thread_id) as i32,
+                name: frame.thread_name.clone().unwrap_or_default(),
+                kind: StackKind::Python,
+                frames: vec![],
+            });
+        stack.frames.push(CallFrame::PyFrame {
+            file: frame.file,
+            func: frame.func,
+            lineno: frame.lineno,
+            locals: Default::default(),
+        });
+    }
+    Ok(threads.values().map(ToString::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action() {
+        assert_eq!(Action::parse("backtrace", None).unwrap(), Action::Backtrace);
+        assert_eq!(
+            Action::parse("flamegraph", Some("2")).unwrap(),
+            Action::Flamegraph {
+                duration: Duration::from_secs(2)
+            }
+        );
+        assert!(Action::parse("flamegraph", Some("soon")).is_err());
+        assert!(Action::parse("heapdump", None).is_err());
+    }
+
+    #[test]
+    fn test_write_result_refuses_symlinks() {
+        let dir = std::env::temp_dir().join(format!("probing-oneshot-link-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("result.out");
+        let victim = dir.join("victim");
+        std::fs::write(&victim, "intact").unwrap();
+        std::os::unix::fs::symlink(&victim, output.with_extension("partial")).unwrap();
+
+        assert!(write_result(&output, Ok("stacks".to_string())).is_err());
+        assert_eq!(std::fs::read_to_string(&victim).unwrap(), "intact");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_result() {
+        let dir = std::env::temp_dir().join(format!("probing-oneshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("result.out");
+
+        write_result(&output, Ok("stacks".to_string())).unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "stacks");
+        write_result(&output, Err(anyhow::anyhow!("no GIL"))).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(output.with_extension("error")).unwrap(),
+            "no GIL"
+        );
+        assert!(!output.with_extension("partial").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
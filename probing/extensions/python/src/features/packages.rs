@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use pyo3::prelude::*;
+
+/// An installed Python distribution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    /// Directory the distribution is installed into, e.g. `site-packages`
+    pub location: Option<String>,
+}
+
+/// Installed Python distributions, read through `importlib.metadata` and
+/// sorted by name.
+pub fn package_list() -> Result<Vec<PackageInfo>> {
+    Python::with_gil(|py| {
+        let metadata = py.import("importlib.metadata")?;
+        let mut packages = vec![];
+        for dist in metadata.call_method0("distributions")?.try_iter()? {
+            let dist = dist?;
+            let name = dist.getattr("metadata")?.get_item("Name")?;
+            if name.is_none() {
+                continue;
+            }
+            let location = dist
+                .call_method1("locate_file", ("",))
+                .and_then(|path| path.str())
+                .map(|path| path.to_string())
+                .ok();
+            packages.push(PackageInfo {
+                name: name.extract::<String>()?,
+                version: dist.getattr("version")?.extract::<String>()?,
+                location,
+            });
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(packages)
+    })
+    .map_err(|e: PyErr| anyhow::anyhow!("failed to list python packages: {e}"))
+}
+
+/// Installed Python distributions as `name -> version`
+pub fn installed_packages() -> Result<BTreeMap<String, String>> {
+    Ok(package_list()?
+        .into_iter()
+        .map(|p| (p.name, p.version))
+        .collect())
+}
@@ -1,68 +1,197 @@
 use anyhow::Result;
 
-use once_cell::sync::Lazy;
-use pprof::ProfilerGuard;
-use pprof::ProfilerGuardBuilder;
-use std::sync::Mutex;
-
-pub struct PprofHolder(Mutex<Option<ProfilerGuard<'static>>>);
-
-impl PprofHolder {
-    pub fn reset(&self) {
-        let _ = self.0.lock().map(|mut holder| {
-            *holder = None;
-        });
-    }
+use std::sync::{Mutex, Once};
+use std::time::Duration;
+
+use super::cpu_sampler::CPU_SAMPLER;
+use super::wall_profiler::WALL_PROFILER;
+
+/// What the profiler samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProfileMode {
+    /// On-CPU native samples (SIGPROF driven), see [`super::cpu_sampler`].
+    #[default]
+    Cpu,
+    /// All Python threads weighted by wall time, tagged with their state.
+    Wall,
+}
+
+impl std::str::FromStr for ProfileMode {
+    type Err = anyhow::Error;
 
-    pub fn setup(&self, freq: i32) {
-        log::debug!("setup pprof with sample freq: {freq}");
-        let _ = self.0.lock().map(|mut holder| {
-            match ProfilerGuardBuilder::default().frequency(freq).build() {
-                Ok(ph) => holder.replace(ph),
-                Err(_) => todo!(),
-            };
-        });
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "cpu" => Ok(ProfileMode::Cpu),
+            "wall" => Ok(ProfileMode::Wall),
+            _ => Err(anyhow::anyhow!(
+                "unknown profile mode: {s}, expect cpu or wall"
+            )),
+        }
     }
+}
 
-    // pub fn report(&self) -> Option<String> {
-    //     self.0.lock().ok().and_then(|pp| match pp.as_ref() {
-    //         Some(pp) => {
-    //             if let Ok(report) = pp.report().build() {
-    //                 Some(format!("report: {:?}", &report))
-    //             } else {
-    //                 None
-    //             }
-    //         }
-    //         None => None,
-    //     })
-    // }
-
-    pub fn flamegraph(&self) -> Result<String> {
-        let holder = self.0.lock().unwrap();
-
-        if let Some(pp) = holder.as_ref() {
-            let report = pp.report().build()?;
-            let mut graph: Vec<u8> = vec![];
-            report.flamegraph(&mut graph).unwrap();
-            let graph = String::from_utf8(graph)?;
-            Ok(graph)
-        } else {
-            Err(anyhow::anyhow!("no pprof"))
+impl std::fmt::Display for ProfileMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileMode::Cpu => write!(f, "cpu"),
+            ProfileMode::Wall => write!(f, "wall"),
         }
     }
 }
 
-pub static PPROF_HOLDER: Lazy<PprofHolder> = Lazy::new(|| PprofHolder(Mutex::new(None)));
+/// Mode of the most recent `setup_with_mode`, so the flamegraph of a paused
+/// profiler is still taken from the right sampler.
+static ACTIVE_MODE: Mutex<ProfileMode> = Mutex::new(ProfileMode::Cpu);
 
-pub fn pprof_handler() {
-    PPROF_HOLDER.setup(100);
-}
+/// Start of the current sampling session in microseconds since epoch
+static STARTED_AT: Mutex<Option<i64>> = Mutex::new(None);
+
+/// The samplers are stopped at shutdown by one hook, whatever the restarts
+static SHUTDOWN_HOOK: Once = Once::new();
 
 pub fn setup(freq: u64) -> Result<()> {
-    PPROF_HOLDER.setup(freq as i32);
+    setup_with_mode(freq, ProfileMode::Cpu)
+}
+
+pub fn setup_with_mode(freq: u64, mode: ProfileMode) -> Result<()> {
+    reset();
+    // each run draws the same jitter when `probing.pprof.seed` is set
+    super::sampling::restart();
+    if let Ok(mut active) = ACTIVE_MODE.lock() {
+        *active = mode;
+    }
+    match mode {
+        ProfileMode::Cpu => CPU_SAMPLER.setup(freq as i32),
+        ProfileMode::Wall => WALL_PROFILER.setup(freq as i32),
+    }
+    if let Ok(mut started) = STARTED_AT.lock() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        *started = Some(now.as_micros() as i64);
+    }
+    SHUTDOWN_HOOK.call_once(|| probing_core::core::shutdown::register_hook("pprof", reset));
     Ok(())
 }
 
-pub fn flamegraph() -> Result<String> {
-    PPROF_HOLDER.flamegraph()
+/// Change the sample rate of the running profiler in place, without
+/// restarting it. Returns `false` if nothing is sampling.
+pub fn set_frequency(freq: u64) -> Result<bool> {
+    match ACTIVE_MODE.lock().map(|m| *m).unwrap_or_default() {
+        ProfileMode::Cpu => CPU_SAMPLER.retime(freq as i32),
+        ProfileMode::Wall => Ok(WALL_PROFILER.set_frequency(freq as i32)),
+    }
+}
+
+pub fn reset() {
+    CPU_SAMPLER.reset();
+    WALL_PROFILER.reset();
+    if let Ok(mut started) = STARTED_AT.lock() {
+        *started = None;
+    }
+}
+
+/// Sample rate of the running profiler in Hz, 0 when nothing is sampling
+pub fn frequency() -> u64 {
+    CPU_SAMPLER.frequency().max(WALL_PROFILER.frequency())
+}
+
+/// Mode of the profiler the flamegraphs are taken from
+pub fn active_mode() -> ProfileMode {
+    ACTIVE_MODE.lock().map(|m| *m).unwrap_or_default()
+}
+
+/// When the running profiler started sampling, in microseconds since epoch;
+/// its flamegraphs cover the time since
+pub fn started_at() -> Option<i64> {
+    STARTED_AT.lock().ok().and_then(|started| *started)
+}
+
+/// Sampling counters of one profiler, reported by `probe.overhead`
+#[derive(Debug, Clone)]
+pub struct SamplerStats {
+    pub sampler: &'static str,
+    pub running: bool,
+    /// Sample rate in Hz, 0 when stopped
+    pub frequency: u64,
+    pub captured: u64,
+    /// Samples lost without being aggregated, so the profile has fewer
+    /// samples than its rate and duration promise
+    pub dropped: u64,
+    /// Samples buffered for the aggregator
+    pub pending: u64,
+    pub capacity: u64,
+}
+
+/// Counters of the cpu and wall samplers since they were last started
+pub fn sampler_stats() -> Vec<SamplerStats> {
+    let cpu = CPU_SAMPLER.stats();
+    let (captured, missed) = WALL_PROFILER.stats();
+    vec![
+        SamplerStats {
+            sampler: "cpu",
+            running: CPU_SAMPLER.frequency() > 0,
+            frequency: CPU_SAMPLER.frequency(),
+            captured: cpu.pushed,
+            dropped: cpu.dropped,
+            pending: cpu.pending,
+            capacity: cpu.capacity,
+        },
+        SamplerStats {
+            sampler: "wall",
+            running: WALL_PROFILER.frequency() > 0,
+            frequency: WALL_PROFILER.frequency(),
+            captured,
+            dropped: missed,
+            pending: 0,
+            capacity: 0,
+        },
+    ]
+}
+
+/// Flamegraph of the active profiler, of the samples taken within a trace
+/// span called `span` if given
+pub fn flamegraph(span: Option<&str>) -> Result<String> {
+    match ACTIVE_MODE.lock().map(|m| *m).unwrap_or_default() {
+        ProfileMode::Cpu => CPU_SAMPLER.flamegraph(span),
+        ProfileMode::Wall => WALL_PROFILER.flamegraph(span),
+    }
+}
+
+/// Collapsed stacks of the active profiler, see [`flamegraph`]
+pub fn folded(span: Option<&str>) -> Result<Vec<String>> {
+    match ACTIVE_MODE.lock().map(|m| *m).unwrap_or_default() {
+        ProfileMode::Cpu => CPU_SAMPLER.folded(span),
+        ProfileMode::Wall => WALL_PROFILER.folded(span),
+    }
+}
+
+/// Self time of a trace span estimated from the samples of the active
+/// profiler, reported by `probe.spans`
+#[derive(Debug, Clone)]
+pub struct SpanTime {
+    /// Innermost active span of the samples, `None` for those outside spans
+    pub span: Option<String>,
+    /// On CPU in cpu mode, wall time in wall mode
+    pub self_time: Duration,
+    /// Fraction of all the samples
+    pub share: f64,
+}
+
+pub fn span_times() -> Result<Vec<SpanTime>> {
+    let weights = match ACTIVE_MODE.lock().map(|m| *m).unwrap_or_default() {
+        ProfileMode::Cpu => CPU_SAMPLER.span_weights()?,
+        ProfileMode::Wall => WALL_PROFILER.span_weights(),
+    };
+    let total = weights.values().sum::<u64>().max(1) as f64;
+    let mut times = weights
+        .into_iter()
+        .map(|(span, weight)| SpanTime {
+            span,
+            self_time: Duration::from_micros(weight),
+            share: weight as f64 / total,
+        })
+        .collect::<Vec<_>>();
+    times.sort_by(|a, b| b.self_time.cmp(&a.self_time));
+    Ok(times)
 }
@@ -2,10 +2,14 @@ use pyo3::prelude::*;
 use pyo3::types::PyModule;
 
 use crate::extensions;
+use crate::features::func_tracer::{_trace_enter, _trace_exit};
+use crate::features::sampling::_sampling_seed;
+use crate::features::step_profile::{_profile_step_begin, _profile_step_end};
+use crate::features::udf::_register_udf;
 use crate::features::vm_tracer::{
     _get_python_frames, _get_python_stacks, disable_tracer, enable_tracer, initialize_globals,
 };
-use crate::pkg::TCPStore;
+use crate::pkg::{Histogram, TCPStore};
 use probing_core::ENGINE;
 
 #[pyfunction]
@@ -21,6 +25,25 @@ fn query_json(_py: Python, sql: String) -> PyResult<String> {
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
 }
 
+/// Publish hot metrics to the shared-memory status of the probe, read by
+/// `probing <pid> status --fast`
+#[pyfunction]
+#[pyo3(signature = (step=None, loss=None, gpu_util=None))]
+fn publish_metrics(step: Option<u64>, loss: Option<f64>, gpu_util: Option<f64>) {
+    let Some(metrics) = probing_core::core::hot_metrics::local() else {
+        return;
+    };
+    if let Some(step) = step {
+        metrics.set_step(step);
+    }
+    if let Some(loss) = loss {
+        metrics.set_loss(loss);
+    }
+    if let Some(gpu_util) = gpu_util {
+        metrics.set_gpu_util(gpu_util);
+    }
+}
+
 pub fn create_probing_module() -> PyResult<()> {
     if initialize_globals() {
         #[cfg(feature = "tracing")]
@@ -42,11 +65,19 @@ pub fn create_probing_module() -> PyResult<()> {
         m.setattr(pyo3::intern!(py, "_C"), 42)?;
         m.add_class::<extensions::python::ExternalTable>()?;
         m.add_class::<TCPStore>()?;
+        m.add_class::<Histogram>()?;
         m.add_function(wrap_pyfunction!(query_json, py)?)?;
+        m.add_function(wrap_pyfunction!(publish_metrics, py)?)?;
         m.add_function(wrap_pyfunction!(enable_tracer, py)?)?;
         m.add_function(wrap_pyfunction!(disable_tracer, py)?)?;
         m.add_function(wrap_pyfunction!(_get_python_stacks, py)?)?;
         m.add_function(wrap_pyfunction!(_get_python_frames, py)?)?;
+        m.add_function(wrap_pyfunction!(_trace_enter, py)?)?;
+        m.add_function(wrap_pyfunction!(_trace_exit, py)?)?;
+        m.add_function(wrap_pyfunction!(_register_udf, py)?)?;
+        m.add_function(wrap_pyfunction!(_profile_step_begin, py)?)?;
+        m.add_function(wrap_pyfunction!(_profile_step_end, py)?)?;
+        m.add_function(wrap_pyfunction!(_sampling_seed, py)?)?;
         Ok(())
     })
 }
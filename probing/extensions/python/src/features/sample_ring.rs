@@ -0,0 +1,183 @@
+//! Bounded lock-free queue carrying samples out of signal handlers.
+//!
+//! Producers run inside signal handlers, so a push never blocks, allocates or
+//! retries for long: when the ring is full the sample is dropped and counted,
+//! and the profile is known to be under-sampled instead of silently biased.
+//! The slots follow Dmitry Vyukov's bounded MPMC queue, each carrying a
+//! sequence number that tells whether it is free or holds a value.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct SampleRing<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    pushed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+// Values are moved in and out of slots exclusively, guarded by `seq`.
+unsafe impl<T: Send> Send for SampleRing<T> {}
+unsafe impl<T: Send> Sync for SampleRing<T> {}
+
+/// Counters of a [`SampleRing`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RingStats {
+    /// Samples accepted since creation
+    pub pushed: u64,
+    /// Samples lost because the ring was full
+    pub dropped: u64,
+    /// Samples waiting for the consumer
+    pub pending: u64,
+    pub capacity: u64,
+}
+
+impl<T: Copy> SampleRing<T> {
+    /// Ring of at least `capacity` slots, rounded up to a power of two
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Self {
+            slots,
+            mask: capacity - 1,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            pushed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Enqueue `value`, or count it as dropped if the ring is full.
+    ///
+    /// Async-signal-safe: only atomics and a copy into a preallocated slot.
+    pub fn push(&self, value: T) -> bool {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq as isize - pos as isize {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos + 1, Ordering::Release);
+                        self.pushed.fetch_add(1, Ordering::Relaxed);
+                        return true;
+                    }
+                    Err(current) => pos = current,
+                },
+                diff if diff < 0 => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Dequeue the oldest sample, if any
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq as isize - (pos + 1) as isize {
+                0 => match self.tail.compare_exchange_weak(
+                    pos,
+                    pos + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.seq.store(pos + self.mask + 1, Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                },
+                diff if diff < 0 => return None,
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    pub fn stats(&self) -> RingStats {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        RingStats {
+            pushed: self.pushed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            pending: head.saturating_sub(tail) as u64,
+            capacity: self.slots.len() as u64,
+        }
+    }
+
+    /// Zero the counters, e.g. when a new profile starts
+    pub fn reset_stats(&self) {
+        self.pushed.store(0, Ordering::Relaxed);
+        self.dropped.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop_and_drops() {
+        let ring = SampleRing::<u32>::new(3);
+        assert_eq!(ring.stats().capacity, 4);
+        for i in 0..6 {
+            ring.push(i);
+        }
+        assert_eq!(
+            ring.stats(),
+            RingStats {
+                pushed: 4,
+                dropped: 2,
+                pending: 4,
+                capacity: 4
+            }
+        );
+        assert_eq!(
+            std::iter::from_fn(|| ring.pop()).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert!(ring.push(7));
+        assert_eq!(ring.pop(), Some(7));
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn test_concurrent_producers() {
+        let ring = std::sync::Arc::new(SampleRing::<usize>::new(1 << 16));
+        let producers = (0..4)
+            .map(|t| {
+                let ring = ring.clone();
+                std::thread::spawn(move || (0..1000).for_each(|i| assert!(ring.push(t * 1000 + i))))
+            })
+            .collect::<Vec<_>>();
+        producers.into_iter().for_each(|p| p.join().unwrap());
+
+        let mut values = std::iter::from_fn(|| ring.pop()).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (0..4000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,142 @@
+//! Timing and random choices of the samplers.
+//!
+//! By default a sampler fires at a fixed interval from the moment it starts.
+//! `jitter` stretches or shrinks each interval of the wall sampler at random,
+//! so that sampling cannot run in lockstep with periodic work of the target,
+//! and `phase` delays the first sample of both samplers. With a `seed`, every
+//! random choice is drawn from a generator restarted from it whenever
+//! sampling starts, and the torch probe samples its modules from it too, so
+//! repeated runs of a workload are sampled alike and their profiles can be
+//! compared across revisions.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use pyo3::prelude::*;
+
+/// Largest `jitter`, beyond it intervals could shrink to nothing
+pub const MAX_JITTER: f64 = 0.5;
+
+/// `jitter` as the bits of an `f64` fraction of the interval
+static JITTER: AtomicU64 = AtomicU64::new(0);
+
+/// `phase` as the bits of an `f64` fraction of the interval
+static PHASE: AtomicU64 = AtomicU64::new(0);
+
+static SEED: Mutex<Option<u64>> = Mutex::new(None);
+
+/// State of the splitmix64 generator
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+pub fn set_jitter(jitter: f64) {
+    JITTER.store(jitter.clamp(0.0, MAX_JITTER).to_bits(), Ordering::Relaxed);
+}
+
+pub fn set_phase(phase: f64) {
+    PHASE.store(phase.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+}
+
+/// Fix the random choices of the samplers to `seed`, or let them vary from
+/// run to run with `None`
+pub fn set_seed(seed: Option<u64>) {
+    *SEED.lock().unwrap() = seed;
+    restart();
+    if let Err(e) = seed_torch_probe(seed) {
+        log::warn!("failed to seed the torch probe: {e}");
+    }
+}
+
+pub fn seed() -> Option<u64> {
+    *SEED.lock().unwrap()
+}
+
+/// Restart the generator, from the seed if one is set; called whenever a
+/// sampler starts so that each run draws the same sequence
+pub fn restart() {
+    let state = seed().unwrap_or_else(|| {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        nanos ^ (std::process::id() as u64).rotate_left(32)
+    });
+    STATE.store(state, Ordering::Relaxed);
+}
+
+fn next_u64() -> u64 {
+    let mut z = STATE
+        .fetch_add(GOLDEN_GAMMA, Ordering::Relaxed)
+        .wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Uniform draw in `[0, 1)`
+fn unit() -> f64 {
+    (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Delay of the first sample of a sampler firing every `interval`
+pub fn first_delay(interval: u64) -> u64 {
+    let phase = f64::from_bits(PHASE.load(Ordering::Relaxed));
+    interval + (interval as f64 * phase).round() as u64
+}
+
+/// Delay of the next sample of a sampler firing every `interval`, varied by
+/// up to `jitter` of it either way
+pub fn next_delay(interval: u64) -> u64 {
+    let jitter = f64::from_bits(JITTER.load(Ordering::Relaxed));
+    if jitter == 0.0 {
+        return interval;
+    }
+    let factor = 1.0 + jitter * (2.0 * unit() - 1.0);
+    ((interval as f64 * factor).round() as u64).max(1)
+}
+
+/// Reseed the module sampling of the torch probe, if it is loaded; probes
+/// created later read the seed with `probing._sampling_seed()`
+fn seed_torch_probe(seed: Option<u64>) -> PyResult<()> {
+    Python::with_gil(|py| {
+        let modules = py.import("sys")?.getattr("modules")?;
+        if let Ok(module) = modules.get_item("probing.profiling.torch_probe") {
+            module.call_method1("set_seed", (seed,))?;
+        }
+        Ok(())
+    })
+}
+
+/// Seed of the random choices of the samplers, `None` unless
+/// `probing.pprof.seed` is set
+#[pyfunction]
+pub fn _sampling_seed() -> Option<u64> {
+    seed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_delays() {
+        *SEED.lock().unwrap() = Some(42);
+        set_jitter(0.2);
+        set_phase(0.5);
+        restart();
+        let first = (0..16).map(|_| next_delay(10_000)).collect::<Vec<_>>();
+        restart();
+        let second = (0..16).map(|_| next_delay(10_000)).collect::<Vec<_>>();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|d| (8_000..=12_000).contains(d)));
+        assert!(first.iter().any(|d| *d != 10_000));
+        assert_eq!(first_delay(10_000), 15_000);
+
+        set_jitter(0.0);
+        set_phase(0.0);
+        assert_eq!(next_delay(10_000), 10_000);
+        assert_eq!(first_delay(10_000), 10_000);
+        *SEED.lock().unwrap() = None;
+    }
+}
@@ -240,7 +240,7 @@ impl TryFrom<&RawCallLocation> for CallLocation {
     }
 }
 
-fn parse_lineno<T: CodeObject>(code: *const T, lasti: i32) -> i32 {
+pub(crate) fn parse_lineno<T: CodeObject>(code: *const T, lasti: i32) -> i32 {
     if code.is_null() {
         return 0;
     }
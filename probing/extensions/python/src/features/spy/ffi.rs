@@ -22,6 +22,19 @@ extern "C" {
 
     pub fn PyInterpreterState_Get() -> *mut PyInterpreterState;
 
+    pub fn PyInterpreterState_Head() -> *mut PyInterpreterState;
+
+    pub fn PyInterpreterState_Next(interp: *mut PyInterpreterState) -> *mut PyInterpreterState;
+
+    pub fn PyInterpreterState_GetID(interp: *mut PyInterpreterState) -> i64;
+
+    pub fn PyInterpreterState_ThreadHead(interp: *mut PyInterpreterState) -> *mut PyThreadState;
+
+    pub fn PyThreadState_Next(ts: *mut PyThreadState) -> *mut PyThreadState;
+
+    /// Type of code objects, used to tell real frames from C-stack shims
+    pub static mut PyCode_Type: pyo3::ffi::PyTypeObject;
+
     pub fn _PyEval_EvalFrameDefault(
         ts: *mut PyThreadState,
         frame: *mut PyFrameObject,
@@ -4,6 +4,7 @@ pub(crate) mod python_interpreters;
 
 pub(crate) mod call;
 pub(crate) mod ffi;
+pub(crate) mod threads;
 
 pub use python_bindings::version::Version;
 
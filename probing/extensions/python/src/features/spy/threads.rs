@@ -0,0 +1,238 @@
+//! Walk the thread states of every interpreter in the process.
+//!
+//! `sys._current_frames()` only reports threads of the calling interpreter, so
+//! processes running subinterpreters (PEP 684, e.g. per-request isolation) lose
+//! whole interpreters from their profiles. This walker follows the runtime's
+//! interpreter list and reads each thread's frames directly, spy style. The
+//! other interpreters keep running while we read, so the result is a best
+//! effort snapshot and frames that look invalid end the walk.
+//!
+//! Their GIL and the runtime's `HEAD_LOCK` are not held, so a thread state or
+//! frame freed during the walk is dereferenced anyway and may crash the
+//! process. The walk of the subinterpreters is therefore off until
+//! `python.subinterpreters` is set.
+//!
+//! The wall profiler walks the main interpreter the same way, without taking
+//! its GIL, so that threads are sampled while another one holds the GIL. A
+//! thread exiting during the walk is the same hazard there; the frames of the
+//! running threads stay allocated while they are read.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use probing_proto::prelude::CallFrame;
+
+use super::call::{parse_lineno, Symbol};
+use super::ffi;
+use super::python_bindings::{v3_10_0, v3_11_0, v3_12_0, v3_13_0};
+use super::python_interpreters::{FrameObject, ThreadState};
+use super::Version;
+
+/// Stop walking stacks deeper than this, a cycle means we read garbage
+const MAX_DEPTH: usize = 1024;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Walk the subinterpreters too, off by default
+pub fn enable(enable: bool) {
+    ENABLED.store(enable, Ordering::Relaxed);
+}
+
+/// A thread of one of the interpreters, with its frames innermost first
+#[derive(Debug, Clone)]
+pub struct InterpreterThread {
+    /// Id of the interpreter, as in `interpreters.get_current().id`
+    pub interpreter: i64,
+    pub thread_id: u64,
+    pub native_id: Option<u64>,
+    /// Whether the thread held the GIL of its interpreter when read
+    pub holds_gil: bool,
+    pub frames: Vec<CallFrame>,
+}
+
+fn is_valid<T>(ptr: *const T) -> bool {
+    !ptr.is_null() && ptr.is_aligned() && ptr as usize > 0xffffff
+}
+
+/// Thread states that can tell whether they hold the GIL of their interpreter
+trait HoldsGil: ThreadState {
+    unsafe fn holds_gil(&self) -> bool;
+}
+
+impl HoldsGil for v3_10_0::PyThreadState {
+    unsafe fn holds_gil(&self) -> bool {
+        // one GIL for the whole runtime, `last_holder` is kept after release
+        let interp = self.interp();
+        if !is_valid(interp) || !is_valid((*interp).runtime) {
+            return false;
+        }
+        let gil = &(*(*interp).runtime).ceval.gil;
+        gil.locked._value != 0 && gil.last_holder._value == self as *const _ as usize
+    }
+}
+
+impl HoldsGil for v3_11_0::PyThreadState {
+    unsafe fn holds_gil(&self) -> bool {
+        let interp = self.interp();
+        if !is_valid(interp) || !is_valid((*interp).runtime) {
+            return false;
+        }
+        let gil = &(*(*interp).runtime).ceval.gil;
+        gil.locked._value != 0 && gil.last_holder._value == self as *const _ as usize
+    }
+}
+
+impl HoldsGil for v3_12_0::PyThreadState {
+    unsafe fn holds_gil(&self) -> bool {
+        // per interpreter GIL, shared with the main one unless `own_gil`
+        let interp = self.interp();
+        if !is_valid(interp) || !is_valid((*interp).ceval.gil) {
+            return false;
+        }
+        let gil = &*(*interp).ceval.gil;
+        gil.locked._value != 0 && gil.last_holder._value == self as *const _ as usize
+    }
+}
+
+impl HoldsGil for v3_13_0::PyThreadState {
+    unsafe fn holds_gil(&self) -> bool {
+        self._status.holds_gil() != 0
+    }
+}
+
+unsafe fn current_frame<T: ThreadState>(ts: *const T) -> *mut T::FrameObject {
+    match (*ts).frame_address() {
+        // 3.11 and 3.12 keep the current frame behind `cframe`
+        Some(addr) if is_valid(addr as *const usize) => *(addr as *const *mut T::FrameObject),
+        Some(_) => std::ptr::null_mut(),
+        None => (*ts).frame(None),
+    }
+}
+
+unsafe fn walk_thread<T: HoldsGil>(interpreter: i64, ts: *const T) -> InterpreterThread {
+    let mut frames = vec![];
+    let mut frame = current_frame(ts) as *const T::FrameObject;
+    while is_valid(frame) && frames.len() < MAX_DEPTH {
+        let code = (*frame).code();
+        // frames owned by the C stack carry no code object
+        if is_valid(code)
+            && pyo3::ffi::Py_TYPE(code as *mut pyo3::ffi::PyObject)
+                == std::ptr::addr_of_mut!(ffi::PyCode_Type)
+        {
+            if let Ok(symbol) = Symbol::try_from(code as *const _) {
+                frames.push(CallFrame::PyFrame {
+                    file: symbol.file,
+                    func: symbol.name,
+                    lineno: parse_lineno(code as *const _, (*frame).lasti()) as i64,
+                    locals: Default::default(),
+                });
+            }
+        }
+        frame = (*frame).back();
+    }
+    InterpreterThread {
+        interpreter,
+        thread_id: (*ts).thread_id(),
+        native_id: (*ts).native_thread_id(),
+        holds_gil: (*ts).holds_gil(),
+        frames,
+    }
+}
+
+unsafe fn walk_threadstate(
+    ver: &Version,
+    interpreter: i64,
+    ts: usize,
+) -> Option<InterpreterThread> {
+    match (ver.major, ver.minor) {
+        (3, 10) => Some(walk_thread(
+            interpreter,
+            ts as *const v3_10_0::PyThreadState,
+        )),
+        (3, 11) => Some(walk_thread(
+            interpreter,
+            ts as *const v3_11_0::PyThreadState,
+        )),
+        (3, 12) => Some(walk_thread(
+            interpreter,
+            ts as *const v3_12_0::PyThreadState,
+        )),
+        (3, 13) => Some(walk_thread(
+            interpreter,
+            ts as *const v3_13_0::PyThreadState,
+        )),
+        _ => None,
+    }
+}
+
+/// Whether the thread states of `ver` can be walked
+pub fn supported(ver: &Version) -> bool {
+    matches!((ver.major, ver.minor), (3, 10..=13))
+}
+
+/// Threads of the interpreters whose id is accepted by `walked`
+fn interpreter_threads(ver: &Version, walked: impl Fn(i64) -> bool) -> Vec<InterpreterThread> {
+    let mut threads = vec![];
+    unsafe {
+        let mut interp = ffi::PyInterpreterState_Head();
+        while !interp.is_null() {
+            let id = ffi::PyInterpreterState_GetID(interp);
+            if walked(id) {
+                let mut ts = ffi::PyInterpreterState_ThreadHead(interp);
+                while !ts.is_null() {
+                    threads.extend(walk_threadstate(ver, id, ts as usize));
+                    ts = ffi::PyThreadState_Next(ts);
+                }
+            }
+            interp = ffi::PyInterpreterState_Next(interp);
+        }
+    }
+    threads
+}
+
+unsafe fn find_holder<T: HoldsGil>(mut ts: *const T) -> Option<u64> {
+    while is_valid(ts) {
+        if (*ts).holds_gil() {
+            return Some((*ts).thread_id());
+        }
+        ts = (*ts).next();
+    }
+    None
+}
+
+/// Id of the main interpreter thread holding the GIL, read without taking it,
+/// so that it can be told apart before a capture waits for the GIL
+pub fn gil_holder(ver: &Version) -> Option<u64> {
+    unsafe {
+        let mut interp = ffi::PyInterpreterState_Head();
+        while !interp.is_null() && ffi::PyInterpreterState_GetID(interp) != 0 {
+            interp = ffi::PyInterpreterState_Next(interp);
+        }
+        if interp.is_null() {
+            return None;
+        }
+        let head = ffi::PyInterpreterState_ThreadHead(interp) as usize;
+        match (ver.major, ver.minor) {
+            (3, 10) => find_holder(head as *const v3_10_0::PyThreadState),
+            (3, 11) => find_holder(head as *const v3_11_0::PyThreadState),
+            (3, 12) => find_holder(head as *const v3_12_0::PyThreadState),
+            (3, 13) => find_holder(head as *const v3_13_0::PyThreadState),
+            _ => None,
+        }
+    }
+}
+
+/// Threads of all subinterpreters, none unless enabled. Must be called with
+/// the GIL of the calling interpreter held.
+pub fn subinterpreter_threads(ver: &Version) -> Vec<InterpreterThread> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return vec![];
+    }
+    interpreter_threads(ver, |id| id != 0)
+}
+
+/// Threads of the main interpreter, and of the subinterpreters when enabled,
+/// read without holding any GIL
+pub fn all_threads(ver: &Version) -> Vec<InterpreterThread> {
+    let subinterpreters = ENABLED.load(Ordering::Relaxed);
+    interpreter_threads(ver, |id| id == 0 || subinterpreters)
+}
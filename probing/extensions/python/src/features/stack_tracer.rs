@@ -9,13 +9,57 @@ use lazy_static::lazy_static;
 use nix::libc;
 use once_cell::sync::Lazy;
 
-use probing_proto::prelude::CallFrame;
+use probing_proto::prelude::{CallFrame, StackKind, ThreadStack};
 
+use crate::features::backtrace::policy as backtrace_policy;
 use crate::features::vm_tracer::get_python_stacks_raw;
 
 #[async_trait]
 pub trait StackTracer: Send + Sync + std::fmt::Debug {
     fn trace(&self, tid: Option<i32>) -> Result<Vec<CallFrame>>;
+
+    /// Stack of thread `tid`, native-only if it has no Python state
+    fn trace_thread(&self, tid: i32) -> Result<ThreadStack>;
+
+    /// Stacks of every thread of the process
+    fn trace_threads(&self) -> Result<Vec<ThreadStack>> {
+        let mut tids = std::fs::read_dir("/proc/self/task")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .collect::<Vec<_>>();
+        tids.sort();
+        Ok(tids
+            .into_iter()
+            .filter_map(|tid| match self.trace_thread(tid) {
+                Ok(stack) => Some(stack),
+                // the thread may have exited meanwhile
+                Err(err) => {
+                    log::warn!("failed to capture stack of thread {tid}: {err}");
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Stacks captured by the signal handler on the target thread
+struct SignalCapture {
+    native: Vec<CallFrame>,
+    /// `None` for threads without Python state
+    python: Option<Vec<CallFrame>>,
+}
+
+/// Whether the calling thread has a Python thread state, e.g. is not a
+/// thread started by a native library such as NCCL's watchdog
+fn has_python_state() -> bool {
+    unsafe {
+        pyo3::ffi::Py_IsInitialized() != 0 && !pyo3::ffi::PyGILState_GetThisThreadState().is_null()
+    }
+}
+
+fn thread_name(tid: i32) -> String {
+    std::fs::read_to_string(format!("/proc/self/task/{tid}/comm"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| tid.to_string())
 }
 
 #[derive(Debug)]
@@ -56,11 +100,13 @@ impl SignalTracer {
         Some(frames)
     }
 
-    fn send_frames(frames: Vec<CallFrame>) -> Result<()> {
+    fn send_capture(capture: SignalCapture) -> Result<()> {
         match NATIVE_CALLSTACK_SENDER_SLOT.try_lock() {
             Ok(guard) => {
                 if let Some(sender) = guard.as_ref() {
-                    sender.send(frames)?;
+                    sender
+                        .send(capture)
+                        .map_err(|_| anyhow::anyhow!("receiver of the stacks is gone"))?;
                     Ok(())
                 } else {
                     Err(anyhow::anyhow!("No sender available in channel slot"))
@@ -134,22 +180,17 @@ impl SignalTracer {
         }
         merged
     }
-}
-
-#[async_trait]
-impl StackTracer for SignalTracer {
-    fn trace(&self, tid: Option<i32>) -> Result<Vec<CallFrame>> {
-        log::debug!("Collecting backtrace for TID: {tid:?}");
 
+    /// Signal thread `tid` and wait for the stacks captured by its handler
+    fn capture(tid: i32) -> Result<SignalCapture> {
         let pid = nix::unistd::getpid().as_raw(); // PID of the current process (thread group ID)
-        let tid = tid.unwrap_or(pid); // Target thread ID, or current process's PID if tid_param is None (signals the main thread)
 
         let _guard = BACKTRACE_MUTEX.try_lock().map_err(|e| {
             log::error!("Failed to acquire BACKTRACE_MUTEX: {e}");
             anyhow::anyhow!("Failed to acquire backtrace lock: {}", e)
         })?;
 
-        let (tx, rx) = mpsc::channel::<Vec<CallFrame>>();
+        let (tx, rx) = mpsc::channel::<SignalCapture>();
         NATIVE_CALLSTACK_SENDER_SLOT
             .try_lock()
             .map_err(|err| {
@@ -174,29 +215,57 @@ impl StackTracer for SignalTracer {
             return Err(anyhow::anyhow!(error_msg));
         }
 
-        let native_frames = rx.recv_timeout(Duration::from_secs(2))?;
-        let python_frames = rx.recv_timeout(Duration::from_secs(2))?;
+        Ok(rx.recv_timeout(Duration::from_secs(2))?)
+    }
+}
 
-        Ok(Self::merge_python_native_stacks(
-            python_frames,
-            native_frames,
-        ))
+#[async_trait]
+impl StackTracer for SignalTracer {
+    fn trace(&self, tid: Option<i32>) -> Result<Vec<CallFrame>> {
+        log::debug!("Collecting backtrace for TID: {tid:?}");
+
+        let pid = nix::unistd::getpid().as_raw(); // PID of the current process (thread group ID)
+        let tid = tid.unwrap_or(pid); // Target thread ID, or current process's PID if tid_param is None (signals the main thread)
+
+        let capture = Self::capture(tid)?;
+        let mut frames = match capture.python {
+            Some(python_frames) => Self::merge_python_native_stacks(python_frames, capture.native),
+            None => capture.native,
+        };
+        backtrace_policy().truncate(&mut frames);
+        Ok(frames)
+    }
+
+    fn trace_thread(&self, tid: i32) -> Result<ThreadStack> {
+        let capture = Self::capture(tid)?;
+        let (kind, mut frames) = match capture.python {
+            Some(python_frames) => (
+                StackKind::Python,
+                Self::merge_python_native_stacks(python_frames, capture.native),
+            ),
+            None => (StackKind::Native, capture.native),
+        };
+        backtrace_policy().truncate(&mut frames);
+        Ok(ThreadStack {
+            tid,
+            name: thread_name(tid),
+            kind,
+            frames,
+        })
     }
 }
 
 pub fn backtrace_signal_handler() {
-    let native_stacks = SignalTracer::get_native_stacks().unwrap_or_default();
-    let python_stacks = get_python_stacks_raw();
-    if SignalTracer::send_frames(native_stacks).is_err() {
-        log::error!("Signal handler: CRITICAL - Failed to send native stacks. Receiver might timeout or get incomplete data.");
-    }
-    if SignalTracer::send_frames(python_stacks).is_err() {
-        log::error!("Signal handler: CRITICAL - Failed to send Python stacks. Receiver might timeout or get incomplete data.");
+    let native = SignalTracer::get_native_stacks().unwrap_or_default();
+    // threads of native libraries have no interpreter frames to walk
+    let python = has_python_state().then(get_python_stacks_raw);
+    if SignalTracer::send_capture(SignalCapture { native, python }).is_err() {
+        log::error!("Signal handler: CRITICAL - Failed to send stacks. Receiver might timeout or get incomplete data.");
     }
 }
 
 /// Define a static Mutex for the backtrace function
 static BACKTRACE_MUTEX: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
 
-pub static NATIVE_CALLSTACK_SENDER_SLOT: Lazy<Mutex<Option<mpsc::Sender<Vec<CallFrame>>>>> =
+static NATIVE_CALLSTACK_SENDER_SLOT: Lazy<Mutex<Option<mpsc::Sender<SignalCapture>>>> =
     Lazy::new(|| Mutex::new(None));
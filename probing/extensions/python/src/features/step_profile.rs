@@ -0,0 +1,345 @@
+//! Profiles of single training steps, bracketed by `probing.profile_step()`.
+//!
+//! [`begin`] raises the sample rate of the profiler, starting it if nothing
+//! samples yet, and opens a `step` span carrying the step id, so the samples
+//! of the step are the ones taken within that span. [`end`] closes the span,
+//! puts the profiler back as it was and keeps the stacks sampled in between
+//! as the profile of the step. The last [`DEFAULT_KEPT`] of them are listed by
+//! `probe.steps`, and two steps can be compared with a differential
+//! flamegraph.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use pyo3::prelude::*;
+
+use probing_core::trace;
+use probing_core::trace::SpanStatus;
+
+use super::pprof::{self, ProfileMode};
+
+/// Name of the span opened around each profiled step
+pub const STEP_SPAN: &str = "step";
+
+/// Sample rate inside the bracket unless the caller asks for another one
+pub const DEFAULT_FREQUENCY: u64 = 1000;
+
+/// Step profiles kept, the oldest being dropped first
+pub const DEFAULT_KEPT: usize = 64;
+
+/// Profile of one step
+#[derive(Debug, Clone)]
+pub struct StepProfile {
+    pub step: i64,
+    /// Start in microseconds since epoch
+    pub timestamp: i64,
+    pub duration: Duration,
+    pub mode: ProfileMode,
+    /// Sample rate in Hz within the step
+    pub frequency: u64,
+    /// Error raised out of the step, if any
+    pub error: Option<String>,
+    /// `outer;...;inner weight` lines of the samples taken within the step
+    pub folded: Vec<String>,
+}
+
+impl StepProfile {
+    pub fn samples(&self) -> u64 {
+        weights(&self.folded).values().sum()
+    }
+}
+
+/// Step being profiled and how the profiler was before it
+struct Active {
+    step: i64,
+    timestamp: i64,
+    started: Instant,
+    mode: ProfileMode,
+    frequency: u64,
+    /// Rate of the profiler before the step, `None` if it was not sampling
+    previous: Option<u64>,
+    /// Stacks sampled within earlier steps, still counted by the profiler
+    before: Vec<String>,
+}
+
+static ACTIVE: Lazy<Mutex<Option<Active>>> = Lazy::new(|| Mutex::new(None));
+static LAST_STEP: Mutex<Option<i64>> = Mutex::new(None);
+static PROFILES: Lazy<Mutex<VecDeque<StepProfile>>> = Lazy::new(Default::default);
+
+/// Stack weights of folded lines; unparsable lines are skipped
+fn weights(lines: &[String]) -> BTreeMap<&str, u64> {
+    let mut weights = BTreeMap::new();
+    for line in lines {
+        if let Some((stack, weight)) = line.rsplit_once(' ') {
+            if let Ok(weight) = weight.parse::<u64>() {
+                *weights.entry(stack).or_default() += weight;
+            }
+        }
+    }
+    weights
+}
+
+/// Folded lines of the samples in `after` that were not yet in `before`
+pub fn subtract(after: &[String], before: &[String]) -> Vec<String> {
+    let before = weights(before);
+    weights(after)
+        .into_iter()
+        .filter_map(|(stack, weight)| {
+            let weight = weight.saturating_sub(before.get(stack).copied().unwrap_or_default());
+            (weight > 0).then(|| format!("{stack} {weight}"))
+        })
+        .collect()
+}
+
+/// `stack before after` lines comparing two profiles, read by inferno as a
+/// differential flamegraph
+pub fn compare(before: &[String], after: &[String]) -> Vec<String> {
+    let (before, after) = (weights(before), weights(after));
+    let mut stacks = before.keys().chain(after.keys()).collect::<Vec<_>>();
+    stacks.sort();
+    stacks.dedup();
+    stacks
+        .into_iter()
+        .map(|stack| {
+            let count = |w: &BTreeMap<&str, u64>| w.get(stack).copied().unwrap_or_default();
+            format!("{stack} {} {}", count(&before), count(&after))
+        })
+        .collect()
+}
+
+/// Samples taken within step spans so far, empty if there are none
+fn step_samples() -> Vec<String> {
+    pprof::folded(Some(STEP_SPAN)).unwrap_or_default()
+}
+
+/// Start profiling step `step`, the one after the last profiled step if not
+/// given, sampling at `frequency` Hz. A profiler not running yet is started
+/// in `mode`. Returns the step id.
+pub fn begin(step: Option<i64>, frequency: Option<u64>, mode: Option<ProfileMode>) -> Result<i64> {
+    let mut active = ACTIVE
+        .lock()
+        .map_err(|_| anyhow::anyhow!("poisoned lock"))?;
+    if let Some(active) = active.as_ref() {
+        bail!("step {} is still being profiled", active.step);
+    }
+    let step = match (step, LAST_STEP.lock().ok().and_then(|last| *last)) {
+        (Some(step), _) => step,
+        (None, Some(last)) => last + 1,
+        (None, None) => 0,
+    };
+    let frequency = frequency.unwrap_or(DEFAULT_FREQUENCY).max(1);
+    let previous = match pprof::frequency() {
+        0 => {
+            pprof::setup_with_mode(frequency, mode.unwrap_or_default())?;
+            None
+        }
+        rate => {
+            pprof::set_frequency(frequency)?;
+            Some(rate)
+        }
+    };
+
+    let span = trace::begin_span(STEP_SPAN, Some("step"), None);
+    if let Err(e) = span.and_then(|_| trace::add_attr("step", step)) {
+        restore(previous)?;
+        bail!("failed to begin span: {e:?}");
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64;
+    *active = Some(Active {
+        step,
+        timestamp,
+        started: Instant::now(),
+        mode: pprof::active_mode(),
+        frequency,
+        previous,
+        before: match previous {
+            Some(_) => step_samples(),
+            None => vec![],
+        },
+    });
+    if let Ok(mut last) = LAST_STEP.lock() {
+        *last = Some(step);
+    }
+    Ok(step)
+}
+
+/// Put the profiler back to sampling at `previous` Hz, or stop it
+fn restore(previous: Option<u64>) -> Result<()> {
+    match previous {
+        Some(rate) => {
+            pprof::set_frequency(rate)?;
+        }
+        None => pprof::reset(),
+    }
+    Ok(())
+}
+
+/// Finish the step being profiled, `error` being what it raised, and keep its
+/// profile
+pub fn end(error: Option<String>) -> Result<StepProfile> {
+    let Some(active) = ACTIVE.lock().ok().and_then(|mut active| active.take()) else {
+        bail!("no step is being profiled");
+    };
+    let status = match &error {
+        Some(error) => SpanStatus::Error(Some(error.clone())),
+        None => SpanStatus::Close,
+    };
+    trace::end_span_with_status(status)
+        .map_err(|e| anyhow::anyhow!("failed to end span: {e:?}"))?;
+
+    let folded = subtract(&step_samples(), &active.before);
+    restore(active.previous)?;
+    let profile = StepProfile {
+        step: active.step,
+        timestamp: active.timestamp,
+        duration: active.started.elapsed(),
+        mode: active.mode,
+        frequency: active.frequency,
+        error,
+        folded,
+    };
+    keep(profile.clone());
+    Ok(profile)
+}
+
+fn keep(profile: StepProfile) {
+    let Ok(mut profiles) = PROFILES.lock() else {
+        return;
+    };
+    profiles.retain(|p| p.step != profile.step);
+    profiles.push_back(profile);
+    while profiles.len() > DEFAULT_KEPT {
+        profiles.pop_front();
+    }
+}
+
+/// Profiles of the last steps, oldest first
+pub fn profiles() -> Vec<StepProfile> {
+    PROFILES
+        .lock()
+        .map(|profiles| profiles.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Folded stacks of step `step`
+pub fn folded(step: i64) -> Result<Vec<String>> {
+    let profiles = PROFILES
+        .lock()
+        .map_err(|_| anyhow::anyhow!("poisoned lock"))?;
+    match profiles.iter().find(|p| p.step == step) {
+        Some(profile) => Ok(profile.folded.clone()),
+        None => bail!("no profile of step {step}, see probe.steps"),
+    }
+}
+
+/// Flamegraph of step `step`, or of how it differs from step `base`
+pub fn flamegraph(step: i64, base: Option<i64>) -> Result<String> {
+    let after = folded(step)?;
+    let mut opt = inferno::flamegraph::Options::default();
+    opt.deterministic = true;
+    let lines = match base {
+        Some(base) => {
+            opt.title = format!("Step {step} against step {base}");
+            compare(&folded(base)?, &after)
+        }
+        None => {
+            opt.title = format!("Step {step}");
+            after
+        }
+    };
+    if lines.is_empty() {
+        bail!("no samples within step {step}");
+    }
+    let mut graph: Vec<u8> = vec![];
+    inferno::flamegraph::from_lines(&mut opt, lines.iter().map(|x| x.as_str()), &mut graph)?;
+    Ok(String::from_utf8(graph)?)
+}
+
+/// Called by `probing.profile_step()` when entering the step
+#[pyfunction]
+#[pyo3(signature = (step=None, frequency=None, mode=None))]
+pub fn _profile_step_begin(
+    step: Option<i64>,
+    frequency: Option<u64>,
+    mode: Option<&str>,
+) -> PyResult<i64> {
+    let to_err =
+        |e: anyhow::Error| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string());
+    let mode = mode
+        .map(|m| m.parse::<ProfileMode>())
+        .transpose()
+        .map_err(to_err)?;
+    begin(step, frequency, mode).map_err(to_err)
+}
+
+/// Called by `probing.profile_step()` when leaving the step, with the
+/// exception raised out of it if any; returns the number of samples taken
+#[pyfunction]
+#[pyo3(signature = (error=None))]
+pub fn _profile_step_end(error: Option<String>) -> PyResult<u64> {
+    end(error)
+        .map(|profile| profile.samples())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_subtract() {
+        let before = lines(&["main;step;forward 10", "main;step;backward 5"]);
+        let after = lines(&[
+            "main;step;forward 14",
+            "main;step;backward 5",
+            "main;step;optimizer 3",
+        ]);
+        assert_eq!(
+            subtract(&after, &before),
+            lines(&["main;step;forward 4", "main;step;optimizer 3"])
+        );
+        assert_eq!(subtract(&after, &[]).len(), 3);
+    }
+
+    #[test]
+    fn test_compare() {
+        let before = lines(&["main;forward 10", "main;backward 5"]);
+        let after = lines(&["main;forward 4", "main;optimizer 3"]);
+        assert_eq!(
+            compare(&before, &after),
+            lines(&[
+                "main;backward 5 0",
+                "main;forward 10 4",
+                "main;optimizer 0 3"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_keep_replaces_step() {
+        let profile = |step, weight: u64| StepProfile {
+            step,
+            timestamp: 0,
+            duration: Duration::from_millis(10),
+            mode: ProfileMode::Cpu,
+            frequency: DEFAULT_FREQUENCY,
+            error: None,
+            folded: vec![format!("main;step {weight}")],
+        };
+        keep(profile(-7, 1));
+        keep(profile(-7, 2));
+        assert_eq!(folded(-7).unwrap(), vec!["main;step 2"]);
+        assert_eq!(profiles().iter().filter(|p| p.step == -7).count(), 1);
+        assert!(folded(-8).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Python functions callable from SQL.
+//!
+//! ```sql
+//! -- after probing.register_udf("tokens_per_sec", f, returns=float) in the target
+//! SELECT tokens_per_sec(tokens, duration) FROM python.steps;
+//! ```
+//!
+//! Rows are passed to the function one by one under the GIL, see
+//! `probing.core.udf._apply`; a batch taking longer than `python.udf_timeout`
+//! milliseconds fails the query. Only functions registered by the target are
+//! callable, a query cannot send Python code of its own.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array, StringArray,
+};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+use pyo3::IntoPyObjectExt;
+
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+static TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_MS);
+
+/// Limit the time a UDF may spend on one batch of rows
+pub fn set_timeout(ms: u64) {
+    TIMEOUT_MS.store(ms, Ordering::Relaxed);
+}
+
+#[derive(Debug)]
+struct PythonUdf {
+    name: String,
+    func: Py<PyAny>,
+    returns: DataType,
+    signature: Signature,
+}
+
+/// Types passed to and from Python as numbers and booleans, others as `str`
+fn python_type(data_type: &DataType) -> DataType {
+    match data_type {
+        DataType::Int32
+        | DataType::Int64
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Boolean => data_type.clone(),
+        _ => DataType::Utf8,
+    }
+}
+
+fn parse_returns(returns: &str) -> Option<DataType> {
+    match returns {
+        "int" => Some(DataType::Int64),
+        "float" => Some(DataType::Float64),
+        "bool" => Some(DataType::Boolean),
+        "str" => Some(DataType::Utf8),
+        _ => None,
+    }
+}
+
+fn py_err(name: &str, err: PyErr) -> DataFusionError {
+    DataFusionError::Execution(format!("{name}: {err}"))
+}
+
+fn to_python<'py>(py: Python<'py>, array: &ArrayRef) -> PyResult<Bound<'py, PyList>> {
+    macro_rules! values {
+        ($ty:ty) => {
+            array.as_any().downcast_ref::<$ty>().map(|a| {
+                a.iter()
+                    .map(|v| v.into_py_any(py))
+                    .collect::<PyResult<Vec<_>>>()
+            })
+        };
+    }
+    let values = match python_type(array.data_type()) {
+        DataType::Int32 => values!(Int32Array),
+        DataType::Int64 => values!(Int64Array),
+        DataType::Float32 => values!(Float32Array),
+        DataType::Float64 => values!(Float64Array),
+        DataType::Boolean => values!(BooleanArray),
+        _ => {
+            let text = cast(array, &DataType::Utf8)
+                .map_err(|e| pyo3::exceptions::PyTypeError::new_err(e.to_string()))?;
+            text.as_any().downcast_ref::<StringArray>().map(|a| {
+                a.iter()
+                    .map(|v| v.into_py_any(py))
+                    .collect::<PyResult<Vec<_>>>()
+            })
+        }
+    };
+    let values = values.unwrap_or_else(|| Ok(vec![]))?;
+    PyList::new(py, values)
+}
+
+fn from_python(values: &Bound<'_, PyAny>, data_type: &DataType) -> PyResult<ArrayRef> {
+    let items = values.try_iter()?.collect::<PyResult<Vec<_>>>()?;
+    macro_rules! extract {
+        ($ty:ty, $array:ty) => {
+            Arc::new(<$array>::from(
+                items
+                    .iter()
+                    .map(|v| v.extract::<Option<$ty>>())
+                    .collect::<PyResult<Vec<_>>>()?,
+            )) as ArrayRef
+        };
+    }
+    Ok(match data_type {
+        DataType::Int32 => extract!(i32, Int32Array),
+        DataType::Int64 => extract!(i64, Int64Array),
+        DataType::Float32 => extract!(f32, Float32Array),
+        DataType::Float64 => extract!(f64, Float64Array),
+        DataType::Boolean => extract!(bool, BooleanArray),
+        _ => Arc::new(StringArray::from(
+            items
+                .iter()
+                .map(|v| match v.is_none() {
+                    true => Ok(None),
+                    false => v.str().map(|s| Some(s.to_string())),
+                })
+                .collect::<PyResult<Vec<_>>>()?,
+        )) as ArrayRef,
+    })
+}
+
+impl ScalarUDFImpl for PythonUdf {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(self.returns.clone())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let rows = args.number_rows;
+        let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+        let timeout = TIMEOUT_MS.load(Ordering::Relaxed) as f64 / 1000.0;
+
+        Python::with_gil(|py| {
+            let func = self.func.clone_ref(py).into_bound(py);
+            let columns = arrays
+                .iter()
+                .map(|array| to_python(py, array))
+                .collect::<PyResult<Vec<_>>>()?;
+            let results = py
+                .import("probing.core.udf")?
+                .call_method1("_apply", (func, PyList::new(py, columns)?, timeout, rows))?;
+            from_python(&results, args.return_type)
+        })
+        .map(ColumnarValue::Array)
+        .map_err(|e| py_err(&self.name, e))
+    }
+}
+
+/// Register `func` as SQL function `name` returning `int`, `float`, `bool` or
+/// `str`, used by `probing.register_udf`
+#[pyfunction]
+#[pyo3(signature = (name, func, returns = "float"))]
+pub fn _register_udf(name: String, func: Py<PyAny>, returns: &str) -> PyResult<()> {
+    let returns = parse_returns(returns).ok_or_else(|| {
+        pyo3::exceptions::PyValueError::new_err(format!(
+            "unsupported return type {returns}, expected int, float, bool or str"
+        ))
+    })?;
+    log::info!("registering Python UDF {name} returning {returns}");
+    probing_core::core::functions::register_udf(ScalarUDF::new_from_impl(PythonUdf {
+        name,
+        func,
+        returns,
+        signature: Signature::variadic_any(Volatility::Volatile),
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_return_types() {
+        assert_eq!(parse_returns("float"), Some(DataType::Float64));
+        assert_eq!(parse_returns("decimal"), None);
+        assert_eq!(python_type(&DataType::Int64), DataType::Int64);
+        assert_eq!(python_type(&DataType::Date32), DataType::Utf8);
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use nix::libc;
+use once_cell::sync::Lazy;
+use probing_core::trace::active::{self, SpanTag};
+
+use probing_proto::prelude::CallFrame;
+
+use super::backtrace;
+use super::sampling;
+use super::spy::threads::{self, all_threads};
+use super::spy::PYVERSION;
+use crate::extensions::python::{get_all_python_stacks, ThreadFrame};
+
+/// Distinct stacks kept, later ones are weighed together per thread state
+const MAX_STACKS: usize = 100_000;
+/// Source lines counted for the heatmaps, later ones are not counted
+const MAX_LINES: usize = 100_000;
+
+/// Scheduling state of a sampled thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThreadState {
+    /// Runnable or running on a CPU.
+    OnCpu,
+    /// Sleeping on a futex while not inside a Python blocking primitive,
+    /// which is almost always a GIL waiter.
+    Gil,
+    /// Blocked in a read/write/poll style syscall.
+    Io,
+    /// Any other sleep (`time.sleep`, `Event.wait`, `Lock.acquire`, ...).
+    Idle,
+}
+
+impl ThreadState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThreadState::OnCpu => "on-cpu",
+            ThreadState::Gil => "gil-wait",
+            ThreadState::Io => "io-wait",
+            ThreadState::Idle => "idle",
+        }
+    }
+}
+
+/// Python functions that block on a futex on purpose; a thread parked in one
+/// of these is idle rather than waiting for the GIL.
+const BLOCKING_FUNCS: &[&str] = &["wait", "acquire", "join", "get", "_wait_for_tstate_lock"];
+
+const IO_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_accept4,
+    libc::SYS_connect,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_pwait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_select,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_accept,
+];
+
+/// Classify a thread from `/proc/self/task/<tid>/{stat,syscall}` and the name
+/// of its innermost Python frame.
+pub fn thread_state(native_id: i64, top_func: &str) -> ThreadState {
+    let stat = std::fs::read_to_string(format!("/proc/self/task/{native_id}/stat"));
+    let state = stat.ok().and_then(|s| {
+        s.rsplit_once(')')
+            .and_then(|(_, rest)| rest.trim().chars().next())
+    });
+    if matches!(state, Some('R')) {
+        return ThreadState::OnCpu;
+    }
+
+    let syscall = std::fs::read_to_string(format!("/proc/self/task/{native_id}/syscall"))
+        .ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<libc::c_long>().ok());
+    match syscall {
+        Some(nr) if IO_SYSCALLS.contains(&nr) => ThreadState::Io,
+        Some(nr) if nr == libc::SYS_futex && !BLOCKING_FUNCS.contains(&top_func) => {
+            ThreadState::Gil
+        }
+        _ => ThreadState::Idle,
+    }
+}
+
+/// Samples that hit one line of Python source
+#[derive(Debug, Clone, Default)]
+pub struct LineHits {
+    pub file: String,
+    pub lineno: i64,
+    pub func: String,
+    /// Samples with the line anywhere on the stack
+    pub samples: u64,
+    /// Samples with the line in the innermost frame
+    pub self_samples: u64,
+}
+
+/// Folded stacks (`state;outer;...;inner`) weighted by wall time in
+/// microseconds, at most [`MAX_STACKS`] of them and [`MAX_LINES`] lines.
+#[derive(Default)]
+struct Samples {
+    /// Weights by span stack of the thread, without span ids, and folded stack
+    stacks: HashMap<(SpanTag, String), u64>,
+    /// Per-line counts for the execution heatmaps, keyed by `(file, lineno)`
+    lines: HashMap<(String, i64), LineHits>,
+}
+
+impl Samples {
+    fn record(&mut self, frames: &[ThreadFrame], weight: u64) {
+        let mut threads: HashMap<(i64, i64), Vec<&ThreadFrame>> = HashMap::new();
+        for frame in frames.iter().filter(|f| !f.capturing) {
+            threads
+                .entry((frame.interpreter, frame.thread_id))
+                .or_default()
+                .push(frame);
+        }
+
+        for (_, mut stack) in threads {
+            stack.sort_by_key(|f| -f.depth);
+            let top = stack.last().map(|f| f.func.as_str()).unwrap_or_default();
+            let state = stack[0]
+                .native_id
+                .map(|tid| thread_state(tid, top))
+                .unwrap_or(ThreadState::Idle);
+            let span = stack[0]
+                .native_id
+                .map(|tid| SpanTag {
+                    span_id: 0,
+                    ..active::of_thread(tid as i32)
+                })
+                .unwrap_or_default();
+
+            let mut line = String::from(state.as_str());
+            if stack[0].interpreter != 0 {
+                line.push_str(&format!(";interp-{}", stack[0].interpreter));
+            }
+            if let Some(name) = &stack[0].thread_name {
+                line.push(';');
+                line.push_str(name);
+            }
+            let innermost = stack.len() - 1;
+            let mut seen = HashSet::new();
+            for (depth, f) in stack.into_iter().enumerate() {
+                line.push_str(&format!(";{} ({}:{})", f.func, f.file, f.lineno));
+                // a line recursing into itself counts once per sample
+                let first = seen.insert((f.file.as_str(), f.lineno));
+                let key = (f.file.clone(), f.lineno);
+                if self.lines.len() >= MAX_LINES && !self.lines.contains_key(&key) {
+                    continue;
+                }
+                let hits = self.lines.entry(key).or_insert_with(|| LineHits {
+                    file: f.file.clone(),
+                    lineno: f.lineno,
+                    func: f.func.clone(),
+                    ..Default::default()
+                });
+                if first {
+                    hits.samples += 1;
+                }
+                if depth == innermost {
+                    hits.self_samples += 1;
+                }
+            }
+            let mut key = (span, line);
+            if self.stacks.len() >= MAX_STACKS && !self.stacks.contains_key(&key) {
+                key = (
+                    SpanTag::default(),
+                    format!("{};[truncated]", state.as_str()),
+                );
+            }
+            *self.stacks.entry(key).or_default() += weight;
+        }
+    }
+}
+
+/// Name of the native thread `tid`, as set with `prctl(PR_SET_NAME)`
+fn native_thread_name(tid: u64) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/self/task/{tid}/comm"))
+        .ok()
+        .map(|name| name.trim_end().to_string())
+}
+
+/// Stacks of all Python threads, read through the thread states without the
+/// GIL so that a thread holding it does not delay the sample, or through
+/// `sys._current_frames()` on Python versions the reader does not know
+fn capture() -> Result<Vec<ThreadFrame>> {
+    #[allow(static_mut_refs)]
+    let version = unsafe { &PYVERSION };
+    if !threads::supported(version) {
+        return Ok(get_all_python_stacks()?);
+    }
+    let policy = backtrace::policy();
+    let mut rows = vec![];
+    for mut thread in all_threads(version) {
+        policy.truncate(&mut thread.frames);
+        let thread_name = thread.native_id.and_then(native_thread_name);
+        for (depth, frame) in thread.frames.into_iter().enumerate() {
+            if let CallFrame::PyFrame {
+                file, func, lineno, ..
+            } = frame
+            {
+                rows.push(ThreadFrame {
+                    interpreter: thread.interpreter,
+                    thread_id: thread.thread_id as i64,
+                    native_id: thread.native_id.map(|id| id as i64),
+                    thread_name: thread_name.clone(),
+                    is_daemon: None,
+                    holds_gil: Some(thread.holds_gil),
+                    capturing: false,
+                    depth: depth as i64,
+                    file,
+                    func,
+                    lineno,
+                });
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn interval_us(freq: i32) -> u64 {
+    1_000_000 / freq.max(1) as u64
+}
+
+struct Sampler {
+    running: Arc<AtomicBool>,
+    /// Sampling interval in microseconds, read before every sample
+    interval: Arc<AtomicU64>,
+    handle: JoinHandle<()>,
+}
+
+/// Wall-clock sampler: snapshots every Python thread at a fixed rate, so time
+/// spent blocked shows up in the flamegraph alongside time spent on CPU. The
+/// sampler does not wait for the GIL, threads blocked on it are sampled while
+/// another thread holds it.
+pub struct WallProfiler {
+    sampler: Mutex<Option<Sampler>>,
+    samples: Arc<Mutex<Samples>>,
+    /// Sampling rounds taken since the last setup
+    captured: Arc<AtomicU64>,
+    /// Rounds skipped because the previous one overran its interval
+    missed: Arc<AtomicU64>,
+}
+
+impl WallProfiler {
+    pub fn setup(&self, freq: i32) {
+        log::debug!("setup wall profiler with sample freq: {freq}");
+        self.reset();
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.stacks.clear();
+            samples.lines.clear();
+        }
+        self.captured.store(0, Ordering::Relaxed);
+        self.missed.store(0, Ordering::Relaxed);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let interval = Arc::new(AtomicU64::new(interval_us(freq)));
+        let samples = self.samples.clone();
+        let flag = running.clone();
+        let period = interval.clone();
+        let (captured, missed) = (self.captured.clone(), self.missed.clone());
+        let handle = std::thread::Builder::new()
+            .name("probing-wall-sampler".to_string())
+            .spawn(move || {
+                let mut last = Instant::now();
+                let mut delay = sampling::first_delay(period.load(Ordering::Relaxed));
+                while flag.load(Ordering::Relaxed) {
+                    // parked rather than sleeping, so a stop or a new rate
+                    // takes effect without waiting out the old interval
+                    std::thread::park_timeout(Duration::from_micros(delay));
+                    if !flag.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let weight = last.elapsed().as_micros() as u64;
+                    last = Instant::now();
+                    // the weight keeps the profile unbiased, also over
+                    // jittered intervals, but fewer stacks were seen than
+                    // the rate promises
+                    let expected = weight / delay.max(1);
+                    missed.fetch_add(expected.saturating_sub(1), Ordering::Relaxed);
+                    captured.fetch_add(1, Ordering::Relaxed);
+                    delay = sampling::next_delay(period.load(Ordering::Relaxed));
+                    match capture() {
+                        Ok(frames) => {
+                            if let Ok(mut samples) = samples.lock() {
+                                samples.record(&frames, weight);
+                            }
+                        }
+                        Err(e) => log::warn!("wall sampler failed to capture stacks: {e}"),
+                    }
+                }
+            });
+
+        match handle {
+            Ok(handle) => {
+                if let Ok(mut sampler) = self.sampler.lock() {
+                    sampler.replace(Sampler {
+                        running,
+                        interval,
+                        handle,
+                    });
+                }
+            }
+            Err(e) => log::error!("failed to start wall sampler: {e}"),
+        }
+    }
+
+    pub fn reset(&self) {
+        let sampler = self.sampler.lock().ok().and_then(|mut s| s.take());
+        if let Some(sampler) = sampler {
+            sampler.running.store(false, Ordering::Relaxed);
+            sampler.handle.thread().unpark();
+            let _ = sampler.handle.join();
+        }
+    }
+
+    /// Change the rate of a running sampler, keeping the samples collected so
+    /// far. Returns `false` if no sampler is running.
+    pub fn set_frequency(&self, freq: i32) -> bool {
+        let sampler = self.sampler.lock().unwrap();
+        match sampler.as_ref() {
+            Some(sampler) => {
+                sampler.interval.store(interval_us(freq), Ordering::Relaxed);
+                sampler.handle.thread().unpark();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sample rate in Hz, 0 when not sampling
+    pub fn frequency(&self) -> u64 {
+        let sampler = self.sampler.lock().unwrap();
+        sampler
+            .as_ref()
+            .map(|s| 1_000_000 / s.interval.load(Ordering::Relaxed).max(1))
+            .unwrap_or(0)
+    }
+
+    /// Sampling rounds taken and missed since the last setup
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.captured.load(Ordering::Relaxed),
+            self.missed.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Collapsed stacks weighted by wall time in microseconds, only those
+    /// sampled within a span called `span` if given
+    pub fn folded(&self, span: Option<&str>) -> Result<Vec<String>> {
+        let samples = self
+            .samples
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock wall samples: {:?}", e))?;
+        if samples.stacks.is_empty() {
+            return Err(anyhow::anyhow!("no wall samples"));
+        }
+        let lines = samples
+            .stacks
+            .iter()
+            .filter(|((tag, _), _)| span.is_none_or(|span| tag.within(span)))
+            .map(|((_, stack), weight)| format!("{stack} {weight}"))
+            .collect::<Vec<_>>();
+        match (lines.is_empty(), span) {
+            (true, Some(span)) => Err(anyhow::anyhow!("no wall samples within span {span}")),
+            _ => Ok(lines),
+        }
+    }
+
+    /// Wall time in microseconds by innermost active span, `None` outside
+    /// any span
+    pub fn span_weights(&self) -> HashMap<Option<String>, u64> {
+        let mut spans = HashMap::new();
+        if let Ok(samples) = self.samples.lock() {
+            for ((span, _), weight) in samples.stacks.iter() {
+                *spans.entry(span.innermost()).or_default() += weight;
+            }
+        }
+        spans
+    }
+
+    /// Per-line sample counts of the files accepted by `filter`
+    pub fn line_hits(&self, filter: impl Fn(&str) -> bool) -> Vec<LineHits> {
+        let Ok(samples) = self.samples.lock() else {
+            return vec![];
+        };
+        samples
+            .lines
+            .values()
+            .filter(|hits| filter(&hits.file))
+            .cloned()
+            .collect()
+    }
+
+    pub fn flamegraph(&self, span: Option<&str>) -> Result<String> {
+        let lines = self.folded(span)?;
+
+        let mut opt = inferno::flamegraph::Options::default();
+        opt.deterministic = true;
+        opt.count_name = "us".to_string();
+        let mut graph: Vec<u8> = vec![];
+        inferno::flamegraph::from_lines(&mut opt, lines.iter().map(|x| x.as_str()), &mut graph)?;
+        Ok(String::from_utf8(graph)?)
+    }
+}
+
+pub static WALL_PROFILER: Lazy<WallProfiler> = Lazy::new(|| WallProfiler {
+    sampler: Mutex::new(None),
+    samples: Arc::new(Mutex::new(Samples::default())),
+    captured: Arc::new(AtomicU64::new(0)),
+    missed: Arc::new(AtomicU64::new(0)),
+});
@@ -12,3 +12,13 @@ pub mod python;
 pub mod repl;
 
 mod setup;
+
+/// Cargo features this crate was built with
+pub const FEATURES: &[&str] = &[
+    #[cfg(feature = "extension-module")]
+    "extension-module",
+    #[cfg(feature = "tracing")]
+    "tracing",
+    #[cfg(feature = "cupti")]
+    "cupti",
+];
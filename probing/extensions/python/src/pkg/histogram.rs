@@ -0,0 +1,122 @@
+use probing_proto::prelude::Histogram as _Histogram;
+use pyo3::exceptions::PyValueError;
+use pyo3::types::PyType;
+use pyo3::{pyclass, pymethods, Bound, PyErr, PyResult};
+
+fn value_error<E: ToString>(e: E) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Mergeable fixed-bucket histogram, appended to an `ExternalTable` as text so
+/// `histogram_quantile` and `histogram_merge` can be used on it in SQL
+#[pyclass]
+#[derive(Clone)]
+pub struct Histogram(pub _Histogram);
+
+#[pymethods]
+impl Histogram {
+    #[new]
+    #[pyo3(signature = (bounds=None))]
+    fn new(bounds: Option<Vec<f64>>) -> PyResult<Self> {
+        match bounds {
+            Some(bounds) => _Histogram::new(bounds).map(Histogram).map_err(value_error),
+            None => Ok(Histogram(_Histogram::default())),
+        }
+    }
+
+    #[classmethod]
+    fn exponential(
+        _cls: &Bound<'_, PyType>,
+        start: f64,
+        factor: f64,
+        count: usize,
+    ) -> PyResult<Self> {
+        _Histogram::exponential(start, factor, count)
+            .map(Histogram)
+            .map_err(value_error)
+    }
+
+    #[classmethod]
+    fn linear(_cls: &Bound<'_, PyType>, start: f64, width: f64, count: usize) -> PyResult<Self> {
+        _Histogram::linear(start, width, count)
+            .map(Histogram)
+            .map_err(value_error)
+    }
+
+    /// Parse the text form produced by `str(histogram)`
+    #[classmethod]
+    fn parse(_cls: &Bound<'_, PyType>, text: &str) -> PyResult<Self> {
+        text.parse().map(Histogram).map_err(value_error)
+    }
+
+    #[pyo3(signature = (value, n=1))]
+    fn record(&mut self, value: f64, n: u64) {
+        self.0.record_n(value, n)
+    }
+
+    fn merge(&mut self, other: &Histogram) -> PyResult<()> {
+        self.0.merge(&other.0).map_err(value_error)
+    }
+
+    fn quantile(&self, q: f64) -> Option<f64> {
+        self.0.quantile(q)
+    }
+
+    /// Drop the recorded values, keeping the buckets
+    fn reset(&mut self) -> PyResult<()> {
+        self.0 = _Histogram::new(self.0.bounds().to_vec()).map_err(value_error)?;
+        Ok(())
+    }
+
+    #[getter]
+    fn count(&self) -> u64 {
+        self.0.count()
+    }
+
+    #[getter]
+    fn sum(&self) -> f64 {
+        self.0.sum()
+    }
+
+    #[getter]
+    fn mean(&self) -> Option<f64> {
+        self.0.mean()
+    }
+
+    #[getter]
+    fn min(&self) -> Option<f64> {
+        self.0.min()
+    }
+
+    #[getter]
+    fn max(&self) -> Option<f64> {
+        self.0.max()
+    }
+
+    #[getter]
+    fn bounds(&self) -> Vec<f64> {
+        self.0.bounds().to_vec()
+    }
+
+    #[getter]
+    fn counts(&self) -> Vec<u64> {
+        self.0.counts().to_vec()
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.count() as usize
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Histogram(count={}, p50={:?}, p99={:?})",
+            self.0.count(),
+            self.0.quantile(0.5),
+            self.0.quantile(0.99)
+        )
+    }
+}
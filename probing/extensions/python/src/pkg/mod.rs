@@ -1,37 +1,84 @@
+mod histogram;
+
 use std::time::Duration;
 
+use probing_store::store::DurableStore;
 use probing_store::store::TCPStore as _TCPStore;
+use probing_store::store::WriteAheadLog;
 use pyo3::{exceptions::PyException, pyclass, pymethods, PyErr, PyResult};
 
+pub use histogram::Histogram;
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
 #[pyclass]
 pub struct TCPStore {
-    store: _TCPStore,
+    store: DurableStore,
+    /// Whether `set` goes through the write-ahead queue
+    durable: bool,
 }
 
 #[pymethods]
 impl TCPStore {
+    /// With `wal`, writes are queued in a write-ahead log at that path and
+    /// retried until the master acknowledges them, instead of raising.
     #[new]
-    pub fn new(endpoint: String, timeout: Option<u64>) -> Self {
+    #[pyo3(signature = (endpoint, timeout=None, wal=None))]
+    pub fn new(endpoint: String, timeout: Option<u64>, wal: Option<String>) -> PyResult<Self> {
         let timeout = timeout.unwrap_or(1000);
         let store = _TCPStore::new(endpoint).with_timeout(Duration::from_millis(timeout));
-        TCPStore { store }
+        let (store, durable) = match wal {
+            Some(path) => (
+                DurableStore::open(store, path.into())
+                    .map_err(|e| PyErr::new::<PyException, _>(format!("WAL error: {e}")))?,
+                true,
+            ),
+            None => (DurableStore::new(store, WriteAheadLog::in_memory()), false),
+        };
+        Ok(TCPStore { store, durable })
     }
 
     pub fn set(&mut self, key: &str, value: &str) -> PyResult<()> {
-        let ret = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(self.store.set(key, value));
+        let ret = match self.durable {
+            true => block_on(self.store.set(key, value)).map(|_| ()),
+            false => block_on(self.store.store().set(key, value)),
+        };
         ret.map_err(|e| PyErr::new::<PyException, _>(format!("Set error: {e}")))
     }
 
     pub fn get(&mut self, key: &str) -> PyResult<String> {
-        let ret = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(self.store.get(key));
+        let ret = block_on(self.store.store().get(key));
+        ret.map_err(|e| PyErr::new::<PyException, _>(format!("Get error: {e}")))
+    }
+
+    pub fn multi_set(&mut self, items: Vec<(String, String)>) -> PyResult<()> {
+        let ret = match self.durable {
+            true => block_on(self.store.multi_set(&items)).map(|_| ()),
+            false => block_on(self.store.store().multi_set(&items)),
+        };
+        ret.map_err(|e| PyErr::new::<PyException, _>(format!("Set error: {e}")))
+    }
+
+    pub fn multi_get(&mut self, keys: Vec<String>) -> PyResult<Vec<String>> {
+        let ret = block_on(self.store.store().multi_get(&keys));
         ret.map_err(|e| PyErr::new::<PyException, _>(format!("Get error: {e}")))
     }
+
+    /// Send queued writes, retrying with backoff; raises if the master stays
+    /// unreachable
+    pub fn flush(&mut self) -> PyResult<()> {
+        block_on(self.store.flush())
+            .map_err(|e| PyErr::new::<PyException, _>(format!("Flush error: {e}")))
+    }
+
+    /// Number of queued writes not yet acknowledged by the master
+    pub fn pending(&self) -> usize {
+        self.store.pending()
+    }
 }
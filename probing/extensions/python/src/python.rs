@@ -9,6 +9,14 @@ use pyo3::{types::PyDict, Python};
 
 use crate::pycode::get_code;
 
+/// Version of the interpreter the probe is loaded into
+pub fn python_version() -> String {
+    Python::with_gil(|py| {
+        let v = py.version_info();
+        format!("{}.{}.{}", v.major, v.minor, v.patch)
+    })
+}
+
 pub static CRASH_HANDLER: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
 pub static OLD_HANDLER: Lazy<Option<Py<PyAny>>> = Lazy::new(|| None);
 
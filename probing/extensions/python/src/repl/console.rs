@@ -1,3 +1,4 @@
+use probing_proto::prelude::EvalResult;
 use pyo3::ffi::c_str;
 use pyo3::{
     types::{PyAnyMethods, PyDict},
@@ -7,6 +8,8 @@ use pyo3::{
 use crate::repl::python_repl::PythonConsole;
 
 pub struct NativePythonConsole {
+    /// `probing.repl`, whose `push` feeds the debug console and records the
+    /// statements of the session
     console: Py<PyAny>,
 }
 
@@ -16,10 +19,10 @@ impl Default for NativePythonConsole {
         Self {
             console: Python::with_gil(|py| {
                 let global = PyDict::new(py);
-                let code = c_str!("from probing.repl import debug_console");
+                let code = c_str!("import probing.repl as repl");
                 let _ = py.run(code, Some(&global), Some(&global));
                 let ret: Bound<'_, PyAny> = global
-                    .get_item("debug_console")
+                    .get_item("repl")
                     .map_err(|err| {
                         eprintln!("error initializing console: {err}");
                     })
@@ -43,4 +46,17 @@ impl PythonConsole for NativePythonConsole {
             Err(err) => Some(err.to_string()),
         })
     }
+
+    fn evaluate(&mut self, code: String) -> EvalResult {
+        let reply = Python::with_gil(|py| {
+            py.import("probing.repl")?
+                .call_method1("evaluate", (code,))?
+                .extract::<String>()
+        });
+        match reply {
+            Ok(text) => serde_json::from_str(&text)
+                .unwrap_or_else(|err| EvalResult::failure("ProtocolError", err.to_string())),
+            Err(err) => EvalResult::failure("InternalError", err.to_string()),
+        }
+    }
 }
@@ -1,6 +1,8 @@
 use crate::repl::console::NativePythonConsole;
 use std::sync::{Arc, Mutex};
 
+use probing_proto::prelude::EvalResult;
+
 pub trait Repl {
     fn feed(&mut self, s: String) -> Option<String>;
     fn is_alive(&self) -> bool;
@@ -8,6 +10,9 @@ pub trait Repl {
 
 pub trait PythonConsole {
     fn try_execute(&mut self, cmd: String) -> Option<String>;
+
+    /// Run `code` and report its output, value and error separately
+    fn evaluate(&mut self, code: String) -> EvalResult;
 }
 
 pub struct PythonRepl {
@@ -31,6 +36,10 @@ impl PythonRepl {
     pub fn process(&mut self, cmd: &str) -> Option<String> {
         self.console.lock().unwrap().try_execute(cmd.to_string())
     }
+
+    pub fn evaluate(&mut self, code: &str) -> EvalResult {
+        self.console.lock().unwrap().evaluate(code.to_string())
+    }
 }
 
 impl Repl for PythonRepl {
@@ -11,9 +11,85 @@ struct OptionMetadata {
     aliases: Vec<String>,
     description: String,
     managed: bool,
+    requires_restart: bool,
+    depends_on: Option<String>,
+    secret: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    choices: Vec<String>,
 }
 
-#[proc_macro_derive(EngineExtension, attributes(option))]
+impl OptionMetadata {
+    /// The option name followed by its aliases
+    fn matchers(&self) -> Vec<String> {
+        let mut matchers = vec![self.name.clone()];
+        matchers.extend(self.aliases.iter().cloned());
+        matchers
+    }
+
+    /// Checks of `value` against the `min`, `max` and `choices` constraints;
+    /// an empty value resets the option and is always accepted
+    fn constraint_checks(&self) -> impl quote::ToTokens {
+        let mut checks = vec![];
+        if let Some(min) = self.min {
+            checks.push(quote! {
+                if !value.parse::<f64>().is_ok_and(|v| v >= #min) {
+                    return Err(EngineError::InvalidOptionValue(
+                        key.to_string(),
+                        format!("{value}, expected at least {}", #min),
+                    ));
+                }
+            });
+        }
+        if let Some(max) = self.max {
+            checks.push(quote! {
+                if !value.parse::<f64>().is_ok_and(|v| v <= #max) {
+                    return Err(EngineError::InvalidOptionValue(
+                        key.to_string(),
+                        format!("{value}, expected at most {}", #max),
+                    ));
+                }
+            });
+        }
+        if !self.choices.is_empty() {
+            let choices = &self.choices;
+            let expected = choices.join(", ");
+            checks.push(quote! {
+                if ![#(#choices),*].contains(&value.trim()) {
+                    return Err(EngineError::InvalidOptionValue(
+                        key.to_string(),
+                        format!("{value}, expected one of {}", #expected),
+                    ));
+                }
+            });
+        }
+        if checks.is_empty() {
+            return quote! {};
+        }
+        quote! {
+            if !value.is_empty() {
+                #(#checks)*
+            }
+        }
+    }
+
+    /// Constraints shown in the help of the option, e.g. `Range[1, 100]`
+    fn constraint_help(&self) -> String {
+        let mut help = String::new();
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => help.push_str(&format!("Range[{min}, {max}]\n")),
+            (Some(min), None) => help.push_str(&format!("Min[{min}]\n")),
+            (None, Some(max)) => help.push_str(&format!("Max[{max}]\n")),
+            (None, None) => {}
+        }
+        if !self.choices.is_empty() {
+            help.push_str(&format!("Choices[{}]\n", self.choices.join("|")));
+        }
+        help
+    }
+}
+
+#[proc_macro_derive(EngineExtension, attributes(option, extension))]
 pub fn derive_engine_extension(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
     impl_engine_extension(&ast)
@@ -40,13 +116,31 @@ fn impl_engine_extension(ast: &DeriveInput) -> TokenStream {
         .collect::<Vec<_>>();
     // eprintln!("== {:?}", field_metadata);
 
+    // `#[extension(on_enable = "method", on_disable = "method")]` forwards the
+    // lifecycle hooks to inherent methods of the extension, and
+    // `selftest = "method"` its self-test.
+    let lifecycle_hooks = parse_lifecycle_hooks(ast)
+        .into_iter()
+        .map(|(hook, method)| {
+            let method = format_ident!("{}", method);
+            if hook == "selftest" {
+                return quote! {
+                    fn selftest(&self) -> Vec<probing_proto::prelude::SelfCheck> {
+                        self.#method()
+                    }
+                };
+            }
+            let hook = format_ident!("{}", hook);
+            quote! {
+                fn #hook(&mut self) -> Result<(), EngineError> {
+                    self.#method()
+                }
+            }
+        });
+
     let get_matches = field_metadata.iter().map(|meta| {
         let field_ident = format_ident!("{}", meta.field);
-
-        let field_name = meta.name.to_string();
-        let aliases = &meta.aliases;
-        let mut matchers = vec![field_name.clone()];
-        matchers.extend(aliases.iter().cloned());
+        let matchers = meta.matchers();
 
         quote! {
             #(#matchers)|* => Ok(self.#field_ident.to_string())
@@ -56,14 +150,12 @@ fn impl_engine_extension(ast: &DeriveInput) -> TokenStream {
     let set_matches = field_metadata.iter().map(|meta| {
         let field_ident = format_ident!("{}", meta.field);
         let set_field = format_ident!("set_{}", meta.field);
-
-        let field_name = meta.name.to_string();
-        let aliases = &meta.aliases;
-        let mut matchers = vec![field_name.clone()];
-        matchers.extend(aliases.iter().cloned());
+        let matchers = meta.matchers();
+        let checks = meta.constraint_checks();
 
         quote! {
             #(#matchers)|* => {
+                #checks
                 let old = self.#field_ident.to_string();
                 let new = value.parse()
                 .map_err(|_| EngineError::InvalidOptionValue(key.to_string(), value.to_string()))?;
@@ -74,25 +166,65 @@ fn impl_engine_extension(ast: &DeriveInput) -> TokenStream {
         }
     });
 
-    let options = field_metadata.iter().map(|meta| {
-        let name = format!("{}.{}", namespace.to_lowercase(), meta.name);
-        let desc = format!(
-            "{}.\nENV[PROBING_{}_{}]",
-            meta.description,
-            namespace.to_uppercase(),
-            name.to_string().to_uppercase().replace(".", "_")
-        );
-        let field_ident = format_ident!("{}", meta.field);
+    let validate_matches = field_metadata.iter().map(|meta| {
+        let set_field = format_ident!("set_{}", meta.field);
+        let matchers = meta.matchers();
+        let checks = meta.constraint_checks();
 
         quote! {
-            EngineExtensionOption {
-                key: #name.to_string(),
-                value: Some(self.#field_ident.to_string()),
-                help: #desc,
+            #(#matchers)|* => {
+                #checks
+                if !parses(Self::#set_field, value) {
+                    return Err(EngineError::InvalidOptionValue(key.to_string(), value.to_string()));
+                }
+                Ok(())
             }
         }
     });
 
+    let option_values = field_metadata
+        .iter()
+        .map(|meta| {
+            let name = format!("{}.{}", namespace.to_lowercase(), meta.name);
+            let desc = format!(
+                "{}.\n{}ENV[PROBING_{}_{}]",
+                meta.description,
+                meta.constraint_help(),
+                namespace.to_uppercase(),
+                name.to_string().to_uppercase().replace(".", "_")
+            );
+            let field_ident = format_ident!("{}", meta.field);
+            let requires_restart = meta.requires_restart;
+            let secret = meta.secret;
+            let depends_on = match &meta.depends_on {
+                Some(option) => quote! { Some(#option) },
+                None => quote! { None },
+            };
+
+            quote! {
+                EngineExtensionOption {
+                    key: #name.to_string(),
+                    value: Some(self.#field_ident.to_string()),
+                    help: #desc,
+                    requires_restart: #requires_restart,
+                    depends_on: #depends_on,
+                    secret: #secret,
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let options = option_values.iter();
+
+    let option_matches = field_metadata
+        .iter()
+        .zip(&option_values)
+        .map(|(meta, option)| {
+            let matchers = meta.matchers();
+            quote! {
+                #(#matchers)|* => Some(#option)
+            }
+        });
+
     // Generate option name constants for consistent usage
     let option_constants = field_metadata.iter().map(|meta| {
         let const_name = format_ident!("OPTION_{}", meta.field.to_uppercase());
@@ -123,12 +255,36 @@ fn impl_engine_extension(ast: &DeriveInput) -> TokenStream {
                 }
             }
 
+            fn validate(&self, key: &str, value: &str) -> Result<(), EngineError> {
+                // whether `value` parses as the argument of the setter
+                #[allow(unused)]
+                fn parses<S, T: std::str::FromStr>(
+                    _setter: fn(&mut S, T) -> Result<(), EngineError>,
+                    value: &str,
+                ) -> bool {
+                    value.parse::<T>().is_ok()
+                }
+                match key {
+                    #(#validate_matches,)*
+                    _ => Err(EngineError::UnsupportedOption(key.to_string()))
+                }
+            }
+
             fn options(&self) -> Vec<EngineExtensionOption> {
                 vec![
                     #(#options,)*
                 ]
             }
 
+            fn option(&self, key: &str) -> Option<EngineExtensionOption> {
+                match key {
+                    #(#option_matches,)*
+                    _ => None
+                }
+            }
+
+            #(#lifecycle_hooks)*
+
             // fn datasrc(&self, namespace: &str, name: Option<&str>) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
             //     self.plugin(namespace, name)
             // }
@@ -150,6 +306,12 @@ fn parse_field_metadata(field: &Field) -> OptionMetadata {
         aliases: vec![],
         description: String::new(),
         managed: false,
+        requires_restart: false,
+        depends_on: None,
+        secret: false,
+        min: None,
+        max: None,
+        choices: vec![],
     };
 
     let mut descriptions: Vec<String> = vec![];
@@ -163,11 +325,24 @@ fn parse_field_metadata(field: &Field) -> OptionMetadata {
                     .unwrap()
                     .iter()
                 {
+                    if let Meta::Path(path) = nested {
+                        // flags such as `#[option(requires_restart)]`
+                        if path.is_ident("requires_restart") {
+                            metadata.requires_restart = true;
+                        } else if path.is_ident("secret") {
+                            metadata.secret = true;
+                        } else {
+                            panic!("Unsupported option flag: {}", quote!(#path));
+                        }
+                    }
                     if let Meta::NameValue(nv) = nested {
                         let name = nv.path.get_ident().unwrap().to_string();
                         let value = match &nv.value {
                             syn::Expr::Lit(lit) => match &lit.lit {
                                 syn::Lit::Str(s) => s.value(),
+                                syn::Lit::Int(i) => i.base10_digits().to_string(),
+                                syn::Lit::Float(f) => f.base10_digits().to_string(),
+                                syn::Lit::Bool(b) => b.value.to_string(),
                                 _ => continue,
                             },
                             syn::Expr::Array(array) => {
@@ -193,6 +368,12 @@ fn parse_field_metadata(field: &Field) -> OptionMetadata {
                         match name.as_str() {
                             "name" => metadata.name = value,
                             "aliases" => metadata.aliases = parse_string_array(&value),
+                            "requires_restart" => metadata.requires_restart = value == "true",
+                            "depends_on" => metadata.depends_on = Some(value),
+                            "secret" => metadata.secret = value == "true",
+                            "min" => metadata.min = Some(parse_number(&name, &value)),
+                            "max" => metadata.max = Some(parse_number(&name, &value)),
+                            "choices" => metadata.choices = parse_string_array(&value),
                             _ => {}
                         }
                     }
@@ -223,6 +404,32 @@ fn parse_field_metadata(field: &Field) -> OptionMetadata {
     metadata
 }
 
+fn parse_lifecycle_hooks(ast: &DeriveInput) -> Vec<(String, String)> {
+    let mut hooks = vec![];
+    for attr in ast.attrs.iter().filter(|a| a.path().is_ident("extension")) {
+        let nested = attr
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .expect("Invalid extension attribute format");
+        for meta in nested.iter() {
+            let Meta::NameValue(nv) = meta else {
+                panic!("Invalid extension attribute format");
+            };
+            let hook = nv.path.get_ident().unwrap().to_string();
+            if !["on_enable", "on_disable", "selftest"].contains(&hook.as_str()) {
+                panic!("Unsupported extension hook: {hook}");
+            }
+            match &nv.value {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }) => hooks.push((hook, s.value())),
+                _ => panic!("Extension hook `{hook}` expects a method name string"),
+            }
+        }
+    }
+    hooks
+}
+
 fn parse_string_array(input: &str) -> Vec<String> {
     input
         .trim_matches(|c| c == '[' || c == ']')
@@ -230,3 +437,9 @@ fn parse_string_array(input: &str) -> Vec<String> {
         .map(|s| s.trim().trim_matches('"').to_string())
         .collect()
 }
+
+fn parse_number(name: &str, value: &str) -> f64 {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("Option constraint `{name}` expects a number, got {value}"))
+}
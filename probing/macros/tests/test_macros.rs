@@ -134,3 +134,96 @@ fn test_macro() {
     assert_eq!(opts[2].value, Some("B".to_string()));
     // assert_eq!(opts[2].help, "describe managed_field_name3");
 }
+
+#[test]
+fn test_macro_lifecycle_hooks() {
+    #[derive(Debug, EngineExtension)]
+    #[extension(on_enable = "start", on_disable = "stop")]
+    struct LifecycleExtension {
+        running: bool,
+    }
+
+    impl EngineCall for LifecycleExtension {}
+
+    impl EngineDatasource for LifecycleExtension {}
+
+    impl LifecycleExtension {
+        fn start(&mut self) -> Result<(), EngineError> {
+            self.running = true;
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), EngineError> {
+            self.running = false;
+            Ok(())
+        }
+    }
+
+    let mut ext = LifecycleExtension { running: true };
+    assert!(ext.on_disable().is_ok());
+    assert!(!ext.running);
+    assert!(ext.on_enable().is_ok());
+    assert!(ext.running);
+    assert!(ext.options().is_empty());
+}
+
+#[test]
+fn test_macro_option_metadata() {
+    #[derive(Debug, EngineExtension)]
+    struct SamplerExtension {
+        /// Sampling interval in milliseconds
+        #[option(requires_restart, min = 1, max = 1000)]
+        interval: i64,
+
+        /// What each sample captures
+        #[option(depends_on = "sampler.interval", choices = ["stack", "full"])]
+        detail: Maybe<String>,
+    }
+
+    impl EngineCall for SamplerExtension {}
+
+    impl EngineDatasource for SamplerExtension {}
+
+    impl SamplerExtension {
+        fn set_interval(&mut self, value: i64) -> Result<(), EngineError> {
+            self.interval = value;
+            Ok(())
+        }
+
+        fn set_detail(&mut self, value: Maybe<String>) -> Result<(), EngineError> {
+            self.detail = value;
+            Ok(())
+        }
+    }
+
+    let mut ext = SamplerExtension {
+        interval: 10,
+        detail: Maybe::Nothing,
+    };
+
+    assert!(ext.validate("interval", "0").is_err());
+    assert!(ext.validate("interval", "often").is_err());
+    assert!(ext.validate("detail", "everything").is_err());
+    assert!(ext.validate("missing", "1").is_err());
+    ext.validate("interval", "100").unwrap();
+    assert_eq!(ext.interval, 10);
+
+    assert!(ext.set("interval", "0").is_err());
+    assert!(ext.set("interval", "5000").is_err());
+    assert!(ext.set("interval", "often").is_err());
+    assert_eq!(ext.set("interval", "100").unwrap(), "10".to_string());
+    assert!(ext.set("detail", "everything").is_err());
+    assert_eq!(ext.set("detail", "full").unwrap(), "".to_string());
+    // an empty value resets the option
+    assert_eq!(ext.set("detail", "").unwrap(), "full".to_string());
+
+    let interval = ext.option("interval").unwrap();
+    assert_eq!(interval.key, "sampler.interval");
+    assert!(interval.requires_restart);
+    assert!(interval.help.contains("Range[1, 1000]"));
+    let detail = ext.option("detail").unwrap();
+    assert!(!detail.requires_restart);
+    assert_eq!(detail.depends_on, Some("sampler.interval"));
+    assert!(detail.help.contains("Choices[stack|full]"));
+    assert!(ext.option("missing").is_none());
+}
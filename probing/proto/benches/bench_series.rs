@@ -23,11 +23,7 @@ fn page_append(n: u64) -> u64 {
                     int_array.push(i as i64);
                 }
             }
-            probing_proto::types::series::Page::Compressed {
-                dtype: _,
-                buffer: _,
-                codebook: _,
-            } => todo!(),
+            probing_proto::types::series::Page::Compressed { .. } => todo!(),
             probing_proto::types::series::Page::Ref => todo!(),
         }
     }
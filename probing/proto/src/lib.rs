@@ -3,17 +3,34 @@ pub mod types;
 
 pub mod prelude {
     // --- Protocol Structures ---
-    pub use crate::protocol::cluster::{Cluster, Node};
+    pub use crate::protocol::archive::ProbeArchive;
+    pub use crate::protocol::capabilities::Capabilities;
+    pub use crate::protocol::cluster::{ClockReply, ClockSync, Cluster, Node};
+    pub use crate::protocol::compare::{AlignBy, CompareRequest, CompareSide};
+    pub use crate::protocol::dash::{ActiveSpan, DashFrame, FailedSpan, HotFunction};
+    pub use crate::protocol::dashboard::{ChartType, Dashboard, DashboardPanel};
+    pub use crate::protocol::eval::{EvalError, EvalResult, EvalValue, ScriptStep, SessionMeta};
+    pub use crate::protocol::extension::{
+        ExtensionStatus, OptionChange, OptionCheck, OptionStatus, SelfCheck, SelfTest,
+    };
+    pub use crate::protocol::flamegraph::{FoldedStack, TreemapNode};
+    pub use crate::protocol::handshake::Handshake;
     pub use crate::protocol::message::Message;
-    pub use crate::protocol::process::{CallFrame, Process};
+    pub use crate::protocol::process::{CallFrame, Process, StackKind, ThreadStack};
 
     pub use crate::protocol::query::{Data as QueryDataFormat, Options as QueryOptions, Query};
-    pub use crate::protocol::query::{ErrorCode, QueryError};
+    pub use crate::protocol::query::{ErrorCode, QueryError, TableTail};
+    pub use crate::protocol::resources::ProbeResources;
+    pub use crate::protocol::snapshot::{EnvSnapshot, SnapshotChange};
+    pub use crate::protocol::status::ProbeStatus;
+    pub use crate::protocol::table::{ColumnDoc, TableDoc};
     pub use crate::protocol::version::ProtocolVersion;
 
     // --- Core Data Types ---
+    pub use crate::types::Codec;
     pub use crate::types::DataFrame;
     pub use crate::types::Ele;
+    pub use crate::types::Histogram;
     pub use crate::types::Seq;
     pub use crate::types::TimeSeries;
     pub use crate::types::Value;
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::DataFrame;
+
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// Tables left out when all the tables of a probe are collected, by its own
+/// archive and by `probing diagnose`: `python.backtrace` interrupts the main
+/// thread, `analysis.anomalies` is derived from other tables and the
+/// `external` tables read the rows other probes pushed to the store on every
+/// query. An entry ending in `.*` covers a whole namespace.
+pub const SKIPPED_TABLES: &[&str] = &["python.backtrace", "analysis.anomalies", "external.*"];
+
+/// Whether `table`, as `namespace.table`, is one of [`SKIPPED_TABLES`]
+pub fn is_skipped(table: &str) -> bool {
+    SKIPPED_TABLES
+        .iter()
+        .any(|skipped| match skipped.strip_suffix(".*") {
+            Some(namespace) => table.split_once('.').is_some_and(|(ns, _)| ns == namespace),
+            None => table == *skipped,
+        })
+}
+
+/// Snapshot of a probe's tables persisted to a local file, so that the data
+/// can still be queried after the process has exited.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ProbeArchive {
+    pub version: u32,
+    pub pid: i32,
+    pub host: String,
+    /// Snapshot time in microseconds since UNIX epoch
+    pub timestamp: u64,
+    /// Archived tables keyed by `namespace.table`
+    pub tables: BTreeMap<String, DataFrame>,
+}
+
+impl ProbeArchive {
+    pub fn new(pid: i32, host: String, timestamp: u64) -> Self {
+        Self {
+            version: ARCHIVE_VERSION,
+            pid,
+            host,
+            timestamp,
+            tables: Default::default(),
+        }
+    }
+
+    /// Write the archive atomically: a temporary file is written and then renamed over `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let data = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let data = std::fs::read(path)?;
+        let archive: Self = serde_json::from_slice(&data).map_err(std::io::Error::other)?;
+        if archive.version > ARCHIVE_VERSION {
+            return Err(std::io::Error::other(format!(
+                "unsupported archive version {}, expected <= {ARCHIVE_VERSION}",
+                archive.version
+            )));
+        }
+        Ok(archive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Seq;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let mut archive = ProbeArchive::new(42, "localhost".to_string(), 1);
+        archive.tables.insert(
+            "python.loss".to_string(),
+            DataFrame::new(
+                vec!["step".to_string(), "loss".to_string()],
+                vec![Seq::SeqI64(vec![1, 2]), Seq::SeqF64(vec![0.5, 0.25])],
+            ),
+        );
+
+        let path = std::env::temp_dir().join(format!("probing-{}.probe", std::process::id()));
+        archive.save(&path).unwrap();
+        let loaded = ProbeArchive::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, archive);
+    }
+
+    #[test]
+    fn test_is_skipped() {
+        assert!(is_skipped("python.backtrace"));
+        assert!(is_skipped("external.worker_metrics"));
+        assert!(!is_skipped("python.stacks"));
+        assert!(!is_skipped("externals.table"));
+    }
+}
@@ -0,0 +1,70 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use super::extension::ExtensionStatus;
+use super::version::ProtocolVersion;
+
+/// What a probe was built with, as reported by `/apis/capabilities`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Capabilities {
+    /// Probe version
+    pub version: String,
+    pub protocol: ProtocolVersion,
+    /// Target architecture and OS, e.g. `x86_64-linux`
+    pub arch: String,
+    /// Enabled cargo features, prefixed by crate, e.g. `cc/kmsg`
+    pub features: Vec<String>,
+    pub extensions: Vec<ExtensionStatus>,
+    /// Version of the Python interpreter the probe runs in
+    pub python: Option<String>,
+}
+
+impl Capabilities {
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features
+            .iter()
+            .any(|f| f == feature || f.rsplit('/').next() == Some(feature))
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version:    {}", self.version)?;
+        writeln!(
+            f,
+            "protocol:   {}.{}.{}",
+            self.protocol.major, self.protocol.minor, self.protocol.patch
+        )?;
+        writeln!(f, "arch:       {}", self.arch)?;
+        if let Some(python) = &self.python {
+            writeln!(f, "python:     {python}")?;
+        }
+        writeln!(f, "features:   {}", self.features.join(", "))?;
+        let extensions = self
+            .extensions
+            .iter()
+            .map(|e| match e.enabled {
+                true => e.name.clone(),
+                false => format!("{} (disabled)", e.name),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "extensions: {}", extensions.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_feature() {
+        let caps = Capabilities {
+            features: vec!["cc/kmsg".to_string(), "python/tracing".to_string()],
+            ..Default::default()
+        };
+        assert!(caps.has_feature("kmsg"));
+        assert!(caps.has_feature("cc/kmsg"));
+        assert!(!caps.has_feature("taskstats"));
+    }
+}
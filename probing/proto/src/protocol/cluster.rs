@@ -1,8 +1,13 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+use super::status::ProbeStatus;
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
 
 pub struct Node {
     pub host: String,
@@ -22,6 +27,39 @@ pub struct Node {
 
     pub status: Option<String>,
     pub timestamp: u64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockSync>,
+
+    /// Labels of Ray/Dask/Celery workers, e.g. `framework`, `job_id`, `queue`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+
+    /// Hot metrics of the node when the heartbeat was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ProbeStatus>,
+}
+
+/// Clock exchange piggybacked on a heartbeat, in microseconds since the epoch.
+///
+/// The node sends the heartbeat at `sent` on its own clock, the master answers
+/// with a [`ClockReply`], and the node derives its offset to the master the
+/// NTP way. The estimate of the previous exchange travels with the next
+/// heartbeat so that the master knows the skew of every node.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct ClockSync {
+    pub sent: u64,
+    /// Master clock minus node clock
+    pub offset: Option<i64>,
+    /// Round trip of the exchange the offset was derived from
+    pub delay: Option<u64>,
+}
+
+/// Master timestamps of a heartbeat, returned by `PUT /apis/nodes`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct ClockReply {
+    pub received: u64,
+    pub replied: u64,
 }
 
 impl Display for Node {
@@ -45,7 +83,7 @@ impl Display for Node {
     }
 }
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Cluster {
     pub nodes: HashMap<String, Node>,     // 使用host:addr作为key
     pub rank_index: HashMap<i32, String>, // rank到节点key的映射
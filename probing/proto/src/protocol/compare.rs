@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{DataFrame, Ele, Seq};
+
+/// Column the rows of both sides are matched on
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AlignBy {
+    /// Training step, the `step` column
+    #[default]
+    Step,
+    /// Buckets of the `timestamp` column, relative to the start of the window
+    /// when a side has one
+    Time,
+}
+
+/// One side of a comparison
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct CompareSide {
+    /// `host:port` of the probe, the probe serving the request when unset
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Start of the window over the `timestamp` column, in microseconds
+    /// since epoch
+    #[serde(default)]
+    pub from: Option<i64>,
+    /// End of the window, excluded
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// Metrics of a table compared between two ranks, or between two windows of
+/// one rank
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct CompareRequest {
+    pub table: String,
+    pub metrics: Vec<String>,
+    #[serde(default)]
+    pub align: AlignBy,
+    /// Width of the time buckets in microseconds, rows of a bucket (or of a
+    /// step) are averaged
+    #[serde(default = "default_bucket")]
+    pub bucket: i64,
+    pub left: CompareSide,
+    pub right: CompareSide,
+}
+
+fn default_bucket() -> i64 {
+    1_000_000
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn as_f64(value: Ele) -> Option<f64> {
+    match value {
+        Ele::I32(x) => Some(x as f64),
+        Ele::I64(x) => Some(x as f64),
+        Ele::F32(x) => Some(x as f64),
+        Ele::F64(x) => Some(x),
+        Ele::DataTime(x) => Some(x as f64),
+        _ => None,
+    }
+}
+
+fn as_i64(value: Ele) -> Option<i64> {
+    match value {
+        Ele::I32(x) => Some(x as i64),
+        Ele::I64(x) => Some(x),
+        Ele::DataTime(x) => Some(x as i64),
+        Ele::F64(x) if x.is_finite() => Some(x as i64),
+        _ => None,
+    }
+}
+
+/// Sum and count of each metric per aligned key
+type Buckets = BTreeMap<i64, Vec<(f64, u64)>>;
+
+impl CompareRequest {
+    fn key_column(&self) -> &'static str {
+        match self.align {
+            AlignBy::Step => "step",
+            AlignBy::Time => "timestamp",
+        }
+    }
+
+    /// Reject names that cannot be used as is in the queries of [`Self::sql`]
+    pub fn validate(&self) -> Result<(), String> {
+        if !is_identifier(&self.table) {
+            return Err(format!("invalid table `{}`", self.table));
+        }
+        if self.metrics.is_empty() {
+            return Err("no metric to compare".to_string());
+        }
+        if let Some(metric) = self.metrics.iter().find(|m| !is_identifier(m)) {
+            return Err(format!("invalid metric `{metric}`"));
+        }
+        if self.bucket <= 0 {
+            return Err(format!("invalid bucket {}", self.bucket));
+        }
+        Ok(())
+    }
+
+    /// Query reading the rows of one side
+    pub fn sql(&self, side: &CompareSide) -> String {
+        let mut columns = vec![format!("\"{}\"", self.key_column())];
+        columns.extend(self.metrics.iter().map(|m| format!("\"{m}\"")));
+        let mut sql = format!("SELECT {} FROM {}", columns.join(", "), self.table);
+        let mut filters = vec![];
+        if let Some(from) = side.from {
+            filters.push(format!("timestamp >= {from}"));
+        }
+        if let Some(to) = side.to {
+            filters.push(format!("timestamp < {to}"));
+        }
+        if !filters.is_empty() {
+            sql.push_str(&format!(" WHERE {}", filters.join(" AND ")));
+        }
+        sql
+    }
+
+    fn buckets(&self, side: &CompareSide, df: &DataFrame) -> Result<Buckets, String> {
+        let column = |name: &str| {
+            df.names
+                .iter()
+                .position(|n| n == name)
+                .map(|idx| &df.cols[idx])
+                .ok_or_else(|| format!("no column `{name}` in {}", self.table))
+        };
+        let keys = column(self.key_column())?;
+        let metrics = self
+            .metrics
+            .iter()
+            .map(|m| column(m))
+            .collect::<Result<Vec<&Seq>, _>>()?;
+
+        let mut buckets = Buckets::new();
+        for row in 0..df.len() {
+            let Some(key) = as_i64(keys.get(row)) else {
+                continue;
+            };
+            let key = match self.align {
+                AlignBy::Step => key,
+                AlignBy::Time => {
+                    let offset = key - side.from.unwrap_or(0);
+                    offset.div_euclid(self.bucket) * self.bucket
+                }
+            };
+            let sums = buckets
+                .entry(key)
+                .or_insert_with(|| vec![(0.0, 0); metrics.len()]);
+            for (sum, metric) in sums.iter_mut().zip(metrics.iter()) {
+                if let Some(value) = as_f64(metric.get(row)).filter(|v| v.is_finite()) {
+                    sum.0 += value;
+                    sum.1 += 1;
+                }
+            }
+        }
+        Ok(buckets)
+    }
+
+    /// Join the rows of both sides on the aligned key, one row per key found
+    /// on both sides, with `<metric>_left`, `<metric>_right` and
+    /// `<metric>_diff` (right minus left) columns
+    pub fn join(&self, left: &DataFrame, right: &DataFrame) -> Result<DataFrame, String> {
+        let left = self.buckets(&self.left, left)?;
+        let right = self.buckets(&self.right, right)?;
+
+        let key_name = match self.align {
+            AlignBy::Step => "step",
+            AlignBy::Time if self.left.from.is_some() || self.right.from.is_some() => "offset",
+            AlignBy::Time => "timestamp",
+        };
+        let mut names = vec![key_name.to_string()];
+        for metric in self.metrics.iter() {
+            names.push(format!("{metric}_left"));
+            names.push(format!("{metric}_right"));
+            names.push(format!("{metric}_diff"));
+        }
+
+        let mean = |(sum, count): (f64, u64)| match count {
+            0 => f64::NAN,
+            n => sum / n as f64,
+        };
+        let mut keys = vec![];
+        let mut values = vec![vec![]; self.metrics.len() * 3];
+        for (key, a) in left.iter() {
+            let Some(b) = right.get(key) else {
+                continue;
+            };
+            keys.push(*key);
+            for (idx, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                let (a, b) = (mean(*a), mean(*b));
+                values[idx * 3].push(a);
+                values[idx * 3 + 1].push(b);
+                values[idx * 3 + 2].push(b - a);
+            }
+        }
+
+        let mut cols = vec![Seq::SeqI64(keys)];
+        cols.extend(values.into_iter().map(Seq::SeqF64));
+        Ok(DataFrame::new(names, cols))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(key: &str, keys: Vec<i64>, loss: Vec<f64>) -> DataFrame {
+        DataFrame::new(
+            vec![key.to_string(), "loss".to_string()],
+            vec![Seq::SeqI64(keys), Seq::SeqF64(loss)],
+        )
+    }
+
+    #[test]
+    fn test_compare_by_step() {
+        let request = CompareRequest {
+            table: "python.torch_trace".to_string(),
+            metrics: vec!["loss".to_string()],
+            bucket: default_bucket(),
+            ..Default::default()
+        };
+        assert!(request.validate().is_ok());
+        assert_eq!(
+            request.sql(&request.left),
+            "SELECT \"step\", \"loss\" FROM python.torch_trace"
+        );
+
+        let left = frame("step", vec![1, 1, 2, 3], vec![1.0, 3.0, 1.0, 5.0]);
+        let right = frame("step", vec![1, 2, 4], vec![4.0, 2.0, 0.0]);
+        let df = request.join(&left, &right).unwrap();
+        assert_eq!(df.names, ["step", "loss_left", "loss_right", "loss_diff"]);
+        assert_eq!(df.cols[0], Seq::SeqI64(vec![1, 2]));
+        assert_eq!(df.cols[1], Seq::SeqF64(vec![2.0, 1.0]));
+        assert_eq!(df.cols[3], Seq::SeqF64(vec![2.0, 1.0]));
+    }
+
+    #[test]
+    fn test_compare_windows() {
+        let request = CompareRequest {
+            table: "t".to_string(),
+            metrics: vec!["loss".to_string()],
+            align: AlignBy::Time,
+            bucket: 10,
+            left: CompareSide {
+                from: Some(100),
+                to: Some(200),
+                ..Default::default()
+            },
+            right: CompareSide {
+                from: Some(1000),
+                ..Default::default()
+            },
+        };
+        assert_eq!(
+            request.sql(&request.left),
+            "SELECT \"timestamp\", \"loss\" FROM t WHERE timestamp >= 100 AND timestamp < 200"
+        );
+
+        let left = frame("timestamp", vec![100, 105, 120], vec![1.0, 2.0, 3.0]);
+        let right = frame("timestamp", vec![1001, 1015, 1020], vec![2.0, 9.0, 5.0]);
+        let df = request.join(&left, &right).unwrap();
+        assert_eq!(df.names[0], "offset");
+        assert_eq!(df.cols[0], Seq::SeqI64(vec![0, 20]));
+        assert_eq!(df.cols[3], Seq::SeqF64(vec![0.5, 2.0]));
+
+        let invalid = CompareRequest {
+            metrics: vec!["loss; DROP".to_string()],
+            ..request
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
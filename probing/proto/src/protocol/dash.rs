@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use super::flamegraph::FoldedStack;
+use super::status::ProbeStatus;
+
+/// Function taking samples of the profiler at the top of its stacks
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct HotFunction {
+    /// Frame as the profiler names it, e.g. `forward (model.py:42)`
+    pub name: String,
+    /// Samples with the function innermost
+    pub samples: u64,
+    /// Fraction of all samples, 0 to 1
+    pub share: f64,
+}
+
+impl HotFunction {
+    /// The `limit` functions with the most self samples in `stacks`
+    pub fn top(stacks: &[FoldedStack], limit: usize) -> Vec<Self> {
+        let mut samples = std::collections::HashMap::<&str, u64>::new();
+        let mut total = 0;
+        for stack in stacks {
+            if let Some(leaf) = stack.frames.last() {
+                *samples.entry(leaf.as_str()).or_default() += stack.weight;
+                total += stack.weight;
+            }
+        }
+        let mut top = samples
+            .into_iter()
+            .map(|(name, samples)| HotFunction {
+                name: name.to_string(),
+                samples,
+                share: samples as f64 / total.max(1) as f64,
+            })
+            .collect::<Vec<_>>();
+        top.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.name.cmp(&b.name)));
+        top.truncate(limit);
+        top
+    }
+}
+
+/// Trace span that ended with an error, e.g. a Python exception raised out
+/// of a traced function
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct FailedSpan {
+    pub name: String,
+    /// Error recorded with the span, e.g. the `repr` of the exception
+    pub message: Option<String>,
+    /// End of the span, in microseconds since the epoch
+    pub time: i64,
+}
+
+/// Span still open on a thread of the probed process
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ActiveSpan {
+    pub thread: String,
+    pub name: String,
+    /// Spans enclosing this one on its thread
+    pub depth: usize,
+    /// Seconds since the span began
+    pub elapsed: f64,
+}
+
+/// Everything the live panels of `probing dash` show, returned by
+/// `/apis/dash` in one reply so a terminal needs one request per refresh
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct DashFrame {
+    pub status: ProbeStatus,
+    /// Most sampled functions of the running profiler, empty when none runs
+    pub functions: Vec<HotFunction>,
+    /// Last spans that failed, newest first
+    pub failures: Vec<FailedSpan>,
+    /// Open spans of every thread, outermost first
+    pub spans: Vec<ActiveSpan>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_functions() {
+        let stacks = [
+            "main;train;forward 30",
+            "main;train;backward 50",
+            "main;load;forward 20",
+            "main 0",
+        ]
+        .iter()
+        .filter_map(|line| FoldedStack::parse(line))
+        .collect::<Vec<_>>();
+
+        let top = HotFunction::top(&stacks, 2);
+        assert_eq!(top.len(), 2);
+        assert_eq!((top[0].name.as_str(), top[0].samples), ("backward", 50));
+        assert_eq!((top[1].name.as_str(), top[1].samples), ("forward", 50));
+        assert!((top[1].share - 0.5).abs() < 1e-9);
+    }
+}
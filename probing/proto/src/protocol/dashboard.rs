@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::ProtoError;
+
+/// How the result of a panel query is rendered
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartType {
+    #[default]
+    Table,
+    Line,
+}
+
+/// One query of a dashboard and how to show its result
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct DashboardPanel {
+    pub title: String,
+    pub query: String,
+    #[serde(default)]
+    pub chart: ChartType,
+    /// X axis column of a line chart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    /// Y axis columns of a line chart
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub y: Vec<String>,
+}
+
+/// A named set of queries forming a standard diagnostic view, e.g. for one
+/// training framework.
+///
+/// ```json
+/// {
+///   "name": "megatron",
+///   "title": "Megatron step health",
+///   "framework": "megatron",
+///   "panels": [
+///     {"title": "Ranks", "query": "SELECT * FROM cluster.nodes"},
+///     {"title": "Loss", "query": "SELECT step, loss FROM python.metrics",
+///      "chart": "line", "x": "step", "y": ["loss"]}
+///   ]
+/// }
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Dashboard {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub framework: Option<String>,
+    #[serde(default)]
+    pub panels: Vec<DashboardPanel>,
+}
+
+impl Dashboard {
+    /// Check that the name can be used in a URL and every panel has a query
+    pub fn validate(&self) -> Result<(), ProtoError> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(ProtoError::InvalidDashboard(format!(
+                "invalid name {:?}, use letters, digits, '_', '-' or '.'",
+                self.name
+            )));
+        }
+        if let Some(panel) = self.panels.iter().find(|p| p.query.trim().is_empty()) {
+            return Err(ProtoError::InvalidDashboard(format!(
+                "panel {:?} has no query",
+                panel.title
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dashboard_defaults() {
+        let dashboard: Dashboard = serde_json::from_str(
+            r#"{"name": "ddp", "panels": [
+                {"title": "nodes", "query": "SELECT * FROM cluster.nodes"},
+                {"title": "loss", "query": "SELECT 1", "chart": "line", "x": "step"}
+            ]}"#,
+        )
+        .unwrap();
+        assert!(dashboard.validate().is_ok());
+        assert_eq!(dashboard.panels[0].chart, ChartType::Table);
+        assert_eq!(dashboard.panels[1].chart, ChartType::Line);
+        assert_eq!(dashboard.panels[1].x.as_deref(), Some("step"));
+
+        let bad = Dashboard {
+            name: "a/b".to_string(),
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+}
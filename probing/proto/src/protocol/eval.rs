@@ -0,0 +1,128 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Outcome of evaluating code in the target process, as returned by
+/// `/apis/pythonext/eval?format=json`.
+///
+/// Output printed by the code is kept apart from the value of its last
+/// expression and from the exception it raised, if any.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct EvalResult {
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    /// Value of the trailing expression, unless it is `None`
+    #[serde(default)]
+    pub value: Option<EvalValue>,
+    #[serde(default)]
+    pub error: Option<EvalError>,
+}
+
+/// `repr()` of a value, cut to a maximum length
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct EvalValue {
+    pub repr: String,
+    /// Qualified type name, e.g. `torch.Tensor`
+    pub type_name: String,
+    /// Whether `repr` was cut
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Exception raised by the evaluated code
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct EvalError {
+    pub type_name: String,
+    pub message: String,
+    /// Formatted traceback, one entry per frame as printed by Python
+    #[serde(default)]
+    pub traceback: Vec<String>,
+}
+
+/// One top-level statement of a script run by
+/// `/apis/pythonext/script`, with what it printed and returned
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ScriptStep {
+    /// Line of the script the statement starts at
+    pub line: usize,
+    pub source: String,
+    pub result: EvalResult,
+}
+
+/// A REPL and eval session saved in the target, as listed by
+/// `/apis/pythonext/sessions`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct SessionMeta {
+    pub name: String,
+    /// Start of the session in microseconds since epoch
+    pub timestamp: u64,
+    /// Statements recorded, failed ones included
+    pub statements: usize,
+}
+
+impl EvalResult {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// A result carrying only an error, for failures outside the evaluated code
+    pub fn failure<T: Into<String>, M: Into<String>>(type_name: T, message: M) -> Self {
+        Self {
+            error: Some(EvalError {
+                type_name: type_name.into(),
+                message: message.into(),
+                traceback: vec![],
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.traceback {
+            write!(f, "{line}")?;
+        }
+        if self.traceback.is_empty() {
+            write!(f, "{}: {}", self.type_name, self.message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_result_from_python() {
+        let text = r#"{"stdout": "hi\n", "stderr": "", "value": null,
+            "error": {"type_name": "ZeroDivisionError", "message": "division by zero",
+                      "traceback": ["Traceback (most recent call last):\n"]}}"#;
+        let result = serde_json::from_str::<EvalResult>(text).unwrap();
+        assert!(!result.is_ok());
+        assert_eq!(result.stdout, "hi\n");
+        assert_eq!(result.error.unwrap().type_name, "ZeroDivisionError");
+
+        let value = serde_json::from_str::<EvalResult>(
+            r#"{"value": {"repr": "[1, 2]", "type_name": "list"}}"#,
+        )
+        .unwrap();
+        assert!(value.is_ok());
+        assert!(!value.value.unwrap().truncated);
+    }
+
+    #[test]
+    fn test_script_steps_from_python() {
+        let text = r#"[{"line": 1, "source": "x = 1",
+            "result": {"stdout": "", "stderr": "", "value": null, "error": null}},
+            {"line": 3, "source": "x + 1",
+            "result": {"value": {"repr": "2", "type_name": "int"}}}]"#;
+        let steps = serde_json::from_str::<Vec<ScriptStep>>(text).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].line, 3);
+        assert!(steps.iter().all(|step| step.result.is_ok()));
+    }
+}
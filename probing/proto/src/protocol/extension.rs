@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// Runtime state of an engine extension, as reported by `/apis/extensions`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct ExtensionStatus {
+    pub name: String,
+    pub enabled: bool,
+}
+
+/// An option of an extension with its metadata, as reported by
+/// `/apis/extensions/options`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct OptionStatus {
+    pub key: String,
+    pub value: Option<String>,
+    pub help: String,
+    /// Changes only take effect once the extension is enabled again
+    pub requires_restart: bool,
+    /// Option that must be set before this one
+    pub depends_on: Option<String>,
+    /// The last change waits for the extension to be enabled again
+    pub restart_pending: bool,
+}
+
+/// A change of an option, checked by `POST /apis/extensions/options/validate`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct OptionChange {
+    pub key: String,
+    pub value: String,
+}
+
+/// Whether a probe would accept an [`OptionChange`]
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct OptionCheck {
+    pub key: String,
+    /// Value before the change, to restore if it must be undone
+    pub current: Option<String>,
+    /// Why the change would be rejected
+    pub error: Option<String>,
+}
+
+/// One check of an extension self-test, e.g. whether taskstats may be read
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct SelfCheck {
+    pub name: String,
+    pub passed: bool,
+    /// What was found, or why the check failed and how to fix it
+    pub detail: String,
+}
+
+impl SelfCheck {
+    pub fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            passed: true,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn fail(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Result of `POST /apis/extensions/<name>/selftest`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct SelfTest {
+    pub extension: String,
+    pub checks: Vec<SelfCheck>,
+}
+
+impl SelfTest {
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// One collapsed stack of a flamegraph, outermost frame first.
+///
+/// Its [`Display`] form is the folded format (`outer;...;inner weight`)
+/// understood by inferno, speedscope and flamegraph.pl, so the stacks of
+/// several ranks can be concatenated and rendered as one flamegraph.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct FoldedStack {
+    pub frames: Vec<String>,
+    pub weight: u64,
+}
+
+impl FoldedStack {
+    /// Parse a folded line; frames may contain spaces, the weight is the last word
+    pub fn parse(line: &str) -> Option<Self> {
+        let (stack, weight) = line.trim().rsplit_once(' ')?;
+        Some(Self {
+            frames: stack.split(';').map(|f| f.to_string()).collect(),
+            weight: weight.parse().ok()?,
+        })
+    }
+}
+
+impl Display for FoldedStack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.frames.join(";"), self.weight)
+    }
+}
+
+/// Node of a treemap of the samples, the nested `name`/`value`/`children`
+/// shape taken by d3-hierarchy and ECharts; `value` is the total weight of
+/// the node, children included.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct TreemapNode {
+    pub name: String,
+    pub value: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreemapNode>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folded_stack_roundtrip() {
+        let line = "MainThread;train (train.py:10);forward (model.py:42) 250";
+        let stack = FoldedStack::parse(line).unwrap();
+        assert_eq!(stack.frames.len(), 3);
+        assert_eq!(stack.frames[2], "forward (model.py:42)");
+        assert_eq!(stack.weight, 250);
+        assert_eq!(stack.to_string(), line);
+
+        assert!(FoldedStack::parse("no-weight").is_none());
+    }
+}
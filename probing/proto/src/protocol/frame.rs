@@ -0,0 +1,83 @@
+//! Compact binary framing for local clients.
+//!
+//! Each frame is a 4-byte big-endian payload length followed by the bincode
+//! encoding of a [`Message`]. The framing is used on the local Unix socket so
+//! that high-frequency pollers skip HTTP and JSON entirely.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::message::Message;
+use crate::types::ProtoError;
+
+/// Size of the length prefix in bytes
+pub const FRAME_HEADER_SIZE: usize = 4;
+
+/// Upper bound for a single frame payload (64 MiB)
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Encode a message into a length-prefixed frame
+pub fn encode_frame<T: Serialize>(message: &Message<T>) -> Result<Vec<u8>, ProtoError> {
+    let payload =
+        bincode::serialize(message).map_err(|e| ProtoError::SerializationError(e.to_string()))?;
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(ProtoError::SerializationError(format!(
+            "frame too large: {} bytes",
+            payload.len()
+        )));
+    }
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// Parse the payload length from a frame header
+pub fn frame_len(header: [u8; FRAME_HEADER_SIZE]) -> Result<usize, ProtoError> {
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(ProtoError::DeserializationError(format!(
+            "frame too large: {len} bytes"
+        )));
+    }
+    Ok(len)
+}
+
+/// Decode a frame payload (without the length prefix) into a message
+pub fn decode_frame<T: DeserializeOwned>(payload: &[u8]) -> Result<Message<T>, ProtoError> {
+    bincode::deserialize(payload).map_err(|e| ProtoError::DeserializationError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::query::{Data, Query};
+    use crate::types::{DataFrame, Seq};
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let query = Message::new(Query::new("select 1".to_string()));
+        let frame = encode_frame(&query).unwrap();
+        let len = frame_len(frame[..FRAME_HEADER_SIZE].try_into().unwrap()).unwrap();
+        assert_eq!(len, frame.len() - FRAME_HEADER_SIZE);
+
+        let decoded: Message<Query> = decode_frame(&frame[FRAME_HEADER_SIZE..]).unwrap();
+        assert_eq!(decoded.payload.expr, "select 1");
+        assert_eq!(decoded.timestamp, query.timestamp);
+
+        let df = DataFrame::new(
+            vec!["a".to_string()],
+            vec![Seq::SeqI64(vec![1, 2, 3])],
+        );
+        let reply = encode_frame(&Message::new(Data::DataFrame(df))).unwrap();
+        let decoded: Message<Data> = decode_frame(&reply[FRAME_HEADER_SIZE..]).unwrap();
+        match decoded.payload {
+            Data::DataFrame(df) => assert_eq!(df.len(), 3),
+            _ => panic!("expected a dataframe"),
+        }
+    }
+
+    #[test]
+    fn test_frame_len_limit() {
+        assert!(frame_len(u32::MAX.to_be_bytes()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Handshake between a client and a probe, exchanged by `POST
+//! /apis/handshake` before the first request.
+//!
+//! The protocol version tells whether the peers can talk at all; the features
+//! tell which optional parts of the protocol the probe implements, so that a
+//! newer client can avoid them on an older probe instead of failing on a reply
+//! it cannot decode.
+
+use serde::{Deserialize, Serialize};
+
+use super::version::ProtocolVersion;
+
+/// Queries run under an id and can be cancelled with `DELETE /query/<id>`
+pub const FEATURE_QUERY_CANCEL: &str = "query.cancel";
+/// `DESCRIBE <table>` reports units and descriptions
+pub const FEATURE_QUERY_DESCRIBE: &str = "query.describe";
+/// `/apis/pythonext/eval?format=json` returns an `EvalResult`
+pub const FEATURE_EVAL_JSON: &str = "eval.json";
+/// Framed queries on the `probing-<pid>.bin` socket
+pub const FEATURE_BINARY_FRAME: &str = "binary.frame";
+/// `/apis/pythonext/tensor` exports tensors as raw bytes
+pub const FEATURE_TENSOR_EXPORT: &str = "tensor.export";
+/// `/apis/pythonext/threads` returns per-thread stacks, native-only for
+/// threads without Python state
+pub const FEATURE_THREAD_STACKS: &str = "stack.threads";
+/// `/apis/status` returns the hot metrics of the probe as a `ProbeStatus`
+pub const FEATURE_STATUS: &str = "status";
+/// Queries accept `opts.codecs` and reply with per-column compressed tables
+pub const FEATURE_QUERY_CODECS: &str = "query.codecs";
+/// `/apis/killswitch` puts the probe into a dormant state and back
+pub const FEATURE_KILL_SWITCH: &str = "killswitch";
+/// `/apis/resources` reports what the probe takes as a `ProbeResources`
+pub const FEATURE_RESOURCES: &str = "resources";
+/// `/apis/extensions/options` lists the options with their metadata as `OptionStatus`
+pub const FEATURE_OPTION_STATUS: &str = "config.options";
+/// `/apis/dash` returns the live panels of `probing dash` as a `DashFrame`
+pub const FEATURE_DASH: &str = "dash";
+
+/// `/apis/pythonext/script` runs scripts statement by statement, and REPL and
+/// eval sessions are recorded and saved by `/apis/pythonext/session`
+pub const FEATURE_EVAL_SCRIPT: &str = "eval.script";
+
+/// `/apis/extensions/options/validate` checks an option change without applying it
+pub const FEATURE_OPTION_VALIDATE: &str = "config.validate";
+
+/// `/apis/export` pushes a snapshot of tables to the store or a parquet file
+pub const FEATURE_TABLE_EXPORT: &str = "table.export";
+
+/// `/apis/extensions/<name>/selftest` checks what an extension needs from the host
+pub const FEATURE_SELFTEST: &str = "extensions.selftest";
+
+/// Protocol features implemented by this build
+pub const FEATURES: &[&str] = &[
+    FEATURE_QUERY_CANCEL,
+    FEATURE_QUERY_DESCRIBE,
+    FEATURE_EVAL_JSON,
+    FEATURE_BINARY_FRAME,
+    FEATURE_TENSOR_EXPORT,
+    FEATURE_THREAD_STACKS,
+    FEATURE_STATUS,
+    FEATURE_QUERY_CODECS,
+    FEATURE_KILL_SWITCH,
+    FEATURE_RESOURCES,
+    FEATURE_OPTION_STATUS,
+    FEATURE_DASH,
+    FEATURE_EVAL_SCRIPT,
+    FEATURE_OPTION_VALIDATE,
+    FEATURE_TABLE_EXPORT,
+    FEATURE_SELFTEST,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Handshake {
+    pub protocol: ProtocolVersion,
+    /// Version of the probing build
+    pub version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl Handshake {
+    /// Handshake of this build
+    pub fn current(version: &str) -> Self {
+        Self {
+            protocol: ProtocolVersion::current(),
+            version: version.to_string(),
+            features: FEATURES.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    /// Peer that predates the handshake, assumed to implement no optional
+    /// feature
+    pub fn legacy() -> Self {
+        Self {
+            protocol: ProtocolVersion {
+                major: 0,
+                minor: 1,
+                patch: 0,
+            },
+            version: "unknown".to_string(),
+            features: vec![],
+        }
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Refuse to talk to `peer` if its protocol is not compatible
+    pub fn check(&self, peer: &Handshake) -> Result<(), String> {
+        if self.protocol.is_compatible_with(&peer.protocol) {
+            return Ok(());
+        }
+        Err(format!(
+            "probe speaks protocol {} (probing {}) but this client speaks {} (probing {}); \
+             use a client matching the probe",
+            peer.protocol, peer.version, self.protocol, self.version
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_and_features() {
+        let client = Handshake::current("0.3.0");
+        let legacy = Handshake::legacy();
+        assert!(client.check(&legacy).is_ok());
+        assert!(!legacy.supports(FEATURE_QUERY_CANCEL));
+        assert!(client.supports(FEATURE_QUERY_CANCEL));
+
+        let future = Handshake {
+            protocol: ProtocolVersion {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            },
+            ..client.clone()
+        };
+        let err = client.check(&future).unwrap_err();
+        assert!(err.contains("protocol 1.0.0"), "{err}");
+
+        // features are optional on the wire
+        let peer: Handshake = serde_json::from_str(
+            r#"{"protocol": {"major": 0, "minor": 2, "patch": 0}, "version": "0.2.0"}"#,
+        )
+        .unwrap();
+        assert!(peer.features.is_empty());
+    }
+}
@@ -1,5 +1,20 @@
+pub mod archive;
+pub mod capabilities;
 pub mod cluster;
+pub mod compare;
+pub mod dash;
+pub mod dashboard;
+pub mod eval;
+pub mod extension;
+pub mod flamegraph;
+pub mod handshake;
+#[cfg(feature = "binary")]
+pub mod frame;
 pub mod message;
 pub mod process;
 pub mod query;
+pub mod resources;
+pub mod snapshot;
+pub mod status;
+pub mod table;
 pub mod version;
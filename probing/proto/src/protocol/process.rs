@@ -60,3 +60,66 @@ impl Display for CallFrame {
         }
     }
 }
+
+/// What a thread was running when its stack was captured
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum StackKind {
+    /// Native frames interleaved with the frames of the Python interpreter
+    #[default]
+    Python,
+    /// A thread without Python state, e.g. the NCCL watchdog or an IO thread
+    Native,
+}
+
+impl Display for StackKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackKind::Python => write!(f, "python"),
+            StackKind::Native => write!(f, "native"),
+        }
+    }
+}
+
+/// Call stack of a single thread of the target
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct ThreadStack {
+    pub tid: i32,
+    pub name: String,
+    pub kind: StackKind,
+    /// Innermost frame first
+    pub frames: Vec<CallFrame>,
+}
+
+impl Display for ThreadStack {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Thread {} ({}) kind={}", self.tid, self.name, self.kind)?;
+        for frame in &self.frames {
+            write!(f, "{frame}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_stack_kind_marker() {
+        let stack = ThreadStack {
+            tid: 42,
+            name: "nccl-watchdog".to_string(),
+            kind: StackKind::Native,
+            frames: vec![CallFrame::CFrame {
+                ip: "0x7f00".to_string(),
+                file: String::new(),
+                func: "pthread_cond_wait".to_string(),
+                lineno: 0,
+            }],
+        };
+        let json = serde_json::to_value(&stack).unwrap();
+        assert_eq!(json["kind"], "native");
+        assert_eq!(serde_json::from_value::<ThreadStack>(json).unwrap(), stack);
+    }
+}
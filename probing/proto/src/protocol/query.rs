@@ -2,11 +2,19 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
-use crate::types::{DataFrame, TimeSeries};
+use crate::types::{Codec, CompressedFrame, DataFrame, TimeSeries};
 
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub struct Options {
     pub limit: Option<usize>,
+    /// Id to run the query under, see `DELETE /query/<id>`; one is
+    /// generated by the server when unset
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Codecs the client can decode; when set, a table is returned as
+    /// `Data::Compressed` with a codec chosen per column
+    #[serde(default)]
+    pub codecs: Vec<Codec>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
@@ -19,6 +27,18 @@ impl Query {
     pub fn new(expr: String) -> Self {
         Self { expr, opts: None }
     }
+
+    /// Run the query under `id` so that it can be cancelled
+    pub fn with_id(mut self, id: String) -> Self {
+        self.opts.get_or_insert_with(Default::default).id = Some(id);
+        self
+    }
+
+    /// Ask for a reply compressed with any of `codecs`
+    pub fn with_codecs(mut self, codecs: Vec<Codec>) -> Self {
+        self.opts.get_or_insert_with(Default::default).codecs = codecs;
+        self
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
@@ -28,6 +48,15 @@ pub enum Data {
     Error(QueryError),
     DataFrame(DataFrame),
     TimeSeries(TimeSeries),
+    Compressed(CompressedFrame),
+}
+
+/// Rows appended to a time-series table since a sequence number
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct TableTail {
+    /// Sequence number to pass as `since` in the next request
+    pub next: u64,
+    pub df: DataFrame,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -46,6 +75,7 @@ pub enum ErrorCode {
     PermissionDenied,
     NotFound,
     Internal,
+    Cancelled,
 }
 
 impl Display for QueryError {
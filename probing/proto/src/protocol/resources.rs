@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Resources the probe itself takes in its process, as reported by
+/// `/apis/resources` and shown by `probing list -vv`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ProbeResources {
+    pub pid: u32,
+    /// CPU time of the probe threads since the probe started, in percent of
+    /// one core
+    pub cpu: Option<f64>,
+    /// Memory held by the in-memory tables of the probe
+    pub buffer_bytes: u64,
+    /// Background threads of the probe without the `probing-` prefix, e.g.
+    /// `hot-met` for `probing-hot-metrics` as the kernel keeps 15 characters
+    /// of thread names
+    pub collectors: Vec<String>,
+    /// Address of the TCP server, if the probe serves one
+    pub address: Option<String>,
+    /// Seconds since the probe started
+    pub uptime: f64,
+}
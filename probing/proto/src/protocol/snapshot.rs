@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Point-in-time capture of a probe's environment: environment variables,
+/// engine settings, loaded libraries and installed Python packages.
+///
+/// Each section maps a key (variable name, setting name, library path or
+/// package name) to its value, so two snapshots can be compared with
+/// [`EnvSnapshot::diff`].
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct EnvSnapshot {
+    pub name: String,
+    /// Capture time in microseconds since UNIX epoch
+    pub timestamp: u64,
+    pub sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// One changed key between two snapshots
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct SnapshotChange {
+    pub section: String,
+    pub key: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Display for SnapshotChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.before, &self.after) {
+            (None, Some(after)) => write!(f, "+ [{}] {} = {}", self.section, self.key, after),
+            (Some(before), None) => write!(f, "- [{}] {} = {}", self.section, self.key, before),
+            (Some(before), Some(after)) => write!(
+                f,
+                "~ [{}] {}: {} -> {}",
+                self.section, self.key, before, after
+            ),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl EnvSnapshot {
+    pub fn new(name: String, timestamp: u64) -> Self {
+        Self {
+            name,
+            timestamp,
+            sections: Default::default(),
+        }
+    }
+
+    /// Changes needed to go from `self` to `other`, ordered by section and key
+    pub fn diff(&self, other: &EnvSnapshot) -> Vec<SnapshotChange> {
+        let empty = BTreeMap::new();
+        let mut sections = self.sections.keys().collect::<Vec<_>>();
+        sections.extend(other.sections.keys());
+        sections.sort();
+        sections.dedup();
+
+        let mut changes = vec![];
+        for section in sections {
+            let before = self.sections.get(section).unwrap_or(&empty);
+            let after = other.sections.get(section).unwrap_or(&empty);
+
+            let mut keys = before.keys().collect::<Vec<_>>();
+            keys.extend(after.keys());
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let (b, a) = (before.get(key), after.get(key));
+                if b != a {
+                    changes.push(SnapshotChange {
+                        section: section.clone(),
+                        key: key.clone(),
+                        before: b.cloned(),
+                        after: a.cloned(),
+                    });
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(name: &str, envs: &[(&str, &str)]) -> EnvSnapshot {
+        let mut snapshot = EnvSnapshot::new(name.to_string(), 0);
+        snapshot.sections.insert(
+            "envs".to_string(),
+            envs.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+        snapshot
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let a = snapshot("a", &[("OMP_NUM_THREADS", "8"), ("HOME", "/root")]);
+        let b = snapshot("b", &[("OMP_NUM_THREADS", "1"), ("NCCL_DEBUG", "INFO")]);
+
+        let changes = a.diff(&b);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].to_string(), "- [envs] HOME = /root");
+        assert_eq!(changes[1].to_string(), "+ [envs] NCCL_DEBUG = INFO");
+        assert_eq!(changes[2].to_string(), "~ [envs] OMP_NUM_THREADS: 8 -> 1");
+        assert!(a.diff(&a).is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Hot metrics of a probed process, as reported by `/apis/status` or read
+/// from its shared-memory segment by `probing <pid> status --fast`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct ProbeStatus {
+    pub pid: u32,
+    /// Optimizer steps taken so far
+    pub step: Option<u64>,
+    /// Last loss published by the training loop
+    pub loss: Option<f64>,
+    /// CPU usage of the process, in percent of one core
+    pub cpu: Option<f64>,
+    /// GPU utilization, in percent
+    pub gpu_util: Option<f64>,
+    /// Time of the last update, in microseconds since the epoch
+    pub updated: Option<i64>,
+}
+
+impl Display for ProbeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn or_dash<T: Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "-".to_string(), |v| v.to_string())
+        }
+        writeln!(f, "pid:      {}", self.pid)?;
+        writeln!(f, "step:     {}", or_dash(self.step))?;
+        writeln!(
+            f,
+            "loss:     {}",
+            or_dash(self.loss.map(|v| format!("{v:.4}")))
+        )?;
+        writeln!(
+            f,
+            "cpu:      {}",
+            or_dash(self.cpu.map(|v| format!("{v:.1}%")))
+        )?;
+        writeln!(
+            f,
+            "gpu util: {}",
+            or_dash(self.gpu_util.map(|v| format!("{v:.0}%")))
+        )?;
+        let age = self.updated.map(|updated| {
+            let age = chrono::Utc::now().timestamp_micros() - updated;
+            format!("{:.1}s ago", age.max(0) as f64 / 1e6)
+        });
+        write!(f, "updated:  {}", or_dash(age))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_unset_metrics() {
+        let status = ProbeStatus {
+            pid: 42,
+            step: Some(1200),
+            cpu: Some(97.3),
+            ..Default::default()
+        };
+        assert_eq!(
+            status.to_string(),
+            "pid:      42\nstep:     1200\nloss:     -\ncpu:      97.3%\ngpu util: -\nupdated:  -"
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// A column of a probe table, as returned by `DESCRIBE` and `/apis/tables`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct ColumnDoc {
+    pub name: String,
+    /// Arrow data type, e.g. `Int64` or `Timestamp(Microsecond, None)`
+    pub data_type: String,
+    pub nullable: bool,
+    /// Unit of the values, e.g. `bytes` or `us`
+    pub unit: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A probe table and its columns, as listed by `/apis/tables`
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq, Clone)]
+pub struct TableDoc {
+    /// Qualified name, e.g. `process.limits`
+    pub name: String,
+    pub description: Option<String>,
+    /// Empty for tables of dynamic namespaces, which are only resolved by
+    /// `DESCRIBE <table>`
+    pub columns: Vec<ColumnDoc>,
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
 /// Protocol version information
@@ -13,11 +15,13 @@ pub struct ProtocolVersion {
 
 impl Default for ProtocolVersion {
     fn default() -> Self {
-        Self {
-            major: 0,
-            minor: 1,
-            patch: 0,
-        }
+        Self::current()
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
     }
 }
 
@@ -28,7 +32,11 @@ impl ProtocolVersion {
     }
 
     /// Get the current protocol version
-    pub fn current() -> Self {
-        Self::default()
+    pub const fn current() -> Self {
+        Self {
+            major: 0,
+            minor: 2,
+            patch: 0,
+        }
     }
 }
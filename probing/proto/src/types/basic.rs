@@ -154,6 +154,19 @@ pub enum Seq {
 }
 
 impl Seq {
+    pub fn dtype(&self) -> EleType {
+        match self {
+            Seq::Nil => EleType::Nil,
+            Seq::SeqBOOL(_) => EleType::BOOL,
+            Seq::SeqI32(_) => EleType::I32,
+            Seq::SeqI64(_) => EleType::I64,
+            Seq::SeqF32(_) => EleType::F32,
+            Seq::SeqF64(_) => EleType::F64,
+            Seq::SeqText(_) => EleType::Text,
+            Seq::SeqDateTime(_) => EleType::DataTime,
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
             Seq::SeqBOOL(vec) => vec.len(),
@@ -218,6 +231,46 @@ impl Seq {
         .unwrap_or(Ele::Nil)
     }
 
+    /// Values at the positions where `keep` is true; positions past the end
+    /// of `keep` are dropped
+    pub fn filter(&self, keep: &[bool]) -> Seq {
+        fn pick<T: Clone>(values: &[T], keep: &[bool]) -> Vec<T> {
+            values
+                .iter()
+                .zip(keep)
+                .filter(|(_, keep)| **keep)
+                .map(|(v, _)| v.clone())
+                .collect()
+        }
+        match self {
+            Seq::Nil => Seq::Nil,
+            Seq::SeqBOOL(vec) => Seq::SeqBOOL(pick(vec, keep)),
+            Seq::SeqI32(vec) => Seq::SeqI32(pick(vec, keep)),
+            Seq::SeqI64(vec) => Seq::SeqI64(pick(vec, keep)),
+            Seq::SeqF32(vec) => Seq::SeqF32(pick(vec, keep)),
+            Seq::SeqF64(vec) => Seq::SeqF64(pick(vec, keep)),
+            Seq::SeqText(vec) => Seq::SeqText(pick(vec, keep)),
+            Seq::SeqDateTime(vec) => Seq::SeqDateTime(pick(vec, keep)),
+        }
+    }
+
+    /// Append all values of `other`, which must hold the same type
+    pub fn extend(&mut self, other: &Seq) -> Result<(), ProtoError> {
+        match (&mut *self, other) {
+            (_, Seq::Nil) => {}
+            (Seq::Nil, other) => *self = other.clone(),
+            (Seq::SeqBOOL(vec), Seq::SeqBOOL(more)) => vec.extend_from_slice(more),
+            (Seq::SeqI32(vec), Seq::SeqI32(more)) => vec.extend_from_slice(more),
+            (Seq::SeqI64(vec), Seq::SeqI64(more)) => vec.extend_from_slice(more),
+            (Seq::SeqF32(vec), Seq::SeqF32(more)) => vec.extend_from_slice(more),
+            (Seq::SeqF64(vec), Seq::SeqF64(more)) => vec.extend_from_slice(more),
+            (Seq::SeqText(vec), Seq::SeqText(more)) => vec.extend_from_slice(more),
+            (Seq::SeqDateTime(vec), Seq::SeqDateTime(more)) => vec.extend_from_slice(more),
+            _ => return Err(ProtoError::WrongSequenceType),
+        }
+        Ok(())
+    }
+
     pub fn append(&mut self, value: impl Into<Ele>) -> Result<(), ProtoError> {
         let value = value.into();
         match (&mut *self, value) {
@@ -309,4 +362,16 @@ mod tests {
         assert_eq!(seq.len(), 0);
         assert!(seq.is_empty());
     }
+
+    #[test]
+    fn test_seq_filter_and_extend() {
+        let seq = Seq::SeqI64(vec![1, 2, 3]);
+        assert_eq!(seq.filter(&[true, false, true]), Seq::SeqI64(vec![1, 3]));
+
+        let mut seq = Seq::Nil;
+        assert!(seq.extend(&Seq::SeqText(vec!["a".to_string()])).is_ok());
+        assert!(seq.extend(&Seq::SeqText(vec!["b".to_string()])).is_ok());
+        assert_eq!(seq, Seq::SeqText(vec!["a".to_string(), "b".to_string()]));
+        assert!(seq.extend(&Seq::SeqI64(vec![1])).is_err());
+    }
 }
@@ -0,0 +1,571 @@
+//! Column codecs, chosen per column.
+//!
+//! [`Codec::Pco`] is the general purpose default. Integer-like columns are
+//! often smaller with [`Codec::Delta`] (zigzag deltas bit-packed per block),
+//! float series with [`Codec::Gorilla`] (XOR of consecutive values) and text
+//! with [`Codec::Zstd`], which can use a dictionary trained on earlier pages
+//! of the same series. Writer and reader agree on a codec per column: stored
+//! pages record the codec they were written with, and query replies only use
+//! codecs the client listed in its query options.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CodeBook, Compressable, Decompressable, EleType, ProtoError, Seq};
+
+/// Values per bit-packed block of [`Codec::Delta`]
+const DELTA_BLOCK: usize = 128;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Pcodec for numbers, a codebook of distinct values for text
+    #[default]
+    Pco,
+    /// Zigzag encoded deltas, bit-packed in blocks of 128 values
+    Delta,
+    /// XOR of each float with its predecessor, as in Facebook's Gorilla
+    Gorilla,
+    /// Zstandard over the little-endian values, with an optional dictionary
+    Zstd,
+}
+
+impl Codec {
+    /// Every codec this build can encode and decode, most specific first
+    pub fn available() -> Vec<Codec> {
+        let mut codecs = vec![Codec::Delta, Codec::Gorilla];
+        if cfg!(feature = "zstd") {
+            codecs.push(Codec::Zstd);
+        }
+        codecs.push(Codec::Pco);
+        codecs
+    }
+
+    pub fn supports(&self, dtype: &EleType) -> bool {
+        match self {
+            Codec::Pco => true,
+            Codec::Delta => matches!(
+                dtype,
+                EleType::BOOL | EleType::I32 | EleType::I64 | EleType::DataTime
+            ),
+            Codec::Gorilla => matches!(dtype, EleType::F32 | EleType::F64),
+            Codec::Zstd => cfg!(feature = "zstd") && !matches!(dtype, EleType::Nil),
+        }
+    }
+
+    /// First codec of `accepted` that suits `dtype`, [`Codec::Pco`] if none
+    pub fn negotiate(dtype: &EleType, accepted: &[Codec]) -> Codec {
+        accepted
+            .iter()
+            .copied()
+            .find(|codec| codec.supports(dtype))
+            .unwrap_or_default()
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Codec::Pco => "pco",
+            Codec::Delta => "delta",
+            Codec::Gorilla => "gorilla",
+            Codec::Zstd => "zstd",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Codec {
+    type Err = ProtoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "pco" => Ok(Codec::Pco),
+            "delta" => Ok(Codec::Delta),
+            "gorilla" => Ok(Codec::Gorilla),
+            "zstd" => Ok(Codec::Zstd),
+            other => Err(ProtoError::CompressError(format!("unknown codec {other}"))),
+        }
+    }
+}
+
+/// Largest column a zstd page may decompress to, whatever its header claims
+const MAX_DECODED_BYTES: usize = 256 * 1024 * 1024;
+
+/// A zstd dictionary trained on sample pages of a series
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Dictionary {
+    /// Hash of `data`, by which pages refer to the dictionary
+    #[serde(default)]
+    pub id: u64,
+    pub data: Vec<u8>,
+}
+
+impl Dictionary {
+    pub fn new(data: Vec<u8>) -> Self {
+        // FNV-1a, stable across builds unlike the std hasher
+        let id = data.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+        });
+        Self { id, data }
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on the values of
+    /// `samples`; zstd needs a few hundred samples to find anything useful
+    #[cfg(feature = "zstd")]
+    pub fn train(samples: &Seq, max_size: usize) -> Result<Self, ProtoError> {
+        let samples = match samples {
+            Seq::SeqText(values) => values.iter().map(|v| v.as_bytes().to_vec()).collect(),
+            other => vec![raw_bytes(other)],
+        };
+        let data = zstd::dict::from_samples(&samples, max_size)
+            .map_err(|e| ProtoError::CompressError(e.to_string()))?;
+        Ok(Self::new(data))
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    pub fn train(_samples: &Seq, _max_size: usize) -> Result<Self, ProtoError> {
+        Err(ProtoError::CompressError(
+            "built without zstd support".to_string(),
+        ))
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u128,
+    len: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            acc: 0,
+            len: 0,
+        }
+    }
+
+    /// Append the `bits` low bits of `value`
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        let value = match bits {
+            64 => value,
+            bits => value & ((1u64 << bits) - 1),
+        };
+        self.acc |= (value as u128) << self.len;
+        self.len += bits;
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    acc: u128,
+    len: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            acc: 0,
+            len: 0,
+        }
+    }
+
+    fn read(&mut self, bits: u32) -> Result<u64, ProtoError> {
+        if bits == 0 {
+            return Ok(0);
+        }
+        while self.len < bits {
+            let byte = self
+                .bytes
+                .get(self.pos)
+                .ok_or_else(|| ProtoError::CompressError("truncated column".to_string()))?;
+            self.acc |= (*byte as u128) << self.len;
+            self.len += 8;
+            self.pos += 1;
+        }
+        let value = match bits {
+            64 => self.acc as u64,
+            bits => self.acc as u64 & ((1u64 << bits) - 1),
+        };
+        self.acc >>= bits;
+        self.len -= bits;
+        Ok(value)
+    }
+}
+
+fn delta_encode(values: &[i64]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write(values.len() as u64, 64);
+    let Some(first) = values.first() else {
+        return writer.finish();
+    };
+    writer.write(*first as u64, 64);
+    let zigzag = values
+        .windows(2)
+        .map(|w| {
+            let delta = w[1].wrapping_sub(w[0]);
+            ((delta << 1) ^ (delta >> 63)) as u64
+        })
+        .collect::<Vec<_>>();
+    for block in zigzag.chunks(DELTA_BLOCK) {
+        let width = 64 - block.iter().fold(0, |acc, v| acc | v).leading_zeros();
+        writer.write(width as u64, 7);
+        for value in block {
+            writer.write(*value, width);
+        }
+    }
+    writer.finish()
+}
+
+fn delta_decode(data: &[u8]) -> Result<Vec<i64>, ProtoError> {
+    let mut reader = BitReader::new(data);
+    let count = reader.read(64)? as usize;
+    let mut values = Vec::with_capacity(count.min(data.len() * 8));
+    if count == 0 {
+        return Ok(values);
+    }
+    let mut last = reader.read(64)? as i64;
+    values.push(last);
+    while values.len() < count {
+        let width = reader.read(7)? as u32;
+        if width > 64 {
+            return Err(ProtoError::CompressError(format!(
+                "corrupt column: delta width {width}"
+            )));
+        }
+        for _ in 0..DELTA_BLOCK.min(count - values.len()) {
+            let zigzag = reader.read(width)?;
+            let delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+            last = last.wrapping_add(delta);
+            values.push(last);
+        }
+    }
+    Ok(values)
+}
+
+fn gorilla_encode(values: impl ExactSizeIterator<Item = f64>) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write(values.len() as u64, 64);
+    let mut prev: Option<u64> = None;
+    // leading and trailing zeros of the last stored XOR
+    let mut window = (u32::MAX, 0);
+    for value in values {
+        let bits = value.to_bits();
+        let Some(last) = prev.replace(bits) else {
+            writer.write(bits, 64);
+            continue;
+        };
+        let xor = bits ^ last;
+        if xor == 0 {
+            writer.write(0, 1);
+            continue;
+        }
+        writer.write(1, 1);
+        let (leading, trailing) = (xor.leading_zeros().min(31), xor.trailing_zeros());
+        if leading >= window.0 && trailing >= window.1 && window.0 != u32::MAX {
+            // fits in the window of the previous value
+            writer.write(0, 1);
+            writer.write(xor >> window.1, 64 - window.0 - window.1);
+        } else {
+            let meaningful = 64 - leading - trailing;
+            writer.write(1, 1);
+            writer.write(leading as u64, 5);
+            writer.write((meaningful - 1) as u64, 6);
+            writer.write(xor >> trailing, meaningful);
+            window = (leading, trailing);
+        }
+    }
+    writer.finish()
+}
+
+fn gorilla_decode(data: &[u8]) -> Result<Vec<f64>, ProtoError> {
+    let mut reader = BitReader::new(data);
+    let count = reader.read(64)? as usize;
+    let mut values = Vec::with_capacity(count.min(data.len() * 8));
+    if count == 0 {
+        return Ok(values);
+    }
+    let mut last = reader.read(64)?;
+    values.push(f64::from_bits(last));
+    let mut window = (0, 0);
+    while values.len() < count {
+        if reader.read(1)? == 1 {
+            if reader.read(1)? == 1 {
+                let leading = reader.read(5)? as u32;
+                let meaningful = reader.read(6)? as u32 + 1;
+                if leading + meaningful > 64 {
+                    return Err(ProtoError::CompressError(format!(
+                        "corrupt column: {leading} leading and {meaningful} meaningful bits"
+                    )));
+                }
+                window = (leading, 64 - leading - meaningful);
+            }
+            let (leading, trailing) = window;
+            last ^= reader.read(64 - leading - trailing)? << trailing;
+        }
+        values.push(f64::from_bits(last));
+    }
+    Ok(values)
+}
+
+/// Little-endian bytes of the values, strings prefixed by their length
+fn raw_bytes(seq: &Seq) -> Vec<u8> {
+    match seq {
+        Seq::Nil => vec![],
+        Seq::SeqBOOL(values) => values.iter().map(|v| *v as u8).collect(),
+        Seq::SeqI32(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Seq::SeqI64(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Seq::SeqF32(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Seq::SeqF64(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Seq::SeqDateTime(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        Seq::SeqText(values) => values
+            .iter()
+            .flat_map(|v| (v.len() as u32).to_le_bytes().into_iter().chain(v.bytes()))
+            .collect(),
+    }
+}
+
+fn from_raw_bytes(dtype: &EleType, data: &[u8]) -> Result<Seq, ProtoError> {
+    fn words<const N: usize>(data: &[u8]) -> impl Iterator<Item = [u8; N]> + '_ {
+        data.chunks_exact(N).map(|c| c.try_into().unwrap())
+    }
+    let seq = match dtype {
+        EleType::Nil => Seq::Nil,
+        EleType::BOOL => Seq::SeqBOOL(data.iter().map(|v| *v != 0).collect()),
+        EleType::I32 => Seq::SeqI32(words(data).map(i32::from_le_bytes).collect()),
+        EleType::I64 => Seq::SeqI64(words(data).map(i64::from_le_bytes).collect()),
+        EleType::F32 => Seq::SeqF32(words(data).map(f32::from_le_bytes).collect()),
+        EleType::F64 => Seq::SeqF64(words(data).map(f64::from_le_bytes).collect()),
+        EleType::DataTime => Seq::SeqDateTime(words(data).map(u64::from_le_bytes).collect()),
+        EleType::Text | EleType::Url => {
+            let mut values = vec![];
+            let mut rest = data;
+            while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+                let len = u32::from_le_bytes(*len) as usize;
+                let value = tail
+                    .get(..len)
+                    .ok_or_else(|| ProtoError::CompressError("truncated column".to_string()))?;
+                values.push(String::from_utf8_lossy(value).to_string());
+                rest = &tail[len..];
+            }
+            Seq::SeqText(values)
+        }
+    };
+    Ok(seq)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_encode(raw: &[u8], dictionary: Option<&Dictionary>) -> Result<Vec<u8>, ProtoError> {
+    let compressed = match dictionary {
+        Some(dictionary) => zstd::bulk::Compressor::with_dictionary(3, &dictionary.data)
+            .and_then(|mut compressor| compressor.compress(raw)),
+        None => zstd::bulk::compress(raw, 3),
+    }
+    .map_err(|e| ProtoError::CompressError(e.to_string()))?;
+    // the decoder needs the raw size up front
+    let mut data = (raw.len() as u64).to_le_bytes().to_vec();
+    data.extend_from_slice(&compressed);
+    Ok(data)
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decode(data: &[u8], dictionary: Option<&Dictionary>) -> Result<Vec<u8>, ProtoError> {
+    let (len, compressed) = data
+        .split_first_chunk::<8>()
+        .ok_or_else(|| ProtoError::CompressError("truncated column".to_string()))?;
+    let len = u64::from_le_bytes(*len);
+    if len > MAX_DECODED_BYTES as u64 {
+        return Err(ProtoError::CompressError(format!(
+            "corrupt column: {len} bytes once decompressed, at most {MAX_DECODED_BYTES}"
+        )));
+    }
+    let len = len as usize;
+    match dictionary {
+        Some(dictionary) => zstd::bulk::Decompressor::with_dictionary(&dictionary.data)
+            .and_then(|mut decompressor| decompressor.decompress(compressed, len)),
+        None => zstd::bulk::decompress(compressed, len),
+    }
+    .map_err(|e| ProtoError::CompressError(e.to_string()))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_encode(_raw: &[u8], _dictionary: Option<&Dictionary>) -> Result<Vec<u8>, ProtoError> {
+    Err(ProtoError::CompressError(
+        "built without zstd support".to_string(),
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decode(_data: &[u8], _dictionary: Option<&Dictionary>) -> Result<Vec<u8>, ProtoError> {
+    Err(ProtoError::CompressError(
+        "built without zstd support".to_string(),
+    ))
+}
+
+/// Compress `seq` with `codec`; the dictionary is only used by zstd
+pub fn encode(
+    seq: &Seq,
+    codec: Codec,
+    dictionary: Option<&Dictionary>,
+) -> Result<(EleType, Vec<u8>, CodeBook), ProtoError> {
+    let dtype = seq.dtype();
+    if !codec.supports(&dtype) {
+        return Err(ProtoError::CompressError(format!(
+            "codec {codec} does not support {dtype:?} columns"
+        )));
+    }
+    let data = match (codec, seq) {
+        (Codec::Pco, seq) => return seq.compress(),
+        (Codec::Delta, Seq::SeqBOOL(values)) => {
+            delta_encode(&values.iter().map(|v| *v as i64).collect::<Vec<_>>())
+        }
+        (Codec::Delta, Seq::SeqI32(values)) => {
+            delta_encode(&values.iter().map(|v| *v as i64).collect::<Vec<_>>())
+        }
+        (Codec::Delta, Seq::SeqI64(values)) => delta_encode(values),
+        (Codec::Delta, Seq::SeqDateTime(values)) => {
+            delta_encode(&values.iter().map(|v| *v as i64).collect::<Vec<_>>())
+        }
+        (Codec::Gorilla, Seq::SeqF32(values)) => gorilla_encode(values.iter().map(|v| *v as f64)),
+        (Codec::Gorilla, Seq::SeqF64(values)) => gorilla_encode(values.iter().copied()),
+        (Codec::Zstd, seq) => zstd_encode(&raw_bytes(seq), dictionary)?,
+        _ => unreachable!("checked by Codec::supports"),
+    };
+    Ok((dtype, data, None))
+}
+
+/// Decompress a column written by [`encode`] with the same codec and
+/// dictionary
+pub fn decode(
+    dtype: EleType,
+    codec: Codec,
+    data: &[u8],
+    codebook: &CodeBook,
+    dictionary: Option<&Dictionary>,
+) -> Result<Seq, ProtoError> {
+    let seq = match (codec, &dtype) {
+        (Codec::Pco, _) => Seq::decompress(dtype, data, codebook)?,
+        (Codec::Delta, EleType::BOOL) => {
+            Seq::SeqBOOL(delta_decode(data)?.iter().map(|v| *v != 0).collect())
+        }
+        (Codec::Delta, EleType::I32) => {
+            Seq::SeqI32(delta_decode(data)?.iter().map(|v| *v as i32).collect())
+        }
+        (Codec::Delta, EleType::I64) => Seq::SeqI64(delta_decode(data)?),
+        (Codec::Delta, EleType::DataTime) => {
+            Seq::SeqDateTime(delta_decode(data)?.iter().map(|v| *v as u64).collect())
+        }
+        (Codec::Gorilla, EleType::F32) => {
+            Seq::SeqF32(gorilla_decode(data)?.iter().map(|v| *v as f32).collect())
+        }
+        (Codec::Gorilla, EleType::F64) => Seq::SeqF64(gorilla_decode(data)?),
+        (Codec::Zstd, dtype) => from_raw_bytes(dtype, &zstd_decode(data, dictionary)?)?,
+        (codec, dtype) => {
+            return Err(ProtoError::CompressError(format!(
+                "codec {codec} does not support {dtype:?} columns"
+            )))
+        }
+    };
+    Ok(seq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(seq: Seq, codec: Codec) -> usize {
+        let (dtype, data, codebook) = encode(&seq, codec, None).unwrap();
+        assert_eq!(decode(dtype, codec, &data, &codebook, None).unwrap(), seq);
+        data.len()
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let timestamps = (0..1000)
+            .map(|i| 1_700_000_000_000_000 + i * 1000)
+            .collect();
+        // a constant stride of 1000 packs into 11 bits per value
+        assert!(roundtrip(Seq::SeqI64(timestamps), Codec::Delta) < 1500);
+        roundtrip(Seq::SeqI64(vec![i64::MIN, i64::MAX, 0, -1]), Codec::Delta);
+        roundtrip(Seq::SeqI32(vec![]), Codec::Delta);
+        roundtrip(Seq::SeqBOOL(vec![true, false, true]), Codec::Delta);
+        roundtrip(Seq::SeqDateTime(vec![u64::MAX, 0, 42]), Codec::Delta);
+    }
+
+    #[test]
+    fn test_gorilla_roundtrip() {
+        let loss = (0..1000).map(|i| 2.5 - (i / 100) as f64 * 0.125).collect();
+        assert!(roundtrip(Seq::SeqF64(loss), Codec::Gorilla) < 1000);
+        roundtrip(
+            Seq::SeqF64(vec![0.1, -0.0, f64::INFINITY, 1e-300, f64::MAX]),
+            Codec::Gorilla,
+        );
+        roundtrip(Seq::SeqF32(vec![1.5, 1.25, 3.0e7]), Codec::Gorilla);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let accepted = [Codec::Delta, Codec::Gorilla, Codec::Pco];
+        assert_eq!(Codec::negotiate(&EleType::I64, &accepted), Codec::Delta);
+        assert_eq!(Codec::negotiate(&EleType::F32, &accepted), Codec::Gorilla);
+        assert_eq!(Codec::negotiate(&EleType::Text, &accepted), Codec::Pco);
+        assert_eq!(Codec::negotiate(&EleType::F64, &[]), Codec::Pco);
+        assert_eq!("Gorilla".parse::<Codec>().unwrap(), Codec::Gorilla);
+        assert!(encode(&Seq::SeqF64(vec![1.0]), Codec::Delta, None).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_columns() {
+        let mut writer = BitWriter::new();
+        writer.write(2, 64);
+        writer.write(0, 64);
+        writer.write(127, 7);
+        assert!(delta_decode(&writer.finish()).is_err());
+
+        let mut writer = BitWriter::new();
+        writer.write(2, 64);
+        writer.write(0, 64);
+        writer.write(0b11, 2);
+        writer.write(31, 5);
+        writer.write(63, 6);
+        assert!(gorilla_decode(&writer.finish()).is_err());
+
+        let mut data = u64::MAX.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0; 16]);
+        assert!(decode(EleType::Text, Codec::Zstd, &data, &None, None).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_with_dictionary() {
+        let paths = (0..2000)
+            .map(|i| format!("/models/transformer/layers.{}/attention/q_proj", i % 48))
+            .collect::<Vec<_>>();
+        let dictionary = Dictionary::train(&Seq::SeqText(paths.clone()), 4096).unwrap();
+        let seq = Seq::SeqText(paths);
+        let (dtype, data, codebook) = encode(&seq, Codec::Zstd, Some(&dictionary)).unwrap();
+        let decoded = decode(dtype, Codec::Zstd, &data, &codebook, Some(&dictionary)).unwrap();
+        assert_eq!(decoded, seq);
+    }
+}
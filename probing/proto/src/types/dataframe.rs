@@ -1,6 +1,11 @@
 use serde::{Deserialize, Serialize};
 
+use super::codec;
+use super::CodeBook;
+use super::Codec;
 use super::Ele;
+use super::EleType;
+use super::ProtoError;
 use super::Seq;
 
 #[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
@@ -37,6 +42,73 @@ impl DataFrame {
             current: 0,
         }
     }
+
+    /// Compress every column with the first of `accepted` that suits it
+    pub fn compress(&self, accepted: &[Codec]) -> Result<CompressedFrame, ProtoError> {
+        let cols = self
+            .cols
+            .iter()
+            .map(|col| {
+                let codec = Codec::negotiate(&col.dtype(), accepted);
+                let (dtype, buffer, codebook) = codec::encode(col, codec, None)?;
+                Ok(CompressedColumn {
+                    dtype,
+                    codec,
+                    buffer,
+                    codebook,
+                })
+            })
+            .collect::<Result<_, ProtoError>>()?;
+        Ok(CompressedFrame {
+            names: self.names.clone(),
+            cols,
+            size: self.size,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct CompressedColumn {
+    pub dtype: EleType,
+    pub codec: Codec,
+    pub buffer: Vec<u8>,
+    pub codebook: CodeBook,
+}
+
+/// A [`DataFrame`] with each column compressed by its own codec, as sent in
+/// reply to queries that list the codecs the client accepts
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct CompressedFrame {
+    pub names: Vec<String>,
+    pub cols: Vec<CompressedColumn>,
+    pub size: u64,
+}
+
+impl CompressedFrame {
+    pub fn nbytes(&self) -> usize {
+        self.cols.iter().map(|col| col.buffer.len()).sum()
+    }
+
+    pub fn decompress(&self) -> Result<DataFrame, ProtoError> {
+        let cols = self
+            .cols
+            .iter()
+            .map(|col| {
+                codec::decode(
+                    col.dtype.clone(),
+                    col.codec,
+                    &col.buffer,
+                    &col.codebook,
+                    None,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(DataFrame {
+            names: self.names.clone(),
+            cols,
+            size: self.size,
+        })
+    }
 }
 
 pub struct DataFrameIterator<'a> {
@@ -60,3 +132,25 @@ impl Iterator for DataFrameIterator<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_frame_roundtrip() {
+        let df = DataFrame::new(
+            vec!["step".to_string(), "loss".to_string(), "rank".to_string()],
+            vec![
+                Seq::SeqI64((0..500).collect()),
+                Seq::SeqF64((0..500).map(|i| 1.0 / (i + 1) as f64).collect()),
+                Seq::SeqText((0..500).map(|i| format!("rank{}", i % 8)).collect()),
+            ],
+        );
+        let frame = df.compress(&[Codec::Delta, Codec::Gorilla]).unwrap();
+        let codecs = frame.cols.iter().map(|col| col.codec).collect::<Vec<_>>();
+        assert_eq!(codecs, vec![Codec::Delta, Codec::Gorilla, Codec::Pco]);
+        assert!(frame.nbytes() < df.cols.iter().map(Seq::nbytes).sum());
+        assert_eq!(frame.decompress().unwrap(), df);
+    }
+}
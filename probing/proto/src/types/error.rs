@@ -39,4 +39,10 @@ pub enum ProtoError {
 
     #[error("node not found: {0}")]
     NodeNotFound(String),
+
+    #[error("invalid histogram: {0}")]
+    InvalidHistogram(String),
+
+    #[error("invalid dashboard: {0}")]
+    InvalidDashboard(String),
 }
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::ProtoError;
+
+/// Upper limit on the number of buckets, keeping a histogram small enough to
+/// be stored per row
+pub const MAX_BUCKETS: usize = 1024;
+
+/// Fixed-bucket histogram of `f64` values.
+///
+/// Bucket `i` counts values in `[bounds[i - 1], bounds[i])`, with one extra
+/// bucket below the first bound and one at or above the last. Histograms with
+/// the same bounds can be merged, so per-step or per-rank histograms can be
+/// combined into one before computing quantiles, which is far cheaper than
+/// keeping every sample of a high-frequency event.
+///
+/// Histograms are stored in tables as text, see the [`Display`] and
+/// [`FromStr`] implementations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Histogram {
+    /// Exponential buckets from 1e-6 to ~1e5 with a 1.5x ratio, wide enough
+    /// for latencies in seconds or milliseconds
+    fn default() -> Self {
+        Self::exponential(1e-6, 1.5, 64).unwrap()
+    }
+}
+
+impl Histogram {
+    /// Create a histogram with the given bucket bounds, which must be finite
+    /// and strictly increasing
+    pub fn new(bounds: Vec<f64>) -> Result<Self, ProtoError> {
+        if bounds.is_empty() || bounds.len() + 1 > MAX_BUCKETS {
+            return Err(ProtoError::InvalidHistogram(format!(
+                "expected 1 to {} bounds, got {}",
+                MAX_BUCKETS - 1,
+                bounds.len()
+            )));
+        }
+        if bounds.iter().any(|b| !b.is_finite()) || bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(ProtoError::InvalidHistogram(
+                "bounds must be finite and strictly increasing".to_string(),
+            ));
+        }
+        Ok(Self {
+            counts: vec![0; bounds.len() + 1],
+            bounds,
+            count: 0,
+            sum: 0.0,
+            min: 0.0,
+            max: 0.0,
+        })
+    }
+
+    /// `count` bounds `start`, `start * factor`, `start * factor^2`, ...
+    pub fn exponential(start: f64, factor: f64, count: usize) -> Result<Self, ProtoError> {
+        Self::new((0..count).map(|i| start * factor.powi(i as i32)).collect())
+    }
+
+    /// `count` bounds `start`, `start + width`, `start + 2 * width`, ...
+    pub fn linear(start: f64, width: f64, count: usize) -> Result<Self, ProtoError> {
+        Self::new((0..count).map(|i| start + width * i as f64).collect())
+    }
+
+    pub fn record(&mut self, value: f64) {
+        self.record_n(value, 1)
+    }
+
+    /// Record `value` `n` times; NaN and infinite values are ignored
+    pub fn record_n(&mut self, value: f64, n: u64) {
+        if !value.is_finite() || n == 0 {
+            return;
+        }
+        let bucket = self.bounds.partition_point(|b| *b <= value);
+        (self.min, self.max) = match self.count {
+            0 => (value, value),
+            _ => (self.min.min(value), self.max.max(value)),
+        };
+        self.counts[bucket] += n;
+        self.count += n;
+        self.sum += value * n as f64;
+    }
+
+    /// Add the counts of `other`, which must have the same bounds
+    pub fn merge(&mut self, other: &Histogram) -> Result<(), ProtoError> {
+        if !self.same_bounds(other) {
+            return Err(ProtoError::InvalidHistogram(
+                "cannot merge histograms with different bounds".to_string(),
+            ));
+        }
+        if other.count == 0 {
+            return Ok(());
+        }
+        (self.min, self.max) = match self.count {
+            0 => (other.min, other.max),
+            _ => (self.min.min(other.min), self.max.max(other.max)),
+        };
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        Ok(())
+    }
+
+    /// Bounds parsed back from text may be off by an ulp from freshly computed
+    /// ones, so compare them with a relative tolerance
+    fn same_bounds(&self, other: &Histogram) -> bool {
+        self.bounds.len() == other.bounds.len()
+            && self
+                .bounds
+                .iter()
+                .zip(&other.bounds)
+                .all(|(a, b)| (a - b).abs() <= 1e-12 * a.abs().max(b.abs()))
+    }
+
+    /// Estimate the `q` quantile, `q` in `[0, 1]`, by interpolating linearly
+    /// inside the bucket holding it. Returns `None` for an empty histogram.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 || q.is_nan() {
+            return None;
+        }
+        let rank = q.clamp(0.0, 1.0) * self.count as f64;
+        let mut seen = 0u64;
+        for (i, &count) in self.counts.iter().enumerate() {
+            if count == 0 || ((seen + count) as f64) < rank {
+                seen += count;
+                continue;
+            }
+            // the outer buckets are unbounded, use the observed extremes
+            let lower = match i {
+                0 => self.min,
+                _ => self.bounds[i - 1].max(self.min),
+            };
+            let upper = self.bounds.get(i).map_or(self.max, |b| b.min(self.max));
+            let fraction = (rank - seen as f64) / count as f64;
+            return Some(lower + (upper - lower) * fraction.clamp(0.0, 1.0));
+        }
+        Some(self.max)
+    }
+
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// Counts per bucket, one more than there are bounds
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// JSON text form, as stored in table columns
+impl Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        f.write_str(&text)
+    }
+}
+
+impl FromStr for Histogram {
+    type Err = ProtoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hist: Histogram =
+            serde_json::from_str(s).map_err(|e| ProtoError::DeserializationError(e.to_string()))?;
+        if hist.counts.len() != hist.bounds.len() + 1 {
+            return Err(ProtoError::InvalidHistogram(
+                "bucket count does not match bounds".to_string(),
+            ));
+        }
+        Ok(hist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_quantile() {
+        let mut hist = Histogram::linear(10.0, 10.0, 9).unwrap();
+        for v in 1..=100 {
+            hist.record(v as f64);
+        }
+        assert_eq!(hist.count(), 100);
+        assert_eq!(hist.mean(), Some(50.5));
+        assert_eq!(hist.quantile(0.0), Some(1.0));
+        assert_eq!(hist.quantile(1.0), Some(100.0));
+        let p50 = hist.quantile(0.5).unwrap();
+        assert!((p50 - 50.0).abs() <= 1.0, "p50 = {p50}");
+        let p99 = hist.quantile(0.99).unwrap();
+        assert!((p99 - 99.0).abs() <= 1.0, "p99 = {p99}");
+
+        assert_eq!(Histogram::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn test_histogram_merge_and_text() {
+        let mut a = Histogram::linear(0.0, 0.25, 16).unwrap();
+        let mut b = a.clone();
+        a.record(0.5);
+        b.record_n(2.0, 3);
+        a.merge(&b).unwrap();
+        assert_eq!(a.count(), 4);
+        assert_eq!(a.min(), Some(0.5));
+        assert_eq!(a.max(), Some(2.0));
+
+        let text = a.to_string();
+        assert_eq!(text.parse::<Histogram>().unwrap(), a);
+        let empty = Histogram::linear(0.0, 0.25, 16).unwrap();
+        assert_eq!(empty.to_string().parse::<Histogram>().unwrap(), empty);
+
+        let other = Histogram::linear(0.0, 1.0, 4).unwrap();
+        assert!(a.merge(&other).is_err());
+        let parsed = Histogram::default().to_string().parse().unwrap();
+        assert!(Histogram::default().merge(&parsed).is_ok());
+        assert!(Histogram::new(vec![2.0, 1.0]).is_err());
+    }
+}
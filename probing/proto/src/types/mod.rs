@@ -1,7 +1,9 @@
 pub mod basic;
+pub mod codec;
 mod compress;
 mod dataframe;
 mod error;
+mod histogram;
 pub mod series;
 mod time_series;
 
@@ -9,10 +11,12 @@ pub use basic::Ele;
 pub use basic::EleType;
 pub use basic::Seq;
 pub use basic::Value;
+pub use codec::{Codec, Dictionary};
 pub use compress::CodeBook;
 pub use compress::Compressable;
 pub use compress::Decompressable;
-pub use dataframe::DataFrame;
+pub use dataframe::{CompressedFrame, DataFrame};
 pub use error::ProtoError;
+pub use histogram::Histogram;
 pub use series::{DiscardStrategy, Series};
 pub use time_series::TimeSeries;
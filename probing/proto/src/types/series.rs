@@ -1,12 +1,14 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ops::Bound::Included;
+use std::sync::Arc;
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+use super::codec;
 use super::CodeBook;
-use super::Compressable;
-use super::Decompressable;
+use super::Codec;
+use super::Dictionary;
 use super::Ele;
 use super::EleType;
 use super::ProtoError;
@@ -17,8 +19,15 @@ pub enum Page {
     Raw(Seq),
     Compressed {
         dtype: EleType,
+        /// Pages written before codecs were selectable are all pco
+        #[serde(default)]
+        codec: Codec,
         buffer: Vec<u8>,
         codebook: CodeBook,
+        /// Id of the zstd dictionary of the series, which is stored once in
+        /// the series rather than in each page
+        #[serde(default)]
+        dictionary_id: Option<u64>,
     },
     Ref,
 }
@@ -32,34 +41,78 @@ impl Page {
         }
     }
 
-    pub fn decompress_buffer(&self, dtype: EleType, buffer: &[u8], cb: &CodeBook) -> Option<Page> {
-        let seq = Seq::decompress(dtype, buffer, cb);
-        seq.map(Page::Raw).ok()
-    }
-
-    pub fn get_value(&self, page_offset: usize) -> Option<Ele> {
+    /// Values of a compressed page, `None` for other pages, if the buffer is
+    /// corrupt or if `dictionary` is not the one the page refers to
+    pub fn decompressed(&self, dictionary: Option<&Dictionary>) -> Option<Seq> {
         match self {
-            Page::Raw(array) => Some(array.get(page_offset)),
             Page::Compressed {
                 dtype,
+                codec,
                 buffer,
                 codebook,
+                dictionary_id,
             } => {
-                if let Some(page) = self.decompress_buffer(dtype.clone(), buffer, codebook) {
-                    return page.get_value(page_offset);
-                }
-                None
+                let dictionary = match dictionary_id {
+                    Some(id) => Some(dictionary.filter(|d| d.id == *id)?),
+                    None => None,
+                };
+                codec::decode(dtype.clone(), *codec, buffer, codebook, dictionary).ok()
             }
+            _ => None,
+        }
+    }
+
+    pub fn get_value(&self, page_offset: usize, dictionary: Option<&Dictionary>) -> Option<Ele> {
+        match self {
+            Page::Raw(array) => Some(array.get(page_offset)),
+            Page::Compressed { .. } => Some(self.decompressed(dictionary)?.get(page_offset)),
             Page::Ref => Some(Ele::Nil),
         }
     }
 }
 
+/// Smallest and largest value of a slice, so scans can skip slices that
+/// cannot match a range filter without decompressing them
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct SliceStats {
+    pub min: Ele,
+    pub max: Ele,
+}
+
+impl SliceStats {
+    /// Statistics of a numeric array, `None` for other types or if all
+    /// values are NaN
+    pub fn of(array: &Seq) -> Option<Self> {
+        fn bounds<T: PartialOrd + Copy>(values: &[T]) -> Option<(T, T)> {
+            // NaN is not comparable, not even to itself
+            let mut iter = values
+                .iter()
+                .copied()
+                .filter(|v| v.partial_cmp(v).is_some());
+            let first = iter.next()?;
+            Some(iter.fold((first, first), |(min, max), v| {
+                (if v < min { v } else { min }, if v > max { v } else { max })
+            }))
+        }
+        let (min, max) = match array {
+            Seq::SeqI32(values) => bounds(values).map(|(a, b)| (Ele::I32(a), Ele::I32(b)))?,
+            Seq::SeqI64(values) => bounds(values).map(|(a, b)| (Ele::I64(a), Ele::I64(b)))?,
+            Seq::SeqF32(values) => bounds(values).map(|(a, b)| (Ele::F32(a), Ele::F32(b)))?,
+            Seq::SeqF64(values) => bounds(values).map(|(a, b)| (Ele::F64(a), Ele::F64(b)))?,
+            _ => return None,
+        };
+        Some(Self { min, max })
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub struct Slice {
     pub offset: usize,
     pub length: usize,
     pub data: Page,
+    /// Computed when the slice is committed
+    #[serde(default)]
+    pub stats: Option<SliceStats>,
 }
 
 impl Slice {
@@ -67,37 +120,40 @@ impl Slice {
         self.data.nbytes()
     }
 
-    pub fn get_value(&self, slice_offset: usize) -> Option<Ele> {
-        self.data.get_value(slice_offset)
+    /// Value at `slice_offset`; `dictionary` is the one of the series, for
+    /// pages compressed with it
+    pub fn get_value(&self, slice_offset: usize, dictionary: Option<&Dictionary>) -> Option<Ele> {
+        self.data.get_value(slice_offset, dictionary)
     }
 
-    pub fn get_with_index(&self, idx: usize) -> Option<Ele> {
-        self.get_value(idx - self.offset)
+    pub fn get_with_index(&self, idx: usize, dictionary: Option<&Dictionary>) -> Option<Ele> {
+        self.get_value(idx - self.offset, dictionary)
     }
 
     pub fn compress(&mut self) {
+        self.compress_with(Codec::Pco, None);
+    }
+
+    /// Compress a raw slice with `codec`, keeping it raw if that fails
+    pub fn compress_with(&mut self, codec: Codec, dictionary: Option<&Dictionary>) {
         if let Page::Raw(array) = &self.data {
-            if let Ok((dtype, buffer, codebook)) = array.compress() {
+            if let Ok((dtype, buffer, codebook)) = codec::encode(array, codec, dictionary) {
                 self.data = Page::Compressed {
                     dtype,
+                    codec,
                     buffer,
                     codebook,
+                    dictionary_id: dictionary
+                        .filter(|_| codec == Codec::Zstd)
+                        .map(|dictionary| dictionary.id),
                 };
             }
         }
     }
 
-    pub fn decompress(&mut self) {
-        if let Page::Compressed {
-            dtype,
-            buffer,
-            codebook,
-        } = &self.data
-        {
-            if let Some(decompressed) = self.data.decompress_buffer(dtype.clone(), buffer, codebook)
-            {
-                self.data = decompressed;
-            }
+    pub fn decompress(&mut self, dictionary: Option<&Dictionary>) {
+        if let Some(decompressed) = self.data.decompressed(dictionary) {
+            self.data = Page::Raw(decompressed);
         }
     }
 }
@@ -108,6 +164,8 @@ impl Slice {
 
 const DISCARD_THRESHOLD_DEFAULT: usize = 20_000_000;
 const CHUNK_SIZE_DEFAULT: usize = 10000;
+/// Largest zstd dictionary trained for a series
+const DICTIONARY_SIZE: usize = 16 * 1024;
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub enum DiscardStrategy {
@@ -154,6 +212,10 @@ pub struct SeriesConfig {
     pub compression_level: usize,
     pub compression_threshold: usize,
     pub discard_strategy: DiscardStrategy,
+    /// Codecs to compress slices with, the first one suiting the dtype
+    /// wins; pco when empty
+    #[serde(default)]
+    pub codecs: Vec<Codec>,
 }
 
 impl Default for SeriesConfig {
@@ -163,6 +225,7 @@ impl Default for SeriesConfig {
             compression_level: 0,
             compression_threshold: 2_000_000,
             discard_strategy: DiscardStrategy::base_memory_size_with_defaults(),
+            codecs: vec![],
         }
     }
 }
@@ -184,6 +247,10 @@ impl SeriesConfig {
         self.discard_strategy = discard_strategy;
         self
     }
+    pub fn with_codecs(mut self, codecs: Vec<Codec>) -> Self {
+        self.codecs = codecs;
+        self
+    }
     pub fn build(self) -> Series {
         Series {
             config: self,
@@ -193,6 +260,7 @@ impl SeriesConfig {
             current_slice: None,
             commit_nbytes: 0,
             commit_counts: 0,
+            dictionary: None,
         }
     }
 }
@@ -212,6 +280,11 @@ pub struct Series {
 
     commit_nbytes: usize,
     commit_counts: usize,
+
+    /// Trained on the first slice compressed with zstd, serialized here once
+    /// and referred to by id from the pages
+    #[serde(default)]
+    dictionary: Option<Arc<Dictionary>>,
 }
 
 impl Series {
@@ -252,6 +325,7 @@ impl Series {
                 offset,
                 length: 1,
                 data: page,
+                stats: None,
             });
 
             if let DiscardStrategy::BaseElementCount { .. } = self.config.discard_strategy {
@@ -311,7 +385,7 @@ impl Series {
         // Check current slice first
         if let Some(slice) = self.current_slice.as_ref() {
             if idx >= slice.offset && idx < slice.offset + slice.length {
-                return slice.get_with_index(idx);
+                return slice.get_with_index(idx, self.dictionary.as_deref());
             }
         }
 
@@ -320,7 +394,7 @@ impl Series {
 
         for (offset, slice) in self.slices.range((Included(&start), Included(&idx))) {
             if idx >= *offset && idx < offset + slice.length {
-                return slice.get_value(idx - offset);
+                return slice.get_value(idx - offset, self.dictionary.as_deref());
             }
         }
 
@@ -330,15 +404,86 @@ impl Series {
     pub fn iter(&self) -> SeriesIterator<'_> {
         SeriesIterator::new(self)
     }
+
+    /// Sequence number of the oldest element still retained
+    pub fn first_offset(&self) -> usize {
+        self.slices
+            .keys()
+            .next()
+            .copied()
+            .or(self.current_slice.as_ref().map(|s| s.offset))
+            .unwrap_or(self.offset)
+    }
+
+    /// Offset and statistics of every retained slice; those of the slice
+    /// still being appended to are computed on the fly
+    pub fn slice_stats(&self) -> Vec<(usize, Option<SliceStats>)> {
+        let mut stats = self
+            .slices
+            .values()
+            .map(|slice| (slice.offset, slice.stats.clone()))
+            .collect::<Vec<_>>();
+        if let Some(slice) = &self.current_slice {
+            let current = match &slice.data {
+                Page::Raw(array) => SliceStats::of(array),
+                _ => slice.stats.clone(),
+            };
+            stats.push((slice.offset, current));
+        }
+        stats
+    }
+
+    /// A copy keeping only the slices starting at `offsets`
+    pub fn with_slices(&self, offsets: &BTreeSet<usize>) -> Series {
+        let slices = self
+            .slices
+            .iter()
+            .filter(|(offset, _)| offsets.contains(offset))
+            .map(|(offset, slice)| (*offset, slice.clone()))
+            .collect::<BTreeMap<_, _>>();
+        Series {
+            config: self.config.clone(),
+            offset: self.offset,
+            dropped: self.dropped,
+            commit_nbytes: slices.values().map(Slice::nbytes).sum(),
+            commit_counts: self.commit_counts,
+            slices,
+            current_slice: self
+                .current_slice
+                .as_ref()
+                .filter(|slice| offsets.contains(&slice.offset))
+                .cloned(),
+            dictionary: self.dictionary.clone(),
+        }
+    }
 }
 
 impl Series {
+    /// Codec for `slice` among the configured ones, training the zstd
+    /// dictionary of the series on it if there is none yet
+    fn codec_for(&mut self, slice: &Slice) -> Codec {
+        let Page::Raw(array) = &slice.data else {
+            return Codec::Pco;
+        };
+        let codec = Codec::negotiate(&array.dtype(), &self.config.codecs);
+        if codec == Codec::Zstd && self.dictionary.is_none() {
+            self.dictionary = Dictionary::train(array, DICTIONARY_SIZE).ok().map(Arc::new);
+        }
+        codec
+    }
+
     fn commit_current_slice(&mut self) {
+        if let Some(slice) = self.current_slice.as_mut() {
+            if let Page::Raw(array) = &slice.data {
+                slice.stats = SliceStats::of(array);
+            }
+        }
         let nbytes = self.nbytes();
         let slice = self.current_slice.take();
         if nbytes > self.config.compression_threshold {
             if let Some(mut slice) = slice {
-                slice.compress();
+                let codec = self.codec_for(&slice);
+                slice.compress_with(codec, self.dictionary.as_deref());
                 self.commit_nbytes += slice.nbytes();
                 self.slices.insert(slice.offset, slice);
             }
@@ -421,6 +566,8 @@ pub struct SeriesIterator<'a> {
     current_btree_iter: std::collections::btree_map::Iter<'a, usize, Slice>,
     current_btree_slice: Option<(&'a usize, &'a Slice)>,
     current_slice: Option<&'a Slice>,
+    /// zstd dictionary of the series, for the pages compressed with it
+    dictionary: Option<&'a Dictionary>,
     elem_idx: usize,
 
     cache: Seq,
@@ -434,6 +581,7 @@ impl<'a> SeriesIterator<'a> {
             current_btree_iter,
             current_btree_slice,
             current_slice: series.current_slice.as_ref(),
+            dictionary: series.dictionary.as_deref(),
             elem_idx: 0,
             cache: Seq::Nil,
         }
@@ -446,22 +594,8 @@ impl<'a> SeriesIterator<'a> {
 
         // Handle decompression for compressed slices on first access
         if self.elem_idx == 0 {
-            if let Page::Compressed {
-                dtype,
-                buffer,
-                codebook,
-            } = &slice.data
-            {
-                if let Some(page) = slice
-                    .data
-                    .decompress_buffer(dtype.clone(), buffer, codebook)
-                {
-                    self.cache = if let Page::Raw(array) = page {
-                        array
-                    } else {
-                        Seq::Nil
-                    }
-                }
+            if let Some(array) = slice.data.decompressed(self.dictionary) {
+                self.cache = array;
             }
         }
 
@@ -694,6 +828,37 @@ mod test {
         assert!(series.get(series.dropped + 1).is_some());
     }
 
+    #[test]
+    fn test_slice_stats() {
+        let mut series = super::Series::builder()
+            .with_compression_threshold(8)
+            .with_discard_strategy(
+                crate::types::series::DiscardStrategy::base_memory_size_with_custom_chunk(4),
+            )
+            .build();
+        for i in [5.0, -1.0, 3.0, 4.0, f64::NAN, 7.0, 2.0] {
+            series.append(i).unwrap();
+        }
+
+        let stats = series.slice_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, 0);
+        let first = stats[0].1.clone().unwrap();
+        assert_eq!(
+            (first.min, first.max),
+            (super::Ele::F64(-1.0), super::Ele::F64(5.0))
+        );
+        let current = stats[1].1.clone().unwrap();
+        assert_eq!(
+            (current.min, current.max),
+            (super::Ele::F64(2.0), super::Ele::F64(7.0))
+        );
+
+        let latest = series.with_slices(&[4].into());
+        assert_eq!(latest.iter().count(), 3);
+        assert_eq!(latest.get(0), None);
+    }
+
     #[test]
     fn test_series_serialization() {
         // Create a series and add some data
@@ -732,4 +897,76 @@ mod test {
         );
         assert_eq!(original_series.nbytes(), deserialized_series.nbytes());
     }
+
+    #[test]
+    fn test_series_codecs() {
+        let mut series = super::Series::builder()
+            .with_discard_strategy(
+                crate::types::series::DiscardStrategy::base_memory_size_with_custom_chunk(256),
+            )
+            .with_compression_threshold(5)
+            .with_codecs(vec![super::Codec::Delta, super::Codec::Gorilla])
+            .build();
+        for i in 0..600 {
+            series.append(1_000_000 + i as i64 * 10).unwrap();
+        }
+
+        let slice = series.slices.get(&0).unwrap();
+        assert!(matches!(
+            slice.data,
+            super::Page::Compressed {
+                codec: super::Codec::Delta,
+                ..
+            }
+        ));
+        let values = series.iter().collect::<Vec<_>>();
+        assert_eq!(values.len(), 600);
+        assert_eq!(values[300], super::Ele::I64(1_003_000));
+
+        // archives written before codecs existed still load as pco pages
+        let mut legacy = serde_json::to_value(&series).unwrap();
+        let page = &mut legacy["slices"]["0"]["data"]["Compressed"];
+        page.as_object_mut().unwrap().remove("codec");
+        page["buffer"] = serde_json::to_value(
+            crate::types::Compressable::compress(&super::Seq::SeqI64(
+                (0..256).map(|i| 1_000_000 + i * 10).collect(),
+            ))
+            .unwrap()
+            .1,
+        )
+        .unwrap();
+        let legacy: super::Series = serde_json::from_value(legacy).unwrap();
+        assert_eq!(legacy.get(255), Some(super::Ele::I64(1_002_550)));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_series_dictionary_stored_once() {
+        let mut series = super::Series::builder()
+            .with_discard_strategy(
+                crate::types::series::DiscardStrategy::base_memory_size_with_custom_chunk(1024),
+            )
+            .with_compression_threshold(5)
+            .with_codecs(vec![super::Codec::Zstd])
+            .build();
+        for i in 0..3000 {
+            let path = format!("/models/transformer/layers.{}/attention/q_proj", i % 48);
+            series.append(path).unwrap();
+        }
+        let id = series.dictionary.as_ref().expect("dictionary trained").id;
+
+        let json = serde_json::to_value(&series).unwrap();
+        for slice in json["slices"].as_object().unwrap().values() {
+            let page = &slice["data"]["Compressed"];
+            assert_eq!(page["dictionary_id"], serde_json::json!(id));
+            assert!(page.get("dictionary").is_none());
+        }
+        let loaded: super::Series = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            loaded.get(1500),
+            Some(super::Ele::Text(
+                "/models/transformer/layers.12/attention/q_proj".to_string()
+            ))
+        );
+    }
 }
@@ -1,10 +1,12 @@
+use std::collections::BTreeSet;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::error::ProtoError;
 use super::series::{DiscardStrategy, SeriesIterator};
-use super::{basic::EleType, series::SeriesConfig, Ele, Series};
+use super::{basic::EleType, series::SeriesConfig, Codec, DataFrame, Ele, Seq, Series};
 
 #[derive(Debug, Error)]
 pub enum TimeSeriesError {
@@ -52,6 +54,11 @@ impl TimeSeries {
         self.timestamp.len()
     }
 
+    /// Bytes held by the timestamps and the columns
+    pub fn nbytes(&self) -> usize {
+        self.timestamp.nbytes() + self.cols.iter().map(Series::nbytes).sum::<usize>()
+    }
+
     pub fn cnts(&self) -> usize {
         self.timestamp.ncounts()
     }
@@ -82,6 +89,62 @@ impl TimeSeries {
         }
     }
 
+    /// Sequence number the next appended row will get.
+    ///
+    /// Rows are numbered from 0 in append order and numbers are never reused,
+    /// so a client can remember this value and later ask for [`Self::since`].
+    pub fn seq(&self) -> usize {
+        self.len()
+    }
+
+    /// Rows appended at or after sequence number `seq`, with a leading
+    /// `timestamp` column. Rows already discarded are silently skipped.
+    pub fn since(&self, seq: usize) -> DataFrame {
+        let skip = seq.saturating_sub(self.timestamp.first_offset());
+        let mut names = vec!["timestamp".to_string()];
+        names.extend(self.names.iter().cloned());
+        let mut cols = vec![Seq::Nil; names.len()];
+        for (timestamp, values) in self.iter().skip(skip) {
+            let row = std::iter::once(timestamp).chain(values);
+            for (col, value) in cols.iter_mut().zip(row) {
+                let _ = col.append(value);
+            }
+        }
+        DataFrame::new(names, cols)
+    }
+
+    /// A copy keeping only the chunks whose timestamps may fall within
+    /// `[min, max]`, judged from the chunk statistics without decompressing
+    /// anything. Rows of the kept chunks are not filtered.
+    pub fn select_range(&self, min: Option<i64>, max: Option<i64>) -> TimeSeries {
+        let as_i64 = |ele: &Ele| match ele {
+            Ele::I32(x) => Some(*x as i64),
+            Ele::I64(x) => Some(*x),
+            _ => None,
+        };
+        let offsets = self
+            .timestamp
+            .slice_stats()
+            .into_iter()
+            .filter(|(_, stats)| {
+                // chunks without usable statistics are always kept
+                let Some(stats) = stats else {
+                    return true;
+                };
+                let (Some(lo), Some(hi)) = (as_i64(&stats.min), as_i64(&stats.max)) else {
+                    return true;
+                };
+                min.is_none_or(|min| hi >= min) && max.is_none_or(|max| lo <= max)
+            })
+            .map(|(offset, _)| offset)
+            .collect::<BTreeSet<_>>();
+        TimeSeries {
+            names: self.names.clone(),
+            timestamp: self.timestamp.with_slices(&offsets),
+            cols: self.cols.iter().map(|c| c.with_slices(&offsets)).collect(),
+        }
+    }
+
     pub fn take(&self, limit: Option<usize>) -> Vec<(Ele, Vec<Ele>)> {
         let iter = self.iter();
         if let Some(limit) = limit {
@@ -117,6 +180,10 @@ impl TimeSeriesConfig {
         self.series_config = self.series_config.with_discard_strategy(discard_strategy);
         self
     }
+    pub fn with_codecs(mut self, codecs: Vec<Codec>) -> Self {
+        self.series_config = self.series_config.with_codecs(codecs);
+        self
+    }
     pub fn with_columns(mut self, names: Vec<String>) -> Self {
         self.names = names;
         self
@@ -187,6 +254,7 @@ mod test {
             super::Ele::I64(1),
             vec![super::Ele::I64(1), super::Ele::I64(2)],
         );
+        assert_eq!(ts.nbytes(), 3 * std::mem::size_of::<i64>());
     }
 
     #[test]
@@ -247,4 +315,51 @@ mod test {
 
         assert!(iter.next().is_none());
     }
+
+    #[test]
+    fn test_timeseries_since() {
+        let mut ts = super::TimeSeries::builder()
+            .with_dtype(super::EleType::I64)
+            .with_discard_strategy(DiscardStrategy::BaseElementCount {
+                discard_threshold: 1000,
+                chunk_size: 2,
+            })
+            .with_columns(vec!["a".to_string()])
+            .build();
+        for i in 0..5 {
+            ts.append(super::Ele::I64(i), vec![super::Ele::I64(i * 10)])
+                .unwrap();
+        }
+        assert_eq!(ts.seq(), 5);
+
+        let df = ts.since(3);
+        assert_eq!(df.names, vec!["timestamp", "a"]);
+        assert_eq!(df.cols[1], super::Seq::SeqI64(vec![30, 40]));
+        assert!(ts.since(ts.seq()).is_empty());
+        assert_eq!(ts.since(0).len(), 5);
+    }
+
+    #[test]
+    fn test_timeseries_select_range() {
+        let mut ts = super::TimeSeries::builder()
+            .with_discard_strategy(DiscardStrategy::BaseElementCount {
+                discard_threshold: 1000,
+                chunk_size: 4,
+            })
+            .with_columns(vec!["a".to_string()])
+            .build();
+        for i in 0..10 {
+            ts.append(super::Ele::I64(i * 100), vec![super::Ele::I64(i)])
+                .unwrap();
+        }
+
+        let rows =
+            |ts: &super::TimeSeries| ts.iter().map(|(_, v)| v[0].clone()).collect::<Vec<_>>();
+        // chunks hold timestamps 0..=300, 400..=700 and 800..=900
+        let recent = ts.select_range(Some(750), None);
+        assert_eq!(rows(&recent), vec![super::Ele::I64(8), super::Ele::I64(9)]);
+        assert_eq!(ts.select_range(Some(250), Some(450)).iter().count(), 8);
+        assert_eq!(ts.select_range(Some(1000), None).iter().count(), 0);
+        assert_eq!(ts.select_range(None, None), ts);
+    }
 }
@@ -0,0 +1,484 @@
+//! Alerts sent to a webhook when a query returns rows.
+//!
+//! Every `alerts.interval` seconds the probe runs `alerts.query`; each row it
+//! returns is a firing alert, e.g. a straggling rank:
+//!
+//! ```sql
+//! SET probing.alerts.query = 'SELECT slowest_rank, min_step, max_step FROM cluster.summary
+//!     WHERE max_step - min_step > 50';
+//! SET probing.alerts.webhook = 'https://hooks.slack.com/services/...';
+//! SET probing.alerts.format = 'slack';
+//! SET probing.alerts.template = 'rank {slowest_rank} stalls at step {min_step} on {host}';
+//! ```
+//!
+//! The message is `alerts.template` with `{column}` replaced by the values of
+//! the row, and `{host}` and `{pid}` by those of the probe unless the row has
+//! such columns. It is posted as a Slack or Feishu text message, or as a JSON
+//! object carrying the row. Rows with the same values in `alerts.key` are the
+//! same alert and are sent again only after `alerts.cooldown` seconds, and at
+//! most `alerts.max_per_minute` messages leave the probe per minute. Each sent
+//! alert is also published as an `alerts.fired` event.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use probing_core::core::{
+    EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
+};
+use probing_proto::prelude::{DataFrame, Ele};
+
+use crate::engine::ENGINE;
+use crate::report::get_hostname;
+use crate::server::SERVER_RUNTIME;
+use crate::shutdown::wait_for_shutdown;
+
+const DEFAULT_INTERVAL: u64 = 30;
+const DEFAULT_COOLDOWN: u64 = 600;
+const DEFAULT_MAX_PER_MINUTE: usize = 10;
+
+/// Body of the webhook request
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Sink {
+    /// `{"alert": <message>, "row": {...}, "host": .., "pid": ..}`
+    #[default]
+    Json,
+    Slack,
+    Feishu,
+}
+
+#[derive(Clone, Debug)]
+struct AlertConfig {
+    query: String,
+    webhook: String,
+    sink: Sink,
+    template: Option<String>,
+    /// Columns telling alerts apart, all of them when empty
+    key: Vec<String>,
+    cooldown: Duration,
+    max_per_minute: usize,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            webhook: String::new(),
+            sink: Sink::default(),
+            template: None,
+            key: vec![],
+            cooldown: Duration::from_secs(DEFAULT_COOLDOWN),
+            max_per_minute: DEFAULT_MAX_PER_MINUTE,
+        }
+    }
+}
+
+static ALERT_CONFIG: Lazy<RwLock<AlertConfig>> = Lazy::new(Default::default);
+
+/// Bumped whenever the query, webhook or interval changes so that stale
+/// workers exit
+static ALERT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+static LIMITER: Lazy<Mutex<Limiter>> = Lazy::new(Default::default);
+
+/// Deduplication and rate limiting of the alerts sent
+#[derive(Debug, Default)]
+struct Limiter {
+    /// Last time each alert was sent, by key
+    last_sent: HashMap<String, Instant>,
+    /// Times of the messages sent within the last minute
+    recent: VecDeque<Instant>,
+    /// Alerts held back by the rate limit since one was last sent, tried
+    /// again on the next evaluations
+    held: HashSet<String>,
+}
+
+impl Limiter {
+    /// Whether alert `key` may be sent at `now`, recording it if so
+    fn admit(&mut self, key: &str, now: Instant, cooldown: Duration, per_minute: usize) -> bool {
+        self.last_sent
+            .retain(|_, sent| now.duration_since(*sent) < cooldown);
+        if self.last_sent.contains_key(key) {
+            return false;
+        }
+        while let Some(sent) = self.recent.front() {
+            if now.duration_since(*sent) < Duration::from_secs(60) {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= per_minute {
+            self.held.insert(key.to_string());
+            return false;
+        }
+        self.recent.push_back(now);
+        self.held.remove(key);
+        self.last_sent.insert(key.to_string(), now);
+        true
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Rows of `df` as (column, value) pairs in column order
+fn rows(df: &DataFrame) -> Vec<Vec<(String, Ele)>> {
+    let count = df.cols.iter().map(|col| col.len()).max().unwrap_or(0);
+    (0..count)
+        .map(|row| {
+            df.names
+                .iter()
+                .zip(df.cols.iter())
+                .map(|(name, col)| (name.clone(), col.get(row)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Value of `name` in the row, falling back to the host and pid of the probe
+fn lookup(row: &[(String, Ele)], name: &str) -> Option<String> {
+    if let Some((_, value)) = row.iter().find(|(column, _)| column == name) {
+        return Some(value.to_string());
+    }
+    match name {
+        "host" => Some(get_hostname().unwrap_or("localhost".to_string())),
+        "pid" => Some(std::process::id().to_string()),
+        _ => None,
+    }
+}
+
+/// `template` with each `{name}` replaced by its value; unknown names are
+/// left as they are
+fn render(template: &str, row: &[(String, Ele)]) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + end + 1];
+        match lookup(row, &placeholder[1..placeholder.len() - 1]) {
+            Some(value) => text.push_str(&value),
+            None => text.push_str(placeholder),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// The message when no template is set: every column of the row
+fn default_message(row: &[(String, Ele)]) -> String {
+    let columns = row
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    render("probing alert on {host} (pid {pid}): ", &[]) + &columns
+}
+
+/// Identity of the alert raised by `row`, made of the `key` columns
+fn alert_key(row: &[(String, Ele)], key: &[String]) -> String {
+    row.iter()
+        .filter(|(name, _)| key.is_empty() || key.contains(name))
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn payload(sink: Sink, message: &str, row: &[(String, Ele)]) -> Value {
+    match sink {
+        Sink::Slack => json!({ "text": message }),
+        Sink::Feishu => json!({ "msg_type": "text", "content": { "text": message } }),
+        Sink::Json => json!({
+            "alert": message,
+            "row": row
+                .iter()
+                .map(|(name, value)| (name.clone(), json!(value.to_string())))
+                .collect::<serde_json::Map<_, _>>(),
+            "host": lookup(&[], "host"),
+            "pid": std::process::id(),
+        }),
+    }
+}
+
+async fn post(url: &str, body: Value) -> Result<()> {
+    let url = url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let rsp = agent
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .send(serde_json::to_vec(&body)?.as_slice())?;
+        match rsp.status().is_success() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("{url}: {}", rsp.status())),
+        }
+    })
+    .await?
+}
+
+/// Run the alert query and send the alerts that pass the limits
+pub(crate) async fn evaluate() -> Result<()> {
+    let config = ALERT_CONFIG.read().unwrap().clone();
+    if config.query.is_empty() || config.webhook.is_empty() {
+        return Ok(());
+    }
+    let df = {
+        let engine = ENGINE.read().await;
+        engine.async_query(config.query.clone()).await?
+    };
+    for row in rows(&df) {
+        let key = alert_key(&row, &config.key);
+        let (admitted, held) = {
+            let mut limiter = LIMITER.lock().unwrap();
+            let admitted =
+                limiter.admit(&key, Instant::now(), config.cooldown, config.max_per_minute);
+            match admitted {
+                true => (true, std::mem::take(&mut limiter.held).len()),
+                false => (false, 0),
+            }
+        };
+        if !admitted {
+            continue;
+        }
+        let mut message = match &config.template {
+            Some(template) => render(template, &row),
+            None => default_message(&row),
+        };
+        if held > 0 {
+            message.push_str(&format!(" ({held} more held back by the rate limit)"));
+        }
+        probing_core::core::events::publish("alerts.fired", "alerts", &message);
+        post(&config.webhook, payload(config.sink, &message, &row)).await?;
+    }
+    Ok(())
+}
+
+async fn alert_worker(interval: Duration, generation: u64) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if ALERT_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        if probing_core::core::killswitch::is_dormant() {
+            continue;
+        }
+        if let Err(err) = evaluate().await {
+            log::error!("failed to evaluate the alert: {err}");
+        }
+    }
+}
+
+/// Webhook notifications for the rows of an alert query
+#[derive(Debug, Default, EngineExtension)]
+pub struct AlertsExtension {
+    /// SQL query whose every returned row is a firing alert
+    #[option()]
+    query: Maybe<String>,
+
+    /// URL the alerts are posted to, often carrying a token
+    #[option(secret)]
+    webhook: Maybe<String>,
+
+    /// Body of the webhook request: json, slack or feishu
+    #[option(choices = ["json", "slack", "feishu"])]
+    format: Maybe<String>,
+
+    /// Message with `{column}` placeholders, every column of the row by default
+    #[option()]
+    template: Maybe<String>,
+
+    /// Comma separated columns telling alerts apart, all of them by default
+    #[option()]
+    key: Maybe<String>,
+
+    /// Seconds between two evaluations of the query
+    #[option(min = 1)]
+    interval: Maybe<u64>,
+
+    /// Seconds before the same alert is sent again
+    #[option(min = 0)]
+    cooldown: Maybe<u64>,
+
+    /// Messages sent at most per minute, the others wait for the next minute
+    #[option(min = 1)]
+    max_per_minute: Maybe<u64>,
+}
+
+impl EngineCall for AlertsExtension {}
+
+impl EngineDatasource for AlertsExtension {}
+
+impl AlertsExtension {
+    /// (Re)start the worker, or stop it without a query or webhook
+    fn restart(&self) {
+        let generation = ALERT_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        {
+            let config = ALERT_CONFIG.read().unwrap();
+            if config.query.is_empty() || config.webhook.is_empty() {
+                return;
+            }
+        }
+        let seconds = match self.interval {
+            Maybe::Just(seconds) if seconds > 0 => seconds,
+            _ => DEFAULT_INTERVAL,
+        };
+        SERVER_RUNTIME.spawn(async move {
+            tokio::select! {
+                _ = alert_worker(Duration::from_secs(seconds), generation) => {}
+                _ = wait_for_shutdown() => {}
+            }
+        });
+    }
+
+    fn set_query(&mut self, query: Maybe<String>) -> Result<(), EngineError> {
+        ALERT_CONFIG.write().unwrap().query = query.clone().into();
+        self.query = query;
+        self.restart();
+        Ok(())
+    }
+
+    fn set_webhook(&mut self, webhook: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = webhook.clone().into();
+        if !value.is_empty() && !value.starts_with("http://") && !value.starts_with("https://") {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_WEBHOOK.to_string(),
+                value,
+            ));
+        }
+        ALERT_CONFIG.write().unwrap().webhook = value;
+        self.webhook = webhook;
+        self.restart();
+        Ok(())
+    }
+
+    fn set_format(&mut self, format: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = format.clone().into();
+        let sink = match value.as_str() {
+            "" | "json" => Sink::Json,
+            "slack" => Sink::Slack,
+            "feishu" => Sink::Feishu,
+            _ => {
+                return Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_FORMAT.to_string(),
+                    value,
+                ))
+            }
+        };
+        ALERT_CONFIG.write().unwrap().sink = sink;
+        self.format = format;
+        Ok(())
+    }
+
+    fn set_template(&mut self, template: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = template.clone().into();
+        ALERT_CONFIG.write().unwrap().template = Some(value).filter(|t| !t.is_empty());
+        self.template = template;
+        Ok(())
+    }
+
+    fn set_key(&mut self, key: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = key.clone().into();
+        ALERT_CONFIG.write().unwrap().key = split_list(&value);
+        self.key = key;
+        Ok(())
+    }
+
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        self.interval = interval;
+        self.restart();
+        Ok(())
+    }
+
+    fn set_cooldown(&mut self, cooldown: Maybe<u64>) -> Result<(), EngineError> {
+        let seconds = match cooldown {
+            Maybe::Just(seconds) => seconds,
+            Maybe::Nothing => DEFAULT_COOLDOWN,
+        };
+        ALERT_CONFIG.write().unwrap().cooldown = Duration::from_secs(seconds);
+        self.cooldown = cooldown;
+        Ok(())
+    }
+
+    fn set_max_per_minute(&mut self, max_per_minute: Maybe<u64>) -> Result<(), EngineError> {
+        let count = match max_per_minute {
+            Maybe::Just(count) => count as usize,
+            Maybe::Nothing => DEFAULT_MAX_PER_MINUTE,
+        };
+        ALERT_CONFIG.write().unwrap().max_per_minute = count;
+        self.max_per_minute = max_per_minute;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> Vec<(String, Ele)> {
+        vec![
+            ("rank".to_string(), Ele::I32(3)),
+            ("step".to_string(), Ele::I64(1200)),
+            ("host".to_string(), Ele::Text("node7".to_string())),
+        ]
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            render("rank {rank} stalls at {step} on {host} {unknown}", &row()),
+            "rank 3 stalls at 1200 on node7 {unknown}"
+        );
+        let pid = std::process::id();
+        assert_eq!(
+            render("pid {pid} {rank", &row()),
+            format!("pid {pid} {{rank")
+        );
+        assert_eq!(alert_key(&row(), &["rank".to_string()]), "rank=3");
+        assert_eq!(alert_key(&row(), &[]), "rank=3;step=1200;host=node7");
+    }
+
+    #[test]
+    fn test_payloads() {
+        let slack = payload(Sink::Slack, "stalled", &row());
+        assert_eq!(slack, json!({ "text": "stalled" }));
+        let feishu = payload(Sink::Feishu, "stalled", &row());
+        assert_eq!(feishu["content"]["text"], "stalled");
+        let generic = payload(Sink::Json, "stalled", &row());
+        assert_eq!(generic["row"]["step"], "1200");
+    }
+
+    #[test]
+    fn test_limiter() {
+        let mut limiter = Limiter::default();
+        let start = Instant::now();
+        let cooldown = Duration::from_secs(600);
+        assert!(limiter.admit("rank=3", start, cooldown, 2));
+        // the same alert waits for the cooldown
+        assert!(!limiter.admit("rank=3", start + Duration::from_secs(5), cooldown, 2));
+        assert!(limiter.admit("rank=4", start, cooldown, 2));
+        // a third alert within the minute is held back
+        assert!(!limiter.admit("rank=5", start, cooldown, 2));
+        assert!(!limiter.admit("rank=5", start, cooldown, 2));
+        assert_eq!(limiter.held.len(), 1);
+        assert!(limiter.admit("rank=5", start + Duration::from_secs(61), cooldown, 2));
+        assert!(limiter.admit("rank=3", start + Duration::from_secs(601), cooldown, 2));
+    }
+}
@@ -0,0 +1,156 @@
+//! Periodic persistence of probe tables into an offline archive.
+//!
+//! With `archive.path` and `archive.interval` set, the probe snapshots its
+//! tables into a local file that `probing open <file> query "..."` can read
+//! after the process has died.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
+};
+use probing_proto::prelude::ProbeArchive;
+use probing_proto::protocol::archive::is_skipped;
+
+use crate::engine::ENGINE;
+use crate::report::get_hostname;
+use crate::server::SERVER_RUNTIME;
+use crate::shutdown::wait_for_shutdown;
+
+#[derive(Clone, Debug, Default)]
+struct ArchiveConfig {
+    path: Option<String>,
+    tables: Vec<String>,
+}
+
+static ARCHIVE_CONFIG: Lazy<RwLock<ArchiveConfig>> = Lazy::new(Default::default);
+
+/// Bumped whenever the interval changes so that stale workers exit
+static ARCHIVE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+async fn archived_tables(config: &ArchiveConfig) -> Result<Vec<String>> {
+    if !config.tables.is_empty() {
+        return Ok(config.tables.clone());
+    }
+    let engine = ENGINE.read().await;
+    let df = engine
+        .async_query(
+            "SELECT table_schema || '.' || table_name FROM information_schema.tables \
+             WHERE table_schema != 'information_schema'",
+        )
+        .await?;
+    Ok(df
+        .iter()
+        .map(|row| row[0].to_string())
+        .filter(|table| !is_skipped(table))
+        .collect())
+}
+
+/// Snapshot the configured tables into the archive file
+pub(crate) async fn persist() -> Result<()> {
+    let config = ARCHIVE_CONFIG.read().unwrap().clone();
+    let Some(path) = config.path.clone() else {
+        return Ok(());
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let mut archive = ProbeArchive::new(
+        std::process::id() as i32,
+        get_hostname().unwrap_or("localhost".to_string()),
+        timestamp,
+    );
+
+    for table in archived_tables(&config).await? {
+        let engine = ENGINE.read().await;
+        match engine.async_query(format!("SELECT * FROM {table}")).await {
+            Ok(df) => {
+                archive.tables.insert(table, df);
+            }
+            Err(err) => log::debug!("skip archiving table {table}: {err}"),
+        }
+    }
+
+    archive.save(&path)?;
+    log::debug!("archived {} tables to {path}", archive.tables.len());
+    Ok(())
+}
+
+async fn archive_worker(interval: Duration, generation: u64) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if ARCHIVE_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        if probing_core::core::killswitch::is_dormant() {
+            continue;
+        }
+        if let Err(err) = persist().await {
+            log::error!("failed to persist probe archive: {err}");
+        }
+    }
+}
+
+/// Offline archive of probe tables for postmortem analysis
+#[derive(Debug, Default, EngineExtension)]
+pub struct ArchiveExtension {
+    /// Archive file the probe tables are persisted to (e.g. /tmp/train.probe)
+    #[option()]
+    path: Maybe<String>,
+
+    /// Seconds between two snapshots (0 to disable)
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Comma separated tables to archive, all tables if empty
+    #[option()]
+    tables: Maybe<String>,
+}
+
+impl EngineCall for ArchiveExtension {}
+
+impl EngineDatasource for ArchiveExtension {}
+
+impl ArchiveExtension {
+    fn set_path(&mut self, path: Maybe<String>) -> Result<(), EngineError> {
+        ARCHIVE_CONFIG.write().unwrap().path = path.clone().into();
+        self.path = path;
+        Ok(())
+    }
+
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let generation = ARCHIVE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Maybe::Just(seconds) = interval {
+            if seconds > 0 {
+                SERVER_RUNTIME.spawn(async move {
+                    tokio::select! {
+                        _ = archive_worker(Duration::from_secs(seconds), generation) => {}
+                        _ = wait_for_shutdown() => {}
+                    }
+                });
+            }
+        }
+        self.interval = interval;
+        Ok(())
+    }
+
+    fn set_tables(&mut self, tables: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = tables.clone().into();
+        ARCHIVE_CONFIG.write().unwrap().tables = value
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        self.tables = tables;
+        Ok(())
+    }
+}
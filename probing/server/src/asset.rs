@@ -2,10 +2,13 @@ use std::env;
 
 use axum::http::{header, StatusCode, Uri};
 use axum::response::IntoResponse;
+use axum::Extension;
 use bytes::Bytes;
 use include_dir::include_dir;
 use include_dir::Dir;
 
+use crate::server::middleware::BasePath;
+
 static ASSET: Dir = include_dir!("app/dist");
 
 pub fn contains(path: &str) -> bool {
@@ -48,8 +51,18 @@ fn get_content_type(path: &str) -> &'static str {
 }
 
 /// Handler for index page
-pub async fn index() -> impl IntoResponse {
-    ([(header::CONTENT_TYPE, "text/html")], get("/index.html"))
+///
+/// The URL prefix is injected as `<base>` and `<meta name="probing-base">` so
+/// the web app resolves assets, routes and API calls under a reverse proxy.
+pub async fn index(Extension(BasePath(base)): Extension<BasePath>) -> impl IntoResponse {
+    let html = String::from_utf8_lossy(&get("/index.html")).replacen(
+        "<head>",
+        &format!(
+            "<head><base href=\"{base}/\" /><meta name=\"probing-base\" content=\"{base}\" />"
+        ),
+        1,
+    );
+    ([(header::CONTENT_TYPE, "text/html")], html)
 }
 
 /// Handler for serving static files
@@ -21,7 +21,7 @@ pub static AUTH_REALM: Lazy<String> =
     Lazy::new(|| env::var(AUTH_REALM_ENV).unwrap_or_else(|_| "Probe Server".to_string()));
 
 /// Get the auth token from the request
-fn get_token_from_request(headers: &HeaderMap) -> Option<String> {
+pub(crate) fn get_token_from_request(headers: &HeaderMap) -> Option<String> {
     // Try Bearer token first
     let bearer_token = headers
         .get("Authorization")
@@ -7,35 +7,138 @@ use probing_python::extensions as py;
 
 use crate::server::error::ApiResult;
 
+use probing_core::core::activity::ActivityExtension;
+use probing_core::core::events::EventsExtension;
+use probing_core::core::masking::MaskingExtension;
+use probing_core::core::scheduler::SchedulerExtension;
+use probing_core::core::{Engine, EngineBuilder};
 pub use probing_core::ENGINE;
 
-pub async fn initialize_engine() -> Result<()> {
-    let builder = probing_core::create_engine()
-        .with_extension(py::PprofExtension::default(), "pprof", None)
+/// Engine with all server extensions, shared by the default probe and the
+/// named instances of [`crate::instances`]
+pub fn engine_builder() -> EngineBuilder {
+    let builder = probing_core::create_engine();
+
+    #[cfg(target_os = "linux")]
+    let builder = builder
+        .with_extension(cc::RdmaExtension::default(), "taskstats", None)
+        .with_extension(cc::TaskStatsExtension::default(), "rdma", Some("flow"));
+
+    builder
+        .with_extension(py::PprofExtension::default(), "probe", Some("overhead"))
+        .with_extension(py::PprofExtension::default(), "probe", Some("heatmap"))
+        .with_extension(py::PprofExtension::default(), "probe", Some("spans"))
+        .with_extension(py::PprofExtension::default(), "probe", Some("packages"))
+        .with_extension(py::PprofExtension::default(), "probe", Some("steps"))
+        .with_extension(ActivityExtension::default(), "probe", Some("activity"))
         .with_extension(py::TorchExtension::default(), "torch", None)
-        .with_extension(se::ServerExtension::default(), "server", None)
+        .with_extension(py::GpuExtension::default(), "gpu", Some("topology"))
+        .with_extension(py::GpuExtension::default(), "gpu", Some("kernels"))
+        .with_extension(py::BacktraceExtension::default(), "backtrace", None)
+        .with_extension(se::ServerExtension::default(), "server", Some("logs"))
+        .with_extension(se::ServerExtension::default(), "server", Some("stats"))
+        .with_extension(crate::archive::ArchiveExtension::default(), "archive", None)
+        .with_extension(crate::export::ExportExtension::default(), "export", None)
+        .with_extension(
+            crate::replication::ReplicationExtension::default(),
+            "replicas",
+            None,
+        )
+        .with_extension(
+            crate::replication::ReplicationExtension::default(),
+            "store",
+            Some("compactions"),
+        )
+        .with_extension(
+            crate::external::ExternalExtension::default(),
+            "external",
+            None,
+        )
+        .with_extension(
+            crate::profiles::ProfilesExtension::default(),
+            "profiles",
+            Some("catalog"),
+        )
         .with_extension(py::PythonExt::default(), "python", None)
         .with_extension(cc::ClusterExtension::default(), "cluster", Some("nodes"))
+        .with_extension(cc::ClockExtension::default(), "cluster", Some("clock_skew"))
+        .with_extension(
+            crate::relay::RelayExtension::default(),
+            "cluster",
+            Some("metrics"),
+        )
+        .with_extension(
+            crate::relay::RelayExtension::default(),
+            "cluster",
+            Some("summary"),
+        )
         .with_extension(cc::EnvExtension::default(), "process", Some("envs"))
-        .with_extension(cc::FilesExtension::default(), "files", None);
-
-    #[cfg(target_os = "linux")]
-    let builder = builder.with_extension(cc::RdmaExtension::default(), "taskstats", None);
+        .with_extension(cc::EnvExtension::default(), "process", Some("envs_initial"))
+        .with_extension(cc::EnvExtension::default(), "process", Some("envs_diff"))
+        .with_extension(cc::LimitsExtension::default(), "process", Some("limits"))
+        .with_extension(cc::MemoryExtension::default(), "memory", Some("pressure"))
+        .with_extension(cc::K8sExtension::default(), "process", Some("k8s"))
+        .with_extension(cc::SocketsExtension::default(), "process", Some("sockets"))
+        .with_extension(cc::OutputExtension::default(), "process", Some("output"))
+        .with_extension(
+            cc::ThreadsExtension::default(),
+            "process",
+            Some("thread_events"),
+        )
+        .with_extension(
+            cc::MappingsExtension::default(),
+            "process",
+            Some("memory_by_mapping"),
+        )
+        .with_extension(
+            cc::ProcessTreeExtension::default(),
+            "system",
+            Some("process_tree"),
+        )
+        .with_extension(
+            cc::DataExtension::default(),
+            "data",
+            Some("file_access_samples"),
+        )
+        .with_extension(cc::FilesExtension::default(), "files", Some("events"))
+        .with_extension(
+            cc::AnalysisExtension::default(),
+            "analysis",
+            Some("anomalies"),
+        )
+        .with_extension(SchedulerExtension::default(), "scheduler", None)
+        .with_extension(EventsExtension::default(), "events", Some("log"))
+        .with_extension(EventsExtension::default(), "events", Some("rules"))
+        .with_extension(MaskingExtension::default(), "masking", None)
+        .with_extension(crate::otlp::OtlpExtension::default(), "otlp", None)
+        .with_extension(crate::alerts::AlertsExtension::default(), "alerts", None)
+}
 
-    #[cfg(target_os = "linux")]
-    let builder = builder.with_extension(cc::TaskStatsExtension::default(), "rdma", Some("flow"));
+#[cfg_attr(feature = "embedded", allow(dead_code))]
+pub async fn initialize_engine() -> Result<()> {
+    crate::server::profiling::register_scheduler_actions();
+    probing_core::initialize_engine(engine_builder()).await
+}
 
-    probing_core::initialize_engine(builder).await
+/// Query the engine from a thread without a tokio runtime, see
+/// [`probing_core::block_on`]
+pub fn query_blocking(request: Query) -> Result<QueryDataFormat> {
+    probing_core::block_on(handle_query(request))?
 }
 
 pub async fn handle_query(request: Query) -> Result<QueryDataFormat> {
-    let Query { expr, opts: _ } = request;
-
     // No more thread::spawn or block_on needed here.
     // We are already running within the Axum/Tokio runtime.
 
     // Acquire the engine lock asynchronously
     let engine = ENGINE.read().await;
+    execute_query(&engine, request).await
+}
+
+/// Run a query against `engine`, splitting `SET` statements
+pub async fn execute_query(engine: &Engine, request: Query) -> Result<QueryDataFormat> {
+    let Query { expr, opts } = request;
+    let codecs = opts.map(|opts| opts.codecs).unwrap_or_default();
 
     if expr.starts_with("set ") || expr.starts_with("SET ") {
         // Split potentially multiple SET statements
@@ -66,7 +169,8 @@ pub async fn handle_query(request: Query) -> Result<QueryDataFormat> {
         log::debug!("Executing SELECT query: {expr}");
         // Use the fully async query method and await it
         match engine.async_query(&expr).await {
-            Ok(dataframe) => Ok(QueryDataFormat::DataFrame(dataframe)),
+            Ok(dataframe) if codecs.is_empty() => Ok(QueryDataFormat::DataFrame(dataframe)),
+            Ok(dataframe) => Ok(QueryDataFormat::Compressed(dataframe.compress(&codecs)?)),
             Err(e) => {
                 log::error!("Error executing SELECT query '{expr}': {e}");
                 // Convert DataFusionError/EngineError into anyhow::Error
@@ -87,18 +191,8 @@ pub async fn query(req: String) -> ApiResult<String> {
         }
     };
 
-    // Await the async handle_query function
-    let reply_payload = match handle_query(request).await {
-        Ok(reply) => reply,
-        Err(err) => {
-            // Error already logged in handle_query if it originated there
-            QueryDataFormat::Error(QueryError {
-                code: ErrorCode::Internal,
-                message: err.to_string(),
-                details: None,
-            })
-        }
-    };
+    // Run under a query id so that it can be cancelled with DELETE /query/<id>
+    let reply_payload = crate::server::queries::run(request).await;
 
     // Wrap the payload in a Message
     let reply_message = Message::new(reply_payload);
@@ -0,0 +1,345 @@
+//! Server-side export of table snapshots, for `probing <pid> export`.
+//!
+//! The selected tables are read one after the other and stamped with one
+//! timestamp, then pushed by the probe itself, so the data never flows
+//! through the host of the CLI:
+//!
+//! - `store` pushes them as replicas to the peers of `replication.peers`,
+//!   where they are queryable under the `replicas` namespace;
+//! - a `.parquet` file name writes them into `export.dir` on the host of the
+//!   probe, one file per table when several are exported.
+//!
+//! `export.dir` comes from `PROBING_EXPORT_DIR` when the probe starts and
+//! cannot be changed by a query, otherwise anyone allowed to query could
+//! have files written anywhere the target may write. Existing files are never
+//! overwritten, an export to a taken name goes to `<stem>.<n>.parquet`.
+
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use axum::Json;
+use once_cell::sync::Lazy;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+
+use probing_core::core::{
+    EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
+    RecordBatch, SchemaRef,
+};
+use probing_proto::prelude::DataFrame;
+
+use crate::engine::ENGINE;
+use crate::replication::{now_micros, push_replicas, timestamp_unit};
+use crate::server::error::ApiResult;
+
+/// Variable giving `export.dir`, read once at startup
+const EXPORT_DIR_ENV: &str = "PROBING_EXPORT_DIR";
+
+/// Files tried next to a taken name before giving up
+const MAX_RENAMES: usize = 1000;
+
+/// Directory parquet exports are written into, none unless
+/// `PROBING_EXPORT_DIR` names one
+static EXPORT_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    let dir = std::env::var(EXPORT_DIR_ENV).ok()?;
+    match dir.trim() {
+        "" => None,
+        path => match std::fs::canonicalize(path) {
+            Ok(path) => Some(path),
+            Err(err) => {
+                log::warn!("parquet exports disabled, cannot use {EXPORT_DIR_ENV}={path}: {err}");
+                None
+            }
+        },
+    }
+});
+
+/// Body of `POST /apis/export`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportRequest {
+    pub tables: Vec<String>,
+    /// `store`, or a `.parquet` file in `export.dir` on the host of the probe
+    pub to: String,
+}
+
+/// Where an exported table went
+#[derive(Debug, Clone, Serialize)]
+pub struct Exported {
+    pub table: String,
+    pub rows: usize,
+    pub destination: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Store,
+    Parquet(PathBuf),
+}
+
+impl Target {
+    /// `to` is `store`, or a `.parquet` file inside `dir`, given by its name
+    /// or by a path there
+    fn parse(to: &str, dir: Option<&Path>) -> Result<Self> {
+        match to {
+            "store" => Ok(Target::Store),
+            path if path.ends_with(".parquet") => {
+                let Some(dir) = dir else {
+                    anyhow::bail!(
+                        "cannot export to {to}: start the target with {EXPORT_DIR_ENV} to allow parquet exports"
+                    );
+                };
+                let path = dir.join(path);
+                let escapes = path
+                    .components()
+                    .any(|c| matches!(c, Component::ParentDir | Component::CurDir));
+                if escapes || path.parent() != Some(dir) {
+                    anyhow::bail!("cannot export to {to}: outside of {}", dir.display());
+                }
+                Ok(Target::Parquet(path))
+            }
+            _ => anyhow::bail!("cannot export to {to}: expected `store` or a .parquet file"),
+        }
+    }
+}
+
+/// Reject anything but a dotted identifier such as `python.stacks`, since the
+/// name goes both into SQL and into file names
+fn check_table(table: &str) -> Result<()> {
+    let identifier = |part: &str| {
+        part.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let parts = table.split('.').collect::<Vec<_>>();
+    if parts.len() > 3 || !parts.iter().all(|part| identifier(part)) {
+        anyhow::bail!("cannot export {table}: not a table name");
+    }
+    Ok(())
+}
+
+/// File of `table` when exporting `count` tables to `path`: the path itself
+/// for a single table, `<stem>.<table>.parquet` next to it otherwise
+fn parquet_path(path: &Path, table: &str, count: usize) -> PathBuf {
+    if count == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{table}.parquet"))
+}
+
+/// `n`-th name tried for `path` when it is taken: `<stem>.<n>.parquet`
+fn renamed_path(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{n}.parquet"))
+}
+
+/// Write the batches to a new file at `path`, or next to it when taken, and
+/// return the file written
+fn write_parquet(path: &Path, schema: SchemaRef, batches: &[RecordBatch]) -> Result<PathBuf> {
+    let mut opened = None;
+    for n in 0..MAX_RENAMES {
+        let candidate = renamed_path(path, n);
+        // never overwrite nor follow a symlink planted in the export directory
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(nix::libc::O_NOFOLLOW)
+            .open(&candidate)
+        {
+            Ok(file) => {
+                opened = Some((file, candidate));
+                break;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("cannot create {}", candidate.display()))
+            }
+        }
+    }
+    let Some((file, written)) = opened else {
+        anyhow::bail!("cannot create {}: the name is taken", path.display());
+    };
+    let mut writer = ArrowWriter::try_new(file, schema, None)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    Ok(written)
+}
+
+async fn export_to_store(tables: &[String]) -> Result<Vec<Exported>> {
+    let timestamp = now_micros();
+    let mut frames: Vec<(String, DataFrame, Option<String>)> = vec![];
+    {
+        let engine = ENGINE.read().await;
+        for table in tables {
+            let mut df = engine
+                .async_query(format!("SELECT * FROM {table}"))
+                .await
+                .with_context(|| format!("cannot read {table}"))?;
+            probing_core::core::cluster::correct_timestamps(&mut df);
+            frames.push((table.clone(), df, timestamp_unit(&engine, table).await));
+        }
+    }
+    let exported = frames
+        .iter()
+        .map(|(table, df, _)| Exported {
+            table: table.clone(),
+            rows: df.len(),
+            destination: "store".to_string(),
+        })
+        .collect();
+    push_replicas(frames, timestamp).await?;
+    Ok(exported)
+}
+
+async fn export_to_parquet(tables: &[String], path: &Path) -> Result<Vec<Exported>> {
+    let mut snapshot = vec![];
+    {
+        let engine = ENGINE.read().await;
+        for table in tables {
+            let df = engine
+                .sql(&format!("SELECT * FROM {table}"))
+                .await
+                .with_context(|| format!("cannot read {table}"))?;
+            // kept apart from the batches, which are missing for empty tables
+            let schema = df.schema().inner().clone();
+            snapshot.push((table.clone(), schema, df.collect().await?));
+        }
+    }
+    let mut exported = vec![];
+    for (table, schema, batches) in snapshot {
+        let file = parquet_path(path, &table, tables.len());
+        let written = tokio::task::block_in_place(|| write_parquet(&file, schema, &batches))?;
+        exported.push(Exported {
+            rows: batches.iter().map(|b| b.num_rows()).sum(),
+            table,
+            destination: written.display().to_string(),
+        });
+    }
+    Ok(exported)
+}
+
+/// Export a snapshot of `tables` to `to`
+pub(crate) async fn export(tables: &[String], to: &str) -> Result<Vec<Exported>> {
+    if tables.is_empty() {
+        anyhow::bail!("no table to export");
+    }
+    for table in tables {
+        check_table(table)?;
+    }
+    match Target::parse(to, EXPORT_DIR.as_deref())? {
+        Target::Store => export_to_store(tables).await,
+        Target::Parquet(path) => export_to_parquet(tables, &path).await,
+    }
+}
+
+/// Handler of `POST /apis/export`
+pub async fn export_tables(Json(request): Json<ExportRequest>) -> ApiResult<Json<Vec<Exported>>> {
+    Ok(Json(export(&request.tables, &request.to).await?))
+}
+
+/// Snapshots of tables pushed to the store or written as parquet files
+#[derive(Debug, EngineExtension)]
+pub struct ExportExtension {
+    /// Directory parquet exports are written into, from `PROBING_EXPORT_DIR`
+    /// at startup (none to refuse them)
+    #[option(requires_restart)]
+    dir: Maybe<String>,
+}
+
+impl Default for ExportExtension {
+    fn default() -> Self {
+        Self {
+            dir: match EXPORT_DIR.as_ref() {
+                Some(dir) => Maybe::Just(dir.display().to_string()),
+                None => Maybe::Nothing,
+            },
+        }
+    }
+}
+
+impl EngineCall for ExportExtension {}
+
+impl EngineDatasource for ExportExtension {}
+
+impl ExportExtension {
+    fn set_dir(&mut self, _dir: Maybe<String>) -> Result<(), EngineError> {
+        Err(EngineError::ReadOnlyOption(format!(
+            "{}: set {EXPORT_DIR_ENV} when starting the target",
+            Self::OPTION_DIR
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_parse() {
+        let dir = Path::new("/data");
+        assert_eq!(Target::parse("store", None).unwrap(), Target::Store);
+        assert_eq!(
+            Target::parse("stacks.parquet", Some(dir)).unwrap(),
+            Target::Parquet(PathBuf::from("/data/stacks.parquet"))
+        );
+        assert_eq!(
+            Target::parse("/data/stacks.parquet", Some(dir)).unwrap(),
+            Target::Parquet(PathBuf::from("/data/stacks.parquet"))
+        );
+        assert!(Target::parse("stacks.parquet", None).is_err());
+        assert!(Target::parse("/tmp/stacks.parquet", Some(dir)).is_err());
+        assert!(Target::parse("../stacks.parquet", Some(dir)).is_err());
+        assert!(Target::parse("sub/stacks.parquet", Some(dir)).is_err());
+        assert!(Target::parse("stacks.csv", Some(dir)).is_err());
+    }
+
+    #[test]
+    fn test_check_table() {
+        assert!(check_table("python.stacks").is_ok());
+        assert!(check_table("probe.overhead").is_ok());
+        assert!(check_table("python.stacks/../x").is_err());
+        assert!(check_table("a.b; DROP TABLE c").is_err());
+        assert!(check_table("..").is_err());
+        assert!(check_table("").is_err());
+    }
+
+    #[test]
+    fn test_parquet_path() {
+        let path = Path::new("/tmp/out.parquet");
+        assert_eq!(parquet_path(path, "python.stacks", 1), path);
+        assert_eq!(
+            parquet_path(path, "python.stacks", 2),
+            Path::new("/tmp/out.python.stacks.parquet")
+        );
+    }
+
+    #[test]
+    fn test_write_parquet_keeps_existing_files() {
+        use probing_core::core::{DataType, Field, Schema};
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("probing-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.parquet");
+        std::fs::write(&path, b"kept").unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("x", DataType::Int64, false)]));
+        let written = write_parquet(&path, schema, &[]).unwrap();
+        assert_eq!(written, dir.join("out.1.parquet"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"kept");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dir_is_read_only() {
+        let mut ext = ExportExtension::default();
+        assert!(ext.set("dir", "/tmp").is_err());
+    }
+}
@@ -2,6 +2,12 @@ use probing_core::core::{
     EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
 };
 
+use std::sync::atomic::Ordering;
+
+use crate::report::start_report_sinks;
+use crate::server::config::{normalize_prefix, set_base_path, set_cors_origins};
+use crate::server::limits;
+use crate::shutdown::{DEFAULT_SHUTDOWN_TIMEOUT_MS, SHUTDOWN_TIMEOUT_MS};
 use crate::{start_remote, start_report_worker};
 
 #[derive(Debug, EngineExtension)]
@@ -19,8 +25,14 @@ pub struct ServerExtension {
     #[option(aliases=["report.addr"])]
     report_addr: Maybe<String>,
 
+    /// Comma separated report sinks, each reported to independently:
+    /// `host:port`, `store://host:port` or `file:///path`, optionally with
+    /// `?interval=<secs>` (e.g. `store://master:29500?interval=30`)
+    #[option(aliases=["report.sinks"])]
+    report_sinks: Maybe<String>,
+
     /// Authentication token for the server
-    #[option(aliases=["auth.token"])]
+    #[option(aliases=["auth.token"], secret)]
     auth_token: Maybe<String>,
 
     /// Maximum number of connections allowed
@@ -42,11 +54,45 @@ pub struct ServerExtension {
     /// Root path for assets used by the probing UI dashboard
     #[option(aliases=["assets.root"])]
     assets_root: Maybe<String>,
+
+    /// Deadline in milliseconds for graceful shutdown when the process exits
+    #[option(aliases=["shutdown.timeout"])]
+    shutdown_timeout: Maybe<u64>,
+
+    /// Comma separated origins allowed for CORS requests (`*` for any)
+    #[option(aliases=["cors.origins"])]
+    cors_origins: Maybe<String>,
+
+    /// URL prefix when served behind a proxy that does not strip it
+    #[option(aliases=["base.path"])]
+    base_path: Maybe<String>,
+
+    /// Requests per second by route prefix, e.g. `/query=20,/apis/pythonext/eval=2`
+    #[option(aliases=["rate.limits"])]
+    rate_limits: Maybe<String>,
+
+    /// Concurrent requests by route prefix, e.g. `/query=4,/apis/pythonext/eval=1`
+    #[option(aliases=["max.concurrency"])]
+    max_concurrency: Maybe<String>,
 }
 
 impl EngineCall for ServerExtension {}
 
-impl EngineDatasource for ServerExtension {}
+impl EngineDatasource for ServerExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        match name {
+            Some("stats") => Some(crate::server::limits::StatsPlugin::create(
+                namespace, "stats",
+            )),
+            Some(name) => Some(crate::logger::LogsPlugin::create(namespace, name)),
+            None => None,
+        }
+    }
+}
 
 impl Default for ServerExtension {
     fn default() -> Self {
@@ -54,12 +100,18 @@ impl Default for ServerExtension {
             address: Maybe::Nothing,
             unix_socket: Maybe::Nothing,
             report_addr: Maybe::Nothing,
+            report_sinks: Maybe::Nothing,
             auth_token: Maybe::Nothing,
             max_connections: Maybe::Just(20), // Default to 20 connections
             timeout: Maybe::Just(30),         // Default timeout of 30 seconds
             debug: Maybe::Just(false),        // Debug mode off by default
             log_level: Maybe::Just("info".to_string()), // Default log level
             assets_root: Maybe::Nothing,
+            shutdown_timeout: Maybe::Just(DEFAULT_SHUTDOWN_TIMEOUT_MS),
+            cors_origins: Maybe::Nothing,
+            base_path: Maybe::Nothing,
+            rate_limits: Maybe::Nothing,
+            max_concurrency: Maybe::Nothing,
         }
     }
 }
@@ -93,6 +145,16 @@ impl ServerExtension {
         Ok(())
     }
 
+    fn set_report_sinks(&mut self, report_sinks: Maybe<String>) -> Result<(), EngineError> {
+        let specs: String = report_sinks.clone().into();
+        let address_str: String = self.address.clone().into();
+        start_report_sinks(&specs, address_str).map_err(|e| {
+            EngineError::InvalidOptionValue(Self::OPTION_REPORT_SINKS.to_string(), e.to_string())
+        })?;
+        self.report_sinks = report_sinks;
+        Ok(())
+    }
+
     fn set_auth_token(&mut self, auth_token: Maybe<String>) -> Result<(), EngineError> {
         self.auth_token = auth_token;
         Ok(())
@@ -125,6 +187,7 @@ impl ServerExtension {
         if let Maybe::Just(ref level_str) = log_level {
             match level_str.to_lowercase().as_str() {
                 "trace" | "debug" | "info" | "warn" | "error" => {
+                    crate::logger::set_filter(level_str);
                     self.log_level = log_level;
                     Ok(())
                 }
@@ -143,6 +206,58 @@ impl ServerExtension {
         self.assets_root = assets_root;
         Ok(())
     }
+
+    fn set_shutdown_timeout(&mut self, shutdown_timeout: Maybe<u64>) -> Result<(), EngineError> {
+        match shutdown_timeout {
+            Maybe::Just(timeout) => {
+                SHUTDOWN_TIMEOUT_MS.store(timeout, Ordering::Relaxed);
+                self.shutdown_timeout = shutdown_timeout;
+                Ok(())
+            }
+            Maybe::Nothing => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_SHUTDOWN_TIMEOUT.to_string(),
+                shutdown_timeout.into(),
+            )),
+        }
+    }
+
+    fn set_cors_origins(&mut self, cors_origins: Maybe<String>) -> Result<(), EngineError> {
+        let origins: String = cors_origins.clone().into();
+        set_cors_origins(&origins);
+        self.cors_origins = cors_origins;
+        Ok(())
+    }
+
+    fn set_base_path(&mut self, base_path: Maybe<String>) -> Result<(), EngineError> {
+        let path: String = base_path.clone().into();
+        if normalize_prefix(&path).is_none() {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_BASE_PATH.to_string(),
+                path,
+            ));
+        }
+        set_base_path(&path);
+        self.base_path = base_path;
+        Ok(())
+    }
+
+    fn set_rate_limits(&mut self, rate_limits: Maybe<String>) -> Result<(), EngineError> {
+        let spec: String = rate_limits.clone().into();
+        limits::set_rate_limits(&spec).map_err(|e| {
+            EngineError::InvalidOptionValue(Self::OPTION_RATE_LIMITS.to_string(), e)
+        })?;
+        self.rate_limits = rate_limits;
+        Ok(())
+    }
+
+    fn set_max_concurrency(&mut self, max_concurrency: Maybe<String>) -> Result<(), EngineError> {
+        let spec: String = max_concurrency.clone().into();
+        limits::set_max_concurrency(&spec).map_err(|e| {
+            EngineError::InvalidOptionValue(Self::OPTION_MAX_CONCURRENCY.to_string(), e)
+        })?;
+        self.max_concurrency = max_concurrency;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -191,6 +306,23 @@ mod test {
         // Test report address
         assert!(ext.set("report_addr", "127.0.0.1:9922").is_ok());
         assert_eq!(ext.get("report_addr").unwrap(), "127.0.0.1:9922");
+        assert!(ext.set("report.sinks", "kafka://broker:9092").is_err());
+
+        // Test shutdown timeout
+        assert_eq!(ext.get("shutdown_timeout").unwrap(), "3000");
+        assert!(ext.set("shutdown_timeout", "500").is_ok());
+        assert_eq!(ext.get("shutdown_timeout").unwrap(), "500");
+
+        // Test reverse proxy settings
+        assert!(ext.set("cors.origins", "https://hub.example.com").is_ok());
+        assert_eq!(ext.get("cors_origins").unwrap(), "https://hub.example.com");
+        assert!(ext.set("base_path", "/user/alice/probing/").is_ok());
+        assert!(ext.set("base_path", "/a\"><script>").is_err());
+        assert!(ext.set("base_path", "").is_ok());
+
+        // Test route limits, invalid specs are rejected before being applied
+        assert!(ext.set("rate.limits", "/query=fast").is_err());
+        assert!(ext.set("max_concurrency", "query=1").is_err());
 
         // Test invalid option
         assert!(ext.set("invalid.key", "value").is_err());
@@ -198,10 +330,11 @@ mod test {
 
         // Test options list
         let options = ext.options();
-        assert_eq!(options.len(), 9); // Updated count to include all options
+        assert_eq!(options.len(), 15); // Updated count to include all options
         assert!(options.iter().any(|opt| opt.key == "server.address"));
         assert!(options.iter().any(|opt| opt.key == "server.unix_socket"));
         assert!(options.iter().any(|opt| opt.key == "server.report_addr"));
+        assert!(options.iter().any(|opt| opt.key == "server.report_sinks"));
         assert!(options.iter().any(|opt| opt.key == "server.auth_token"));
         assert!(options
             .iter()
@@ -209,5 +342,8 @@ mod test {
         assert!(options.iter().any(|opt| opt.key == "server.timeout"));
         assert!(options.iter().any(|opt| opt.key == "server.debug"));
         assert!(options.iter().any(|opt| opt.key == "server.log_level"));
+        assert!(options
+            .iter()
+            .any(|opt| opt.key == "server.shutdown_timeout"));
     }
 }
@@ -0,0 +1,487 @@
+//! Tables over the rows other probes push into the store of this probe.
+//!
+//! Workers POST JSON values to `/apis/store/<key>` of the master, one object
+//! or an array of objects per key, e.g. their metrics under
+//! `metrics/<rank>/<step>`. A table registered over a key prefix serves them
+//! under the `external` namespace, one row per object, with the store key as
+//! the leading `key` column:
+//!
+//! ```sql
+//! SET probing.external.table.worker_metrics = 'metrics/';
+//! SELECT m.key, m.loss, s.step FROM external.worker_metrics m
+//!     JOIN python.steps s ON m.step = s.step
+//!     WHERE m.key LIKE 'metrics/3/%';
+//! SET probing.external.table.worker_metrics = '';  -- unregister the table
+//! ```
+//!
+//! The rows are read from the store on each query. Filters on `key` by
+//! equality, `IN`, `LIKE 'prefix%'` or `starts_with` are pushed down, so only
+//! the values under the matching keys are decoded. The columns are inferred
+//! from the first values of the table by key.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::ScalarValue;
+use datafusion::datasource::memory::{DataSourceExec, MemorySourceConfig};
+use datafusion::datasource::TableType;
+use datafusion::error::Result;
+use datafusion::logical_expr::expr::{InList, Like, ScalarFunction};
+use datafusion::logical_expr::{BinaryExpr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::Expr;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+
+use probing_core::core::{
+    CustomNamespace, EngineCall, EngineDatasource, EngineError, EngineExtension,
+    EngineExtensionOption, NamespacePluginHelper, Plugin,
+};
+
+use crate::replication::REPLICA_STORE;
+
+/// Option prefix of the tables, `external.table.<name>`
+const TABLE_PREFIX: &str = "table.";
+
+const KEY: &str = "key";
+
+/// Values read to infer the columns of a table
+const SCHEMA_SAMPLE: usize = 256;
+
+/// Key prefix of each registered table, by table name
+static EXTERNAL_TABLES: Lazy<RwLock<BTreeMap<String, String>>> = Lazy::new(Default::default);
+
+/// Table names from `PROBING_EXTERNAL_TABLE_<NAME>` come with dots,
+/// `worker.metrics` is `worker_metrics`
+fn table_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/// Register, replace or, with an empty `prefix`, unregister table `name`
+fn set_table(name: &str, prefix: &str) -> std::result::Result<(), String> {
+    let name = table_name(name);
+    if name.is_empty() {
+        return Err("missing table name".to_string());
+    }
+    let mut tables = EXTERNAL_TABLES.write().unwrap();
+    match prefix.trim() {
+        "" => tables.remove(&name),
+        prefix => tables.insert(name, prefix.to_string()),
+    };
+    Ok(())
+}
+
+fn table_prefix(name: &str) -> Option<String> {
+    EXTERNAL_TABLES
+        .read()
+        .unwrap()
+        .get(&table_name(name))
+        .cloned()
+}
+
+fn is_key(expr: &Expr) -> bool {
+    matches!(expr, Expr::Column(col) if col.name == KEY)
+}
+
+fn literal_str(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(s)))
+        | Expr::Literal(ScalarValue::LargeUtf8(Some(s)))
+        | Expr::Literal(ScalarValue::Utf8View(Some(s))) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+/// Prefixes one of which the key of a row satisfying `filter` starts with,
+/// if the filter constrains the key
+fn key_prefixes(filter: &Expr) -> Option<Vec<String>> {
+    match filter {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        }) => {
+            let value = match (is_key(left), is_key(right)) {
+                (true, _) => literal_str(right)?,
+                (_, true) => literal_str(left)?,
+                _ => return None,
+            };
+            Some(vec![value])
+        }
+        Expr::InList(InList {
+            expr,
+            list,
+            negated: false,
+        }) if is_key(expr) => list.iter().map(literal_str).collect(),
+        Expr::Like(Like {
+            negated: false,
+            expr,
+            pattern,
+            escape_char: None,
+            case_insensitive: false,
+        }) if is_key(expr) => {
+            let pattern = literal_str(pattern)?;
+            let prefix = pattern.split(['%', '_']).next().unwrap_or_default();
+            Some(vec![prefix.to_string()])
+        }
+        Expr::ScalarFunction(ScalarFunction { func, args })
+            if func.name() == "starts_with" && args.len() == 2 && is_key(&args[0]) =>
+        {
+            Some(vec![literal_str(&args[1])?])
+        }
+        _ => None,
+    }
+}
+
+/// Whether `key` may satisfy all the filters constraining the key
+fn key_matches(key: &str, constraints: &[Vec<String>]) -> bool {
+    constraints
+        .iter()
+        .all(|prefixes| prefixes.iter().any(|p| key.starts_with(p.as_str())))
+}
+
+/// Rows stored under one key: an object, or an array of objects
+fn decode_rows(data: &[u8]) -> Vec<Map<String, Value>> {
+    match serde_json::from_slice::<Value>(data) {
+        Ok(Value::Object(row)) => vec![row],
+        Ok(Value::Array(rows)) => rows
+            .into_iter()
+            .filter_map(|row| match row {
+                Value::Object(row) => Some(row),
+                _ => None,
+            })
+            .collect(),
+        // binary entities such as table replicas
+        _ => vec![],
+    }
+}
+
+fn value_type(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Null => None,
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Number(n) if n.is_i64() => Some(DataType::Int64),
+        Value::Number(_) => Some(DataType::Float64),
+        _ => Some(DataType::Utf8),
+    }
+}
+
+fn merge_type(seen: &DataType, value: DataType) -> DataType {
+    match (seen, value) {
+        (a, b) if *a == b => b,
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            DataType::Float64
+        }
+        _ => DataType::Utf8,
+    }
+}
+
+/// `key` followed by the fields of `rows` by name
+fn infer_schema<'a>(rows: impl Iterator<Item = &'a Map<String, Value>>) -> SchemaRef {
+    let mut fields: BTreeMap<String, Option<DataType>> = BTreeMap::new();
+    for row in rows {
+        for (name, value) in row.iter().filter(|(name, _)| name.as_str() != KEY) {
+            let seen = fields.entry(name.clone()).or_default();
+            if let Some(dtype) = value_type(value) {
+                *seen = Some(match seen {
+                    Some(seen) => merge_type(seen, dtype),
+                    None => dtype,
+                });
+            }
+        }
+    }
+    let mut schema = vec![Field::new(KEY, DataType::Utf8, false)];
+    schema.extend(
+        fields
+            .into_iter()
+            .map(|(name, dtype)| Field::new(name, dtype.unwrap_or(DataType::Utf8), true)),
+    );
+    SchemaRef::new(Schema::new(schema))
+}
+
+/// Rows as a batch of `schema`, values of another type than their column
+/// are null, except in text columns
+fn to_batch(schema: SchemaRef, rows: &[(String, Map<String, Value>)]) -> Result<RecordBatch> {
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(StringArray::from_iter_values(
+        rows.iter().map(|(key, _)| key.as_str()),
+    ))];
+    for field in schema.fields().iter().skip(1) {
+        let values = rows.iter().map(|(_, row)| row.get(field.name()));
+        let column: ArrayRef = match field.data_type() {
+            DataType::Boolean => Arc::new(BooleanArray::from(
+                values.map(|v| v?.as_bool()).collect::<Vec<_>>(),
+            )),
+            DataType::Int64 => Arc::new(Int64Array::from(
+                values.map(|v| v?.as_i64()).collect::<Vec<_>>(),
+            )),
+            DataType::Float64 => Arc::new(Float64Array::from(
+                values.map(|v| v?.as_f64()).collect::<Vec<_>>(),
+            )),
+            _ => Arc::new(StringArray::from(
+                values
+                    .map(|v| match v? {
+                        Value::Null => None,
+                        Value::String(s) => Some(s.clone()),
+                        v => Some(v.to_string()),
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+        };
+        columns.push(column);
+    }
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Rows under the keys starting with `prefix` that may satisfy
+/// `constraints`, ordered by key
+async fn read_rows(prefix: &str, constraints: &[Vec<String>]) -> Vec<(String, Map<String, Value>)> {
+    let mut entries = REPLICA_STORE
+        .raw_entities_filter(|key| key.starts_with(prefix) && key_matches(key, constraints))
+        .await;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+        .into_iter()
+        .flat_map(|(key, data)| {
+            decode_rows(&data)
+                .into_iter()
+                .map(move |row| (key.clone(), row))
+        })
+        .collect()
+}
+
+/// A table over the values under a key prefix of the store
+#[derive(Debug)]
+pub struct StoreTableSource {
+    name: String,
+    prefix: String,
+    schema: SchemaRef,
+}
+
+#[async_trait]
+impl TableProvider for StoreTableSource {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match key_prefixes(filter) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let constraints = filters.iter().filter_map(key_prefixes).collect::<Vec<_>>();
+        let mut rows = read_rows(&self.prefix, &constraints).await;
+        if let Some(limit) = limit {
+            rows.truncate(limit);
+        }
+        log::debug!("external.{}: {} rows read", self.name, rows.len());
+        let batch = to_batch(self.schema.clone(), &rows)?;
+        let srccfg =
+            MemorySourceConfig::try_new(&[vec![batch]], self.schema.clone(), projection.cloned())?;
+        Ok(Arc::new(DataSourceExec::new(Arc::new(srccfg))))
+    }
+}
+
+/// Tables registered over key prefixes of the store
+#[derive(Default, Debug)]
+pub struct ExternalNamespace {}
+
+#[async_trait]
+impl CustomNamespace for ExternalNamespace {
+    fn name() -> &'static str {
+        "external"
+    }
+
+    fn list() -> Vec<String> {
+        EXTERNAL_TABLES.read().unwrap().keys().cloned().collect()
+    }
+
+    async fn table(expr: String) -> Result<Option<Arc<dyn TableProvider>>> {
+        let Some(prefix) = table_prefix(&expr) else {
+            return Ok(None);
+        };
+        let mut sample = REPLICA_STORE
+            .raw_entities_filter(|key| key.starts_with(prefix.as_str()))
+            .await;
+        sample.sort_by(|a, b| a.0.cmp(&b.0));
+        let rows = sample
+            .iter()
+            .take(SCHEMA_SAMPLE)
+            .flat_map(|(_, data)| decode_rows(data))
+            .collect::<Vec<_>>();
+        let table: Arc<dyn TableProvider> = Arc::new(StoreTableSource {
+            name: expr,
+            prefix,
+            schema: infer_schema(rows.iter()),
+        });
+        Ok(Some(table))
+    }
+}
+
+pub type ExternalPlugin = NamespacePluginHelper<ExternalNamespace>;
+
+/// Tables over the store, see the module documentation
+#[derive(Debug, Default)]
+pub struct ExternalExtension {}
+
+impl EngineCall for ExternalExtension {}
+
+impl EngineDatasource for ExternalExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        _name: Option<&str>,
+    ) -> Option<Arc<dyn Plugin + Sync + Send>> {
+        Some(ExternalPlugin::create(namespace))
+    }
+}
+
+impl EngineExtension for ExternalExtension {
+    fn name(&self) -> String {
+        "external".to_string()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> std::result::Result<String, EngineError> {
+        let Some(name) = key.strip_prefix(TABLE_PREFIX) else {
+            return Err(EngineError::UnsupportedOption(key.to_string()));
+        };
+        let old = table_prefix(name).unwrap_or_default();
+        set_table(name, value).map_err(|err| {
+            EngineError::InvalidOptionValue(key.to_string(), format!("{value}: {err}"))
+        })?;
+        Ok(old)
+    }
+
+    fn get(&self, key: &str) -> std::result::Result<String, EngineError> {
+        key.strip_prefix(TABLE_PREFIX)
+            .and_then(table_prefix)
+            .ok_or_else(|| EngineError::UnsupportedOption(key.to_string()))
+    }
+
+    fn options(&self) -> Vec<EngineExtensionOption> {
+        let tables = EXTERNAL_TABLES.read().unwrap();
+        tables
+            .iter()
+            .map(|(name, prefix)| EngineExtensionOption {
+                key: format!("external.{TABLE_PREFIX}{name}"),
+                value: Some(prefix.clone()),
+                help: "Key prefix of the store read as external.<name>, empty to remove it.\nENV[PROBING_EXTERNAL_TABLE_<NAME>]",
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::Array;
+    use datafusion::prelude::{col, lit};
+
+    use super::*;
+
+    #[test]
+    fn test_key_prefixes() {
+        assert_eq!(
+            key_prefixes(&col("key").like(lit("metrics/3/%"))),
+            Some(vec!["metrics/3/".to_string()])
+        );
+        assert_eq!(
+            key_prefixes(&lit("metrics/1").eq(col("key"))),
+            Some(vec!["metrics/1".to_string()])
+        );
+        assert_eq!(
+            key_prefixes(&col("key").in_list(vec![lit("a"), lit("b")], false)),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(key_prefixes(&col("key").not_like(lit("a%"))), None);
+        assert_eq!(key_prefixes(&col("loss").eq(lit("1"))), None);
+
+        let constraints = vec![
+            vec!["metrics/".to_string()],
+            vec!["metrics/1".to_string(), "metrics/2".to_string()],
+        ];
+        assert!(key_matches("metrics/2/10", &constraints));
+        assert!(!key_matches("metrics/3/10", &constraints));
+        assert!(key_matches("anything", &[]));
+    }
+
+    #[test]
+    fn test_decode_rows() {
+        let rows = [
+            r#"{"step": 1, "loss": 2, "ok": true}"#,
+            r#"[{"step": 2, "loss": 1.5, "tag": "a"}, 3]"#,
+            r#"{"step": 3, "loss": null, "tag": {"x": 1}}"#,
+        ]
+        .iter()
+        .enumerate()
+        .flat_map(|(i, data)| {
+            decode_rows(data.as_bytes())
+                .into_iter()
+                .map(move |row| (format!("metrics/{i}"), row))
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert!(decode_rows(b"\x00\x01").is_empty());
+
+        let schema = infer_schema(rows.iter().map(|(_, row)| row));
+        let types = schema
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                ("key", DataType::Utf8),
+                ("loss", DataType::Float64),
+                ("ok", DataType::Boolean),
+                ("step", DataType::Int64),
+                ("tag", DataType::Utf8),
+            ]
+        );
+
+        let batch = to_batch(schema, &rows).unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        let loss = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(loss.value(0), 2.0);
+        assert!(loss.is_null(2));
+        let tag = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(tag.value(2), r#"{"x":1}"#);
+    }
+}
@@ -0,0 +1,234 @@
+//! Named probe instances.
+//!
+//! A process embedding several frameworks may need more than one probe
+//! session. A named instance owns its own engine, with its own DataFusion
+//! settings (`SET datafusion.*`), and is served on its own socket
+//! `probing-<pid>-<name>`. Only `/query` is answered by the instance engine;
+//! every other route falls through to the default probe.
+//!
+//! The options of the extensions (`SET probing.*`) set on an instance are
+//! kept by the extensions of its own engine, so two instances read back the
+//! values they set. Collectors that run once per process, such as the
+//! sampler of pprof, follow the value set last by any probe. The options of
+//! the server itself (`probing.server.*`, e.g. its address and auth token)
+//! and the plugin libraries of `probing.extensions.load` belong to the
+//! process: instances refuse them, they are set on the default probe.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use once_cell::sync::Lazy;
+
+use probing_core::core::Engine;
+use probing_proto::prelude::*;
+
+use crate::engine::{engine_builder, execute_query};
+use crate::server::error::ApiResult;
+use crate::server::SERVER_RUNTIME;
+use crate::shutdown::{track, wait_for_shutdown};
+
+static INSTANCES: Lazy<RwLock<BTreeMap<String, Arc<Engine>>>> =
+    Lazy::new(|| RwLock::new(BTreeMap::new()));
+
+/// Instance names end up in socket names, so keep them to a safe alphabet
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        anyhow::bail!("invalid probe instance name {name:?}, expected [A-Za-z0-9_-]+");
+    }
+    Ok(())
+}
+
+pub fn socket_path(name: &str) -> String {
+    let pid = std::process::id();
+    if cfg!(target_os = "linux") {
+        format!("\0probing-{pid}-{name}")
+    } else {
+        std::env::temp_dir()
+            .join(format!("probing-{pid}-{name}.sock"))
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+pub fn instance_names() -> Vec<String> {
+    INSTANCES.read().unwrap().keys().cloned().collect()
+}
+
+/// Create the instance `name` and start its server, returning `false` if it
+/// already exists
+pub fn create_instance(name: &str) -> Result<bool> {
+    validate_name(name)?;
+    if cfg!(feature = "embedded") {
+        anyhow::bail!("probe instances need the server runtime, the probe is built embedded");
+    }
+    let engine = {
+        let mut instances = INSTANCES.write().unwrap();
+        if instances.contains_key(name) {
+            return Ok(false);
+        }
+        let engine = Arc::new(engine_builder().build()?);
+        instances.insert(name.to_string(), engine.clone());
+        engine
+    };
+
+    let name = name.to_string();
+    track(SERVER_RUNTIME.spawn(async move {
+        if let Err(err) = instance_server(&name, engine).await {
+            log::error!("Failed to start probe instance {name}: {err}");
+            INSTANCES.write().unwrap().remove(&name);
+        }
+    }));
+    Ok(true)
+}
+
+async fn instance_server(name: &str, engine: Arc<Engine>) -> Result<()> {
+    let path = socket_path(name);
+    #[cfg(not(target_os = "linux"))]
+    let _ = std::fs::remove_file(&path);
+
+    eprintln!(
+        "Starting probe instance {name} at {}",
+        path.replace('\0', "@")
+    );
+
+    let app = axum::Router::new()
+        .route(
+            "/query",
+            axum::routing::post(move |body: String| instance_query(engine.clone(), body)),
+        )
+        .fallback_service(crate::server::build_app(false));
+    axum::serve(tokio::net::UnixListener::bind(path)?, app)
+        .with_graceful_shutdown(wait_for_shutdown())
+        .await?;
+    Ok(())
+}
+
+/// Options of the process rather than of a probe, refused by instances
+const PROCESS_OPTIONS: &[&str] = &["probing.server.", "probing.extensions.load"];
+
+/// Refuse `SET` statements of `expr` changing an option of the process,
+/// which is shared by all its probes
+fn check_shared_options(expr: &str) -> Result<()> {
+    for statement in expr.split(';') {
+        let statement = statement.trim();
+        let Some(setting) = statement
+            .get(..4)
+            .filter(|set| set.eq_ignore_ascii_case("set "))
+            .map(|_| statement[4..].trim_start())
+        else {
+            continue;
+        };
+        let key = setting
+            .split(|c: char| c == '=' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let lowercase = key.to_ascii_lowercase();
+        if PROCESS_OPTIONS
+            .iter()
+            .any(|option| lowercase.starts_with(option))
+        {
+            anyhow::bail!(
+                "{key} is shared by all the probes of the process, set it on the default probe"
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn instance_query(engine: Arc<Engine>, body: String) -> impl IntoResponse {
+    let request = match serde_json::from_str::<Message<Query>>(&body) {
+        Ok(request) => request.payload,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request format: {err}"),
+            )
+                .into_response()
+        }
+    };
+    let reply = match check_shared_options(&request.expr) {
+        Ok(()) => execute_query(&engine, request).await,
+        Err(err) => Err(err),
+    };
+    let reply = reply.unwrap_or_else(|err| {
+        QueryDataFormat::Error(QueryError {
+            code: ErrorCode::Internal,
+            message: err.to_string(),
+            details: None,
+        })
+    });
+    match serde_json::to_string(&Message::new(reply)) {
+        Ok(reply) => (StatusCode::OK, reply).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// List the named instances of this process
+pub async fn list_instances() -> Json<Vec<String>> {
+    Json(instance_names())
+}
+
+/// Create a named instance, a no-op if it is already running
+pub async fn start_instance(Path(name): Path<String>) -> ApiResult<impl IntoResponse> {
+    let msg = match create_instance(&name)? {
+        true => format!("probe instance {name} started"),
+        false => format!("probe instance {name} is already running"),
+    };
+    Ok((StatusCode::OK, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("team-a_1").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name("a b").is_err());
+    }
+
+    #[test]
+    fn test_check_shared_options() {
+        assert!(check_shared_options("SELECT * FROM probe.activity").is_ok());
+        assert!(check_shared_options("SET datafusion.execution.batch_size = 1024").is_ok());
+        assert!(check_shared_options("set probing.pprof.sample_freq=10").is_ok());
+        assert!(check_shared_options("set probing.server.auth_token='x'").is_err());
+        assert!(check_shared_options("SET datafusion.x=1; SET PROBING.server.addr TO 1").is_err());
+        assert!(check_shared_options("set probing.extensions.load='/x.so'").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_options_per_instance() {
+        use probing_core::core::EngineExtensionManager;
+
+        let first = engine_builder().build().unwrap();
+        let second = engine_builder().build().unwrap();
+        for (engine, tables) in [(&first, "a.x"), (&second, "b.y")] {
+            let set = Query::new(format!("SET probing.archive.tables='{tables}'"));
+            execute_query(engine, set).await.unwrap();
+        }
+        let read = |engine: &Engine| {
+            engine
+                .context
+                .state()
+                .config()
+                .options()
+                .extensions
+                .get::<EngineExtensionManager>()
+                .cloned()
+                .unwrap()
+        };
+        let first = read(&first).get_option("archive.tables").await.unwrap();
+        let second = read(&second).get_option("archive.tables").await.unwrap();
+        assert_eq!(first, "a.x");
+        assert_eq!(second, "b.y");
+    }
+}
@@ -1,17 +1,37 @@
+mod alerts;
+mod archive;
 mod asset;
 mod auth;
 mod engine;
+mod export;
 mod extensions;
+mod external;
+mod instances;
+mod logger;
+mod otlp;
+mod ports;
+mod profiles;
+mod relay;
+mod replication;
 mod report;
 mod server;
+mod shutdown;
 mod vars;
 
+pub use self::engine::query_blocking;
+pub use self::instances::create_instance;
+pub use self::logger::init as init_logger;
+pub use self::ports::{set_port_range, PortRange};
 pub use self::report::start_report_worker;
 pub use self::server::start_local;
 pub use self::server::start_remote;
 pub use self::server::sync_env_settings;
+pub use self::shutdown::shutdown;
 
 pub fn cleanup() -> anyhow::Result<()> {
+    ports::release();
+    probing_core::core::hot_metrics::remove();
+
     let prefix = std::env::var("PROBING_CTRL_ROOT").unwrap_or("/tmp/probing/".to_string());
 
     let pid = std::process::id();
@@ -0,0 +1,210 @@
+//! The probe's own logger.
+//!
+//! Records are written to stderr like `env_logger` does, filtered by
+//! `PROBING_LOGLEVEL` at startup and by `server.log_level` once the probe is
+//! running. The most recent ones are also kept for the `server.logs` table, so
+//! a misbehaving probe can be diagnosed through its own query interface.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use env_logger::{Builder, Env, Logger};
+use log::{Log, Metadata, Record};
+use once_cell::sync::Lazy;
+
+use probing_core::core::ordering::SEQ;
+use probing_core::core::{
+    CustomTable, DataType, Field, FieldDoc, Int64Array, RecordBatch, Schema, SchemaRef,
+    StringArray, TablePluginHelper,
+};
+
+const ENV_PROBING_LOGLEVEL: &str = "PROBING_LOGLEVEL";
+
+/// Number of records kept for `server.logs`
+const MAX_RECORDS: usize = 1024;
+
+#[derive(Debug, Clone)]
+struct LogEntry {
+    /// Number of the record since the probe started
+    seq: i64,
+    /// Microseconds since epoch
+    timestamp: i64,
+    level: String,
+    target: String,
+    thread: String,
+    message: String,
+}
+
+struct ProbeLogger {
+    inner: RwLock<Logger>,
+    records: Mutex<VecDeque<LogEntry>>,
+    /// Records ever kept, numbering the next one
+    kept: AtomicI64,
+}
+
+static LOGGER: Lazy<ProbeLogger> = Lazy::new(|| ProbeLogger {
+    inner: RwLock::new(Builder::from_env(Env::new().filter(ENV_PROBING_LOGLEVEL)).build()),
+    records: Mutex::new(VecDeque::with_capacity(MAX_RECORDS)),
+    kept: AtomicI64::new(0),
+});
+
+impl Log for ProbeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner
+            .read()
+            .map(|inner| inner.enabled(metadata))
+            .unwrap_or(false)
+    }
+
+    fn log(&self, record: &Record) {
+        let Ok(inner) = self.inner.read() else {
+            return;
+        };
+        if !inner.matches(record) {
+            return;
+        }
+        inner.log(record);
+        drop(inner);
+
+        let mut entry = LogEntry {
+            seq: 0,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as i64,
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string(),
+            message: record.args().to_string(),
+        };
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() >= MAX_RECORDS {
+                records.pop_front();
+            }
+            entry.seq = self.kept.fetch_add(1, Ordering::Relaxed);
+            records.push_back(entry);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(inner) = self.inner.read() {
+            inner.flush();
+        }
+    }
+}
+
+/// Install the probe logger, configured from `PROBING_LOGLEVEL`
+pub fn init() {
+    let logger: &'static ProbeLogger = &LOGGER;
+    if log::set_logger(logger).is_err() {
+        log::warn!("a logger is already installed, probe logs are not captured");
+        return;
+    }
+    if let Ok(inner) = logger.inner.read() {
+        log::set_max_level(inner.filter());
+    }
+}
+
+/// Replace the filter of the probe logger, e.g. `debug` or
+/// `probing_server=trace,info`
+pub fn set_filter(spec: &str) {
+    let logger = Builder::new().parse_filters(spec).build();
+    let level = logger.filter();
+    if let Ok(mut inner) = LOGGER.inner.write() {
+        *inner = logger;
+        log::set_max_level(level);
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct LogsTable {}
+
+impl CustomTable for LogsTable {
+    fn name() -> &'static str {
+        "logs"
+    }
+
+    fn description() -> &'static str {
+        "Most recent log records of the probe itself"
+    }
+
+    fn ordering() -> Option<&'static str> {
+        Some(SEQ)
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new(SEQ, DataType::Int64, false)
+                .with_doc("Number of the record since the probe started"),
+            Field::new("timestamp", DataType::Int64, false)
+                .with_doc("Time of the record since the epoch")
+                .with_unit("us"),
+            Field::new("level", DataType::Utf8, false),
+            Field::new("target", DataType::Utf8, false).with_doc("Module that logged the record"),
+            Field::new("thread", DataType::Utf8, false),
+            Field::new("message", DataType::Utf8, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let entries = LOGGER
+            .records
+            .lock()
+            .map(|records| records.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(entries.iter().map(|e| e.seq))),
+                Arc::new(Int64Array::from_iter_values(
+                    entries.iter().map(|e| e.timestamp),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|e| e.level.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|e| e.target.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|e| e.thread.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    entries.iter().map(|e| e.message.as_str()),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type LogsPlugin = TablePluginHelper<LogsTable>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture() {
+        set_filter("warn");
+        let logger: &ProbeLogger = &LOGGER;
+        logger.log(
+            &Record::builder()
+                .level(log::Level::Warn)
+                .target("probing_server::tests")
+                .args(format_args!("captured by the probe"))
+                .build(),
+        );
+
+        let batches = LogsTable::data();
+        assert!(batches.iter().map(|b| b.num_rows()).sum::<usize>() >= 1);
+        let captured = LOGGER.records.lock().unwrap();
+        assert!(captured
+            .iter()
+            .any(|e| e.level == "WARN" && e.message == "captured by the probe"));
+    }
+}
@@ -0,0 +1,494 @@
+//! Export of trace spans and selected tables to an OpenTelemetry collector.
+//!
+//! With `otlp.endpoint` set (e.g. `http://collector:4318`), the probe ships
+//! every `otlp.interval` seconds the spans ended since the last export to
+//! `/v1/traces`, and the numeric columns of the tables listed in
+//! `otlp.metrics` as gauges to `/v1/metrics`, using OTLP/HTTP with JSON
+//! encoding. The resource carries the process identity: host, pid, ranks and
+//! the framework labels shown in `cluster.nodes`.
+//!
+//! A gauge is named `<table>.<column>` and its data points are labeled with
+//! the text columns of their row, so `SELECT * FROM torch.cuda_memory` gives
+//! one `torch.cuda_memory.allocated` point per device.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use probing_core::core::{
+    EngineCall, EngineDatasource, EngineError, EngineExtension, EngineExtensionOption, Maybe,
+};
+use probing_core::trace::{Span, SpanStatus};
+use probing_proto::prelude::{DataFrame, Ele, Seq};
+
+use crate::engine::ENGINE;
+use crate::report::get_hostname;
+use crate::server::SERVER_RUNTIME;
+use crate::shutdown::wait_for_shutdown;
+
+const DEFAULT_INTERVAL: u64 = 10;
+
+#[derive(Clone, Debug, Default)]
+struct OtlpConfig {
+    endpoint: String,
+    tables: Vec<String>,
+    headers: Vec<(String, String)>,
+    service_name: Option<String>,
+}
+
+static OTLP_CONFIG: Lazy<RwLock<OtlpConfig>> = Lazy::new(Default::default);
+
+/// Bumped whenever the endpoint or interval changes so that stale workers exit
+static OTLP_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Mixed into trace ids, which are only unique within a process
+static TRACE_SALT: Lazy<u128> = Lazy::new(|| {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    (nanos << 32) | std::process::id() as u128
+});
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+fn now_nanos() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+fn any_value(value: &Ele) -> Value {
+    match value {
+        Ele::Nil => json!({}),
+        Ele::BOOL(x) => json!({ "boolValue": x }),
+        // 64-bit integers are strings in the JSON encoding of OTLP
+        Ele::I32(x) => json!({ "intValue": x.to_string() }),
+        Ele::I64(x) => json!({ "intValue": x.to_string() }),
+        Ele::F32(x) => json!({ "doubleValue": x }),
+        Ele::F64(x) => json!({ "doubleValue": x }),
+        Ele::DataTime(x) => json!({ "intValue": x.to_string() }),
+        Ele::Text(x) | Ele::Url(x) => json!({ "stringValue": x }),
+    }
+}
+
+fn key_value(key: &str, value: &Ele) -> Value {
+    json!({ "key": key, "value": any_value(value) })
+}
+
+fn env_i64(name: &str) -> Option<i64> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Resource attributes identifying this process
+fn resource(config: &OtlpConfig) -> Value {
+    let labels = probing_core::core::frameworks::detect_self();
+    let exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_name()?.to_string_lossy().to_string()));
+    let service = config
+        .service_name
+        .clone()
+        .or_else(|| std::env::var("ROLE_NAME").ok())
+        .or_else(|| labels.get("framework").cloned())
+        .or(exe)
+        .unwrap_or_else(|| "probing".to_string());
+
+    let mut attributes: BTreeMap<String, Ele> = BTreeMap::new();
+    attributes.insert("service.name".to_string(), Ele::Text(service));
+    attributes.insert(
+        "service.instance.id".to_string(),
+        Ele::Text(crate::replication::local_worker()),
+    );
+    attributes.insert(
+        "host.name".to_string(),
+        Ele::Text(get_hostname().unwrap_or("localhost".to_string())),
+    );
+    attributes.insert(
+        "process.pid".to_string(),
+        Ele::I64(std::process::id() as i64),
+    );
+    for name in ["RANK", "LOCAL_RANK", "WORLD_SIZE", "GROUP_RANK"] {
+        if let Some(value) = env_i64(name) {
+            attributes.insert(format!("probing.{}", name.to_lowercase()), Ele::I64(value));
+        }
+    }
+    for (key, value) in labels {
+        let key = match key.as_str() {
+            "framework" => "probing.framework".to_string(),
+            _ => format!("probing.label.{key}"),
+        };
+        attributes.insert(key, Ele::Text(value));
+    }
+    json!({
+        "attributes": attributes
+            .iter()
+            .map(|(key, value)| key_value(key, value))
+            .collect::<Vec<_>>()
+    })
+}
+
+fn scope() -> Value {
+    json!({ "name": "probing", "version": env!("CARGO_PKG_VERSION") })
+}
+
+fn trace_id(span: &Span) -> String {
+    format!("{:032x}", span.trace_id.as_u128() ^ *TRACE_SALT)
+}
+
+/// Span ids are per-thread sequence numbers starting at 0, which OTLP treats
+/// as invalid; a trace never crosses threads, so shifting them stays unique
+fn span_id(id: u64) -> String {
+    format!("{:016x}", id.wrapping_add(1))
+}
+
+fn otlp_span(span: &Span) -> Value {
+    let mut attributes = span
+        .attributes
+        .iter()
+        .flatten()
+        .map(|attr| key_value(attr.key(), attr.value()))
+        .collect::<Vec<_>>();
+    if let Some(kind) = &span.kind {
+        attributes.push(key_value("probing.kind", &Ele::Text(kind.clone())));
+    }
+    let status = match &span.status {
+        SpanStatus::Error(message) => {
+            json!({ "code": 2, "message": message.clone().unwrap_or_default() })
+        }
+        _ => json!({ "code": 1 }),
+    };
+    let events = span
+        .events
+        .iter()
+        .map(|event| {
+            json!({
+                "name": event.name.clone().unwrap_or_default(),
+                "timeUnixNano": event.timestamp.as_nanos().to_string(),
+                "attributes": event
+                    .attributes
+                    .iter()
+                    .flatten()
+                    .map(|attr| key_value(attr.key(), attr.value()))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let end = span.end_time.unwrap_or(span.start_time);
+    let mut value = json!({
+        "traceId": trace_id(span),
+        "spanId": span_id(span.span_id.as_u64()),
+        "name": span.name,
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": span.start_time.as_nanos().to_string(),
+        "endTimeUnixNano": end.as_nanos().to_string(),
+        "attributes": attributes,
+        "events": events,
+        "status": status,
+    });
+    if let Some(parent) = span.parent_span_id {
+        value["parentSpanId"] = json!(span_id(parent.as_u64()));
+    }
+    value
+}
+
+fn traces_body(resource: &Value, spans: &[Span]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": resource,
+            "scopeSpans": [{
+                "scope": scope(),
+                "spans": spans.iter().map(otlp_span).collect::<Vec<_>>(),
+            }],
+        }],
+    })
+}
+
+fn as_f64(value: &Ele) -> Option<f64> {
+    match value {
+        Ele::I32(x) => Some(*x as f64),
+        Ele::I64(x) => Some(*x as f64),
+        Ele::F32(x) => Some(*x as f64),
+        Ele::F64(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Gauges of the numeric columns of `df`, labeled with its text columns
+fn table_gauges(table: &str, df: &DataFrame, now: u128) -> Vec<Value> {
+    let rows = df.cols.iter().map(|col| col.len()).max().unwrap_or(0);
+    let column = |name: &str| df.names.iter().position(|n| n == name);
+    // tables report microseconds since epoch in `timestamp`
+    let timestamp = column("timestamp");
+    let labels = df
+        .names
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| matches!(df.cols[*i], Seq::SeqText(_)))
+        .collect::<Vec<_>>();
+
+    let mut gauges = vec![];
+    for (i, name) in df.names.iter().enumerate() {
+        let numeric = matches!(
+            df.cols[i],
+            Seq::SeqI32(_) | Seq::SeqI64(_) | Seq::SeqF32(_) | Seq::SeqF64(_)
+        );
+        if Some(i) == timestamp || !numeric {
+            continue;
+        }
+        let points = (0..rows)
+            .filter_map(|row| {
+                let value = as_f64(&df.cols[i].get(row))?;
+                let time = timestamp
+                    .and_then(|t| as_f64(&df.cols[t].get(row)))
+                    .map(|micros| micros as u128 * 1000)
+                    .unwrap_or(now);
+                Some(json!({
+                    "timeUnixNano": time.to_string(),
+                    "asDouble": value,
+                    "attributes": labels
+                        .iter()
+                        .map(|(l, label)| key_value(label, &df.cols[*l].get(row)))
+                        .collect::<Vec<_>>(),
+                }))
+            })
+            .collect::<Vec<_>>();
+        gauges.push(json!({
+            "name": format!("{table}.{name}"),
+            "gauge": { "dataPoints": points },
+        }));
+    }
+    gauges
+}
+
+async fn metrics_body(resource: &Value, tables: &[String]) -> Value {
+    let now = now_nanos();
+    let mut metrics = vec![];
+    for table in tables {
+        let df = {
+            let engine = ENGINE.read().await;
+            engine.async_query(format!("SELECT * FROM {table}")).await
+        };
+        match df {
+            Ok(df) => metrics.extend(table_gauges(table, &df, now)),
+            Err(err) => log::debug!("skip exporting table {table}: {err}"),
+        }
+    }
+    json!({
+        "resourceMetrics": [{
+            "resource": resource,
+            "scopeMetrics": [{ "scope": scope(), "metrics": metrics }],
+        }],
+    })
+}
+
+async fn post(config: &OtlpConfig, path: &str, body: Value) -> Result<()> {
+    let url = format!("{}{path}", config.endpoint.trim_end_matches('/'));
+    let headers = config.headers.clone();
+    tokio::task::spawn_blocking(move || {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let mut request = agent.post(&url).header("Content-Type", "application/json");
+        for (key, value) in headers.iter() {
+            request = request.header(key, value);
+        }
+        let rsp = request.send(serde_json::to_vec(&body)?.as_slice())?;
+        match rsp.status().is_success() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!("{url}: {}", rsp.status())),
+        }
+    })
+    .await?
+}
+
+/// Ship the spans ended since the last export and the configured tables
+pub(crate) async fn export() -> Result<()> {
+    let config = OTLP_CONFIG.read().unwrap().clone();
+    if config.endpoint.is_empty() {
+        return Ok(());
+    }
+    let resource = resource(&config);
+
+    let (spans, dropped) = probing_core::trace::take_completed_spans()
+        .map_err(|err| anyhow::anyhow!("cannot take completed spans: {err:?}"))?;
+    if dropped > 0 {
+        log::warn!("{dropped} spans dropped before they could be exported");
+    }
+    if !spans.is_empty() {
+        post(&config, "/v1/traces", traces_body(&resource, &spans)).await?;
+    }
+    if !config.tables.is_empty() {
+        let body = metrics_body(&resource, &config.tables).await;
+        post(&config, "/v1/metrics", body).await?;
+    }
+    Ok(())
+}
+
+async fn otlp_worker(interval: Duration, generation: u64) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if OTLP_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        if probing_core::core::killswitch::is_dormant() {
+            continue;
+        }
+        if let Err(err) = export().await {
+            log::error!("failed to export to the OTLP collector: {err}");
+        }
+    }
+}
+
+/// Export of spans and metrics to an OpenTelemetry collector
+#[derive(Debug, Default, EngineExtension)]
+pub struct OtlpExtension {
+    /// Base URL of the OTLP/HTTP receiver (e.g. http://collector:4318)
+    #[option()]
+    endpoint: Maybe<String>,
+
+    /// Seconds between two exports
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Comma separated tables exported as gauges (e.g. torch.cuda_memory)
+    #[option()]
+    metrics: Maybe<String>,
+
+    /// Comma separated `key=value` HTTP headers (e.g. authorization=Bearer xyz)
+    #[option(secret)]
+    headers: Maybe<String>,
+
+    /// `service.name` of the resource, the role or framework by default
+    #[option(aliases=["service.name"])]
+    service_name: Maybe<String>,
+}
+
+impl EngineCall for OtlpExtension {}
+
+impl EngineDatasource for OtlpExtension {}
+
+impl OtlpExtension {
+    /// (Re)start the worker, or stop it when there is no endpoint
+    fn restart(&self) {
+        let generation = OTLP_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        let enabled = !OTLP_CONFIG.read().unwrap().endpoint.is_empty();
+        probing_core::trace::collect_completed_spans(enabled);
+        if !enabled {
+            return;
+        }
+        let seconds = match self.interval {
+            Maybe::Just(seconds) if seconds > 0 => seconds,
+            _ => DEFAULT_INTERVAL,
+        };
+        SERVER_RUNTIME.spawn(async move {
+            tokio::select! {
+                _ = otlp_worker(Duration::from_secs(seconds), generation) => {}
+                _ = wait_for_shutdown() => {}
+            }
+        });
+    }
+
+    fn set_endpoint(&mut self, endpoint: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = endpoint.clone().into();
+        if !value.is_empty() && !value.starts_with("http://") && !value.starts_with("https://") {
+            return Err(EngineError::InvalidOptionValue(
+                Self::OPTION_ENDPOINT.to_string(),
+                value,
+            ));
+        }
+        OTLP_CONFIG.write().unwrap().endpoint = value;
+        self.endpoint = endpoint;
+        self.restart();
+        Ok(())
+    }
+
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        self.interval = interval;
+        self.restart();
+        Ok(())
+    }
+
+    fn set_metrics(&mut self, metrics: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = metrics.clone().into();
+        OTLP_CONFIG.write().unwrap().tables = split_list(&value);
+        self.metrics = metrics;
+        Ok(())
+    }
+
+    fn set_headers(&mut self, headers: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = headers.clone().into();
+        let parsed = split_list(&value)
+            .iter()
+            .map(|header| {
+                let (key, value) = header.split_once('=')?;
+                Some((key.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                EngineError::InvalidOptionValue(Self::OPTION_HEADERS.to_string(), value)
+            })?;
+        OTLP_CONFIG.write().unwrap().headers = parsed;
+        self.headers = headers;
+        Ok(())
+    }
+
+    fn set_service_name(&mut self, service_name: Maybe<String>) -> Result<(), EngineError> {
+        OTLP_CONFIG.write().unwrap().service_name = service_name.clone().into();
+        self.service_name = service_name;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_gauges() {
+        let df = DataFrame::new(
+            vec![
+                "device".to_string(),
+                "allocated".to_string(),
+                "timestamp".to_string(),
+            ],
+            vec![
+                Seq::SeqText(vec!["cuda:0".to_string(), "cuda:1".to_string()]),
+                Seq::SeqI64(vec![1024, 2048]),
+                Seq::SeqI64(vec![1_700_000_000_000_000, 1_700_000_000_000_001]),
+            ],
+        );
+        let gauges = table_gauges("torch.cuda_memory", &df, 0);
+        assert_eq!(gauges.len(), 1);
+        assert_eq!(gauges[0]["name"], "torch.cuda_memory.allocated");
+        let points = gauges[0]["gauge"]["dataPoints"].as_array().unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1]["asDouble"], 2048.0);
+        assert_eq!(points[1]["timeUnixNano"], "1700000000000001000");
+        assert_eq!(points[1]["attributes"][0]["key"], "device");
+        assert_eq!(points[1]["attributes"][0]["value"]["stringValue"], "cuda:1");
+    }
+
+    #[test]
+    fn test_span_ids_are_valid() {
+        assert_eq!(span_id(0), "0000000000000001");
+        assert_eq!(span_id(0).len(), 16);
+    }
+}
@@ -0,0 +1,173 @@
+//! Selection of a free server port within a configured range.
+//!
+//! `PROBING_PORT=9700-9799` lets every probe on a node pick its own port, even
+//! when several jobs share the node and `PROBING_PORT + LOCAL_RANK` would
+//! collide. A port is claimed with a lock file under `$PROBING_CTRL_ROOT/ports`
+//! before binding, so concurrent probes never race for the same port, and
+//! claims left behind by dead processes are reclaimed.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+/// Range the remote server picks its port from, see [`set_port_range`]
+static PORT_RANGE: Lazy<RwLock<Option<PortRange>>> = Lazy::new(|| RwLock::new(None));
+
+/// Lock file of the port claimed by this process
+static CLAIMED: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl std::str::FromStr for PortRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("expected <start>-<end>, got {s}"))?;
+        let (start, end) = (start.trim().parse::<u16>()?, end.trim().parse::<u16>()?);
+        if start == 0 || start > end {
+            anyhow::bail!("invalid port range {s}");
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl PortRange {
+    /// Ports of the range, starting at `start + hint` and wrapping around
+    pub fn candidates(&self, hint: u16) -> impl Iterator<Item = u16> {
+        let len = (self.end - self.start) as u32 + 1;
+        let start = self.start as u32;
+        (0..len).map(move |i| (start + (hint as u32 + i) % len) as u16)
+    }
+}
+
+pub fn set_port_range(range: PortRange) {
+    *PORT_RANGE.write().unwrap() = Some(range);
+}
+
+pub fn port_range() -> Option<PortRange> {
+    *PORT_RANGE.read().unwrap()
+}
+
+fn lock_dir() -> PathBuf {
+    let root = std::env::var("PROBING_CTRL_ROOT").unwrap_or("/tmp/probing/".to_string());
+    Path::new(&root).join("ports")
+}
+
+fn is_alive(pid: i32) -> bool {
+    match nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None) {
+        Ok(()) => true,
+        Err(errno) => errno == nix::errno::Errno::EPERM,
+    }
+}
+
+/// Claim `port` for this process, reclaiming locks of dead processes
+fn claim(dir: &Path, port: u16) -> Option<PathBuf> {
+    let path = dir.join(format!("{port}.lock"));
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                return Some(path);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                let owner = std::fs::read_to_string(&path).unwrap_or_default();
+                match owner.trim().parse::<i32>() {
+                    Ok(pid) if !is_alive(pid) => {
+                        log::debug!("reclaiming port {port} from dead process {pid}");
+                        let _ = std::fs::remove_file(&path);
+                    }
+                    _ => return None,
+                }
+            }
+            Err(err) => {
+                log::warn!("failed to claim port {port}: {err}");
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Bind the first port of `range` that is neither claimed by another probe
+/// nor in use, trying `range.start + hint` first.
+pub async fn bind_in_range(
+    host: &str,
+    range: PortRange,
+    hint: u16,
+) -> Result<tokio::net::TcpListener> {
+    let dir = lock_dir();
+    std::fs::create_dir_all(&dir)?;
+
+    for port in range.candidates(hint) {
+        let Some(lock) = claim(&dir, port) else {
+            continue;
+        };
+        match tokio::net::TcpListener::bind((host, port)).await {
+            Ok(listener) => {
+                log::debug!("claimed port {port} in {}-{}", range.start, range.end);
+                *CLAIMED.write().unwrap() = Some(lock);
+                return Ok(listener);
+            }
+            Err(err) => {
+                log::debug!("port {port} is not available: {err}");
+                let _ = std::fs::remove_file(&lock);
+            }
+        }
+    }
+    anyhow::bail!("no free port in {}-{}", range.start, range.end)
+}
+
+/// Drop the lock file of the port claimed by this process
+pub fn release() {
+    if let Some(lock) = CLAIMED.write().unwrap().take() {
+        let _ = std::fs::remove_file(lock);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_range() {
+        let range: PortRange = "9700-9702".parse().unwrap();
+        assert_eq!(
+            range,
+            PortRange {
+                start: 9700,
+                end: 9702
+            }
+        );
+        assert!("9702-9700".parse::<PortRange>().is_err());
+        assert!("9700".parse::<PortRange>().is_err());
+        assert_eq!(
+            range.candidates(1).collect::<Vec<_>>(),
+            vec![9701, 9702, 9700]
+        );
+    }
+
+    #[test]
+    fn test_claim_port() {
+        let dir = std::env::temp_dir().join(format!("probing-ports-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let lock = claim(&dir, 9700).unwrap();
+        assert!(claim(&dir, 9700).is_none());
+
+        // a claim held by a process that no longer exists is reclaimed
+        std::fs::write(&lock, i32::MAX.to_string()).unwrap();
+        assert!(claim(&dir, 9700).is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+//! Retention of served flamegraphs.
+//!
+//! Every flamegraph returned by `/apis/flamegraph*` is recomputed from the
+//! live samples, so an interesting capture is gone with the next request.
+//! The last `profiles.max` (16) of them are kept with their metadata, listed
+//! by the `profiles.catalog` table and `/apis/profiles`, and returned as they
+//! were served by `/apis/profiles/{id}`. Snapshots of the Python heap taken
+//! by `@heap_snapshot` are kept alongside, with profiler `heap`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use probing_core::core::{
+    CustomTable, DataType, EngineCall, EngineDatasource, EngineError, EngineExtension,
+    EngineExtensionOption, Field, Float64Array, Int64Array, Maybe, RecordBatch, Schema, SchemaRef,
+    StringArray, TablePluginHelper,
+};
+
+const DEFAULT_MAX_PROFILES: usize = 16;
+
+static MAX_PROFILES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_PROFILES);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static PROFILES: Lazy<Mutex<VecDeque<Profile>>> = Lazy::new(Default::default);
+
+/// Metadata of a retained flamegraph
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileMeta {
+    pub id: u64,
+    /// Capture time in microseconds since epoch
+    pub timestamp: i64,
+    /// `pprof`, `torch`, `gpu`, or `heap` for the snapshots of the Python heap
+    pub profiler: String,
+    /// Sampling mode of pprof, `cpu` or `wall`
+    pub mode: Option<String>,
+    /// `svg`, `folded` or `json`
+    pub format: String,
+    /// What asked for the flamegraph, `api` unless the caller passed `trigger`
+    pub trigger: String,
+    /// Seconds of samples covered, when the profiler start is known
+    pub window: Option<f64>,
+    pub bytes: usize,
+}
+
+struct Profile {
+    meta: ProfileMeta,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+/// Keep a served flamegraph, dropping the oldest beyond `profiles.max`
+pub fn record(mut meta: ProfileMeta, content_type: &'static str, body: Vec<u8>) {
+    meta.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    meta.bytes = body.len();
+    let message = format!("{} {} from {}", meta.id, meta.profiler, meta.trigger);
+    {
+        let Ok(mut profiles) = PROFILES.lock() else {
+            return;
+        };
+        profiles.push_back(Profile {
+            meta,
+            content_type,
+            body,
+        });
+        let max = MAX_PROFILES.load(Ordering::Relaxed);
+        while profiles.len() > max {
+            profiles.pop_front();
+        }
+    }
+    probing_core::core::events::publish("profiles.captured", "profiles", &message);
+}
+
+fn catalog() -> Vec<ProfileMeta> {
+    PROFILES
+        .lock()
+        .map(|profiles| profiles.iter().map(|p| p.meta.clone()).collect())
+        .unwrap_or_default()
+}
+
+/// `GET /apis/profiles`
+pub async fn list_profiles() -> Json<Vec<ProfileMeta>> {
+    Json(catalog())
+}
+
+/// `GET /apis/profiles/{id}`, the flamegraph as it was served
+pub async fn get_profile(Path(id): Path<u64>) -> Response {
+    let Ok(profiles) = PROFILES.lock() else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    match profiles.iter().find(|p| p.meta.id == id) {
+        Some(profile) => (
+            [("Content-Type", profile.content_type)],
+            profile.body.clone(),
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, format!("no profile {id}")).into_response(),
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ProfileCatalogTable {}
+
+impl CustomTable for ProfileCatalogTable {
+    fn name() -> &'static str {
+        "catalog"
+    }
+
+    fn ordering() -> Option<&'static str> {
+        Some("id")
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("profiler", DataType::Utf8, false),
+            Field::new("mode", DataType::Utf8, true),
+            Field::new("format", DataType::Utf8, false),
+            Field::new("trigger", DataType::Utf8, false),
+            Field::new("window", DataType::Float64, true),
+            Field::new("bytes", DataType::Int64, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let profiles = catalog();
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(
+                    profiles.iter().map(|p| p.id as i64),
+                )),
+                Arc::new(Int64Array::from_iter_values(
+                    profiles.iter().map(|p| p.timestamp),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    profiles.iter().map(|p| p.profiler.as_str()),
+                )),
+                Arc::new(StringArray::from_iter(
+                    profiles.iter().map(|p| p.mode.as_deref()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    profiles.iter().map(|p| p.format.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    profiles.iter().map(|p| p.trigger.as_str()),
+                )),
+                Arc::new(Float64Array::from_iter(profiles.iter().map(|p| p.window))),
+                Arc::new(Int64Array::from_iter_values(
+                    profiles.iter().map(|p| p.bytes as i64),
+                )),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type ProfileCatalogPlugin = TablePluginHelper<ProfileCatalogTable>;
+
+/// Retention of served flamegraphs
+#[derive(Debug, EngineExtension)]
+pub struct ProfilesExtension {
+    /// Number of most recent flamegraphs kept
+    #[option()]
+    max: Maybe<usize>,
+}
+
+impl Default for ProfilesExtension {
+    fn default() -> Self {
+        Self {
+            max: Maybe::Just(DEFAULT_MAX_PROFILES),
+        }
+    }
+}
+
+impl EngineCall for ProfilesExtension {}
+
+impl EngineDatasource for ProfilesExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        name.map(|name| ProfileCatalogPlugin::create(namespace, name))
+    }
+}
+
+impl ProfilesExtension {
+    fn set_max(&mut self, max: Maybe<usize>) -> Result<(), EngineError> {
+        match max {
+            Maybe::Just(n) if n > 0 => {
+                MAX_PROFILES.store(n, Ordering::Relaxed);
+                if let Ok(mut profiles) = PROFILES.lock() {
+                    while profiles.len() > n {
+                        profiles.pop_front();
+                    }
+                }
+                self.max = max;
+                Ok(())
+            }
+            _ => Err(EngineError::InvalidOptionValue(
+                Self::OPTION_MAX.to_string(),
+                max.into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(trigger: &str) -> ProfileMeta {
+        ProfileMeta {
+            id: 0,
+            timestamp: 1,
+            profiler: "pprof".to_string(),
+            mode: Some("cpu".to_string()),
+            format: "folded".to_string(),
+            trigger: trigger.to_string(),
+            window: Some(2.5),
+            bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_record_keeps_latest() {
+        MAX_PROFILES.store(2, Ordering::Relaxed);
+        for trigger in ["a", "b", "c"] {
+            record(meta(trigger), "text/plain", trigger.as_bytes().to_vec());
+        }
+        let profiles = catalog();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].trigger, "b");
+        assert_eq!(profiles[1].trigger, "c");
+        assert_eq!(profiles[1].bytes, 1);
+        assert!(profiles[0].id < profiles[1].id);
+        MAX_PROFILES.store(DEFAULT_MAX_PROFILES, Ordering::Relaxed);
+    }
+}
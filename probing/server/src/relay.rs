@@ -0,0 +1,474 @@
+//! Store-and-forward relay of the heartbeats of all ranks.
+//!
+//! With `relay.dir` set on the master (rank 0), every heartbeat it receives,
+//! hot metrics of the rank included, is appended to a [`RecordLog`] in that
+//! directory, by a writer thread off the heartbeat handlers, and kept in
+//! memory for `relay.retention` seconds, up to [`MAX_HISTORY`] of them. A master
+//! restarted on the same directory replays the log, so the history survives
+//! it. Operators query the master alone instead of every worker:
+//!
+//! ```sql
+//! SELECT rank, max(step), avg(cpu) FROM cluster.metrics GROUP BY rank;
+//! SELECT * FROM cluster.summary;
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::array::TimestampMicrosecondArray;
+use once_cell::sync::Lazy;
+
+use probing_core::core::cluster::now_micros;
+use probing_core::core::{
+    CustomTable, DataType, EngineCall, EngineDatasource, EngineError, EngineExtension,
+    EngineExtensionOption, Field, FieldDoc, Float64Array, Int32Array, Int64Array, Maybe,
+    RecordBatch, Schema, SchemaRef, StringArray, TablePluginHelper, TimeUnit,
+};
+use probing_proto::prelude::Node;
+use probing_store::store::RecordLog;
+
+const DEFAULT_RETENTION: Duration = Duration::from_secs(3600);
+/// Expired segments of the log are looked for at most this often
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+/// Heartbeats kept in memory, the oldest are dropped first
+const MAX_HISTORY: usize = 262_144;
+/// Writes queued for the log before heartbeats stop being persisted
+const MAX_PENDING_WRITES: usize = 4096;
+
+/// Write to the log, done by its writer thread
+enum LogWrite {
+    Append(u64, String),
+    Expire(u64),
+}
+
+struct Relay {
+    /// Queue of the writer thread, which owns the log so that no heartbeat
+    /// handler waits for the disk
+    writes: SyncSender<LogWrite>,
+    writer: JoinHandle<()>,
+    /// Heartbeats within the retention, oldest first
+    history: VecDeque<Node>,
+    last_expire: Instant,
+}
+
+static RELAY: Lazy<Mutex<Option<Relay>>> = Lazy::new(Default::default);
+
+/// Retention in microseconds
+static RETENTION: AtomicU64 = AtomicU64::new(DEFAULT_RETENTION.as_micros() as u64);
+
+fn retention_start() -> u64 {
+    now_micros().saturating_sub(RETENTION.load(Ordering::Relaxed))
+}
+
+fn write_log(mut log: RecordLog, writes: Receiver<LogWrite>) {
+    for write in writes {
+        match write {
+            LogWrite::Append(timestamp, record) => {
+                if let Err(err) = log.append(timestamp, &record) {
+                    log::warn!("failed to relay a heartbeat: {err}");
+                }
+            }
+            LogWrite::Expire(before) => {
+                if let Err(err) = log.expire(before) {
+                    log::warn!("failed to expire relayed heartbeats: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// Relay the heartbeats to the log in `dir`, replaying the heartbeats it
+/// holds; an empty `dir` stops relaying
+fn open(dir: &str) -> anyhow::Result<()> {
+    let previous = RELAY.lock().map_err(|e| anyhow::anyhow!("{e}"))?.take();
+    // the queued writes land before the log is read again
+    if let Some(Relay { writes, writer, .. }) = previous {
+        drop(writes);
+        let _ = writer.join();
+    }
+    let relay = match dir {
+        "" => None,
+        dir => {
+            let log = RecordLog::open(dir)?;
+            let mut history = log
+                .replay(retention_start())?
+                .into_iter()
+                .filter_map(|(_, record)| serde_json::from_str::<Node>(&record).ok())
+                .collect::<VecDeque<_>>();
+            history.drain(..history.len().saturating_sub(MAX_HISTORY));
+            log::info!("relaying heartbeats to {dir}, {} replayed", history.len());
+            let (writes, queue) = std::sync::mpsc::sync_channel(MAX_PENDING_WRITES);
+            let writer = std::thread::Builder::new()
+                .name("probing-relay".to_string())
+                .spawn(move || write_log(log, queue))?;
+            Some(Relay {
+                writes,
+                writer,
+                history,
+                last_expire: Instant::now(),
+            })
+        }
+    };
+    *RELAY.lock().map_err(|e| anyhow::anyhow!("{e}"))? = relay;
+    Ok(())
+}
+
+/// Record a heartbeat received by the master, once it is timestamped. The
+/// log is written by its own thread, a heartbeat is only queued here.
+pub fn record(node: &Node) {
+    let record = match serde_json::to_string(node) {
+        Ok(record) => Some(record),
+        Err(err) => {
+            log::warn!("failed to serialize the heartbeat of {node}: {err}");
+            None
+        }
+    };
+    let Ok(mut relay) = RELAY.lock() else {
+        return;
+    };
+    let Some(relay) = relay.as_mut() else {
+        return;
+    };
+    if let Some(record) = record {
+        match relay
+            .writes
+            .try_send(LogWrite::Append(node.timestamp, record))
+        {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                log::warn!("relay log is behind, the heartbeat of {node} is not persisted")
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                log::warn!("relay log is closed, the heartbeat of {node} is not persisted")
+            }
+        }
+    }
+    relay.history.push_back(node.clone());
+
+    let start = retention_start();
+    while relay.history.front().is_some_and(|n| n.timestamp < start)
+        || relay.history.len() > MAX_HISTORY
+    {
+        relay.history.pop_front();
+    }
+    if relay.last_expire.elapsed() >= EXPIRE_INTERVAL {
+        relay.last_expire = Instant::now();
+        let _ = relay.writes.try_send(LogWrite::Expire(start));
+    }
+}
+
+fn history() -> Vec<Node> {
+    let start = retention_start();
+    RELAY
+        .lock()
+        .ok()
+        .and_then(|relay| {
+            relay.as_ref().map(|relay| {
+                relay
+                    .history
+                    .iter()
+                    .filter(|node| node.timestamp >= start)
+                    .cloned()
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Heartbeats relayed by the master, one row per heartbeat
+#[derive(Default, Debug)]
+pub struct MetricsTable {}
+
+impl CustomTable for MetricsTable {
+    fn name() -> &'static str {
+        "metrics"
+    }
+
+    fn description() -> &'static str {
+        "Heartbeats and hot metrics of every rank relayed by the master, see relay.dir"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                false,
+            )
+            .with_doc("When the master received the heartbeat"),
+            Field::new("host", DataType::Utf8, false),
+            Field::new("addr", DataType::Utf8, false),
+            Field::new("rank", DataType::Int32, true),
+            Field::new("status", DataType::Utf8, true),
+            Field::new("step", DataType::Int64, true).with_doc("Optimizer steps taken"),
+            Field::new("loss", DataType::Float64, true),
+            Field::new("cpu", DataType::Float64, true)
+                .with_doc("CPU usage of the process")
+                .with_unit("%"),
+            Field::new("gpu_util", DataType::Float64, true).with_unit("%"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let nodes = history();
+        let metric = |f: fn(&probing_proto::prelude::ProbeStatus) -> Option<f64>| {
+            Arc::new(Float64Array::from(
+                nodes
+                    .iter()
+                    .map(|n| n.metrics.as_ref().and_then(f))
+                    .collect::<Vec<_>>(),
+            ))
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(TimestampMicrosecondArray::from_iter_values(
+                    nodes.iter().map(|n| n.timestamp as i64),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    nodes.iter().map(|n| n.host.as_str()),
+                )),
+                Arc::new(StringArray::from_iter_values(
+                    nodes.iter().map(|n| n.addr.as_str()),
+                )),
+                Arc::new(Int32Array::from(
+                    nodes.iter().map(|n| n.rank).collect::<Vec<_>>(),
+                )),
+                Arc::new(StringArray::from(
+                    nodes.iter().map(|n| n.status.clone()).collect::<Vec<_>>(),
+                )),
+                Arc::new(Int64Array::from(
+                    nodes
+                        .iter()
+                        .map(|n| n.metrics.as_ref()?.step.map(|step| step as i64))
+                        .collect::<Vec<_>>(),
+                )),
+                metric(|m| m.loss),
+                metric(|m| m.cpu),
+                metric(|m| m.gpu_util),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type MetricsPlugin = TablePluginHelper<MetricsTable>;
+
+/// Latest heartbeat of every node, by `host:addr`
+fn latest() -> Vec<Node> {
+    let mut latest = BTreeMap::new();
+    for node in history() {
+        latest.insert(format!("{}:{}", node.host, node.addr), node);
+    }
+    latest.into_values().collect()
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// The job at a glance, from the latest heartbeat of every node
+#[derive(Default, Debug)]
+pub struct SummaryTable {}
+
+impl CustomTable for SummaryTable {
+    fn name() -> &'static str {
+        "summary"
+    }
+
+    fn description() -> &'static str {
+        "Ranks, progress and load of the job from the latest relayed heartbeat of every node"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("nodes", DataType::Int64, false),
+            Field::new("running", DataType::Int64, false)
+                .with_doc("Nodes whose latest heartbeat says running"),
+            Field::new("min_step", DataType::Int64, true),
+            Field::new("max_step", DataType::Int64, true),
+            Field::new("slowest_rank", DataType::Int32, true)
+                .with_doc("Rank with the fewest steps, a straggler if far behind max_step"),
+            Field::new("mean_loss", DataType::Float64, true),
+            Field::new("mean_cpu", DataType::Float64, true).with_unit("%"),
+            Field::new("max_cpu", DataType::Float64, true).with_unit("%"),
+            Field::new("mean_gpu_util", DataType::Float64, true).with_unit("%"),
+            Field::new(
+                "oldest_heartbeat",
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                true,
+            )
+            .with_doc("Latest heartbeat of the node heard from last, a hung rank if old"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let nodes = latest();
+        let metrics = nodes
+            .iter()
+            .filter_map(|n| Some((n.rank, n.metrics.as_ref()?)))
+            .collect::<Vec<_>>();
+        let steps = metrics
+            .iter()
+            .filter_map(|(rank, m)| Some((m.step?, *rank)))
+            .collect::<Vec<_>>();
+        let slowest = steps.iter().min_by_key(|(step, _)| *step);
+        let max_cpu = metrics
+            .iter()
+            .filter_map(|(_, m)| m.cpu)
+            .fold(None, |max: Option<f64>, cpu| {
+                Some(max.map_or(cpu, |m| m.max(cpu)))
+            });
+        let running = nodes
+            .iter()
+            .filter(|n| n.status.as_deref() == Some("running"))
+            .count();
+
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(Int64Array::from(vec![nodes.len() as i64])),
+                Arc::new(Int64Array::from(vec![running as i64])),
+                Arc::new(Int64Array::from(
+                    vec![slowest.map(|(step, _)| *step as i64)],
+                )),
+                Arc::new(Int64Array::from(vec![steps
+                    .iter()
+                    .map(|(step, _)| *step as i64)
+                    .max()])),
+                Arc::new(Int32Array::from(vec![slowest.and_then(|(_, rank)| *rank)])),
+                Arc::new(Float64Array::from(vec![mean(
+                    metrics.iter().filter_map(|(_, m)| m.loss),
+                )])),
+                Arc::new(Float64Array::from(vec![mean(
+                    metrics.iter().filter_map(|(_, m)| m.cpu),
+                )])),
+                Arc::new(Float64Array::from(vec![max_cpu])),
+                Arc::new(Float64Array::from(vec![mean(
+                    metrics.iter().filter_map(|(_, m)| m.gpu_util),
+                )])),
+                Arc::new(TimestampMicrosecondArray::from(vec![nodes
+                    .iter()
+                    .map(|n| n.timestamp as i64)
+                    .min()])),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type SummaryPlugin = TablePluginHelper<SummaryTable>;
+
+/// Relay of the heartbeats of all ranks on the master
+#[derive(Debug, Default, EngineExtension)]
+pub struct RelayExtension {
+    /// Directory the relayed heartbeats are persisted to, enables the relay
+    #[option()]
+    dir: Maybe<String>,
+
+    /// Seconds of heartbeats kept (default 3600)
+    #[option()]
+    retention: Maybe<u64>,
+}
+
+impl EngineCall for RelayExtension {}
+
+impl EngineDatasource for RelayExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        match name {
+            Some("summary") => Some(SummaryPlugin::create(namespace, "summary")),
+            Some(name) => Some(MetricsPlugin::create(namespace, name)),
+            None => None,
+        }
+    }
+}
+
+impl RelayExtension {
+    fn set_dir(&mut self, dir: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = dir.clone().into();
+        open(value.trim()).map_err(|err| {
+            EngineError::InvalidOptionValue(Self::OPTION_DIR.to_string(), err.to_string())
+        })?;
+        self.dir = dir;
+        Ok(())
+    }
+
+    fn set_retention(&mut self, retention: Maybe<u64>) -> Result<(), EngineError> {
+        let retention_value = match retention {
+            Maybe::Just(0) => {
+                return Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_RETENTION.to_string(),
+                    "0".to_string(),
+                ))
+            }
+            Maybe::Just(seconds) => Duration::from_secs(seconds),
+            Maybe::Nothing => DEFAULT_RETENTION,
+        };
+        RETENTION.store(retention_value.as_micros() as u64, Ordering::Relaxed);
+        self.retention = retention;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::array::Array;
+
+    use super::*;
+
+    #[test]
+    fn test_relay_replay() {
+        let dir = std::env::temp_dir().join(format!("probing-relay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let node = |rank: i32, step: u64| Node {
+            host: "node1".to_string(),
+            addr: format!("10.0.0.1:{}", 9700 + rank),
+            rank: Some(rank),
+            status: Some("running".to_string()),
+            timestamp: now_micros(),
+            metrics: Some(probing_proto::prelude::ProbeStatus {
+                step: Some(step),
+                cpu: Some(50.0 + rank as f64),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        open(dir.to_str().unwrap()).unwrap();
+        record(&node(0, 10));
+        record(&node(1, 7));
+        record(&node(0, 12));
+        assert_eq!(history().len(), 3);
+
+        // a restarted master replays the log
+        open(dir.to_str().unwrap()).unwrap();
+        assert_eq!(history().len(), 3);
+        let latest = latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].metrics.as_ref().unwrap().step, Some(12));
+
+        let summary = &SummaryTable::data()[0];
+        let slowest = summary
+            .column(4)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(slowest.value(0), 1);
+        assert_eq!(MetricsTable::data()[0].num_rows(), 3);
+
+        open("").unwrap();
+        assert!(history().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,706 @@
+//! Replication of critical probe tables to peer probes.
+//!
+//! With `replication.peers` and `replication.tables` set, the probe copies the
+//! listed tables to its peers every `replication.interval` seconds through the
+//! distributed entity store. Each peer keeps the copies it received under the
+//! `replicas` namespace, so the data of a crashed rank can still be queried:
+//!
+//! ```sql
+//! SELECT * FROM replicas."analysis.anomalies" WHERE source = 'node1:1234'
+//! ```
+//!
+//! Replicas written with `replication.ttl` expire, as do their rows by their
+//! `timestamp` column. Every `replication.compaction_interval` seconds the
+//! received replicas are compacted: expired data is dropped and small segments
+//! of probes that stopped replicating are merged into one. Each run is
+//! recorded in `store.compactions`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use datafusion::catalog::TableProvider;
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    ArrayRef, CustomNamespace, CustomTable, DataType, Engine, EngineCall, EngineDatasource,
+    EngineError, EngineExtension, EngineExtensionOption, Field, Int64Array, LazyTableSource, Maybe,
+    NamespacePluginHelper, RecordBatch, Schema, SchemaRef, TablePluginHelper,
+};
+use probing_core::storage::replica::MERGED_WORKER;
+use probing_core::storage::{
+    ConsistencyLevel, DistributedEntityStore, EntityStore, MemoryStore, RemoteStoreClient,
+    TableReplica, TopologyView,
+};
+use probing_proto::prelude::DataFrame;
+
+use crate::engine::ENGINE;
+use crate::report::get_hostname;
+use crate::server::SERVER_RUNTIME;
+use crate::shutdown::wait_for_shutdown;
+
+/// Replicas received from peer probes
+pub(crate) static REPLICA_STORE: Lazy<Arc<MemoryStore>> = Lazy::new(Default::default);
+
+/// Last replicas this probe sent, its own copy of what the peers hold
+static SENT_REPLICAS: Lazy<Arc<MemoryStore>> = Lazy::new(Default::default);
+
+/// Names of the replicated tables held in [`REPLICA_STORE`]
+static REPLICA_TABLES: Lazy<RwLock<BTreeSet<String>>> = Lazy::new(Default::default);
+
+#[derive(Clone, Debug)]
+struct ReplicationConfig {
+    peers: Vec<String>,
+    tables: Vec<String>,
+    consistency: ConsistencyLevel,
+}
+
+impl Default for ReplicationConfig {
+    fn default() -> Self {
+        Self {
+            peers: vec![],
+            tables: vec![],
+            consistency: ConsistencyLevel::Quorum,
+        }
+    }
+}
+
+static REPLICATION_CONFIG: Lazy<RwLock<ReplicationConfig>> = Lazy::new(Default::default);
+
+/// Store sending the replicas of this probe to its peers, built on first use
+/// and dropped when `replication.peers` or `replication.consistency` change
+static PEER_STORE: Lazy<Mutex<Option<Arc<DistributedEntityStore>>>> = Lazy::new(Default::default);
+
+/// Bumped whenever the interval changes so that stale workers exit
+static REPLICATION_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// TTL attached to the replicas sent to the peers, in micros (0 for none)
+static REPLICA_TTL: AtomicU64 = AtomicU64::new(0);
+
+const DEFAULT_COMPACTION_INTERVAL: u64 = 60;
+
+/// Segments not refreshed for that long belong to probes that stopped
+/// replicating, and are merged when small
+const STALE_SEGMENT: Duration = Duration::from_secs(600);
+const SMALL_SEGMENT_ROWS: usize = 1000;
+
+/// Number of compaction runs kept in `store.compactions`
+const MAX_COMPACTIONS: usize = 1000;
+
+static COMPACTION_INTERVAL: AtomicU64 = AtomicU64::new(DEFAULT_COMPACTION_INTERVAL);
+static COMPACTION_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Set once the first replica is received, compaction has nothing to do before
+static COMPACTION_STARTED: AtomicBool = AtomicBool::new(false);
+
+static COMPACTIONS: Lazy<Mutex<VecDeque<CompactionStats>>> = Lazy::new(Default::default);
+
+pub(crate) fn now_micros() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Identity of this probe in the replicas held by its peers
+pub(crate) fn local_worker() -> String {
+    format!(
+        "{}:{}",
+        get_hostname().unwrap_or("localhost".to_string()),
+        std::process::id()
+    )
+}
+
+/// Record a replica written into [`REPLICA_STORE`] under `key`
+pub(crate) fn index_replica(key: &str) {
+    let Some(id) = key.strip_prefix("table_replica::") else {
+        return;
+    };
+    if let Some((table, _worker)) = id.rsplit_once('@') {
+        REPLICA_TABLES.write().unwrap().insert(table.to_string());
+    }
+    if !COMPACTION_STARTED.swap(true, Ordering::SeqCst) {
+        spawn_compaction_worker(COMPACTION_GENERATION.load(Ordering::SeqCst));
+    }
+}
+
+/// Store client talking to the `/apis/store` endpoints of a peer probe
+struct HttpStoreClient {
+    addr: String,
+}
+
+/// `server.auth_token` of this probe, sent to the peers, which share it
+async fn auth_token() -> Option<String> {
+    probing_core::config::get("server.auth_token")
+        .await
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+impl HttpStoreClient {
+    fn url(&self, key: &str) -> String {
+        format!("http://{}/apis/store/{key}", self.addr)
+    }
+
+    fn with_token<B>(
+        request: ureq::RequestBuilder<B>,
+        token: Option<&str>,
+    ) -> ureq::RequestBuilder<B> {
+        match token {
+            Some(token) => request.header("X-Probing-Token", token),
+            None => request,
+        }
+    }
+
+    fn agent() -> ureq::Agent {
+        ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .http_status_as_error(false)
+            .build()
+            .into()
+    }
+}
+
+#[async_trait]
+impl RemoteStoreClient for HttpStoreClient {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let (url, data, token) = (self.url(key), data.to_vec(), auth_token().await);
+        tokio::task::spawn_blocking(move || {
+            let rsp =
+                Self::with_token(Self::agent().post(&url), token.as_deref()).send(&data[..])?;
+            match rsp.status().is_success() {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!("{url}: {}", rsp.status())),
+            }
+        })
+        .await?
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let (url, token) = (self.url(key), auth_token().await);
+        tokio::task::spawn_blocking(move || {
+            let mut rsp = Self::with_token(Self::agent().get(&url), token.as_deref()).call()?;
+            match rsp.status().as_u16() {
+                404 => Ok(None),
+                200 => Ok(Some(rsp.body_mut().read_to_vec()?)),
+                status => Err(anyhow::anyhow!("{url}: {status}")),
+            }
+        })
+        .await?
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        let (url, token) = (self.url(key), auth_token().await);
+        tokio::task::spawn_blocking(move || {
+            let rsp = Self::with_token(Self::agent().delete(&url), token.as_deref()).call()?;
+            match rsp.status().is_success() {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!("{url}: {}", rsp.status())),
+            }
+        })
+        .await?
+    }
+
+    async fn is_healthy(&self) -> bool {
+        self.get("healthz").await.is_ok()
+    }
+}
+
+async fn build_store(config: &ReplicationConfig) -> DistributedEntityStore {
+    let worker = local_worker();
+    let mut workers_per_node = HashMap::new();
+    workers_per_node.insert(worker.clone(), vec![worker.clone()]);
+    for peer in config.peers.iter() {
+        workers_per_node.insert(peer.clone(), vec![peer.clone()]);
+    }
+    let topology = TopologyView::new(workers_per_node, config.peers.len() + 1);
+
+    let store = DistributedEntityStore::new(
+        worker.clone(),
+        worker,
+        topology,
+        SENT_REPLICAS.clone(),
+        config.peers.len(),
+    )
+    .with_consistency(config.consistency.clone());
+    for peer in config.peers.iter() {
+        store
+            .add_remote_client(
+                peer.clone(),
+                Arc::new(HttpStoreClient { addr: peer.clone() }),
+            )
+            .await;
+    }
+    store
+}
+
+/// The store sending replicas to the peers of `config`, kept across rounds
+async fn peer_store(config: &ReplicationConfig) -> Arc<DistributedEntityStore> {
+    if let Some(store) = PEER_STORE.lock().unwrap().clone() {
+        return store;
+    }
+    let store = Arc::new(build_store(config).await);
+    PEER_STORE.lock().unwrap().get_or_insert(store).clone()
+}
+
+/// Copy the configured tables to the peers
+pub(crate) async fn replicate() -> Result<()> {
+    let config = REPLICATION_CONFIG.read().unwrap().clone();
+    if config.peers.is_empty() || config.tables.is_empty() {
+        return Ok(());
+    }
+
+    let store = peer_store(&config).await;
+    let timestamp = now_micros();
+    let ttl = Some(REPLICA_TTL.load(Ordering::Relaxed)).filter(|ttl| *ttl > 0);
+
+    for table in config.tables.iter() {
+        let (df, unit) = {
+            let engine = ENGINE.read().await;
+            let df = engine.async_query(format!("SELECT * FROM {table}")).await;
+            (df, timestamp_unit(&engine, table).await)
+        };
+        let mut df = match df {
+            Ok(df) => df,
+            Err(err) => {
+                log::debug!("skip replicating table {table}: {err}");
+                continue;
+            }
+        };
+        probing_core::core::cluster::correct_timestamps(&mut df);
+        let replica = TableReplica::new(table, store.worker_id(), timestamp, df)
+            .with_ttl(ttl)
+            .with_timestamp_unit(unit);
+        match store.put(&replica).await {
+            Ok(()) => log::debug!("replicated {table} to {:?}", config.peers),
+            Err(err) => log::warn!("failed to replicate {table}: {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Unit of the `timestamp` column of `table`, from its schema
+pub(crate) async fn timestamp_unit(engine: &Engine, table: &str) -> Option<String> {
+    let columns = engine.describe(table).await.ok()?;
+    columns.into_iter().find(|c| c.name == "timestamp")?.unit
+}
+
+/// Push `frames`, a snapshot of tables taken at `timestamp` with the unit of
+/// their `timestamp` column, to the peers of `replication.peers` as replicas
+/// of this probe
+pub(crate) async fn push_replicas(
+    frames: Vec<(String, DataFrame, Option<String>)>,
+    timestamp: u64,
+) -> Result<()> {
+    let config = REPLICATION_CONFIG.read().unwrap().clone();
+    if config.peers.is_empty() {
+        anyhow::bail!("no store is configured, set probing.replication.peers first");
+    }
+
+    let store = peer_store(&config).await;
+    let ttl = Some(REPLICA_TTL.load(Ordering::Relaxed)).filter(|ttl| *ttl > 0);
+    for (table, df, unit) in frames {
+        let replica = TableReplica::new(&table, store.worker_id(), timestamp, df)
+            .with_ttl(ttl)
+            .with_timestamp_unit(unit);
+        store
+            .put(&replica)
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to push {table}: {err}"))?;
+    }
+    Ok(())
+}
+
+async fn replication_worker(interval: Duration, generation: u64) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if REPLICATION_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        if probing_core::core::killswitch::is_dormant() {
+            continue;
+        }
+        if let Err(err) = replicate().await {
+            log::error!("failed to replicate probe tables: {err}");
+        }
+    }
+}
+
+/// Outcome of one compaction of [`REPLICA_STORE`]
+#[derive(Debug, Default, Clone)]
+struct CompactionStats {
+    timestamp: u64,
+    segments_before: usize,
+    segments_after: usize,
+    expired_segments: usize,
+    expired_rows: usize,
+    merged_segments: usize,
+    bytes_before: usize,
+    bytes_after: usize,
+    duration_us: u64,
+}
+
+/// Remove `replica` unless its source wrote a new one since it was read
+async fn delete_unchanged(replica: &TableReplica) -> Result<()> {
+    let current = REPLICA_STORE.get::<TableReplica>(&replica.id).await?;
+    if current.is_some_and(|r| r.timestamp == replica.timestamp) {
+        REPLICA_STORE.del::<TableReplica>(&replica.id).await?;
+    }
+    Ok(())
+}
+
+/// Write back `replica`, trimmed of its expired rows, unless its source wrote
+/// a new one since it was read
+async fn put_unchanged(replica: &TableReplica) -> Result<bool> {
+    let current = REPLICA_STORE.get::<TableReplica>(&replica.id).await?;
+    if !current.is_some_and(|r| r.timestamp == replica.timestamp) {
+        return Ok(false);
+    }
+    REPLICA_STORE.put(replica).await?;
+    Ok(true)
+}
+
+/// Drop the expired replicas and rows, then merge the small stale segments of
+/// each table
+async fn compact() -> Result<CompactionStats> {
+    let started = Instant::now();
+    let now = now_micros();
+    let replicas = REPLICA_STORE.list_all::<TableReplica>().await?;
+    let mut stats = CompactionStats {
+        timestamp: now,
+        segments_before: replicas.len(),
+        bytes_before: REPLICA_STORE.raw_entities_nbytes().await,
+        ..Default::default()
+    };
+
+    let mut tables: BTreeMap<String, Vec<TableReplica>> = BTreeMap::new();
+    for mut replica in replicas {
+        if replica.is_expired(now) {
+            delete_unchanged(&replica).await?;
+            stats.expired_segments += 1;
+            continue;
+        }
+        let expired = replica.expire_rows(now);
+        if expired > 0 {
+            stats.expired_rows += expired;
+            if replica.data.is_empty() {
+                delete_unchanged(&replica).await?;
+                stats.expired_segments += 1;
+                continue;
+            }
+            // replaced by its source since it was read, left to the next run
+            if !put_unchanged(&replica).await? {
+                continue;
+            }
+        }
+        tables
+            .entry(replica.table.clone())
+            .or_default()
+            .push(replica);
+    }
+
+    let stale = now.saturating_sub(STALE_SEGMENT.as_micros() as u64);
+    for segments in tables.values() {
+        // the merged segment goes first so that merging into it keeps its columns
+        let mut small: Vec<TableReplica> = segments
+            .iter()
+            .filter(|r| r.worker == MERGED_WORKER)
+            .cloned()
+            .collect();
+        small.extend(
+            segments
+                .iter()
+                .filter(|r| {
+                    r.worker != MERGED_WORKER
+                        && r.timestamp < stale
+                        && r.data.len() < SMALL_SEGMENT_ROWS
+                })
+                .cloned(),
+        );
+        let sources: Vec<&TableReplica> =
+            small.iter().filter(|r| r.worker != MERGED_WORKER).collect();
+        if small.len() < 2 || sources.is_empty() {
+            continue;
+        }
+        let Some(merged) = TableReplica::merge(&small) else {
+            log::debug!(
+                "skip compacting {}: segments disagree on columns",
+                small[0].table
+            );
+            continue;
+        };
+        REPLICA_STORE.put(&merged).await?;
+        stats.merged_segments += sources.len();
+        for replica in sources {
+            delete_unchanged(replica).await?;
+        }
+    }
+
+    let remaining = REPLICA_STORE.list_all::<TableReplica>().await?;
+    *REPLICA_TABLES.write().unwrap() = remaining.iter().map(|r| r.table.clone()).collect();
+    stats.segments_after = remaining.len();
+    stats.bytes_after = REPLICA_STORE.raw_entities_nbytes().await;
+    stats.duration_us = started.elapsed().as_micros() as u64;
+    Ok(stats)
+}
+
+async fn compaction_worker(interval: Duration, generation: u64) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if COMPACTION_GENERATION.load(Ordering::SeqCst) != generation {
+            break;
+        }
+        if probing_core::core::killswitch::is_dormant() {
+            continue;
+        }
+        match compact().await {
+            Ok(stats) => {
+                let mut compactions = COMPACTIONS.lock().unwrap();
+                if compactions.len() >= MAX_COMPACTIONS {
+                    compactions.pop_front();
+                }
+                compactions.push_back(stats);
+            }
+            Err(err) => log::error!("failed to compact replicas: {err}"),
+        }
+    }
+}
+
+fn spawn_compaction_worker(generation: u64) {
+    let seconds = COMPACTION_INTERVAL.load(Ordering::SeqCst);
+    if seconds == 0 {
+        return;
+    }
+    SERVER_RUNTIME.spawn(async move {
+        tokio::select! {
+            _ = compaction_worker(Duration::from_secs(seconds), generation) => {}
+            _ = wait_for_shutdown() => {}
+        }
+    });
+}
+
+/// Compaction runs over the replicas received from peer probes
+#[derive(Default, Debug)]
+pub struct CompactionsTable {}
+
+impl CustomTable for CompactionsTable {
+    fn name() -> &'static str {
+        "compactions"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("timestamp", DataType::Int64, false),
+            Field::new("segments_before", DataType::Int64, false),
+            Field::new("segments_after", DataType::Int64, false),
+            Field::new("expired_segments", DataType::Int64, false),
+            Field::new("expired_rows", DataType::Int64, false),
+            Field::new("merged_segments", DataType::Int64, false),
+            Field::new("bytes_before", DataType::Int64, false),
+            Field::new("bytes_after", DataType::Int64, false),
+            Field::new("duration_us", DataType::Int64, false),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let compactions = COMPACTIONS.lock().unwrap();
+        let column = |f: fn(&CompactionStats) -> u64| -> ArrayRef {
+            Arc::new(Int64Array::from_iter_values(
+                compactions.iter().map(|c| f(c) as i64),
+            ))
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                column(|c| c.timestamp),
+                column(|c| c.segments_before as u64),
+                column(|c| c.segments_after as u64),
+                column(|c| c.expired_segments as u64),
+                column(|c| c.expired_rows as u64),
+                column(|c| c.merged_segments as u64),
+                column(|c| c.bytes_before as u64),
+                column(|c| c.bytes_after as u64),
+                column(|c| c.duration_us),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type CompactionsPlugin = TablePluginHelper<CompactionsTable>;
+
+/// Tables replicated to this probe by its peers, one table per source table
+#[derive(Default, Debug)]
+pub struct ReplicaNamespace {}
+
+#[async_trait]
+impl CustomNamespace for ReplicaNamespace {
+    fn name() -> &'static str {
+        "replicas"
+    }
+
+    fn list() -> Vec<String> {
+        REPLICA_TABLES.read().unwrap().iter().cloned().collect()
+    }
+
+    async fn table(expr: String) -> datafusion::error::Result<Option<Arc<dyn TableProvider>>> {
+        let replicas = REPLICA_STORE
+            .list_all::<TableReplica>()
+            .await
+            .unwrap_or_default();
+        let mut data = vec![];
+        for replica in replicas.iter().filter(|r| r.table == expr) {
+            match probing_core::archive::dataframe_to_recordbatch(&replica.with_source()) {
+                Ok(batch) => data.push(batch),
+                Err(err) => log::warn!("skip replica {}: {err}", replica.id),
+            }
+        }
+        // replicas of one table coming from different versions may disagree on schema
+        if let Some(schema) = data.first().map(|b| b.schema()) {
+            data.retain(|b| b.schema() == schema);
+        }
+        let table: Arc<dyn TableProvider> = Arc::new(LazyTableSource {
+            name: expr,
+            schema: data.first().map(|b| b.schema()),
+            data,
+        });
+        Ok(Some(table))
+    }
+}
+
+pub type ReplicaPlugin = NamespacePluginHelper<ReplicaNamespace>;
+
+/// Replication of critical tables to peer probes
+#[derive(Debug, Default, EngineExtension)]
+pub struct ReplicationExtension {
+    /// Comma separated `host:port` addresses of the peer probes
+    #[option()]
+    peers: Maybe<String>,
+
+    /// Comma separated tables to replicate (e.g. analysis.anomalies)
+    #[option()]
+    tables: Maybe<String>,
+
+    /// Acknowledgements required per write: primary, quorum or all
+    #[option()]
+    consistency: Maybe<String>,
+
+    /// Seconds between two replications (0 to disable)
+    #[option()]
+    interval: Maybe<u64>,
+
+    /// Seconds the replicas sent to the peers, and their rows, are kept
+    #[option()]
+    ttl: Maybe<u64>,
+
+    /// Seconds between two compactions of the received replicas (0 to disable)
+    #[option()]
+    compaction_interval: Maybe<u64>,
+}
+
+impl EngineCall for ReplicationExtension {}
+
+impl EngineDatasource for ReplicationExtension {
+    fn datasrc(
+        &self,
+        namespace: &str,
+        name: Option<&str>,
+    ) -> Option<std::sync::Arc<dyn probing_core::core::Plugin + Sync + Send>> {
+        match name {
+            Some(name) => Some(CompactionsPlugin::create(namespace, name)),
+            None => Some(ReplicaPlugin::create(namespace)),
+        }
+    }
+}
+
+impl ReplicationExtension {
+    fn set_peers(&mut self, peers: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = peers.clone().into();
+        REPLICATION_CONFIG.write().unwrap().peers = split_list(&value);
+        PEER_STORE.lock().unwrap().take();
+        self.peers = peers;
+        Ok(())
+    }
+
+    fn set_tables(&mut self, tables: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = tables.clone().into();
+        REPLICATION_CONFIG.write().unwrap().tables = split_list(&value);
+        self.tables = tables;
+        Ok(())
+    }
+
+    fn set_consistency(&mut self, consistency: Maybe<String>) -> Result<(), EngineError> {
+        let value: String = consistency.clone().into();
+        let level = match value.to_lowercase().as_str() {
+            "" | "quorum" => ConsistencyLevel::Quorum,
+            "primary" => ConsistencyLevel::Primary,
+            "all" => ConsistencyLevel::All,
+            _ => {
+                return Err(EngineError::InvalidOptionValue(
+                    Self::OPTION_CONSISTENCY.to_string(),
+                    value,
+                ))
+            }
+        };
+        REPLICATION_CONFIG.write().unwrap().consistency = level;
+        PEER_STORE.lock().unwrap().take();
+        self.consistency = consistency;
+        Ok(())
+    }
+
+    fn set_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let generation = REPLICATION_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Maybe::Just(seconds) = interval {
+            if seconds > 0 {
+                SERVER_RUNTIME.spawn(async move {
+                    tokio::select! {
+                        _ = replication_worker(Duration::from_secs(seconds), generation) => {}
+                        _ = wait_for_shutdown() => {}
+                    }
+                });
+            }
+        }
+        self.interval = interval;
+        Ok(())
+    }
+
+    fn set_ttl(&mut self, ttl: Maybe<u64>) -> Result<(), EngineError> {
+        let seconds = match ttl {
+            Maybe::Just(seconds) => seconds,
+            Maybe::Nothing => 0,
+        };
+        REPLICA_TTL.store(seconds.saturating_mul(1_000_000), Ordering::Relaxed);
+        self.ttl = ttl;
+        Ok(())
+    }
+
+    fn set_compaction_interval(&mut self, interval: Maybe<u64>) -> Result<(), EngineError> {
+        let seconds = match interval {
+            Maybe::Just(seconds) => seconds,
+            Maybe::Nothing => DEFAULT_COMPACTION_INTERVAL,
+        };
+        COMPACTION_INTERVAL.store(seconds, Ordering::SeqCst);
+        let generation = COMPACTION_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        if COMPACTION_STARTED.load(Ordering::SeqCst) {
+            spawn_compaction_worker(generation);
+        }
+        self.compaction_interval = interval;
+        Ok(())
+    }
+}
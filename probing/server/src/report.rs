@@ -1,10 +1,183 @@
+//! Node heartbeats sent to one or more report sinks.
+//!
+//! Each sink runs in its own task with its own interval, so a slow or
+//! unreachable destination never delays the others. Sinks are given as specs:
+//!
+//! - `host:port` or `http://host:port`: PUT to `/apis/nodes` of the master
+//! - `store://host:port`: set `probing/nodes/<host>/<addr>` in a TCPStore
+//! - `file:///path`: append one JSON line per heartbeat
+//!
+//! optionally followed by `?interval=<secs>` (default 10).
+//!
+//! Heartbeats to the master also estimate the offset between the node clock
+//! and the master clock, see [`ClockEstimator`].
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
 use anyhow::Result;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use tokio::task::JoinHandle;
 
 use super::vars::PROBING_ADDRESS;
 use crate::server::SERVER_RUNTIME;
-use probing_proto::prelude::Node;
+use crate::shutdown::wait_for_shutdown;
+use probing_core::core::cluster::{now_micros, set_local_clock_offset, ClockEstimator};
+use probing_proto::prelude::{ClockReply, ClockSync, Node};
+use probing_store::store::TCPStore;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Group of the sink set by `server.report_addr`
+const REPORT_ADDR_GROUP: &str = "report_addr";
+/// Group of the sinks set by `server.report_sinks`
+const REPORT_SINKS_GROUP: &str = "report_sinks";
+
+/// A destination for node heartbeats
+#[async_trait]
+pub trait ReportSink: Send + Sync {
+    fn name(&self) -> String;
+    async fn report(&self, node: &Node) -> Result<()>;
+}
+
+/// The master HTTP server; rank 0 is the master and records itself directly
+struct HttpSink {
+    url: String,
+    clock: Mutex<ClockEstimator>,
+}
+
+#[async_trait]
+impl ReportSink for HttpSink {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn report(&self, node: &Node) -> Result<()> {
+        let mut node = node.clone();
+        if node.rank == Some(0) {
+            node.clock = Some(ClockSync {
+                sent: now_micros(),
+                offset: Some(0),
+                delay: Some(0),
+            });
+            node.timestamp = now_micros();
+            crate::relay::record(&node);
+            probing_core::core::cluster::update_node(node);
+            return Ok(());
+        }
+        let url = self.url.clone();
+        let sent = now_micros();
+        node.clock = self.clock.lock().ok().map(|clock| clock.sync(sent));
+        let reply = tokio::task::spawn_blocking(move || request_remote(&url, node)).await??;
+        let returned = now_micros();
+        log::debug!("node status reported to {}: {reply:?}", self.url);
+
+        // masters predating the clock exchange answer with an empty body
+        if let Ok(reply) = serde_json::from_str::<ClockReply>(&reply) {
+            if let Ok(mut clock) = self.clock.lock() {
+                let (offset, delay) = clock.add(sent, reply, returned);
+                log::debug!("clock offset to master: {offset}us (round trip {delay}us)");
+                set_local_clock_offset(offset);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A TCPStore backend, one key per node
+struct StoreSink {
+    store: TCPStore,
+    endpoint: String,
+}
+
+#[async_trait]
+impl ReportSink for StoreSink {
+    fn name(&self) -> String {
+        format!("store://{}", self.endpoint)
+    }
+
+    async fn report(&self, node: &Node) -> Result<()> {
+        let key = format!("probing/nodes/{}/{}", node.host, node.addr);
+        self.store
+            .set(&key, &serde_json::to_string(node)?)
+            .await
+            .map_err(|e| anyhow::anyhow!("{e}"))
+    }
+}
+
+/// A local JSON lines file
+struct FileSink {
+    path: String,
+}
+
+#[async_trait]
+impl ReportSink for FileSink {
+    fn name(&self) -> String {
+        format!("file://{}", self.path)
+    }
+
+    async fn report(&self, node: &Node) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(node)?)?;
+        Ok(())
+    }
+}
+
+/// Parse a sink spec into the sink and its reporting interval
+pub fn parse_sink(spec: &str) -> Result<(Arc<dyn ReportSink>, Duration)> {
+    let (target, interval) = match spec.split_once("?interval=") {
+        Some((target, secs)) => (
+            target,
+            Duration::from_secs(
+                secs.parse()
+                    .map_err(|_| anyhow::anyhow!("invalid report interval in {spec:?}"))?,
+            ),
+        ),
+        None => (spec, DEFAULT_INTERVAL),
+    };
+    if interval.is_zero() {
+        anyhow::bail!("report interval must be positive in {spec:?}");
+    }
+
+    let sink: Arc<dyn ReportSink> = if let Some(path) = target.strip_prefix("file://") {
+        Arc::new(FileSink {
+            path: path.to_string(),
+        })
+    } else if let Some(endpoint) = target.strip_prefix("store://") {
+        Arc::new(StoreSink {
+            store: TCPStore::new(endpoint.to_string()).with_timeout(Duration::from_secs(1)),
+            endpoint: endpoint.to_string(),
+        })
+    } else {
+        let addr = target.strip_prefix("http://").unwrap_or(target);
+        if addr.is_empty() || addr.contains("://") {
+            anyhow::bail!("unsupported report sink {spec:?}");
+        }
+        Arc::new(HttpSink {
+            url: format!("http://{addr}/apis/nodes"),
+            clock: Default::default(),
+        })
+    };
+    Ok((sink, interval))
+}
+
+struct Worker {
+    sink: Arc<dyn ReportSink>,
+    handle: JoinHandle<()>,
+}
+
+/// Running workers by the option that configured them
+static WORKERS: Lazy<RwLock<BTreeMap<String, Vec<Worker>>>> =
+    Lazy::new(|| RwLock::new(BTreeMap::new()));
+
+/// Local address reported when `PROBING_ADDRESS` is not set
+static LOCAL_ADDR: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(String::new()));
 
 pub fn get_hostname() -> Result<String> {
     let uname = nix::sys::utsname::uname()?;
@@ -12,56 +185,140 @@ pub fn get_hostname() -> Result<String> {
     Ok(hostname)
 }
 
+/// Report to the master at `report_addr`, replacing the previous master
 pub fn start_report_worker(report_addr: String, local_addr: String) {
     log::debug!("start report worker: {local_addr} => {report_addr}");
-    SERVER_RUNTIME.spawn(report_worker(report_addr, local_addr));
+    if let Err(err) = start_group(REPORT_ADDR_GROUP, &report_addr, local_addr) {
+        log::error!("failed to start report worker for {report_addr}: {err}");
+    }
+}
+
+/// Report to the comma separated sink `specs`, replacing the previous sinks.
+/// Nothing is changed if any spec is invalid.
+pub fn start_report_sinks(specs: &str, local_addr: String) -> Result<()> {
+    start_group(REPORT_SINKS_GROUP, specs, local_addr)
 }
 
-async fn report_worker(report_addr: String, local_addr: String) {
-    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(10));
+fn start_group(group: &str, specs: &str, local_addr: String) -> Result<()> {
+    let sinks = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(parse_sink)
+        .collect::<Result<Vec<_>>>()?;
 
+    if let Ok(mut addr) = LOCAL_ADDR.write() {
+        *addr = local_addr;
+    }
+    let workers = sinks
+        .into_iter()
+        .map(|(sink, interval)| {
+            let worker_sink = sink.clone();
+            let handle = SERVER_RUNTIME.spawn(async move {
+                tokio::select! {
+                    _ = report_worker(worker_sink, interval) => {}
+                    _ = wait_for_shutdown() => {}
+                }
+            });
+            Worker { sink, handle }
+        })
+        .collect::<Vec<_>>();
+
+    let previous = WORKERS
+        .write()
+        .map_err(|e| anyhow::anyhow!("{e}"))?
+        .insert(group.to_string(), workers);
+    for worker in previous.into_iter().flatten() {
+        worker.handle.abort();
+    }
+    Ok(())
+}
+
+async fn report_worker(sink: Arc<dyn ReportSink>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
     loop {
         interval.tick().await;
+        // a dormant probe reported so when the kill switch was engaged
+        if !probing_core::core::killswitch::is_dormant() {
+            report_node(sink.as_ref(), current_node("running")).await;
+        }
+    }
+}
 
-        let report_addr = format!("http://{report_addr}/apis/nodes");
-        let hostname = get_hostname().unwrap_or("localhost".to_string());
-        let address = {
-            let probing_address = PROBING_ADDRESS.read().unwrap();
-            if !probing_address.is_empty() {
-                probing_address.clone()
-            } else {
-                local_addr.clone()
-            }
-        };
-        let node = Node {
-            host: hostname,
-            addr: address,
-            local_rank: get_i32_env("LOCAL_RANK"),
-            rank: get_i32_env("RANK"),
-            world_size: get_i32_env("WORLD_SIZE"),
-            group_rank: get_i32_env("GROUP_RANK"),
-            group_world_size: get_i32_env("GROUP_WORLD_SIZE"),
-            role_name: std::env::var("ROLE_NAME").ok(),
-            role_rank: get_i32_env("ROLE_RANK"),
-            role_world_size: get_i32_env("ROLE_WORLD_SIZE"),
-            status: Some("running".to_string()),
-            timestamp: 0,
-        };
-
-        log::debug!("reporting node status to {report_addr}: {node:?}");
-        if node.rank == Some(0) {
-            probing_core::core::cluster::update_node(node);
+/// Send a final heartbeat marking this node as departed to every sink
+pub(crate) async fn report_departure() {
+    report_state("departed").await;
+}
+
+/// Send a heartbeat with node status `state` to every sink right away
+pub(crate) async fn report_state(state: &str) {
+    let sinks = WORKERS
+        .read()
+        .map(|workers| {
+            workers
+                .values()
+                .flatten()
+                .map(|worker| worker.sink.clone())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let node = current_node(state);
+    futures_util::future::join_all(
+        sinks
+            .iter()
+            .map(|sink| report_node(sink.as_ref(), node.clone())),
+    )
+    .await;
+}
+
+/// Framework and Kubernetes labels of this process, detected once
+static LABELS: Lazy<BTreeMap<String, String>> = Lazy::new(|| {
+    let mut labels = probing_core::core::frameworks::detect_self();
+    if let Some(pod) = probing_core::core::k8s::detect_self() {
+        labels.extend(pod.labels());
+    }
+    labels
+});
+
+fn current_node(status: &str) -> Node {
+    let hostname = get_hostname().unwrap_or("localhost".to_string());
+    let address = {
+        let probing_address = PROBING_ADDRESS.read().unwrap();
+        if !probing_address.is_empty() {
+            probing_address.clone()
         } else {
-            let node_display = format!("{node}");
-            match request_remote(&report_addr, node).await {
-                Ok(reply) => {
-                    log::debug!("node status reported to {report_addr}: {reply:?}");
-                }
-                Err(err) => {
-                    log::error!("failed to report {node_display} to {report_addr}, {err}");
-                }
-            }
+            LOCAL_ADDR
+                .read()
+                .map(|addr| addr.clone())
+                .unwrap_or_default()
         }
+    };
+    Node {
+        host: hostname,
+        addr: address,
+        local_rank: get_i32_env("LOCAL_RANK"),
+        rank: get_i32_env("RANK"),
+        world_size: get_i32_env("WORLD_SIZE"),
+        group_rank: get_i32_env("GROUP_RANK"),
+        group_world_size: get_i32_env("GROUP_WORLD_SIZE"),
+        // group framework workers by framework when there is no torchrun role
+        role_name: std::env::var("ROLE_NAME")
+            .ok()
+            .or_else(|| LABELS.get("framework").cloned()),
+        role_rank: get_i32_env("ROLE_RANK"),
+        role_world_size: get_i32_env("ROLE_WORLD_SIZE"),
+        status: Some(status.to_string()),
+        timestamp: 0,
+        clock: None,
+        labels: LABELS.clone(),
+        metrics: probing_core::core::hot_metrics::local().map(|metrics| metrics.status()),
+    }
+}
+
+async fn report_node(sink: &dyn ReportSink, node: Node) {
+    log::debug!("reporting node status to {}: {node:?}", sink.name());
+    if let Err(err) = sink.report(&node).await {
+        log::error!("failed to report {node} to {}, {err}", sink.name());
     }
 }
 
@@ -69,7 +326,7 @@ fn get_i32_env(name: &str) -> Option<i32> {
     std::env::var(name).unwrap_or_default().parse().ok()
 }
 
-async fn request_remote(url: &str, node: Node) -> Result<String> {
+fn request_remote(url: &str, node: Node) -> Result<String> {
     Ok(ureq::put(url)
         .config()
         .no_delay(true)
@@ -79,3 +336,26 @@ async fn request_remote(url: &str, node: Node) -> Result<String> {
         .body_mut()
         .read_to_string()?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink() {
+        let (sink, interval) = parse_sink("127.0.0.1:9922").unwrap();
+        assert_eq!(sink.name(), "http://127.0.0.1:9922/apis/nodes");
+        assert_eq!(interval, DEFAULT_INTERVAL);
+
+        let (sink, interval) = parse_sink("store://master:29500?interval=30").unwrap();
+        assert_eq!(sink.name(), "store://master:29500");
+        assert_eq!(interval, Duration::from_secs(30));
+
+        let (sink, _) = parse_sink("file:///tmp/nodes.jsonl").unwrap();
+        assert_eq!(sink.name(), "file:///tmp/nodes.jsonl");
+
+        assert!(parse_sink("kafka://broker:9092").is_err());
+        assert!(parse_sink("file:///tmp/x?interval=0").is_err());
+        assert!(parse_sink("file:///tmp/x?interval=abc").is_err());
+    }
+}
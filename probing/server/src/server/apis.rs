@@ -1,14 +1,83 @@
-use axum::{routing::get, Router};
+use axum::{
+    routing::{get, post},
+    Router,
+};
 
-use super::{cluster, extension_handler, file_api, profiling, system};
+use super::{
+    cluster, compare, dash, dashboard, events, extension_handler, file_api, grafana, killswitch,
+    profiling, snapshot, store, system, tables,
+};
 
 /// Main router for all API endpoints
 pub fn apis_route() -> Router {
     Router::new()
         .route("/overview", get(system::get_overview_json))
+        .route("/capabilities", get(system::get_capabilities))
+        .route("/handshake", post(system::handshake))
+        .route("/status", get(system::get_status))
+        .route("/resources", get(system::get_resources))
+        .route("/dash", get(dash::get_dash))
+        .route(
+            "/killswitch",
+            get(killswitch::get_state)
+                .post(killswitch::engage)
+                .delete(killswitch::release),
+        )
+        .route("/events", post(events::publish_event))
+        .route("/export", post(crate::export::export_tables))
         .route("/files", get(file_api::read_file))
+        .route("/compare", post(compare::compare))
         .route("/nodes", get(cluster::get_nodes).put(cluster::put_node))
+        .route("/flamegraph", get(profiling::get_flamegraph))
         .route("/flamegraph/torch", get(profiling::get_torch_flamegraph))
         .route("/flamegraph/pprof", get(profiling::get_pprof_flamegraph))
+        .route("/heatmap", get(profiling::get_heatmap))
+        .route("/profiles", get(crate::profiles::list_profiles))
+        .route("/profiles/{id}", get(crate::profiles::get_profile))
+        .route("/steps/{step}", get(profiling::get_step_flamegraph))
+        .route("/extensions", get(extension_handler::list_extensions))
+        .route(
+            "/extensions/options",
+            get(extension_handler::list_extension_options),
+        )
+        .route(
+            "/extensions/options/validate",
+            post(extension_handler::validate_extension_option),
+        )
+        .route(
+            "/extensions/{name}/enable",
+            post(extension_handler::enable_extension),
+        )
+        .route(
+            "/extensions/{name}/disable",
+            post(extension_handler::disable_extension),
+        )
+        .route(
+            "/extensions/{name}/selftest",
+            post(extension_handler::selftest_extension),
+        )
+        .route("/tables", get(tables::list_tables))
+        .route("/tables/{name}", get(tables::tail_table))
+        .route("/instances", get(crate::instances::list_instances))
+        .route("/instances/{name}", post(crate::instances::start_instance))
+        .route("/dashboards", get(dashboard::list_dashboards))
+        .route(
+            "/dashboards/{name}",
+            get(dashboard::get_dashboard)
+                .post(dashboard::save_dashboard)
+                .delete(dashboard::delete_dashboard),
+        )
+        .nest("/grafana", grafana::grafana_route())
+        .route("/snapshots", get(snapshot::list_snapshots))
+        .route(
+            "/snapshots/{name}",
+            get(snapshot::get_snapshot).post(snapshot::save_snapshot),
+        )
+        .route(
+            "/store/{*key}",
+            get(store::get_entity)
+                .post(store::put_entity)
+                .delete(store::del_entity),
+        )
         .fallback(extension_handler::handle_extension_call)
 }
@@ -0,0 +1,88 @@
+//! Binary query endpoint on a second local Unix socket.
+//!
+//! Frames are defined in [`probing_proto::protocol::frame`]. A client keeps
+//! the connection open and sends one `Message<Query>` per poll; each request
+//! is answered with one `Message<QueryDataFormat>`.
+
+use anyhow::Result;
+use probing_proto::prelude::{ErrorCode, Message, Query, QueryDataFormat, QueryError};
+use probing_proto::protocol::frame::{decode_frame, encode_frame, frame_len, FRAME_HEADER_SIZE};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::engine::handle_query;
+use crate::shutdown::wait_for_shutdown;
+
+/// Path of the binary socket for a process, next to the HTTP socket
+pub fn binary_socket_path(pid: u32) -> String {
+    #[cfg(target_os = "linux")]
+    let path = format!("\0probing-{pid}.bin");
+    #[cfg(not(target_os = "linux"))]
+    let path = std::env::temp_dir()
+        .join(format!("probing-{pid}.bin.sock"))
+        .to_string_lossy()
+        .to_string();
+    path
+}
+
+#[cfg_attr(feature = "embedded", allow(dead_code))]
+pub async fn binary_server() -> Result<()> {
+    let socket_path = binary_socket_path(std::process::id());
+    #[cfg(not(target_os = "linux"))]
+    let _ = std::fs::remove_file(&socket_path);
+
+    log::debug!(
+        "Starting binary query server at {}",
+        socket_path.replace('\0', "@")
+    );
+    let listener = UnixListener::bind(socket_path)?;
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                tokio::spawn(async move {
+                    if let Err(err) = serve_connection(stream).await {
+                        log::debug!("binary connection closed: {err}");
+                    }
+                });
+            }
+            _ = wait_for_shutdown() => return Ok(()),
+        }
+    }
+}
+
+async fn serve_connection(mut stream: UnixStream) -> Result<()> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    loop {
+        match stream.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+        let mut payload = vec![0u8; frame_len(header)?];
+        stream.read_exact(&mut payload).await?;
+
+        let reply = match decode_frame::<Query>(&payload) {
+            Ok(_) if probing_core::core::killswitch::is_dormant() => {
+                error_reply(ErrorCode::PermissionDenied, "probe is dormant".to_string())
+            }
+            Ok(request) => handle_query(request.payload)
+                .await
+                .unwrap_or_else(|err| error_reply(ErrorCode::ExecutionError, err.to_string())),
+            Err(err) => error_reply(ErrorCode::ParseError, err.to_string()),
+        };
+
+        stream
+            .write_all(&encode_frame(&Message::new(reply))?)
+            .await?;
+    }
+}
+
+fn error_reply(code: ErrorCode, message: String) -> QueryDataFormat {
+    QueryDataFormat::Error(QueryError {
+        code,
+        message,
+        details: None,
+    })
+}
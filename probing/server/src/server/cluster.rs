@@ -1,12 +1,20 @@
-use probing_core::core::cluster::{get_nodes as core_get_nodes, update_node};
+use probing_core::core::cluster::{get_nodes as core_get_nodes, now_micros, update_node};
 use probing_proto::prelude::*;
 
 use super::error::ApiResult;
 
-/// Update a node in the cluster (HTTP handler)
-pub async fn put_node(axum::Json(node): axum::Json<Node>) -> ApiResult<()> {
+/// Update a node in the cluster (HTTP handler), replying with the master
+/// timestamps the node estimates its clock offset from
+pub async fn put_node(axum::Json(node): axum::Json<Node>) -> ApiResult<axum::Json<ClockReply>> {
+    let received = now_micros();
+    let mut node = node;
+    node.timestamp = received;
+    crate::relay::record(&node);
     update_node(node);
-    Ok(())
+    Ok(axum::Json(ClockReply {
+        received,
+        replied: now_micros(),
+    }))
 }
 
 /// Get all nodes in the cluster as JSON
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+
+use probing_core::core::cluster::get_nodes;
+use probing_proto::prelude::{
+    CompareRequest, CompareSide, DataFrame, Message, Node, Query, QueryDataFormat,
+};
+
+use super::error::ApiResult;
+use crate::auth::get_token_from_request;
+use crate::engine::ENGINE;
+
+/// Whether `addr` is the address of a node of the cluster, given as the node
+/// reported it or with its host when it listens on all interfaces
+fn is_node(addr: &str, nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| {
+        node.addr == addr
+            || node
+                .addr
+                .strip_prefix("0.0.0.0:")
+                .is_some_and(|port| addr == format!("{}:{port}", node.host))
+    })
+}
+
+/// Run `sql` on the probe at `addr` through its `/query` endpoint, with the
+/// token the request was authorized with
+async fn remote_query(addr: &str, sql: String, token: Option<String>) -> Result<DataFrame> {
+    let url = format!("http://{addr}/query");
+    let body = serde_json::to_string(&Message::new(Query {
+        expr: sql,
+        opts: None,
+    }))?;
+    let reply = tokio::task::spawn_blocking(move || -> Result<String> {
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+        let request = agent.post(&url);
+        let request = match token {
+            Some(token) => request.header("X-Probing-Token", token),
+            None => request,
+        };
+        Ok(request.send(body.as_bytes())?.body_mut().read_to_string()?)
+    })
+    .await??;
+    match serde_json::from_str::<Message<QueryDataFormat>>(&reply)?.payload {
+        QueryDataFormat::DataFrame(df) => Ok(df),
+        QueryDataFormat::Compressed(frame) => Ok(frame.decompress()?),
+        QueryDataFormat::Nil => Ok(DataFrame::default()),
+        QueryDataFormat::Error(err) => anyhow::bail!("{addr}: {}", err.message),
+        QueryDataFormat::TimeSeries(_) => anyhow::bail!("{addr}: unexpected time series"),
+    }
+}
+
+async fn fetch(
+    request: &CompareRequest,
+    side: &CompareSide,
+    token: Option<String>,
+) -> Result<DataFrame> {
+    let sql = request.sql(side);
+    match &side.target {
+        Some(addr) => remote_query(addr, sql, token).await,
+        None => {
+            let engine = ENGINE.read().await;
+            engine.async_query(sql).await
+        }
+    }
+}
+
+/// `POST /apis/compare`: the metrics of two targets, or of two windows of one
+/// target, aligned by step or time with their differences. Targets are
+/// limited to the nodes of the cluster, so the probe queries no other host.
+pub async fn compare(
+    headers: HeaderMap,
+    Json(request): Json<CompareRequest>,
+) -> ApiResult<impl IntoResponse> {
+    if let Err(err) = request.validate() {
+        return Ok((StatusCode::BAD_REQUEST, err).into_response());
+    }
+    let nodes = get_nodes();
+    for side in [&request.left, &request.right] {
+        if let Some(addr) = side.target.as_deref().filter(|addr| !is_node(addr, &nodes)) {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                format!("{addr} is not a node of the cluster"),
+            )
+                .into_response());
+        }
+    }
+    let token = get_token_from_request(&headers);
+    let (left, right) = tokio::try_join!(
+        fetch(&request, &request.left, token.clone()),
+        fetch(&request, &request.right, token)
+    )?;
+    match request.join(&left, &right) {
+        Ok(df) => Ok(Json(df).into_response()),
+        Err(err) => Ok((StatusCode::BAD_REQUEST, err).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_node() {
+        let nodes = vec![
+            Node {
+                host: "10.0.0.2".to_string(),
+                addr: "0.0.0.0:9700".to_string(),
+                ..Default::default()
+            },
+            Node {
+                host: "worker-3".to_string(),
+                addr: "10.0.0.3:9700".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert!(is_node("10.0.0.2:9700", &nodes));
+        assert!(is_node("0.0.0.0:9700", &nodes));
+        assert!(is_node("10.0.0.3:9700", &nodes));
+        assert!(!is_node("10.0.0.3:9701", &nodes));
+        assert!(!is_node("169.254.169.254:80", &nodes));
+    }
+}
@@ -1,3 +1,7 @@
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
 /// Maximum request body size allowed (5MB)
 pub const MAX_REQUEST_BODY_SIZE: usize = 5 * 1024 * 1024;
 
@@ -25,3 +29,46 @@ pub fn get_max_file_size() -> u64 {
         .parse::<u64>()
         .unwrap_or(MAX_FILE_SIZE)
 }
+
+/// Origins allowed to issue cross-origin requests; `*` allows any origin.
+/// Empty means CORS headers are never sent.
+pub static CORS_ORIGINS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(vec![]));
+
+/// URL prefix the server is mounted under when the proxy does not strip it
+/// (e.g. `/user/alice/probing`). Empty means the server is mounted at `/`.
+pub static BASE_PATH: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(String::new()));
+
+/// Replace the allowed CORS origins with a comma separated list
+pub fn set_cors_origins(origins: &str) {
+    let origins = origins
+        .split(',')
+        .map(|x| x.trim().trim_end_matches('/').to_string())
+        .filter(|x| !x.is_empty())
+        .collect();
+    *CORS_ORIGINS.write().unwrap() = origins;
+}
+
+/// Set the URL prefix the server is mounted under
+pub fn set_base_path(base_path: &str) {
+    *BASE_PATH.write().unwrap() = normalize_prefix(base_path).unwrap_or_default();
+}
+
+/// Normalize a URL prefix to `/a/b` form, rejecting values that are unsafe
+/// to echo back into HTML.
+pub fn normalize_prefix(prefix: &str) -> Option<String> {
+    let prefix = prefix.trim().trim_end_matches('/');
+    if prefix.is_empty() {
+        return Some(String::new());
+    }
+    if !prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "/-_.~%@".contains(c))
+    {
+        return None;
+    }
+    if prefix.starts_with('/') {
+        Some(prefix.to_string())
+    } else {
+        Some(format!("/{prefix}"))
+    }
+}
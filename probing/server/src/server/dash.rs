@@ -0,0 +1,93 @@
+//! Data of the terminal dashboard, `probing dash`.
+//!
+//! `GET /apis/dash` assembles the live panels in one reply: the hot metrics,
+//! the most sampled functions of the running profiler, the last failed trace
+//! spans and the spans open on every thread. Terminals refresh by polling it,
+//! so SSH access to the host is enough and no browser needs the web UI port.
+
+use axum::extract::Query;
+use serde::Deserialize;
+
+use probing_core::trace::{Span, SpanStatus, Timestamp};
+use probing_proto::prelude::{ActiveSpan, DashFrame, FailedSpan, FoldedStack, HotFunction};
+
+use super::error::ApiResult;
+use super::system::local_status;
+
+const DEFAULT_TOP: usize = 10;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DashParams {
+    /// Functions and failures returned at most, 10 by default
+    top: Option<usize>,
+}
+
+/// The live panels of `probing dash`
+///
+/// `/apis/dash?top=20`
+pub async fn get_dash(Query(params): Query<DashParams>) -> ApiResult<axum::Json<DashFrame>> {
+    let top = params.top.unwrap_or(DEFAULT_TOP).max(1);
+    Ok(axum::Json(DashFrame {
+        status: local_status(),
+        functions: hot_functions(top),
+        failures: failures(top),
+        spans: active_spans(),
+    }))
+}
+
+fn hot_functions(top: usize) -> Vec<HotFunction> {
+    let folded = match probing_python::features::pprof::folded(None) {
+        Ok(folded) => folded,
+        Err(err) => {
+            log::debug!("no profile for the dashboard: {err}");
+            return vec![];
+        }
+    };
+    let stacks = folded
+        .iter()
+        .filter_map(|line| FoldedStack::parse(line))
+        .collect::<Vec<_>>();
+    HotFunction::top(&stacks, top)
+}
+
+fn failures(top: usize) -> Vec<FailedSpan> {
+    let spans = probing_core::trace::recent_failed_spans().unwrap_or_default();
+    spans
+        .into_iter()
+        .take(top)
+        .map(|span| {
+            let time = span.end_time.unwrap_or(span.start_time).as_nanos() / 1000;
+            let message = match span.status {
+                SpanStatus::Error(message) => message,
+                _ => None,
+            };
+            FailedSpan {
+                name: span.name,
+                message,
+                time: time as i64,
+            }
+        })
+        .collect()
+}
+
+fn active_spans() -> Vec<ActiveSpan> {
+    let now = Timestamp::now();
+    let threads = probing_core::trace::all_thread_spans().unwrap_or_default();
+    let mut spans = threads
+        .into_iter()
+        .flat_map(|(thread, stack)| {
+            let thread = format!("{thread:?}");
+            stack
+                .into_iter()
+                .enumerate()
+                .map(move |(depth, span): (usize, Span)| ActiveSpan {
+                    thread: thread.clone(),
+                    name: span.name,
+                    depth,
+                    elapsed: now.duration_since(span.start_time).as_secs_f64(),
+                })
+        })
+        .collect::<Vec<_>>();
+    spans.sort_by(|a, b| a.thread.cmp(&b.thread).then(a.depth.cmp(&b.depth)));
+    spans
+}
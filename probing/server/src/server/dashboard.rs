@@ -0,0 +1,159 @@
+//! Dashboard definitions rendered by the web app.
+//!
+//! Definitions are either uploaded with `POST /apis/dashboards/{name}` or
+//! shipped as `dashboards/*.{json,toml}` under `PROBING_CODE_ROOT`. Uploaded
+//! definitions replace shipped ones of the same name.
+
+use std::collections::BTreeMap;
+use std::path::Path as FsPath;
+
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use probing_core::storage::{EntityStore, MemoryStore, PersistentEntity};
+use probing_proto::prelude::Dashboard;
+
+use super::error::ApiResult;
+
+/// Dashboards uploaded through the API
+static DASHBOARDS: Lazy<MemoryStore> = Lazy::new(MemoryStore::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+struct StoredDashboard(Dashboard);
+
+impl PersistentEntity for StoredDashboard {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.0.name
+    }
+
+    fn entity_type() -> &'static str {
+        "dashboard"
+    }
+}
+
+/// Parse a definition in JSON or TOML, a missing name defaults to `name`
+pub fn parse_dashboard(name: &str, text: &str) -> anyhow::Result<Dashboard> {
+    let mut dashboard: Dashboard = if text.trim_start().starts_with('{') {
+        serde_json::from_str(text)?
+    } else {
+        toml::from_str(text)?
+    };
+    if dashboard.name.is_empty() {
+        dashboard.name = name.to_string();
+    }
+    dashboard.validate()?;
+    Ok(dashboard)
+}
+
+/// Definitions shipped under `$PROBING_CODE_ROOT/dashboards`, read on every
+/// request so edits show up without restarting the probe
+fn shipped_dashboards() -> Vec<Dashboard> {
+    let Ok(root) = std::env::var("PROBING_CODE_ROOT") else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(FsPath::new(&root).join("dashboards")) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "json" || ext == "toml")
+        })
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            let text = std::fs::read_to_string(&path).ok()?;
+            parse_dashboard(&name, &text)
+                .map_err(|e| log::warn!("skip dashboard {}: {e}", path.display()))
+                .ok()
+        })
+        .collect()
+}
+
+async fn all_dashboards() -> anyhow::Result<BTreeMap<String, Dashboard>> {
+    let mut dashboards = shipped_dashboards()
+        .into_iter()
+        .map(|d| (d.name.clone(), d))
+        .collect::<BTreeMap<_, _>>();
+    for StoredDashboard(dashboard) in DASHBOARDS.list_all::<StoredDashboard>().await? {
+        dashboards.insert(dashboard.name.clone(), dashboard);
+    }
+    Ok(dashboards)
+}
+
+/// List all dashboards ordered by name
+pub async fn list_dashboards() -> ApiResult<Json<Vec<Dashboard>>> {
+    Ok(Json(all_dashboards().await?.into_values().collect()))
+}
+
+/// Fetch a dashboard by name
+pub async fn get_dashboard(Path(name): Path<String>) -> ApiResult<impl IntoResponse> {
+    match all_dashboards().await?.remove(&name) {
+        Some(dashboard) => Ok(Json(dashboard).into_response()),
+        None => Ok((StatusCode::NOT_FOUND, format!("no dashboard named {name}")).into_response()),
+    }
+}
+
+/// Store a JSON or TOML definition under `name`, replacing any previous one
+pub async fn save_dashboard(
+    Path(name): Path<String>,
+    body: String,
+) -> ApiResult<impl IntoResponse> {
+    let dashboard = parse_dashboard(&name, &body).and_then(|mut dashboard| {
+        dashboard.name = name.clone();
+        dashboard.validate()?;
+        Ok(dashboard)
+    });
+    let dashboard = match dashboard {
+        Ok(dashboard) => dashboard,
+        Err(e) => return Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    };
+    DASHBOARDS.put(&StoredDashboard(dashboard)).await?;
+    Ok((StatusCode::OK, format!("dashboard {name} saved")).into_response())
+}
+
+/// Remove an uploaded dashboard, shipped ones stay available
+pub async fn delete_dashboard(Path(name): Path<String>) -> ApiResult<impl IntoResponse> {
+    DASHBOARDS.del::<StoredDashboard>(&name).await?;
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dashboard_toml() {
+        let dashboard = parse_dashboard(
+            "ddp",
+            r#"
+title = "DDP health"
+framework = "torch"
+
+[[panels]]
+title = "Nodes"
+query = "SELECT * FROM cluster.nodes"
+
+[[panels]]
+title = "Loss"
+query = "SELECT step, loss FROM python.metrics"
+chart = "line"
+x = "step"
+y = ["loss"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(dashboard.name, "ddp");
+        assert_eq!(dashboard.panels.len(), 2);
+        assert_eq!(dashboard.panels[1].y, vec!["loss".to_string()]);
+
+        let json = parse_dashboard("x", r#"{"name": "y", "panels": []}"#).unwrap();
+        assert_eq!(json.name, "y");
+        assert!(parse_dashboard("x", r#"{"panels": [{"title": "t", "query": ""}]}"#).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use serde::Deserialize;
+
+use probing_core::core::events;
+
+#[derive(Debug, Deserialize)]
+pub struct PublishedEvent {
+    topic: String,
+    #[serde(default)]
+    message: String,
+    /// Who published the event, `api` by default
+    source: Option<String>,
+}
+
+/// `POST /apis/events`: publish an event on behalf of an external tool, such
+/// as a job watchdog, so that the probe's rules can react to it
+pub async fn publish_event(Json(event): Json<PublishedEvent>) -> impl IntoResponse {
+    let topic = event.topic.trim();
+    if topic.is_empty() || topic.contains(char::is_whitespace) {
+        return (StatusCode::BAD_REQUEST, format!("invalid topic `{topic}`"));
+    }
+    let source = event.source.unwrap_or_else(|| "api".to_string());
+    events::publish(topic, &source, &event.message);
+    (StatusCode::OK, format!("published {topic}"))
+}
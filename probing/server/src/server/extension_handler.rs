@@ -1,16 +1,121 @@
 use std::collections::HashMap;
 
 use axum::{
+    extract::Path,
     http::StatusCode,
     response::{AppendHeaders, IntoResponse, Response},
+    Json,
 };
 use http_body_util::BodyExt;
 
 use probing_core::core::EngineExtensionManager;
+use probing_proto::prelude::{ExtensionStatus, OptionChange, OptionCheck, OptionStatus};
 
 use super::error::ApiResult;
 use crate::engine::ENGINE;
 
+async fn extension_manager() -> Option<EngineExtensionManager> {
+    let engine = ENGINE.read().await;
+    let state = engine.context.state();
+    state
+        .config()
+        .options()
+        .extensions
+        .get::<EngineExtensionManager>()
+        .cloned()
+}
+
+/// List all extensions together with their enabled state
+pub async fn list_extensions() -> ApiResult<Json<Vec<ExtensionStatus>>> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    let extensions = eem
+        .list()
+        .await
+        .into_iter()
+        .map(|(name, enabled)| ExtensionStatus { name, enabled })
+        .collect();
+    Ok(Json(extensions))
+}
+
+/// List the options of all extensions with whether their changes apply
+/// immediately or once the extension is enabled again
+pub async fn list_extension_options() -> ApiResult<Json<Vec<OptionStatus>>> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    let pending = eem.restart_pending();
+    let options = eem
+        .options()
+        .await
+        .into_iter()
+        .map(|option| OptionStatus {
+            restart_pending: pending.contains(&option.key),
+            key: option.key,
+            value: option.value,
+            help: option.help.to_string(),
+            requires_restart: option.requires_restart,
+            depends_on: option.depends_on.map(str::to_string),
+        })
+        .collect();
+    Ok(Json(options))
+}
+
+/// Check whether an option change would be accepted, without applying it,
+/// so that a change of many probes can be validated on all of them first
+pub async fn validate_extension_option(
+    Json(change): Json<OptionChange>,
+) -> ApiResult<Json<OptionCheck>> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    let key = change.key.trim_start_matches("probing.");
+    Ok(Json(OptionCheck {
+        key: key.to_string(),
+        current: eem.show_option(key).await.ok(),
+        error: eem
+            .validate_option(key, &change.value)
+            .await
+            .err()
+            .map(|e| e.to_string()),
+    }))
+}
+
+/// Enable an extension by name
+pub async fn enable_extension(Path(name): Path<String>) -> ApiResult<Response> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    match eem.enable(&name).await {
+        Ok(()) => Ok((StatusCode::OK, format!("extension {name} enabled")).into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+/// Disable an extension by name
+pub async fn disable_extension(Path(name): Path<String>) -> ApiResult<Response> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    match eem.disable(&name).await {
+        Ok(()) => Ok((StatusCode::OK, format!("extension {name} disabled")).into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, e.to_string()).into_response()),
+    }
+}
+
+/// Run the self-test of an extension by name, telling why its tables may
+/// stay empty
+pub async fn selftest_extension(Path(name): Path<String>) -> ApiResult<Response> {
+    let eem = extension_manager()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("extension manager not available"))?;
+    match eem.selftest(&name).await {
+        Ok(selftest) => Ok(Json(selftest).into_response()),
+        Err(e) => Ok((StatusCode::NOT_FOUND, e.to_string()).into_response()),
+    }
+}
+
 /// Handle extension API calls
 #[axum::debug_handler]
 pub async fn handle_extension_call(req: axum::extract::Request) -> ApiResult<Response> {
@@ -31,24 +136,17 @@ pub async fn handle_extension_call(req: axum::extract::Request) -> ApiResult<Res
         body_bytes.len()
     );
 
-    let eem = {
-        let engine = ENGINE.write().await;
-        let state = engine.context.state();
-        state
-            .config()
-            .options()
-            .extensions
-            .get::<EngineExtensionManager>()
-            .cloned()
-    };
-
-    if let Some(eem) = eem {
+    if let Some(eem) = extension_manager().await {
         match eem.call(path, &params, &body_bytes).await {
             Ok(response) => {
                 // If response is a string, return it as plain text
+                let content_type = match std::str::from_utf8(&response) {
+                    Ok(_) => "text/plain",
+                    Err(_) => "application/octet-stream",
+                };
                 return Ok((
                     StatusCode::OK,
-                    AppendHeaders([("Content-Type", "text/plain")]),
+                    AppendHeaders([("Content-Type", content_type)]),
                     response,
                 )
                     .into_response());
@@ -63,3 +161,125 @@ pub async fn handle_extension_call(req: axum::extract::Request) -> ApiResult<Res
     // Return 404 if no extension manager is available
     Ok((StatusCode::NOT_FOUND, "Extension not found").into_response())
 }
+
+#[cfg(test)]
+mod tests {
+    use probing_core::core::Engine;
+    use probing_proto::prelude::{Message, OptionCheck, OptionStatus, Query};
+
+    use crate::extensions::ServerExtension;
+    use crate::profiles::ProfilesExtension;
+
+    async fn get(url: String) -> (u16, String) {
+        tokio::task::spawn_blocking(move || {
+            let agent: ureq::Agent = ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into();
+            let mut response = agent.get(&url).call().unwrap();
+            let status = response.status().as_u16();
+            (status, response.body_mut().read_to_string().unwrap())
+        })
+        .await
+        .unwrap()
+    }
+
+    /// POST `body` to `url`, as JSON if `json`, returning the status and body
+    async fn post(url: String, body: String, json: bool) -> (u16, String) {
+        tokio::task::spawn_blocking(move || {
+            let agent: ureq::Agent = ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .build()
+                .into();
+            let mut request = agent.post(&url);
+            if json {
+                request = request.header("Content-Type", "application/json");
+            }
+            let mut response = request.send(body).unwrap();
+            let status = response.status().as_u16();
+            (status, response.body_mut().read_to_string().unwrap())
+        })
+        .await
+        .unwrap()
+    }
+
+    /// The two phases of `probing cluster config --two-phase` over HTTP
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_validate_then_apply() {
+        let builder = Engine::builder()
+            .with_default_namespace("probe")
+            .with_extension(ProfilesExtension::default(), "profiles", Some("catalog"))
+            .with_extension(ServerExtension::default(), "server", None);
+        probing_core::initialize_engine(builder).await.unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, crate::server::build_app(false)).await });
+
+        let validate = format!("{base}/apis/extensions/options/validate");
+        let change =
+            |value: &str| format!(r#"{{"key": "probing.profiles.max", "value": "{value}"}}"#);
+        // the Json extractor refuses a body not declared as JSON
+        let (status, _) = post(validate.clone(), change("7"), false).await;
+        assert_eq!(status, 415);
+
+        let (status, body) = post(validate.clone(), change("0"), true).await;
+        assert_eq!(status, 200);
+        assert!(serde_json::from_str::<OptionCheck>(&body)
+            .unwrap()
+            .error
+            .is_some());
+
+        let (status, body) = post(validate.clone(), change("7"), true).await;
+        assert_eq!(status, 200);
+        let check = serde_json::from_str::<OptionCheck>(&body).unwrap();
+        assert_eq!(check.error, None);
+        assert_ne!(check.current.as_deref(), Some("7"));
+
+        let query = Message::new(Query::new("set probing.profiles.max=7".to_string()));
+        let (status, _) = post(
+            format!("{base}/query"),
+            serde_json::to_string(&query).unwrap(),
+            true,
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let (_, body) = post(validate.clone(), change("8"), true).await;
+        let check = serde_json::from_str::<OptionCheck>(&body).unwrap();
+        assert_eq!(check.current.as_deref(), Some("7"));
+
+        // secrets are set through the API but never read back from it
+        let query = Message::new(Query::new(
+            "set probing.server.auth_token='hunter2'".to_string(),
+        ));
+        let (status, _) = post(
+            format!("{base}/query"),
+            serde_json::to_string(&query).unwrap(),
+            true,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            probing_core::config::get("server.auth_token")
+                .await
+                .unwrap(),
+            "hunter2"
+        );
+        let (status, body) = get(format!("{base}/config/server.auth_token")).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "<redacted>");
+        let token = r#"{"key": "probing.server.auth_token", "value": "x"}"#;
+        let (_, body) = post(validate, token.to_string(), true).await;
+        let check = serde_json::from_str::<OptionCheck>(&body).unwrap();
+        assert_eq!(check.current.as_deref(), Some("<redacted>"));
+        let (status, body) = get(format!("{base}/apis/extensions/options")).await;
+        assert_eq!(status, 200);
+        let options = serde_json::from_str::<Vec<OptionStatus>>(&body).unwrap();
+        let token = options
+            .iter()
+            .find(|option| option.key == "server.auth_token")
+            .unwrap();
+        assert_eq!(token.value.as_deref(), Some("<redacted>"));
+        assert!(!body.contains("hunter2"));
+    }
+}
@@ -0,0 +1,295 @@
+//! Endpoints of Grafana's JSON data source, so an existing Grafana can chart
+//! probe tables without an exporter. Point the data source at
+//! `http://<probe>/apis/grafana`.
+//!
+//! A query target is either a table name, e.g. `python.metrics`, read over
+//! the dashboard time range, or SQL using the macros `$__timeFilter(col)`,
+//! `$__from` and `$__to` (microseconds since epoch, like table timestamps):
+//!
+//! ```sql
+//! SELECT timestamp, rank, loss FROM python.metrics WHERE $__timeFilter(timestamp)
+//! ```
+//!
+//! Time series are built from the `timestamp` (or `ts`, `time`) column; every
+//! numeric column is a series, split by the values of the text columns, so the
+//! query above charts one `loss` line per rank.
+
+use std::collections::BTreeMap;
+
+use axum::{routing::get, routing::post, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use probing_proto::prelude::{DataFrame, Ele};
+
+use super::error::ApiResult;
+use crate::engine::ENGINE;
+
+const TIME_COLUMNS: &[&str] = &["timestamp", "ts", "time"];
+
+#[derive(Debug, Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    #[serde(default, rename = "refId")]
+    ref_id: String,
+    #[serde(default)]
+    target: String,
+    /// `table` for a table response, time series otherwise
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    hide: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryRequest {
+    range: Range,
+    #[serde(default)]
+    targets: Vec<Target>,
+}
+
+pub fn grafana_route() -> Router {
+    Router::new()
+        .route("/", get(|| async { "OK" }))
+        .route("/search", post(search))
+        .route("/metrics", post(metrics))
+        .route("/query", post(query))
+}
+
+/// Tables with a time column, the targets offered by the query editor
+async fn time_tables() -> anyhow::Result<Vec<String>> {
+    let sql = format!(
+        "SELECT DISTINCT table_schema || '.' || table_name FROM information_schema.columns \
+         WHERE column_name IN ({}) ORDER BY 1",
+        TIME_COLUMNS
+            .iter()
+            .map(|c| format!("'{c}'"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let df = ENGINE.read().await.async_query(sql.as_str()).await?;
+    Ok(df.iter().map(|row| row[0].to_string()).collect())
+}
+
+async fn search() -> ApiResult<Json<Vec<String>>> {
+    Ok(Json(time_tables().await?))
+}
+
+async fn metrics() -> ApiResult<Json<Vec<Value>>> {
+    Ok(Json(
+        time_tables()
+            .await?
+            .into_iter()
+            .map(|name| json!({"label": name, "value": name}))
+            .collect(),
+    ))
+}
+
+async fn query(Json(request): Json<QueryRequest>) -> ApiResult<Json<Vec<Value>>> {
+    let (from, to) = (
+        parse_time(&request.range.from)?,
+        parse_time(&request.range.to)?,
+    );
+    let mut replies = vec![];
+    for target in request
+        .targets
+        .iter()
+        .filter(|t| !t.hide && !t.target.is_empty())
+    {
+        let sql = expand_target(&target.target, from, to);
+        log::debug!("grafana query {}: {sql}", target.ref_id);
+        let df = ENGINE.read().await.async_query(sql.as_str()).await?;
+        if target.format.as_deref() == Some("table") {
+            replies.push(to_table(&df));
+        } else {
+            replies.extend(to_timeseries(&df));
+        }
+    }
+    Ok(Json(replies))
+}
+
+/// Parse an RFC 3339 time of the request range into microseconds
+fn parse_time(time: &str) -> anyhow::Result<i64> {
+    Ok(chrono::DateTime::parse_from_rfc3339(time)?.timestamp_micros())
+}
+
+/// Turn a target into SQL: a bare table name is read over the time range,
+/// otherwise the time macros are substituted
+fn expand_target(target: &str, from: i64, to: i64) -> String {
+    let target = target.trim();
+    if target
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return format!(
+            "SELECT * FROM {target} WHERE timestamp >= {from} AND timestamp <= {to} \
+             ORDER BY timestamp"
+        );
+    }
+
+    let mut sql = String::new();
+    let mut rest = target;
+    while let Some(start) = rest.find("$__timeFilter(") {
+        let args = &rest[start + "$__timeFilter(".len()..];
+        let Some(end) = args.find(')') else {
+            break;
+        };
+        let column = args[..end].trim();
+        sql.push_str(&rest[..start]);
+        sql.push_str(&format!("({column} >= {from} AND {column} <= {to})"));
+        rest = &args[end + 1..];
+    }
+    sql.push_str(rest);
+    sql.replace("$__from", &from.to_string())
+        .replace("$__to", &to.to_string())
+}
+
+fn number(ele: &Ele) -> Option<f64> {
+    match ele {
+        Ele::I32(x) => Some(*x as f64),
+        Ele::I64(x) => Some(*x as f64),
+        Ele::F32(x) => Some(*x as f64),
+        Ele::F64(x) => Some(*x),
+        Ele::DataTime(x) => Some(*x as f64),
+        _ => None,
+    }
+}
+
+fn is_text(ele: &Ele) -> bool {
+    matches!(ele, Ele::Text(_) | Ele::Url(_))
+}
+
+/// One series per numeric column and distinct combination of text columns,
+/// with timestamps converted from microseconds to Grafana's milliseconds
+fn to_timeseries(df: &DataFrame) -> Vec<Value> {
+    let Some(time) = df
+        .names
+        .iter()
+        .position(|name| TIME_COLUMNS.contains(&name.as_str()))
+    else {
+        return vec![];
+    };
+    let rows = df.iter().collect::<Vec<_>>();
+    let Some(first) = rows.first() else {
+        return vec![];
+    };
+    let labels = (0..df.names.len())
+        .filter(|i| *i != time && is_text(&first[*i]))
+        .collect::<Vec<_>>();
+    let values = (0..df.names.len())
+        .filter(|i| *i != time && number(&first[*i]).is_some())
+        .collect::<Vec<_>>();
+
+    let mut series: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+    for row in &rows {
+        let Some(ts) = number(&row[time]) else {
+            continue;
+        };
+        let label = labels
+            .iter()
+            .map(|i| format!("{}={}", df.names[*i], row[*i]))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for i in &values {
+            let name = match label.is_empty() {
+                true => df.names[*i].clone(),
+                false => format!("{} {{{label}}}", df.names[*i]),
+            };
+            if let Some(value) = number(&row[*i]) {
+                series.entry(name).or_default().push((value, ts / 1000.0));
+            }
+        }
+    }
+    series
+        .into_iter()
+        .map(|(target, mut datapoints)| {
+            datapoints.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let datapoints = datapoints
+                .into_iter()
+                .map(|(value, ts)| json!([value, ts]))
+                .collect::<Vec<_>>();
+            json!({"target": target, "datapoints": datapoints})
+        })
+        .collect()
+}
+
+fn to_json(ele: Ele) -> Value {
+    match ele {
+        Ele::Nil => Value::Null,
+        Ele::BOOL(x) => json!(x),
+        Ele::I32(x) => json!(x),
+        Ele::I64(x) => json!(x),
+        Ele::F32(x) => json!(x),
+        Ele::F64(x) => json!(x),
+        Ele::Text(x) | Ele::Url(x) => json!(x),
+        Ele::DataTime(x) => json!(x / 1000),
+    }
+}
+
+fn to_table(df: &DataFrame) -> Value {
+    let rows = df.iter().collect::<Vec<_>>();
+    let columns = df
+        .names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let kind = match rows.first().map(|row| &row[i]) {
+                Some(Ele::DataTime(_)) => "time",
+                Some(ele) if number(ele).is_some() => "number",
+                _ => "string",
+            };
+            json!({"text": name, "type": kind})
+        })
+        .collect::<Vec<_>>();
+    let rows = rows
+        .into_iter()
+        .map(|row| row.into_iter().map(to_json).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    json!({"type": "table", "columns": columns, "rows": rows})
+}
+
+#[cfg(test)]
+mod tests {
+    use probing_proto::prelude::Seq;
+
+    use super::*;
+
+    #[test]
+    fn test_expand_target() {
+        assert_eq!(
+            expand_target("python.metrics", 1, 2),
+            "SELECT * FROM python.metrics WHERE timestamp >= 1 AND timestamp <= 2 \
+             ORDER BY timestamp"
+        );
+        assert_eq!(
+            expand_target(
+                "SELECT * FROM t WHERE $__timeFilter(ts) AND x < $__to",
+                1,
+                2
+            ),
+            "SELECT * FROM t WHERE (ts >= 1 AND ts <= 2) AND x < 2"
+        );
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let df = DataFrame::new(
+            vec!["timestamp".into(), "rank".into(), "loss".into()],
+            vec![
+                Seq::SeqI64(vec![2000, 1000, 1000]),
+                Seq::SeqText(vec!["0".into(), "0".into(), "1".into()]),
+                Seq::SeqF64(vec![0.5, 1.0, 2.0]),
+            ],
+        );
+        let series = to_timeseries(&df);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0]["target"], "loss {rank=0}");
+        assert_eq!(series[0]["datapoints"], json!([[1.0, 1.0], [0.5, 2.0]]));
+        assert_eq!(series[1]["datapoints"], json!([[2.0, 1.0]]));
+    }
+}
@@ -0,0 +1,121 @@
+//! Kill switch for a probe misbehaving in production.
+//!
+//! `POST /apis/killswitch` stops the collectors (see
+//! [`probing_core::core::killswitch`]), closes the remote listener and makes
+//! the probe answer nothing but the handshake and this endpoint. Over TCP it
+//! is only accepted when `server.auth_token` is configured, so that the
+//! request went through authentication. `DELETE /apis/killswitch` resumes the
+//! probe and is only accepted over the local socket.
+
+use std::sync::Mutex;
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Extension;
+use once_cell::sync::Lazy;
+use probing_core::core::killswitch;
+use tokio::sync::watch;
+
+use crate::report::report_state;
+
+/// Marks requests received by the remote (TCP) server
+#[derive(Debug, Clone, Copy)]
+pub struct Remote;
+
+static DORMANT: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Address of the remote server closed by the kill switch, rebound on resume
+static CLOSED_REMOTE: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+
+/// Resolves once the kill switch is engaged
+pub async fn wait_for_dormant() {
+    let mut rx = DORMANT.subscribe();
+    let _ = rx.wait_for(|dormant| *dormant).await;
+}
+
+/// Remember `addr` as the address to serve on again once resumed
+pub(crate) fn remote_closed(addr: String) {
+    if let Ok(mut closed) = CLOSED_REMOTE.lock() {
+        *closed = Some(addr);
+    }
+}
+
+/// Paths a dormant probe still answers, so that a client can tell why it is
+/// refused and resume it
+pub fn allowed_while_dormant(path: &str) -> bool {
+    path == "/apis/handshake" || path == "/apis/killswitch"
+}
+
+fn state() -> &'static str {
+    match killswitch::is_dormant() {
+        true => "dormant",
+        false => "active",
+    }
+}
+
+/// `GET /apis/killswitch`: `dormant` or `active`
+pub async fn get_state() -> impl IntoResponse {
+    state()
+}
+
+/// `POST /apis/killswitch`: put the probe into the dormant state
+pub async fn engage(remote: Option<Extension<Remote>>) -> impl IntoResponse {
+    if remote.is_some() {
+        let token = probing_core::config::get("server.auth_token")
+            .await
+            .unwrap_or_default();
+        if token.is_empty() {
+            return (
+                StatusCode::FORBIDDEN,
+                "the kill switch can only be engaged remotely when server.auth_token is set"
+                    .to_string(),
+            );
+        }
+    }
+    // stopping collectors joins their threads
+    let engaged = tokio::task::spawn_blocking(killswitch::engage)
+        .await
+        .unwrap_or(false);
+    if !engaged {
+        return (StatusCode::OK, "probe is already dormant".to_string());
+    }
+    DORMANT.send_replace(true);
+    report_state("dormant").await;
+    (StatusCode::OK, "probe is dormant".to_string())
+}
+
+/// `DELETE /apis/killswitch`: leave the dormant state, local clients only
+pub async fn release(remote: Option<Extension<Remote>>) -> impl IntoResponse {
+    if remote.is_some() {
+        return (
+            StatusCode::FORBIDDEN,
+            "a dormant probe can only be resumed from its host".to_string(),
+        );
+    }
+    if !killswitch::release() {
+        return (StatusCode::OK, "probe is already active".to_string());
+    }
+    DORMANT.send_replace(false);
+    let closed = CLOSED_REMOTE.lock().ok().and_then(|mut addr| addr.take());
+    if let Some(addr) = closed {
+        super::start_remote(Some(addr));
+    }
+    (
+        StatusCode::OK,
+        "probe is active, collectors stopped by the kill switch need to be enabled again"
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_while_dormant() {
+        assert!(allowed_while_dormant("/apis/handshake"));
+        assert!(allowed_while_dormant("/apis/killswitch"));
+        assert!(!allowed_while_dormant("/query"));
+        assert!(!allowed_while_dormant("/apis/killswitch/extra"));
+    }
+}
@@ -0,0 +1,308 @@
+//! Per-route rate limits and concurrency caps.
+//!
+//! A dashboard refreshing many panels at once, or several users opening it,
+//! can flood `/query` and `eval` with work that all runs inside the target
+//! process. Routes are matched by path prefix, the longest configured prefix
+//! winning, and requests over the limits of their route are answered with
+//! `429 Too Many Requests` instead of being queued. Accepted and rejected
+//! requests are counted in `server.stats`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+
+use probing_core::core::{
+    CustomTable, DataType, Field, FieldDoc, Float64Array, Int64Array, RecordBatch, Schema,
+    SchemaRef, StringArray, TablePluginHelper,
+};
+
+/// Counters and token bucket of a route, kept while its limits change
+#[derive(Debug)]
+struct RouteState {
+    /// Tokens left and when they were last refilled
+    bucket: Mutex<(f64, Instant)>,
+    in_flight: AtomicUsize,
+    accepted: AtomicU64,
+    rate_limited: AtomicU64,
+    concurrency_limited: AtomicU64,
+}
+
+impl Default for RouteState {
+    fn default() -> Self {
+        Self {
+            bucket: Mutex::new((f64::MAX, Instant::now())),
+            in_flight: AtomicUsize::new(0),
+            accepted: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            concurrency_limited: AtomicU64::new(0),
+        }
+    }
+}
+
+impl RouteState {
+    /// Take a token from a bucket refilled at `rate` per second and holding
+    /// one second worth of requests at most
+    fn take_token(&self, rate: f64) -> bool {
+        let Ok(mut bucket) = self.bucket.lock() else {
+            return true;
+        };
+        let (tokens, last) = *bucket;
+        let now = Instant::now();
+        let burst = rate.max(1.0);
+        let tokens = (tokens + now.duration_since(last).as_secs_f64() * rate).min(burst);
+        if tokens < 1.0 {
+            *bucket = (tokens, now);
+            return false;
+        }
+        *bucket = (tokens - 1.0, now);
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Rule {
+    /// Requests per second
+    rate: Option<f64>,
+    max_concurrent: Option<usize>,
+    state: Arc<RouteState>,
+}
+
+/// Rules by route prefix
+static RULES: Lazy<RwLock<BTreeMap<String, Rule>>> = Lazy::new(Default::default);
+
+/// Parse `<prefix>=<value>` pairs separated by commas, e.g.
+/// `/query=20,/apis/pythonext/eval=2`
+fn parse_spec<T: std::str::FromStr + PartialOrd + Default>(
+    spec: &str,
+) -> Result<BTreeMap<String, T>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (prefix, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected <route>=<limit>, got `{item}`"))?;
+            let prefix = prefix.trim().trim_end_matches('/');
+            let prefix = if prefix.is_empty() { "/" } else { prefix };
+            if !prefix.starts_with('/') {
+                return Err(format!("route `{prefix}` does not start with /"));
+            }
+            match value.trim().parse::<T>() {
+                Ok(limit) if limit > T::default() => Ok((prefix.to_string(), limit)),
+                _ => Err(format!("invalid limit `{}` for {prefix}", value.trim())),
+            }
+        })
+        .collect()
+}
+
+/// Replace one kind of limit of every route, dropping routes left without
+/// any limit
+fn update<T>(limits: BTreeMap<String, T>, set: impl Fn(&mut Rule, Option<T>)) {
+    let Ok(mut rules) = RULES.write() else {
+        return;
+    };
+    for rule in rules.values_mut() {
+        set(rule, None);
+    }
+    for (prefix, limit) in limits {
+        set(rules.entry(prefix).or_default(), Some(limit));
+    }
+    rules.retain(|_, rule| rule.rate.is_some() || rule.max_concurrent.is_some());
+}
+
+/// Set the requests per second of the routes, see [`parse_spec`]
+pub fn set_rate_limits(spec: &str) -> Result<(), String> {
+    let limits = parse_spec::<f64>(spec)?;
+    update(limits, |rule, rate| rule.rate = rate);
+    Ok(())
+}
+
+/// Set the concurrent requests of the routes, see [`parse_spec`]
+pub fn set_max_concurrency(spec: &str) -> Result<(), String> {
+    let limits = parse_spec::<usize>(spec)?;
+    update(limits, |rule, max| rule.max_concurrent = max);
+    Ok(())
+}
+
+/// Rule of the longest configured prefix of `path`
+fn rule_for(path: &str) -> Option<Rule> {
+    let rules = RULES.read().ok()?;
+    rules
+        .iter()
+        .filter(|(prefix, _)| {
+            prefix.as_str() == "/"
+                || path
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, rule)| rule.clone())
+}
+
+/// Releases the concurrency slot of a request when it completes
+struct InFlight(Arc<RouteState>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn too_many_requests(path: &str, reason: String) -> Response {
+    log::debug!("Request to {path} rejected: {reason}");
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, reason).into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+    response
+}
+
+/// Middleware enforcing the limits configured with `server.rate_limits` and
+/// `server.max_concurrency`
+pub async fn route_limit_middleware(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let Some(rule) = rule_for(&path) else {
+        return next.run(request).await;
+    };
+    let state = rule.state.clone();
+
+    if let Some(rate) = rule.rate {
+        if !state.take_token(rate) {
+            state.rate_limited.fetch_add(1, Ordering::Relaxed);
+            return too_many_requests(&path, format!("rate limit of {rate} requests/s exceeded"));
+        }
+    }
+    let in_flight = state.in_flight.fetch_add(1, Ordering::Relaxed);
+    let guard = InFlight(state.clone());
+    if let Some(max) = rule.max_concurrent {
+        if in_flight >= max {
+            drop(guard);
+            state.concurrency_limited.fetch_add(1, Ordering::Relaxed);
+            return too_many_requests(&path, format!("{max} concurrent requests already running"));
+        }
+    }
+    state.accepted.fetch_add(1, Ordering::Relaxed);
+    let response = next.run(request).await;
+    drop(guard);
+    response
+}
+
+/// Limits and counters of the routes with a rate limit or a concurrency cap
+#[derive(Default, Debug)]
+pub struct StatsTable {}
+
+impl CustomTable for StatsTable {
+    fn name() -> &'static str {
+        "stats"
+    }
+
+    fn description() -> &'static str {
+        "Requests accepted and rejected by the limits of each route"
+    }
+
+    fn schema() -> SchemaRef {
+        SchemaRef::new(Schema::new(vec![
+            Field::new("route", DataType::Utf8, false).with_doc("Path prefix of the route"),
+            Field::new("rate_limit", DataType::Float64, true).with_unit("requests/s"),
+            Field::new("max_concurrency", DataType::Int64, true),
+            Field::new("in_flight", DataType::Int64, false)
+                .with_doc("Requests being served")
+                .with_unit("requests"),
+            Field::new("accepted", DataType::Int64, false).with_unit("requests"),
+            Field::new("rate_limited", DataType::Int64, false)
+                .with_doc("Requests answered 429 for exceeding the rate limit")
+                .with_unit("requests"),
+            Field::new("concurrency_limited", DataType::Int64, false)
+                .with_doc("Requests answered 429 while the route was at its concurrency cap")
+                .with_unit("requests"),
+        ]))
+    }
+
+    fn data() -> Vec<RecordBatch> {
+        let rules = RULES
+            .read()
+            .map(|rules| rules.clone().into_iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let counter = |f: fn(&RouteState) -> u64| {
+            Int64Array::from_iter_values(rules.iter().map(|(_, rule)| f(&rule.state) as i64))
+        };
+        RecordBatch::try_new(
+            Self::schema(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    rules.iter().map(|(prefix, _)| prefix.as_str()),
+                )),
+                Arc::new(Float64Array::from(
+                    rules.iter().map(|(_, rule)| rule.rate).collect::<Vec<_>>(),
+                )),
+                Arc::new(Int64Array::from(
+                    rules
+                        .iter()
+                        .map(|(_, rule)| rule.max_concurrent.map(|max| max as i64))
+                        .collect::<Vec<_>>(),
+                )),
+                Arc::new(counter(|s| s.in_flight.load(Ordering::Relaxed) as u64)),
+                Arc::new(counter(|s| s.accepted.load(Ordering::Relaxed))),
+                Arc::new(counter(|s| s.rate_limited.load(Ordering::Relaxed))),
+                Arc::new(counter(|s| s.concurrency_limited.load(Ordering::Relaxed))),
+            ],
+        )
+        .map(|rb| vec![rb])
+        .unwrap_or_default()
+    }
+}
+
+pub type StatsPlugin = TablePluginHelper<StatsTable>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let limits = parse_spec::<f64>("/query=20, /apis/pythonext/eval/=2.5,").unwrap();
+        assert_eq!(limits["/query"], 20.0);
+        assert_eq!(limits["/apis/pythonext/eval"], 2.5);
+        assert!(parse_spec::<f64>("/query").is_err());
+        assert!(parse_spec::<f64>("query=1").is_err());
+        assert!(parse_spec::<usize>("/query=0").is_err());
+        assert!(parse_spec::<usize>("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let state = RouteState::default();
+        assert!(state.take_token(2.0));
+        assert!(state.take_token(2.0));
+        assert!(!state.take_token(2.0));
+    }
+
+    #[test]
+    fn test_rules() {
+        set_rate_limits("/query=5").unwrap();
+        set_max_concurrency("/query=2,/apis=4").unwrap();
+        let rule = rule_for("/query/async").unwrap();
+        assert_eq!((rule.rate, rule.max_concurrent), (Some(5.0), Some(2)));
+        assert_eq!(rule_for("/apis/nodes").unwrap().max_concurrent, Some(4));
+        assert!(rule_for("/queryx").is_none());
+
+        // counters survive a change of the other limit
+        rule.state.accepted.fetch_add(1, Ordering::Relaxed);
+        set_rate_limits("").unwrap();
+        let rule = rule_for("/query").unwrap();
+        assert_eq!(rule.rate, None);
+        assert_eq!(rule.state.accepted.load(Ordering::Relaxed), 1);
+
+        set_max_concurrency("").unwrap();
+        assert!(rule_for("/query").is_none());
+    }
+}
@@ -1,8 +1,8 @@
-use super::config::get_max_request_body_size;
+use super::config::{get_max_request_body_size, normalize_prefix, BASE_PATH, CORS_ORIGINS};
 use axum::{
     body::Body,
     extract::Request,
-    http::StatusCode,
+    http::{header, HeaderValue, Method, StatusCode, Uri},
     middleware::Next,
     response::{IntoResponse, Response},
 };
@@ -75,6 +75,52 @@ async fn collect_body_with_limit(body: Body, limit: usize) -> Result<Bytes, &'st
     Ok(bytes)
 }
 
+/// Header flagging the replies refused by a dormant probe, which a proxy
+/// must not mistake for an outage
+pub const DORMANT_HEADER: &str = "x-probing-dormant";
+
+/// Middleware refusing requests while the kill switch is engaged
+pub async fn dormant_middleware(request: Request, next: Next) -> Response {
+    if probing_core::core::killswitch::is_dormant()
+        && !super::killswitch::allowed_while_dormant(request.uri().path())
+    {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(DORMANT_HEADER, "true")],
+            "probe is dormant, resume it from its host with `probing <pid> kill-switch --resume`",
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Header naming the user a client acts for, `user@host` for the CLI
+pub const USER_HEADER: &str = "x-probing-user";
+
+/// Actor of the changes made by requests not naming their user
+const ANONYMOUS: &str = "anonymous";
+
+/// Actor recorded in `probe.activity` for the changes made by `request`,
+/// `claimed:<user>` since any client can name any user, the token of the
+/// server being shared by all of them
+fn request_actor(request: &Request) -> String {
+    request
+        .headers()
+        .get(USER_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|user| !user.is_empty())
+        .map(|user| format!("claimed:{user}"))
+        .unwrap_or_else(|| ANONYMOUS.to_string())
+}
+
+/// Middleware running each request as the user it names, so that the
+/// extensions it enables and the options it sets are recorded as theirs
+pub async fn actor_middleware(request: Request, next: Next) -> Response {
+    let actor = request_actor(&request);
+    probing_core::core::activity::with_actor(actor, next.run(request)).await
+}
+
 /// Middleware for logging requests (optional - for debugging)
 pub async fn request_logging_middleware(request: Request, next: Next) -> Response {
     let method = request.method().to_string();
@@ -97,6 +143,111 @@ pub async fn request_logging_middleware(request: Request, next: Next) -> Respons
     response
 }
 
+/// URL prefix the current request is served under, taken from
+/// `X-Forwarded-Prefix` or the configured `server.base_path`.
+#[derive(Clone, Debug, Default)]
+pub struct BasePath(pub String);
+
+/// Middleware for serving behind a reverse proxy under a URL prefix.
+///
+/// Strips the configured base path from the request URI (for proxies that do
+/// not strip it themselves) and records the prefix as a [`BasePath`] extension
+/// so the UI can be told where it lives.
+pub async fn base_path_middleware(mut request: Request, next: Next) -> Response {
+    let base_path = BASE_PATH.read().unwrap().clone();
+    if !base_path.is_empty() {
+        if let Some(uri) = strip_uri_prefix(request.uri(), &base_path) {
+            *request.uri_mut() = uri;
+        }
+    }
+
+    let prefix = request
+        .headers()
+        .get("x-forwarded-prefix")
+        .and_then(|v| v.to_str().ok())
+        .and_then(normalize_prefix)
+        .filter(|p| !p.is_empty())
+        .unwrap_or(base_path);
+    request.extensions_mut().insert(BasePath(prefix));
+
+    next.run(request).await
+}
+
+fn strip_uri_prefix(uri: &Uri, prefix: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(prefix)?;
+    let rest = match rest {
+        "" => "/",
+        rest if rest.starts_with('/') => rest,
+        _ => return None,
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{rest}?{query}"),
+        None => rest.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Middleware answering CORS preflights and tagging responses for the
+/// origins configured in `server.cors_origins`.
+pub async fn cors_middleware(request: Request, next: Next) -> Response {
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|origin| allowed_origin(origin, &CORS_ORIGINS.read().unwrap()));
+
+    let Some(origin) = origin else {
+        return next.run(request).await;
+    };
+
+    let is_preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut response = if is_preflight {
+        let allow_headers = request
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .cloned()
+            .unwrap_or(HeaderValue::from_static(
+                "authorization, content-type, x-probing-token",
+            ));
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, PUT, DELETE, OPTIONS"),
+        );
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static("600"),
+        );
+        response
+    } else {
+        next.run(request).await
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    response
+}
+
+fn allowed_origin(origin: &str, allowed: &[String]) -> Option<HeaderValue> {
+    if allowed
+        .iter()
+        .any(|x| x == "*" || x.eq_ignore_ascii_case(origin))
+    {
+        HeaderValue::from_str(origin).ok()
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,4 +269,43 @@ mod tests {
         let result = collect_body_with_limit(body, 100).await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_request_actor() {
+        let request = |user: Option<&str>| {
+            let mut builder = Request::builder().uri("/query");
+            if let Some(user) = user {
+                builder = builder.header(USER_HEADER, user);
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        assert_eq!(
+            request_actor(&request(Some("alice@node1"))),
+            "claimed:alice@node1"
+        );
+        assert_eq!(request_actor(&request(Some(" "))), ANONYMOUS);
+        assert_eq!(request_actor(&request(None)), ANONYMOUS);
+    }
+
+    #[test]
+    fn test_strip_uri_prefix() {
+        let uri: Uri = "/user/alice/probing/apis/nodes?x=1".parse().unwrap();
+        let stripped = strip_uri_prefix(&uri, "/user/alice/probing").unwrap();
+        assert_eq!(stripped.to_string(), "/apis/nodes?x=1");
+
+        let uri: Uri = "/user/alice/probing".parse().unwrap();
+        assert_eq!(strip_uri_prefix(&uri, "/user/alice/probing").unwrap(), "/");
+
+        let uri: Uri = "/user/alice/probingx".parse().unwrap();
+        assert!(strip_uri_prefix(&uri, "/user/alice/probing").is_none());
+    }
+
+    #[test]
+    fn test_allowed_origin() {
+        let allowed = vec!["https://hub.example.com".to_string()];
+        assert!(allowed_origin("https://hub.example.com", &allowed).is_some());
+        assert!(allowed_origin("https://evil.example.com", &allowed).is_none());
+        assert!(allowed_origin("https://any.example.com", &["*".to_string()]).is_some());
+        assert!(allowed_origin("https://hub.example.com", &[]).is_none());
+    }
 }
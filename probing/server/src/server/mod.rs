@@ -1,14 +1,26 @@
 mod apis;
+mod binary;
 mod repl;
 
 pub mod cluster;
+pub mod compare;
 pub mod config;
+pub mod dash;
+pub mod dashboard;
 pub mod error;
+pub mod events;
 pub mod extension_handler;
 pub mod file_api;
+pub mod grafana;
+pub mod killswitch;
+pub mod limits;
 pub mod middleware;
 pub mod profiling;
+pub mod queries;
+pub mod snapshot;
+pub mod store;
 pub mod system;
+pub mod tables;
 
 use anyhow::Result;
 use apis::apis_route;
@@ -18,15 +30,19 @@ use once_cell::sync::Lazy;
 use crate::asset::{index, static_files};
 use crate::engine::{handle_query, initialize_engine};
 use crate::server::repl::ws_handler;
+use crate::shutdown::{track, wait_for_shutdown};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use middleware::{request_logging_middleware, request_size_limit_middleware};
+use middleware::{
+    actor_middleware, base_path_middleware, cors_middleware, dormant_middleware,
+    request_logging_middleware, request_size_limit_middleware,
+};
 use probing_proto::prelude::Query;
 
 async fn get_config_value_handler(
     axum::extract::Path(config_key): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    match probing_core::config::get(&config_key).await {
+    match probing_core::config::show(&config_key).await {
         Ok(value) => (StatusCode::OK, value).into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -55,7 +71,7 @@ pub static SERVER_RUNTIME: Lazy<tokio::runtime::Runtime> = Lazy::new(|| {
         .unwrap()
 });
 
-fn build_app(auth: bool) -> axum::Router {
+pub(crate) fn build_app(auth: bool) -> axum::Router {
     let mut app = axum::Router::new()
         .route("/", axum::routing::get(index))
         .route("/overview", axum::routing::get(index))
@@ -65,7 +81,14 @@ fn build_app(auth: bool) -> axum::Router {
         .route("/timeseries", axum::routing::get(index))
         .route("/index.html", axum::routing::get(index))
         .route("/profiler", axum::routing::get(index))
-        .route("/query", axum::routing::post(query))
+        .route("/dashboards", axum::routing::get(index))
+        .route("/dashboards/{name}", axum::routing::get(index))
+        .route("/query", axum::routing::post(query).get(queries::list))
+        .route("/query/async", axum::routing::post(queries::submit))
+        .route(
+            "/query/{id}",
+            axum::routing::get(queries::fetch).delete(queries::cancel),
+        )
         .route(
             "/config/{config_key}",
             axum::routing::get(get_config_value_handler),
@@ -75,17 +98,31 @@ fn build_app(auth: bool) -> axum::Router {
         .fallback(static_files)
         // Apply request size limiting middleware
         .layer(axum::middleware::from_fn(request_size_limit_middleware))
+        // Reject requests over the rate and concurrency limits of their route
+        .layer(axum::middleware::from_fn(limits::route_limit_middleware))
+        // Record the changes made by the request as made by its user
+        .layer(axum::middleware::from_fn(actor_middleware))
         // Apply request logging middleware (optional, for debugging)
-        .layer(axum::middleware::from_fn(request_logging_middleware));
+        .layer(axum::middleware::from_fn(request_logging_middleware))
+        // A dormant probe only answers authenticated requests to the kill switch
+        .layer(axum::middleware::from_fn(dormant_middleware));
 
     // Apply authentication middleware if auth token is configured
     if auth {
-        app = app.layer(axum::middleware::from_fn(
-            crate::auth::selective_auth_middleware,
-        ));
+        app = app
+            .layer(axum::middleware::from_fn(
+                crate::auth::selective_auth_middleware,
+            ))
+            .layer(axum::Extension(killswitch::Remote));
     }
 
-    app
+    // CORS must wrap authentication so that preflight requests are answered
+    app = app.layer(axum::middleware::from_fn(cors_middleware));
+
+    // Prefix stripping has to run before routing, so wrap the whole router
+    axum::Router::new()
+        .fallback_service(app)
+        .layer(axum::middleware::from_fn(base_path_middleware))
 }
 
 /// HTTP handler wrapper for query endpoint
@@ -96,6 +133,8 @@ async fn query(body: String) -> impl IntoResponse {
     }
 }
 
+// servers only started with the server runtime
+#[cfg_attr(feature = "embedded", allow(dead_code))]
 pub async fn local_server() -> Result<()> {
     #[cfg(target_os = "linux")]
     let socket_path = format!("\0probing-{}", std::process::id());
@@ -117,21 +156,47 @@ pub async fn local_server() -> Result<()> {
     );
 
     let app = build_app(false);
-    axum::serve(tokio::net::UnixListener::bind(socket_path)?, app).await?;
+    axum::serve(tokio::net::UnixListener::bind(socket_path)?, app)
+        .with_graceful_shutdown(wait_for_shutdown())
+        .await?;
     Ok(())
 }
 
+/// With the `embedded` feature only the engine is initialized, on the calling
+/// thread: no server runtime is started and queries go through
+/// [`crate::query_blocking`]
+#[cfg(feature = "embedded")]
+pub fn start_local() {
+    std::sync::LazyLock::force(&crate::vars::PROBE_STARTED);
+    probing_cc::extensions::envs::record_initial();
+    probing_core::core::hot_metrics::start(std::time::Duration::from_secs(1));
+    crate::server::profiling::register_scheduler_actions();
+    probing_core::initialize_engine_blocking(crate::engine::engine_builder())
+        .unwrap_or_else(|err| error!("Failed to initialize engine: {err}"));
+}
+
+#[cfg(not(feature = "embedded"))]
 pub fn start_local() {
+    std::sync::LazyLock::force(&crate::vars::PROBE_STARTED);
+    // before the server or the application change anything
+    probing_cc::extensions::envs::record_initial();
+    probing_core::core::hot_metrics::start(std::time::Duration::from_secs(1));
     SERVER_RUNTIME.block_on(async move {
         initialize_engine()
             .await
             .unwrap_or_else(|err| error!("Failed to initialize engine: {err}"));
     });
-    SERVER_RUNTIME.spawn(async move {
+    track(SERVER_RUNTIME.spawn(async move {
         let _ = local_server().await;
-    });
+    }));
+    track(SERVER_RUNTIME.spawn(async move {
+        if let Err(err) = binary::binary_server().await {
+            error!("Failed to start binary query server: {err}");
+        }
+    }));
 }
 
+#[cfg_attr(feature = "embedded", allow(dead_code))]
 pub async fn remote_server(addr: Option<String>) -> Result<()> {
     use nu_ansi_term::Color::{Green, Red};
 
@@ -139,9 +204,20 @@ pub async fn remote_server(addr: Option<String>) -> Result<()> {
     log::info!("Starting probe server at {addr}");
 
     let app = build_app(true);
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let listener = match (addr.rsplit_once(':'), crate::ports::port_range()) {
+        // an unspecified port is picked from the configured range
+        (Some((host, "0")), Some(range)) => {
+            let hint = std::env::var("LOCAL_RANK")
+                .ok()
+                .and_then(|rank| rank.parse().ok())
+                .unwrap_or(0);
+            crate::ports::bind_in_range(host, range, hint).await?
+        }
+        _ => tokio::net::TcpListener::bind(addr).await?,
+    };
 
-    match listener.local_addr() {
+    let local_addr = listener.local_addr();
+    match &local_addr {
         Ok(addr) => {
             {
                 let mut probing_address = crate::vars::PROBING_ADDRESS.write().unwrap();
@@ -159,15 +235,34 @@ pub async fn remote_server(addr: Option<String>) -> Result<()> {
             );
         }
     }
-    axum::serve(listener, app).await?;
+    // the kill switch closes the listener, resuming the probe binds it again
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            tokio::select! {
+                _ = wait_for_shutdown() => {}
+                _ = killswitch::wait_for_dormant() => {}
+            }
+        })
+        .await?;
+    if let (true, Ok(addr)) = (probing_core::core::killswitch::is_dormant(), local_addr) {
+        log::warn!("remote server at {addr} closed by the kill switch");
+        killswitch::remote_closed(addr.to_string());
+    }
 
     Ok(())
 }
 
+#[cfg(not(feature = "embedded"))]
 pub fn start_remote(addr: Option<String>) {
-    SERVER_RUNTIME.spawn(async move {
+    track(SERVER_RUNTIME.spawn(async move {
         let _ = remote_server(addr).await;
-    });
+    }));
+}
+
+/// Without the server runtime there is no remote server to start
+#[cfg(feature = "embedded")]
+pub fn start_remote(addr: Option<String>) {
+    log::warn!("remote server {addr:?} not started, the probe is built embedded");
 }
 
 pub fn sync_env_settings() {
@@ -177,20 +272,22 @@ pub fn sync_env_settings() {
             k.starts_with("PROBING_")
                 && ![
                     "PROBING_PORT",
+                    "PROBING_NAME",
                     "PROBING_LOGLEVEL",
                     "PROBING_ASSETS_ROOT",
                     "PROBING_SERVER_ADDRPATTERN",
                     "PROBING_AUTH_TOKEN", // Skip syncing the auth token for security reasons
+                    "PROBING_EXPORT_DIR", // read once by the export extension
                 ]
                 .contains(&k.as_str())
         })
         .collect();
 
-    // Spawn the task onto the existing Tokio runtime
-    SERVER_RUNTIME.spawn(async move {
+    let sync = async move {
         for (k, v) in env_vars {
             let k = k.replace("_", ".").to_lowercase();
-            let setting = format!("set {k}={v}");
+            // quoted so that values with spaces, e.g. scheduled tasks, parse
+            let setting = format!("set {k}='{}'", v.replace('\'', "''"));
             // Since handle_query might not be async itself, but interacts with
             // components managed by the runtime, it's safer to run it within
             // the runtime's context. If handle_query becomes async, add .await
@@ -208,5 +305,13 @@ pub fn sync_env_settings() {
                 }
             };
         }
-    });
+    };
+    // Spawn the task onto the existing Tokio runtime
+    #[cfg(not(feature = "embedded"))]
+    SERVER_RUNTIME.spawn(sync);
+    // or apply the settings on the calling thread, without the server runtime
+    #[cfg(feature = "embedded")]
+    if let Err(err) = probing_core::block_on(sync) {
+        error!("Failed to sync env settings: {err}");
+    }
 }
@@ -1,29 +1,322 @@
-use axum::response::IntoResponse;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+use probing_proto::prelude::{FoldedStack, TreemapNode};
+use probing_python::features::step_profile;
 
 use super::error::ApiResult;
+use crate::profiles::ProfileMeta;
 
 /// Generate flamegraph using torch profiler
-pub async fn get_torch_flamegraph() -> ApiResult<impl IntoResponse> {
-    let graph = probing_python::features::torch::flamegraph();
-    Ok((
-        [
-            ("Content-Type", "image/svg+xml"),
-            ("Content-Disposition", "attachment; filename=flamegraph.svg"),
-        ],
-        graph,
-    ))
+pub async fn get_torch_flamegraph(
+    Query(params): Query<FlamegraphParams>,
+) -> ApiResult<axum::response::Response> {
+    render(
+        FlamegraphFormat::Svg,
+        FlamegraphSource::Torch,
+        params.trigger,
+        None,
+    )
 }
 
 /// Generate flamegraph using pprof
-pub async fn get_pprof_flamegraph() -> ApiResult<impl IntoResponse> {
-    match probing_python::features::pprof::flamegraph() {
-        Ok(graph) => Ok((
+pub async fn get_pprof_flamegraph(
+    Query(params): Query<FlamegraphParams>,
+) -> ApiResult<axum::response::Response> {
+    render(
+        FlamegraphFormat::Svg,
+        FlamegraphSource::Pprof,
+        params.trigger,
+        params.span,
+    )
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlamegraphFormat {
+    #[default]
+    Svg,
+    /// One `outer;...;inner weight` line per stack
+    Folded,
+    /// Array of [`FoldedStack`]
+    Json,
+    /// [`TreemapNode`] of the pprof samples by origin, package and innermost
+    /// frame
+    Treemap,
+}
+
+impl std::fmt::Display for FlamegraphFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlamegraphFormat::Svg => write!(f, "svg"),
+            FlamegraphFormat::Folded => write!(f, "folded"),
+            FlamegraphFormat::Json => write!(f, "json"),
+            FlamegraphFormat::Treemap => write!(f, "treemap"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlamegraphSource {
+    #[default]
+    Pprof,
+    Torch,
+    /// Python stacks down to the GPU kernels they launched, weighted by GPU
+    /// time, from `gpu.kernels`
+    Gpu,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FlamegraphParams {
+    #[serde(default)]
+    format: FlamegraphFormat,
+    #[serde(default)]
+    profiler: FlamegraphSource,
+    /// Recorded with the profile in `profiles.catalog`, `api` by default
+    trigger: Option<String>,
+    /// Only the pprof samples taken within a trace span of this name
+    span: Option<String>,
+}
+
+/// Flamegraph of either profiler as SVG, folded stacks or JSON
+///
+/// `/apis/flamegraph?format=folded&profiler=torch`, `&span=forward` for the
+/// pprof samples within `forward` spans, `?format=treemap` for the CPU time
+/// of the pprof samples by package, `?profiler=gpu` for the GPU time of the
+/// kernels under the Python code launching them
+pub async fn get_flamegraph(
+    Query(params): Query<FlamegraphParams>,
+) -> ApiResult<axum::response::Response> {
+    render(params.format, params.profiler, params.trigger, params.span)
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StepParams {
+    #[serde(default)]
+    format: FlamegraphFormat,
+    /// Step to compare with in a differential flamegraph
+    base: Option<i64>,
+}
+
+/// Profile of a step bracketed by `probing.profile_step()`, listed in
+/// `probe.steps`
+///
+/// `/apis/steps/42` as SVG, `?format=folded` or `json` for its stacks, and
+/// `?base=41` for a differential flamegraph of step 42 against step 41, red
+/// where step 42 took more samples and blue where it took fewer
+pub async fn get_step_flamegraph(
+    Path(step): Path<i64>,
+    Query(params): Query<StepParams>,
+) -> ApiResult<axum::response::Response> {
+    let (content_type, body) = match (params.format, params.base) {
+        (FlamegraphFormat::Svg, base) => ("image/svg+xml", step_profile::flamegraph(step, base)?),
+        (FlamegraphFormat::Folded, None) => (
+            "text/plain; charset=utf-8",
+            step_profile::folded(step)?.join("\n"),
+        ),
+        (FlamegraphFormat::Json, None) => {
+            let stacks = step_profile::folded(step)?
+                .iter()
+                .filter_map(|line| FoldedStack::parse(line))
+                .collect::<Vec<_>>();
+            ("application/json", serde_json::to_string(&stacks)?)
+        }
+        (FlamegraphFormat::Treemap, _) | (_, Some(_)) => {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                "steps are served as svg, folded or json, and compared as svg only",
+            )
+                .into_response())
+        }
+    };
+    Ok(([("Content-Type", content_type)], body).into_response())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HeatmapParams {
+    /// Defaults to `probing.pprof.heatmap_module`
+    module: Option<String>,
+}
+
+/// Source of a Python module annotated with its wall samples per line
+///
+/// `/apis/heatmap?module=mypkg.trainer`
+pub async fn get_heatmap(
+    Query(params): Query<HeatmapParams>,
+) -> ApiResult<axum::response::Response> {
+    let module = params
+        .module
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or_else(probing_python::features::heatmap::module);
+    if module.is_empty() {
+        return Ok((
+            axum::http::StatusCode::BAD_REQUEST,
+            "no module given, pass ?module= or set probing.pprof.heatmap_module",
+        )
+            .into_response());
+    }
+    let html = probing_python::features::heatmap::render_html(&module)?;
+    Ok(([("Content-Type", "text/html; charset=utf-8")], html).into_response())
+}
+
+/// Make `@flamegraph [pprof|torch|gpu]` available to scheduled tasks, each run
+/// keeping an SVG in `profiles.catalog` with trigger `scheduler`, and
+/// `@heap_snapshot` keeping a JSON snapshot of the Python heap with profiler
+/// `heap`, triggered by the task or rule that ran it
+pub fn register_scheduler_actions() {
+    probing_core::core::scheduler::register_action(
+        "heap_snapshot",
+        std::sync::Arc::new(|_: &str| {
+            let body = probing_python::features::heap::snapshot()?;
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as i64;
+            let bytes = body.len();
+            crate::profiles::record(
+                ProfileMeta {
+                    id: 0,
+                    timestamp,
+                    profiler: "heap".to_string(),
+                    mode: None,
+                    format: "json".to_string(),
+                    trigger: probing_core::core::activity::actor(),
+                    window: None,
+                    bytes: 0,
+                },
+                "application/json",
+                body.into_bytes(),
+            );
+            Ok(format!("{bytes} bytes"))
+        }),
+    );
+    probing_core::core::scheduler::register_action(
+        "flamegraph",
+        std::sync::Arc::new(|args: &str| {
+            let profiler = match args.trim() {
+                "" | "pprof" => FlamegraphSource::Pprof,
+                "torch" => FlamegraphSource::Torch,
+                "gpu" => FlamegraphSource::Gpu,
+                other => {
+                    anyhow::bail!("unknown profiler `{other}`, expected pprof, torch or gpu")
+                }
+            };
+            let (_, body) = build(
+                FlamegraphFormat::Svg,
+                profiler,
+                Some("scheduler".to_string()),
+                None,
+            )?;
+            Ok(format!("{} bytes", body.len()))
+        }),
+    );
+}
+
+/// Build the flamegraph and keep a copy in the profile registry
+fn render(
+    format: FlamegraphFormat,
+    profiler: FlamegraphSource,
+    trigger: Option<String>,
+    span: Option<String>,
+) -> ApiResult<axum::response::Response> {
+    let svg = matches!(format, FlamegraphFormat::Svg);
+    let (content_type, body) = build(format, profiler, trigger, span)?;
+    let response = match svg {
+        true => (
             [
-                ("Content-Type", "image/svg+xml"),
+                ("Content-Type", content_type),
                 ("Content-Disposition", "attachment; filename=flamegraph.svg"),
             ],
-            graph,
-        )),
-        Err(err) => Err(anyhow::anyhow!(err).into()),
-    }
+            body,
+        )
+            .into_response(),
+        false => ([("Content-Type", content_type)], body).into_response(),
+    };
+    Ok(response)
+}
+
+/// Flamegraph of `profiler` in `format` with its content type, recorded in
+/// the profile registry
+fn build(
+    format: FlamegraphFormat,
+    profiler: FlamegraphSource,
+    trigger: Option<String>,
+    span: Option<String>,
+) -> anyhow::Result<(&'static str, String)> {
+    let (content_type, body) = match (&format, &profiler) {
+        (FlamegraphFormat::Svg, FlamegraphSource::Pprof) => (
+            "image/svg+xml",
+            probing_python::features::pprof::flamegraph(span.as_deref())
+                .map_err(|err| anyhow::anyhow!(err))?,
+        ),
+        (FlamegraphFormat::Svg, FlamegraphSource::Torch) => (
+            "image/svg+xml",
+            probing_python::features::torch::flamegraph(),
+        ),
+        (FlamegraphFormat::Svg, FlamegraphSource::Gpu) => (
+            "image/svg+xml",
+            probing_python::features::gpu_kernels::flamegraph()?,
+        ),
+        (FlamegraphFormat::Treemap, FlamegraphSource::Pprof) => {
+            let tree: TreemapNode = probing_python::features::breakdown::treemap(span.as_deref())?;
+            ("application/json", serde_json::to_string(&tree)?)
+        }
+        (FlamegraphFormat::Treemap, FlamegraphSource::Torch | FlamegraphSource::Gpu) => {
+            anyhow::bail!("the treemap is built from the pprof samples only")
+        }
+        (_, source) => {
+            let folded = match source {
+                FlamegraphSource::Pprof => {
+                    probing_python::features::pprof::folded(span.as_deref())?
+                }
+                FlamegraphSource::Torch => probing_python::features::torch::query_profiling()?,
+                FlamegraphSource::Gpu => probing_python::features::gpu_kernels::folded()?,
+            };
+            match &format {
+                FlamegraphFormat::Json => {
+                    let stacks = folded
+                        .iter()
+                        .filter_map(|line| FoldedStack::parse(line))
+                        .collect::<Vec<_>>();
+                    ("application/json", serde_json::to_string(&stacks)?)
+                }
+                _ => ("text/plain; charset=utf-8", folded.join("\n")),
+            }
+        }
+    };
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64;
+    let (profiler, mode, window) = match profiler {
+        FlamegraphSource::Pprof => (
+            "pprof",
+            Some(probing_python::features::pprof::active_mode().to_string()),
+            probing_python::features::pprof::started_at()
+                .map(|started| (timestamp - started) as f64 / 1e6),
+        ),
+        FlamegraphSource::Torch => ("torch", None, None),
+        FlamegraphSource::Gpu => ("gpu", None, None),
+    };
+    crate::profiles::record(
+        ProfileMeta {
+            id: 0,
+            timestamp,
+            profiler: profiler.to_string(),
+            mode,
+            format: format.to_string(),
+            trigger: trigger.unwrap_or_else(|| "api".to_string()),
+            window,
+            bytes: 0,
+        },
+        content_type,
+        body.clone().into_bytes(),
+    );
+    Ok((content_type, body))
 }
@@ -0,0 +1,228 @@
+//! Running queries by id, so they can be cancelled server-side.
+//!
+//! Every query sent to `/query` runs as its own task under an id, either the
+//! `opts.id` chosen by the client or a generated one. `POST /query/async`
+//! returns the id right away and the result is fetched later with
+//! `GET /query/{id}`; `DELETE /query/{id}` aborts the task, which drops the
+//! DataFusion stream and stops the scan.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::task::{AbortHandle, JoinHandle};
+
+use probing_proto::prelude::*;
+
+use super::SERVER_RUNTIME;
+use crate::engine::handle_query;
+
+/// Finished async results kept until fetched, oldest dropped first
+const MAX_FINISHED: usize = 64;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+enum State {
+    Running(AbortHandle),
+    Finished(QueryDataFormat),
+}
+
+struct Entry {
+    expr: String,
+    started: Instant,
+    state: State,
+}
+
+static QUERIES: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(Default::default);
+
+/// A running query as listed by `GET /query`
+#[derive(Debug, Serialize)]
+pub struct RunningQuery {
+    pub id: String,
+    pub expr: String,
+    pub elapsed_ms: u64,
+    pub finished: bool,
+}
+
+fn cancelled(id: &str) -> QueryDataFormat {
+    QueryDataFormat::Error(QueryError {
+        code: ErrorCode::Cancelled,
+        message: format!("query {id} was cancelled"),
+        details: None,
+    })
+}
+
+fn internal(message: String) -> QueryDataFormat {
+    QueryDataFormat::Error(QueryError {
+        code: ErrorCode::Internal,
+        message,
+        details: None,
+    })
+}
+
+/// Register `request` and spawn it, failing if its id is already in use
+fn spawn(request: Query) -> Result<(String, JoinHandle<QueryDataFormat>), QueryDataFormat> {
+    let id = request
+        .opts
+        .as_ref()
+        .and_then(|opts| opts.id.clone())
+        .unwrap_or_else(|| format!("q{}", NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+    let mut queries = QUERIES.lock().map_err(|e| internal(e.to_string()))?;
+    if queries.contains_key(&id) {
+        return Err(internal(format!("query id {id} is already in use")));
+    }
+
+    let expr = request.expr.clone();
+    let handle = SERVER_RUNTIME.spawn(async move {
+        handle_query(request)
+            .await
+            .unwrap_or_else(|err| internal(err.to_string()))
+    });
+    queries.insert(
+        id.clone(),
+        Entry {
+            expr,
+            started: Instant::now(),
+            state: State::Running(handle.abort_handle()),
+        },
+    );
+    Ok((id, handle))
+}
+
+/// Aborts and forgets the query once the HTTP handler is done or dropped,
+/// e.g. when the client disconnects
+struct AbortOnDrop(String, AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.1.abort();
+        if let Ok(mut queries) = QUERIES.lock() {
+            queries.remove(&self.0);
+        }
+    }
+}
+
+async fn join(id: &str, handle: JoinHandle<QueryDataFormat>) -> QueryDataFormat {
+    match handle.await {
+        Ok(reply) => reply,
+        Err(err) if err.is_cancelled() => cancelled(id),
+        Err(err) => internal(err.to_string()),
+    }
+}
+
+/// Run a query to completion, it can be cancelled by id meanwhile
+pub async fn run(request: Query) -> QueryDataFormat {
+    let (id, handle) = match spawn(request) {
+        Ok(spawned) => spawned,
+        Err(reply) => return reply,
+    };
+    let _guard = AbortOnDrop(id.clone(), handle.abort_handle());
+    join(&id, handle).await
+}
+
+/// Start a query in the background and return its id
+pub async fn submit(body: String) -> impl IntoResponse {
+    let request = match serde_json::from_str::<Message<Query>>(&body) {
+        Ok(request) => request.payload,
+        Err(err) => return (StatusCode::BAD_REQUEST, format!("invalid request: {err}")),
+    };
+    let (id, handle) = match spawn(request) {
+        Ok(spawned) => spawned,
+        Err(reply) => return (StatusCode::CONFLICT, reply_text(reply)),
+    };
+    let task_id = id.clone();
+    SERVER_RUNTIME.spawn(async move {
+        let reply = join(&task_id, handle).await;
+        let Ok(mut queries) = QUERIES.lock() else {
+            return;
+        };
+        if let Some(entry) = queries.get_mut(&task_id) {
+            entry.state = State::Finished(reply);
+        }
+        evict_finished(&mut queries);
+    });
+    (StatusCode::OK, id)
+}
+
+fn evict_finished(queries: &mut HashMap<String, Entry>) {
+    let mut finished = queries
+        .iter()
+        .filter(|(_, entry)| matches!(entry.state, State::Finished(_)))
+        .map(|(id, entry)| (entry.started, id.clone()))
+        .collect::<Vec<_>>();
+    if finished.len() > MAX_FINISHED {
+        finished.sort();
+        for (_, id) in &finished[..finished.len() - MAX_FINISHED] {
+            queries.remove(id);
+        }
+    }
+}
+
+fn reply_text(reply: QueryDataFormat) -> String {
+    serde_json::to_string(&Message::new(reply)).unwrap_or_default()
+}
+
+/// Result of an async query; 202 while it is still running. A finished
+/// result is returned only once.
+pub async fn fetch(Path(id): Path<String>) -> impl IntoResponse {
+    let Ok(mut queries) = QUERIES.lock() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, String::new());
+    };
+    if let Some(Entry {
+        state: State::Running(_),
+        ..
+    }) = queries.get(&id)
+    {
+        return (StatusCode::ACCEPTED, format!("query {id} is running"));
+    }
+    match queries.remove(&id) {
+        Some(Entry {
+            state: State::Finished(reply),
+            ..
+        }) => (StatusCode::OK, reply_text(reply)),
+        _ => (StatusCode::NOT_FOUND, format!("no query {id}")),
+    }
+}
+
+/// Cancel a running query, or discard the result of a finished one
+pub async fn cancel(Path(id): Path<String>) -> impl IntoResponse {
+    let Ok(mut queries) = QUERIES.lock() else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, String::new());
+    };
+    if let Some(Entry {
+        state: State::Running(handle),
+        ..
+    }) = queries.get(&id)
+    {
+        log::info!("cancelling query {id}");
+        handle.abort();
+        return (StatusCode::OK, format!("query {id} cancelled"));
+    }
+    match queries.remove(&id) {
+        Some(_) => (StatusCode::OK, format!("query {id} discarded")),
+        None => (StatusCode::NOT_FOUND, format!("no query {id}")),
+    }
+}
+
+/// Queries currently known to the server
+pub async fn list() -> Json<Vec<RunningQuery>> {
+    let queries = QUERIES
+        .lock()
+        .map(|queries| {
+            queries
+                .iter()
+                .map(|(id, entry)| RunningQuery {
+                    id: id.clone(),
+                    expr: entry.expr.clone(),
+                    elapsed_ms: entry.started.elapsed().as_millis() as u64,
+                    finished: matches!(entry.state, State::Finished(_)),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Json(queries)
+}
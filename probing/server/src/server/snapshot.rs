@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use probing_core::storage::{EntityStore, MemoryStore, PersistentEntity};
+use probing_proto::prelude::EnvSnapshot;
+
+use super::error::ApiResult;
+use crate::engine::ENGINE;
+
+/// Named snapshots taken by `probing <pid> snapshot save <name>`
+static SNAPSHOTS: Lazy<MemoryStore> = Lazy::new(MemoryStore::new);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+struct StoredSnapshot(EnvSnapshot);
+
+impl PersistentEntity for StoredSnapshot {
+    type Id = String;
+
+    fn id(&self) -> &Self::Id {
+        &self.0.name
+    }
+
+    fn entity_type() -> &'static str {
+        "snapshot"
+    }
+}
+
+/// Shared libraries currently mapped into the process, keyed by path
+fn loaded_libraries() -> BTreeMap<String, String> {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .filter(|path| path.starts_with('/') && path.contains(".so"))
+        .map(|path| {
+            let size = std::fs::metadata(path)
+                .map(|m| m.len().to_string())
+                .unwrap_or_default();
+            (path.to_string(), size)
+        })
+        .collect()
+}
+
+async fn engine_settings() -> anyhow::Result<BTreeMap<String, String>> {
+    let engine = ENGINE.read().await;
+    let df = engine
+        .async_query("SELECT name, value FROM information_schema.df_settings")
+        .await?;
+    Ok(df
+        .iter()
+        .map(|row| (row[0].to_string(), row[1].to_string()))
+        .collect())
+}
+
+/// Capture envs, settings, libraries and packages of the current process
+pub async fn capture(name: String) -> anyhow::Result<EnvSnapshot> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let mut snapshot = EnvSnapshot::new(name, timestamp);
+
+    snapshot
+        .sections
+        .insert("envs".to_string(), std::env::vars().collect());
+    snapshot
+        .sections
+        .insert("settings".to_string(), engine_settings().await?);
+    snapshot
+        .sections
+        .insert("libraries".to_string(), loaded_libraries());
+    match probing_python::features::packages::installed_packages() {
+        Ok(packages) => {
+            snapshot.sections.insert("packages".to_string(), packages);
+        }
+        Err(e) => log::warn!("snapshot without python packages: {e}"),
+    }
+    Ok(snapshot)
+}
+
+/// Capture a snapshot and store it under `name`, replacing any previous one
+pub async fn save_snapshot(Path(name): Path<String>) -> ApiResult<impl IntoResponse> {
+    let snapshot = capture(name.clone()).await?;
+    SNAPSHOTS.put(&StoredSnapshot(snapshot)).await?;
+    Ok((StatusCode::OK, format!("snapshot {name} saved")))
+}
+
+/// Fetch a stored snapshot by name
+pub async fn get_snapshot(Path(name): Path<String>) -> ApiResult<impl IntoResponse> {
+    match SNAPSHOTS.get::<StoredSnapshot>(&name).await? {
+        Some(snapshot) => Ok(Json(snapshot.0).into_response()),
+        None => Ok((StatusCode::NOT_FOUND, format!("no snapshot named {name}")).into_response()),
+    }
+}
+
+/// List the names of all stored snapshots
+pub async fn list_snapshots() -> ApiResult<Json<Vec<String>>> {
+    let mut names = SNAPSHOTS
+        .list_all::<StoredSnapshot>()
+        .await?
+        .into_iter()
+        .map(|s| s.0.name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(Json(names))
+}
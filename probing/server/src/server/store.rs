@@ -0,0 +1,84 @@
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
+};
+
+use super::error::ApiResult;
+use super::killswitch::Remote;
+use crate::replication::{index_replica, REPLICA_STORE};
+
+/// Bytes held by the store at most, replicas and pushed rows together
+const MAX_STORE_BYTES: usize = 256 * 1024 * 1024;
+
+/// Over TCP the store is only served when `server.auth_token` is set, so that
+/// peers and workers went through authentication to read or write it
+async fn refuse_unauthenticated(remote: Option<Extension<Remote>>) -> Option<Response> {
+    remote?;
+    let token = probing_core::config::get("server.auth_token")
+        .await
+        .unwrap_or_default();
+    token.is_empty().then(|| {
+        (
+            StatusCode::FORBIDDEN,
+            "the store is only served remotely when server.auth_token is set",
+        )
+            .into_response()
+    })
+}
+
+/// Raw entity written by a peer probe, see `replication`
+pub async fn put_entity(
+    remote: Option<Extension<Remote>>,
+    Path(key): Path<String>,
+    body: Bytes,
+) -> ApiResult<Response> {
+    if let Some(refused) = refuse_unauthenticated(remote).await {
+        return Ok(refused);
+    }
+    let replaced = REPLICA_STORE
+        .raw_entities_get(&key)
+        .await?
+        .map_or(0, |data| data.len());
+    let held = REPLICA_STORE.raw_entities_nbytes().await - replaced;
+    if held + body.len() > MAX_STORE_BYTES {
+        return Ok((
+            StatusCode::INSUFFICIENT_STORAGE,
+            format!("the store is full ({held} of {MAX_STORE_BYTES} bytes held)"),
+        )
+            .into_response());
+    }
+    REPLICA_STORE
+        .raw_entities_save(key.clone(), body.to_vec())
+        .await?;
+    index_replica(&key);
+    Ok(StatusCode::OK.into_response())
+}
+
+/// Fetch a raw entity previously written by a peer probe
+pub async fn get_entity(
+    remote: Option<Extension<Remote>>,
+    Path(key): Path<String>,
+) -> ApiResult<Response> {
+    if let Some(refused) = refuse_unauthenticated(remote).await {
+        return Ok(refused);
+    }
+    match REPLICA_STORE.raw_entities_get(&key).await? {
+        Some(data) => Ok((StatusCode::OK, data).into_response()),
+        None => Ok((StatusCode::NOT_FOUND, format!("no entity {key}")).into_response()),
+    }
+}
+
+/// Remove a raw entity written by a peer probe
+pub async fn del_entity(
+    remote: Option<Extension<Remote>>,
+    Path(key): Path<String>,
+) -> ApiResult<Response> {
+    if let Some(refused) = refuse_unauthenticated(remote).await {
+        return Ok(refused);
+    }
+    REPLICA_STORE.raw_entities_delete(&key).await?;
+    Ok(StatusCode::OK.into_response())
+}
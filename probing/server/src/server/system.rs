@@ -44,3 +44,118 @@ pub async fn get_overview_json() -> ApiResult<axum::Json<Process>> {
     let overview = get_overview()?;
     Ok(axum::Json(overview))
 }
+
+/// Cargo features of the probe and its extensions, prefixed by crate
+fn build_features() -> Vec<String> {
+    let cc = probing_cc::FEATURES.iter().map(|f| format!("cc/{f}"));
+    let python = probing_python::FEATURES
+        .iter()
+        .map(|f| format!("python/{f}"));
+    let mut features = cc.chain(python).collect::<Vec<_>>();
+    if cfg!(feature = "extension-module") {
+        features.push("server/extension-module".to_string());
+    }
+    features
+}
+
+/// Build metadata and available features, so clients can tell what the probe supports
+pub async fn get_capabilities() -> ApiResult<axum::Json<Capabilities>> {
+    let extensions = super::extension_handler::list_extensions()
+        .await
+        .map(|x| x.0)
+        .unwrap_or_default();
+    Ok(axum::Json(Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol: ProtocolVersion::current(),
+        arch: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+        features: build_features(),
+        extensions,
+        python: Some(probing_python::python::python_version()),
+    }))
+}
+
+/// Hot metrics of the probe, the same as its shared-memory segment holds
+pub async fn get_status() -> ApiResult<axum::Json<ProbeStatus>> {
+    Ok(axum::Json(local_status()))
+}
+
+/// Hot metrics of this process, only the pid when none were published
+pub fn local_status() -> ProbeStatus {
+    probing_core::core::hot_metrics::local()
+        .map(|metrics| metrics.status())
+        .unwrap_or_else(|| ProbeStatus {
+            pid: std::process::id(),
+            ..Default::default()
+        })
+}
+
+/// Exchange protocol versions and features with a client before it sends
+/// requests; an incompatible client is told so and refuses on its side
+pub async fn handshake(
+    axum::Json(client): axum::Json<Handshake>,
+) -> ApiResult<axum::Json<Handshake>> {
+    let probe = Handshake::current(env!("CARGO_PKG_VERSION"));
+    if let Err(err) = client.check(&probe) {
+        log::warn!("incompatible client: {err}");
+    }
+    Ok(axum::Json(probe))
+}
+
+/// Threads of the probe: its background threads and the server runtime
+#[cfg(target_os = "linux")]
+fn is_probe_thread(comm: &str) -> bool {
+    comm.starts_with("probing-") || comm.starts_with("server runtime")
+}
+
+/// CPU seconds used by the probe threads and the names of the background
+/// ones, which are the collectors
+#[cfg(target_os = "linux")]
+fn probe_threads() -> Result<(f64, Vec<String>)> {
+    let mut ticks = 0;
+    let mut collectors = std::collections::BTreeSet::new();
+    for task in procfs::process::Process::myself()?.tasks()?.flatten() {
+        let Ok(stat) = task.stat() else {
+            continue;
+        };
+        if !is_probe_thread(&stat.comm) {
+            continue;
+        }
+        ticks += stat.utime + stat.stime;
+        if let Some(name) = stat.comm.strip_prefix("probing-") {
+            collectors.insert(name.to_string());
+        }
+    }
+    let seconds = ticks as f64 / procfs::ticks_per_second() as f64;
+    Ok((seconds, collectors.into_iter().collect()))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe_threads() -> Result<(f64, Vec<String>)> {
+    anyhow::bail!("thread statistics are only read on Linux")
+}
+
+/// Resources taken by the probe in its process, for `probing list -vv`
+pub async fn get_resources() -> ApiResult<axum::Json<ProbeResources>> {
+    let uptime = crate::vars::PROBE_STARTED.elapsed().as_secs_f64();
+    let (cpu, collectors) = match probe_threads() {
+        Ok((seconds, collectors)) if uptime > 0.0 => (Some(seconds / uptime * 100.0), collectors),
+        Ok((_, collectors)) => (None, collectors),
+        Err(err) => {
+            log::debug!("failed to read the probe threads: {err}");
+            (None, vec![])
+        }
+    };
+    let address = crate::vars::PROBING_ADDRESS
+        .read()
+        .ok()
+        .map(|addr| addr.clone())
+        .filter(|addr| !addr.is_empty());
+    Ok(axum::Json(ProbeResources {
+        pid: std::process::id(),
+        cpu,
+        buffer_bytes: probing_python::extensions::python::external_tables_nbytes() as u64,
+        collectors,
+        address,
+        uptime,
+    }))
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use datafusion::arrow::datatypes::DataType;
+use serde::Deserialize;
+
+use probing_core::core::ordering::ordering;
+use probing_proto::prelude::{DataFrame, Seq, TableDoc, TableTail};
+use probing_python::extensions::python::external_time_series;
+
+use super::error::ApiResult;
+use crate::engine::ENGINE;
+
+/// Upper bound of the long-poll wait, in seconds
+const MAX_WAIT_SECS: f64 = 60.0;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TailParams {
+    /// Sequence number of the first row to return
+    #[serde(default)]
+    since: u64,
+    /// Seconds to wait for new rows when there are none yet
+    #[serde(default)]
+    wait: f64,
+}
+
+/// Every queryable table with its description and documented columns
+pub async fn list_tables() -> ApiResult<Json<Vec<TableDoc>>> {
+    Ok(Json(ENGINE.read().await.tables().await?))
+}
+
+fn deadline(params: &TailParams) -> Instant {
+    Instant::now() + Duration::from_secs_f64(params.wait.clamp(0.0, MAX_WAIT_SECS))
+}
+
+/// Rows appended to a time-series table, or to a table numbering its rows
+/// with a sequence column such as `server.logs`, since `since`
+///
+/// `/apis/tables/python.metrics?since=120&wait=30` returns the rows numbered
+/// 120 and above together with the sequence number for the next request. When
+/// no row has been appended yet, the request is held for up to `wait` seconds.
+pub async fn tail_table(
+    Path(name): Path<String>,
+    Query(params): Query<TailParams>,
+) -> ApiResult<Response> {
+    let table = name.strip_prefix("python.").unwrap_or(&name);
+    let Some(ts) = external_time_series(table) else {
+        return tail_sequenced(name, params).await;
+    };
+
+    let since = params.since as usize;
+    let deadline = deadline(&params);
+    loop {
+        let seq = ts.lock().unwrap().seq();
+        if seq > since || Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    let ts = ts.lock().unwrap();
+    Ok(Json(TableTail {
+        next: ts.seq() as u64,
+        df: ts.since(since),
+    })
+    .into_response())
+}
+
+/// Largest value of the Int64 column `column` of `df`
+fn max_seq(df: &DataFrame, column: &str) -> Option<u64> {
+    let index = df.names.iter().position(|n| n == column)?;
+    match &df.cols[index] {
+        Seq::SeqI64(values) => values.iter().max().map(|v| *v as u64),
+        _ => None,
+    }
+}
+
+/// Rows of a table whose ordering column numbers them, from `since` on
+async fn tail_sequenced(name: String, params: TailParams) -> ApiResult<Response> {
+    let not_found =
+        |reason: &str| Ok((StatusCode::NOT_FOUND, format!("{name} {reason}")).into_response());
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        return not_found("is not a table name");
+    }
+    let schema = match ENGINE
+        .read()
+        .await
+        .context
+        .table_provider(name.as_str())
+        .await
+    {
+        Ok(provider) => provider.schema(),
+        Err(_) => return not_found("is not a table"),
+    };
+    let column = match ordering(&schema) {
+        Some(column)
+            if schema
+                .field_with_name(column)
+                .is_ok_and(|f| f.data_type() == &DataType::Int64) =>
+        {
+            column.to_string()
+        }
+        _ => return not_found("has no sequence column to follow"),
+    };
+
+    let sql = format!(
+        "SELECT * FROM {name} WHERE \"{column}\" >= {}",
+        params.since
+    );
+    let deadline = deadline(&params);
+    loop {
+        let df = ENGINE.read().await.async_query(sql.as_str()).await?;
+        let next = max_seq(&df, &column).map(|max| max + 1);
+        if next.is_some() || Instant::now() >= deadline {
+            return Ok(Json(TableTail {
+                next: next.unwrap_or(params.since),
+                df,
+            })
+            .into_response());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,99 @@
+//! Deadline-aware graceful shutdown of the probe server.
+//!
+//! When the host process exits we stop accepting connections, stop samplers
+//! through the core shutdown hooks, flush the spans and series collected since
+//! the last export and replication, let in-flight HTTP responses complete,
+//! and send a final heartbeat marking the node as departed. Everything is
+//! bounded by the configured shutdown timeout.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::archive::persist;
+use crate::otlp::export;
+use crate::replication::replicate;
+use crate::report::report_departure;
+#[cfg(not(feature = "embedded"))]
+use crate::server::SERVER_RUNTIME;
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT_MS: u64 = 3000;
+
+pub(crate) static SHUTDOWN_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_SHUTDOWN_TIMEOUT_MS);
+
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+static SERVER_TASKS: Lazy<Mutex<Vec<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Track a server task so that shutdown can wait for it to drain
+pub(crate) fn track(handle: JoinHandle<()>) {
+    if let Ok(mut tasks) = SERVER_TASKS.lock() {
+        tasks.push(handle);
+    }
+}
+
+/// Resolves once shutdown has been requested
+pub async fn wait_for_shutdown() {
+    let mut rx = SHUTDOWN.subscribe();
+    let _ = rx.wait_for(|stopping| *stopping).await;
+}
+
+/// Shut the probe server down, giving up after the configured timeout.
+pub fn shutdown() {
+    let deadline = Duration::from_millis(SHUTDOWN_TIMEOUT_MS.load(Ordering::Relaxed));
+    shutdown_with_deadline(deadline);
+}
+
+pub fn shutdown_with_deadline(deadline: Duration) {
+    if SHUTDOWN.send_replace(true) {
+        return;
+    }
+    log::debug!("shutting down probe server, deadline {deadline:?}");
+
+    let hooks = probing_core::core::shutdown::run_hooks();
+    log::debug!("{hooks} shutdown hooks executed");
+
+    let tasks = SERVER_TASKS
+        .lock()
+        .map(|mut tasks| std::mem::take(&mut *tasks))
+        .unwrap_or_default();
+
+    let drain = async move {
+        tokio::time::timeout(deadline, async move {
+            // the periodic workers exit on shutdown, so what they have not
+            // shipped yet goes out here
+            if let Err(err) = export().await {
+                log::error!("failed to export to the OTLP collector: {err}");
+            }
+            if let Err(err) = replicate().await {
+                log::error!("failed to replicate probe tables: {err}");
+            }
+            if let Err(err) = persist().await {
+                log::error!("failed to persist probe archive: {err}");
+            }
+            report_departure().await;
+            for task in tasks {
+                let _ = task.await;
+            }
+        })
+        .await
+    };
+    #[cfg(not(feature = "embedded"))]
+    let drained = SERVER_RUNTIME.block_on(drain);
+    // without the server runtime, drained on the calling thread
+    #[cfg(feature = "embedded")]
+    let drained = match probing_core::block_on(drain) {
+        Ok(drained) => drained,
+        Err(err) => {
+            log::error!("cannot shut the probe server down: {err}");
+            return;
+        }
+    };
+    if drained.is_err() {
+        log::warn!("probe server shutdown exceeded deadline of {deadline:?}");
+    }
+}
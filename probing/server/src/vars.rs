@@ -1,4 +1,8 @@
 use std::sync::{LazyLock, RwLock};
+use std::time::Instant;
 
 pub static PROBING_ADDRESS: LazyLock<RwLock<String>> =
     LazyLock::new(|| RwLock::new(Default::default()));
+
+/// When the probe started, forced by `start_local`
+pub static PROBE_STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
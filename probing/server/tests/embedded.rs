@@ -0,0 +1,33 @@
+//! The probe built with `--features embedded` runs on the calling thread,
+//! without the server runtime, even through the entry points of the ctor.
+#![cfg(feature = "embedded")]
+
+use probing_proto::prelude::{Query, QueryDataFormat};
+
+/// Threads of the server runtime, named `server runtime`
+fn server_threads() -> usize {
+    let Ok(tasks) = std::fs::read_dir("/proc/self/task") else {
+        return 0;
+    };
+    tasks
+        .filter_map(|task| task.ok())
+        .filter(|task| {
+            std::fs::read_to_string(task.path().join("comm"))
+                .is_ok_and(|name| name.trim() == "server runtime")
+        })
+        .count()
+}
+
+#[test]
+fn test_embedded_probe() {
+    probing_server::start_local();
+    probing_server::sync_env_settings();
+    probing_server::start_remote(Some("127.0.0.1:0".to_string()));
+    assert!(probing_server::create_instance("embedded").is_err());
+
+    let reply = probing_server::query_blocking(Query::new("SELECT 1 AS one".to_string()));
+    assert!(matches!(reply, Ok(QueryDataFormat::DataFrame(_))));
+
+    probing_server::shutdown();
+    assert_eq!(server_threads(), 0);
+}
@@ -52,16 +52,54 @@ def initialize_probing():
     )
 
 
-initialize_probing()
+def _launching():
+    """
+    Whether the package is imported to run `python -m probing`, which only
+    launches a target and needs no probe of its own.
+    """
+    import sys
+
+    # `sys.argv` is `["-m"]` while any `-m` module is located, so the command
+    # line the interpreter was started with is checked instead
+    args = iter(getattr(sys, "orig_argv", [])[1:])
+    for arg in args:
+        if arg == "-m":
+            return next(args, None) == "probing"
+        if arg.startswith("-m"):
+            return arg[2:] == "probing"
+        if arg in ("-X", "-W", "--check-hash-based-pycs"):
+            next(args, None)
+        elif arg == "-c" or not arg.startswith("-"):
+            return False
+    return False
+
+
+try:
+    if _launching():
+        raise ImportError("no probe in the launcher")
+    initialize_probing()
+    CLIENT_ONLY = False
+except ImportError:
+    # Without the native library only the pure-Python client is available,
+    # e.g. `probing.discover()[0].query("SHOW TABLES")`.
+    CLIENT_ONLY = True
+
+from probing.client import Probe, discover
 
-import probing.hooks.import_hook
-import probing.inspect
+if not CLIENT_ONLY:
+    import probing.hooks.import_hook
+    import probing.inspect
 
-from probing.core.engine import query
-from probing.core.engine import load_extension
+    from probing.core.engine import query
+    from probing.core.engine import load_extension
+    from probing.core.udf import register_udf
+    from probing.inspect import watch_model
+    from probing.profiling.step import profile_step
 
 __all__ = [
-    "query",
-    "load_extension",
+    "Probe",
+    "discover",
     "VERSION",
 ]
+if not CLIENT_ONLY:
+    __all__ += ["query", "load_extension", "register_udf", "watch_model", "profile_step"]
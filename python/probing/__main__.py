@@ -0,0 +1,3 @@
+from probing.launcher import main
+
+main()
@@ -0,0 +1,243 @@
+"""
+Pure-Python client for running probes.
+
+This module talks to probes over their control sockets without loading the
+native library, so it works in environments where the probing wheel cannot
+be built or loaded. Only the standard library is required; pandas is used for
+results when it is available.
+
+Examples:
+    >>> from probing.client import discover, Probe
+    >>> [p.pid for p in discover()]  # doctest: +SKIP
+    [1234]
+    >>> Probe(1234).query("SELECT 1 AS a")  # doctest: +SKIP
+       a
+    0  1
+"""
+
+import http.client
+import json
+import os
+import socket
+import sys
+import time
+
+DEFAULT_CTRL_ROOT = "/tmp/probing/"
+
+
+def _user():
+    """``user@host`` recorded by the probe as the actor of the changes we make."""
+    import getpass
+
+    try:
+        user = getpass.getuser()
+    except Exception:
+        user = "unknown"
+    return f"{user}@{socket.gethostname()}"
+
+
+class _UnixConnection(http.client.HTTPConnection):
+    def __init__(self, path, timeout=None):
+        super().__init__("localhost", timeout=timeout)
+        self._path = path
+
+    def connect(self):
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        if self.timeout is not None:
+            sock.settimeout(self.timeout)
+        sock.connect(self._path)
+        self.sock = sock
+
+
+def _socket_path(pid, name=None):
+    base = f"probing-{pid}" if name is None else f"probing-{pid}-{name}"
+    if sys.platform.startswith("linux"):
+        return f"\0{base}"
+    import tempfile
+
+    return os.path.join(tempfile.gettempdir(), f"{base}.sock")
+
+
+def _abstract_socket_pids():
+    pids = set()
+    try:
+        with open("/proc/net/unix") as f:
+            next(f, None)
+            for line in f:
+                fields = line.split()
+                if len(fields) >= 8 and fields[7].startswith("@probing-"):
+                    pid = fields[7][len("@probing-") :]
+                    if pid.isdigit():
+                        pids.add(int(pid))
+    except OSError:
+        pass
+    return pids
+
+
+class Probe:
+    """
+    A probe reachable by pid (local Unix socket), `pid@name` (a named probe
+    instance of a local process) or `host:port`.
+    """
+
+    def __init__(self, target, timeout=10.0):
+        self.target = str(target)
+        self.timeout = timeout
+
+    @property
+    def pid(self):
+        pid = self.target.split("@", 1)[0]
+        return int(pid) if pid.isdigit() else None
+
+    @property
+    def name(self):
+        return self.target.split("@", 1)[1] if "@" in self.target else None
+
+    def __repr__(self):
+        return f"Probe({self.target!r})"
+
+    def _connection(self):
+        if self.pid is not None:
+            return _UnixConnection(_socket_path(self.pid, self.name), timeout=self.timeout)
+        host, port = self.target.rsplit(":", 1)
+        return http.client.HTTPConnection(host, int(port), timeout=self.timeout)
+
+    def request(self, path, body=None):
+        """Send a request to the probe and return the raw response body."""
+        conn = self._connection()
+        try:
+            method = "GET" if body is None else "POST"
+            conn.request(method, path, body=body, headers={"X-Probing-User": _user()})
+            rsp = conn.getresponse()
+            data = rsp.read()
+            if rsp.status >= 400:
+                raise RuntimeError(f"{path}: {rsp.status} {data.decode(errors='replace')}")
+            return data
+        finally:
+            conn.close()
+
+    def query(self, sql):
+        """
+        Run a SQL query on the probe.
+
+        Returns a pandas DataFrame when pandas is installed, otherwise a dict
+        mapping column names to lists.
+        """
+        msg = {
+            "version": {"major": 0, "minor": 1, "patch": 0},
+            "message_id": None,
+            "timestamp": int(time.time() * 1e6),
+            "payload": {"expr": sql, "opts": None},
+        }
+        reply = json.loads(self.request("/query", json.dumps(msg)))
+        payload = reply["payload"]
+        if payload == "Nil":
+            return to_frame({"names": [], "cols": []})
+        if "Error" in payload:
+            raise RuntimeError(payload["Error"].get("message", payload["Error"]))
+        if "DataFrame" not in payload:
+            raise RuntimeError(f"unexpected reply: {list(payload)}")
+        return to_frame(payload["DataFrame"])
+
+    def tail(self, table, since=0, wait=0):
+        """
+        Fetch rows appended to a time-series table, or to a table numbering its
+        rows with a `seq` column such as `server.logs`, since sequence number
+        `since`.
+
+        Returns `(next, rows)`; pass `next` as `since` in the following call to
+        only receive new rows. With `wait`, the probe holds the request for up to
+        that many seconds until a row arrives.
+        """
+        reply = json.loads(self.request(f"/apis/tables/{table}?since={since}&wait={wait}"))
+        return reply["next"], to_frame(reply["df"])
+
+    def eval(self, code, structured=False):
+        """
+        Evaluate Python code in the target process and return its output.
+
+        With `structured`, return a dict with the captured `stdout` and `stderr`,
+        the `value` of the trailing expression (`repr`, `type_name`,
+        `truncated`) and the raised `error` (`type_name`, `message`,
+        `traceback`), each None when absent.
+        """
+        if structured:
+            return json.loads(self.request("/apis/pythonext/eval?format=json", code))
+        return self.request("/apis/pythonext/eval", code).decode()
+
+    def tensor(self, expr, index=None, limit=None):
+        """
+        Fetch a tensor of the target process as a numpy array.
+
+        `expr` is evaluated in `__main__` (watched models are available as
+        `models["name"]`) and sliced with `index`, e.g. `"0:4, :8"`, before it
+        leaves its device, so a small slice of a large GPU tensor is cheap to
+        fetch. Requires numpy on both sides.
+        """
+        from urllib.parse import urlencode
+
+        params = {k: v for k, v in (("index", index), ("limit", limit)) if v is not None}
+        path = "/apis/pythonext/tensor"
+        if params:
+            path += "?" + urlencode(params)
+        _, array = decode_tensor(self.request(path, expr))
+        return array
+
+
+def decode_tensor(data):
+    """Split a tensor reply into its JSON header and a numpy array."""
+    import struct
+
+    import numpy as np
+
+    (size,) = struct.unpack_from("<I", data)
+    header = json.loads(data[4 : 4 + size])
+    body = data[4 + size :]
+    if header["dtype"] == "bfloat16":
+        # sent as raw bits, widen to float32
+        array = (np.frombuffer(body, dtype="<u2").astype(np.uint32) << 16).view(np.float32)
+    else:
+        array = np.frombuffer(body, dtype=np.dtype(header["dtype"]).newbyteorder("<"))
+    return header, array.reshape(header["shape"])
+
+
+def to_frame(df):
+    """Convert a serialized probing DataFrame into pandas (or a plain dict)."""
+    data = {}
+    for name, col in zip(df["names"], df["cols"]):
+        # a column without values is all nulls, as long as the frame
+        data[name] = [None] * df["size"] if col == "Nil" else list(col.values())[0]
+    try:
+        import pandas as pd
+
+        return pd.DataFrame(data)
+    except ImportError:
+        return data
+
+
+def discover(ctrl_root=None):
+    """
+    Find local processes with a probe.
+
+    Scans `PROBING_CTRL_ROOT` (default `/tmp/probing/`) for per-pid entries and,
+    on Linux, the abstract `@probing-<pid>` sockets in `/proc/net/unix`.
+    """
+    ctrl_root = ctrl_root or os.environ.get("PROBING_CTRL_ROOT", DEFAULT_CTRL_ROOT)
+    pids = _abstract_socket_pids()
+    try:
+        for entry in os.listdir(ctrl_root):
+            if entry.isdigit():
+                pids.add(int(entry))
+    except OSError:
+        pass
+    return [Probe(pid) for pid in sorted(pids) if _alive(pid)]
+
+
+def _alive(pid):
+    try:
+        os.kill(pid, 0)
+    except ProcessLookupError:
+        return False
+    except PermissionError:
+        pass
+    return True
@@ -0,0 +1,73 @@
+"""
+Python functions callable from SQL queries of the probe.
+
+Functions registered here are called row by row under the GIL, so they can
+use anything the target process has imported, e.g. its tokenizer or model
+config. A batch taking longer than ``python.udf_timeout`` milliseconds
+(default 1000) is interrupted with a ``TimeoutError`` that fails the query.
+The interruption is checked between Python bytecodes, so a single call stuck
+in native code is not cut short.
+
+Examples:
+    >>> import probing
+    >>> probing.register_udf("tokens_per_sec", lambda tokens, ms: tokens / ms * 1000)
+    >>> df = probing.query("SELECT tokens_per_sec(tokens, duration) AS tps FROM python.steps")
+
+Only functions registered by the target are callable, a query cannot send
+Python code of its own.
+"""
+
+import ctypes
+import threading
+
+_RETURN_TYPES = {"int", "float", "bool", "str"}
+
+
+def register_udf(name: str, func, returns="float") -> None:
+    """
+    Make ``func`` callable from SQL as ``name``.
+
+    Args:
+        name (str): Name of the SQL function, replaces an earlier one with the same name.
+        func (callable): Called with one argument per SQL argument for each row.
+        returns: Result type, one of ``int``, ``float``, ``bool`` or ``str``
+            (the types or their names).
+    """
+    import sys
+
+    if isinstance(returns, type):
+        returns = returns.__name__
+    if returns not in _RETURN_TYPES:
+        raise ValueError(
+            f"unsupported return type {returns!r}, expected one of {sorted(_RETURN_TYPES)}"
+        )
+    if not callable(func):
+        raise TypeError(f"{func!r} is not callable")
+    sys.modules["probing"]._register_udf(name, func, returns)
+
+
+def _apply(func, columns, timeout: float, rows: int):
+    """Call ``func`` on each row of ``columns``, raising ``TimeoutError``
+    after ``timeout`` seconds. Rows with a NULL give NULL without a call."""
+    ident = threading.get_ident()
+    lock = threading.Lock()
+    running = [True]
+
+    def interrupt():
+        with lock:
+            if running[0]:
+                ctypes.pythonapi.PyThreadState_SetAsyncExc(
+                    ctypes.c_ulong(ident), ctypes.py_object(TimeoutError)
+                )
+
+    timer = threading.Timer(timeout, interrupt)
+    timer.daemon = True
+    timer.start()
+    try:
+        if not columns:
+            return [func() for _ in range(rows)]
+        return [None if None in row else func(*row) for row in zip(*columns)]
+    finally:
+        with lock:
+            running[0] = False
+        timer.cancel()
@@ -1,6 +1,7 @@
 from .torch import get_torch_modules
 from .torch import get_torch_tensors
 from .torch import get_torch_optimizers
+from .torch import watch_model
 
 def get_dict():
     return {
@@ -0,0 +1,180 @@
+"""GPU devices of the host, their interconnects and the library versions
+loaded by the process, served as the `gpu.topology` table.
+
+Devices and links are read through NVML (``pynvml``), versions from the loaded
+``torch``; without NVML only the devices visible to torch are listed.
+"""
+
+import os
+import sys
+
+# NVML topology levels, named as in `nvidia-smi topo -m`
+TOPOLOGY_LEVELS = {
+    0: "internal",
+    10: "PIX",
+    20: "PXB",
+    30: "PHB",
+    40: "NODE",
+    50: "SYS",
+}
+
+
+def _text(value):
+    return value.decode() if isinstance(value, bytes) else value
+
+
+def visible_devices(value, uuids):
+    """Physical indexes of the devices seen by CUDA, in logical order.
+
+    ``value`` is ``CUDA_VISIBLE_DEVICES`` and ``uuids`` the UUIDs of the
+    physical devices. Every device is visible when the variable is unset.
+    Entries are indexes or UUID prefixes, and CUDA stops at the first one it
+    cannot resolve.
+    """
+    if value is None:
+        return list(range(len(uuids)))
+    devices = []
+    for entry in value.split(","):
+        entry = entry.strip()
+        if entry.isdigit() and int(entry) < len(uuids):
+            physical = int(entry)
+        else:
+            matches = [i for i, uuid in enumerate(uuids) if entry and uuid.startswith(entry)]
+            if len(matches) != 1:
+                break
+            physical = matches[0]
+        if physical in devices:
+            break
+        devices.append(physical)
+    return devices
+
+
+def library_versions():
+    """CUDA, cuDNN and NCCL versions of the loaded torch, None when unknown."""
+    versions = {"cuda": None, "cudnn": None, "nccl": None}
+    torch = sys.modules.get("torch")
+    if torch is None:
+        return versions
+    versions["cuda"] = getattr(torch.version, "cuda", None)
+    try:
+        cudnn = torch.backends.cudnn.version()
+        versions["cudnn"] = str(cudnn) if cudnn else None
+    except Exception:
+        pass
+    try:
+        nccl = torch.cuda.nccl.version()
+        if isinstance(nccl, tuple):
+            nccl = ".".join(str(part) for part in nccl)
+        versions["nccl"] = str(nccl)
+    except Exception:
+        pass
+    return versions
+
+
+def _nvlinks(pynvml, handle, peer_bus_id):
+    """Active NVLinks from ``handle`` to the device on ``peer_bus_id``."""
+    count = 0
+    for link in range(getattr(pynvml, "NVML_NVLINK_MAX_LINKS", 18)):
+        try:
+            if not pynvml.nvmlDeviceGetNvLinkState(handle, link):
+                continue
+            remote = _text(pynvml.nvmlDeviceGetNvLinkRemotePciInfo(handle, link).busId)
+        except Exception:  # no such link, or no NVLink at all
+            break
+        if remote.lower() == peer_bus_id.lower():
+            count += 1
+    return count
+
+
+def _nvml_rows(versions):
+    import pynvml
+
+    pynvml.nvmlInit()
+    try:
+        driver = _text(pynvml.nvmlSystemGetDriverVersion())
+        handles = [
+            pynvml.nvmlDeviceGetHandleByIndex(i)
+            for i in range(pynvml.nvmlDeviceGetCount())
+        ]
+        devices = [
+            {
+                "name": _text(pynvml.nvmlDeviceGetName(handle)),
+                "uuid": _text(pynvml.nvmlDeviceGetUUID(handle)),
+                "bus_id": _text(pynvml.nvmlDeviceGetPciInfo(handle).busId),
+            }
+            for handle in handles
+        ]
+        visible = visible_devices(
+            os.environ.get("CUDA_VISIBLE_DEVICES"), [d["uuid"] for d in devices]
+        )
+        logical = {physical: i for i, physical in enumerate(visible)}
+
+        rows = []
+        for i, (handle, device) in enumerate(zip(handles, devices)):
+            row = dict(device, device=logical.get(i), physical=i, driver=driver, **versions)
+            peers = [j for j in range(len(handles)) if j != i]
+            if not peers:
+                rows.append(dict(row, peer=None, peer_device=None, link=None, nvlinks=0))
+            for j in peers:
+                nvlinks = _nvlinks(pynvml, handle, devices[j]["bus_id"])
+                if nvlinks:
+                    link = "NVLink"
+                else:
+                    try:
+                        level = pynvml.nvmlDeviceGetTopologyCommonAncestor(handle, handles[j])
+                        link = TOPOLOGY_LEVELS.get(level, str(level))
+                    except Exception:
+                        link = None
+                rows.append(
+                    dict(row, peer=j, peer_device=logical.get(j), link=link, nvlinks=nvlinks)
+                )
+        return rows
+    finally:
+        pynvml.nvmlShutdown()
+
+
+def _torch_rows(versions):
+    torch = sys.modules.get("torch")
+    # querying devices would create a CUDA context in a process without one
+    if torch is None or not torch.cuda.is_initialized():
+        return []
+    return [
+        dict(
+            versions,
+            device=i,
+            physical=None,
+            name=torch.cuda.get_device_name(i),
+            uuid=None,
+            bus_id=None,
+            peer=None,
+            peer_device=None,
+            link=None,
+            nvlinks=0,
+            driver=None,
+        )
+        for i in range(torch.cuda.device_count())
+    ]
+
+
+def check_nvml():
+    """Initialise NVML once, returning its driver and device count; raises
+    with the reason when ``gpu.topology`` cannot read devices through it."""
+    try:
+        import pynvml
+    except ImportError:
+        raise RuntimeError("pynvml is not installed, `pip install nvidia-ml-py`") from None
+    pynvml.nvmlInit()
+    try:
+        driver = _text(pynvml.nvmlSystemGetDriverVersion())
+        return f"driver {driver}, {pynvml.nvmlDeviceGetCount()} devices"
+    finally:
+        pynvml.nvmlShutdown()
+
+
+def topology():
+    """One row per pair of devices of the host, one per device without peers."""
+    versions = library_versions()
+    try:
+        return _nvml_rows(versions)
+    except Exception:  # pynvml is missing or there is no driver
+        return _torch_rows(versions)
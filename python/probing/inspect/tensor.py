@@ -0,0 +1,125 @@
+"""
+Export tensors of the target process as raw bytes for remote inspection.
+
+A tensor is selected with a Python expression evaluated in ``__main__``, where
+models registered with ``probing.watch_model`` are also reachable as
+``models["name"]``. It is sliced where it lives, so only the requested part of
+an on-GPU tensor is copied to the host, and then exchanged through DLPack
+instead of being formatted as text.
+
+The reply is a 4-byte little-endian header length, a JSON header with
+``dtype``, ``shape``, ``device`` and the ``source_shape`` before slicing, and
+the C-contiguous little-endian data. ``bfloat16`` data is sent as its raw 16
+bits. ``probing.client.decode_tensor`` turns a reply back into an array.
+
+Examples:
+    >>> from probing.client import Probe
+    >>> Probe(1234).tensor('models["Net"].fc.weight', "0:4, :8")  # doctest: +SKIP
+    array([[...]], dtype=float32)
+"""
+
+import json
+import math
+import struct
+
+# Elements exported at most, larger selections must be sliced further
+MAX_ELEMENTS = 1 << 20
+
+
+def parse_index(index):
+    """Parse a slice such as ``"0, 2:10:2, ..."`` into an indexing tuple."""
+    if index is None or not index.strip():
+        return ()
+    items = []
+    for part in index.split(","):
+        part = part.strip()
+        if part == "...":
+            items.append(Ellipsis)
+        elif ":" in part:
+            bounds = [int(b) if b.strip() else None for b in part.split(":")]
+            if len(bounds) > 3:
+                raise ValueError(f"invalid slice {part!r}")
+            items.append(slice(*bounds))
+        else:
+            items.append(int(part))
+    return tuple(items)
+
+
+def _namespace():
+    import __main__
+
+    namespace = dict(vars(__main__))
+    try:
+        from .torch import _watched
+
+        namespace["models"] = {name: model for name, model, _ in _watched()}
+    except ImportError:
+        pass
+    return namespace
+
+
+def _to_numpy(value):
+    """Move ``value`` to host memory through DLPack, copying only if needed."""
+    import numpy as np
+
+    if type(value).__module__.startswith("torch"):
+        value = value.detach()
+        if str(value.dtype) == "torch.bfloat16":
+            # numpy has no bfloat16, keep the bits
+            import torch
+
+            return value.view(torch.int16).cpu().numpy().view(np.uint16), "bfloat16"
+        if value.device.type != "cpu":
+            value = value.cpu()
+    if hasattr(value, "__dlpack__"):
+        try:
+            array = np.from_dlpack(value)
+        except (BufferError, RuntimeError, TypeError):
+            array = np.asarray(value)
+    else:
+        array = np.asarray(value)
+    return array, str(array.dtype)
+
+
+def export(expr, index=None, limit=MAX_ELEMENTS):
+    """
+    Evaluate ``expr``, slice it with ``index`` and return the encoded tensor.
+
+    Args:
+        expr (str): Expression giving a tensor or any DLPack/array-like object.
+        index (str): Optional slice, e.g. ``"0:4, :8"``, applied before the data
+            leaves the device.
+        limit (int): Maximum number of elements to export.
+    """
+    import numpy as np
+
+    value = eval(expr, _namespace())
+    source_shape = list(getattr(value, "shape", ()))
+    device = str(getattr(value, "device", "cpu"))
+    key = parse_index(index)
+    if key:
+        value = value[key]
+
+    # checked before anything is copied off the device
+    size = math.prod(getattr(value, "shape", ()))
+    if size > limit:
+        raise ValueError(
+            f"{expr} selects {size} elements, more than {limit}; pass a smaller slice"
+        )
+
+    array, dtype = _to_numpy(value)
+    array = np.ascontiguousarray(array)
+    if array.dtype.byteorder == ">":
+        array = array.byteswap().view(array.dtype.newbyteorder("<"))
+        dtype = str(array.dtype)
+
+    header = json.dumps(
+        {
+            "dtype": dtype,
+            "shape": list(array.shape),
+            "device": device,
+            "source_shape": [int(d) for d in source_shape],
+        }
+    ).encode()
+    return struct.pack("<I", len(header)) + header + array.tobytes()
+
@@ -92,4 +92,102 @@ def get_torch_optimizers():
         # Rebuild the list from the now-refreshed cache
         active_items, _ = _build_active_list_and_clean_cache(optim_cache)
         
-    return active_items
\ No newline at end of file
+    return active_items
+
+# Models (and their optimizers) registered with `probing.watch_model`
+watched_models = {}
+
+
+def watch_model(model, optimizer=None, name=None):
+    """Register a model (and optionally its optimizer) for inspection.
+
+    Watched models back the `torch.parameters` and `torch.optimizer_state`
+    tables. Only weak references are kept, so watching a model does not
+    extend its lifetime.
+
+    Returns the name the model is registered under.
+    """
+    if name is None:
+        name = type(model).__name__
+        base, idx = name, 1
+        while name in watched_models and watched_models[name][0]() not in (None, model):
+            name = f"{base}_{idx}"
+            idx += 1
+    watched_models[name] = (
+        weakref.ref(model),
+        weakref.ref(optimizer) if optimizer is not None else None,
+    )
+    return name
+
+
+def _watched():
+    for name, (model_ref, optim_ref) in list(watched_models.items()):
+        model = model_ref()
+        if model is None:
+            del watched_models[name]
+            continue
+        yield name, model, optim_ref() if optim_ref is not None else None
+
+
+def _tensor_norm(tensor):
+    if tensor.is_sparse:
+        tensor = tensor.coalesce().values()
+    return float(tensor.detach().float().norm())
+
+
+def get_torch_parameters():
+    rows = []
+    for model_name, model, _ in _watched():
+        for name, param in model.named_parameters():
+            grad = param.grad
+            rows.append({
+                "model": model_name,
+                "name": name,
+                "shape": str(list(param.shape)),
+                "dtype": str(param.dtype),
+                "device": str(param.device),
+                "layout": str(param.layout),
+                "numel": param.numel(),
+                "requires_grad": param.requires_grad,
+                "norm": _tensor_norm(param),
+                "grad_norm": _tensor_norm(grad) if grad is not None else None,
+            })
+    return rows
+
+
+def get_torch_optimizer_state():
+    import torch
+
+    rows = []
+    for model_name, model, optimizer in _watched():
+        if optimizer is None:
+            continue
+        names = {id(p): n for n, p in model.named_parameters()}
+        for group_idx, group in enumerate(optimizer.param_groups):
+            lr = group.get("lr")
+            for param in group["params"]:
+                for slot, value in optimizer.state.get(param, {}).items():
+                    row = {
+                        "model": model_name,
+                        "optimizer": type(optimizer).__name__,
+                        "param_group": group_idx,
+                        "lr": float(lr) if lr is not None else None,
+                        "param": names.get(id(param), str(id(param))),
+                        "slot": str(slot),
+                        "shape": None,
+                        "dtype": None,
+                        "layout": None,
+                        "numel": None,
+                        "norm": None,
+                        "value": None,
+                    }
+                    if isinstance(value, torch.Tensor) and value.dim() > 0:
+                        row["shape"] = str(list(value.shape))
+                        row["dtype"] = str(value.dtype)
+                        row["layout"] = str(value.layout)
+                        row["numel"] = value.numel()
+                        row["norm"] = _tensor_norm(value)
+                    elif isinstance(value, (torch.Tensor, int, float)):
+                        row["value"] = float(value)
+                    rows.append(row)
+    return rows
@@ -0,0 +1,156 @@
+"""
+Launch a script with a probe: `python -m probing run train.py --port 9700`.
+
+Options of the launcher may follow the target; the arguments of the target
+come after `--`.
+
+The target is activated through the `PROBING` environment variable that
+`probing_hook` reads at interpreter startup, so neither LD_PRELOAD nor ptrace
+is involved. Settings are passed as `PROBING_*` variables, which the probe
+applies as `SET probing.*` once it is up.
+
+Examples:
+    python -m probing run train.py --port 9700 --enable pprof -- --lr 0.1
+    python -m probing run --nested --console torchrun -- --nproc-per-node 8 train.py
+"""
+
+import argparse
+import os
+import subprocess
+import sys
+import time
+
+# settings applied by `--enable <feature>`
+FEATURES = {
+    "pprof": {"PROBING_PPROF_SAMPLE_FREQ": "99"},
+    "output": {"PROBING_OUTPUT_CAPTURE": "true"},
+}
+
+
+def parser():
+    p = argparse.ArgumentParser(prog="python -m probing")
+    commands = p.add_subparsers(dest="command", required=True)
+
+    run = commands.add_parser("run", help="run a script or command with a probe")
+    run.add_argument("target", help="Python script, or a command such as torchrun")
+    run.add_argument("--port", help="serve the probe over TCP, a port or a range like 9700-9799")
+    run.add_argument(
+        "--enable",
+        action="append",
+        default=[],
+        choices=sorted(FEATURES),
+        help="turn on a feature at startup, may be repeated",
+    )
+    run.add_argument(
+        "--set",
+        action="append",
+        default=[],
+        metavar="KEY=VALUE",
+        help="apply `SET probing.KEY=VALUE` at startup, e.g. pprof.sample.freq=199",
+    )
+    run.add_argument(
+        "--nested",
+        action="store_true",
+        help="probe the child processes of the target too, e.g. the workers of torchrun",
+    )
+    run.add_argument(
+        "--console",
+        action="store_true",
+        help="attach a SQL console to the probe of the target",
+    )
+    return p
+
+
+def build_env(args, base=None):
+    """Environment of the target for the parsed `run` arguments"""
+    env = dict(os.environ if base is None else base)
+    env["PROBING"] = "2" if args.nested else "1"
+    if args.port:
+        env["PROBING_PORT"] = str(args.port)
+    for feature in args.enable:
+        env.update(FEATURES[feature])
+    for setting in args.set:
+        key, sep, value = setting.partition("=")
+        if not sep or not key.strip():
+            raise ValueError(f"invalid setting `{setting}`, expected KEY=VALUE")
+        key = key.strip()
+        if key.startswith("probing."):
+            key = key[len("probing.") :]
+        env["PROBING_" + key.replace(".", "_").upper()] = value
+    return env
+
+
+def command(args):
+    """Command line of the target, scripts are run by the current interpreter"""
+    if args.target.endswith(".py"):
+        return [sys.executable, args.target, *args.args]
+    return [args.target, *args.args]
+
+
+def wait_for_probe(proc, timeout=30.0):
+    """Probe of `proc` once its socket accepts queries, None if it never does"""
+    from probing.client import Probe
+
+    probe = Probe(proc.pid, timeout=2.0)
+    deadline = time.monotonic() + timeout
+    while proc.poll() is None and time.monotonic() < deadline:
+        try:
+            probe.query("SELECT 1")
+            return probe
+        except (OSError, RuntimeError):
+            time.sleep(0.2)
+    return None
+
+
+def console(probe, proc):
+    """Read SQL statements until EOF or until the target exits"""
+    print(f"probing console attached to pid {proc.pid}, Ctrl-D to detach", file=sys.stderr)
+    while proc.poll() is None:
+        try:
+            sql = input("probing> ").strip()
+        except EOFError:
+            break
+        if not sql:
+            continue
+        try:
+            print(probe.query(sql))
+        except (OSError, RuntimeError) as e:
+            print(f"error: {e}", file=sys.stderr)
+
+
+def run(args):
+    try:
+        env = build_env(args)
+    except ValueError as e:
+        print(e, file=sys.stderr)
+        return 2
+    proc = subprocess.Popen(command(args), env=env)
+    try:
+        if args.console:
+            probe = wait_for_probe(proc)
+            if probe is None:
+                print("probe of the target did not come up, no console", file=sys.stderr)
+            else:
+                console(probe, proc)
+        return proc.wait()
+    except KeyboardInterrupt:
+        # the target got the same SIGINT, let it shut down on its own
+        return proc.wait()
+
+
+def parse(argv):
+    """Launcher options before `--`, arguments of the target after"""
+    if "--" in argv:
+        split = argv.index("--")
+        argv, extra = argv[:split], argv[split + 1 :]
+    else:
+        extra = []
+    args = parser().parse_args(argv)
+    args.args = extra
+    return args
+
+
+def main(argv=None):
+    args = parse(sys.argv[1:] if argv is None else argv)
+    if args.command == "run":
+        sys.exit(run(args))
@@ -0,0 +1,153 @@
+"""File, network and subprocess access monitor based on `sys.addaudithook`.
+
+Matching audit events are recorded in the `python.audit_events` table, which
+helps explain unexpected network calls or misconfigured dataset paths:
+
+    set probing.python.enabled = 'probing.profiling.audit_monitor';
+    select event, target, count(*) from python.audit_events group by event, target;
+
+Audit hooks cannot be removed once installed, so `disable()` only stops
+recording. Use `configure()` to narrow down what is recorded and to cap the
+number of events per second; events over the cap are counted in `dropped()`.
+"""
+
+import re
+import sys
+import threading
+import time
+import traceback
+from dataclasses import dataclass
+from typing import Optional
+
+from probing.core import table
+
+DEFAULT_EVENTS = (
+    "open",
+    "socket.connect",
+    "socket.bind",
+    "subprocess.Popen",
+    "os.system",
+    "os.exec",
+    "os.posix_spawn",
+)
+
+
+@table("audit_events")
+@dataclass
+class AuditEvent:
+    event: Optional[str] = None
+    target: Optional[str] = None
+    detail: Optional[str] = None
+    thread: Optional[str] = None
+    caller: Optional[str] = None
+
+
+_installed = False
+_enabled = False
+_events = set(DEFAULT_EVENTS)
+_pattern = None
+_max_per_second = 100
+_window = 0
+_count = 0
+_dropped = 0
+_local = threading.local()
+
+
+def configure(events=None, pattern=None, max_per_second=None):
+    """
+    Set which events are recorded.
+
+    Args:
+        events: audit event names to record, defaults to file opens, socket
+            connects/binds and process creation.
+        pattern: regex that the event target (path, address, executable) must
+            match; `None` records every target.
+        max_per_second: upper bound of recorded events per second.
+    """
+    global _events, _pattern, _max_per_second
+    if events is not None:
+        _events = set(events)
+    _pattern = re.compile(pattern) if pattern else None
+    if max_per_second is not None:
+        _max_per_second = int(max_per_second)
+
+
+def _target(event, args):
+    if event == "open":
+        return str(args[0]), f"mode={args[1]}"
+    if event.startswith("socket."):
+        return str(args[1]), None
+    if event == "subprocess.Popen":
+        return str(args[0]), " ".join(map(str, args[1] or []))
+    if event == "os.system":
+        return str(args[0]), None
+    return str(args[0]) if args else None, None
+
+
+def _caller():
+    for frame in reversed(traceback.extract_stack(limit=8)[:-3]):
+        if "/probing/" not in frame.filename:
+            return f"{frame.filename}:{frame.lineno}"
+    return None
+
+
+def _allow():
+    global _window, _count, _dropped
+    now = int(time.monotonic())
+    if now != _window:
+        _window, _count = now, 0
+    if _count >= _max_per_second:
+        _dropped += 1
+        return False
+    _count += 1
+    return True
+
+
+def _hook(event, args):
+    if not _enabled or event not in _events or getattr(_local, "busy", False):
+        return
+    _local.busy = True
+    try:
+        target, detail = _target(event, args)
+        if _pattern is not None and (target is None or not _pattern.search(target)):
+            return
+        if not _allow():
+            return
+        AuditEvent(
+            event=event,
+            target=target,
+            detail=detail,
+            thread=threading.current_thread().name,
+            caller=_caller(),
+        ).save()
+    except Exception:
+        # Audit hooks must never break the audited operation
+        pass
+    finally:
+        _local.busy = False
+
+
+def enable():
+    global _installed, _enabled
+    if not _installed:
+        sys.addaudithook(_hook)
+        _installed = True
+    _enabled = True
+
+
+def disable():
+    global _enabled
+    _enabled = False
+
+
+def dropped():
+    """Number of events skipped because of the rate cap."""
+    return _dropped
+
+
+def init():
+    enable()
+
+
+def deinit():
+    disable()
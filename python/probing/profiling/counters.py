@@ -0,0 +1,208 @@
+"""Call and exception counters based on PEP 669 `sys.monitoring`
+(Python 3.12+), served as the `python.counters` table once enabled with
+`SET probing.python.counters=true`.
+
+Calls are counted per function, by file and qualified name, and reported per
+function group, the top level package the code belongs to (`torch`, `numpy`,
+`__main__`, ...). Exceptions are counted per type where they are raised.
+
+Two tool ids are used. The first sees the first call of every function, arms
+the `PY_START` events of that code object alone for the second one and
+returns `DISABLE`, so it costs nothing afterwards. A function then stops
+reporting its calls once it reached `MAX_CALLS_PER_TICK` within a tick, by
+switching its local events off, and every `TICK` seconds the quiet functions
+are armed again one by one; the events of other tools are left alone. Cold
+functions are counted exactly, the calls of a hot one are a lower bound and
+its group is flagged as `saturated`. Exceptions are rare enough to always be
+counted exactly. At most `MAX_FUNCTIONS` functions are kept, the calls of
+later ones are counted under `<other>` of their file.
+
+    select "group", count, functions, saturated from python.counters
+    where kind = 'call' order by count desc;
+"""
+
+import os
+import sys
+import threading
+import weakref
+from collections import defaultdict
+
+TOOL_NAME = "probing.counters"
+
+# seconds between two re-arms of the call events
+TICK = 10.0
+# calls of one function counted per tick before it goes quiet until the next
+MAX_CALLS_PER_TICK = 100
+# functions counted separately, code compiled at runtime adds up
+MAX_FUNCTIONS = 10000
+
+_tool_ids = None  # (tool seeing first calls, tool counting calls)
+_calls = defaultdict(int)  # (file, qualname) -> counted calls
+_tick = defaultdict(int)  # (file, qualname) -> calls counted in this tick
+_saturated = set()  # (file, qualname) of functions that hit the cap at least once
+_armed = weakref.WeakSet()  # code objects seen, with local events
+_quiet = weakref.WeakSet()  # code objects switched off until the next tick
+_exceptions = defaultdict(int)  # exception type -> raised
+_stop = None
+
+
+def _monitoring():
+    monitoring = getattr(sys, "monitoring", None)
+    if monitoring is None:
+        raise RuntimeError(
+            f"sys.monitoring requires Python 3.12+, got {sys.version.split()[0]}"
+        )
+    return monitoring
+
+
+def _key(code):
+    key = (code.co_filename, code.co_qualname)
+    if key in _calls or len(_calls) < MAX_FUNCTIONS:
+        return key
+    return (code.co_filename, "<other>")
+
+
+def _count(code, tool_id):
+    key = _key(code)
+    calls = _tick[key] = _tick[key] + 1
+    _calls[key] += 1
+    if calls >= MAX_CALLS_PER_TICK:
+        _saturated.add(key)
+        sys.monitoring.set_local_events(tool_id, code, 0)
+        _quiet.add(code)
+
+
+def _on_first_call(code, offset):
+    tool_id = _tool_ids[1]
+    sys.monitoring.set_local_events(tool_id, code, sys.monitoring.events.PY_START)
+    _armed.add(code)
+    _count(code, tool_id)
+    return sys.monitoring.DISABLE
+
+
+def _on_start(code, offset):
+    _count(code, _tool_ids[1])
+
+
+def _on_raise(code, offset, exception):
+    _exceptions[type(exception)] += 1
+
+
+def _rearm(stop, monitoring, tool_id):
+    while not stop.wait(TICK):
+        _tick.clear()
+        quiet = list(_quiet)
+        _quiet.clear()
+        for code in quiet:
+            monitoring.set_local_events(tool_id, code, monitoring.events.PY_START)
+
+
+def enable():
+    """Start counting; calling it twice is a no-op."""
+    global _tool_ids, _stop
+    if _tool_ids is not None:
+        return
+    monitoring = _monitoring()
+    # the ids of debuggers and coverage tools are left alone
+    free = [tool_id for tool_id in (4, 3, 2) if monitoring.get_tool(tool_id) is None]
+    if len(free) < 2:
+        raise RuntimeError("no free sys.monitoring tool ids")
+    first, counting = free[:2]
+    events = monitoring.events
+    monitoring.use_tool_id(first, TOOL_NAME)
+    monitoring.use_tool_id(counting, TOOL_NAME)
+    monitoring.register_callback(first, events.PY_START, _on_first_call)
+    monitoring.register_callback(first, events.RAISE, _on_raise)
+    monitoring.register_callback(counting, events.PY_START, _on_start)
+    _tool_ids = (first, counting)
+    # functions seen before a `disable` are not reported as first calls again
+    for code in list(_armed):
+        monitoring.set_local_events(counting, code, events.PY_START)
+    monitoring.set_events(first, events.PY_START | events.RAISE)
+
+    _stop = threading.Event()
+    threading.Thread(
+        target=_rearm,
+        args=(_stop, monitoring, counting),
+        name="probing-counters",
+        daemon=True,
+    ).start()
+
+
+def disable():
+    """Stop counting, keeping the counts so far."""
+    global _tool_ids, _stop
+    if _tool_ids is None:
+        return
+    monitoring = _monitoring()
+    _stop.set()
+    first, counting = _tool_ids
+    monitoring.set_events(first, 0)
+    for code in list(_armed):
+        monitoring.set_local_events(counting, code, 0)
+    _quiet.clear()
+    for tool_id in _tool_ids:
+        for event in (monitoring.events.PY_START, monitoring.events.RAISE):
+            monitoring.register_callback(tool_id, event, None)
+        monitoring.free_tool_id(tool_id)
+    _tool_ids, _stop = None, None
+
+
+def is_enabled():
+    return _tool_ids is not None
+
+
+def _groups():
+    """Top level package of every loaded module, by source file."""
+    groups = {}
+    for name, module in list(sys.modules.items()):
+        path = getattr(module, "__file__", None)
+        if path:
+            groups[path] = name.split(".")[0]
+    return groups
+
+
+def _group(filename, groups):
+    group = groups.get(filename)
+    if group is not None:
+        return group
+    if filename.startswith("<"):
+        return filename  # e.g. <string> or <frozen importlib._bootstrap>
+    return os.path.splitext(os.path.basename(filename))[0]
+
+
+def counters():
+    """Rows of `python.counters`: calls per function group, most called
+    first, then exceptions per type."""
+    groups = _groups()
+    calls = defaultdict(lambda: {"count": 0, "functions": 0, "saturated": False})
+    for key, count in list(_calls.items()):
+        row = calls[_group(key[0], groups)]
+        row["count"] += count
+        row["functions"] += 1
+        row["saturated"] |= key in _saturated
+    rows = [
+        dict(kind="call", group=group, name=None, **row)
+        for group, row in sorted(calls.items(), key=lambda item: -item[1]["count"])
+    ]
+    for kind, count in sorted(list(_exceptions.items()), key=lambda item: -item[1]):
+        module = kind.__module__
+        rows.append(
+            dict(
+                kind="exception",
+                group=module.split(".")[0],
+                name=kind.__qualname__ if module == "builtins" else f"{module}.{kind.__qualname__}",
+                count=count,
+                functions=None,
+                saturated=False,
+            )
+        )
+    return rows
+
+
+def reset():
+    """Forget the counts so far."""
+    _calls.clear()
+    _tick.clear()
+    _saturated.clear()
+    _exceptions.clear()
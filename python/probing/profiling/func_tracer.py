@@ -0,0 +1,159 @@
+"""Per-function tracing based on PEP 669 `sys.monitoring` (Python 3.12+).
+
+Only the code objects of the selected functions get local events enabled, so
+untraced code runs at full speed. Each call is recorded as a span in the
+probing trace system. `PY_UNWIND` cannot be enabled per code object, so it is
+turned on for the whole process only while a traced call is running, to end
+its span when an exception leaves it.
+
+Functions are selected with `pkg.mod:func` or `pkg.mod:Class.method`, comma
+separated:
+
+    set probing.python.trace_functions = 'train:step,model:Net.forward';
+
+Setting an empty value turns tracing off.
+"""
+
+import importlib
+import inspect
+import sys
+
+TOOL_NAME = "probing"
+
+_tool_id = None
+_traced = {}  # code object -> span name
+_running = 0  # traced calls in flight, PY_UNWIND is on while nonzero
+
+
+def _monitoring():
+    monitoring = getattr(sys, "monitoring", None)
+    if monitoring is None:
+        raise RuntimeError(
+            f"sys.monitoring requires Python 3.12+, got {sys.version.split()[0]}"
+        )
+    return monitoring
+
+
+def _acquire_tool_id(monitoring):
+    global _tool_id
+    if _tool_id is not None:
+        return _tool_id
+    for tool_id in (3, 4, 0, 1, 2, 5):
+        if monitoring.get_tool(tool_id) is None:
+            monitoring.use_tool_id(tool_id, TOOL_NAME)
+            _tool_id = tool_id
+            return tool_id
+    raise RuntimeError("no free sys.monitoring tool id")
+
+
+def _release_tool_id(monitoring):
+    global _tool_id, _running
+    if _tool_id is None:
+        return
+    _running = 0
+    monitoring.set_events(_tool_id, 0)
+    for event in (
+        monitoring.events.PY_START,
+        monitoring.events.PY_RESUME,
+        monitoring.events.PY_RETURN,
+        monitoring.events.PY_YIELD,
+        monitoring.events.PY_UNWIND,
+    ):
+        monitoring.register_callback(_tool_id, event, None)
+    monitoring.free_tool_id(_tool_id)
+    _tool_id = None
+
+
+def resolve(spec):
+    """Resolve `pkg.mod:func` or `pkg.mod:Class.method` to a code object."""
+    if ":" not in spec:
+        raise ValueError(f"invalid function spec `{spec}`, expected `module:qualname`")
+    modname, qualname = spec.split(":", 1)
+    obj = importlib.import_module(modname)
+    for attr in qualname.split("."):
+        obj = getattr(obj, attr)
+    obj = inspect.unwrap(getattr(obj, "__func__", obj))
+    code = getattr(obj, "__code__", None)
+    if code is None:
+        raise ValueError(f"`{spec}` is not a Python function")
+    return code
+
+
+def _enter():
+    global _running
+    _running += 1
+    if _running == 1 and _tool_id is not None:
+        sys.monitoring.set_events(_tool_id, sys.monitoring.events.PY_UNWIND)
+
+
+def _exit():
+    global _running
+    _running = max(_running - 1, 0)
+    if _running == 0 and _tool_id is not None:
+        sys.monitoring.set_events(_tool_id, 0)
+
+
+def _on_start(code, offset):
+    name = _traced.get(code)
+    if name is not None:
+        import probing
+
+        _enter()
+        probing._trace_enter(name, f"{code.co_filename}:{code.co_firstlineno}")
+
+
+def _on_return(code, offset, retval):
+    if code in _traced:
+        import probing
+
+        probing._trace_exit(None)
+        _exit()
+
+
+def _on_unwind(code, offset, exception):
+    # fires for every frame unwound in the process while a traced call runs
+    if code in _traced:
+        import probing
+
+        probing._trace_exit(repr(exception))
+        _exit()
+
+
+def set_trace_functions(specs):
+    """Replace the traced function set; an empty spec disables tracing."""
+    monitoring = _monitoring()
+    specs = [s.strip() for s in (specs or "").split(",") if s.strip()]
+
+    traced = {}
+    for spec in specs:
+        traced[resolve(spec)] = spec
+
+    if _tool_id is not None:
+        for code in _traced:
+            monitoring.set_local_events(_tool_id, code, 0)
+    _traced.clear()
+
+    if not traced:
+        _release_tool_id(monitoring)
+        return []
+
+    tool_id = _acquire_tool_id(monitoring)
+
+    events = monitoring.events
+    monitoring.register_callback(tool_id, events.PY_START, _on_start)
+    monitoring.register_callback(tool_id, events.PY_RESUME, _on_start)
+    monitoring.register_callback(tool_id, events.PY_RETURN, _on_return)
+    monitoring.register_callback(tool_id, events.PY_YIELD, _on_return)
+    monitoring.register_callback(tool_id, events.PY_UNWIND, _on_unwind)
+
+    local_events = (
+        events.PY_START | events.PY_RESUME | events.PY_RETURN | events.PY_YIELD
+    )
+    for code, spec in traced.items():
+        monitoring.set_local_events(tool_id, code, local_events)
+        _traced[code] = spec
+    return list(traced.values())
+
+
+def traced_functions():
+    return list(_traced.values())
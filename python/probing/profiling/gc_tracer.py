@@ -0,0 +1,76 @@
+"""Garbage collection pause tracking via `gc.callbacks`.
+
+Every collection is recorded in the `python.gc` table with its generation,
+pause duration in seconds and the number of collected and uncollectable
+objects. Long generation 2 pauses are a common cause of periodic step-time
+spikes:
+
+    set probing.python.enabled = 'probing.profiling.gc_tracer';
+    select * from python.gc where generation = 2 order by duration desc;
+"""
+
+import gc
+import time
+from dataclasses import dataclass
+from typing import Optional
+
+from probing.core import table
+
+
+@table("gc")
+@dataclass
+class GcEvent:
+    generation: Optional[int] = None
+    duration: float = 0.0
+    collected: int = 0
+    uncollectable: int = 0
+
+
+_start = None
+
+
+def _callback(phase, info):
+    global _start
+    if phase == "start":
+        _start = time.perf_counter()
+        return
+    if _start is None:
+        return
+    duration = time.perf_counter() - _start
+    _start = None
+    try:
+        GcEvent(
+            generation=info.get("generation"),
+            duration=duration,
+            collected=info.get("collected", 0),
+            uncollectable=info.get("uncollectable", 0),
+        ).save()
+    except Exception:
+        # Never let a probing failure escape into the collector
+        pass
+
+
+def enable():
+    """Start recording collections; calling it twice is a no-op."""
+    if _callback not in gc.callbacks:
+        gc.callbacks.append(_callback)
+
+
+def disable():
+    """Stop recording collections, keeping the events recorded so far."""
+    global _start
+    if _callback in gc.callbacks:
+        gc.callbacks.remove(_callback)
+    _start = None
+
+
+def is_enabled():
+    return _callback in gc.callbacks
+
+
+def init():
+    enable()
+
+
+def deinit():
+    disable()
@@ -0,0 +1,130 @@
+"""Snapshots of the Python heap, taken by the ``@heap_snapshot`` action.
+
+A snapshot lists the object types holding the most memory, the CPU tensors
+when torch is loaded and, if ``tracemalloc`` is tracing, the source lines that
+allocated the most. Wired to the memory warnings of the probe, it tells what
+filled the memory before the OOM killer ends the process:
+
+    set probing.memory.interval = 5;
+    set probing.events.rule.oom_snapshot = 'memory.warning @heap_snapshot';
+    select id, timestamp, trigger, bytes from profiles.catalog where profiler = 'heap';
+
+Sizes of objects are shallow (``sys.getsizeof``): a list is counted without
+its items, which are counted under their own types.
+"""
+
+import gc
+import json
+import sys
+import time
+
+# types, tensors groups and allocation sites reported
+TOP = 30
+
+
+def _rss():
+    try:
+        with open("/proc/self/status") as f:
+            for line in f:
+                if line.startswith("VmRSS:"):
+                    return int(line.split()[1]) * 1024
+    except OSError:
+        pass
+    return None
+
+
+def _type_name(t):
+    module = getattr(t, "__module__", None)
+    name = getattr(t, "__qualname__", t.__name__)
+    return name if module in (None, "builtins") else f"{module}.{name}"
+
+
+def _types(objects, top):
+    sizes = {}
+    for obj in objects:
+        t = type(obj)
+        try:
+            size = sys.getsizeof(obj)
+        except Exception:
+            size = 0
+        count, total = sizes.get(t, (0, 0))
+        sizes[t] = (count + 1, total + size)
+    ranked = sorted(sizes.items(), key=lambda item: item[1][1], reverse=True)
+    return [
+        {"type": _type_name(t), "count": count, "size": size}
+        for t, (count, size) in ranked[:top]
+    ]
+
+
+def _tensors(objects, top):
+    """CPU tensors by dtype and shape, each storage counted once."""
+    torch = sys.modules.get("torch")
+    if torch is None:
+        return None
+    seen = set()
+    groups = {}
+    for obj in objects:
+        try:
+            if not isinstance(obj, torch.Tensor) or obj.device.type != "cpu":
+                continue
+            storage = obj.untyped_storage()
+            key = storage.data_ptr()
+            size = 0 if key in seen else storage.nbytes()
+            seen.add(key)
+            group = f"{obj.dtype} {tuple(obj.shape)}"
+        except Exception:
+            continue
+        count, total = groups.get(group, (0, 0))
+        groups[group] = (count + 1, total + size)
+    ranked = sorted(groups.items(), key=lambda item: item[1][1], reverse=True)
+    return [
+        {"tensor": group, "count": count, "size": size}
+        for group, (count, size) in ranked[:top]
+    ]
+
+
+def _allocations(top):
+    """Source lines holding the most memory, when tracemalloc is tracing."""
+    tracemalloc = sys.modules.get("tracemalloc")
+    if tracemalloc is None or not tracemalloc.is_tracing():
+        return None
+    stats = tracemalloc.take_snapshot().statistics("lineno")
+    return [
+        {
+            "site": f"{stat.traceback[0].filename}:{stat.traceback[0].lineno}",
+            "count": stat.count,
+            "size": stat.size,
+        }
+        for stat in stats[:top]
+    ]
+
+
+def snapshot(top=TOP):
+    """
+    Take a snapshot of the heap.
+
+    Returns a dict with the resident memory of the process (``rss``), the
+    ``top`` object types by size (``types``), CPU tensors by dtype and shape
+    (``tensors``, None without torch) and allocation sites (``allocations``,
+    None unless tracemalloc is tracing).
+    """
+    started = time.time()
+    objects = gc.get_objects()
+    try:
+        result = {
+            "timestamp": int(started * 1e6),
+            "rss": _rss(),
+            "objects": len(objects),
+            "types": _types(objects, top),
+            "tensors": _tensors(objects, top),
+            "allocations": _allocations(top),
+        }
+    finally:
+        del objects
+    result["duration"] = time.time() - started
+    return result
+
+
+def snapshot_json(top=TOP):
+    """`snapshot()` as JSON, taken by the ``@heap_snapshot`` action."""
+    return json.dumps(snapshot(top))
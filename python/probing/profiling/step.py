@@ -0,0 +1,47 @@
+"""
+Profiles of single training steps.
+
+``profile_step()`` brackets one step: the profiler samples at a high rate
+only inside the bracket, starting if nothing was sampling yet, and the
+samples are tagged with a ``step`` span carrying the step id. When the step
+ends its stacks are kept as the profile of the step, listed by
+``probe.steps`` and served by ``/apis/steps/<step>``, where ``?base=<step>``
+compares two steps in a differential flamegraph.
+
+Examples:
+    >>> import probing
+    >>> for step, batch in enumerate(loader):
+    ...     with probing.profile_step(step):
+    ...         train_step(batch)
+
+    >>> # at 2000Hz, in wall mode to see the time waiting for data
+    >>> with probing.profile_step(frequency=2000, mode="wall"):
+    ...     train_step(next(loader))
+"""
+
+import contextlib
+
+
+@contextlib.contextmanager
+def profile_step(step=None, frequency=None, mode=None):
+    """
+    Profile the code run within the ``with`` block as step ``step``.
+
+    Args:
+        step (int): Id of the step, the one after the last profiled step by default.
+        frequency (int): Sample rate within the step in Hz, 1000 by default.
+        mode (str): ``cpu`` or ``wall``, used when nothing was sampling before
+            the step; a running profiler keeps its mode.
+
+    Yields the id of the step. The samples of the step are the ones taken in
+    the thread entering the block, while it is inside.
+    """
+    import probing
+
+    step = probing._profile_step_begin(step, frequency, mode)
+    try:
+        yield step
+    except BaseException as e:
+        probing._profile_step_end(repr(e))
+        raise
+    probing._profile_step_end(None)
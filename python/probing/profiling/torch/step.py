@@ -1,6 +1,35 @@
+import sys
+import time
+
 LAYER_ITER = 0
 OPTIM_ITER = 1
 
+# GPU utilization is read through NVML, sample it at most once per second
+GPU_SAMPLE_INTERVAL = 1.0
+_LAST_GPU_SAMPLE = 0.0
+
+
+def _gpu_util():
+    global _LAST_GPU_SAMPLE
+    now = time.monotonic()
+    if now - _LAST_GPU_SAMPLE < GPU_SAMPLE_INTERVAL:
+        return None
+    _LAST_GPU_SAMPLE = now
+    torch = sys.modules.get("torch")
+    try:
+        if torch is None or not torch.cuda.is_available():
+            return None
+        return float(torch.cuda.utilization())
+    except Exception:  # pynvml is missing or the driver refused
+        return None
+
+
+def _publish_step():
+    """Mirror the step into the status segment read by `probing status --fast`."""
+    publish = getattr(sys.modules.get("probing"), "publish_metrics", None)
+    if publish is not None:
+        publish(step=OPTIM_ITER, gpu_util=_gpu_util())
+
 
 def reset_layer(cnt=None):
     global LAYER_ITER
@@ -17,11 +46,13 @@ def reset_step(cnt=None):
     global OPTIM_ITER
     cnt = 0 if cnt is None else cnt
     OPTIM_ITER = cnt
+    _publish_step()
 
 
 def next_step():
     global OPTIM_ITER
     OPTIM_ITER += 1
+    _publish_step()
 
 
 def step():
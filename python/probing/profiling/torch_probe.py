@@ -111,11 +111,21 @@ class Timer:
         return time_offset, None
 
 
+def _sampling_seed():
+    """Seed of `probing.pprof.seed`, None when unset or without the probe."""
+    import probing
+
+    seed = getattr(probing, "_sampling_seed", None)
+    return seed() if seed is not None else None
+
+
 class Sampler:
     def __init__(self, mode="ordered", rate=1.0, **kwargs):
         # Strategy configuration
         self.mode = mode
         self.rate = rate
+        # sampling choices, repeatable across runs when a seed is set
+        self.random = random.Random(_sampling_seed())
 
         # Module tracking state
         self.mod_names = {}  # Maps module IDs to names
@@ -161,11 +171,11 @@ class Sampler:
 
         if self.mode == "ordered":
             return id(mod) == self.curr_mod
-        return random.random() < self.rate
+        return self.random.random() < self.rate
 
     def next_mod(self) -> None:
         if self.mod_queue and self.mode == "ordered":
-            self.sampled_step = random.random() < self.rate
+            self.sampled_step = self.random.random() < self.rate
             idx = (self.curr_idx + 1) % len(self.mod_queue)
             self.curr_idx = idx
             self.curr_mod = self.mod_queue[idx]
@@ -380,3 +390,12 @@ def set_sampling_mode(mode):
             obj.set_sampling_mode(mode)
     except Exception as e:
         print(f"Error setting mode: {e}")
+
+
+def set_seed(seed):
+    """Reseed the module sampling of every probe, see `probing.pprof.seed`."""
+    import gc
+
+    for obj in gc.get_objects():
+        if isinstance(obj, TorchProbe):
+            obj.random.seed(seed)
@@ -1,5 +1,13 @@
+import ast
 import code
+import codeop
 import io
+import json
+import os
+import threading
+import time
+import traceback
+from collections import OrderedDict, deque
 from contextlib import redirect_stderr, redirect_stdout
 from types import CodeType
 from typing import Any, Dict, List, Type
@@ -61,3 +69,212 @@ except:
     print("DebugConsole not found, using default DebugConsole")
     
 debug_console = DebugConsole()
+
+# saved sessions kept in the process, the oldest are dropped beyond this
+MAX_SESSIONS = 16
+# statements kept in the current session, the oldest are dropped beyond this
+MAX_STATEMENTS = 1000
+
+
+class SessionRecorder:
+    """Statements run through the REPL and `eval`, replayable as a script.
+
+    The current session collects the last `MAX_STATEMENTS` complete
+    statements until it is saved under a name; saved sessions are kept as
+    artifacts of the process.
+    """
+
+    def __init__(self):
+        self.lock = threading.Lock()
+        self.started = time.time()
+        self.statements = deque(maxlen=MAX_STATEMENTS)
+        self.saved = OrderedDict()
+
+    def record(self, source: str, error: str = None) -> None:
+        with self.lock:
+            self.statements.append((source.rstrip("\n"), error))
+
+    def script(self, statements=None, started=None) -> str:
+        """Render statements as a script; failed ones are kept as comments."""
+        with self.lock:
+            statements = list(self.statements if statements is None else statements)
+            started = self.started if started is None else started
+        lines = [
+            "# probing session of pid {} started at {}".format(
+                os.getpid(), time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(started))
+            ),
+            "",
+        ]
+        for source, error in statements:
+            if error is None:
+                lines.append(source)
+            else:
+                lines.append(f"# raised {error}:")
+                lines.extend("# " + line for line in source.splitlines())
+            lines.append("")
+        return "\n".join(lines)
+
+    def save(self, name: str) -> dict:
+        """Keep the current session as `name` and start a new one."""
+        with self.lock:
+            statements, started = self.statements, self.started
+            self.statements, self.started = deque(maxlen=MAX_STATEMENTS), time.time()
+        session = {
+            "name": name,
+            "timestamp": int(started * 1e6),
+            "statements": len(statements),
+            "script": self.script(statements, started),
+        }
+        with self.lock:
+            self.saved.pop(name, None)
+            self.saved[name] = session
+            while len(self.saved) > MAX_SESSIONS:
+                self.saved.popitem(last=False)
+        return session
+
+
+recorder = SessionRecorder()
+_pending = []
+
+
+def push(line: str):
+    """Feed a line of the interactive console, recording complete statements."""
+    _pending.append(line)
+    source = "\n".join(_pending)
+    try:
+        complete = codeop.compile_command(source, "<input>", "single") is not None
+    except (OverflowError, SyntaxError, ValueError) as exc:
+        _pending.clear()
+        recorder.record(source, type(exc).__name__)
+    else:
+        if complete:
+            _pending.clear()
+            recorder.record(source)
+    return debug_console.push(line)
+
+
+def _namespace() -> Dict[str, Any]:
+    """Variables shared with the debug console, including its IPython kernel."""
+    executor = getattr(debug_console, "code_executor", None)
+    if executor is not None and executor.km.has_kernel:
+        return executor.km.kernel.shell.user_ns
+    return debug_console.locals
+
+
+def _evaluate(source: str, max_repr: int) -> Dict[str, Any]:
+    """Run `source` and return an `EvalResult` as a dict."""
+    result = {"stdout": "", "stderr": "", "value": None, "error": None}
+    out, err = io.StringIO(), io.StringIO()
+    try:
+        tree = ast.parse(source, "<eval>", "exec")
+        last = None
+        if tree.body and isinstance(tree.body[-1], ast.Expr):
+            last = ast.Expression(tree.body.pop().value)
+        namespace = _namespace()
+        with redirect_stderr(err), redirect_stdout(out):
+            exec(compile(tree, "<eval>", "exec"), namespace)
+            if last is not None:
+                value = eval(compile(last, "<eval>", "eval"), namespace)
+                if value is not None:
+                    text = repr(value)
+                    kind = type(value)
+                    result["value"] = {
+                        "repr": text[:max_repr],
+                        "type_name": f"{kind.__module__}.{kind.__qualname__}"
+                        if kind.__module__ != "builtins"
+                        else kind.__qualname__,
+                        "truncated": len(text) > max_repr,
+                    }
+    except Exception as exc:
+        # hide the frames of this function, and of the parser for syntax errors
+        tb = None if isinstance(exc, SyntaxError) else exc.__traceback__.tb_next
+        result["error"] = {
+            "type_name": type(exc).__name__,
+            "message": str(exc),
+            "traceback": traceback.format_exception(type(exc), exc, tb),
+        }
+    result["stdout"] = out.getvalue()
+    result["stderr"] = err.getvalue()
+    return result
+
+
+def evaluate(source: str, max_repr: int = 10000) -> str:
+    """Run `source` and return a JSON encoded `EvalResult`.
+
+    Output written to stdout and stderr is captured separately from the value
+    of the trailing expression and from a raised exception.
+
+    >>> import json
+    >>> json.loads(evaluate("print('hi'); 1 + 1"))["value"]["repr"]
+    '2'
+    >>> json.loads(evaluate("1 / 0"))["error"]["type_name"]
+    'ZeroDivisionError'
+    """
+    result = _evaluate(source, max_repr)
+    error = result["error"]
+    recorder.record(source, error["type_name"] if error else None)
+    return json.dumps(result)
+
+
+def split_statements(source: str) -> List[Dict[str, Any]]:
+    """Top-level statements of `source` with their first line.
+
+    Comments and blank lines between statements are dropped; decorators stay
+    with the definition they belong to.
+
+    >>> [s["source"] for s in split_statements("x = 1\\n\\nif x:\\n    print(x)\\n")]
+    ['x = 1', 'if x:\\n    print(x)']
+    """
+    lines = source.splitlines()
+    statements = []
+    for node in ast.parse(source, "<script>", "exec").body:
+        first = min([node.lineno] + [d.lineno for d in getattr(node, "decorator_list", [])])
+        statements.append(
+            {"line": first, "source": "\n".join(lines[first - 1 : node.end_lineno])}
+        )
+    return statements
+
+
+def evaluate_script(source: str, keep_going: bool = False, max_repr: int = 10000) -> str:
+    """Run the statements of `source` one by one, returning JSON `ScriptStep`s.
+
+    Each statement gets its own output and value, as if typed into the REPL.
+    The run stops at the first failing statement unless `keep_going` is set;
+    a script that does not parse yields a single failed step.
+    """
+    try:
+        statements = split_statements(source)
+    except SyntaxError:
+        return json.dumps([{"line": 1, "source": source, "result": _evaluate(source, max_repr)}])
+    steps = []
+    for statement in statements:
+        result = _evaluate(statement["source"], max_repr)
+        error = result["error"]
+        recorder.record(statement["source"], error["type_name"] if error else None)
+        steps.append(dict(statement, result=result))
+        if error and not keep_going:
+            break
+    return json.dumps(steps)
+
+
+def sessions() -> str:
+    """JSON list of the saved sessions, without their scripts."""
+    with recorder.lock:
+        saved = list(recorder.saved.values())
+    return json.dumps([{k: v for k, v in s.items() if k != "script"} for s in saved])
+
+
+def session_script(name: str = None, save: str = None) -> str:
+    """Script of the saved session `name`, of the current one by default.
+
+    With `save` the current session is first stored under that name.
+    """
+    if save:
+        return recorder.save(save)["script"]
+    if name is None:
+        return recorder.script()
+    with recorder.lock:
+        session = recorder.saved.get(name)
+    if session is None:
+        raise KeyError(f"no session named {name}")
+    return session["script"]
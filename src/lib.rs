@@ -3,10 +3,10 @@ extern crate ctor;
 
 use anyhow::Result;
 
+use probing_python::features::oneshot;
 use probing_python::features::python_api::create_probing_module;
 use probing_server::sync_env_settings;
 
-const ENV_PROBING_LOGLEVEL: &str = "PROBING_LOGLEVEL";
 const ENV_PROBING_PORT: &str = "PROBING_PORT";
 
 #[cfg(feature = "use-mimalloc")]
@@ -43,17 +43,41 @@ pub fn get_hostname() -> Result<String> {
         .ok_or_else(|| anyhow::anyhow!("No suitable IP address found"))
 }
 
+/// Rank 0 listens on all interfaces, other ranks on their own address
+fn serving_host() -> String {
+    if std::env::var("RANK").unwrap_or_else(|_| "0".to_string()) == "0" {
+        "0.0.0.0".to_string()
+    } else {
+        get_hostname().unwrap_or_else(|err| {
+            log::warn!("Failed to get hostname: {err}, defaulting to localhost");
+            "localhost".to_string()
+        })
+    }
+}
+
 #[ctor]
 fn setup() {
     let pid = std::process::id();
     eprintln!("Initializing libprobing for process {pid} ...",);
 
     // initialize logging
-    env_logger::init_from_env(env_logger::Env::new().filter(ENV_PROBING_LOGLEVEL));
+    probing_server::init_logger();
+
+    // a one-shot probe runs its action and leaves nothing behind to unload
+    if oneshot::start_if_requested() {
+        return;
+    }
 
     // initialize probing server (local Unix domain socket)
     probing_server::start_local();
 
+    // a named instance keeps its own options and socket `probing-<pid>-<name>`
+    if let Ok(name) = std::env::var("PROBING_NAME") {
+        if let Err(err) = probing_server::create_instance(&name) {
+            log::error!("Failed to create probe instance {name}: {err}");
+        }
+    }
+
     let mut report_port_basis: Option<u16> = None;
 
     match std::env::var(ENV_PROBING_PORT) {
@@ -64,6 +88,15 @@ fn setup() {
                 );
                 std::env::set_var("PROBING_SERVER_ADDR", "'0.0.0.0:0'");
                 // report_port_basis remains None for RANDOM
+            } else if let Ok(range) = port_env_val.parse::<probing_server::PortRange>() {
+                // A range lets probes of jobs sharing the node pick distinct ports
+                log::debug!(
+                    "ENV_PROBING_PORT is a range: {port_env_val}. Port will be picked from it."
+                );
+                report_port_basis = Some(range.start);
+                probing_server::set_port_range(range);
+
+                std::env::set_var("PROBING_SERVER_ADDR", format!("'{}:0'", serving_host()));
             } else {
                 // Not "RANDOM", try to parse as a specific port number
                 match port_env_val.parse::<u16>() {
@@ -79,17 +112,7 @@ fn setup() {
                             .unwrap_or(0);
                         let serving_port = port_number.saturating_add(local_rank);
 
-                        let hostname =
-                            if std::env::var("RANK").unwrap_or_else(|_| "0".to_string()) == "0" {
-                                "0.0.0.0".to_string()
-                            } else {
-                                get_hostname().unwrap_or_else(|err| {
-                                    log::warn!(
-                                        "Failed to get hostname: {err}, defaulting to localhost"
-                                    );
-                                    "localhost".to_string()
-                                })
-                            };
+                        let hostname = serving_host();
                         std::env::set_var(
                             "PROBING_SERVER_ADDR",
                             format!("'{hostname}:{serving_port}'"),
@@ -134,6 +157,10 @@ fn setup() {
 
 #[dtor]
 fn cleanup() {
+    if oneshot::active() {
+        return;
+    }
+    probing_server::shutdown();
     if let Err(e) = probing_server::cleanup() {
         log::error!("Failed to cleanup unix socket: {e}");
     }
@@ -0,0 +1,44 @@
+def test_client_to_frame():
+    from probing.client import to_frame
+
+    df = to_frame(
+        {
+            "names": ["a", "b", "c"],
+            "cols": [{"SeqI64": [1, 2]}, {"SeqText": ["x", "y"]}, "Nil"],
+            "size": 2,
+        }
+    )
+    assert df["a"].tolist() == [1, 2]
+    assert df["b"].tolist() == ["x", "y"]
+    assert df["c"].tolist() == [None, None]
+
+
+def test_client_discover(tmp_path):
+    import os
+
+    from probing.client import discover
+
+    (tmp_path / str(os.getpid())).touch()
+    (tmp_path / "not-a-pid").touch()
+    pids = [p.pid for p in discover(str(tmp_path))]
+    assert os.getpid() in pids
+
+
+def test_client_tensor_slice():
+    import __main__
+
+    import pytest
+
+    np = pytest.importorskip("numpy")
+    from probing.client import decode_tensor
+    from probing.inspect.tensor import export
+
+    __main__.probing_test_tensor = np.arange(12, dtype=np.float32).reshape(3, 4)
+    try:
+        header, array = decode_tensor(export("probing_test_tensor", "1:, ::2"))
+        assert header["source_shape"] == [3, 4]
+        assert array.tolist() == [[4, 6], [8, 10]]
+        with pytest.raises(ValueError):
+            export("probing_test_tensor", limit=4)
+    finally:
+        del __main__.probing_test_tensor
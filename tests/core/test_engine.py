@@ -1,3 +1,4 @@
+import pytest
 from json import load
 
 
@@ -21,4 +22,37 @@ def test_load_extension():
     statement = "probing.ext.example"
     load_extension(statement)
     
-    assert "probing.ext.example" in sys.modules
\ No newline at end of file
+    assert "probing.ext.example" in sys.modules
+
+def test_histogram_quantile():
+    import probing
+    from probing import query
+
+    hist = probing.Histogram.linear(10.0, 10.0, 9)
+    for v in range(1, 101):
+        hist.record(float(v))
+    assert hist.count == 100
+    assert abs(hist.quantile(0.5) - 50) <= 1
+
+    table = probing.ExternalTable.get_or_create("step_latency_hist", ["rank", "latency"])
+    table.append([0, hist])
+    table.append([1, hist])
+
+    df = query(
+        "SELECT histogram_quantile(histogram_merge(latency), 0.99) AS p99 "
+        "FROM python.step_latency_hist"
+    )
+    assert abs(df["p99"][0] - 99) <= 1
+
+
+def test_python_udf():
+    import probing
+    from probing import query
+
+    # a query cannot send Python code of its own
+    with pytest.raises(Exception, match="py_eval"):
+        query("SELECT py_eval('lambda x: x', a) AS b FROM (VALUES (1)) AS t(a)")
+
+    probing.register_udf("scaled", lambda x, k: x * k, returns=float)
+    df = query("SELECT scaled(a, 0.5) AS s FROM (VALUES (1), (4)) AS t(a)")
+    assert df["s"].tolist() == [0.5, 2.0]
@@ -0,0 +1,18 @@
+def test_audit_monitor_records_file_opens(tmp_path):
+    import probing
+    from probing.profiling import audit_monitor
+
+    path = tmp_path / "dataset.bin"
+    audit_monitor.configure(pattern="dataset", max_per_second=100)
+    audit_monitor.enable()
+    try:
+        path.write_bytes(b"x")
+        open(tmp_path / "ignored.txt", "w").close()
+    finally:
+        audit_monitor.disable()
+        audit_monitor.configure(pattern=None)
+
+    df = probing.query("select event, target from python.audit_events")
+    targets = set(df["target"])
+    assert str(path) in targets
+    assert str(tmp_path / "ignored.txt") not in targets
@@ -0,0 +1,43 @@
+import sys
+
+import pytest
+
+
+def _raise_key_error():
+    try:
+        {}["missing"]
+    except KeyError:
+        pass
+
+
+@pytest.mark.skipif(sys.version_info < (3, 12), reason="requires sys.monitoring")
+def test_counters_count_calls_and_exceptions():
+    import probing
+    from probing.profiling import counters
+
+    counters.enable()
+    assert counters.is_enabled()
+    counters.reset()
+    for _ in range(3):
+        _raise_key_error()
+
+    df = probing.query('select kind, "group", name, count from python.counters')
+    errors = df[(df["kind"] == "exception") & (df["name"] == "KeyError")]
+    assert errors["count"].sum() == 3
+    assert (df[df["kind"] == "call"]["count"] > 0).any()
+
+
+@pytest.mark.skipif(sys.version_info < (3, 12), reason="requires sys.monitoring")
+def test_counters_cap_hot_functions():
+    from probing.profiling import counters
+
+    counters.enable()
+    counters.reset()
+    for _ in range(counters.MAX_CALLS_PER_TICK * 2):
+        _raise_key_error()
+
+    rows = counters.counters()
+    assert any(row["saturated"] for row in rows if row["kind"] == "call")
+    # exceptions are never capped
+    raised = [row["count"] for row in rows if row["name"] == "KeyError"]
+    assert raised == [counters.MAX_CALLS_PER_TICK * 2]
@@ -0,0 +1,83 @@
+import sys
+
+import pytest
+
+
+def traced_target(x):
+    return x + 1
+
+
+def failing_target():
+    raise ValueError("boom")
+
+
+@pytest.mark.skipif(sys.version_info < (3, 12), reason="requires sys.monitoring")
+def test_func_tracer_records_calls(monkeypatch):
+    import probing
+    from probing.profiling import func_tracer
+
+    events = []
+    monkeypatch.setattr(
+        probing, "_trace_enter", lambda name, loc=None: events.append(("enter", name)), raising=False
+    )
+    monkeypatch.setattr(
+        probing, "_trace_exit", lambda err=None: events.append(("exit", err)), raising=False
+    )
+
+    spec = f"{__name__}:traced_target"
+    assert func_tracer.set_trace_functions(spec) == [spec]
+    try:
+        assert traced_target(1) == 2
+        with pytest.raises(ValueError):
+            failing_target()
+    finally:
+        func_tracer.set_trace_functions("")
+
+    assert events == [("enter", spec), ("exit", None)]
+    assert func_tracer.traced_functions() == []
+
+    traced_target(1)
+    assert len(events) == 2
+
+
+@pytest.mark.skipif(sys.version_info < (3, 12), reason="requires sys.monitoring")
+def test_func_tracer_unwind_only_while_traced(monkeypatch):
+    import probing
+    from probing.profiling import func_tracer
+
+    monitoring = sys.monitoring
+    events = []
+    monkeypatch.setattr(
+        probing,
+        "_trace_enter",
+        lambda name, loc=None: events.append(
+            ("enter", monitoring.get_events(func_tracer._tool_id))
+        ),
+        raising=False,
+    )
+    monkeypatch.setattr(
+        probing, "_trace_exit", lambda err=None: events.append(("exit", err)), raising=False
+    )
+
+    spec = f"{__name__}:failing_target"
+    func_tracer.set_trace_functions(spec)
+    try:
+        tool_id = func_tracer._tool_id
+        assert monitoring.get_events(tool_id) == 0
+        with pytest.raises(ValueError):
+            failing_target()
+        assert monitoring.get_events(tool_id) == 0
+    finally:
+        func_tracer.set_trace_functions("")
+
+    assert events == [
+        ("enter", monitoring.events.PY_UNWIND),
+        ("exit", repr(ValueError("boom"))),
+    ]
+
+
+def test_func_tracer_rejects_invalid_spec():
+    from probing.profiling import func_tracer
+
+    with pytest.raises(ValueError):
+        func_tracer.resolve("no_colon_here")
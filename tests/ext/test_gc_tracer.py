@@ -0,0 +1,18 @@
+import gc
+
+
+def test_gc_tracer_records_collections():
+    import probing
+    from probing.profiling import gc_tracer
+
+    gc_tracer.enable()
+    try:
+        assert gc_tracer.is_enabled()
+        gc.collect(2)
+    finally:
+        gc_tracer.disable()
+    assert not gc_tracer.is_enabled()
+
+    df = probing.query("select generation, duration, collected from python.gc")
+    assert 2 in set(df["generation"])
+    assert (df["duration"] >= 0).all()
@@ -0,0 +1,40 @@
+import json
+
+from probing import repl
+
+
+def test_evaluate_script_per_statement():
+    steps = json.loads(repl.evaluate_script("x = 20\nprint(x)\n\nx + 22\n"))
+
+    assert [step["line"] for step in steps] == [1, 2, 4]
+    assert steps[1]["result"]["stdout"] == "20\n"
+    assert steps[2]["result"]["value"]["repr"] == "42"
+
+
+def test_evaluate_script_stops_at_error():
+    script = "y = 1\n1 / 0\ny = 2\n"
+
+    steps = json.loads(repl.evaluate_script(script))
+    assert len(steps) == 2
+    assert steps[-1]["result"]["error"]["type_name"] == "ZeroDivisionError"
+
+    steps = json.loads(repl.evaluate_script(script, True))
+    assert len(steps) == 3
+    assert repl._namespace()["y"] == 2
+
+
+def test_saved_session_replays():
+    repl.session_script(save="discard")
+    repl.evaluate("z = [1, 2]")
+    repl.evaluate("z.append(3)")
+    repl.evaluate("undefined_name")
+
+    script = repl.session_script(save="runbook")
+    assert "z.append(3)" in script
+    assert "# raised NameError:" in script
+    assert repl.session_script("runbook") == script
+    assert "runbook" in [s["name"] for s in json.loads(repl.sessions())]
+
+    namespace = {}
+    exec(script, namespace)
+    assert namespace["z"] == [1, 2, 3]
@@ -0,0 +1,18 @@
+from probing.inspect import gpu
+
+
+def test_visible_devices_mapping():
+    uuids = ["GPU-1111", "GPU-2222", "GPU-3333"]
+    assert gpu.visible_devices(None, uuids) == [0, 1, 2]
+    assert gpu.visible_devices("2,0", uuids) == [2, 0]
+    assert gpu.visible_devices("GPU-22,0", uuids) == [1, 0]
+    # CUDA ignores everything from the first entry it cannot resolve
+    assert gpu.visible_devices("1,7,0", uuids) == [1]
+    assert gpu.visible_devices("GPU-,0", uuids) == []
+
+
+def test_gpu_topology_table():
+    import probing
+
+    df = probing.query("select device, physical, peer, link, driver from gpu.topology")
+    assert list(df.columns) == ["device", "physical", "peer", "link", "driver"]
@@ -36,3 +36,46 @@ def test_probing_python_dict_list():
     assert df["float"][1] == '2.0'
     assert df["str"][0] == 'str'
     assert df["str"][1] == 'str2'
+
+def test_probing_python_stacks():
+    import threading
+
+    import probing
+
+    ready = threading.Event()
+    done = threading.Event()
+
+    def worker():
+        ready.set()
+        done.wait()
+
+    thread = threading.Thread(target=worker, name="stacks-worker", daemon=True)
+    thread.start()
+    ready.wait()
+    try:
+        df = probing.query("select * from python.stacks")
+        assert "stacks-worker" in set(df["thread_name"])
+        frames = df[df["thread_name"] == "stacks-worker"]
+        assert "worker" in set(frames["func"])
+        assert frames["is_daemon"].all()
+        # blocked on the event, the worker released the GIL
+        assert not frames["holds_gil"].fillna(False).any()
+    finally:
+        done.set()
+        thread.join()
+
+def test_probing_python_packages():
+    import importlib.metadata
+
+    import probing
+
+    df = probing.query("select * from python.packages")
+    # a distribution installed in several locations has a row for each
+    installed = set(zip(df["name"], df["version"]))
+    expected = {
+        (dist.metadata["Name"], dist.version)
+        for dist in importlib.metadata.distributions()
+        if dist.metadata["Name"] is not None
+    }
+    assert installed == expected
+    assert df["location"].notnull().all()
@@ -0,0 +1,33 @@
+import pytest
+
+torch = pytest.importorskip("torch")
+
+
+def test_probing_torch_parameters():
+    import probing
+
+    model = torch.nn.Linear(4, 2)
+    model(torch.randn(3, 4)).sum().backward()
+    name = probing.watch_model(model, name="linear")
+    assert name == "linear"
+
+    df = probing.query("select * from torch.parameters where model = 'linear'")
+    assert set(df["name"]) == {"weight", "bias"}
+    assert df["requires_grad"].all()
+    assert (df["grad_norm"] > 0).all()
+
+
+def test_probing_torch_optimizer_state():
+    import probing
+
+    model = torch.nn.Linear(4, 2)
+    optimizer = torch.optim.Adam(model.parameters(), lr=0.01)
+    model(torch.randn(3, 4)).sum().backward()
+    optimizer.step()
+    probing.watch_model(model, optimizer, name="adam_linear")
+
+    df = probing.query(
+        "select * from torch.optimizer_state where model = 'adam_linear'"
+    )
+    assert {"exp_avg", "exp_avg_sq", "step"} <= set(df["slot"])
+    assert set(df["param"]) == {"weight", "bias"}
@@ -0,0 +1,77 @@
+import os
+import subprocess
+import sys
+import tempfile
+import unittest
+from unittest import mock
+
+import probing
+from probing import launcher
+
+
+class TestLauncher(unittest.TestCase):
+    def test_parse_and_env(self):
+        args = launcher.parse(
+            [
+                "run",
+                "train.py",
+                "--port",
+                "9700",
+                "--enable",
+                "pprof",
+                "--set",
+                "probing.torch.sample.rate=0.1",
+                "--",
+                "--lr",
+                "0.1",
+            ]
+        )
+        self.assertEqual(launcher.command(args)[1:], ["train.py", "--lr", "0.1"])
+
+        env = launcher.build_env(args, base={"PATH": "/bin"})
+        self.assertEqual(env["PROBING"], "1")
+        self.assertEqual(env["PROBING_PORT"], "9700")
+        self.assertEqual(env["PROBING_PPROF_SAMPLE_FREQ"], "99")
+        self.assertEqual(env["PROBING_TORCH_SAMPLE_RATE"], "0.1")
+        self.assertEqual(env["PATH"], "/bin")
+
+    def test_nested_command(self):
+        args = launcher.parse(["run", "--nested", "torchrun", "--", "--nproc-per-node", "8", "train.py"])
+        self.assertEqual(launcher.command(args), ["torchrun", "--nproc-per-node", "8", "train.py"])
+        self.assertEqual(launcher.build_env(args, base={})["PROBING"], "2")
+
+    def test_invalid_setting(self):
+        args = launcher.parse(["run", "train.py", "--set", "pprof"])
+        with self.assertRaises(ValueError):
+            launcher.build_env(args, base={})
+
+    def test_launching(self):
+        cases = [
+            (["python", "-m", "probing", "run", "train.py"], True),
+            (["python", "-X", "dev", "-mprobing", "run", "train.py"], True),
+            (["python", "-m", "torch.distributed.run", "train.py"], False),
+            (["python", "-m", "mypkg.train"], False),
+            (["python", "train.py", "-m", "probing"], False),
+        ]
+        for argv, launching in cases:
+            with mock.patch.object(sys, "orig_argv", argv, create=True):
+                self.assertEqual(probing._launching(), launching, argv)
+
+    def test_probe_starts_under_dash_m(self):
+        with tempfile.TemporaryDirectory() as tmp:
+            with open(os.path.join(tmp, "probed_module.py"), "w") as f:
+                f.write("import probing\nprint(probing.CLIENT_ONLY)\n")
+            env = dict(os.environ, PROBING="1")
+            out = subprocess.run(
+                [sys.executable, "-m", "probed_module"],
+                cwd=tmp,
+                env=env,
+                capture_output=True,
+                text=True,
+                timeout=60,
+            )
+        self.assertEqual(out.stdout.strip().splitlines()[-1], "False", out.stderr)
+
+
+if __name__ == "__main__":
+    unittest.main()